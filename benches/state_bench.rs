@@ -0,0 +1,172 @@
+//! Throughput of read-heavy handlers sharing one piece of state.
+//!
+//! Every request reads a device record from a map in the application state
+//! and serializes it. The map sits behind a mutex, a read-write lock or a
+//! `ShardedMap`, and the same number of requests is spread over an
+//! increasing number of concurrent "connections".
+
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::{Arc, RwLock},
+};
+
+use coapum::{
+    CoapRequest, Raw, RequestType,
+    extract::{Identity, State},
+    router::{CoapumRequest, RouterBuilder},
+    sharded::ShardedMap,
+};
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tower::Service;
+
+const DEVICES: usize = 1024;
+const REQUESTS: usize = 4096;
+
+#[derive(Clone, Debug, Serialize)]
+struct Device {
+    firmware: String,
+    battery: u8,
+    tags: Vec<String>,
+}
+
+fn device(i: usize) -> Device {
+    Device {
+        firmware: format!("1.{}.0", i % 10),
+        battery: (i % 100) as u8,
+        tags: vec!["fleet-a".to_string(), format!("rack-{}", i % 16)],
+    }
+}
+
+fn raw(device: Option<&Device>) -> Raw {
+    Raw {
+        payload: serde_json::to_vec(&device).unwrap(),
+        content_format: None,
+    }
+}
+
+#[derive(Clone, Debug)]
+struct MutexState(Arc<Mutex<HashMap<String, Device>>>);
+
+#[derive(Clone, Debug)]
+struct RwLockState(Arc<RwLock<HashMap<String, Device>>>);
+
+#[derive(Clone, Debug)]
+struct ShardedState(ShardedMap<String, Device>);
+
+impl AsRef<MutexState> for MutexState {
+    fn as_ref(&self) -> &MutexState {
+        self
+    }
+}
+
+impl AsRef<RwLockState> for RwLockState {
+    fn as_ref(&self) -> &RwLockState {
+        self
+    }
+}
+
+impl AsRef<ShardedState> for ShardedState {
+    fn as_ref(&self) -> &ShardedState {
+        self
+    }
+}
+
+async fn read_mutex(Identity(id): Identity, State(state): State<MutexState>) -> Raw {
+    raw(state.0.lock().await.get(&id))
+}
+
+async fn read_rwlock(Identity(id): Identity, State(state): State<RwLockState>) -> Raw {
+    raw(state.0.read().unwrap().get(&id))
+}
+
+async fn read_sharded(Identity(id): Identity, State(state): State<ShardedState>) -> Raw {
+    state
+        .0
+        .with(&id, |d| raw(Some(d)))
+        .unwrap_or_else(|| raw(None))
+}
+
+fn request(i: usize) -> CoapumRequest<SocketAddr> {
+    let mut req: CoapRequest<SocketAddr> = CoapRequest::new();
+    req.set_method(RequestType::Get);
+    req.set_path("/device");
+    req.source = Some(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)));
+    let mut req: CoapumRequest<SocketAddr> = req.into();
+    req.identity = format!("device-{}", i % DEVICES);
+    req
+}
+
+/// Send `REQUESTS` requests through `router` from `connections` tasks
+async fn load<R>(router: R, connections: usize)
+where
+    R: Service<CoapumRequest<SocketAddr>> + Clone + Send + 'static,
+    R::Future: Send,
+{
+    let per_connection = REQUESTS / connections;
+    let tasks: Vec<_> = (0..connections)
+        .map(|c| {
+            let mut router = router.clone();
+            tokio::spawn(async move {
+                for i in 0..per_connection {
+                    let _ = router.call(request(c * per_connection + i)).await;
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+}
+
+fn state_benchmark(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let devices: HashMap<String, Device> = (0..DEVICES)
+        .map(|i| (format!("device-{i}"), device(i)))
+        .collect();
+    let sharded = ShardedMap::new();
+    for (id, device) in &devices {
+        sharded.insert(id.clone(), device.clone());
+    }
+
+    let mutex = RouterBuilder::new(MutexState(Arc::new(Mutex::new(devices.clone()))), ())
+        .get("/device", read_mutex)
+        .build();
+    let rwlock = RouterBuilder::new(RwLockState(Arc::new(RwLock::new(devices))), ())
+        .get("/device", read_rwlock)
+        .build();
+    let sharded = RouterBuilder::new(ShardedState(sharded), ())
+        .get("/device", read_sharded)
+        .build();
+
+    let mut group = c.benchmark_group("state_read");
+    group.throughput(Throughput::Elements(REQUESTS as u64));
+    for connections in [1, 8, 64, 256] {
+        group.bench_with_input(
+            BenchmarkId::new("mutex", connections),
+            &connections,
+            |b, &n| b.iter(|| rt.block_on(load(mutex.clone(), n))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("rwlock", connections),
+            &connections,
+            |b, &n| b.iter(|| rt.block_on(load(rwlock.clone(), n))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("sharded", connections),
+            &connections,
+            |b, &n| b.iter(|| rt.block_on(load(sharded.clone(), n))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, state_benchmark);
+criterion_main!(benches);
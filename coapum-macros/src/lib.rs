@@ -0,0 +1,417 @@
+//! Procedural macros for coapum
+//!
+//! Enable coapum's `macros` feature and use the re-exports in `coapum`
+//! instead of depending on this crate directly; the generated code refers
+//! to `::coapum`.
+
+use std::collections::HashSet;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    FnArg, GenericArgument, Ident, ItemFn, LitStr, Pat, PathArguments, Token, Type,
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+};
+
+/// Handlers take at most this many extractors (see `coapum::Handler`)
+const MAX_EXTRACTORS: usize = 4;
+
+/// Declare the method and path a handler is served at
+///
+/// ```ignore
+/// #[coap_route(get, "/sensors/:id")]
+/// async fn get_sensor(Path(id): Path<String>) -> StatusCode { ... }
+/// ```
+///
+/// The path is checked against the handler's signature at compile time:
+/// a `Path` extractor on a route without parameters, a `Path` type other
+/// than `Path<String>`, or a binding named differently from the route's
+/// only parameter are errors.
+///
+/// Next to the function, the macro defines a type of the same name with
+/// the route's `METHOD` and `PATH` and a `register` function adding the
+/// route to a `RouterBuilder`. Collect routes with `coapum::routes!`.
+#[proc_macro_attribute]
+pub fn coap_route(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as RouteArgs);
+    let handler = parse_macro_input!(item as ItemFn);
+
+    match expand(args, handler) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+struct RouteArgs {
+    method: Ident,
+    path: LitStr,
+}
+
+impl Parse for RouteArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let method = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let path = input.parse()?;
+        if input.peek(Token![,]) {
+            input.parse::<Token![,]>()?;
+        }
+        Ok(Self { method, path })
+    }
+}
+
+/// A dynamic segment of a route path
+#[derive(Debug, PartialEq)]
+struct RouteParam {
+    name: String,
+    wildcard: bool,
+}
+
+/// Parses the `:name` and `*name` segments out of a route path
+///
+/// Names must be identifiers and unique, and a wildcard must be the last
+/// segment since it swallows the rest of the path.
+fn route_params(path: &str) -> Result<Vec<RouteParam>, String> {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let mut params = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (i, segment) in segments.iter().enumerate() {
+        let (name, wildcard) = if let Some(name) = segment.strip_prefix(':') {
+            (name, false)
+        } else if let Some(name) = segment.strip_prefix('*') {
+            (name, true)
+        } else {
+            continue;
+        };
+
+        if !is_identifier(name) {
+            return Err(format!("invalid route parameter `{}`", segment));
+        }
+        if !seen.insert(name) {
+            return Err(format!("route parameter `{}` appears more than once", name));
+        }
+        if wildcard && i + 1 != segments.len() {
+            return Err(format!("wildcard `{}` must be the last segment", segment));
+        }
+        params.push(RouteParam {
+            name: name.to_string(),
+            wildcard,
+        });
+    }
+
+    Ok(params)
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The type parameter of a `Path<T>` extractor
+fn path_extractor(ty: &Type) -> Option<&Type> {
+    let Type::Path(ty) = ty else {
+        return None;
+    };
+    let last = ty.path.segments.last()?;
+    if last.ident != "Path" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &last.arguments else {
+        return None;
+    };
+    args.args.iter().find_map(|arg| match arg {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None,
+    })
+}
+
+fn is_string(ty: &Type) -> bool {
+    matches!(ty, Type::Path(ty) if ty.path.segments.last().is_some_and(|s| s.ident == "String"))
+}
+
+/// The variable a `Path(name)` pattern binds
+fn path_binding(pat: &Pat) -> Option<&Ident> {
+    let Pat::TupleStruct(pat) = pat else {
+        return None;
+    };
+    match pat.elems.first() {
+        Some(Pat::Ident(binding)) if pat.elems.len() == 1 => Some(&binding.ident),
+        _ => None,
+    }
+}
+
+/// Checks the handler's extractors against the route's parameters
+fn validate(handler: &ItemFn, route: &LitStr, params: &[RouteParam]) -> syn::Result<()> {
+    let sig = &handler.sig;
+    if sig.asyncness.is_none() {
+        return Err(syn::Error::new_spanned(
+            sig.fn_token,
+            "#[coap_route] handlers must be `async fn`",
+        ));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &sig.generics,
+            "#[coap_route] handlers can't be generic",
+        ));
+    }
+    if sig.inputs.len() > MAX_EXTRACTORS {
+        return Err(syn::Error::new_spanned(
+            &sig.inputs,
+            format!("handlers take at most {} extractors", MAX_EXTRACTORS),
+        ));
+    }
+
+    let mut path_seen = false;
+    for input in &sig.inputs {
+        let arg = match input {
+            FnArg::Receiver(receiver) => {
+                return Err(syn::Error::new_spanned(
+                    receiver,
+                    "#[coap_route] handlers can't take `self`",
+                ));
+            }
+            FnArg::Typed(arg) => arg,
+        };
+        let Some(inner) = path_extractor(&arg.ty) else {
+            continue;
+        };
+
+        if path_seen {
+            return Err(syn::Error::new_spanned(
+                &arg.ty,
+                "the path can only be extracted once",
+            ));
+        }
+        path_seen = true;
+
+        if params.is_empty() {
+            return Err(syn::Error::new_spanned(
+                &arg.ty,
+                format!(
+                    "`Path` extractor on route \"{}\", which has no parameters",
+                    route.value()
+                ),
+            ));
+        }
+        if !is_string(inner) {
+            return Err(syn::Error::new_spanned(
+                inner,
+                "route parameters are extracted as `Path<String>`",
+            ));
+        }
+        if let ([param], Some(binding)) = (params, path_binding(&arg.pat)) {
+            let bound = binding.to_string();
+            if bound.trim_start_matches('_') != param.name {
+                let segment = if param.wildcard { '*' } else { ':' };
+                return Err(syn::Error::new_spanned(
+                    binding,
+                    format!(
+                        "handler binds `{}` but the route parameter is `{}{}`",
+                        bound, segment, param.name
+                    ),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn expand(args: RouteArgs, handler: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let method = match args.method.to_string().as_str() {
+        "get" => quote!(Get),
+        "post" => quote!(Post),
+        "put" => quote!(Put),
+        "delete" => quote!(Delete),
+        "fetch" => quote!(Fetch),
+        "patch" => quote!(Patch),
+        "ipatch" => quote!(IPatch),
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &args.method,
+                "expected one of get, post, put, delete, fetch, patch, ipatch",
+            ));
+        }
+    };
+    let params =
+        route_params(&args.path.value()).map_err(|e| syn::Error::new(args.path.span(), e))?;
+    validate(&handler, &args.path, &params)?;
+
+    let builder_method = &args.method;
+    let path = &args.path;
+    let vis = &handler.vis;
+    let name = &handler.sig.ident;
+    let extractors: Vec<&Type> = handler
+        .sig
+        .inputs
+        .iter()
+        .filter_map(|input| match input {
+            FnArg::Typed(arg) => Some(&*arg.ty),
+            FnArg::Receiver(_) => None,
+        })
+        .collect();
+    let doc = format!(
+        "Route for [`{}()`]: {} \"{}\"",
+        name,
+        args.method.to_string().to_uppercase(),
+        path.value()
+    );
+
+    Ok(quote! {
+        #handler
+
+        #[doc = #doc]
+        #[allow(non_camel_case_types)]
+        #vis struct #name {}
+
+        impl #name {
+            /// Request method of the route
+            pub const METHOD: ::coapum::RequestType = ::coapum::RequestType::#method;
+            /// Path of the route
+            pub const PATH: &'static str = #path;
+
+            /// Adds the route to `builder`
+            pub fn register<O, S>(
+                builder: ::coapum::RouterBuilder<O, S>,
+            ) -> ::coapum::RouterBuilder<O, S>
+            where
+                O: ::coapum::Observer + ::core::marker::Send + ::core::marker::Sync + ::core::clone::Clone + 'static,
+                S: ::core::clone::Clone + ::core::fmt::Debug + ::core::marker::Send + ::core::marker::Sync + 'static,
+                #(
+                    #extractors: ::coapum::FromRequest<S> + ::core::marker::Send + ::core::marker::Sync + 'static,
+                    <#extractors as ::coapum::FromRequest<S>>::Rejection: ::core::marker::Send + 'static,
+                )*
+            {
+                builder.#builder_method(Self::PATH, #name)
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn param(name: &str, wildcard: bool) -> RouteParam {
+        RouteParam {
+            name: name.to_string(),
+            wildcard,
+        }
+    }
+
+    #[test]
+    fn test_route_params() {
+        assert_eq!(route_params("/sensors").unwrap(), vec![]);
+        assert_eq!(
+            route_params("/sensors/:id").unwrap(),
+            vec![param("id", false)]
+        );
+        assert_eq!(
+            route_params("/d/:device/*path").unwrap(),
+            vec![param("device", false), param("path", true)]
+        );
+
+        assert!(route_params("/sensors/:").is_err());
+        assert!(route_params("/sensors/:1st").is_err());
+        assert!(route_params("/:id/:id").is_err());
+        assert!(route_params("/t/*path/latest").is_err());
+    }
+
+    fn check(path: &str, handler: ItemFn) -> syn::Result<()> {
+        let route = LitStr::new(path, proc_macro2::Span::call_site());
+        let params = route_params(path).unwrap();
+        validate(&handler, &route, &params)
+    }
+
+    #[test]
+    fn test_validate_path_extractor() {
+        assert!(
+            check(
+                "/sensors/:id",
+                parse_quote!(
+                    async fn get(Path(id): Path<String>) {}
+                )
+            )
+            .is_ok()
+        );
+        assert!(
+            check(
+                "/sensors/:id",
+                parse_quote!(
+                    async fn get(Path(_id): Path<String>, State(s): State<App>) {}
+                )
+            )
+            .is_ok()
+        );
+        // Extracting the parameters is optional
+        assert!(
+            check(
+                "/sensors/:id",
+                parse_quote!(
+                    async fn get(id: Identity) {}
+                )
+            )
+            .is_ok()
+        );
+
+        let err = check(
+            "/sensors",
+            parse_quote!(
+                async fn get(Path(id): Path<String>) {}
+            ),
+        );
+        assert!(err.unwrap_err().to_string().contains("no parameters"));
+
+        let err = check(
+            "/sensors/:id",
+            parse_quote!(
+                async fn get(Path(sensor): Path<String>) {}
+            ),
+        );
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "handler binds `sensor` but the route parameter is `:id`"
+        );
+
+        let err = check(
+            "/sensors/:id",
+            parse_quote!(
+                async fn get(Path(id): Path<u32>) {}
+            ),
+        );
+        assert!(err.unwrap_err().to_string().contains("Path<String>"));
+    }
+
+    #[test]
+    fn test_validate_signature() {
+        let err = check(
+            "/a",
+            parse_quote!(
+                fn get() {}
+            ),
+        );
+        assert!(err.unwrap_err().to_string().contains("async fn"));
+
+        let err = check(
+            "/a",
+            parse_quote!(
+                async fn get<T>(t: T) {}
+            ),
+        );
+        assert!(err.unwrap_err().to_string().contains("generic"));
+
+        let err = check(
+            "/a",
+            parse_quote!(
+                async fn get(a: A, b: B, c: C, d: D, e: E) {}
+            ),
+        );
+        assert!(err.unwrap_err().to_string().contains("at most 4"));
+    }
+}
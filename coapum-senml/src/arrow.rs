@@ -0,0 +1,259 @@
+//! Arrow and Parquet export of normalized packs
+//!
+//! [`NormalizedPack::to_record_batch`] maps records to the columns of
+//! [`schema`], one row per record, for loading into Arrow-based query
+//! engines. With the `parquet` feature, [`ParquetWriter`] appends packs to a
+//! Parquet file as they arrive, so gateways can land device data in a data
+//! lake without a conversion step. Time series built with
+//! [`TimeSeriesBuilder`](crate::builder::TimeSeriesBuilder) export like any
+//! other pack once normalized.
+//!
+//! Numeric values and sums are stored as 64-bit floats, so integers beyond
+//! 2^53 lose precision. Times are stored as UTC timestamps in microseconds,
+//! with relative times resolved against the current time; update times stay
+//! durations in seconds.
+
+use std::sync::Arc;
+
+use arrow_array::{
+    ArrayRef, BinaryArray, BooleanArray, Float64Array, RecordBatch, StringArray,
+    TimestampMicrosecondArray,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+
+use crate::{NormalizedPack, NormalizedRecord, Result, SenMLError};
+
+/// Columns records are exported to
+///
+/// | Column         | Type                    | SenML field |
+/// |----------------|-------------------------|-------------|
+/// | `name`         | Utf8, not null          | `n`         |
+/// | `unit`         | Utf8                    | `u`         |
+/// | `value`        | Float64                 | `v`         |
+/// | `string_value` | Utf8                    | `vs`        |
+/// | `bool_value`   | Boolean                 | `vb`        |
+/// | `data_value`   | Binary                  | `vd`        |
+/// | `sum`          | Float64                 | `s`         |
+/// | `time`         | Timestamp(µs, UTC)      | `t`         |
+/// | `update_time`  | Float64                 | `ut`        |
+pub fn schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("name", DataType::Utf8, false),
+        Field::new("unit", DataType::Utf8, true),
+        Field::new("value", DataType::Float64, true),
+        Field::new("string_value", DataType::Utf8, true),
+        Field::new("bool_value", DataType::Boolean, true),
+        Field::new("data_value", DataType::Binary, true),
+        Field::new("sum", DataType::Float64, true),
+        Field::new(
+            "time",
+            DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            true,
+        ),
+        Field::new("update_time", DataType::Float64, true),
+    ]))
+}
+
+/// Convert `records` into a batch with the columns of [`schema`]
+///
+/// Useful to combine the records of several packs into one batch.
+pub fn record_batch<'a>(
+    records: impl IntoIterator<Item = &'a NormalizedRecord>,
+) -> Result<RecordBatch> {
+    let records: Vec<_> = records.into_iter().collect();
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(
+            records.iter().map(|r| r.name.as_str()),
+        )),
+        Arc::new(StringArray::from_iter(
+            records.iter().map(|r| r.unit.as_deref()),
+        )),
+        Arc::new(Float64Array::from_iter(
+            records.iter().map(|r| r.value.map(|v| v.as_f64())),
+        )),
+        Arc::new(StringArray::from_iter(
+            records.iter().map(|r| r.string_value.as_deref()),
+        )),
+        Arc::new(BooleanArray::from_iter(
+            records.iter().map(|r| r.bool_value),
+        )),
+        Arc::new(BinaryArray::from_iter(
+            records.iter().map(|r| r.data_value.as_deref()),
+        )),
+        Arc::new(Float64Array::from_iter(
+            records.iter().map(|r| r.sum.map(|s| s.as_f64())),
+        )),
+        Arc::new(
+            TimestampMicrosecondArray::from_iter(records.iter().map(|r| {
+                r.absolute_time()
+                    .map(|time| (time * 1_000_000.0).round() as i64)
+            }))
+            .with_timezone("UTC"),
+        ),
+        Arc::new(Float64Array::from_iter(
+            records.iter().map(|r| r.update_time),
+        )),
+    ];
+    RecordBatch::try_new(schema(), columns).map_err(|e| SenMLError::serialization(e.to_string()))
+}
+
+impl NormalizedPack {
+    /// The records of the pack as an Arrow batch with the columns of
+    /// [`schema`]
+    pub fn to_record_batch(&self) -> Result<RecordBatch> {
+        record_batch(&self.records)
+    }
+}
+
+#[cfg(feature = "parquet")]
+pub use self::parquet_writer::ParquetWriter;
+
+#[cfg(feature = "parquet")]
+mod parquet_writer {
+    use std::io::Write;
+
+    use parquet::arrow::ArrowWriter;
+
+    use super::{record_batch, schema};
+    use crate::{NormalizedPack, Result, SenMLError};
+
+    /// Writes normalized packs to a Parquet file
+    ///
+    /// Rows are buffered into row groups and written as they fill up; the
+    /// file is complete once [`finish`](Self::finish) has written its
+    /// footer. Compression is off unless a codec feature of the `parquet`
+    /// crate is enabled and selected through [`ArrowWriter`]'s properties,
+    /// which [`from_writer`](Self::from_writer) accepts.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use coapum_senml::arrow::ParquetWriter;
+    /// use coapum_senml::{NormalizedPack, SenMLBuilder};
+    ///
+    /// let pack = SenMLBuilder::new()
+    ///     .base_name("urn:dev:ow:10e2073a01080063:")
+    ///     .base_unit("Cel")
+    ///     .add_value("temp", 23.1)
+    ///     .build();
+    ///
+    /// let file = std::fs::File::create("readings.parquet").unwrap();
+    /// let mut writer = ParquetWriter::new(file)?;
+    /// writer.write_pack(&NormalizedPack::from_pack(&pack))?;
+    /// writer.finish()?;
+    /// # Ok::<(), coapum_senml::SenMLError>(())
+    /// ```
+    pub struct ParquetWriter<W: Write + Send> {
+        writer: ArrowWriter<W>,
+    }
+
+    impl<W: Write + Send> ParquetWriter<W> {
+        /// Write a Parquet file with the columns of [`schema`] to `out`
+        pub fn new(out: W) -> Result<Self> {
+            ArrowWriter::try_new(out, schema(), None)
+                .map(Self::from_writer)
+                .map_err(|e| SenMLError::serialization(e.to_string()))
+        }
+
+        /// Write through an [`ArrowWriter`] created with the columns of
+        /// [`schema`], e.g. to set compression or row group sizes
+        pub fn from_writer(writer: ArrowWriter<W>) -> Self {
+            Self { writer }
+        }
+
+        /// Append the records of `pack`
+        pub fn write_pack(&mut self, pack: &NormalizedPack) -> Result<()> {
+            let batch = record_batch(&pack.records)?;
+            self.writer
+                .write(&batch)
+                .map_err(|e| SenMLError::serialization(e.to_string()))
+        }
+
+        /// Write the buffered rows and the footer, and return the output
+        pub fn finish(self) -> Result<W> {
+            self.writer
+                .into_inner()
+                .map_err(|e| SenMLError::serialization(e.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SenMLPack, SenMLRecord};
+    use arrow_array::Array;
+
+    fn pack() -> NormalizedPack {
+        let pack = SenMLPack {
+            records: vec![
+                SenMLRecord {
+                    bn: Some("dev:".to_string()),
+                    bt: Some(1_700_000_000.0),
+                    bu: Some("Cel".to_string()),
+                    ..SenMLRecord::with_value("temp", 21.5)
+                },
+                SenMLRecord::with_string_value("status", "ok").with_time(1.5),
+                SenMLRecord::with_data_value("raw", vec![1, 2, 3]),
+            ],
+        };
+        NormalizedPack::from_pack(&pack)
+    }
+
+    #[test]
+    fn test_record_batch() {
+        let batch = pack().to_record_batch().unwrap();
+        assert_eq!(batch.schema(), schema());
+        assert_eq!(batch.num_rows(), 3);
+
+        let column = |name| batch.column_by_name(name).unwrap();
+        let names = column("name")
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(names.value(1), "dev:status");
+        let values = column("value")
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(values.value(0), 21.5);
+        assert!(values.is_null(1));
+        let strings = column("string_value")
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(strings.value(1), "ok");
+        let data = column("data_value")
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .unwrap();
+        assert_eq!(data.value(2), &[1, 2, 3]);
+        let times = column("time")
+            .as_any()
+            .downcast_ref::<TimestampMicrosecondArray>()
+            .unwrap();
+        assert_eq!(times.value(0), 1_700_000_000_000_000);
+        assert_eq!(times.value(1), 1_700_000_001_500_000);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_writer() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let mut file = tempfile::tempfile().unwrap();
+        let mut writer = ParquetWriter::new(file.try_clone().unwrap()).unwrap();
+        writer.write_pack(&pack()).unwrap();
+        writer.write_pack(&pack()).unwrap();
+        writer.finish().unwrap();
+
+        std::io::Seek::rewind(&mut file).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<_> = reader.map(|batch| batch.unwrap()).collect();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 6);
+        assert_eq!(batches[0].schema(), schema());
+    }
+}
@@ -1,6 +1,6 @@
 //! Builder pattern for creating SenML packs
 
-use crate::{SenMLPack, SenMLRecord, SenMLValue};
+use crate::{SenMLNumber, SenMLPack, SenMLRecord, SenMLValue};
 
 /// Builder for creating SenML packs with a fluent API
 #[derive(Debug, Default)]
@@ -49,8 +49,8 @@ impl SenMLBuilder {
         self
     }
 
-    /// Add a record with a numeric value
-    pub fn add_value<S: Into<String>>(mut self, name: S, value: f64) -> Self {
+    /// Add a record with a numeric value, integers are kept exact
+    pub fn add_value<S: Into<String>, V: Into<SenMLNumber>>(mut self, name: S, value: V) -> Self {
         self.records.push(SenMLRecord::with_value(name, value));
         self
     }
@@ -75,17 +75,22 @@ impl SenMLBuilder {
     }
 
     /// Add a measurement with timestamp
-    pub fn add_measurement<S: Into<String>>(mut self, name: S, value: f64, time: f64) -> Self {
+    pub fn add_measurement<S: Into<String>, V: Into<SenMLNumber>>(
+        mut self,
+        name: S,
+        value: V,
+        time: f64,
+    ) -> Self {
         self.records
             .push(SenMLRecord::with_value(name, value).with_time(time));
         self
     }
 
     /// Add a measurement with unit and timestamp
-    pub fn add_measurement_with_unit<S: Into<String>, U: Into<String>>(
+    pub fn add_measurement_with_unit<S: Into<String>, V: Into<SenMLNumber>, U: Into<String>>(
         mut self,
         name: S,
-        value: f64,
+        value: V,
         unit: U,
         time: f64,
     ) -> Self {
@@ -98,7 +103,12 @@ impl SenMLBuilder {
     }
 
     /// Add a sum measurement
-    pub fn add_sum<S: Into<String>>(mut self, name: S, sum: f64, time: f64) -> Self {
+    pub fn add_sum<S: Into<String>, V: Into<SenMLNumber>>(
+        mut self,
+        name: S,
+        sum: V,
+        time: f64,
+    ) -> Self {
         self.records.push(
             SenMLRecord::new()
                 .with_name(name)
@@ -301,7 +311,11 @@ impl ConfigBuilder {
 
         for (name, value) in self.parameters {
             let record = match value {
+                SenMLValue::Integer(i) => SenMLRecord::with_value(name, i),
+                SenMLValue::Unsigned(u) => SenMLRecord::with_value(name, u),
                 SenMLValue::Number(n) => SenMLRecord::with_value(name, n),
+                #[cfg(feature = "decimal")]
+                SenMLValue::Decimal(d) => SenMLRecord::with_value(name, d),
                 SenMLValue::String(s) => SenMLRecord::with_string_value(name, s),
                 SenMLValue::Boolean(b) => SenMLRecord::with_bool_value(name, b),
                 SenMLValue::Data(d) => SenMLRecord::with_data_value(name, d),
@@ -341,7 +355,7 @@ mod tests {
 
         assert_eq!(pack.records.len(), 1);
         let record = &pack.records[0];
-        assert_eq!(record.v, Some(25.0));
+        assert_eq!(record.v, Some(25.0.into()));
         assert_eq!(record.u, Some("Cel".to_string()));
         assert_eq!(record.t, Some(1640995200.0));
     }
@@ -400,6 +414,6 @@ mod tests {
 
         // Should not create base record if no base values
         assert_eq!(pack.records.len(), 1);
-        assert_eq!(pack.records[0].v, Some(42.0));
+        assert_eq!(pack.records[0].v, Some(42.0.into()));
     }
 }
@@ -6,9 +6,28 @@ use crate::{Result, SenMLPack};
 #[cfg(feature = "cbor")]
 impl SenMLPack {
     /// Serialize SenML pack to CBOR with specific options.
-    /// Uses RFC 8428 integer labels regardless of the `canonical` flag.
-    pub fn to_cbor_with_options(&self, _canonical: bool) -> Result<Vec<u8>> {
-        self.to_cbor()
+    /// Uses RFC 8428 integer labels regardless of the `canonical` flag; with
+    /// `canonical` set the output follows the deterministic encoding rules of
+    /// RFC 8949 §4.2.1.
+    pub fn to_cbor_with_options(&self, canonical: bool) -> Result<Vec<u8>> {
+        if canonical {
+            self.to_canonical_cbor()
+        } else {
+            self.to_cbor()
+        }
+    }
+
+    /// Serialize to deterministic CBOR (RFC 8949 §4.2.1)
+    ///
+    /// Equal packs always produce identical bytes, so the output can be
+    /// hashed, signed or compared across devices.
+    pub fn to_canonical_cbor(&self) -> Result<Vec<u8>> {
+        let mut value = self.to_cbor_value();
+        encoding::canonicalize(&mut value);
+        let mut buffer = Vec::new();
+        ciborium::ser::into_writer(&value, &mut buffer)
+            .map_err(|e| crate::SenMLError::serialization(e.to_string()))?;
+        Ok(buffer)
     }
 
     /// Deserialize from CBOR with validation
@@ -77,6 +96,38 @@ pub mod utils {
 #[cfg(feature = "cbor")]
 pub mod encoding {
     use super::*;
+    use ciborium::Value;
+
+    /// Rewrite a CBOR value into deterministic form (RFC 8949 §4.2.1)
+    ///
+    /// Map entries are sorted by the bytewise order of their encoded keys,
+    /// recursively. ciborium already writes integers, lengths and floats in
+    /// their shortest form and never uses indefinite lengths, so ordering is
+    /// all that is left to fix.
+    pub fn canonicalize(value: &mut Value) {
+        match value {
+            Value::Array(items) => items.iter_mut().for_each(canonicalize),
+            Value::Tag(_, inner) => canonicalize(inner),
+            Value::Map(entries) => {
+                for (key, val) in entries.iter_mut() {
+                    canonicalize(key);
+                    canonicalize(val);
+                }
+                // Keys that fail to encode cannot be serialized anyway
+                let mut keyed: Vec<(Vec<u8>, (Value, Value))> = entries
+                    .drain(..)
+                    .map(|entry| {
+                        let mut key = Vec::new();
+                        let _ = ciborium::ser::into_writer(&entry.0, &mut key);
+                        (key, entry)
+                    })
+                    .collect();
+                keyed.sort_by(|a, b| a.0.cmp(&b.0));
+                entries.extend(keyed.into_iter().map(|(_, entry)| entry));
+            }
+            _ => {}
+        }
+    }
 
     /// Encode SenML pack with space optimization
     pub fn encode_compact(pack: &SenMLPack) -> Result<Vec<u8>> {
@@ -158,6 +209,49 @@ mod tests {
         assert_eq!(from_canonical, from_regular);
     }
 
+    #[test]
+    fn test_cbor_canonical_is_deterministic() {
+        use ciborium::Value;
+
+        let mut pack = SenMLPack::new();
+        pack.add_record(
+            SenMLRecord::with_value("temp", 25.0)
+                .with_unit("Cel")
+                .with_time(1.0),
+        );
+        pack.records[0].bn = Some("urn:dev:1:".to_string());
+
+        let canonical = pack.to_canonical_cbor().unwrap();
+        assert_eq!(SenMLPack::from_cbor(&canonical).unwrap(), pack);
+
+        // Unsigned labels sort before negative ones: n(0) u(1) v(2) t(6) bn(-2)
+        let value: Value = ciborium::de::from_reader(&canonical[..]).unwrap();
+        let Value::Array(records) = value else {
+            panic!("expected array");
+        };
+        let Value::Map(entries) = &records[0] else {
+            panic!("expected map");
+        };
+        let labels: Vec<i128> = entries
+            .iter()
+            .map(|(k, _)| i128::from(k.as_integer().unwrap()))
+            .collect();
+        assert_eq!(labels, vec![0, 1, 2, 6, -2]);
+
+        // Map keys inside arbitrary values are ordered too
+        let mut a = Value::Map(vec![
+            (Value::Text("b".into()), Value::Integer(1.into())),
+            (Value::Text("a".into()), Value::Integer(2.into())),
+        ]);
+        let mut b = Value::Map(vec![
+            (Value::Text("a".into()), Value::Integer(2.into())),
+            (Value::Text("b".into()), Value::Integer(1.into())),
+        ]);
+        encoding::canonicalize(&mut a);
+        encoding::canonicalize(&mut b);
+        assert_eq!(a, b);
+    }
+
     #[test]
     fn test_utils_cbor_major_type() {
         let array_cbor = [0x80u8]; // Empty array
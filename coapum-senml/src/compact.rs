@@ -0,0 +1,275 @@
+//! SenML compaction - rewriting packs to use base values for smaller encodings
+
+use crate::normalize::{NormalizedPack, NormalizedRecord};
+use crate::{SenMLNumber, SenMLPack, SenMLRecord};
+
+/// Base values chosen for a compacted pack
+#[derive(Debug, Clone, Default)]
+struct Bases {
+    bn: Option<String>,
+    bt: Option<f64>,
+    bu: Option<String>,
+    bv: Option<f64>,
+    bs: Option<f64>,
+}
+
+/// Rewrite a pack so that it uses base values to minimise its CBOR size
+///
+/// The pack is normalized first, then base name, unit, time, value and sum are
+/// tried in turn. Each base is only kept if it makes the encoding smaller and
+/// the result still normalizes to exactly the same records (floating point
+/// offsets that do not round-trip are rejected). The returned pack is never
+/// larger than the input.
+pub(crate) fn compact(pack: &SenMLPack) -> SenMLPack {
+    let normalized = pack.normalize();
+    if normalized.records.is_empty() {
+        return pack.clone();
+    }
+
+    let plain = normalized.to_pack();
+    let mut bases = Bases::default();
+    let mut best = encode(&normalized, &plain, &bases);
+    let mut best_size = encoded_size(&best);
+
+    let candidates: [fn(&mut Bases, &[NormalizedRecord]) -> bool; 5] = [
+        |b, r| {
+            b.bn = common_name_prefix(r);
+            b.bn.is_some()
+        },
+        |b, r| {
+            b.bu = common_unit(r);
+            b.bu.is_some()
+        },
+        |b, r| {
+            b.bt = first_offset(r.iter().map(|r| r.time), true);
+            b.bt.is_some()
+        },
+        |b, r| {
+            b.bv = first_offset(r.iter().map(|r| r.value.map(|v| v.as_f64())), false);
+            b.bv.is_some()
+        },
+        |b, r| {
+            b.bs = first_offset(r.iter().map(|r| r.sum.map(|s| s.as_f64())), false);
+            b.bs.is_some()
+        },
+    ];
+
+    for apply in candidates {
+        let mut trial = bases.clone();
+        if !apply(&mut trial, &normalized.records) {
+            continue;
+        }
+
+        let candidate = encode(&normalized, &plain, &trial);
+        let size = encoded_size(&candidate);
+        if size < best_size && candidate.normalize() == normalized {
+            bases = trial;
+            best = candidate;
+            best_size = size;
+        }
+    }
+
+    if encoded_size(pack) <= best_size {
+        pack.clone()
+    } else {
+        best
+    }
+}
+
+/// Encode normalized records relative to the given base values
+///
+/// `plain` is the normalized pack converted back with `to_pack`, so string,
+/// boolean and data values are already in wire form.
+fn encode(normalized: &NormalizedPack, plain: &SenMLPack, bases: &Bases) -> SenMLPack {
+    let mut records: Vec<SenMLRecord> = normalized
+        .records
+        .iter()
+        .zip(plain.records.iter())
+        .map(|(nr, record)| {
+            let mut record = record.clone();
+
+            if let Some(bn) = &bases.bn {
+                let local = &nr.name[bn.len()..];
+                record.n = (!local.is_empty()).then(|| local.to_string());
+            }
+
+            if bases.bu.is_some() && nr.unit == bases.bu {
+                record.u = None;
+            }
+
+            if let Some(bt) = bases.bt {
+                // A record without `t` resolves to `bt`, so zero offsets can be dropped
+                record.t = nr.time.map(|t| t - bt).filter(|offset| *offset != 0.0);
+            }
+
+            if let Some(bv) = bases.bv {
+                record.v = nr.value.map(|v| v - SenMLNumber::Float(bv));
+            }
+
+            if let Some(bs) = bases.bs {
+                record.s = nr.sum.map(|s| s - SenMLNumber::Float(bs));
+            }
+
+            record
+        })
+        .collect();
+
+    if let Some(first) = records.first_mut() {
+        first.bn = bases.bn.clone();
+        first.bt = bases.bt;
+        first.bu = bases.bu.clone();
+        first.bv = bases.bv;
+        first.bs = bases.bs;
+        first.bver = normalized.version;
+    }
+
+    SenMLPack { records }
+}
+
+/// Longest common name prefix shared by every record, if there is more than one
+fn common_name_prefix(records: &[NormalizedRecord]) -> Option<String> {
+    if records.len() < 2 {
+        return None;
+    }
+
+    let first = records[0].name.as_str();
+    let mut len = first.len();
+    for record in &records[1..] {
+        len = first
+            .char_indices()
+            .zip(record.name.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((i, c), _)| i + c.len_utf8())
+            .min(len);
+    }
+
+    (len > 0).then(|| first[..len].to_string())
+}
+
+/// Most frequent unit, only if every record carries a unit
+///
+/// A record without a unit would otherwise inherit the base unit.
+fn common_unit(records: &[NormalizedRecord]) -> Option<String> {
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for record in records {
+        let unit = record.unit.as_deref()?;
+        match counts.iter_mut().find(|(u, _)| *u == unit) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((unit, 1)),
+        }
+    }
+
+    counts
+        .iter()
+        .enumerate()
+        .max_by_key(|(i, (_, count))| (*count, std::cmp::Reverse(*i)))
+        .map(|(_, (unit, _))| unit.to_string())
+}
+
+/// First non-zero value to use as a base offset
+///
+/// When `require_all` is set every record must carry the field, since records
+/// without it would pick up the base (as happens with base time).
+fn first_offset(values: impl Iterator<Item = Option<f64>>, require_all: bool) -> Option<f64> {
+    let mut first = None;
+    for value in values {
+        match value {
+            Some(v) if first.is_none() => first = Some(v),
+            Some(_) => {}
+            None if require_all => return None,
+            None => {}
+        }
+    }
+
+    // Normalization ignores a zero base, so it would not save anything
+    first.filter(|v| *v != 0.0 && v.is_finite())
+}
+
+fn encoded_size(pack: &SenMLPack) -> usize {
+    pack.to_cbor().map_or(usize::MAX, |bytes| bytes.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{SenMLBuilder, SenMLPack, SenMLRecord};
+
+    fn assert_equivalent(pack: &SenMLPack) -> SenMLPack {
+        let compacted = pack.compact();
+        assert_eq!(compacted.normalize(), pack.normalize());
+        assert!(compacted.to_cbor().unwrap().len() <= pack.to_cbor().unwrap().len());
+        compacted
+    }
+
+    #[test]
+    fn test_compact_time_series() {
+        let mut pack = SenMLPack::new();
+        for i in 0..10 {
+            pack.add_record(
+                SenMLRecord::with_value("urn:dev:ow:10e2073a01080063/temp", 20.0 + i as f64)
+                    .with_unit("Cel")
+                    .with_time(1_640_995_200.0 + i as f64 * 60.0),
+            );
+        }
+
+        let compacted = assert_equivalent(&pack);
+        let first = &compacted.records[0];
+        assert_eq!(
+            first.bn.as_deref(),
+            Some("urn:dev:ow:10e2073a01080063/temp")
+        );
+        assert_eq!(first.bu.as_deref(), Some("Cel"));
+        assert_eq!(first.bt, Some(1_640_995_200.0));
+        assert!(compacted.records.iter().all(|r| r.n.is_none()));
+        assert!(compacted.to_cbor().unwrap().len() < pack.to_cbor().unwrap().len());
+    }
+
+    #[test]
+    fn test_compact_mixed_records() {
+        let mut pack = SenMLPack::new();
+        pack.add_record(SenMLRecord::with_value("dev/temp", 22.5).with_unit("Cel"));
+        pack.add_record(SenMLRecord::with_value("dev/hum", 45.0).with_time(10.0));
+        pack.add_record(SenMLRecord::with_string_value("dev/state", "running"));
+        pack.add_record(SenMLRecord::with_bool_value("dev/door", true));
+        pack.add_record(SenMLRecord::with_data_value("dev/blob", vec![1, 2, 3]));
+        pack.add_record(SenMLRecord::with_value("dev/energy", 1.0).with_sum(1500.0));
+
+        let compacted = assert_equivalent(&pack);
+        assert_eq!(compacted.records[0].bn.as_deref(), Some("dev/"));
+        // Not every record has a unit or time, so those bases must not be used
+        assert_eq!(compacted.records[0].bu, None);
+        assert_eq!(compacted.records[0].bt, None);
+    }
+
+    #[test]
+    fn test_compact_already_compact_pack() {
+        let pack = SenMLBuilder::new()
+            .base_name("sensor/")
+            .base_time(1_640_995_200.0)
+            .base_unit("Cel")
+            .add_measurement("temp", 22.5, 0.0)
+            .add_measurement("temp", 22.7, 60.0)
+            .build();
+
+        assert_equivalent(&pack);
+    }
+
+    #[test]
+    fn test_compact_preserves_version() {
+        let mut pack = SenMLPack::new();
+        pack.add_record(SenMLRecord {
+            bver: Some(10),
+            ..SenMLRecord::with_value("a/x", 1.0)
+        });
+        pack.add_record(SenMLRecord::with_value("a/y", 2.0));
+
+        let compacted = assert_equivalent(&pack);
+        assert_eq!(compacted.records[0].bver, Some(10));
+    }
+
+    #[test]
+    fn test_compact_empty_pack() {
+        let pack = SenMLPack::new();
+        assert_eq!(pack.compact(), pack);
+    }
+}
@@ -0,0 +1,239 @@
+//! CSV export of normalized packs
+//!
+//! [`CsvWriter`] writes one row per record with the columns
+//! `name,time,unit,value`, ready for spreadsheets and warehouse loaders.
+//! Rows are written as records are handed to it, so packs read one at a
+//! time from a device log can be exported without holding them all.
+//! [`NormalizedPack::to_csv`] exports a single pack to a string.
+//!
+//! The value column holds whichever value the record carries: numbers as
+//! written, booleans as `true`/`false` and data values base64-encoded.
+//! Records carrying only a sum leave it empty. Fields containing commas,
+//! quotes or line breaks are quoted as described in RFC 4180.
+
+use std::io::Write;
+
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+use crate::record::base64_encode;
+use crate::{NormalizedPack, NormalizedRecord, Result, SenMLError, SenMLValue};
+
+/// How the time column is written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeFormat {
+    /// SenML time as is: seconds since the Unix epoch, or relative to the
+    /// current time for times below 2^28
+    #[default]
+    Seconds,
+    /// Milliseconds since the Unix epoch
+    UnixMillis,
+    /// RFC 3339 timestamp in UTC, e.g. `2011-10-31T13:24:24Z`
+    Rfc3339,
+}
+
+/// Writes normalized records as CSV rows
+///
+/// The header row is written before the first record.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum_senml::csv::{CsvWriter, TimeFormat};
+/// use coapum_senml::{SenMLBuilder, NormalizedPack};
+///
+/// let pack = SenMLBuilder::new()
+///     .base_name("urn:dev:ow:10e2073a01080063:")
+///     .base_time(1.320067464e+09)
+///     .base_unit("Cel")
+///     .add_value("temp", 23.1)
+///     .build();
+///
+/// let mut writer = CsvWriter::new(Vec::new()).time_format(TimeFormat::Rfc3339);
+/// writer.write_pack(&NormalizedPack::from_pack(&pack))?;
+/// let csv = String::from_utf8(writer.into_inner()).unwrap();
+///
+/// assert_eq!(
+///     csv,
+///     "name,time,unit,value\n\
+///      urn:dev:ow:10e2073a01080063:temp,2011-10-31T13:24:24Z,Cel,23.1\n"
+/// );
+/// # Ok::<(), coapum_senml::SenMLError>(())
+/// ```
+pub struct CsvWriter<W> {
+    out: W,
+    time_format: TimeFormat,
+    header: bool,
+    /// Whether a row was written
+    started: bool,
+}
+
+impl<W: Write> CsvWriter<W> {
+    /// Write rows to `out`
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            time_format: TimeFormat::default(),
+            header: true,
+            started: false,
+        }
+    }
+
+    /// Format of the time column. Default: [`TimeFormat::Seconds`].
+    pub fn time_format(mut self, format: TimeFormat) -> Self {
+        self.time_format = format;
+        self
+    }
+
+    /// Whether to write the header row, e.g. `false` when appending to an
+    /// existing file. Default: `true`.
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Write a row for `record`
+    pub fn write_record(&mut self, record: &NormalizedRecord) -> Result<()> {
+        if !self.started && self.header {
+            self.out
+                .write_all(b"name,time,unit,value\n")
+                .map_err(|e| SenMLError::serialization(e.to_string()))?;
+        }
+        self.started = true;
+
+        let time = self.format_time(record)?;
+        let value = match record.primary_value() {
+            Some(SenMLValue::Integer(i)) => i.to_string(),
+            Some(SenMLValue::Unsigned(u)) => u.to_string(),
+            Some(SenMLValue::Number(v)) => v.to_string(),
+            #[cfg(feature = "decimal")]
+            Some(SenMLValue::Decimal(d)) => d.to_string(),
+            Some(SenMLValue::String(s)) => s,
+            Some(SenMLValue::Boolean(b)) => b.to_string(),
+            Some(SenMLValue::Data(d)) => base64_encode(&d),
+            None => String::new(),
+        };
+        let row = [
+            escape(&record.name),
+            time,
+            escape(record.unit.as_deref().unwrap_or_default()),
+            escape(&value),
+        ]
+        .join(",");
+        writeln!(self.out, "{}", row).map_err(|e| SenMLError::serialization(e.to_string()))
+    }
+
+    /// Write a row for every record of `pack`
+    pub fn write_pack(&mut self, pack: &NormalizedPack) -> Result<()> {
+        pack.records
+            .iter()
+            .try_for_each(|record| self.write_record(record))
+    }
+
+    /// Flush the output and return it
+    pub fn into_inner(mut self) -> W {
+        let _ = self.out.flush();
+        self.out
+    }
+
+    fn format_time(&self, record: &NormalizedRecord) -> Result<String> {
+        let time = match self.time_format {
+            TimeFormat::Seconds => record.time,
+            TimeFormat::UnixMillis | TimeFormat::Rfc3339 => record.absolute_time(),
+        };
+        let Some(time) = time else {
+            return Ok(String::new());
+        };
+        match self.time_format {
+            TimeFormat::Seconds => Ok(time.to_string()),
+            TimeFormat::UnixMillis => Ok(((time * 1000.0).round() as i64).to_string()),
+            // f64 seconds hold about microsecond precision at current times
+            TimeFormat::Rfc3339 => {
+                OffsetDateTime::from_unix_timestamp_nanos((time * 1e6).round() as i128 * 1000)
+                    .map_err(|e| SenMLError::time(e.to_string()))?
+                    .format(&Rfc3339)
+                    .map_err(|e| SenMLError::time(e.to_string()))
+            }
+        }
+    }
+}
+
+impl NormalizedPack {
+    /// The records of the pack as CSV, with times as SenML seconds
+    ///
+    /// Use [`CsvWriter`] for other time formats or to write to a file.
+    pub fn to_csv(&self) -> Result<String> {
+        let mut writer = CsvWriter::new(Vec::new());
+        writer.write_pack(self)?;
+        String::from_utf8(writer.into_inner()).map_err(|e| SenMLError::serialization(e.to_string()))
+    }
+}
+
+/// Quote `field` if it contains a separator, quote or line break
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SenMLPack, SenMLRecord};
+
+    fn pack() -> NormalizedPack {
+        let pack = SenMLPack {
+            records: vec![
+                SenMLRecord {
+                    bn: Some("dev:".to_string()),
+                    bt: Some(1_700_000_000.0),
+                    ..SenMLRecord::with_value("temp", 21.5)
+                },
+                SenMLRecord {
+                    u: Some("%RH".to_string()),
+                    t: Some(0.25),
+                    ..SenMLRecord::with_value("hum", 40.0)
+                },
+                SenMLRecord::with_string_value("status", "ok, \"idle\""),
+                SenMLRecord::with_bool_value("door", true),
+            ],
+        };
+        NormalizedPack::from_pack(&pack)
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let csv = pack().to_csv().unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "name,time,unit,value",
+                "dev:temp,1700000000,,21.5",
+                "dev:hum,1700000000.25,%RH,40",
+                "dev:status,1700000000,,\"ok, \"\"idle\"\"\"",
+                "dev:door,1700000000,,true",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_time_formats() {
+        let pack = pack();
+        let rows = |format| {
+            let mut writer = CsvWriter::new(Vec::new()).time_format(format).header(false);
+            writer.write_record(&pack.records[1]).unwrap();
+            String::from_utf8(writer.into_inner()).unwrap()
+        };
+        assert_eq!(
+            rows(TimeFormat::UnixMillis),
+            "dev:hum,1700000000250,%RH,40\n"
+        );
+        assert_eq!(
+            rows(TimeFormat::Rfc3339),
+            "dev:hum,2023-11-14T22:13:20.25Z,%RH,40\n"
+        );
+    }
+}
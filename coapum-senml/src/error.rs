@@ -112,6 +112,104 @@ impl SenMLError {
     }
 }
 
+/// Category of a [`ValidationIssue`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IssueKind {
+    /// The pack has no records
+    EmptyPack,
+    /// A record has neither a value nor a sum
+    MissingValue,
+    /// A record has more than one value field
+    MultipleValues,
+    /// A numeric field is not finite or out of range
+    InvalidNumber,
+    /// A data value is not valid base64
+    InvalidEncoding,
+    /// A name fails strict name validation
+    InvalidName,
+    /// A name uses a pattern reserved by RFC 8428
+    ReservedName,
+    /// A measurement is missing its required unit or uses another one
+    UnitMismatch,
+    /// A unit is not in the SenML Units registry
+    UnregisteredUnit,
+    /// The pack declares an unsupported SenML version
+    UnsupportedVersion,
+    /// The same name appears more than once at the same time
+    DuplicateEntry,
+    /// Timestamps go backward by more than the allowed drift
+    TimeDrift,
+}
+
+/// A single problem found while validating a pack or record
+///
+/// Reported by [`SenMLPack::validation_issues`](crate::SenMLPack::validation_issues),
+/// [`SenMLRecord::validation_issues`](crate::SenMLRecord::validation_issues)
+/// and, with the `validation` feature, `PackValidator::validate_all`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    /// Index of the offending record, or `None` for pack-level issues
+    pub record_index: Option<usize>,
+    /// SenML field label the issue relates to (e.g. `"v"`, `"bt"`), if any
+    pub field: Option<&'static str>,
+    /// Category of the issue
+    pub kind: IssueKind,
+    /// Human readable description
+    pub message: String,
+}
+
+impl ValidationIssue {
+    pub(crate) fn pack(kind: IssueKind, message: impl Into<String>) -> Self {
+        Self {
+            record_index: None,
+            field: None,
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn record(
+        index: usize,
+        field: &'static str,
+        kind: IssueKind,
+        message: String,
+    ) -> Self {
+        Self::field(field, kind, message).at(index)
+    }
+
+    /// An issue with a field of a record whose index is not known yet
+    pub(crate) fn field(field: &'static str, kind: IssueKind, message: String) -> Self {
+        Self {
+            record_index: None,
+            field: Some(field),
+            kind,
+            message,
+        }
+    }
+
+    /// Attribute this issue to the record at `index`
+    pub(crate) fn at(mut self, index: usize) -> Self {
+        self.record_index = Some(index);
+        self
+    }
+}
+
+impl std::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.record_index, self.field) {
+            (Some(i), Some(field)) => write!(f, "Record {} field '{}': {}", i, field, self.message),
+            (Some(i), None) => write!(f, "Record {}: {}", i, self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl From<ValidationIssue> for SenMLError {
+    fn from(issue: ValidationIssue) -> Self {
+        Self::validation(issue.to_string())
+    }
+}
+
 #[cfg(feature = "json")]
 impl From<serde_json::Error> for SenMLError {
     fn from(err: serde_json::Error) -> Self {
@@ -14,6 +14,7 @@
 //! - **Normalization**: Convert SenML packs to resolved form
 //! - **Builder Pattern**: Ergonomic API for creating SenML data
 //! - **Time Series**: Specialized support for time-series sensor data
+//! - **Streaming**: Read and write SenSML streams record by record
 //!
 //! ## Quick Start
 //!
@@ -45,10 +46,13 @@
 //! subsequent records in the pack.
 
 pub mod builder;
+pub mod csv;
 pub mod error;
 pub mod normalize;
+pub mod number;
 pub mod pack;
 pub mod record;
+pub mod units;
 
 #[cfg(feature = "validation")]
 pub mod validation;
@@ -59,16 +63,35 @@ pub mod json;
 #[cfg(feature = "cbor")]
 pub mod cbor;
 
+#[cfg(feature = "cbor")]
+mod compact;
+
+#[cfg(feature = "cbor")]
+pub mod writer;
+
+#[cfg(all(feature = "json", feature = "cbor"))]
+pub mod stream;
+
 #[cfg(feature = "xml")]
 pub mod xml;
 
+#[cfg(feature = "arrow")]
+pub mod arrow;
+
 // Re-export main types
 pub use builder::SenMLBuilder;
-pub use error::{Result, SenMLError};
+pub use error::{IssueKind, Result, SenMLError, ValidationIssue};
 pub use normalize::{NormalizedPack, NormalizedRecord};
+pub use number::SenMLNumber;
 pub use pack::SenMLPack;
 pub use record::{SenMLRecord, SenMLValue};
 
+#[cfg(feature = "cbor")]
+pub use writer::PackWriter;
+
+#[cfg(all(feature = "json", feature = "cbor"))]
+pub use stream::{SensMLStream, SensMLWriter, StreamFormat};
+
 #[cfg(feature = "validation")]
 pub use validation::Validate;
 
@@ -107,6 +130,6 @@ mod tests {
         // Check that base name was stored in the first record's bn field
         assert_eq!(pack.records[0].bn, Some("urn:dev:sensor1".to_string()));
         // Check that measurement record exists
-        assert_eq!(pack.records[1].v, Some(22.5));
+        assert_eq!(pack.records[1].v, Some(22.5.into()));
     }
 }
@@ -1,8 +1,11 @@
 //! SenML normalization - converting packs to resolved form
 
-use crate::{Result, SenMLError, SenMLPack, SenMLRecord, SenMLValue};
+use crate::{Result, SenMLError, SenMLNumber, SenMLPack, SenMLRecord, SenMLValue};
 use serde::{Deserialize, Serialize};
 
+/// Times below 2^28 seconds are relative to the current time
+const RELATIVE_TIME_THRESHOLD: f64 = 268435456.0;
+
 /// A normalized SenML pack where all base values have been resolved into individual records
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct NormalizedPack {
@@ -20,7 +23,7 @@ pub struct NormalizedRecord {
     /// Resolved unit (base unit or record unit)
     pub unit: Option<String>,
     /// Resolved numeric value (base value + record value)
-    pub value: Option<f64>,
+    pub value: Option<SenMLNumber>,
     /// String value (unchanged)
     pub string_value: Option<String>,
     /// Boolean value (unchanged)
@@ -28,7 +31,7 @@ pub struct NormalizedRecord {
     /// Data value (unchanged)  
     pub data_value: Option<Vec<u8>>,
     /// Resolved sum (base sum + record sum)
-    pub sum: Option<f64>,
+    pub sum: Option<SenMLNumber>,
     /// Resolved timestamp (base time + record time)
     pub time: Option<f64>,
     /// Update time (unchanged)
@@ -93,14 +96,14 @@ impl NormalizedPack {
 
         // Resolve numeric value (add base value if both present)
         let value = match (record.v, base_value != 0.0) {
-            (Some(v), true) => Some(v + base_value),
+            (Some(v), true) => Some(v + SenMLNumber::Float(base_value)),
             (Some(v), false) => Some(v),
             (None, _) => None,
         };
 
         // Resolve sum (add base sum if both present)
         let sum = match (record.s, base_sum != 0.0) {
-            (Some(s), true) => Some(s + base_sum),
+            (Some(s), true) => Some(s + SenMLNumber::Float(base_sum)),
             (Some(s), false) => Some(s),
             (None, _) => None,
         };
@@ -224,7 +227,7 @@ impl NormalizedRecord {
     /// Get the primary value from this record
     pub fn primary_value(&self) -> Option<SenMLValue> {
         if let Some(v) = self.value {
-            Some(SenMLValue::Number(v))
+            Some(v.into())
         } else if let Some(ref vs) = self.string_value {
             Some(SenMLValue::String(vs.clone()))
         } else if let Some(vb) = self.bool_value {
@@ -236,6 +239,24 @@ impl NormalizedRecord {
         }
     }
 
+    /// The time of this record in seconds since the Unix epoch
+    ///
+    /// Times below 2^28 are relative to the current time (RFC 8428 §4.5.3)
+    /// and are resolved against it.
+    pub fn absolute_time(&self) -> Option<f64> {
+        self.time.map(|time| {
+            if time < RELATIVE_TIME_THRESHOLD {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs_f64();
+                now + time
+            } else {
+                time
+            }
+        })
+    }
+
     /// Check if this record has any value
     pub fn has_value(&self) -> bool {
         self.value.is_some()
@@ -401,7 +422,7 @@ mod tests {
         assert_eq!(normalized.records.len(), 1);
         let record = &normalized.records[0];
         assert_eq!(record.name, "device1/temp");
-        assert_eq!(record.value, Some(22.5));
+        assert_eq!(record.value, Some(22.5.into()));
         assert_eq!(record.unit, Some("Cel".to_string()));
         assert_eq!(record.time, Some(1640995200.0));
     }
@@ -420,7 +441,7 @@ mod tests {
         assert_eq!(normalized.records.len(), 1);
         let record = &normalized.records[0];
         assert_eq!(record.name, "sensor/temp");
-        assert_eq!(record.value, Some(22.5)); // 20.0 + 2.5
+        assert_eq!(record.value, Some(22.5.into())); // 20.0 + 2.5
         assert_eq!(record.time, Some(1060.0)); // 1000.0 + 60.0
     }
 
@@ -434,7 +455,49 @@ mod tests {
         assert_eq!(normalized.records.len(), 1);
         let record = &normalized.records[0];
         assert_eq!(record.name, "standalone");
-        assert_eq!(record.value, Some(42.0));
+        assert_eq!(record.value, Some(42.0.into()));
+    }
+
+    #[test]
+    fn test_normalization_keeps_integers_exact() {
+        let pack = SenMLBuilder::new()
+            .base_name("meter/")
+            .base_value(1000.0)
+            .add_value("energy", 9_007_199_254_740_993_i64)
+            .add_value("import", u64::MAX - 1000)
+            .add_value("ratio", 0.5)
+            .build();
+
+        let normalized = pack.normalize();
+        assert_eq!(
+            normalized.records[0].value,
+            Some(SenMLNumber::Integer(9_007_199_254_740_993 + 1000))
+        );
+        assert_eq!(
+            normalized.records[1].value.and_then(SenMLNumber::as_u64),
+            Some(u64::MAX)
+        );
+        assert_eq!(
+            normalized.records[2].value,
+            Some(SenMLNumber::Float(1000.5))
+        );
+    }
+
+    #[cfg(all(feature = "decimal", feature = "json"))]
+    #[test]
+    fn test_normalization_keeps_decimals_exact() {
+        let pack = SenMLPack::from_json(
+            r#"[{"bn":"meter/","bv":0.1,"bs":0.2,"n":"kwh","v":0.2,"s":0.1}]"#,
+        )
+        .unwrap();
+
+        let record = &pack.normalize().records[0];
+        assert_eq!(record.value.unwrap().to_string(), "0.3");
+        assert_eq!(record.sum.unwrap().to_string(), "0.3");
+        assert_eq!(
+            record.value.unwrap().as_decimal(),
+            Some("0.3".parse().unwrap())
+        );
     }
 
     #[test]
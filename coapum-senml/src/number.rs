@@ -0,0 +1,504 @@
+//! Numeric SenML values
+//!
+//! SenML does not distinguish integers from floating point numbers, but
+//! widening every value to `f64` rounds counters and timestamps above 2^53.
+//! [`SenMLNumber`] keeps integers exact and, with the `decimal` feature,
+//! decimal fractions as well.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[cfg(feature = "decimal")]
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+
+/// CBOR tag for decimal fractions (RFC 8949 §3.4.4)
+#[cfg(feature = "cbor")]
+const DECIMAL_FRACTION_TAG: u64 = 4;
+
+/// Numeric SenML value (the `v` and `s` fields)
+///
+/// Integers are kept as `i64`, or `u64` when they do not fit, and only become
+/// floating point when combined with a fractional value.
+///
+/// With the `decimal` feature, fractional values read from JSON and CBOR
+/// decimal fractions are kept as [`rust_decimal::Decimal`], so billing values
+/// such as `0.1` add up exactly. JSON numbers are parsed as `f64` first and
+/// converted through their shortest representation, which recovers the
+/// original digits for values with up to 15 significant digits.
+///
+/// Equality and ordering compare the numeric value, so `Integer(20)` equals
+/// `Float(20.0)`.
+#[derive(Debug, Clone, Copy)]
+pub enum SenMLNumber {
+    /// Signed integer
+    Integer(i64),
+    /// Unsigned integer larger than `i64::MAX`
+    Unsigned(u64),
+    /// Floating point value
+    Float(f64),
+    /// Exact decimal value
+    #[cfg(feature = "decimal")]
+    Decimal(Decimal),
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Add,
+    Sub,
+}
+
+impl SenMLNumber {
+    /// The value as `f64`, rounding integers above 2^53
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Self::Integer(i) => i as f64,
+            Self::Unsigned(u) => u as f64,
+            Self::Float(f) => f,
+            #[cfg(feature = "decimal")]
+            Self::Decimal(d) => d.to_f64().unwrap_or(f64::NAN),
+        }
+    }
+
+    /// The value as `i64`, if it is an integer in range
+    pub fn as_i64(self) -> Option<i64> {
+        self.as_i128().and_then(|i| i64::try_from(i).ok())
+    }
+
+    /// The value as `u64`, if it is a non-negative integer in range
+    pub fn as_u64(self) -> Option<u64> {
+        self.as_i128().and_then(|i| u64::try_from(i).ok())
+    }
+
+    /// The value as a decimal, if it can be represented as one
+    ///
+    /// Floats are converted through their shortest representation.
+    #[cfg(feature = "decimal")]
+    pub fn as_decimal(self) -> Option<Decimal> {
+        match self {
+            Self::Integer(i) => Some(i.into()),
+            Self::Unsigned(u) => Some(u.into()),
+            Self::Float(f) => decimal_from_f64(f),
+            Self::Decimal(d) => Some(d),
+        }
+    }
+
+    /// Returns true unless the value is an infinite or NaN float
+    pub fn is_finite(self) -> bool {
+        match self {
+            Self::Float(f) => f.is_finite(),
+            _ => true,
+        }
+    }
+
+    /// Returns true for the integer variants
+    pub fn is_integer(self) -> bool {
+        matches!(self, Self::Integer(_) | Self::Unsigned(_))
+    }
+
+    /// Exact integer value, including floats without a fractional part
+    fn as_i128(self) -> Option<i128> {
+        match self {
+            Self::Integer(i) => Some(i.into()),
+            Self::Unsigned(u) => Some(u.into()),
+            Self::Float(f) if f.fract() == 0.0 && f.abs() < i128::MAX as f64 => Some(f as i128),
+            Self::Float(_) => None,
+            #[cfg(feature = "decimal")]
+            Self::Decimal(d) if d.fract().is_zero() => d.to_i128(),
+            #[cfg(feature = "decimal")]
+            Self::Decimal(_) => None,
+        }
+    }
+
+    fn from_i128(value: i128) -> Self {
+        if let Ok(i) = i64::try_from(value) {
+            Self::Integer(i)
+        } else if let Ok(u) = u64::try_from(value) {
+            Self::Unsigned(u)
+        } else {
+            Self::Float(value as f64)
+        }
+    }
+
+    /// Build a value from a decimal fraction `mantissa * 10^exponent`
+    #[cfg(feature = "cbor")]
+    fn from_decimal_fraction(mantissa: i128, exponent: i64) -> Option<Self> {
+        #[cfg(feature = "decimal")]
+        {
+            let decimal = if exponent <= 0 {
+                u32::try_from(-exponent)
+                    .ok()
+                    .and_then(|scale| Decimal::try_from_i128_with_scale(mantissa, scale).ok())
+            } else {
+                u32::try_from(exponent)
+                    .ok()
+                    .and_then(|exponent| 10i128.checked_pow(exponent))
+                    .and_then(|factor| mantissa.checked_mul(factor))
+                    .and_then(|value| Decimal::try_from_i128_with_scale(value, 0).ok())
+            };
+            if let Some(decimal) = decimal {
+                return Some(Self::Decimal(decimal));
+            }
+        }
+
+        // Anything beyond this over- or underflows f64 anyway
+        let exponent = exponent.clamp(-400, 400) as i32;
+        if exponent >= 0 {
+            let exact = 10i128
+                .checked_pow(exponent.unsigned_abs())
+                .and_then(|factor| mantissa.checked_mul(factor));
+            if let Some(value) = exact {
+                return Some(Self::from_i128(value));
+            }
+            Some(Self::Float(mantissa as f64 * 10f64.powi(exponent)))
+        } else {
+            Some(Self::Float(mantissa as f64 / 10f64.powi(-exponent)))
+        }
+    }
+
+    /// Encode as a CBOR integer, float or decimal fraction
+    #[cfg(feature = "cbor")]
+    pub(crate) fn to_cbor_value(self) -> ciborium::Value {
+        use ciborium::Value;
+
+        match self {
+            Self::Integer(i) => Value::Integer(i.into()),
+            Self::Unsigned(u) => Value::Integer(u.into()),
+            Self::Float(f) => Value::Float(f),
+            #[cfg(feature = "decimal")]
+            Self::Decimal(d) => match ciborium::value::Integer::try_from(d.mantissa()) {
+                Ok(mantissa) => Value::Tag(
+                    DECIMAL_FRACTION_TAG,
+                    Box::new(Value::Array(vec![
+                        Value::Integer((-i64::from(d.scale())).into()),
+                        Value::Integer(mantissa),
+                    ])),
+                ),
+                Err(_) => Value::Float(self.as_f64()),
+            },
+        }
+    }
+
+    /// Decode a CBOR integer, float or decimal fraction
+    #[cfg(feature = "cbor")]
+    pub(crate) fn from_cbor_value(value: &ciborium::Value) -> Option<Self> {
+        use ciborium::Value;
+
+        match value {
+            Value::Float(f) => Some(Self::Float(*f)),
+            Value::Integer(i) => {
+                let i = i128::from(*i);
+                i64::try_from(i)
+                    .map(Self::Integer)
+                    .or_else(|_| u64::try_from(i).map(Self::Unsigned))
+                    .ok()
+            }
+            Value::Tag(DECIMAL_FRACTION_TAG, inner) => {
+                let [exponent, mantissa] = inner.as_array()?.as_slice() else {
+                    return None;
+                };
+                let exponent = i64::try_from(i128::from(exponent.as_integer()?)).ok()?;
+                Self::from_decimal_fraction(i128::from(mantissa.as_integer()?), exponent)
+            }
+            _ => None,
+        }
+    }
+
+    /// Apply an arithmetic operation, keeping decimals and integers exact
+    /// while the result fits
+    fn combine(self, rhs: Self, op: Op) -> Self {
+        #[cfg(feature = "decimal")]
+        if matches!(self, Self::Decimal(_)) || matches!(rhs, Self::Decimal(_)) {
+            let exact = self
+                .as_decimal()
+                .zip(rhs.as_decimal())
+                .and_then(|(a, b)| match op {
+                    Op::Add => a.checked_add(b),
+                    Op::Sub => a.checked_sub(b),
+                });
+            if let Some(value) = exact {
+                return Self::Decimal(value);
+            }
+        }
+
+        if self.is_integer() || rhs.is_integer() {
+            let exact = self
+                .as_i128()
+                .zip(rhs.as_i128())
+                .and_then(|(a, b)| match op {
+                    Op::Add => a.checked_add(b),
+                    Op::Sub => a.checked_sub(b),
+                });
+            if let Some(value) = exact {
+                return Self::from_i128(value);
+            }
+        }
+
+        let (a, b) = (self.as_f64(), rhs.as_f64());
+        Self::Float(match op {
+            Op::Add => a + b,
+            Op::Sub => a - b,
+        })
+    }
+}
+
+/// Convert a float to the decimal with the same shortest representation
+#[cfg(feature = "decimal")]
+fn decimal_from_f64(value: f64) -> Option<Decimal> {
+    if !value.is_finite() {
+        return None;
+    }
+    value.to_string().parse().ok()
+}
+
+impl std::ops::Add for SenMLNumber {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        self.combine(rhs, Op::Add)
+    }
+}
+
+impl std::ops::Sub for SenMLNumber {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        self.combine(rhs, Op::Sub)
+    }
+}
+
+impl PartialEq for SenMLNumber {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for SenMLNumber {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if let (Self::Float(a), Self::Float(b)) = (self, other) {
+            return a.partial_cmp(b);
+        }
+
+        #[cfg(feature = "decimal")]
+        if let (Some(a), Some(b)) = (self.as_decimal(), other.as_decimal()) {
+            return Some(a.cmp(&b));
+        }
+
+        match (self.as_i128(), other.as_i128()) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => self.as_f64().partial_cmp(&other.as_f64()),
+        }
+    }
+}
+
+#[cfg(feature = "validation")]
+impl validator::ValidateRange<SenMLNumber> for SenMLNumber {
+    fn greater_than(&self, max: SenMLNumber) -> Option<bool> {
+        Some(*self > max)
+    }
+
+    fn less_than(&self, min: SenMLNumber) -> Option<bool> {
+        Some(*self < min)
+    }
+}
+
+#[cfg(feature = "validation")]
+impl validator::ValidateRange<SenMLNumber> for Option<SenMLNumber> {
+    fn greater_than(&self, max: SenMLNumber) -> Option<bool> {
+        self.map(|v| v > max)
+    }
+
+    fn less_than(&self, min: SenMLNumber) -> Option<bool> {
+        self.map(|v| v < min)
+    }
+}
+
+impl fmt::Display for SenMLNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer(i) => write!(f, "{}", i),
+            Self::Unsigned(u) => write!(f, "{}", u),
+            Self::Float(v) => write!(f, "{}", v),
+            #[cfg(feature = "decimal")]
+            Self::Decimal(d) => write!(f, "{}", d),
+        }
+    }
+}
+
+impl Serialize for SenMLNumber {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Self::Integer(i) => serializer.serialize_i64(i),
+            Self::Unsigned(u) => serializer.serialize_u64(u),
+            Self::Float(f) => serializer.serialize_f64(f),
+            // The shortest representation of the nearest f64 prints the
+            // decimal's digits for up to 15 significant digits
+            #[cfg(feature = "decimal")]
+            Self::Decimal(_) => serializer.serialize_f64(self.as_f64()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SenMLNumber {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NumberVisitor;
+
+        impl Visitor<'_> for NumberVisitor {
+            type Value = SenMLNumber;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a number")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(SenMLNumber::Integer(v))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(v.into())
+            }
+
+            fn visit_i128<E: de::Error>(self, v: i128) -> Result<Self::Value, E> {
+                Ok(SenMLNumber::from_i128(v))
+            }
+
+            fn visit_u128<E: de::Error>(self, v: u128) -> Result<Self::Value, E> {
+                Ok(i128::try_from(v)
+                    .map(SenMLNumber::from_i128)
+                    .unwrap_or(SenMLNumber::Float(v as f64)))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                #[cfg(feature = "decimal")]
+                if let Some(decimal) = decimal_from_f64(v) {
+                    return Ok(SenMLNumber::Decimal(decimal));
+                }
+                Ok(SenMLNumber::Float(v))
+            }
+        }
+
+        deserializer.deserialize_any(NumberVisitor)
+    }
+}
+
+impl From<f64> for SenMLNumber {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<f32> for SenMLNumber {
+    fn from(value: f32) -> Self {
+        Self::Float(value.into())
+    }
+}
+
+impl From<i64> for SenMLNumber {
+    fn from(value: i64) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl From<i32> for SenMLNumber {
+    fn from(value: i32) -> Self {
+        Self::Integer(value.into())
+    }
+}
+
+impl From<u32> for SenMLNumber {
+    fn from(value: u32) -> Self {
+        Self::Integer(value.into())
+    }
+}
+
+impl From<u64> for SenMLNumber {
+    fn from(value: u64) -> Self {
+        Self::from_i128(value.into())
+    }
+}
+
+#[cfg(feature = "decimal")]
+impl From<Decimal> for SenMLNumber {
+    fn from(value: Decimal) -> Self {
+        Self::Decimal(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number_arithmetic_stays_exact() {
+        let big = SenMLNumber::from(9_007_199_254_740_993_i64);
+        assert_eq!(
+            big + SenMLNumber::Float(1.0),
+            SenMLNumber::Integer(9_007_199_254_740_994)
+        );
+        assert_eq!(big - big, SenMLNumber::Integer(0));
+        assert_eq!(
+            SenMLNumber::from(i64::MAX) + SenMLNumber::Integer(1),
+            SenMLNumber::Unsigned(i64::MAX as u64 + 1)
+        );
+        assert_eq!(
+            SenMLNumber::Integer(2) + SenMLNumber::Float(0.5),
+            SenMLNumber::Float(2.5)
+        );
+        assert_eq!(SenMLNumber::Integer(20), SenMLNumber::Float(20.0));
+        assert_ne!(
+            SenMLNumber::Integer(9_007_199_254_740_993),
+            SenMLNumber::Float(9_007_199_254_740_992.0)
+        );
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn test_decimal_arithmetic_is_exact() {
+        let a = SenMLNumber::Decimal("0.1".parse().unwrap());
+        let b = SenMLNumber::Float(0.2);
+        let sum = a + b;
+
+        assert!(matches!(sum, SenMLNumber::Decimal(_)));
+        assert_eq!(sum.to_string(), "0.3");
+        assert_eq!(sum.as_f64(), 0.3);
+    }
+
+    #[cfg(all(feature = "decimal", feature = "json"))]
+    #[test]
+    fn test_decimal_json_round_trip() {
+        let values: Vec<SenMLNumber> = serde_json::from_str("[0.1, 1234567.891, 42]").unwrap();
+        assert!(matches!(values[0], SenMLNumber::Decimal(_)));
+        assert_eq!(values[1].to_string(), "1234567.891");
+        assert_eq!(values[2], SenMLNumber::Integer(42));
+
+        assert_eq!(
+            serde_json::to_string(&values).unwrap(),
+            "[0.1,1234567.891,42]"
+        );
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor_decimal_fraction() {
+        use ciborium::Value;
+
+        // 273.15 as a decimal fraction: [-2, 27315]
+        let fraction = Value::Tag(
+            DECIMAL_FRACTION_TAG,
+            Box::new(Value::Array(vec![
+                Value::Integer((-2).into()),
+                Value::Integer(27315.into()),
+            ])),
+        );
+        let number = SenMLNumber::from_cbor_value(&fraction).unwrap();
+        assert_eq!(number.as_f64(), 273.15);
+
+        #[cfg(feature = "decimal")]
+        {
+            assert_eq!(number.to_string(), "273.15");
+            assert_eq!(number.to_cbor_value(), fraction);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 //! SenML Pack - collection of SenML records
 
-use crate::{Result, SenMLError, SenMLRecord};
+use crate::{IssueKind, Result, SenMLError, SenMLRecord, SenMLValue, ValidationIssue};
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "validation")]
@@ -121,6 +121,10 @@ impl SenMLPack {
     }
 
     /// Validate this pack according to RFC 8428
+    ///
+    /// Fails on the first problem found, checking the records before the
+    /// base values. Use [`validation_issues`](Self::validation_issues) to
+    /// collect every issue instead.
     pub fn validate(&self) -> Result<()> {
         if self.records.is_empty() {
             return Err(SenMLError::validation("SenML pack cannot be empty"));
@@ -133,27 +137,59 @@ impl SenMLPack {
             })?;
         }
 
-        // Validate base values if present
-        let base = self.base_values();
-        if let Some(bt) = base.bt
-            && !bt.is_finite()
-        {
-            return Err(SenMLError::invalid_field_value("bt", &bt.to_string()));
+        match self.invalid_base_values().next() {
+            Some((field, value)) => Err(SenMLError::invalid_field_value(
+                field,
+                value.to_string().as_str(),
+            )),
+            None => Ok(()),
         }
+    }
 
-        if let Some(bv) = base.bv
-            && !bv.is_finite()
-        {
-            return Err(SenMLError::invalid_field_value("bv", &bv.to_string()));
+    /// Check this pack against the RFC 8428 rules and report every issue
+    ///
+    /// Base value issues come first, then the issues of each record in turn.
+    /// An empty vector means the pack is valid.
+    pub fn validation_issues(&self) -> Vec<ValidationIssue> {
+        if self.records.is_empty() {
+            return vec![ValidationIssue::pack(
+                IssueKind::EmptyPack,
+                "SenML pack cannot be empty",
+            )];
         }
 
-        if let Some(bs) = base.bs
-            && !bs.is_finite()
-        {
-            return Err(SenMLError::invalid_field_value("bs", &bs.to_string()));
+        let mut issues = self.base_issues();
+        for (i, record) in self.records.iter().enumerate() {
+            issues.extend(
+                record
+                    .validation_issues()
+                    .into_iter()
+                    .map(|issue| issue.at(i)),
+            );
         }
+        issues
+    }
 
-        Ok(())
+    /// Issues with the base values, which live on the first record
+    pub(crate) fn base_issues(&self) -> Vec<ValidationIssue> {
+        self.invalid_base_values()
+            .map(|(field, v)| {
+                ValidationIssue::record(
+                    0,
+                    field,
+                    IssueKind::InvalidNumber,
+                    format!("Base value '{}' must be finite, got {}", field, v),
+                )
+            })
+            .collect()
+    }
+
+    /// Base values that are not finite
+    fn invalid_base_values(&self) -> impl Iterator<Item = (&'static str, f64)> {
+        let base = self.base_values();
+        [("bt", base.bt), ("bv", base.bv), ("bs", base.bs)]
+            .into_iter()
+            .filter_map(|(field, value)| value.filter(|v| !v.is_finite()).map(|v| (field, v)))
     }
 
     /// Convert this pack to a normalized form
@@ -161,6 +197,127 @@ impl SenMLPack {
         crate::normalize::NormalizedPack::from_pack(self)
     }
 
+    /// Rewrite this pack to use base values so that it encodes as compactly
+    /// as possible in CBOR
+    ///
+    /// Common name prefixes, units, times, values and sums are hoisted into
+    /// base fields on the first record. The result normalizes to the same
+    /// records as `self` and is never larger than the original encoding.
+    #[cfg(feature = "cbor")]
+    pub fn compact(&self) -> SenMLPack {
+        crate::compact::compact(self)
+    }
+
+    /// Append the records of `other`, e.g. a later uplink of the same
+    /// device, and drop duplicates as [`dedup`](Self::dedup) does
+    ///
+    /// Both packs are resolved against their own base values first, so they
+    /// may use different ones. The merged pack keeps the higher `bver`.
+    pub fn merge(&mut self, other: SenMLPack) {
+        let mut merged = self.normalize();
+        let other = other.normalize();
+        merged.records.extend(other.records);
+        merged.version = merged.version.max(other.version);
+        *self = Self::from_resolved(merged);
+    }
+
+    /// Drop records with the same resolved name and time as a later record
+    ///
+    /// Of each set of duplicates, the one appearing last in the pack is kept
+    /// in its position, so data re-sent after a buffered upload replaces the
+    /// earlier copy. The pack is resolved in the process; with the `cbor`
+    /// feature its base fields are then chosen anew as by
+    /// [`compact`](Self::compact).
+    pub fn dedup(&mut self) {
+        *self = Self::from_resolved(self.normalize());
+    }
+
+    /// Rebuild a pack from resolved records without duplicates
+    fn from_resolved(mut resolved: crate::normalize::NormalizedPack) -> SenMLPack {
+        let mut seen = std::collections::HashSet::new();
+        let mut records: Vec<_> = resolved
+            .records
+            .into_iter()
+            .rev()
+            .filter(|record| seen.insert((record.name.clone(), record.time.map(f64::to_bits))))
+            .collect();
+        records.reverse();
+        resolved.records = records;
+
+        let mut pack = resolved.to_pack();
+        if let Some(first) = pack.records.first_mut() {
+            first.bver = resolved.version;
+        }
+        #[cfg(feature = "cbor")]
+        let pack = pack.compact();
+        pack
+    }
+
+    /// Iterate over the resolved values of every record in this pack
+    ///
+    /// Base values are applied first, so numeric values include `bv`.
+    /// Records without a value (e.g. sum-only records) are skipped.
+    pub fn iter_values(&self) -> impl Iterator<Item = SenMLValue> {
+        self.normalize()
+            .records
+            .into_iter()
+            .filter_map(|record| record.primary_value())
+    }
+
+    /// Get all values recorded for the given resolved name, in pack order
+    ///
+    /// The name is matched against the full name (base name + record name).
+    pub fn values_for(&self, name: &str) -> Vec<SenMLValue> {
+        self.normalize()
+            .records
+            .into_iter()
+            .filter(|record| record.name == name)
+            .filter_map(|record| record.primary_value())
+            .collect()
+    }
+
+    /// Get the most recent value for the given resolved name
+    ///
+    /// Records are compared by resolved time. When times are equal or absent
+    /// the record appearing later in the pack wins.
+    pub fn latest(&self, name: &str) -> Option<SenMLValue> {
+        let normalized = self.normalize();
+        let mut latest: Option<&crate::normalize::NormalizedRecord> = None;
+
+        for record in normalized.records.iter().filter(|r| r.name == name) {
+            let newer = match latest {
+                Some(current) => {
+                    record.time.unwrap_or(f64::NEG_INFINITY)
+                        >= current.time.unwrap_or(f64::NEG_INFINITY)
+                }
+                None => true,
+            };
+            if newer && record.has_value() {
+                latest = Some(record);
+            }
+        }
+
+        latest.and_then(|record| record.primary_value())
+    }
+
+    /// Get all resolved records with the given unit (record unit or base unit)
+    pub fn filter_by_unit(&self, unit: &str) -> Vec<crate::normalize::NormalizedRecord> {
+        self.normalize()
+            .records
+            .into_iter()
+            .filter(|record| record.unit.as_deref() == Some(unit))
+            .collect()
+    }
+
+    /// Sum all resolved numeric values in this pack
+    ///
+    /// Returns `None` if the pack contains no numeric values.
+    pub fn sum(&self) -> Option<f64> {
+        self.iter_values()
+            .filter_map(|value| value.as_f64())
+            .fold(None, |acc, v| Some(acc.unwrap_or(0.0) + v))
+    }
+
     /// Extract base values from a record (typically the first one)
     fn extract_base_values(&self, record: &SenMLRecord) -> BaseValues {
         BaseValues {
@@ -229,15 +386,18 @@ impl SenMLPack {
     /// Serialize to CBOR bytes using RFC 8428 integer labels (Table 6).
     #[cfg(feature = "cbor")]
     pub fn to_cbor(&self) -> Result<Vec<u8>> {
-        use ciborium::Value;
-
-        let array: Vec<Value> = self.records.iter().map(record_to_cbor_value).collect();
         let mut buffer = Vec::new();
-        ciborium::ser::into_writer(&Value::Array(array), &mut buffer)
+        ciborium::ser::into_writer(&self.to_cbor_value(), &mut buffer)
             .map_err(|e| SenMLError::serialization(e.to_string()))?;
         Ok(buffer)
     }
 
+    /// The pack as a CBOR array of records keyed by RFC 8428 integer labels.
+    #[cfg(feature = "cbor")]
+    pub(crate) fn to_cbor_value(&self) -> ciborium::Value {
+        ciborium::Value::Array(self.records.iter().map(record_to_cbor_value).collect())
+    }
+
     /// Deserialize from CBOR bytes using RFC 8428 integer labels (Table 6).
     ///
     /// Uses a recursion depth limit of 32 to prevent stack overflow from
@@ -287,7 +447,8 @@ mod cbor_labels {
 
 /// Convert a SenMLRecord to a CBOR Value map with integer keys.
 #[cfg(feature = "cbor")]
-fn record_to_cbor_value(record: &SenMLRecord) -> ciborium::Value {
+pub(crate) fn record_to_cbor_value(record: &SenMLRecord) -> ciborium::Value {
+    use crate::SenMLNumber;
     use cbor_labels::*;
     use ciborium::Value;
 
@@ -309,11 +470,11 @@ fn record_to_cbor_value(record: &SenMLRecord) -> ciborium::Value {
     ));
     push_opt!(N, record.n, |v: &String| Value::Text(v.clone()));
     push_opt!(U, record.u, |v: &String| Value::Text(v.clone()));
-    push_opt!(V, record.v, |v: &f64| Value::Float(*v));
+    push_opt!(V, record.v, |v: &SenMLNumber| v.to_cbor_value());
     push_opt!(VS, record.vs, |v: &String| Value::Text(v.clone()));
     push_opt!(VB, record.vb, |v: &bool| Value::Bool(*v));
     push_opt!(VD, record.vd, |v: &String| Value::Text(v.clone()));
-    push_opt!(S, record.s, |v: &f64| Value::Float(*v));
+    push_opt!(S, record.s, |v: &SenMLNumber| v.to_cbor_value());
     push_opt!(T, record.t, |v: &f64| Value::Float(*v));
     push_opt!(UT, record.ut, |v: &f64| Value::Float(*v));
 
@@ -322,7 +483,8 @@ fn record_to_cbor_value(record: &SenMLRecord) -> ciborium::Value {
 
 /// Convert a CBOR Value map with integer keys to a SenMLRecord.
 #[cfg(feature = "cbor")]
-fn cbor_value_to_record(value: ciborium::Value) -> Result<SenMLRecord> {
+pub(crate) fn cbor_value_to_record(value: ciborium::Value) -> Result<SenMLRecord> {
+    use crate::SenMLNumber;
     use cbor_labels::*;
     use ciborium::Value;
 
@@ -355,7 +517,7 @@ fn cbor_value_to_record(value: ciborium::Value) -> Result<SenMLRecord> {
             BVER => record.bver = as_i32(&val),
             N => record.n = val.into_text().ok(),
             U => record.u = val.into_text().ok(),
-            V => record.v = as_f64(&val),
+            V => record.v = SenMLNumber::from_cbor_value(&val),
             VS => record.vs = val.into_text().ok(),
             VB => {
                 if let Value::Bool(b) = val {
@@ -363,7 +525,7 @@ fn cbor_value_to_record(value: ciborium::Value) -> Result<SenMLRecord> {
                 }
             }
             VD => record.vd = val.into_text().ok(),
-            S => record.s = as_f64(&val),
+            S => record.s = SenMLNumber::from_cbor_value(&val),
             T => record.t = as_f64(&val),
             UT => record.ut = as_f64(&val),
             _ => {} // unknown label — ignore
@@ -446,7 +608,154 @@ mod tests {
         assert!(empty_pack.validate().is_err());
     }
 
+    #[test]
+    fn test_pack_validation_issues() {
+        let mut pack = SenMLPack::new();
+        pack.add_record(SenMLRecord {
+            bt: Some(f64::NAN),
+            ..SenMLRecord::with_value("temp", 25.0)
+        });
+        pack.add_record(SenMLRecord::new());
+        pack.add_record(SenMLRecord::with_value("temp", 1.0).with_time(f64::INFINITY));
+
+        let summary: Vec<_> = pack
+            .validation_issues()
+            .iter()
+            .map(|i| (i.record_index, i.field, i.kind))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Some(0), Some("bt"), IssueKind::InvalidNumber),
+                (Some(1), Some("v"), IssueKind::MissingValue),
+                (Some(2), Some("t"), IssueKind::InvalidNumber),
+            ]
+        );
+
+        // validate checks the records first and keeps its error kinds
+        let err = pack.validate().unwrap_err();
+        assert!(err.to_string().contains("Invalid record at index 1"));
+        pack.records.truncate(1);
+        assert_eq!(
+            pack.validate().unwrap_err(),
+            SenMLError::invalid_field_value("bt", "NaN")
+        );
+    }
+
+    fn query_pack() -> SenMLPack {
+        let mut pack = SenMLPack::new();
+        pack.add_record(SenMLRecord {
+            bn: Some("dev1/".to_string()),
+            bt: Some(1000.0),
+            bu: Some("Cel".to_string()),
+            n: Some("temp".to_string()),
+            v: Some(20.0.into()),
+            ..Default::default()
+        });
+        pack.add_record(SenMLRecord::with_value("temp", 22.0).with_time(10.0));
+        pack.add_record(SenMLRecord::with_value("temp", 21.0).with_time(5.0));
+        pack.add_record(SenMLRecord::with_value("hum", 40.0).with_unit("%RH"));
+        pack.add_record(SenMLRecord::with_string_value("state", "ok"));
+        pack
+    }
+
+    #[test]
+    fn test_query_values_for() {
+        let pack = query_pack();
+
+        assert_eq!(
+            pack.values_for("dev1/temp"),
+            vec![
+                SenMLValue::Number(20.0),
+                SenMLValue::Number(22.0),
+                SenMLValue::Number(21.0),
+            ]
+        );
+        assert!(pack.values_for("temp").is_empty());
+    }
+
+    #[test]
+    fn test_query_latest() {
+        let pack = query_pack();
+
+        assert_eq!(pack.latest("dev1/temp"), Some(SenMLValue::Number(22.0)));
+        assert_eq!(
+            pack.latest("dev1/state"),
+            Some(SenMLValue::String("ok".to_string()))
+        );
+        assert_eq!(pack.latest("dev1/missing"), None);
+    }
+
+    #[test]
+    fn test_query_filter_by_unit() {
+        let pack = query_pack();
+
+        let celsius = pack.filter_by_unit("Cel");
+        assert_eq!(celsius.len(), 4);
+        assert!(celsius.iter().all(|r| r.unit.as_deref() == Some("Cel")));
+
+        let humidity = pack.filter_by_unit("%RH");
+        assert_eq!(humidity.len(), 1);
+        assert_eq!(humidity[0].name, "dev1/hum");
+    }
+
+    #[test]
+    fn test_query_sum_and_iter_values() {
+        let pack = query_pack();
+
+        assert_eq!(pack.iter_values().count(), 5);
+        assert_eq!(pack.sum(), Some(103.0));
+        assert_eq!(SenMLPack::new().sum(), None);
+    }
+
     #[cfg(feature = "json")]
+    #[test]
+    fn test_merge_and_dedup() {
+        // Buffered offline, uploaded with a base name and relative times
+        let mut batch = SenMLPack::new();
+        batch.add_record(SenMLRecord {
+            bn: Some("urn:dev:ow:10e2073a01080063:".to_string()),
+            bt: Some(1.32e9),
+            ..SenMLRecord::with_value("temp", 23.1)
+        });
+        batch.add_record(SenMLRecord::with_value("temp", 23.4).with_time(60.0));
+
+        // The next uplink repeats the last reading with a correction
+        let uplink = SenMLPack {
+            records: vec![
+                SenMLRecord::with_value("urn:dev:ow:10e2073a01080063:temp", 23.5)
+                    .with_time(1.32e9 + 60.0),
+                SenMLRecord::with_value("urn:dev:ow:10e2073a01080063:temp", 23.7)
+                    .with_time(1.32e9 + 120.0),
+            ],
+        };
+        batch.merge(uplink);
+
+        let resolved = batch.normalize().records;
+        let readings: Vec<_> = resolved
+            .iter()
+            .map(|r| (r.time.unwrap(), r.value.unwrap().as_f64()))
+            .collect();
+        assert_eq!(
+            readings,
+            vec![
+                (1.32e9, 23.1),
+                (1.32e9 + 60.0, 23.5),
+                (1.32e9 + 120.0, 23.7)
+            ]
+        );
+        assert!(
+            resolved
+                .iter()
+                .all(|r| r.name == "urn:dev:ow:10e2073a01080063:temp")
+        );
+
+        // Deduplicating a pack without duplicates keeps its records
+        let before = batch.normalize();
+        batch.dedup();
+        assert_eq!(batch.normalize(), before);
+    }
+
     #[test]
     fn test_json_serialization() {
         let mut pack = SenMLPack::new();
@@ -479,7 +788,7 @@ mod tests {
         pack.add_record(SenMLRecord {
             bn: Some("device/".to_string()),
             n: Some("temp".to_string()),
-            v: Some(25.0),
+            v: Some(25.0.into()),
             ..Default::default()
         });
 
@@ -517,7 +826,7 @@ mod tests {
             bs: Some(100.0),
             bver: Some(10),
             n: Some("temp".to_string()),
-            v: Some(2.5),
+            v: Some(2.5.into()),
             t: Some(60.0),
             ..Default::default()
         });
@@ -526,4 +835,30 @@ mod tests {
         let restored = SenMLPack::from_cbor(&cbor).unwrap();
         assert_eq!(pack, restored);
     }
+
+    #[cfg(all(feature = "json", feature = "cbor"))]
+    #[test]
+    fn test_large_integers_round_trip() {
+        use crate::SenMLNumber;
+
+        let mut pack = SenMLPack::new();
+        pack.add_record(SenMLRecord::with_value("energy", u64::MAX));
+        pack.add_record(SenMLRecord::with_value("offset", i64::MIN));
+        pack.add_record(SenMLRecord::with_value("count", 9_007_199_254_740_993_i64));
+
+        let expected = [
+            SenMLNumber::Unsigned(u64::MAX),
+            SenMLNumber::Integer(i64::MIN),
+            SenMLNumber::Integer(9_007_199_254_740_993),
+        ];
+        let from_json = SenMLPack::from_json(&pack.to_json().unwrap()).unwrap();
+        let from_cbor = SenMLPack::from_cbor(&pack.to_cbor().unwrap()).unwrap();
+        for restored in [from_json, from_cbor] {
+            for (record, expected) in restored.records.iter().zip(expected) {
+                // Integer equality is exact, a float would round to 2^53 or 2^64
+                assert_eq!(record.v, Some(expected));
+                assert!(record.v.unwrap().is_integer());
+            }
+        }
+    }
 }
@@ -1,5 +1,6 @@
 //! SenML Record types and values
 
+use crate::{IssueKind, SenMLNumber, ValidationIssue};
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "validation")]
@@ -45,10 +46,13 @@ pub struct SenMLRecord {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub u: Option<String>,
 
-    /// Value - numeric measurement value
+    /// Value - numeric measurement value, integers are kept exact
     #[serde(skip_serializing_if = "Option::is_none")]
-    #[cfg_attr(feature = "validation", validate(range(min = -1e38, max = 1e38)))]
-    pub v: Option<f64>,
+    #[cfg_attr(
+        feature = "validation",
+        validate(range(min = SenMLNumber::Float(-1e38), max = SenMLNumber::Float(1e38)))
+    )]
+    pub v: Option<SenMLNumber>,
 
     /// String Value - textual measurement value
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -62,9 +66,9 @@ pub struct SenMLRecord {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub vd: Option<String>,
 
-    /// Sum - integrated sum of values over time
+    /// Sum - integrated sum of values over time, integers are kept exact
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub s: Option<f64>,
+    pub s: Option<SenMLNumber>,
 
     /// Time - timestamp relative to base time
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -76,9 +80,15 @@ pub struct SenMLRecord {
 }
 
 /// Union type for SenML values
+///
+/// Integers are listed first so deserialization does not widen them to `f64`.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum SenMLValue {
+    /// Signed integer value
+    Integer(i64),
+    /// Unsigned integer value larger than `i64::MAX`
+    Unsigned(u64),
     /// Numeric value
     Number(f64),
     /// String value
@@ -87,6 +97,36 @@ pub enum SenMLValue {
     Boolean(bool),
     /// Binary data (base64 encoded)
     Data(Vec<u8>),
+    /// Exact decimal value
+    #[cfg(feature = "decimal")]
+    #[serde(with = "rust_decimal::serde::float")]
+    Decimal(rust_decimal::Decimal),
+}
+
+impl SenMLValue {
+    /// The numeric value as `f64`, if this is a number
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Integer(i) => Some(*i as f64),
+            Self::Unsigned(u) => Some(*u as f64),
+            Self::Number(n) => Some(*n),
+            #[cfg(feature = "decimal")]
+            Self::Decimal(d) => Some(SenMLNumber::Decimal(*d).as_f64()),
+            _ => None,
+        }
+    }
+}
+
+impl From<SenMLNumber> for SenMLValue {
+    fn from(value: SenMLNumber) -> Self {
+        match value {
+            SenMLNumber::Integer(i) => Self::Integer(i),
+            SenMLNumber::Unsigned(u) => Self::Unsigned(u),
+            SenMLNumber::Float(f) => Self::Number(f),
+            #[cfg(feature = "decimal")]
+            SenMLNumber::Decimal(d) => Self::Decimal(d),
+        }
+    }
 }
 
 impl SenMLRecord {
@@ -96,10 +136,12 @@ impl SenMLRecord {
     }
 
     /// Create a record with a numeric value
-    pub fn with_value<S: Into<String>>(name: S, value: f64) -> Self {
+    ///
+    /// Integer arguments are stored exactly, see [`SenMLNumber`].
+    pub fn with_value<S: Into<String>, V: Into<SenMLNumber>>(name: S, value: V) -> Self {
         Self {
             n: Some(name.into()),
-            v: Some(value),
+            v: Some(value.into()),
             ..Default::default()
         }
     }
@@ -145,15 +187,15 @@ impl SenMLRecord {
     }
 
     /// Set the sum value for this record
-    pub fn with_sum(mut self, sum: f64) -> Self {
-        self.s = Some(sum);
+    pub fn with_sum<V: Into<SenMLNumber>>(mut self, sum: V) -> Self {
+        self.s = Some(sum.into());
         self
     }
 
     /// Get the primary value from this record
     pub fn value(&self) -> Option<SenMLValue> {
         if let Some(v) = self.v {
-            Some(SenMLValue::Number(v))
+            Some(v.into())
         } else if let Some(ref vs) = self.vs {
             Some(SenMLValue::String(vs.clone()))
         } else if let Some(vb) = self.vb {
@@ -195,78 +237,129 @@ impl SenMLRecord {
     }
 
     /// Validate this record according to RFC 8428 rules
+    ///
+    /// Fails with the first of the [`validation_issues`](Self::validation_issues):
+    /// non-finite numbers and invalid base64 as
+    /// [`InvalidFieldValue`](crate::SenMLError::InvalidFieldValue), other
+    /// issues as [`ValidationError`](crate::SenMLError::ValidationError).
     pub fn validate(&self) -> crate::Result<()> {
-        // A record must have at least one value field
-        if !self.has_value() && self.s.is_none() {
-            return Err(crate::SenMLError::validation(
-                "Record must have at least one value field (v, vs, vb, vd, or s)",
-            ));
+        match self.validation_issues().into_iter().next() {
+            Some(issue) => Err(self.issue_error(issue)),
+            None => Ok(()),
         }
+    }
+
+    /// The error [`validate`](Self::validate) reports for `issue`
+    fn issue_error(&self, issue: ValidationIssue) -> crate::SenMLError {
+        let value = match issue.field {
+            Some("v") => self.v.map(|v| v.to_string()),
+            Some("s") => self.s.map(|s| s.to_string()),
+            Some("t") => self.t.map(|t| t.to_string()),
+            Some("ut") => self.ut.map(|ut| ut.to_string()),
+            Some("vd") => Some("invalid base64".to_string()),
+            _ => None,
+        };
+        match (issue.kind, issue.field, value) {
+            (IssueKind::InvalidNumber | IssueKind::InvalidEncoding, Some(field), Some(value)) => {
+                crate::SenMLError::invalid_field_value(field, value.as_str())
+            }
+            _ => crate::SenMLError::validation(issue.message),
+        }
+    }
 
-        // RFC 8428 §4.3: A record must have at most one value field (v, vs, vb, or vd)
-        let value_count = [
-            self.v.is_some(),
-            self.vs.is_some(),
-            self.vb.is_some(),
-            self.vd.is_some(),
+    /// Check this record against the RFC 8428 rules and report every issue
+    ///
+    /// The issues carry no record index. An empty vector means the record is
+    /// valid.
+    pub fn validation_issues(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+
+        // RFC 8428 §4.3: exactly one value field, or a sum
+        let value_fields: Vec<&'static str> = [
+            ("v", self.v.is_some()),
+            ("vs", self.vs.is_some()),
+            ("vb", self.vb.is_some()),
+            ("vd", self.vd.is_some()),
         ]
-        .iter()
-        .filter(|&&x| x)
-        .count();
-        if value_count > 1 {
-            return Err(crate::SenMLError::validation(
-                "Record must have at most one value field (v, vs, vb, or vd)",
+        .into_iter()
+        .filter_map(|(field, present)| present.then_some(field))
+        .collect();
+
+        if value_fields.is_empty() && self.s.is_none() {
+            issues.push(ValidationIssue::field(
+                "v",
+                IssueKind::MissingValue,
+                "Record must have at least one value field (v, vs, vb, vd, or s)".to_string(),
+            ));
+        }
+        if value_fields.len() > 1 {
+            issues.push(ValidationIssue::field(
+                value_fields[1],
+                IssueKind::MultipleValues,
+                format!(
+                    "Record must have at most one value field, found {}",
+                    value_fields.join(", ")
+                ),
             ));
         }
 
-        // Validate time values
-        if let Some(t) = self.t
-            && !t.is_finite()
-        {
-            return Err(crate::SenMLError::invalid_field_value("t", &t.to_string()));
+        let v = self.v.map(|v| v.as_f64());
+        let s = self.s.map(|s| s.as_f64());
+        for (field, value) in [("v", v), ("s", s), ("t", self.t)] {
+            if let Some(value) = value
+                && !value.is_finite()
+            {
+                issues.push(ValidationIssue::field(
+                    field,
+                    IssueKind::InvalidNumber,
+                    format!("Field '{}' must be finite, got {}", field, value),
+                ));
+            }
         }
 
         if let Some(ut) = self.ut
             && (!ut.is_finite() || ut < 0.0)
         {
-            return Err(crate::SenMLError::invalid_field_value(
+            issues.push(ValidationIssue::field(
                 "ut",
-                &ut.to_string(),
+                IssueKind::InvalidNumber,
+                format!("Update time must be finite and non-negative, got {}", ut),
             ));
         }
 
-        // Validate numeric values
-        if let Some(v) = self.v
-            && !v.is_finite()
-        {
-            return Err(crate::SenMLError::invalid_field_value("v", &v.to_string()));
-        }
-
-        if let Some(s) = self.s
-            && !s.is_finite()
-        {
-            return Err(crate::SenMLError::invalid_field_value("s", &s.to_string()));
-        }
-
         // Validate data field is valid base64
         if let Some(ref vd) = self.vd
             && base64_decode(vd).is_err()
         {
-            return Err(crate::SenMLError::invalid_field_value(
+            issues.push(ValidationIssue::field(
                 "vd",
-                "invalid base64",
+                IssueKind::InvalidEncoding,
+                "Data value is not valid base64".to_string(),
             ));
         }
 
-        Ok(())
+        issues
     }
 }
 
 impl From<SenMLValue> for SenMLRecord {
     fn from(value: SenMLValue) -> Self {
         match value {
+            SenMLValue::Integer(i) => Self {
+                v: Some(i.into()),
+                ..Default::default()
+            },
+            SenMLValue::Unsigned(u) => Self {
+                v: Some(u.into()),
+                ..Default::default()
+            },
             SenMLValue::Number(n) => Self {
-                v: Some(n),
+                v: Some(n.into()),
+                ..Default::default()
+            },
+            #[cfg(feature = "decimal")]
+            SenMLValue::Decimal(d) => Self {
+                v: Some(d.into()),
                 ..Default::default()
             },
             SenMLValue::String(s) => Self {
@@ -286,7 +379,7 @@ impl From<SenMLValue> for SenMLRecord {
 }
 
 // Helper functions for base64 encoding/decoding
-fn base64_encode(data: &[u8]) -> String {
+pub(crate) fn base64_encode(data: &[u8]) -> String {
     const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
 
     let mut result = String::new();
@@ -328,7 +421,7 @@ fn base64_encode(data: &[u8]) -> String {
     result
 }
 
-fn base64_decode(s: &str) -> Result<Vec<u8>, &'static str> {
+pub(crate) fn base64_decode(s: &str) -> Result<Vec<u8>, &'static str> {
     // Simple base64 decoder - in production you'd use a proper library
     let chars: Vec<char> = s.chars().filter(|&c| c != '=').collect();
     let mut result = Vec::new();
@@ -371,7 +464,30 @@ mod tests {
     fn test_record_creation() {
         let record = SenMLRecord::with_value("temperature", 22.5);
         assert_eq!(record.n, Some("temperature".to_string()));
-        assert_eq!(record.v, Some(22.5));
+        assert_eq!(record.v, Some(22.5.into()));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_integer_value_round_trip() {
+        let record = SenMLRecord::with_value("energy", u64::MAX - 1);
+        assert_eq!(record.v, Some(SenMLNumber::Unsigned(u64::MAX - 1)));
+
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(json.contains("18446744073709551614"));
+        let parsed: SenMLRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.v.and_then(SenMLNumber::as_u64), Some(u64::MAX - 1));
+
+        let parsed: SenMLRecord =
+            serde_json::from_str(r#"{"n":"t","v":-9007199254740993}"#).unwrap();
+        assert_eq!(parsed.v, Some(SenMLNumber::Integer(-9_007_199_254_740_993)));
+        assert_eq!(
+            parsed.value(),
+            Some(SenMLValue::Integer(-9_007_199_254_740_993))
+        );
+
+        let parsed: SenMLRecord = serde_json::from_str(r#"{"n":"t","v":21.5}"#).unwrap();
+        assert_eq!(parsed.v, Some(SenMLNumber::Float(21.5)));
     }
 
     #[test]
@@ -399,6 +515,12 @@ mod tests {
 
         let empty_record = SenMLRecord::new();
         assert!(empty_record.validate().is_err());
+
+        let record = SenMLRecord::with_value("temp", 25.0).with_time(f64::INFINITY);
+        assert_eq!(
+            record.validate().unwrap_err(),
+            crate::SenMLError::invalid_field_value("t", "inf")
+        );
     }
 
     #[test]
@@ -0,0 +1,528 @@
+//! Streaming SenML (SenSML) readers and writers
+//!
+//! RFC 8428 defines SenSML for packs that are sent as they are produced:
+//! `application/sensml+json` (Content-Format 111) and
+//! `application/sensml+cbor` (113) use the same array of records as SenML,
+//! but the receiver processes records as they arrive instead of waiting for
+//! the end of the array, which may never come.
+//!
+//! [`SensMLStream`] reads records one at a time from any [`io::Read`], and
+//! [`SensMLWriter`] writes them one at a time to any [`io::Write`]. Neither
+//! holds more than one record in memory. JSON streams may also be sent as
+//! JSON Lines, one record per line without the enclosing array; the reader
+//! accepts both.
+//!
+//! Base fields apply to the records that follow them, as in a pack. Records
+//! are handed over as sent, so collect them into a
+//! [`SenMLPack`](crate::SenMLPack) and normalize it, or track the base values
+//! while reading, to resolve them.
+
+use std::io::{self, BufRead, BufReader, Write};
+
+use crate::{Result, SenMLError, SenMLRecord, content_format};
+
+/// Largest JSON record the reader buffers before giving up
+const MAX_JSON_RECORD: usize = 64 * 1024;
+
+/// Nesting limit of a CBOR record, as in [`SenMLPack::from_cbor`](crate::SenMLPack::from_cbor)
+const MAX_CBOR_RECURSION_DEPTH: usize = 32;
+
+/// Encoding of a SenSML stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StreamFormat {
+    /// `application/sensml+json`
+    Json,
+    /// `application/sensml+cbor`
+    Cbor,
+}
+
+impl StreamFormat {
+    /// The stream format of CoAP Content-Format `id`, if it is a SenSML one
+    pub fn from_content_format(id: u16) -> Option<Self> {
+        match id {
+            content_format::SENSML_JSON => Some(Self::Json),
+            content_format::SENSML_CBOR => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+
+    /// CoAP Content-Format of the stream format
+    pub fn content_format(self) -> u16 {
+        match self {
+            Self::Json => content_format::SENSML_JSON,
+            Self::Cbor => content_format::SENSML_CBOR,
+        }
+    }
+}
+
+/// Position of a reader in the stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Nothing read yet
+    Start,
+    /// Inside a JSON array, or a CBOR array of indefinite length
+    Array,
+    /// Inside a CBOR array with this many records left
+    Counted(u64),
+    /// Between JSON Lines records
+    Lines,
+    /// At the end of the stream, or after an error
+    Done,
+}
+
+/// Reads the records of a SenSML stream one at a time
+///
+/// An iterator of records that ends with the stream. After an error it
+/// returns no further records.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum_senml::{SenMLPack, stream::SensMLStream};
+///
+/// let stream = br#"[{"bn":"urn:dev:ow:10e2073a01080063:","bt":1700000000,"n":"temp","v":23.1},
+///                   {"n":"temp","t":60,"v":23.4}]"#;
+///
+/// let mut temperatures = Vec::new();
+/// for record in SensMLStream::json(&stream[..]) {
+///     temperatures.push(record?.v);
+/// }
+/// assert_eq!(temperatures.len(), 2);
+///
+/// // Or collect the whole stream into a pack
+/// let pack: SenMLPack = SensMLStream::json(&stream[..]).collect::<Result<_, _>>()?;
+/// assert_eq!(pack.normalize().records[1].time, Some(1_700_000_060.0));
+/// # Ok::<(), coapum_senml::SenMLError>(())
+/// ```
+pub struct SensMLStream<R> {
+    reader: BufReader<R>,
+    format: StreamFormat,
+    state: State,
+    /// Records read so far
+    count: usize,
+}
+
+impl<R: io::Read> SensMLStream<R> {
+    /// Read a stream in `format` from `reader`
+    pub fn new(reader: R, format: StreamFormat) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            format,
+            state: State::Start,
+            count: 0,
+        }
+    }
+
+    /// Read an `application/sensml+json` stream, or JSON Lines
+    pub fn json(reader: R) -> Self {
+        Self::new(reader, StreamFormat::Json)
+    }
+
+    /// Read an `application/sensml+cbor` stream
+    pub fn cbor(reader: R) -> Self {
+        Self::new(reader, StreamFormat::Cbor)
+    }
+
+    /// Format of the stream
+    pub fn format(&self) -> StreamFormat {
+        self.format
+    }
+
+    /// Number of records read so far
+    pub fn records_read(&self) -> usize {
+        self.count
+    }
+
+    fn next_json(&mut self) -> Result<Option<SenMLRecord>> {
+        match self.state {
+            State::Start => match self.next_non_whitespace()? {
+                // An empty stream has no records
+                None => Ok(None),
+                Some(b'[') => {
+                    self.state = State::Array;
+                    match self.next_non_whitespace()? {
+                        Some(b']') => Ok(None),
+                        Some(b'{') => self.json_record().map(Some),
+                        other => Err(unexpected(other, "'{' or ']'")),
+                    }
+                }
+                Some(b'{') => {
+                    self.state = State::Lines;
+                    self.json_record().map(Some)
+                }
+                other => Err(unexpected(other, "'[' or '{'")),
+            },
+            State::Array => match self.next_non_whitespace()? {
+                Some(b']') => Ok(None),
+                Some(b',') => match self.next_non_whitespace()? {
+                    Some(b'{') => self.json_record().map(Some),
+                    other => Err(unexpected(other, "'{'")),
+                },
+                other => Err(unexpected(other, "',' or ']'")),
+            },
+            State::Lines => match self.next_non_whitespace()? {
+                None => Ok(None),
+                Some(b'{') => self.json_record().map(Some),
+                other => Err(unexpected(other, "'{'")),
+            },
+            State::Counted(_) | State::Done => Ok(None),
+        }
+    }
+
+    /// Read a JSON record whose opening brace was just read
+    fn json_record(&mut self) -> Result<SenMLRecord> {
+        let mut record = vec![b'{'];
+        let mut depth = 1usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        while depth > 0 {
+            let byte = self
+                .next_byte()?
+                .ok_or_else(|| SenMLError::deserialization("stream ended inside a record"))?;
+            if record.len() == MAX_JSON_RECORD {
+                return Err(SenMLError::deserialization(format!(
+                    "record {} exceeds {} bytes",
+                    self.count, MAX_JSON_RECORD
+                )));
+            }
+            record.push(byte);
+            if in_string {
+                match byte {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    b'"' => in_string = false,
+                    _ => {}
+                }
+            } else {
+                match byte {
+                    b'"' => in_string = true,
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => depth -= 1,
+                    _ => {}
+                }
+            }
+        }
+        serde_json::from_slice(&record)
+            .map_err(|e| SenMLError::deserialization(format!("record {}: {}", self.count, e)))
+    }
+
+    fn next_cbor(&mut self) -> Result<Option<SenMLRecord>> {
+        match self.state {
+            State::Start => {
+                let Some(initial) = self.next_byte()? else {
+                    return Ok(None);
+                };
+                self.state = self.cbor_array_header(initial)?;
+                self.next_cbor()
+            }
+            State::Array => match self.peek_byte()? {
+                None => Err(SenMLError::deserialization(
+                    "stream ended before its break byte",
+                )),
+                Some(0xff) => {
+                    self.reader.consume(1);
+                    Ok(None)
+                }
+                Some(_) => self.cbor_record().map(Some),
+            },
+            State::Counted(0) | State::Lines | State::Done => Ok(None),
+            State::Counted(left) => {
+                self.state = State::Counted(left - 1);
+                self.cbor_record().map(Some)
+            }
+        }
+    }
+
+    /// The state after the CBOR array header starting with `initial`
+    fn cbor_array_header(&mut self, initial: u8) -> Result<State> {
+        let len = match initial {
+            0x80..=0x97 => u64::from(initial & 0x1f),
+            0x98..=0x9b => {
+                // 1, 2, 4 or 8 length bytes follow
+                let mut len = [0u8; 8];
+                let size = 1usize << (initial - 0x98);
+                io::Read::read_exact(&mut self.reader, &mut len[8 - size..]).map_err(read_error)?;
+                u64::from_be_bytes(len)
+            }
+            0x9f => return Ok(State::Array),
+            _ => return Err(SenMLError::deserialization("expected CBOR array")),
+        };
+        Ok(State::Counted(len))
+    }
+
+    fn cbor_record(&mut self) -> Result<SenMLRecord> {
+        let value: ciborium::Value = ciborium::de::from_reader_with_recursion_limit(
+            &mut self.reader,
+            MAX_CBOR_RECURSION_DEPTH,
+        )
+        .map_err(|e| SenMLError::deserialization(format!("record {}: {}", self.count, e)))?;
+        crate::pack::cbor_value_to_record(value)
+    }
+
+    fn next_non_whitespace(&mut self) -> Result<Option<u8>> {
+        loop {
+            match self.next_byte()? {
+                Some(b' ' | b'\t' | b'\n' | b'\r') => {}
+                other => return Ok(other),
+            }
+        }
+    }
+
+    fn next_byte(&mut self) -> Result<Option<u8>> {
+        let byte = self.peek_byte()?;
+        if byte.is_some() {
+            self.reader.consume(1);
+        }
+        Ok(byte)
+    }
+
+    fn peek_byte(&mut self) -> Result<Option<u8>> {
+        loop {
+            match self.reader.fill_buf() {
+                Ok(buf) => return Ok(buf.first().copied()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(read_error(e)),
+            }
+        }
+    }
+}
+
+impl<R: io::Read> Iterator for SensMLStream<R> {
+    type Item = Result<SenMLRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.format {
+            StreamFormat::Json => self.next_json(),
+            StreamFormat::Cbor => self.next_cbor(),
+        };
+        match record {
+            Ok(Some(record)) => {
+                self.count += 1;
+                Some(Ok(record))
+            }
+            Ok(None) => {
+                self.state = State::Done;
+                None
+            }
+            Err(e) => {
+                self.state = State::Done;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<R> std::fmt::Debug for SensMLStream<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SensMLStream")
+            .field("format", &self.format)
+            .field("state", &self.state)
+            .field("count", &self.count)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Writes a SenSML stream one record at a time
+///
+/// Records reach the underlying writer as they are pushed; call
+/// [`flush`](Self::flush) to pass them on right away when it buffers.
+/// [`finish`](Self::finish) closes the array.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum_senml::{SenMLRecord, stream::{SensMLStream, SensMLWriter}};
+///
+/// let mut writer = SensMLWriter::cbor(Vec::new());
+/// for i in 0..3 {
+///     writer.push(&SenMLRecord::with_value("temp", 20.0 + i as f64))?;
+/// }
+/// let stream = writer.finish()?;
+///
+/// assert_eq!(SensMLStream::cbor(&stream[..]).count(), 3);
+/// # Ok::<(), coapum_senml::SenMLError>(())
+/// ```
+pub struct SensMLWriter<W: Write> {
+    writer: W,
+    format: StreamFormat,
+    /// Records written so far
+    count: usize,
+}
+
+impl<W: Write> SensMLWriter<W> {
+    /// Write a stream in `format` to `writer`
+    pub fn new(writer: W, format: StreamFormat) -> Self {
+        Self {
+            writer,
+            format,
+            count: 0,
+        }
+    }
+
+    /// Write an `application/sensml+json` stream
+    pub fn json(writer: W) -> Self {
+        Self::new(writer, StreamFormat::Json)
+    }
+
+    /// Write an `application/sensml+cbor` stream
+    pub fn cbor(writer: W) -> Self {
+        Self::new(writer, StreamFormat::Cbor)
+    }
+
+    /// Number of records written so far
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Append a record to the stream, opening it first if needed
+    pub fn push(&mut self, record: &SenMLRecord) -> Result<()> {
+        if self.count == 0 {
+            self.open()?;
+        } else if self.format == StreamFormat::Json {
+            self.write(b",")?;
+        }
+        match self.format {
+            StreamFormat::Json => serde_json::to_writer(&mut self.writer, record)
+                .map_err(|e| SenMLError::serialization(e.to_string()))?,
+            StreamFormat::Cbor => ciborium::ser::into_writer(
+                &crate::pack::record_to_cbor_value(record),
+                &mut self.writer,
+            )
+            .map_err(|e| SenMLError::serialization(e.to_string()))?,
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Flush the underlying writer
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush().map_err(write_error)
+    }
+
+    /// Close the stream and return the underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        if self.count == 0 {
+            self.open()?;
+        }
+        match self.format {
+            StreamFormat::Json => self.write(b"]")?,
+            StreamFormat::Cbor => self.write(&[0xff])?,
+        }
+        self.flush()?;
+        Ok(self.writer)
+    }
+
+    fn open(&mut self) -> Result<()> {
+        match self.format {
+            StreamFormat::Json => self.write(b"["),
+            // An array of indefinite length, ended by a break byte
+            StreamFormat::Cbor => self.write(&[0x9f]),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.writer.write_all(bytes).map_err(write_error)
+    }
+}
+
+impl<W: Write> std::fmt::Debug for SensMLWriter<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SensMLWriter")
+            .field("format", &self.format)
+            .field("count", &self.count)
+            .finish_non_exhaustive()
+    }
+}
+
+fn unexpected(byte: Option<u8>, expected: &str) -> SenMLError {
+    match byte {
+        Some(byte) => {
+            SenMLError::deserialization(format!("expected {}, found {:?}", expected, byte as char))
+        }
+        None => SenMLError::deserialization(format!("expected {}, found end of stream", expected)),
+    }
+}
+
+fn read_error(e: io::Error) -> SenMLError {
+    SenMLError::deserialization(e.to_string())
+}
+
+fn write_error(e: io::Error) -> SenMLError {
+    SenMLError::serialization(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SenMLBuilder, SenMLPack};
+
+    fn pack() -> SenMLPack {
+        SenMLBuilder::new()
+            .base_name("urn:dev:ow:10e2073a01080063:")
+            .base_time(1_700_000_000.0)
+            .add_measurement_with_unit("temp", 23.1, "Cel", 0.0)
+            .add_string_value("state", "on \"heating\" {}")
+            .add_measurement("temp", 23.4, 60.0)
+            .build()
+    }
+
+    fn read(stream: &[u8], format: StreamFormat) -> Result<SenMLPack> {
+        SensMLStream::new(stream, format).collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let pack = pack();
+        for format in [StreamFormat::Json, StreamFormat::Cbor] {
+            let mut writer = SensMLWriter::new(Vec::new(), format);
+            for record in pack.iter() {
+                writer.push(record).unwrap();
+            }
+            assert_eq!(writer.count(), pack.len());
+            let stream = writer.finish().unwrap();
+            assert_eq!(read(&stream, format).unwrap(), pack);
+
+            let empty = SensMLWriter::new(Vec::new(), format).finish().unwrap();
+            assert!(read(&empty, format).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_reads_senml_packs_and_json_lines() {
+        let pack = pack();
+
+        // A SenML pack is a stream that ends
+        let json = pack.to_json_pretty().unwrap();
+        assert_eq!(read(json.as_bytes(), StreamFormat::Json).unwrap(), pack);
+        let cbor = pack.to_cbor().unwrap();
+        assert_eq!(read(&cbor, StreamFormat::Cbor).unwrap(), pack);
+
+        let lines: String = pack
+            .iter()
+            .map(|record| serde_json::to_string(record).unwrap() + "\n")
+            .collect();
+        assert_eq!(read(lines.as_bytes(), StreamFormat::Json).unwrap(), pack);
+    }
+
+    #[test]
+    fn test_truncated_stream() {
+        let mut writer = SensMLWriter::cbor(Vec::new());
+        for record in pack().iter() {
+            writer.push(record).unwrap();
+        }
+        let stream = writer.finish().unwrap();
+
+        // Records before the cut are still read
+        let mut records = SensMLStream::cbor(&stream[..stream.len() - 1]);
+        assert_eq!(records.by_ref().take(4).filter(Result::is_ok).count(), 4);
+        assert!(records.next().unwrap().is_err());
+        assert!(records.next().is_none());
+
+        let json = br#"[{"n":"temp","v":1},{"n":"te"#;
+        let mut records = SensMLStream::json(&json[..]);
+        assert!(records.next().unwrap().is_ok());
+        assert!(records.next().unwrap().is_err());
+        assert!(records.next().is_none());
+        assert_eq!(records.records_read(), 1);
+    }
+}
@@ -0,0 +1,258 @@
+//! The IANA SenML Units registry
+//!
+//! [`UNITS`] holds the units registered for the SenML `u` and `bu` fields
+//! (RFC 8428 §12.1, extended by RFC 8798). [`SECONDARY_UNITS`] holds the
+//! secondary units of RFC 8798 §3, which are not SenML units themselves but
+//! convert to one by a scale and offset; senders may use them only where the
+//! receiver is known to understand them.
+//!
+//! Units marked with an asterisk in RFC 8428 (`g`, `l`, `l/s`, `Bspl`,
+//! `1/min`, `beat/min`, `beats`) are registered for compatibility and should
+//! not be produced by new senders; they are still accepted here.
+//!
+//! ```rust
+//! use coapum_senml::units;
+//!
+//! assert!(units::is_registered("Cel"));
+//! assert!(!units::is_registered("celsius"));
+//!
+//! let kwh = units::secondary_unit("kWh").unwrap();
+//! assert_eq!(kwh.primary, "J");
+//! assert_eq!(kwh.to_primary(1.5), 5_400_000.0);
+//! ```
+
+/// A unit of the SenML Units registry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unit {
+    /// Symbol used in the `u` and `bu` fields
+    pub symbol: &'static str,
+    /// What the unit measures
+    pub description: &'static str,
+}
+
+/// A secondary unit and its conversion to a registered unit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SecondaryUnit {
+    /// Symbol of the secondary unit
+    pub symbol: &'static str,
+    /// What the unit measures
+    pub description: &'static str,
+    /// Symbol of the registered unit it converts to
+    pub primary: &'static str,
+    /// Factor a value is multiplied with to convert it
+    pub scale: f64,
+    /// Amount added to the scaled value
+    pub offset: f64,
+}
+
+impl SecondaryUnit {
+    /// Convert a value in this unit to the primary unit
+    pub fn to_primary(&self, value: f64) -> f64 {
+        value * self.scale + self.offset
+    }
+}
+
+const fn unit(symbol: &'static str, description: &'static str) -> Unit {
+    Unit {
+        symbol,
+        description,
+    }
+}
+
+const fn secondary(
+    symbol: &'static str,
+    description: &'static str,
+    primary: &'static str,
+    scale: f64,
+    offset: f64,
+) -> SecondaryUnit {
+    SecondaryUnit {
+        symbol,
+        description,
+        primary,
+        scale,
+        offset,
+    }
+}
+
+/// Units of the SenML Units registry
+pub const UNITS: &[Unit] = &[
+    // RFC 8428
+    unit("m", "meter"),
+    unit("kg", "kilogram"),
+    unit("g", "gram"),
+    unit("s", "second"),
+    unit("A", "ampere"),
+    unit("K", "kelvin"),
+    unit("cd", "candela"),
+    unit("mol", "mole"),
+    unit("Hz", "hertz"),
+    unit("rad", "radian"),
+    unit("sr", "steradian"),
+    unit("N", "newton"),
+    unit("Pa", "pascal"),
+    unit("J", "joule"),
+    unit("W", "watt"),
+    unit("C", "coulomb"),
+    unit("V", "volt"),
+    unit("F", "farad"),
+    unit("Ohm", "ohm"),
+    unit("S", "siemens"),
+    unit("Wb", "weber"),
+    unit("T", "tesla"),
+    unit("H", "henry"),
+    unit("Cel", "degrees Celsius"),
+    unit("lm", "lumen"),
+    unit("lx", "lux"),
+    unit("Bq", "becquerel"),
+    unit("Gy", "gray"),
+    unit("Sv", "sievert"),
+    unit("kat", "katal"),
+    unit("m2", "square meter (area)"),
+    unit("m3", "cubic meter (volume)"),
+    unit("l", "liter (volume)"),
+    unit("m/s", "meter per second (velocity)"),
+    unit("m/s2", "meter per square second (acceleration)"),
+    unit("m3/s", "cubic meter per second (flow rate)"),
+    unit("l/s", "liter per second (flow rate)"),
+    unit("W/m2", "watt per square meter (irradiance)"),
+    unit("cd/m2", "candela per square meter (luminance)"),
+    unit("bit", "bit (information content)"),
+    unit("bit/s", "bit per second (data rate)"),
+    unit("lat", "degrees latitude"),
+    unit("lon", "degrees longitude"),
+    unit("pH", "pH value (acidity)"),
+    unit("dB", "decibel"),
+    unit("dBW", "decibel relative to 1 W (power level)"),
+    unit("Bspl", "bel (sound pressure level)"),
+    unit("count", "1 (counter value)"),
+    unit("/", "1 (ratio, e.g. value of a switch)"),
+    unit("%", "1 (ratio in percent)"),
+    unit("%RH", "percentage (relative humidity)"),
+    unit("%EL", "percentage (remaining battery energy level)"),
+    unit("EL", "seconds (remaining battery energy level)"),
+    unit("1/s", "1 per second (event rate)"),
+    unit("1/min", "1 per minute (event rate)"),
+    unit("beat/min", "1 per minute (heart rate)"),
+    unit("beats", "1 (cumulative number of heart beats)"),
+    unit("S/m", "siemens per meter (conductivity)"),
+    // RFC 8798
+    unit("B", "byte (information content)"),
+    unit("VA", "volt-ampere (apparent power)"),
+    unit("VAs", "volt-ampere second (apparent energy)"),
+    unit("var", "volt-ampere reactive (reactive power)"),
+    unit("vars", "volt-ampere reactive second (reactive energy)"),
+    unit("J/m", "joule per meter (energy per distance)"),
+    unit("kg/m3", "kilogram per cubic meter (mass density)"),
+    unit("deg", "degree (angle)"),
+    unit("NTU", "nephelometric turbidity unit"),
+];
+
+/// Secondary units of RFC 8798 §3
+pub const SECONDARY_UNITS: &[SecondaryUnit] = &[
+    secondary("ms", "millisecond", "s", 1.0 / 1000.0, 0.0),
+    secondary("min", "minute", "s", 60.0, 0.0),
+    secondary("h", "hour", "s", 3600.0, 0.0),
+    secondary("MHz", "megahertz", "Hz", 1_000_000.0, 0.0),
+    secondary("kW", "kilowatt", "W", 1000.0, 0.0),
+    secondary("kVA", "kilovolt-ampere", "VA", 1000.0, 0.0),
+    secondary("kvar", "kilovar", "var", 1000.0, 0.0),
+    secondary("Ah", "ampere-hour", "C", 3600.0, 0.0),
+    secondary("Wh", "watt-hour", "J", 3600.0, 0.0),
+    secondary("kWh", "kilowatt-hour", "J", 3_600_000.0, 0.0),
+    secondary("varh", "var-hour", "vars", 3600.0, 0.0),
+    secondary("kvarh", "kilovar-hour", "vars", 3_600_000.0, 0.0),
+    secondary("kVAh", "kilovolt-ampere-hour", "VAs", 3_600_000.0, 0.0),
+    secondary("Wh/km", "watt-hour per kilometer", "J/m", 3.6, 0.0),
+    secondary("KiB", "kibibyte", "B", 1024.0, 0.0),
+    secondary("GB", "gigabyte", "B", 1e9, 0.0),
+    secondary("Mbit/s", "megabit per second", "bit/s", 1_000_000.0, 0.0),
+    secondary("B/s", "byte per second", "bit/s", 8.0, 0.0),
+    secondary("MB/s", "megabyte per second", "bit/s", 8_000_000.0, 0.0),
+    secondary("mV", "millivolt", "V", 1.0 / 1000.0, 0.0),
+    secondary("mA", "milliampere", "A", 1.0 / 1000.0, 0.0),
+    secondary("dBm", "decibel relative to 1 mW", "dBW", 1.0, -30.0),
+    secondary("ug/m3", "microgram per cubic meter", "kg/m3", 1e-9, 0.0),
+    secondary("mm/h", "millimeter per hour", "m/s", 1.0 / 3_600_000.0, 0.0),
+    secondary("m/h", "meter per hour", "m/s", 1.0 / 3600.0, 0.0),
+    secondary("ppm", "parts per million", "/", 1e-6, 0.0),
+    secondary("/100", "percent", "/", 1.0 / 100.0, 0.0),
+    secondary("/1000", "permille", "/", 1.0 / 1000.0, 0.0),
+    secondary("hPa", "hectopascal", "Pa", 100.0, 0.0),
+    secondary("mm", "millimeter", "m", 1.0 / 1000.0, 0.0),
+    secondary("cm", "centimeter", "m", 1.0 / 100.0, 0.0),
+    secondary("km", "kilometer", "m", 1000.0, 0.0),
+    secondary("km/h", "kilometer per hour", "m/s", 1.0 / 3.6, 0.0),
+];
+
+/// Prefix of private units, which are agreed between sender and receiver
+/// and never registered
+pub const PRIVATE_UNIT_PREFIX: &str = "1/";
+
+/// The registered unit with `symbol`
+pub fn unit_of(symbol: &str) -> Option<&'static Unit> {
+    UNITS.iter().find(|unit| unit.symbol == symbol)
+}
+
+/// The secondary unit with `symbol`
+pub fn secondary_unit(symbol: &str) -> Option<&'static SecondaryUnit> {
+    SECONDARY_UNITS.iter().find(|unit| unit.symbol == symbol)
+}
+
+/// Whether `symbol` is a registered unit or a secondary unit
+///
+/// Symbols are case-sensitive: `Cel` is registered, `cel` is not.
+pub fn is_registered(symbol: &str) -> bool {
+    unit_of(symbol).is_some() || secondary_unit(symbol).is_some()
+}
+
+/// Whether `symbol` follows the private unit convention: the `1/` prefix
+/// followed by a name that is not registered, e.g. `1/acme-flow`
+pub fn is_private(symbol: &str) -> bool {
+    symbol.len() > PRIVATE_UNIT_PREFIX.len()
+        && symbol.starts_with(PRIVATE_UNIT_PREFIX)
+        && !is_registered(symbol)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_lookup() {
+        assert_eq!(unit_of("Cel").unwrap().description, "degrees Celsius");
+        assert!(is_registered("%RH"));
+        assert!(is_registered("kg/m3"));
+        assert!(is_registered("hPa"));
+        assert!(!is_registered("cel"));
+        assert!(!is_registered("degF"));
+        assert!(!is_registered(""));
+
+        // Every secondary unit converts to a registered one, and no symbol
+        // is registered twice
+        for secondary in SECONDARY_UNITS {
+            assert!(unit_of(secondary.primary).is_some(), "{}", secondary.symbol);
+            assert!(unit_of(secondary.symbol).is_none(), "{}", secondary.symbol);
+        }
+        let mut symbols: Vec<_> = UNITS.iter().map(|unit| unit.symbol).collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+        assert_eq!(symbols.len(), UNITS.len());
+    }
+
+    #[test]
+    fn test_secondary_conversion() {
+        assert_eq!(secondary_unit("hPa").unwrap().to_primary(1013.25), 101325.0);
+        assert_eq!(secondary_unit("dBm").unwrap().to_primary(0.0), -30.0);
+        assert_eq!(secondary_unit("min").unwrap().to_primary(2.0), 120.0);
+    }
+
+    #[test]
+    fn test_private_units() {
+        assert!(is_private("1/acme-flow"));
+        // Registered units with the prefix are not private
+        assert!(!is_private("1/s"));
+        assert!(!is_private("1/"));
+        assert!(!is_private("acme-flow"));
+    }
+}
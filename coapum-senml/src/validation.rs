@@ -2,6 +2,8 @@
 
 use crate::{NormalizedPack, Result, SenMLError, SenMLPack, SenMLRecord};
 
+pub use crate::error::{IssueKind, ValidationIssue};
+
 /// Trait for validating SenML data structures
 pub trait Validate {
     /// Validate this item according to RFC 8428 rules
@@ -44,6 +46,11 @@ pub struct PackValidator {
     pub required_units: std::collections::HashMap<String, String>,
     /// Enforce RFC 8428 strict compliance
     pub rfc_strict: bool,
+    /// Reject units missing from the SenML Units registry (default: false)
+    pub registered_units: bool,
+    /// Accept private units with the `1/` prefix when `registered_units` is
+    /// set (default: false)
+    pub private_units: bool,
 }
 
 impl Default for PackValidator {
@@ -54,6 +61,8 @@ impl Default for PackValidator {
             max_time_drift: None,
             required_units: std::collections::HashMap::new(),
             rfc_strict: true,
+            registered_units: false,
+            private_units: false,
         }
     }
 }
@@ -98,47 +107,136 @@ impl PackValidator {
         self
     }
 
+    /// Reject units that are neither registered nor secondary units, see
+    /// [`units`](crate::units)
+    pub fn registered_units(mut self, registered: bool) -> Self {
+        self.registered_units = registered;
+        self
+    }
+
+    /// Accept private units like `1/acme-flow` alongside registered ones
+    pub fn private_units(mut self, allow: bool) -> Self {
+        self.private_units = allow;
+        self
+    }
+
     /// Validate a SenML pack with these settings
+    ///
+    /// Fails on the first problem found. Use [`validate_all`](Self::validate_all)
+    /// to collect every issue in the pack instead.
     pub fn validate_pack(&self, pack: &SenMLPack) -> Result<()> {
-        // Check empty pack rule before basic validation (which rejects empty packs)
+        match self.validate_all(pack).into_iter().next() {
+            Some(issue) => Err(SenMLError::validation(issue.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Validate a SenML pack and report every issue found
+    ///
+    /// Unlike [`validate_pack`](Self::validate_pack) this does not stop at the
+    /// first error. Issues are returned in the order they are detected: pack
+    /// level checks first, then each record in turn, then cross-record checks.
+    /// An empty vector means the pack is valid.
+    pub fn validate_all(&self, pack: &SenMLPack) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+
         if pack.is_empty() {
             if !self.allow_empty {
-                return Err(SenMLError::validation("Empty pack not allowed"));
+                issues.push(ValidationIssue::pack(
+                    IssueKind::EmptyPack,
+                    "Empty pack not allowed",
+                ));
             }
-            return Ok(());
+            return issues;
         }
 
-        // Basic validation
-        pack.validate()?;
+        // Base values live on the first record - RFC 8428 Section 4.1
+        issues.extend(pack.base_issues());
+        let base = pack.base_values();
 
-        // RFC 8428 strict compliance checks
         if self.rfc_strict {
-            self.validate_rfc_compliance(pack)?;
+            let version = base.bver.unwrap_or(DEFAULT_SENML_VERSION);
+            if version != DEFAULT_SENML_VERSION {
+                issues.push(ValidationIssue::record(
+                    0,
+                    "bver",
+                    IssueKind::UnsupportedVersion,
+                    format!(
+                        "Unsupported SenML version: {} (expected: {})",
+                        version, DEFAULT_SENML_VERSION
+                    ),
+                ));
+            }
         }
 
-        // Validate each record with extended rules
         for (i, record) in pack.iter().enumerate() {
-            self.validate_record(record).map_err(|e| {
-                SenMLError::validation(format!("Record {} validation failed: {}", i, e))
-            })?;
+            issues.extend(
+                record
+                    .validation_issues()
+                    .into_iter()
+                    .map(|issue| issue.at(i)),
+            );
+            self.record_issues(i, record, &mut issues);
+
+            // RFC 8428 Section 4.1: fields ending with "_" are reserved
+            if self.rfc_strict
+                && let Some(ref name) = record.n
+                && name.ends_with('_')
+            {
+                issues.push(ValidationIssue::record(
+                    i,
+                    "n",
+                    IssueKind::ReservedName,
+                    format!("Field name '{}' ends with reserved '_' character", name),
+                ));
+            }
         }
 
-        // Cross-record validation
-        self.validate_pack_consistency(pack)?;
+        self.consistency_issues(pack, &mut issues);
 
-        Ok(())
+        issues
     }
 
     /// Validate a single record with extended rules
     pub fn validate_record(&self, record: &SenMLRecord) -> Result<()> {
-        // Basic record validation
-        record.validate()?;
+        let mut issues = record.validation_issues();
+        self.record_issues(0, record, &mut issues);
+
+        match issues.into_iter().next() {
+            Some(issue) => Err(SenMLError::validation(issue.message)),
+            None => Ok(()),
+        }
+    }
+
+    /// Collect the issues for the rules this validator adds to
+    /// [`SenMLRecord::validation_issues`]
+    fn record_issues(&self, index: usize, record: &SenMLRecord, issues: &mut Vec<ValidationIssue>) {
+        let mut push = |field: &'static str, kind: IssueKind, message: String| {
+            issues.push(ValidationIssue::record(index, field, kind, message));
+        };
 
         // Strict name validation
         if self.strict_names
             && let Some(ref name) = record.n
+            && let Some(problem) = Self::name_problem(name)
         {
-            self.validate_name(name)?;
+            push("n", IssueKind::InvalidName, problem.to_string());
+        }
+
+        // Registered units - RFC 8428 Section 12.1
+        if self.registered_units {
+            for (field, unit) in [("u", &record.u), ("bu", &record.bu)] {
+                if let Some(unit) = unit
+                    && !crate::units::is_registered(unit)
+                    && !(self.private_units && crate::units::is_private(unit))
+                {
+                    push(
+                        field,
+                        IssueKind::UnregisteredUnit,
+                        format!("Unit '{}' is not in the SenML Units registry", unit),
+                    );
+                }
+            }
         }
 
         // Unit requirements
@@ -147,212 +245,102 @@ impl PackValidator {
         {
             match &record.u {
                 Some(unit) if unit == required_unit => {} // OK
-                Some(unit) => {
-                    return Err(SenMLError::validation(format!(
+                Some(unit) => push(
+                    "u",
+                    IssueKind::UnitMismatch,
+                    format!(
                         "Measurement '{}' requires unit '{}', got '{}'",
                         name, required_unit, unit
-                    )));
-                }
-                None => {
-                    return Err(SenMLError::validation(format!(
-                        "Measurement '{}' requires unit '{}'",
-                        name, required_unit
-                    )));
-                }
+                    ),
+                ),
+                None => push(
+                    "u",
+                    IssueKind::UnitMismatch,
+                    format!("Measurement '{}' requires unit '{}'", name, required_unit),
+                ),
             }
         }
-
-        Ok(())
     }
 
     /// Validate measurement name according to strict rules
     fn validate_name(&self, name: &str) -> Result<()> {
+        match Self::name_problem(name) {
+            Some(problem) => Err(SenMLError::validation(problem)),
+            None => Ok(()),
+        }
+    }
+
+    /// Describe why a name fails strict validation, if it does
+    fn name_problem(name: &str) -> Option<&'static str> {
         // Must not be empty
         if name.is_empty() {
-            return Err(SenMLError::validation("Name cannot be empty"));
+            return Some("Name cannot be empty");
         }
 
         // Check for invalid characters (basic URI safety)
         if name.contains(' ') || name.contains('\t') || name.contains('\n') {
-            return Err(SenMLError::validation("Name contains invalid whitespace"));
+            return Some("Name contains invalid whitespace");
         }
 
         // Check for control characters
         if name.chars().any(|c| c.is_control()) {
-            return Err(SenMLError::validation("Name contains control characters"));
+            return Some("Name contains control characters");
         }
 
         // Maximum length (reasonable limit)
         if name.len() > 256 {
-            return Err(SenMLError::validation("Name too long (max 256 characters)"));
+            return Some("Name too long (max 256 characters)");
         }
 
-        Ok(())
+        None
     }
 
-    /// Validate pack-wide consistency
-    fn validate_pack_consistency(&self, pack: &SenMLPack) -> Result<()> {
-        if pack.is_empty() {
-            return Ok(());
-        }
-
+    /// Collect pack-wide consistency issues
+    fn consistency_issues(&self, pack: &SenMLPack, issues: &mut Vec<ValidationIssue>) {
         // Check for duplicate names at same timestamp
         let mut seen_entries = std::collections::HashSet::new();
 
-        for record in pack.iter() {
+        for (i, record) in pack.iter().enumerate() {
             if let Some(ref name) = record.n {
                 let time = record.t.unwrap_or(0.0);
                 let entry = (name.clone(), time as i64); // Use integer for floating point comparison
 
-                if seen_entries.contains(&entry) {
-                    return Err(SenMLError::validation(format!(
-                        "Duplicate entry for '{}' at time {}",
-                        name, time
-                    )));
+                if !seen_entries.insert(entry) {
+                    issues.push(ValidationIssue::record(
+                        i,
+                        "n",
+                        IssueKind::DuplicateEntry,
+                        format!("Duplicate entry for '{}' at time {}", name, time),
+                    ));
                 }
-                seen_entries.insert(entry);
             }
         }
 
-        // Validate time ordering if requested
+        // Validate that timestamps are reasonably ordered
         if let Some(max_drift) = self.max_time_drift {
-            self.validate_time_ordering(pack, max_drift)?;
-        }
+            let mut last_time: Option<f64> = None;
 
-        Ok(())
-    }
-
-    /// Validate that timestamps are reasonably ordered
-    fn validate_time_ordering(&self, pack: &SenMLPack, max_drift: f64) -> Result<()> {
-        let mut last_time: Option<f64> = None;
-
-        for record in pack.iter() {
-            if let Some(time) = record.t {
-                if let Some(prev_time) = last_time {
+            for (i, record) in pack.iter().enumerate() {
+                if let Some(time) = record.t {
                     // Check for excessive backward drift
-                    if prev_time - time > max_drift {
-                        return Err(SenMLError::validation(format!(
-                            "Time goes backward by {:.2}s (max drift: {:.2}s)",
-                            prev_time - time,
-                            max_drift
-                        )));
+                    if let Some(prev_time) = last_time
+                        && prev_time - time > max_drift
+                    {
+                        issues.push(ValidationIssue::record(
+                            i,
+                            "t",
+                            IssueKind::TimeDrift,
+                            format!(
+                                "Time goes backward by {:.2}s (max drift: {:.2}s)",
+                                prev_time - time,
+                                max_drift
+                            ),
+                        ));
                     }
+                    last_time = Some(time);
                 }
-                last_time = Some(time);
             }
         }
-
-        Ok(())
-    }
-
-    /// Validate RFC 8428 strict compliance
-    fn validate_rfc_compliance(&self, pack: &SenMLPack) -> Result<()> {
-        if pack.is_empty() {
-            return Ok(());
-        }
-
-        // Check Base Version (bver) - RFC 8428 Section 4.1
-        let base_values = pack.base_values();
-        let version = base_values.bver.unwrap_or(DEFAULT_SENML_VERSION);
-        if version != DEFAULT_SENML_VERSION {
-            return Err(SenMLError::validation(format!(
-                "Unsupported SenML version: {} (expected: {})",
-                version, DEFAULT_SENML_VERSION
-            )));
-        }
-
-        // Validate time handling - RFC 8428 Section 4.5.2
-        for record in pack.iter() {
-            if let Some(time) = record.t {
-                self.validate_time_value(time)?;
-            }
-        }
-
-        // Check for fields ending with "_" - RFC 8428 Section 4.1
-        for record in pack.iter() {
-            if let Some(ref name) = record.n {
-                self.validate_field_names(name)?;
-            }
-        }
-
-        // Validate IEEE double-precision compliance
-        for record in pack.iter() {
-            self.validate_ieee_compliance(record)?;
-        }
-
-        Ok(())
-    }
-
-    /// Validate time value according to RFC 8428
-    fn validate_time_value(&self, time: f64) -> Result<()> {
-        if !time.is_finite() {
-            return Err(SenMLError::validation("Time value must be finite"));
-        }
-
-        // RFC 8428: Time values < 2^28 are relative, >= 2^28 are absolute
-        if time >= TIME_THRESHOLD {
-            // Absolute time - should be reasonable Unix timestamp
-            if time < 0.0 {
-                return Err(SenMLError::validation(
-                    "Absolute time values must be positive",
-                ));
-            }
-        }
-        // Relative times can be negative (past events)
-
-        Ok(())
-    }
-
-    /// Validate field names don't use reserved patterns
-    fn validate_field_names(&self, name: &str) -> Result<()> {
-        // RFC 8428: Fields ending with "_" are reserved
-        if name.ends_with('_') {
-            return Err(SenMLError::validation(format!(
-                "Field name '{}' ends with reserved '_' character",
-                name
-            )));
-        }
-        Ok(())
-    }
-
-    /// Validate IEEE double-precision compliance
-    fn validate_ieee_compliance(&self, record: &SenMLRecord) -> Result<()> {
-        // Check all numeric fields are valid IEEE 754 double-precision
-        if let Some(v) = record.v
-            && !v.is_finite()
-            && !v.is_nan()
-        {
-            return Err(SenMLError::validation(
-                "Numeric values must be finite or NaN (IEEE 754)",
-            ));
-        }
-
-        if let Some(s) = record.s
-            && !s.is_finite()
-            && !s.is_nan()
-        {
-            return Err(SenMLError::validation(
-                "Sum values must be finite or NaN (IEEE 754)",
-            ));
-        }
-
-        if let Some(t) = record.t
-            && !t.is_finite()
-        {
-            return Err(SenMLError::validation(
-                "Time values must be finite (IEEE 754)",
-            ));
-        }
-
-        if let Some(ut) = record.ut
-            && (!ut.is_finite() || ut < 0.0)
-        {
-            return Err(SenMLError::validation(
-                "Update time must be finite and non-negative",
-            ));
-        }
-
-        Ok(())
     }
 }
 
@@ -407,9 +395,12 @@ pub mod utils {
         PackValidator::new().validate_name(name).is_ok()
     }
 
-    /// Check if a unit string is valid SI unit
+    /// Check if a unit string is well-formed
+    ///
+    /// Only the characters are checked; use
+    /// [`units::is_registered`](crate::units::is_registered) to check the
+    /// unit against the SenML Units registry.
     pub fn is_valid_unit(unit: &str) -> bool {
-        // Basic unit validation - in production you'd have a comprehensive list
         !unit.is_empty()
             && !unit.contains(' ')
             && unit
@@ -503,6 +494,46 @@ mod tests {
         assert!(validator.validate_pack(&wrong_unit_pack).is_err());
     }
 
+    #[test]
+    fn test_registered_units() {
+        let pack = SenMLPack {
+            records: vec![
+                SenMLRecord {
+                    bu: Some("Cel".to_string()),
+                    ..SenMLRecord::with_value("temp", 21.5)
+                },
+                SenMLRecord::with_value("pressure", 1013.0).with_unit("hPa"),
+                SenMLRecord::with_value("flow", 3.0).with_unit("1/acme-flow"),
+                SenMLRecord::with_value("humidity", 40.0).with_unit("percent"),
+            ],
+        };
+
+        // Only the characters are checked by default
+        assert!(PackValidator::new().validate_all(&pack).is_empty());
+
+        let issues = PackValidator::new()
+            .registered_units(true)
+            .validate_all(&pack);
+        let unregistered: Vec<_> = issues
+            .iter()
+            .map(|issue| (issue.record_index, issue.kind))
+            .collect();
+        assert_eq!(
+            unregistered,
+            vec![
+                (Some(2), IssueKind::UnregisteredUnit),
+                (Some(3), IssueKind::UnregisteredUnit),
+            ]
+        );
+
+        let issues = PackValidator::new()
+            .registered_units(true)
+            .private_units(true)
+            .validate_all(&pack);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].record_index, Some(3));
+    }
+
     #[test]
     fn test_strict_name_validation() {
         let validator = PackValidator::new().strict_names(true);
@@ -599,6 +630,59 @@ mod tests {
         assert!(!utils::is_valid_field_name("")); // Empty
     }
 
+    #[test]
+    fn test_validate_all_collects_every_issue() {
+        let mut pack = SenMLPack::new();
+        pack.add_record(SenMLRecord {
+            bver: Some(5),
+            ..SenMLRecord::with_value("temperature", 22.5).with_unit("F")
+        });
+        pack.add_record(SenMLRecord::new());
+        pack.add_record(SenMLRecord::with_value("bad name", f64::INFINITY).with_time(100.0));
+        pack.add_record(SenMLRecord::with_value("bad name", 1.0).with_time(100.0));
+
+        let validator = PackValidator::new().require_unit("temperature", "Cel");
+        let issues = validator.validate_all(&pack);
+
+        let summary: Vec<_> = issues
+            .iter()
+            .map(|i| (i.record_index, i.field, i.kind))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Some(0), Some("bver"), IssueKind::UnsupportedVersion),
+                (Some(0), Some("u"), IssueKind::UnitMismatch),
+                (Some(1), Some("v"), IssueKind::MissingValue),
+                (Some(2), Some("v"), IssueKind::InvalidNumber),
+                (Some(2), Some("n"), IssueKind::InvalidName),
+                (Some(3), Some("n"), IssueKind::InvalidName),
+                (Some(3), Some("n"), IssueKind::DuplicateEntry),
+            ]
+        );
+
+        // validate_pack reports the first of these
+        let err = validator.validate_pack(&pack).unwrap_err();
+        assert!(err.to_string().contains("Unsupported SenML version"));
+    }
+
+    #[test]
+    fn test_validate_all_valid_and_empty_packs() {
+        let pack = SenMLBuilder::new().add_value("temp", 22.5).build();
+        assert!(PackValidator::new().validate_all(&pack).is_empty());
+
+        let issues = PackValidator::new().validate_all(&SenMLPack::new());
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, IssueKind::EmptyPack);
+        assert_eq!(issues[0].record_index, None);
+        assert!(
+            PackValidator::new()
+                .allow_empty()
+                .validate_all(&SenMLPack::new())
+                .is_empty()
+        );
+    }
+
     #[test]
     fn test_rfc8428_compliance_validator() {
         let validator = validators::rfc8428_compliant();
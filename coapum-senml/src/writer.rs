@@ -0,0 +1,293 @@
+//! Streaming CBOR pack writer for constrained publishers
+//!
+//! [`PackWriter`] encodes records into a caller-provided buffer as they are
+//! added, instead of building a [`SenMLPack`](crate::SenMLPack) first. The
+//! buffer's length is the size budget of a pack: once the next record would
+//! not fit, the pack is handed to the writer's sink and a new one is started,
+//! so every pack fits into a single message.
+//!
+//! Base fields apply to the records of the pack they appear in (RFC 8428
+//! §4.1). When a pack is split, the base values in effect are repeated in the
+//! first record of the next pack, so every pack resolves on its own.
+
+use crate::pack::BaseValues;
+use crate::{Result, SenMLError, SenMLRecord};
+
+/// Largest CBOR array header the writer produces (up to 65535 records)
+const MAX_HEADER_LEN: usize = 3;
+
+/// Encodes records into SenML CBOR packs of bounded size
+///
+/// # Example
+///
+/// ```rust
+/// use coapum_senml::{PackWriter, SenMLPack, SenMLRecord};
+///
+/// let mut buf = [0u8; 64];
+/// let mut packs = Vec::new();
+/// let mut writer = PackWriter::new(&mut buf, |pack: &[u8]| {
+///     packs.push(SenMLPack::from_cbor(pack)?);
+///     Ok(())
+/// });
+///
+/// let base = SenMLRecord {
+///     bn: Some("urn:dev:mac:0024befffe804ff1:".to_string()),
+///     ..SenMLRecord::with_value("temp", 20.0)
+/// };
+/// writer.push(&base)?;
+/// for i in 1..10 {
+///     writer.push(&SenMLRecord::with_value("temp", 20.0 + i as f64))?;
+/// }
+/// let count = writer.finish()?;
+///
+/// // Every pack carries the base name of the records it holds
+/// assert_eq!(count, packs.len());
+/// assert!(packs.len() > 1);
+/// assert!(packs.iter().all(|pack| pack.records[0].bn.is_some()));
+/// # Ok::<(), coapum_senml::SenMLError>(())
+/// ```
+pub struct PackWriter<'a, F> {
+    buf: &'a mut [u8],
+    sink: F,
+    /// Bytes of encoded records at the start of `buf`
+    len: usize,
+    /// Records in the current pack
+    count: usize,
+    /// Packs handed to the sink
+    packs: usize,
+    /// Base values in effect after the last record
+    bases: BaseValues,
+}
+
+impl<'a, F> PackWriter<'a, F>
+where
+    F: FnMut(&[u8]) -> Result<()>,
+{
+    /// Write packs of at most `buf.len()` bytes, passing each finished pack
+    /// to `sink`
+    pub fn new(buf: &'a mut [u8], sink: F) -> Self {
+        Self {
+            buf,
+            sink,
+            len: 0,
+            count: 0,
+            packs: 0,
+            bases: BaseValues::default(),
+        }
+    }
+
+    /// Size budget of a pack in bytes
+    pub fn max_size(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Encoded size of the current pack so far
+    pub fn len(&self) -> usize {
+        if self.count == 0 {
+            0
+        } else {
+            header_len(self.count) + self.len
+        }
+    }
+
+    /// Whether the current pack holds no records
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Add a record, first passing the current pack to the sink if the
+    /// record doesn't fit into it
+    ///
+    /// Fails if the record doesn't fit into an empty pack either, or if the
+    /// sink fails.
+    pub fn push(&mut self, record: &SenMLRecord) -> Result<()> {
+        if self.count > 0 {
+            if self.try_append(record)? {
+                return Ok(());
+            }
+            self.flush()?;
+        }
+        // The first record of a pack restates the base values
+        let first = with_bases(record, &self.bases);
+        if !self.try_append(&first)? {
+            return Err(SenMLError::serialization(format!(
+                "record does not fit into a pack of {} bytes",
+                self.buf.len()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Pass the current pack to the sink, if it holds any records
+    pub fn flush(&mut self) -> Result<()> {
+        if self.count == 0 {
+            return Ok(());
+        }
+        let header = array_header(self.count);
+        self.buf.copy_within(..self.len, header.len());
+        self.buf[..header.len()].copy_from_slice(&header);
+        let len = header.len() + self.len;
+        self.len = 0;
+        self.count = 0;
+        self.packs += 1;
+        (self.sink)(&self.buf[..len])
+    }
+
+    /// Flush the last pack and return the number of packs written
+    pub fn finish(mut self) -> Result<usize> {
+        self.flush()?;
+        Ok(self.packs)
+    }
+
+    /// Encode a record into the current pack if it fits the budget
+    fn try_append(&mut self, record: &SenMLRecord) -> Result<bool> {
+        if self.count + 1 > u16::MAX as usize {
+            return Ok(false);
+        }
+        // Room is left for the array header of the grown pack
+        let end = self.buf.len().saturating_sub(header_len(self.count + 1));
+        if end < self.len {
+            return Ok(false);
+        }
+        let value = crate::pack::record_to_cbor_value(record);
+        let mut rest = &mut self.buf[self.len..end];
+        let available = rest.len();
+        match ciborium::ser::into_writer(&value, &mut rest) {
+            Ok(()) => {
+                self.len += available - rest.len();
+                self.count += 1;
+                update_bases(&mut self.bases, record);
+                Ok(true)
+            }
+            Err(ciborium::ser::Error::Io(_)) => Ok(false),
+            Err(ciborium::ser::Error::Value(e)) => Err(SenMLError::serialization(e)),
+        }
+    }
+}
+
+impl<F> std::fmt::Debug for PackWriter<'_, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PackWriter")
+            .field("max_size", &self.buf.len())
+            .field("count", &self.count)
+            .field("packs", &self.packs)
+            .field("bases", &self.bases)
+            .finish()
+    }
+}
+
+/// Length of the CBOR header of an array with `count` items
+fn header_len(count: usize) -> usize {
+    match count {
+        0..24 => 1,
+        24..256 => 2,
+        _ => MAX_HEADER_LEN,
+    }
+}
+
+/// CBOR header (major type 4) of an array with `count` items
+fn array_header(count: usize) -> Vec<u8> {
+    match count {
+        0..24 => vec![0x80 | count as u8],
+        24..256 => vec![0x98, count as u8],
+        _ => {
+            let [hi, lo] = (count as u16).to_be_bytes();
+            vec![0x99, hi, lo]
+        }
+    }
+}
+
+/// The record with the base values it doesn't set itself
+fn with_bases(record: &SenMLRecord, bases: &BaseValues) -> SenMLRecord {
+    let mut record = record.clone();
+    record.bn = record.bn.or_else(|| bases.bn.clone());
+    record.bt = record.bt.or(bases.bt);
+    record.bu = record.bu.or_else(|| bases.bu.clone());
+    record.bv = record.bv.or(bases.bv);
+    record.bs = record.bs.or(bases.bs);
+    record.bver = record.bver.or(bases.bver);
+    record
+}
+
+/// Apply the base fields of a written record to the values in effect
+fn update_bases(bases: &mut BaseValues, record: &SenMLRecord) {
+    if let Some(bn) = &record.bn {
+        bases.bn = Some(bn.clone());
+    }
+    if let Some(bu) = &record.bu {
+        bases.bu = Some(bu.clone());
+    }
+    bases.bt = record.bt.or(bases.bt);
+    bases.bv = record.bv.or(bases.bv);
+    bases.bs = record.bs.or(bases.bs);
+    bases.bver = record.bver.or(bases.bver);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SenMLPack;
+
+    fn write(budget: usize, records: &[SenMLRecord]) -> Result<Vec<Vec<u8>>> {
+        let mut buf = vec![0u8; budget];
+        let mut packs = Vec::new();
+        let mut writer = PackWriter::new(&mut buf, |pack: &[u8]| {
+            packs.push(pack.to_vec());
+            Ok(())
+        });
+        for record in records {
+            writer.push(record)?;
+        }
+        writer.finish()?;
+        Ok(packs)
+    }
+
+    #[test]
+    fn test_matches_pack_encoding() {
+        let records: Vec<_> = (0..30)
+            .map(|i| SenMLRecord::with_value(format!("s{i}"), i as f64))
+            .collect();
+        let packs = write(4096, &records).unwrap();
+
+        // One pack, with a two-byte array header, identical to to_cbor
+        assert_eq!(packs.len(), 1);
+        let pack = SenMLPack { records };
+        assert_eq!(packs[0], pack.to_cbor().unwrap());
+    }
+
+    #[test]
+    fn test_splits_at_budget_and_carries_bases() {
+        let mut records = vec![SenMLRecord {
+            bn: Some("urn:dev:ow:10e2073a01080063:".to_string()),
+            bt: Some(1.320067464e9),
+            bu: Some("Cel".to_string()),
+            ..SenMLRecord::with_value("temp", 23.1)
+        }];
+        records.extend((1..40).map(|i| SenMLRecord::with_value("temp", 23.0).with_time(i as f64)));
+
+        let packs = write(128, &records).unwrap();
+        assert!(packs.len() > 1);
+
+        let mut resolved = Vec::new();
+        for bytes in &packs {
+            assert!(bytes.len() <= 128);
+            let pack = SenMLPack::from_cbor(bytes).unwrap();
+            assert_eq!(pack.records[0].bu.as_deref(), Some("Cel"));
+            resolved.extend(pack.normalize().records);
+        }
+
+        // The split packs resolve to the same records as one pack
+        let whole = SenMLPack { records }.normalize().records;
+        assert_eq!(resolved, whole);
+    }
+
+    #[test]
+    fn test_record_over_budget() {
+        let record = SenMLRecord::with_string_value("log", "x".repeat(64));
+        assert!(matches!(
+            write(32, &[record]),
+            Err(SenMLError::SerializationError { .. })
+        ));
+        assert!(write(32, &[]).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,70 @@
+//! # FETCH Server Example
+//!
+//! Answers RFC 8132 FETCH requests from the device's observer document. The
+//! request body is a CBOR array of paths and the response holds the value at
+//! each one, so a device reads several fields in one round trip:
+//!
+//! ```text
+//! FETCH /device  ["battery", "config/interval"]
+//! 2.05 Content   {"battery": 87, "config/interval": 30}
+//! ```
+//!
+//! Run with: cargo run --example fetch_server
+
+use std::collections::HashMap;
+
+use coapum::{
+    MemoryCredentialStore,
+    extract::{Cbor, DeviceDocument, Fetch},
+    observer::{Observer, memory::MemObserver},
+    router::RouterBuilder,
+    serve,
+};
+use serde_json::{Value, json};
+
+const PSK: &[u8] = "63ef2024b1de6417f856fab7005d38f6".as_bytes();
+const IDENTITY: &str = "goobie!";
+
+async fn fetch_fields(Fetch(fields): Fetch<Vec<String>>, document: DeviceDocument) -> Cbor<Value> {
+    tracing::info!("FETCH {:?}", fields);
+    Cbor(document.select(&fields))
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
+
+    let mut clients = HashMap::new();
+    clients.insert(IDENTITY.to_string(), PSK.to_vec());
+    let credential_store = MemoryCredentialStore::from_clients(&clients);
+
+    // State the device would normally have reported itself
+    let mut obs = MemObserver::new();
+    obs.write(
+        IDENTITY,
+        "/",
+        &json!({
+            "battery": 87,
+            "firmware": "1.4.2",
+            "config": {"interval": 30, "led": "on"},
+        }),
+    )
+    .await
+    .unwrap();
+
+    let router = RouterBuilder::new((), obs)
+        .fetch("device", fetch_fields)
+        .build();
+
+    let addr = "127.0.0.1:5684";
+    let cfg = coapum::config::Config {
+        psk_identity_hint: Some(b"coapum server".to_vec()),
+        ..Default::default()
+    };
+
+    tracing::info!("Listening on {}", addr);
+    let _ =
+        serve::serve_with_credential_store(addr.to_string(), cfg, router, credential_store).await;
+}
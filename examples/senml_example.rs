@@ -38,7 +38,7 @@ async fn handle_sensor_data(SenML(pack): SenML, State(state): State<AppState>) -
         println!(
             "  Record: {} = {:?} {} at {:?}",
             record.name,
-            record.value.or_else(|| record
+            record.value.map(|v| v.as_f64()).or_else(|| record
                 .string_value
                 .as_ref()
                 .and_then(|s| s.parse::<f64>().ok())),
@@ -0,0 +1,17 @@
+#![no_main]
+use coap_lite::{MessageClass, RequestType};
+use coapum::serve::process_datagram;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let source = "127.0.0.1:5684".parse().unwrap();
+    if let Some(request) = process_datagram(data, source) {
+        assert!(matches!(
+            request.message.header.code,
+            MessageClass::Request(_)
+        ));
+        if request.get_observe_flag().is_some() && *request.get_method() == RequestType::Get {
+            assert!(coapum::observer::validate_observer_path(request.get_path()).is_ok());
+        }
+    }
+});
@@ -0,0 +1,396 @@
+//! Built-in admin resources served over CoAP.
+//!
+//! Enabled with the `admin` feature. Mounting an [`AdminConfig`] with
+//! [`RouterBuilder::admin`](crate::RouterBuilder::admin) adds read-only GET
+//! resources under `/admin` (or a custom prefix) so that constrained ops
+//! tooling can inspect a running server without a separate HTTP stack:
+//!
+//! | Resource               | Contents                                         |
+//! |------------------------|--------------------------------------------------|
+//! | `/admin/connections`   | Active DTLS sessions from the [`ConnectionRegistry`] |
+//! | `/admin/routes`        | Registered routes and their methods              |
+//! | `/admin/observers`     | Active observation counts per connection         |
+//! | `/admin/metrics`       | Connection, observation, notification, task and route totals |
+//!
+//! Responses are JSON. Access is restricted to an explicit allow-list of PSK
+//! identities; any other client gets 4.03 Forbidden, so admin credentials can
+//! be provisioned separately from device credentials. The list is checked
+//! against the identity that authenticated the connection, not the device ID
+//! an [`IdentityMapper`](crate::IdentityMapper) maps it to.
+
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    fmt::Debug,
+    net::SocketAddr,
+    sync::{Arc, OnceLock},
+    time::Instant,
+};
+
+use async_trait::async_trait;
+use coap_lite::RequestType;
+use serde_json::{Value, json};
+
+use crate::{
+    ConnectionRegistry,
+    extract::{FromRequest, Json, Session, StatusCode},
+    observer::Observer,
+    router::{CoapumRequest, RouteInfo, RouterBuilder, wrapper::method_name},
+};
+
+/// Configuration for the built-in admin resources
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use coapum::{ConnectionRegistry, RouterBuilder, admin::AdminConfig, config::Config};
+/// # use coapum::observer::memory::MemObserver;
+/// # #[derive(Clone, Debug)]
+/// # struct AppState;
+/// let registry = ConnectionRegistry::new();
+/// let mut config = Config::default();
+/// config.set_connection_registry(registry.clone());
+///
+/// let router = RouterBuilder::new(AppState, MemObserver::new())
+///     // .get("/api/data", handler)
+///     .admin(AdminConfig::new(registry).allow_identity("ops_console"))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct AdminConfig {
+    registry: ConnectionRegistry,
+    identities: HashSet<String>,
+    prefix: String,
+}
+
+impl AdminConfig {
+    /// Create an admin configuration reporting on the given registry
+    ///
+    /// No identity is allowed until [`allow_identity`](Self::allow_identity) is called.
+    pub fn new(registry: ConnectionRegistry) -> Self {
+        Self {
+            registry,
+            identities: HashSet::new(),
+            prefix: "/admin".to_string(),
+        }
+    }
+
+    /// Allow a PSK identity to access the admin resources
+    ///
+    /// This is the identity presented in the DTLS handshake, before any
+    /// identity mapping.
+    pub fn allow_identity(mut self, identity: impl Into<String>) -> Self {
+        self.identities.insert(identity.into());
+        self
+    }
+
+    /// Mount the resources under a different path prefix (default: `/admin`)
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        let prefix = prefix.into();
+        self.prefix = format!("/{}", prefix.trim_matches('/'));
+        self
+    }
+
+    /// Whether the identity may access the admin resources
+    pub fn is_allowed(&self, identity: &str) -> bool {
+        self.identities.contains(identity)
+    }
+}
+
+/// The PSK identity of the connection a request came in on
+///
+/// Read from the connection's [`Session`], so an identity mapped to another
+/// device ID is still checked as itself. Requests without a session have not
+/// been mapped and use the request identity.
+struct PskIdentity(String);
+
+#[async_trait]
+impl<S> FromRequest<S> for PskIdentity
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let identity = match req.extensions().get::<Session>() {
+            Some(session) => session.psk_identity().to_string(),
+            None => req.identity.clone(),
+        };
+        Ok(PskIdentity(identity))
+    }
+}
+
+/// Shared state captured by the admin handlers
+struct AdminState {
+    config: AdminConfig,
+    routes: OnceLock<Vec<RouteInfo>>,
+    started_at: Instant,
+}
+
+impl AdminState {
+    fn authorize(&self, identity: &str) -> Result<(), StatusCode> {
+        if self.config.is_allowed(identity) {
+            Ok(())
+        } else {
+            tracing::warn!(identity = %identity, "admin.denied");
+            Err(StatusCode::Forbidden)
+        }
+    }
+
+    fn routes(&self) -> &[RouteInfo] {
+        self.routes.get().map(Vec::as_slice).unwrap_or_default()
+    }
+
+    async fn connections(&self) -> Value {
+        let now = Instant::now();
+        let connections: Vec<Value> = self
+            .config
+            .registry
+            .list()
+            .await
+            .into_iter()
+            .map(|conn| {
+                json!({
+                    "identity": conn.identity,
+                    "remote_addr": conn.remote_addr.to_string(),
+                    "connected_secs": now.duration_since(conn.connected_at).as_secs(),
+                    "idle_secs": now.duration_since(conn.last_activity).as_secs(),
+                    "observations": conn.observation_count,
+                    "reconnects": conn.reconnect_count,
+                    "notifications_dropped": conn.notifications_dropped,
+                })
+            })
+            .collect();
+        Value::Array(connections)
+    }
+
+    fn route_list(&self) -> Value {
+        self.routes()
+            .iter()
+            .map(|route| {
+                json!({
+                    "path": route.path,
+                    "method": method_name(route.method),
+                    "observable": route.observable,
+                    "confirmable_notifications": route.confirmable_notifications,
+                })
+            })
+            .collect()
+    }
+
+    async fn observers(&self) -> Value {
+        let observers: Vec<Value> = self
+            .config
+            .registry
+            .list()
+            .await
+            .into_iter()
+            .filter(|conn| conn.observation_count > 0)
+            .map(|conn| {
+                json!({
+                    "identity": conn.identity,
+                    "observations": conn.observation_count,
+                })
+            })
+            .collect();
+        Value::Array(observers)
+    }
+
+    async fn metrics(&self) -> Value {
+        let connections = self.config.registry.list().await;
+        let observations: usize = connections.iter().map(|c| c.observation_count).sum();
+        let notifications = self.config.registry.notification_stats();
+        let tasks = self.config.registry.task_stats();
+        json!({
+            "uptime_secs": self.started_at.elapsed().as_secs(),
+            "connections": connections.len(),
+            "observations": observations,
+            "notifications_sent": notifications.sent,
+            "notifications_dropped": notifications.dropped,
+            "notifications_failed": notifications.failed,
+            "tasks_running": tasks.running,
+            "tasks_panicked": tasks.panicked,
+            "routes": self.routes().len(),
+        })
+    }
+}
+
+/// Register the admin routes on the builder
+///
+/// Called from [`RouterBuilder::build`] so the route listing is complete.
+pub(crate) fn mount<O, S>(builder: &mut RouterBuilder<O, S>, config: AdminConfig)
+where
+    S: Clone + Debug + Send + Sync + 'static,
+    O: Observer + Send + Sync + Clone + 'static,
+{
+    let prefix = config.prefix.clone();
+    let admin = Arc::new(AdminState {
+        config,
+        routes: OnceLock::new(),
+        started_at: Instant::now(),
+    });
+
+    let state = admin.clone();
+    builder.add_route(
+        &format!("{prefix}/connections"),
+        RequestType::Get,
+        move |PskIdentity(identity): PskIdentity| {
+            let state = state.clone();
+            async move {
+                state.authorize(&identity)?;
+                Ok::<_, StatusCode>(Json(state.connections().await))
+            }
+        },
+    );
+
+    let state = admin.clone();
+    builder.add_route(
+        &format!("{prefix}/routes"),
+        RequestType::Get,
+        move |PskIdentity(identity): PskIdentity| {
+            let state = state.clone();
+            async move {
+                state.authorize(&identity)?;
+                Ok::<_, StatusCode>(Json(state.route_list()))
+            }
+        },
+    );
+
+    let state = admin.clone();
+    builder.add_route(
+        &format!("{prefix}/observers"),
+        RequestType::Get,
+        move |PskIdentity(identity): PskIdentity| {
+            let state = state.clone();
+            async move {
+                state.authorize(&identity)?;
+                Ok::<_, StatusCode>(Json(state.observers().await))
+            }
+        },
+    );
+
+    let state = admin.clone();
+    builder.add_route(
+        &format!("{prefix}/metrics"),
+        RequestType::Get,
+        move |PskIdentity(identity): PskIdentity| {
+            let state = state.clone();
+            async move {
+                state.authorize(&identity)?;
+                Ok::<_, StatusCode>(Json(state.metrics().await))
+            }
+        },
+    );
+
+    let _ = admin.routes.set(builder.router_mut().routes().to_vec());
+    tracing::info!(prefix = %prefix, "admin.mounted");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::{CoapRouter, CoapumRequest};
+    use crate::{CoapRequest, Packet, ResponseType};
+    use std::net::SocketAddr;
+    use tower::Service;
+
+    #[derive(Clone, Debug)]
+    struct TestState;
+
+    fn build_router(config: AdminConfig) -> CoapRouter<(), TestState> {
+        RouterBuilder::new(TestState, ())
+            .get("/sensors/:id", || async { StatusCode::Content })
+            .admin(config)
+            .build()
+    }
+
+    fn request(path: &str, identity: &str) -> CoapumRequest<SocketAddr> {
+        let mut raw = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+        raw.set_method(RequestType::Get);
+        raw.set_path(path);
+        let mut request: CoapumRequest<SocketAddr> = raw.into();
+        request.identity = identity.to_string();
+        request
+    }
+
+    #[test]
+    fn test_route_table_includes_admin_routes() {
+        let router = build_router(AdminConfig::new(ConnectionRegistry::new()));
+        let paths: Vec<_> = router.routes().iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "/sensors/:id",
+                "/admin/connections",
+                "/admin/routes",
+                "/admin/observers",
+                "/admin/metrics",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_custom_prefix() {
+        let config = AdminConfig::new(ConnectionRegistry::new()).prefix("ops/");
+        let router = build_router(config);
+        assert!(router.routes().iter().any(|r| r.path == "/ops/metrics"));
+    }
+
+    #[tokio::test]
+    async fn test_admin_requires_allowed_identity() {
+        let config = AdminConfig::new(ConnectionRegistry::new()).allow_identity("ops");
+        let mut router = build_router(config);
+
+        let denied = router
+            .call(request("/admin/metrics", "device_1"))
+            .await
+            .unwrap();
+        assert_eq!(*denied.get_status(), ResponseType::Forbidden);
+
+        let allowed = router.call(request("/admin/routes", "ops")).await.unwrap();
+        assert_eq!(*allowed.get_status(), ResponseType::Content);
+        let routes: Value = serde_json::from_slice(&allowed.message.payload).unwrap();
+        assert_eq!(routes.as_array().unwrap().len(), 5);
+        assert_eq!(routes[0]["path"], "/sensors/:id");
+        assert_eq!(routes[0]["method"], "GET");
+    }
+
+    #[tokio::test]
+    async fn test_admin_checks_psk_identity_of_mapped_devices() {
+        let config = AdminConfig::new(ConnectionRegistry::new())
+            .allow_identity("acme:ops")
+            .allow_identity("ops");
+        let mut router = build_router(config);
+
+        // What an IdentityMapper splitting "<tenant>:<device>" produces
+        let mapped = |psk_identity: &str, tenant: &str, device_id: &str| {
+            let session = Session::with_tenant(tenant, device_id).with_psk_identity(psk_identity);
+            let mut request = request("/admin/metrics", session.identity());
+            request.extensions_mut().insert(session);
+            request
+        };
+
+        // A listed identity keeps access once it gets a tenant prefix
+        let allowed = router
+            .call(mapped("acme:ops", "acme", "ops"))
+            .await
+            .unwrap();
+        assert_eq!(*allowed.get_status(), ResponseType::Content);
+
+        // An unlisted identity mapped to a listed one gets none
+        let mut rogue = request("/admin/metrics", "ops");
+        rogue
+            .extensions_mut()
+            .insert(Session::new("ops").with_psk_identity("rogue"));
+        let denied = router.call(rogue).await.unwrap();
+        assert_eq!(*denied.get_status(), ResponseType::Forbidden);
+        let denied = router
+            .call(mapped("other:ops", "other", "ops"))
+            .await
+            .unwrap();
+        assert_eq!(*denied.get_status(), ResponseType::Forbidden);
+    }
+}
@@ -0,0 +1,99 @@
+//! Message ID and token allocation for server-originated messages.
+//!
+//! Every connection owns an [`IdAllocator`]. Notifications and NON responses
+//! take their message IDs from it, so each message sent to a peer carries a
+//! distinct ID that RST and ACK messages can be matched against.
+
+use rand::RngExt;
+
+/// Length of tokens generated by [`IdAllocator::next_token`]
+pub const TOKEN_LENGTH: usize = 8;
+
+/// Per-connection allocator for CoAP message IDs and tokens.
+///
+/// Message IDs start at a random value (RFC 7252 §4.4) and increase by one
+/// per message, wrapping at `u16::MAX`. Tokens combine a random per-connection
+/// prefix with a counter, so they are unique for the lifetime of the
+/// connection and hard to guess (RFC 7252 §5.3.1).
+#[derive(Debug, Clone)]
+pub struct IdAllocator {
+    next_message_id: u16,
+    token_prefix: u32,
+    token_counter: u32,
+}
+
+impl IdAllocator {
+    /// Create an allocator with a random starting message ID and token prefix
+    pub fn new() -> Self {
+        let mut rng = rand::rng();
+        Self::with_seed(rng.random(), rng.random())
+    }
+
+    /// Create an allocator with a fixed starting message ID and token prefix
+    ///
+    /// Mostly useful for deterministic tests.
+    pub fn with_seed(first_message_id: u16, token_prefix: u32) -> Self {
+        Self {
+            next_message_id: first_message_id,
+            token_prefix,
+            token_counter: 0,
+        }
+    }
+
+    /// Allocate the next message ID
+    pub fn next_message_id(&mut self) -> u16 {
+        let msg_id = self.next_message_id;
+        self.next_message_id = self.next_message_id.wrapping_add(1);
+        msg_id
+    }
+
+    /// Allocate a new token of [`TOKEN_LENGTH`] bytes
+    pub fn next_token(&mut self) -> Vec<u8> {
+        let counter = self.token_counter;
+        self.token_counter = self.token_counter.wrapping_add(1);
+
+        let mut token = Vec::with_capacity(TOKEN_LENGTH);
+        token.extend_from_slice(&self.token_prefix.to_be_bytes());
+        token.extend_from_slice(&counter.to_be_bytes());
+        token
+    }
+}
+
+impl Default for IdAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_message_ids_are_sequential_and_wrap() {
+        let mut ids = IdAllocator::with_seed(u16::MAX - 1, 0);
+        assert_eq!(ids.next_message_id(), u16::MAX - 1);
+        assert_eq!(ids.next_message_id(), u16::MAX);
+        assert_eq!(ids.next_message_id(), 0);
+    }
+
+    #[test]
+    fn test_message_ids_unique_across_window() {
+        let mut ids = IdAllocator::new();
+        let allocated: HashSet<u16> = (0..1000).map(|_| ids.next_message_id()).collect();
+        assert_eq!(allocated.len(), 1000);
+    }
+
+    #[test]
+    fn test_tokens_unique_with_prefix() {
+        let mut ids = IdAllocator::with_seed(0, 0xDEAD_BEEF);
+        let first = ids.next_token();
+        let second = ids.next_token();
+
+        assert_eq!(first.len(), TOKEN_LENGTH);
+        assert_eq!(&first[..4], &[0xDE, 0xAD, 0xBE, 0xEF]);
+        assert_eq!(&first[..4], &second[..4]);
+        assert_ne!(first, second);
+    }
+}
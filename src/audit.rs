@@ -0,0 +1,303 @@
+//! Request audit log
+//!
+//! Sinks added with [`Config::add_audit_sink`](crate::config::Config::add_audit_sink)
+//! see an [`AuditRecord`] for every request the server answers: the device
+//! that sent it, what it asked for, the response code and how long it took
+//! to handle. Requests answered without reaching a handler, such as those
+//! rejected with 4.02 Bad Option or intermediate blocks of a block-wise
+//! transfer, are recorded too. Retransmissions answered from the
+//! deduplication cache are not.
+//!
+//! [`JsonlSink`] appends records to a file, one JSON object per line. Any
+//! [`AuditSink`] can be added alongside it, e.g. to forward records to a
+//! message broker.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    net::SocketAddr,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+        mpsc,
+    },
+    thread::JoinHandle,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use coap_lite::{CoapOption, MessageClass, Packet, ResponseType};
+use serde::{Deserialize, Serialize};
+
+use crate::router::wrapper::method_name;
+
+/// Records a [`JsonlSink`] buffers before it drops new ones
+const DEFAULT_CAPACITY: usize = 1024;
+
+/// A handled request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// When the request arrived, in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    /// Identity of the device, after identity mapping
+    pub identity: String,
+    /// Address the request came from
+    pub source: SocketAddr,
+    /// Request method, e.g. `GET`
+    pub method: String,
+    /// Path the client requested, before alias resolution
+    pub path: String,
+    /// Response code, e.g. `2.05`
+    pub code: String,
+    /// Time from receiving the request to sending the response, in
+    /// microseconds
+    pub duration_us: u64,
+}
+
+/// The request half of an [`AuditRecord`], taken before the request is handled
+pub(crate) struct AuditStart {
+    received_at: SystemTime,
+    started: Instant,
+    method: &'static str,
+    path: String,
+}
+
+impl AuditStart {
+    /// Method and path of `packet`, if it is a request
+    pub(crate) fn from_packet(packet: &Packet) -> Option<Self> {
+        let MessageClass::Request(method) = packet.header.code else {
+            return None;
+        };
+        let mut path = String::new();
+        for segment in packet.get_option(CoapOption::UriPath).into_iter().flatten() {
+            path.push('/');
+            path.push_str(&String::from_utf8_lossy(segment));
+        }
+        if path.is_empty() {
+            path.push('/');
+        }
+        Some(Self {
+            received_at: SystemTime::now(),
+            started: Instant::now(),
+            method: method_name(method),
+            path,
+        })
+    }
+
+    /// Complete the record with the response code and hand it to `sinks`
+    pub(crate) fn finish(
+        self,
+        sinks: &[Arc<dyn AuditSink>],
+        identity: &str,
+        source: SocketAddr,
+        status: ResponseType,
+    ) {
+        let code = u8::from(MessageClass::Response(status));
+        let record = AuditRecord {
+            timestamp_ms: self
+                .received_at
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64),
+            identity: identity.to_string(),
+            source,
+            method: self.method.to_string(),
+            path: self.path,
+            code: format!("{}.{:02}", code >> 5, code & 0x1f),
+            duration_us: self.started.elapsed().as_micros() as u64,
+        };
+        for sink in sinks {
+            sink.record(&record);
+        }
+    }
+}
+
+/// Receives an [`AuditRecord`] for every request the server answers
+///
+/// `record` is called on the task of the connection the request came in on,
+/// after the response is sent, so it must not block: hand slow work such as
+/// I/O to another thread or task.
+///
+/// Closures `Fn(&AuditRecord)` implement the trait:
+///
+/// ```rust
+/// use coapum::audit::AuditRecord;
+/// use coapum::config::Config;
+///
+/// let mut config = Config::default();
+/// config.add_audit_sink(|record: &AuditRecord| {
+///     if record.code.starts_with('4') || record.code.starts_with('5') {
+///         eprintln!("{} {} {}: {}", record.identity, record.method, record.path, record.code);
+///     }
+/// });
+/// ```
+pub trait AuditSink: Send + Sync + 'static {
+    /// Record a handled request
+    fn record(&self, record: &AuditRecord);
+}
+
+impl<F> AuditSink for F
+where
+    F: Fn(&AuditRecord) + Send + Sync + 'static,
+{
+    fn record(&self, record: &AuditRecord) {
+        self(record)
+    }
+}
+
+/// Appends audit records to a file as JSON Lines
+///
+/// Records are written by a background thread, so connections never wait
+/// for the disk. If the thread falls more than 1024 records behind, new
+/// records are dropped and counted in [`dropped`](Self::dropped). Records
+/// still buffered are written when the sink is dropped.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use coapum::audit::JsonlSink;
+/// use coapum::config::Config;
+///
+/// let mut config = Config::default();
+/// config.add_audit_sink(JsonlSink::open("/var/log/coapum/audit.jsonl")?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct JsonlSink {
+    tx: Option<mpsc::SyncSender<AuditRecord>>,
+    writer: Option<JoinHandle<()>>,
+    dropped: AtomicU64,
+}
+
+impl JsonlSink {
+    /// Append records to the file at `path`, creating it if needed
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let (tx, rx) = mpsc::sync_channel(DEFAULT_CAPACITY);
+        let writer = std::thread::Builder::new()
+            .name("coapum-audit".to_string())
+            .spawn(move || write_records(rx, BufWriter::new(file)))?;
+        Ok(Self {
+            tx: Some(tx),
+            writer: Some(writer),
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /// Number of records dropped because the writer fell behind
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl AuditSink for JsonlSink {
+    fn record(&self, record: &AuditRecord) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        if let Err(mpsc::TrySendError::Full(_)) = tx.try_send(record.clone()) {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            tracing::warn!(dropped, "audit.record_dropped");
+        }
+    }
+}
+
+impl Drop for JsonlSink {
+    fn drop(&mut self) {
+        // Closing the channel lets the writer drain it and exit
+        self.tx.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl std::fmt::Debug for JsonlSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonlSink")
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+/// Write records until the channel closes, flushing whenever it runs empty
+fn write_records(rx: mpsc::Receiver<AuditRecord>, mut out: BufWriter<File>) {
+    while let Ok(record) = rx.recv() {
+        for record in std::iter::once(record).chain(rx.try_iter()) {
+            let written = serde_json::to_writer(&mut out, &record)
+                .map_err(io::Error::from)
+                .and_then(|()| out.write_all(b"\n"));
+            if let Err(e) = written {
+                tracing::error!(error = %e, "audit.write_failed");
+            }
+        }
+        if let Err(e) = out.flush() {
+            tracing::error!(error = %e, "audit.write_failed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coap_lite::RequestType;
+    use std::sync::Mutex;
+
+    fn request(path: &str) -> Packet {
+        let mut request: coap_lite::CoapRequest<SocketAddr> = coap_lite::CoapRequest::new();
+        request.set_method(RequestType::Post);
+        request.set_path(path);
+        request.message
+    }
+
+    #[test]
+    fn test_record_request() {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let sink = records.clone();
+        let sinks: Vec<Arc<dyn AuditSink>> = vec![Arc::new(move |record: &AuditRecord| {
+            sink.lock().unwrap().push(record.clone())
+        })];
+
+        let source: SocketAddr = "192.0.2.1:5684".parse().unwrap();
+        let start = AuditStart::from_packet(&request("/sensors/temp")).unwrap();
+        start.finish(&sinks, "sensor-42", source, ResponseType::Changed);
+
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].identity, "sensor-42");
+        assert_eq!(records[0].source, source);
+        assert_eq!(records[0].method, "POST");
+        assert_eq!(records[0].path, "/sensors/temp");
+        assert_eq!(records[0].code, "2.04");
+
+        // Responses and empty messages are not requests
+        let mut response = Packet::new();
+        response.header.code = MessageClass::Response(ResponseType::Content);
+        assert!(AuditStart::from_packet(&response).is_none());
+    }
+
+    #[test]
+    fn test_jsonl_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let sinks: Vec<Arc<dyn AuditSink>> = vec![Arc::new(JsonlSink::open(&path).unwrap())];
+
+        let source: SocketAddr = "192.0.2.1:5684".parse().unwrap();
+        for (path, status) in [
+            ("/a", ResponseType::Changed),
+            ("/b", ResponseType::NotFound),
+        ] {
+            let start = AuditStart::from_packet(&request(path)).unwrap();
+            start.finish(&sinks, "sensor-42", source, status);
+        }
+        // Dropping the sink writes what is buffered
+        drop(sinks);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<AuditRecord> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].path, "/b");
+        assert_eq!(records[1].code, "4.04");
+    }
+}
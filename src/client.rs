@@ -7,15 +7,19 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use coap_lite::Packet;
 use dimpl::{Dtls, Output};
 use tokio::net::UdpSocket;
 
+use crate::extract::TraceContext;
+
 /// An async DTLS client that wraps dimpl's sans-IO state machine.
 pub struct DtlsClient {
     socket: UdpSocket,
     dtls: Dtls,
     remote: SocketAddr,
     out_buf: Vec<u8>,
+    trace_context: Option<TraceContext>,
 }
 
 impl DtlsClient {
@@ -71,6 +75,7 @@ impl DtlsClient {
                     dtls,
                     remote,
                     out_buf,
+                    trace_context: None,
                 });
             }
 
@@ -99,6 +104,33 @@ impl DtlsClient {
         Ok(())
     }
 
+    /// Serialize and send a CoAP packet.
+    ///
+    /// If a trace context is set, a child of it is attached to the packet so
+    /// the server can join the request to the caller's trace.
+    pub async fn send_packet(
+        &mut self,
+        mut packet: Packet,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(trace) = &self.trace_context {
+            trace.child().inject(&mut packet);
+        }
+        let bytes = packet
+            .to_bytes()
+            .map_err(|e| format!("invalid packet: {e}"))?;
+        self.send(&bytes).await
+    }
+
+    /// Set the trace context propagated by [`send_packet`](Self::send_packet).
+    pub fn set_trace_context(&mut self, trace_context: Option<TraceContext>) {
+        self.trace_context = trace_context;
+    }
+
+    /// Get the trace context propagated by [`send_packet`](Self::send_packet).
+    pub fn trace_context(&self) -> Option<&TraceContext> {
+        self.trace_context.as_ref()
+    }
+
     /// Receive application data from the DTLS connection.
     ///
     /// Blocks until data is available or the timeout is reached.
@@ -224,4 +256,9 @@ impl DtlsClient {
     pub fn remote_addr(&self) -> SocketAddr {
         self.remote
     }
+
+    /// Get the local address of the client socket.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
 }
@@ -0,0 +1,310 @@
+//! Compressed payloads.
+//!
+//! CoAP has no content coding, so coapum follows a convention built on two
+//! options from the experimental range. Both numbers are configurable in
+//! [`CompressionConfig`]:
+//!
+//! - **Content-Coding** (default 65025): the payload is compressed with the
+//!   coding in the option value. The option is critical, so a server that
+//!   doesn't follow the convention rejects the request with 4.02 Bad Option
+//!   instead of handing compressed bytes to a handler.
+//! - **Accept-Coding** (default 65028): a coding the client can decode in
+//!   responses, one option per coding. The option is elective.
+//!
+//! With [`Config::set_compression`](crate::config::Config::set_compression)
+//! the server decompresses request payloads before routing, so extractors
+//! see the plain payload, and compresses responses of at least
+//! [`min_size`](CompressionConfig::min_size) bytes when the client accepts
+//! deflate and compression makes the payload smaller. Brotli payloads are
+//! decompressed but responses are never brotli-compressed.
+//!
+//! # Example
+//!
+//! ```rust
+//! use coapum::compression::{self, CompressionConfig, ContentCoding};
+//! use coap_lite::CoapOption;
+//! use coapum::Packet;
+//!
+//! let config = CompressionConfig::default();
+//! let telemetry = br#"{"temp":21.5,"temp":21.5,"temp":21.5,"temp":21.5}"#;
+//!
+//! let mut packet = Packet::new();
+//! packet.payload = compression::compress(ContentCoding::Deflate, telemetry).unwrap();
+//! packet.add_option(
+//!     CoapOption::Unknown(config.coding_option),
+//!     vec![ContentCoding::Deflate.value()],
+//! );
+//!
+//! config.decode(&mut packet).unwrap();
+//! assert_eq!(packet.payload, telemetry);
+//! ```
+
+use std::{fmt, io::Read, io::Write};
+
+use coap_lite::{CoapOption, Packet, ResponseType};
+
+/// Default Content-Coding option number (critical, safe-to-forward)
+pub const CONTENT_CODING_OPTION: u16 = 65025;
+/// Default Accept-Coding option number (elective, safe-to-forward)
+pub const ACCEPT_CODING_OPTION: u16 = 65028;
+
+/// A payload compression scheme
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentCoding {
+    /// zlib-wrapped DEFLATE (RFC 1950), as HTTP's `deflate`. Option value 1.
+    Deflate,
+    /// Brotli (RFC 7932). Option value 2.
+    Brotli,
+}
+
+impl ContentCoding {
+    /// The coding's option value
+    pub fn value(self) -> u8 {
+        match self {
+            ContentCoding::Deflate => 1,
+            ContentCoding::Brotli => 2,
+        }
+    }
+
+    /// The coding with the given option value
+    pub fn from_value(value: &[u8]) -> Option<Self> {
+        match value {
+            [1] => Some(ContentCoding::Deflate),
+            [2] => Some(ContentCoding::Brotli),
+            _ => None,
+        }
+    }
+}
+
+/// Errors decoding a compressed payload
+#[derive(Debug)]
+pub enum DecompressError {
+    /// The Content-Coding option names an unknown coding
+    UnknownCoding(Vec<u8>),
+    /// The payload decompresses to more than the configured maximum
+    TooLarge(usize),
+    /// The payload is not valid for its coding
+    Corrupt(std::io::Error),
+}
+
+impl DecompressError {
+    /// The response status a request failing with this error gets
+    pub fn status(&self) -> ResponseType {
+        match self {
+            DecompressError::UnknownCoding(_) => ResponseType::UnsupportedContentFormat,
+            DecompressError::TooLarge(_) => ResponseType::RequestEntityTooLarge,
+            DecompressError::Corrupt(_) => ResponseType::BadRequest,
+        }
+    }
+}
+
+impl fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecompressError::UnknownCoding(value) => {
+                write!(f, "unknown content coding {:02x?}", value)
+            }
+            DecompressError::TooLarge(max) => {
+                write!(f, "payload decompresses to more than {} bytes", max)
+            }
+            DecompressError::Corrupt(e) => write!(f, "corrupt compressed payload: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DecompressError {}
+
+/// Option numbers and limits of payload compression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionConfig {
+    /// Content-Coding option number. Default: [`CONTENT_CODING_OPTION`].
+    pub coding_option: u16,
+    /// Accept-Coding option number. Default: [`ACCEPT_CODING_OPTION`].
+    pub accept_option: u16,
+    /// Smallest response payload worth compressing, in bytes. Default: 256.
+    pub min_size: usize,
+    /// Largest decompressed request payload, in bytes; guards against
+    /// compression bombs. Default: 64 KiB.
+    pub max_decompressed_size: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            coding_option: CONTENT_CODING_OPTION,
+            accept_option: ACCEPT_CODING_OPTION,
+            min_size: 256,
+            max_decompressed_size: 64 * 1024,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Decompress the payload of a packet carrying the Content-Coding option
+    /// and remove the option. Packets without it are left alone.
+    pub fn decode(&self, packet: &mut Packet) -> Result<(), DecompressError> {
+        let option = CoapOption::Unknown(self.coding_option);
+        let Some(value) = packet
+            .get_option(option)
+            .and_then(|values| values.front())
+            .cloned()
+        else {
+            return Ok(());
+        };
+        let coding =
+            ContentCoding::from_value(&value).ok_or(DecompressError::UnknownCoding(value))?;
+        packet.payload = decompress(coding, &packet.payload, self.max_decompressed_size)?;
+        packet.clear_option(option);
+        Ok(())
+    }
+
+    /// The coding to compress the response to `request` with, if its client
+    /// accepts one the server can produce
+    pub fn accepted(&self, request: &Packet) -> Option<ContentCoding> {
+        request
+            .get_option(CoapOption::Unknown(self.accept_option))?
+            .iter()
+            .filter_map(|value| ContentCoding::from_value(value))
+            .find(|&coding| coding == ContentCoding::Deflate)
+    }
+
+    /// Compress a response payload with `coding` if it is large enough and
+    /// compression makes it smaller
+    pub fn encode(&self, packet: &mut Packet, coding: ContentCoding) {
+        if packet.payload.len() < self.min_size {
+            return;
+        }
+        if let Some(compressed) = compress(coding, &packet.payload)
+            && compressed.len() < packet.payload.len()
+        {
+            packet.payload = compressed;
+            packet.add_option(
+                CoapOption::Unknown(self.coding_option),
+                vec![coding.value()],
+            );
+        }
+    }
+}
+
+/// Decompress `data`, failing if the result exceeds `max_size` bytes
+pub fn decompress(
+    coding: ContentCoding,
+    data: &[u8],
+    max_size: usize,
+) -> Result<Vec<u8>, DecompressError> {
+    let reader: Box<dyn Read + '_> = match coding {
+        ContentCoding::Deflate => Box::new(flate2::read::ZlibDecoder::new(data)),
+        ContentCoding::Brotli => Box::new(brotli_decompressor::Decompressor::new(data, 4096)),
+    };
+    let mut out = Vec::new();
+    reader
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut out)
+        .map_err(DecompressError::Corrupt)?;
+    if out.len() > max_size {
+        return Err(DecompressError::TooLarge(max_size));
+    }
+    Ok(out)
+}
+
+/// Compress `data`, or `None` for codings the server can't produce (brotli)
+pub fn compress(coding: ContentCoding, data: &[u8]) -> Option<Vec<u8>> {
+    match coding {
+        ContentCoding::Deflate => {
+            let mut encoder =
+                flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).ok()?;
+            encoder.finish().ok()
+        }
+        ContentCoding::Brotli => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compressed_packet(config: &CompressionConfig, payload: &[u8]) -> Packet {
+        let mut packet = Packet::new();
+        packet.payload = compress(ContentCoding::Deflate, payload).unwrap();
+        packet.add_option(
+            CoapOption::Unknown(config.coding_option),
+            vec![ContentCoding::Deflate.value()],
+        );
+        packet
+    }
+
+    #[test]
+    fn test_decode_request() {
+        let config = CompressionConfig::default();
+        let payload = vec![b'a'; 1000];
+
+        let mut packet = compressed_packet(&config, &payload);
+        config.decode(&mut packet).unwrap();
+        assert_eq!(packet.payload, payload);
+        // coap-lite keeps an empty value list for cleared options
+        assert!(
+            packet
+                .get_option(CoapOption::Unknown(config.coding_option))
+                .is_none_or(|values| values.is_empty())
+        );
+
+        // Plain payloads pass through
+        let mut plain = Packet::new();
+        plain.payload = b"plain".to_vec();
+        config.decode(&mut plain).unwrap();
+        assert_eq!(plain.payload, b"plain");
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let config = CompressionConfig {
+            max_decompressed_size: 100,
+            ..Default::default()
+        };
+
+        let mut bomb = compressed_packet(&config, &[0u8; 101]);
+        let err = config.decode(&mut bomb).unwrap_err();
+        assert_eq!(err.status(), ResponseType::RequestEntityTooLarge);
+
+        let mut corrupt = compressed_packet(&config, b"data");
+        corrupt.payload = b"not zlib".to_vec();
+        let err = config.decode(&mut corrupt).unwrap_err();
+        assert_eq!(err.status(), ResponseType::BadRequest);
+
+        let mut unknown = Packet::new();
+        unknown.add_option(CoapOption::Unknown(config.coding_option), vec![9]);
+        let err = config.decode(&mut unknown).unwrap_err();
+        assert_eq!(err.status(), ResponseType::UnsupportedContentFormat);
+    }
+
+    #[test]
+    fn test_encode_response() {
+        let config = CompressionConfig::default();
+        let mut request = Packet::new();
+        assert_eq!(config.accepted(&request), None);
+        request.add_option(
+            CoapOption::Unknown(config.accept_option),
+            vec![ContentCoding::Brotli.value()],
+        );
+        assert_eq!(config.accepted(&request), None);
+        request.add_option(
+            CoapOption::Unknown(config.accept_option),
+            vec![ContentCoding::Deflate.value()],
+        );
+        let coding = config.accepted(&request).unwrap();
+
+        let mut small = Packet::new();
+        small.payload = b"tiny".to_vec();
+        config.encode(&mut small, coding);
+        assert_eq!(small.payload, b"tiny");
+
+        let payload = br#"{"temp":21.5}"#.repeat(40);
+        let mut large = Packet::new();
+        large.payload = payload.clone();
+        config.encode(&mut large, coding);
+        assert!(large.payload.len() < payload.len());
+        config.decode(&mut large).unwrap();
+        assert_eq!(large.payload, payload);
+    }
+}
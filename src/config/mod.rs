@@ -3,6 +3,16 @@ use std::time::Duration;
 
 use tokio::sync::watch;
 
+use crate::audit::AuditSink;
+use crate::compression::CompressionConfig;
+use crate::connection::{ConnectionRegistry, TakeoverPolicy};
+use crate::extract::{DiagnosticFormat, PayloadLimits};
+use crate::identity::IdentityMapper;
+use crate::liveness::LivenessTracker;
+use crate::mtu::{DEFAULT_MTU, MIN_MTU};
+use crate::observer::{PathPolicy, queue::OverflowPolicy};
+use crate::snapshot::SnapshotConfig;
+
 #[derive(Clone)]
 pub struct Config {
     /// DTLS configuration. Must be set before serving.
@@ -34,6 +44,13 @@ pub struct Config {
     /// Default: 1152 bytes (RFC 7252).
     pub max_message_size: usize,
 
+    /// MTU of the path to devices, in bytes.
+    /// Responses that don't fit into one datagram, including the DTLS
+    /// record and IP/UDP headers, are sent in Block2 blocks sized to fit.
+    /// See [`mtu`](crate::mtu).
+    /// Default: 1280 bytes (the IPv6 minimum MTU).
+    pub mtu: usize,
+
     /// Cache expiry duration for block-wise transfer state.
     /// Default: 120 seconds.
     pub block_cache_expiry: Duration,
@@ -43,15 +60,54 @@ pub struct Config {
     /// Default: 100.
     pub max_observers_per_device: usize,
 
+    /// Rules the paths of observations are held to. Observe requests with
+    /// other paths are answered with 4.00 Bad Request.
+    /// Default: [`PathPolicy::default`] (up to 10 components and 256 bytes,
+    /// ASCII alphanumerics, `_` and `-`).
+    pub path_policy: PathPolicy,
+
+    /// Hold the paths of all requests to [`path_policy`](Self::path_policy),
+    /// not just those of observations. `/.well-known/core` is exempt. Routes
+    /// such as `.d/*` need a policy that allows `.`, or this turned off.
+    /// Default: `true`.
+    pub validate_request_paths: bool,
+
     /// Maximum number of concurrent connections.
     /// Prevents DoS attacks using many unique device identities.
     /// Default: 1000.
     pub max_connections: usize,
 
-    /// Timeout in milliseconds for sending observer notifications.
-    /// Prevents slow clients from blocking notifications to other observers.
-    /// Default: 1000ms.
-    pub notification_timeout_ms: u64,
+    /// Maximum number of DTLS handshakes running at once.
+    /// Further new peers wait in the accept queue until a handshake completes.
+    /// Default: 64.
+    pub max_concurrent_handshakes: usize,
+
+    /// Maximum number of new peers waiting for a handshake slot.
+    /// Datagrams from new peers beyond this are dropped.
+    /// Default: 256.
+    pub accept_queue_size: usize,
+
+    /// Time a new peer has to complete the DTLS handshake, including time
+    /// spent in the accept queue.
+    /// Default: 10 seconds.
+    pub handshake_timeout: Duration,
+
+    /// Number of observer notifications queued per connection before the
+    /// [`notification_overflow`](Self::notification_overflow) policy applies.
+    /// Writers never wait for a slow connection.
+    /// Default: 10.
+    pub notification_queue_size: usize,
+
+    /// What a connection's full notification queue does with new
+    /// notifications. Drops are counted in
+    /// [`ConnectionSnapshot::notifications_dropped`](crate::ConnectionSnapshot::notifications_dropped).
+    /// Default: [`OverflowPolicy::DropNewest`].
+    pub notification_overflow: OverflowPolicy,
+
+    /// What happens when a PSK identity that already has an active connection
+    /// connects again.
+    /// Default: [`TakeoverPolicy::EvictOld`].
+    pub takeover_policy: TakeoverPolicy,
 
     /// Minimum interval between reconnection attempts from the same identity.
     /// Rapid reconnections within this window are rate-limited.
@@ -70,6 +126,20 @@ pub struct Config {
     /// Default: `None` (no limit).
     pub max_session_lifetime: Option<Duration>,
 
+    /// Send an empty Confirmable message (a CoAP ping, RFC 7252 §4.3) to a
+    /// device that has sent nothing for this long. Its RST in reply keeps
+    /// NAT bindings on the path open for devices that only listen for
+    /// notifications. A ping that goes unanswered after
+    /// [`max_retransmit`](Self::max_retransmit) retransmissions ends the
+    /// connection. Must be shorter than [`timeout`](Self::timeout).
+    /// Default: `None` (no pings).
+    pub keepalive_interval: Option<Duration>,
+
+    /// Follow peers whose NAT binding changed: a DTLS record from a new port
+    /// of a connected host moves the connection there once it authenticates.
+    /// Default: `true`.
+    pub address_migration: bool,
+
     /// RFC 7252 §4.8.2 MAX_LATENCY: maximum time a datagram is expected to take from the
     /// start of its transmission to the completion of its reception.
     /// Default: 100 seconds.
@@ -95,12 +165,88 @@ pub struct Config {
     /// the server stops accepting new connections and exits gracefully.
     /// Default: `None` (server runs until the process is killed).
     pub shutdown: Option<watch::Receiver<()>>,
+
+    /// Minimum delay between notifications pulled from a
+    /// [`NotificationStream`](crate::extract::NotificationStream).
+    /// Default: 100ms.
+    pub notification_stream_pacing: Duration,
+
+    /// Maximum JSON payload accepted by the [`Json`](crate::extract::Json) extractor.
+    /// Default: 1 MB.
+    pub max_json_payload_size: usize,
+
+    /// Maximum CBOR payload accepted by the [`Cbor`](crate::extract::Cbor) extractor.
+    /// Default: 8 KB.
+    pub max_cbor_payload_size: usize,
+
+    /// Maximum SenML payload accepted by the [`SenML`](crate::extract::SenML) extractors.
+    /// Default: 1 MB.
+    pub max_senml_payload_size: usize,
+
+    /// Optional diagnostic payload on responses to rejected requests,
+    /// naming the failed check and why it failed.
+    /// Default: `None` (rejections carry no payload).
+    pub diagnostic_payloads: Option<DiagnosticFormat>,
+
+    /// Optional registry of active connections. Set this to inspect or
+    /// disconnect connections while the server is running.
+    /// Default: `None` (the server keeps a private registry).
+    pub connection_registry: Option<ConnectionRegistry>,
+
+    /// Optional tracker of when each identity was last seen.
+    /// Default: `None` (last-seen times are not recorded).
+    pub liveness: Option<LivenessTracker>,
+
+    /// Optional mapping from PSK identities to device IDs and tenants,
+    /// applied once per connection.
+    /// Default: `None` (the PSK identity is the device ID).
+    pub identity_mapper: Option<Arc<dyn IdentityMapper>>,
+
+    /// Optional snapshot of runtime state, saved on shutdown and restored
+    /// on startup.
+    /// Default: `None` (state is lost on restart).
+    pub snapshot: Option<SnapshotConfig>,
+
+    /// Optional payload compression: compressed requests are decompressed
+    /// before routing and large responses are compressed for clients that
+    /// accept it.
+    /// Default: `None` (payloads are passed through as-is).
+    pub compression: Option<CompressionConfig>,
+
+    /// Sinks receiving a record of every request the server answers.
+    /// Default: none.
+    pub audit_sinks: Vec<Arc<dyn AuditSink>>,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ConfigError {
-    InvalidBufferSize { size: usize, min: usize, max: usize },
+    InvalidBufferSize {
+        size: usize,
+        min: usize,
+        max: usize,
+    },
     InvalidTimeout(u64),
+    /// [`serve`](crate::serve::serve) needs [`Config::dimpl_cfg`]
+    MissingDtlsConfig,
+    /// A duration that must be greater than zero is zero
+    ZeroDuration(&'static str),
+    /// A limit that must be greater than zero is zero
+    ZeroLimit(&'static str),
+    /// [`Config::ack_random_factor`] is below 1.0 or not a number
+    InvalidAckRandomFactor(f64),
+    /// [`Config::max_retransmit`] is above [`Config::MAX_RETRANSMIT`]
+    InvalidMaxRetransmit(u32),
+    /// [`Config::mtu`] leaves no room for the smallest Block2 block
+    InvalidMtu {
+        mtu: usize,
+        min: usize,
+    },
+    /// [`Config::keepalive_interval`] is not shorter than [`Config::timeout`],
+    /// so connections time out before they are pinged
+    InvalidKeepaliveInterval {
+        interval: Duration,
+        timeout: u64,
+    },
 }
 
 impl std::fmt::Display for ConfigError {
@@ -116,6 +262,33 @@ impl std::fmt::Display for ConfigError {
             ConfigError::InvalidTimeout(timeout) => {
                 write!(f, "Invalid timeout: {} (must be > 0)", timeout)
             }
+            ConfigError::MissingDtlsConfig => write!(
+                f,
+                "DTLS config not set. Set config.dimpl_cfg or use serve_with_credential_store()."
+            ),
+            ConfigError::ZeroDuration(field) => {
+                write!(f, "Invalid {}: must be greater than zero", field)
+            }
+            ConfigError::ZeroLimit(field) => {
+                write!(f, "Invalid {}: must be at least 1", field)
+            }
+            ConfigError::InvalidAckRandomFactor(factor) => {
+                write!(f, "Invalid ack_random_factor: {} (must be >= 1.0)", factor)
+            }
+            ConfigError::InvalidMaxRetransmit(count) => write!(
+                f,
+                "Invalid max_retransmit: {} (must be <= {})",
+                count,
+                Config::MAX_RETRANSMIT
+            ),
+            ConfigError::InvalidMtu { mtu, min } => {
+                write!(f, "Invalid MTU: {} (must be at least {})", mtu, min)
+            }
+            ConfigError::InvalidKeepaliveInterval { interval, timeout } => write!(
+                f,
+                "Invalid keepalive_interval: {:?} (must be shorter than the {}s timeout)",
+                interval, timeout
+            ),
         }
     }
 }
@@ -129,6 +302,83 @@ impl Config {
     pub const MAX_BUFFER_SIZE: usize = 65536;
     /// Default buffer size (8KB)
     pub const DEFAULT_BUFFER_SIZE: usize = 8192;
+    /// Maximum number of CON retransmissions; the timeout doubles with each
+    pub const MAX_RETRANSMIT: u32 = 16;
+
+    /// Check the configuration for values the server can't run with
+    ///
+    /// Every `serve` function calls this before it serves any connection,
+    /// so a misconfigured server fails at startup with the offending
+    /// setting named rather than on the first connection. Fields set
+    /// directly bypass the checks of their setters and are caught here.
+    ///
+    /// DTLS settings are not checked: the server builds the PSK
+    /// configuration of every connection from its credential store, and
+    /// dimpl fixes the cipher suites.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(Self::MIN_BUFFER_SIZE..=Self::MAX_BUFFER_SIZE).contains(&self.buffer_size) {
+            return Err(ConfigError::InvalidBufferSize {
+                size: self.buffer_size,
+                min: Self::MIN_BUFFER_SIZE,
+                max: Self::MAX_BUFFER_SIZE,
+            });
+        }
+        if self.timeout == 0 {
+            return Err(ConfigError::InvalidTimeout(self.timeout));
+        }
+
+        let durations = [
+            ("handshake_timeout", self.handshake_timeout),
+            ("ack_timeout", self.ack_timeout),
+            ("block_cache_expiry", self.block_cache_expiry),
+            (
+                "max_session_lifetime",
+                self.max_session_lifetime.unwrap_or(Duration::MAX),
+            ),
+            (
+                "keepalive_interval",
+                self.keepalive_interval.unwrap_or(Duration::MAX),
+            ),
+        ];
+        if let Some(&(field, _)) = durations.iter().find(|(_, d)| d.is_zero()) {
+            return Err(ConfigError::ZeroDuration(field));
+        }
+
+        let limits = [
+            ("max_connections", self.max_connections),
+            ("max_concurrent_handshakes", self.max_concurrent_handshakes),
+            ("max_message_size", self.max_message_size),
+            ("client_command_buffer", self.client_command_buffer),
+            ("path_policy.max_depth", self.path_policy.max_depth),
+            ("path_policy.max_length", self.path_policy.max_length),
+        ];
+        if let Some(&(field, _)) = limits.iter().find(|(_, limit)| *limit == 0) {
+            return Err(ConfigError::ZeroLimit(field));
+        }
+
+        // Also rejects NaN
+        if !(self.ack_random_factor >= 1.0 && self.ack_random_factor.is_finite()) {
+            return Err(ConfigError::InvalidAckRandomFactor(self.ack_random_factor));
+        }
+        if self.max_retransmit > Self::MAX_RETRANSMIT {
+            return Err(ConfigError::InvalidMaxRetransmit(self.max_retransmit));
+        }
+        if self.mtu < MIN_MTU {
+            return Err(ConfigError::InvalidMtu {
+                mtu: self.mtu,
+                min: MIN_MTU,
+            });
+        }
+        if let Some(interval) = self.keepalive_interval
+            && interval >= Duration::from_secs(self.timeout)
+        {
+            return Err(ConfigError::InvalidKeepaliveInterval {
+                interval,
+                timeout: self.timeout,
+            });
+        }
+        Ok(())
+    }
 
     /// Get the current buffer size
     pub fn buffer_size(&self) -> usize {
@@ -181,6 +431,12 @@ impl Config {
         self.max_message_size = size;
     }
 
+    /// Set the MTU of the path to devices, e.g. 576 for links that don't
+    /// guarantee more.
+    pub fn set_mtu(&mut self, mtu: usize) {
+        self.mtu = mtu;
+    }
+
     /// Set the cache expiry duration for block-wise transfer state.
     pub fn set_block_cache_expiry(&mut self, duration: Duration) {
         self.block_cache_expiry = duration;
@@ -196,9 +452,34 @@ impl Config {
         self.max_connections = max;
     }
 
-    /// Set the notification send timeout in milliseconds.
-    pub fn set_notification_timeout_ms(&mut self, timeout_ms: u64) {
-        self.notification_timeout_ms = timeout_ms;
+    /// Set the maximum number of concurrent DTLS handshakes.
+    pub fn set_max_concurrent_handshakes(&mut self, max: usize) {
+        self.max_concurrent_handshakes = max;
+    }
+
+    /// Set the maximum number of new peers waiting for a handshake slot.
+    pub fn set_accept_queue_size(&mut self, size: usize) {
+        self.accept_queue_size = size;
+    }
+
+    /// Set the time allowed for a DTLS handshake to complete.
+    pub fn set_handshake_timeout(&mut self, timeout: Duration) {
+        self.handshake_timeout = timeout;
+    }
+
+    /// Set the number of notifications queued per connection.
+    pub fn set_notification_queue_size(&mut self, size: usize) {
+        self.notification_queue_size = size;
+    }
+
+    /// Set what a full notification queue does with new notifications.
+    pub fn set_notification_overflow(&mut self, policy: OverflowPolicy) {
+        self.notification_overflow = policy;
+    }
+
+    /// Set the policy for a second connection with an already connected identity.
+    pub fn set_takeover_policy(&mut self, policy: TakeoverPolicy) {
+        self.takeover_policy = policy;
     }
 
     /// Set the minimum interval between reconnection attempts.
@@ -220,6 +501,23 @@ impl Config {
         self.max_session_lifetime = Some(lifetime);
     }
 
+    /// Ping devices that have been silent for `interval`, keeping NAT
+    /// bindings open for devices that rarely send.
+    pub fn set_keepalive_interval(&mut self, interval: Duration) {
+        self.keepalive_interval = Some(interval);
+    }
+
+    /// Enable or disable checking the paths of all requests against the
+    /// path policy, not just those of observations.
+    pub fn set_validate_request_paths(&mut self, enabled: bool) {
+        self.validate_request_paths = enabled;
+    }
+
+    /// Enable or disable following peers to a new address after NAT rebinding.
+    pub fn set_address_migration(&mut self, enabled: bool) {
+        self.address_migration = enabled;
+    }
+
     /// Set a shutdown signal receiver for graceful shutdown.
     ///
     /// When the corresponding [`watch::Sender`] sends a value or is dropped,
@@ -228,6 +526,82 @@ impl Config {
         self.shutdown = Some(rx);
     }
 
+    /// Set the minimum delay between streamed notifications.
+    pub fn set_notification_stream_pacing(&mut self, pacing: Duration) {
+        self.notification_stream_pacing = pacing;
+    }
+
+    /// Set the maximum payload size for the `Json` extractor.
+    pub fn set_max_json_payload_size(&mut self, size: usize) {
+        self.max_json_payload_size = size;
+    }
+
+    /// Set the maximum payload size for the `Cbor` extractor.
+    pub fn set_max_cbor_payload_size(&mut self, size: usize) {
+        self.max_cbor_payload_size = size;
+    }
+
+    /// Set the maximum payload size for the `SenML` extractors.
+    pub fn set_max_senml_payload_size(&mut self, size: usize) {
+        self.max_senml_payload_size = size;
+    }
+
+    /// Payload limits handed to extractors through the request extensions.
+    pub fn payload_limits(&self) -> PayloadLimits {
+        PayloadLimits {
+            json: self.max_json_payload_size,
+            cbor: self.max_cbor_payload_size,
+            senml: self.max_senml_payload_size,
+            route: None,
+        }
+    }
+
+    /// Describe extractor rejections in their responses.
+    ///
+    /// Responses keep their status; the payload carries the rejection's
+    /// kind and description as text or CBOR. See
+    /// [`diagnostic`](crate::extract::diagnostic).
+    pub fn set_diagnostic_payloads(&mut self, format: DiagnosticFormat) {
+        self.diagnostic_payloads = Some(format);
+    }
+
+    /// Share a [`ConnectionRegistry`] with the server.
+    ///
+    /// The server records every established connection in it, so the caller
+    /// can list active sessions or force-disconnect a client.
+    pub fn set_connection_registry(&mut self, registry: ConnectionRegistry) {
+        self.connection_registry = Some(registry);
+    }
+
+    /// Record the last-seen time of every identity in `tracker`, see
+    /// [`liveness`](crate::liveness).
+    pub fn set_liveness_tracker(&mut self, tracker: LivenessTracker) {
+        self.liveness = Some(tracker);
+    }
+
+    /// Map PSK identities to device IDs and tenants, see [`IdentityMapper`].
+    pub fn set_identity_mapper(&mut self, mapper: impl IdentityMapper) {
+        self.identity_mapper = Some(Arc::new(mapper));
+    }
+
+    /// Save runtime state on shutdown and restore it on startup, see
+    /// [`snapshot`](crate::snapshot).
+    pub fn set_snapshot(&mut self, snapshot: SnapshotConfig) {
+        self.snapshot = Some(snapshot);
+    }
+
+    /// Decompress request payloads and compress responses, see
+    /// [`compression`](crate::compression).
+    pub fn set_compression(&mut self, compression: CompressionConfig) {
+        self.compression = Some(compression);
+    }
+
+    /// Record every request the server answers in `sink`, in addition to
+    /// the sinks added before, see [`audit`](crate::audit).
+    pub fn add_audit_sink(&mut self, sink: impl AuditSink) {
+        self.audit_sinks.push(Arc::new(sink));
+    }
+
     /// Set the ACK timeout for Confirmable message retransmission.
     pub fn set_ack_timeout(&mut self, timeout: Duration) {
         self.ack_timeout = timeout;
@@ -273,18 +647,39 @@ impl Default for Config {
             initial_clients: None,
             client_command_buffer: 1000,
             max_message_size: 1152,
+            mtu: DEFAULT_MTU,
             block_cache_expiry: Duration::from_secs(120),
             max_observers_per_device: 100,
+            path_policy: PathPolicy::default(),
+            validate_request_paths: true,
             max_connections: 1000,
-            notification_timeout_ms: 1000,
+            max_concurrent_handshakes: 64,
+            accept_queue_size: 256,
+            handshake_timeout: Duration::from_secs(10),
+            notification_queue_size: 10,
+            notification_overflow: OverflowPolicy::default(),
+            takeover_policy: TakeoverPolicy::EvictOld,
             min_reconnect_interval: Duration::from_secs(5),
             max_reconnect_attempts: 10,
             max_session_lifetime: None,
+            keepalive_interval: None,
+            address_migration: true,
             max_latency: Duration::from_secs(100),
             ack_timeout: Duration::from_secs(2),
             ack_random_factor: 1.5,
             max_retransmit: 4,
             shutdown: None,
+            notification_stream_pacing: Duration::from_millis(100),
+            max_json_payload_size: PayloadLimits::DEFAULT_JSON,
+            max_cbor_payload_size: PayloadLimits::DEFAULT_CBOR,
+            max_senml_payload_size: PayloadLimits::DEFAULT_SENML,
+            diagnostic_payloads: None,
+            connection_registry: None,
+            liveness: None,
+            identity_mapper: None,
+            snapshot: None,
+            compression: None,
+            audit_sinks: Vec::new(),
         }
     }
 }
@@ -300,6 +695,10 @@ mod tests {
         assert_eq!(config.buffer_size(), Config::DEFAULT_BUFFER_SIZE);
         assert!(config.dimpl_cfg.is_none());
         assert!(config.max_session_lifetime.is_none());
+        assert_eq!(config.max_concurrent_handshakes, 64);
+        assert_eq!(config.accept_queue_size, 256);
+        assert_eq!(config.handshake_timeout, Duration::from_secs(10));
+        assert_eq!(config.payload_limits(), PayloadLimits::default());
     }
 
     #[test]
@@ -348,6 +747,85 @@ mod tests {
         assert_eq!(lifetime, Duration::from_secs(247));
     }
 
+    #[test]
+    fn test_validate() {
+        assert_eq!(Config::default().validate(), Ok(()));
+
+        let config = Config {
+            buffer_size: 16,
+            ..Config::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::InvalidBufferSize { size: 16, .. })
+        ));
+
+        let config = Config {
+            max_session_lifetime: Some(Duration::ZERO),
+            ..Config::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::ZeroDuration("max_session_lifetime"))
+        );
+
+        let config = Config {
+            client_command_buffer: 0,
+            ..Config::default()
+        };
+        let err = config.validate().unwrap_err();
+        assert_eq!(err, ConfigError::ZeroLimit("client_command_buffer"));
+        assert_eq!(
+            err.to_string(),
+            "Invalid client_command_buffer: must be at least 1"
+        );
+
+        let config = Config {
+            ack_random_factor: f64::NAN,
+            ..Config::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::InvalidAckRandomFactor(_))
+        ));
+
+        let config = Config {
+            max_retransmit: 64,
+            ..Config::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::InvalidMaxRetransmit(64))
+        );
+
+        let mut config = Config::default();
+        config.set_mtu(100);
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::InvalidMtu {
+                mtu: 100,
+                min: MIN_MTU
+            })
+        );
+
+        let mut config = Config::default();
+        config.set_keepalive_interval(Duration::from_secs(30));
+        assert_eq!(config.validate(), Ok(()));
+        config.set_keepalive_interval(Duration::from_secs(60));
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::InvalidKeepaliveInterval {
+                interval: Duration::from_secs(60),
+                timeout: 60
+            })
+        );
+        config.set_keepalive_interval(Duration::ZERO);
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::ZeroDuration("keepalive_interval"))
+        );
+    }
+
     #[test]
     fn test_timeout_validation() {
         let mut config = Config::default();
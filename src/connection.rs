@@ -0,0 +1,912 @@
+//! Registry of active connections for admin tooling.
+//!
+//! The server records every established DTLS session in a [`ConnectionRegistry`].
+//! Pass your own registry via [`Config::set_connection_registry`](crate::config::Config::set_connection_registry)
+//! to list connections or force-disconnect a client while the server is running.
+//!
+//! The registry also tracks DTLS handshake admission. Handshakes are expensive,
+//! so the server runs at most
+//! [`Config::max_concurrent_handshakes`](crate::config::Config::max_concurrent_handshakes)
+//! of them at once; further peers wait in a bounded accept queue and are dropped
+//! once it is full. [`ConnectionRegistry::handshake_stats`] reports the counters.
+//!
+//! Every connection runs in a task owned by its server. A connection task that
+//! panics still releases its registry entry and observer registrations, and
+//! [`ConnectionRegistry::task_stats`] counts running and panicked tasks.
+//!
+//! Connection and queue timestamps are read from tokio's clock, so tests can
+//! pause and advance time to exercise rate limits and timeouts.
+//!
+//! Connections restored from a [snapshot](crate::snapshot) wait in the
+//! registry until their identity reconnects or the snapshot's
+//! [`max_age`](crate::snapshot::SnapshotConfig::max_age) runs out.
+//!
+//! When a connection ends, the registry keeps the last Observe sequence
+//! number sent to its identity. A device that wakes up and reconnects
+//! continues from there, so its notifications stay fresh by RFC 7641 §3.4
+//! even if it holds on to its observations across the new session.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::{
+    sync::{Mutex, Notify, mpsc::Sender},
+    time::Instant,
+};
+
+use crate::device_client::DeviceRequest;
+use crate::observer::metadata::ObservationMetadata;
+use crate::snapshot::{ConnectionRecord, ObservationRecord};
+use crate::tracker::{Claim, ConnectionTracker, memory::MemoryConnectionTracker};
+
+/// Per-connection counters updated by the connection task without taking
+/// the registry lock.
+#[derive(Debug)]
+pub(crate) struct ConnectionStats {
+    created_at: Instant,
+    /// Milliseconds since `created_at` of the last received packet.
+    last_activity_ms: AtomicU64,
+    observations: AtomicUsize,
+    notifications_dropped: AtomicU64,
+    /// Last Observe sequence number the connection sent.
+    observe_sequence: AtomicU32,
+    /// Observations published by the connection task for snapshots.
+    observed: std::sync::Mutex<Vec<ObservedPath>>,
+}
+
+/// An observation as published by its connection task.
+#[derive(Debug, Clone)]
+pub(crate) struct ObservedPath {
+    pub(crate) path: String,
+    pub(crate) token: Vec<u8>,
+    /// Shared with the observation, so later changes are seen
+    pub(crate) metadata: ObservationMetadata,
+}
+
+impl ConnectionStats {
+    pub(crate) fn new() -> Self {
+        Self {
+            created_at: Instant::now(),
+            last_activity_ms: AtomicU64::new(0),
+            observations: AtomicUsize::new(0),
+            notifications_dropped: AtomicU64::new(0),
+            observe_sequence: AtomicU32::new(0),
+            observed: std::sync::Mutex::default(),
+        }
+    }
+
+    /// Record activity on this connection.
+    pub(crate) fn touch(&self) {
+        let elapsed = self.created_at.elapsed().as_millis() as u64;
+        self.last_activity_ms.store(elapsed, Ordering::Relaxed);
+    }
+
+    /// Record the number of active observations on this connection.
+    pub(crate) fn set_observations(&self, count: usize) {
+        self.observations.store(count, Ordering::Relaxed);
+    }
+
+    /// Record the last Observe sequence number sent on this connection.
+    pub(crate) fn set_observe_sequence(&self, sequence: u32) {
+        self.observe_sequence.store(sequence, Ordering::Relaxed);
+    }
+
+    /// Publish the connection's observations for snapshots.
+    pub(crate) fn set_observed(&self, observed: Vec<ObservedPath>) {
+        *self.observed.lock().unwrap_or_else(|e| e.into_inner()) = observed;
+    }
+
+    fn observation_records(&self) -> Vec<ObservationRecord> {
+        let observed = self.observed.lock().unwrap_or_else(|e| e.into_inner());
+        observed
+            .iter()
+            .map(|o| ObservationRecord {
+                path: o.path.clone(),
+                token: o.token.clone(),
+                metadata: o.metadata.to_map(),
+            })
+            .collect()
+    }
+
+    /// Record the number of notifications the connection's queue dropped,
+    /// returning how many were dropped since the last call.
+    pub(crate) fn set_notifications_dropped(&self, count: u64) -> u64 {
+        let previous = self.notifications_dropped.swap(count, Ordering::Relaxed);
+        count.saturating_sub(previous)
+    }
+
+    fn last_activity(&self) -> Instant {
+        self.created_at + Duration::from_millis(self.last_activity_ms.load(Ordering::Relaxed))
+    }
+}
+
+/// Shared counters for DTLS handshake admission.
+#[derive(Debug, Default)]
+pub(crate) struct HandshakeCounters {
+    in_progress: AtomicUsize,
+    queued: AtomicUsize,
+    dropped: AtomicU64,
+    timed_out: AtomicU64,
+    /// Signalled whenever a handshake slot is released
+    pub(crate) freed: Notify,
+}
+
+impl HandshakeCounters {
+    pub(crate) fn in_progress(&self) -> usize {
+        self.in_progress.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_queued(&self, count: usize) {
+        self.queued.store(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_timeout(&self) {
+        self.timed_out.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Shared counters of the server's connection tasks.
+#[derive(Debug, Default)]
+pub(crate) struct TaskCounters {
+    running: AtomicUsize,
+    panicked: AtomicU64,
+}
+
+impl TaskCounters {
+    pub(crate) fn set_running(&self, count: usize) {
+        self.running.store(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_panic(&self) {
+        self.panicked.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Shared counters of the notifications delivered by the server's
+/// connections.
+#[derive(Debug, Default)]
+pub(crate) struct NotificationCounters {
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl NotificationCounters {
+    pub(crate) fn record_sent(&self) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_dropped(&self, count: u64) {
+        self.dropped.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_failed(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A handshake slot held by a connection task until the DTLS session is
+/// established. Dropping it frees the slot for a queued peer.
+#[derive(Debug)]
+pub(crate) struct HandshakeSlot(Arc<HandshakeCounters>);
+
+impl HandshakeSlot {
+    pub(crate) fn acquire(counters: &Arc<HandshakeCounters>) -> Self {
+        counters.in_progress.fetch_add(1, Ordering::Relaxed);
+        Self(counters.clone())
+    }
+}
+
+impl Drop for HandshakeSlot {
+    fn drop(&mut self) {
+        self.0.in_progress.fetch_sub(1, Ordering::Relaxed);
+        self.0.freed.notify_one();
+    }
+}
+
+/// Outcome of offering a new peer to the [`AcceptQueue`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Enqueued {
+    /// The peer is now waiting for a handshake slot
+    Queued,
+    /// The peer was already waiting; its latest datagram replaced the old one
+    Refreshed,
+    /// The queue is full and the datagram was dropped
+    Full,
+}
+
+/// Bounded FIFO of peers waiting for a handshake slot.
+///
+/// Only the most recent datagram of each peer is kept; DTLS clients
+/// retransmit their ClientHello, so older copies carry nothing new. Each
+/// datagram is kept with the index of the listener that received it.
+#[derive(Debug)]
+pub(crate) struct AcceptQueue {
+    pending: VecDeque<(SocketAddr, (usize, Vec<u8>), Instant)>,
+    capacity: usize,
+    max_wait: Duration,
+}
+
+impl AcceptQueue {
+    pub(crate) fn new(capacity: usize, max_wait: Duration) -> Self {
+        Self {
+            pending: VecDeque::new(),
+            capacity,
+            max_wait,
+        }
+    }
+
+    pub(crate) fn offer(
+        &mut self,
+        remote: SocketAddr,
+        listener: usize,
+        datagram: Vec<u8>,
+    ) -> Enqueued {
+        if let Some(entry) = self.pending.iter_mut().find(|(addr, ..)| *addr == remote) {
+            entry.1 = (listener, datagram);
+            return Enqueued::Refreshed;
+        }
+        if self.pending.len() >= self.capacity {
+            return Enqueued::Full;
+        }
+        self.pending
+            .push_back((remote, (listener, datagram), Instant::now()));
+        Enqueued::Queued
+    }
+
+    /// Remove a peer, e.g. because it was admitted directly
+    pub(crate) fn remove(&mut self, remote: &SocketAddr) {
+        self.pending.retain(|(addr, ..)| addr != remote);
+    }
+
+    /// Take the longest-waiting peer, skipping peers that waited longer than
+    /// a handshake may take (the client has given up on them by now)
+    pub(crate) fn pop(&mut self) -> Option<(SocketAddr, usize, Vec<u8>)> {
+        while let Some((remote, (listener, datagram), queued_at)) = self.pending.pop_front() {
+            if queued_at.elapsed() < self.max_wait {
+                return Some((remote, listener, datagram));
+            }
+            tracing::debug!(addr = %remote, "connection.queue_expired");
+        }
+        None
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Point-in-time view of DTLS handshake admission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HandshakeStats {
+    /// Handshakes currently running
+    pub in_progress: usize,
+    /// Peers waiting in the accept queue for a handshake slot
+    pub queued: usize,
+    /// New peers dropped because the accept queue was full
+    pub dropped: u64,
+    /// Handshakes aborted for not completing within the handshake timeout
+    pub timed_out: u64,
+}
+
+/// Point-in-time view of the server's connection tasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TaskStats {
+    /// Connection tasks currently running, including handshakes
+    pub running: usize,
+    /// Connection tasks that panicked. Each was cleaned up and its
+    /// connection closed.
+    pub panicked: u64,
+}
+
+/// Totals of the observer notifications handled by the server's
+/// connections, including connections that have ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NotificationStats {
+    /// Notifications sent to the client
+    pub sent: u64,
+    /// Notifications dropped because a connection's notification queue was
+    /// full
+    pub dropped: u64,
+    /// Notifications that were dequeued but not sent: the notify handler
+    /// failed, the payload didn't fit the MTU, or the send failed
+    pub failed: u64,
+}
+
+/// What happens when a client authenticates with a PSK identity that already
+/// has an active connection.
+///
+/// Observer registrations are always scoped to the connection that made them,
+/// so whichever policy is chosen, one connection can neither receive nor
+/// cancel another connection's observations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TakeoverPolicy {
+    /// Refuse the new connection while the existing one is active.
+    ///
+    /// Protects a live session from a spoofed or leaked identity, at the cost
+    /// of locking out a legitimate client whose previous session has not timed
+    /// out yet (e.g. after a NAT rebinding).
+    RejectNew,
+    /// Accept the new connection and close the existing one. The evicted
+    /// connection ends each of its observations with a final 5.03 notification
+    /// before it is closed.
+    #[default]
+    EvictOld,
+    /// Keep both connections. The registry tracks the most recent one.
+    AllowBoth,
+}
+
+/// Why a connection task is asked to terminate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DisconnectReason {
+    /// Disconnected through [`ConnectionRegistry::disconnect`]
+    Requested,
+    /// Another connection took over the identity
+    TakenOver,
+}
+
+/// Connection information for security tracking and rate limiting
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectionInfo {
+    pub(crate) sender: Sender<DisconnectReason>,
+    /// Requests for the device, see [`DeviceClient`](crate::device_client::DeviceClient)
+    pub(crate) requests: Sender<DeviceRequest>,
+    pub(crate) established_at: Instant,
+    pub(crate) source_addr: SocketAddr,
+    pub(crate) reconnect_count: u32,
+    pub(crate) stats: Arc<ConnectionStats>,
+    /// The connection's claim in the [`ConnectionTracker`]
+    pub(crate) claim: Claim,
+}
+
+/// Point-in-time view of an active connection.
+#[derive(Debug, Clone)]
+pub struct ConnectionSnapshot {
+    /// PSK identity of the client
+    pub identity: String,
+    /// Remote address of the DTLS session
+    pub remote_addr: SocketAddr,
+    /// When the DTLS session was established
+    pub connected_at: std::time::Instant,
+    /// When the last packet was received from the client
+    pub last_activity: std::time::Instant,
+    /// Number of active observations held by the connection
+    pub observation_count: usize,
+    /// Number of times this identity reconnected while a previous session was still tracked
+    pub reconnect_count: u32,
+    /// Number of observer notifications dropped because the connection's
+    /// notification queue was full
+    pub notifications_dropped: u64,
+}
+
+impl ConnectionSnapshot {
+    fn from_info(identity: &str, info: &ConnectionInfo) -> Self {
+        Self {
+            identity: identity.to_string(),
+            remote_addr: info.source_addr,
+            connected_at: info.established_at.into_std(),
+            last_activity: info
+                .stats
+                .last_activity()
+                .max(info.established_at)
+                .into_std(),
+            observation_count: info.stats.observations.load(Ordering::Relaxed),
+            reconnect_count: info.reconnect_count,
+            notifications_dropped: info.stats.notifications_dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A handle to the set of active connections on a running server.
+///
+/// Cheap to clone; all clones share the same underlying map.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use coapum::{ConnectionRegistry, config::Config};
+/// # async fn example() {
+/// let registry = ConnectionRegistry::new();
+/// let mut config = Config::default();
+/// config.set_connection_registry(registry.clone());
+///
+/// // ... start the server with `config` ...
+///
+/// for conn in registry.list().await {
+///     println!("{} from {} ({} observations)", conn.identity, conn.remote_addr, conn.observation_count);
+/// }
+/// registry.disconnect("revoked_device").await;
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConnectionRegistry {
+    pub(crate) inner: Arc<Mutex<HashMap<String, ConnectionInfo>>>,
+    pub(crate) handshakes: Arc<HandshakeCounters>,
+    pub(crate) tracker: Arc<dyn ConnectionTracker>,
+    pub(crate) tasks: Arc<TaskCounters>,
+    pub(crate) notifications: Arc<NotificationCounters>,
+    /// Connections restored from a snapshot, with their expiry
+    restored: Arc<std::sync::Mutex<HashMap<String, (Instant, ConnectionRecord)>>>,
+    /// Last Observe sequence numbers of ended connections, by identity
+    sequences: Arc<std::sync::Mutex<HashMap<String, u32>>>,
+}
+
+impl Default for ConnectionRegistry {
+    fn default() -> Self {
+        Self::with_tracker(Arc::new(MemoryConnectionTracker::new()))
+    }
+}
+
+impl ConnectionRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty registry that shares identity ownership through
+    /// `tracker`, so takeovers work across server instances.
+    pub fn with_tracker(tracker: Arc<dyn ConnectionTracker>) -> Self {
+        Self {
+            inner: Arc::default(),
+            handshakes: Arc::default(),
+            tracker,
+            tasks: Arc::default(),
+            notifications: Arc::default(),
+            restored: Arc::default(),
+            sequences: Arc::default(),
+        }
+    }
+
+    /// The tracker holding identity ownership
+    pub fn tracker(&self) -> &Arc<dyn ConnectionTracker> {
+        &self.tracker
+    }
+
+    /// List all active connections, sorted by identity
+    pub async fn list(&self) -> Vec<ConnectionSnapshot> {
+        let guard = self.inner.lock().await;
+        let mut snapshots: Vec<_> = guard
+            .iter()
+            .map(|(identity, info)| ConnectionSnapshot::from_info(identity, info))
+            .collect();
+        snapshots.sort_by(|a, b| a.identity.cmp(&b.identity));
+        snapshots
+    }
+
+    /// Get the active connection for an identity, if any
+    pub async fn get(&self, identity: &str) -> Option<ConnectionSnapshot> {
+        self.inner
+            .lock()
+            .await
+            .get(identity)
+            .map(|info| ConnectionSnapshot::from_info(identity, info))
+    }
+
+    /// Number of active connections
+    pub async fn len(&self) -> usize {
+        self.inner.lock().await.len()
+    }
+
+    /// Whether there are no active connections
+    pub async fn is_empty(&self) -> bool {
+        self.inner.lock().await.is_empty()
+    }
+
+    /// Counters of the DTLS handshake admission path
+    pub fn handshake_stats(&self) -> HandshakeStats {
+        let counters = &self.handshakes;
+        HandshakeStats {
+            in_progress: counters.in_progress(),
+            queued: counters.queued.load(Ordering::Relaxed),
+            dropped: counters.dropped.load(Ordering::Relaxed),
+            timed_out: counters.timed_out.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Counters of the connection tasks
+    pub fn task_stats(&self) -> TaskStats {
+        TaskStats {
+            running: self.tasks.running.load(Ordering::Relaxed),
+            panicked: self.tasks.panicked.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Counters of the observer notification path
+    pub fn notification_stats(&self) -> NotificationStats {
+        let counters = &self.notifications;
+        NotificationStats {
+            sent: counters.sent.load(Ordering::Relaxed),
+            dropped: counters.dropped.load(Ordering::Relaxed),
+            failed: counters.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Force-disconnect the connection for an identity.
+    ///
+    /// This terminates the DTLS session and clears its observer registrations.
+    /// Returns `false` if the identity has no active connection.
+    pub async fn disconnect(&self, identity: &str) -> bool {
+        let sender = match self.inner.lock().await.get(identity) {
+            Some(info) => info.sender.clone(),
+            None => return false,
+        };
+
+        // A full channel means a disconnect is already pending
+        let _ = sender.try_send(DisconnectReason::Requested);
+        tracing::info!(identity = %identity, "client.disconnected");
+        true
+    }
+
+    /// Close a connection on behalf of a takeover requested by another node.
+    ///
+    /// Only the connection holding `connection_id` is closed, so a takeover
+    /// request arriving late cannot evict a newer connection.
+    pub(crate) async fn evict(&self, identity: &str, connection_id: u64) -> bool {
+        let sender = match self.inner.lock().await.get(identity) {
+            Some(info) if info.claim.connection_id == connection_id => info.sender.clone(),
+            _ => return false,
+        };
+
+        let _ = sender.try_send(DisconnectReason::TakenOver);
+        tracing::info!(identity = %identity, "connection.takeover.remote");
+        true
+    }
+
+    /// Remove an identity's entry, but only if it still belongs to the
+    /// connection owning `stats` (a reconnect may already have replaced it).
+    /// The connection's claim is released as well.
+    pub(crate) async fn remove(&self, identity: &str, stats: &Arc<ConnectionStats>) {
+        let removed = {
+            let mut guard = self.inner.lock().await;
+            if guard
+                .get(identity)
+                .is_some_and(|info| Arc::ptr_eq(&info.stats, stats))
+            {
+                guard.remove(identity)
+            } else {
+                None
+            }
+        };
+
+        if let Some(info) = removed {
+            self.keep_sequence(identity, &info.stats);
+            if let Err(e) = self.tracker.release(identity, &info.claim).await {
+                tracing::error!(identity = %identity, error = %e, "tracker.release_failed");
+            }
+        }
+    }
+
+    /// Remember the last Observe sequence number of a connection that is
+    /// ending, for the identity's next connection.
+    pub(crate) fn keep_sequence(&self, identity: &str, stats: &ConnectionStats) {
+        let sequence = stats.observe_sequence.load(Ordering::Relaxed);
+        self.sequences
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(identity.to_string(), sequence);
+    }
+
+    /// Take the Observe sequence number a new connection continues from.
+    pub(crate) fn take_sequence(&self, identity: &str) -> Option<u32> {
+        self.sequences
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(identity)
+    }
+
+    /// Record the new address of a connection whose peer moved, if the
+    /// identity's entry still belongs to the connection owning `stats`.
+    pub(crate) async fn migrate(
+        &self,
+        identity: &str,
+        stats: &Arc<ConnectionStats>,
+        remote: SocketAddr,
+    ) {
+        let mut guard = self.inner.lock().await;
+        if let Some(info) = guard.get_mut(identity)
+            && Arc::ptr_eq(&info.stats, stats)
+        {
+            info.source_addr = remote;
+        }
+    }
+
+    /// Records of the established connections for a snapshot.
+    pub(crate) async fn records(&self) -> Vec<ConnectionRecord> {
+        let guard = self.inner.lock().await;
+        let mut records: Vec<_> = guard
+            .iter()
+            .map(|(identity, info)| ConnectionRecord {
+                identity: identity.clone(),
+                remote_addr: info.source_addr,
+                reconnect_count: info.reconnect_count,
+                observations: info.stats.observation_records(),
+            })
+            .collect();
+        records.sort_by(|a, b| a.identity.cmp(&b.identity));
+        records
+    }
+
+    /// Keep snapshot records until their identity reconnects, for at most `window`.
+    pub(crate) fn restore(&self, records: Vec<ConnectionRecord>, window: Duration) {
+        let expires_at = Instant::now() + window;
+        let mut restored = self.restored.lock().unwrap_or_else(|e| e.into_inner());
+        restored.retain(|_, (expiry, _)| *expiry > Instant::now());
+        for record in records {
+            restored.insert(record.identity.clone(), (expires_at, record));
+        }
+    }
+
+    /// Take the restored record of a newly established connection.
+    ///
+    /// The record's reconnect count carries over to the connection.
+    pub(crate) async fn take_restored(&self, identity: &str) -> Option<ConnectionRecord> {
+        let (expires_at, record) = self
+            .restored
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(identity)?;
+        if expires_at <= Instant::now() {
+            return None;
+        }
+        if let Some(info) = self.inner.lock().await.get_mut(identity) {
+            info.reconnect_count = info.reconnect_count.max(record.reconnect_count);
+        }
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn insert(
+        registry: &ConnectionRegistry,
+        identity: &str,
+    ) -> (
+        Arc<ConnectionStats>,
+        tokio::sync::mpsc::Receiver<DisconnectReason>,
+    ) {
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        let (requests, _) = tokio::sync::mpsc::channel(1);
+        let stats = Arc::new(ConnectionStats::new());
+        registry.inner.lock().await.insert(
+            identity.to_string(),
+            ConnectionInfo {
+                sender: tx,
+                requests,
+                established_at: Instant::now(),
+                source_addr: "127.0.0.1:5684".parse().unwrap(),
+                reconnect_count: 0,
+                stats: stats.clone(),
+                claim: Claim::new(
+                    MemoryConnectionTracker::NODE_ID,
+                    "127.0.0.1:5684".parse().unwrap(),
+                ),
+            },
+        );
+        (stats, rx)
+    }
+
+    #[tokio::test]
+    async fn test_registry_list_and_get() {
+        let registry = ConnectionRegistry::new();
+        assert!(registry.is_empty().await);
+
+        let (stats, _rx_b) = insert(&registry, "device_b").await;
+        let (_, _rx_a) = insert(&registry, "device_a").await;
+        stats.set_observations(3);
+        stats.touch();
+
+        let list = registry.list().await;
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].identity, "device_a");
+        assert_eq!(list[1].identity, "device_b");
+
+        let snapshot = registry.get("device_b").await.unwrap();
+        assert_eq!(snapshot.observation_count, 3);
+        assert!(snapshot.last_activity >= snapshot.connected_at);
+        assert!(registry.get("missing").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_registry_disconnect() {
+        let registry = ConnectionRegistry::new();
+        let (_, mut rx) = insert(&registry, "device_1").await;
+
+        assert!(registry.disconnect("device_1").await);
+        assert_eq!(rx.recv().await, Some(DisconnectReason::Requested));
+        assert!(!registry.disconnect("unknown").await);
+    }
+
+    #[tokio::test]
+    async fn test_registry_evict_only_claimed_connection() {
+        let registry = ConnectionRegistry::new();
+        let (_, mut rx) = insert(&registry, "device_1").await;
+        let connection_id = registry.inner.lock().await["device_1"].claim.connection_id;
+
+        assert!(
+            !registry
+                .evict("device_1", connection_id.wrapping_add(1))
+                .await
+        );
+        assert!(registry.evict("device_1", connection_id).await);
+        assert_eq!(rx.recv().await, Some(DisconnectReason::TakenOver));
+    }
+
+    #[tokio::test]
+    async fn test_registry_remove_only_current_connection() {
+        let registry = ConnectionRegistry::new();
+        let (old_stats, _rx_old) = insert(&registry, "device_1").await;
+        // Reconnect replaces the entry before the old task cleans up
+        let (new_stats, _rx_new) = insert(&registry, "device_1").await;
+
+        registry.remove("device_1", &old_stats).await;
+        assert_eq!(registry.len().await, 1);
+
+        registry.remove("device_1", &new_stats).await;
+        assert!(registry.is_empty().await);
+    }
+
+    #[tokio::test]
+    async fn test_registry_migrate() {
+        let registry = ConnectionRegistry::new();
+        let (stats, _rx) = insert(&registry, "device_1").await;
+        let moved: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+
+        // A stale connection of the identity can't move its entry
+        registry
+            .migrate("device_1", &Arc::new(ConnectionStats::new()), moved)
+            .await;
+        assert_ne!(registry.get("device_1").await.unwrap().remote_addr, moved);
+
+        registry.migrate("device_1", &stats, moved).await;
+        assert_eq!(registry.get("device_1").await.unwrap().remote_addr, moved);
+    }
+
+    #[tokio::test]
+    async fn test_registry_keeps_sequence_across_reconnect() {
+        let registry = ConnectionRegistry::new();
+        let (stats, _rx) = insert(&registry, "sleepy").await;
+        stats.set_observe_sequence(41);
+        assert_eq!(registry.take_sequence("sleepy"), None);
+
+        // The device sleeps, its session times out
+        registry.remove("sleepy", &stats).await;
+        assert!(registry.is_empty().await);
+
+        // It wakes up on a new session and continues the sequence once
+        let (_stats, _rx) = insert(&registry, "sleepy").await;
+        assert_eq!(registry.take_sequence("sleepy"), Some(41));
+        assert_eq!(registry.take_sequence("sleepy"), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_registry_records_and_restore() {
+        let registry = ConnectionRegistry::new();
+        let (stats, _rx) = insert(&registry, "device_1").await;
+        let metadata = ObservationMetadata::new();
+        metadata.insert("pmin", "10");
+        stats.set_observed(vec![ObservedPath {
+            path: "/temp".to_string(),
+            token: vec![0xca, 0xfe],
+            metadata,
+        }]);
+        registry
+            .inner
+            .lock()
+            .await
+            .get_mut("device_1")
+            .unwrap()
+            .reconnect_count = 4;
+
+        let mut records = registry.records().await;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].observations[0].token, vec![0xca, 0xfe]);
+        assert_eq!(records[0].observations[0].metadata["pmin"], "10");
+
+        // After a restart the record waits for the identity to reconnect
+        let restarted = ConnectionRegistry::new();
+        records.push(ConnectionRecord {
+            identity: "device_2".to_string(),
+            ..records[0].clone()
+        });
+        restarted.restore(records, Duration::from_secs(60));
+        let (_, _rx) = insert(&restarted, "device_1").await;
+        let record = restarted.take_restored("device_1").await.unwrap();
+        assert_eq!(record.observations.len(), 1);
+        assert_eq!(restarted.get("device_1").await.unwrap().reconnect_count, 4);
+        assert!(restarted.take_restored("device_1").await.is_none());
+
+        // Records expire with the restore window
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert!(restarted.take_restored("device_2").await.is_none());
+    }
+
+    #[test]
+    fn test_accept_queue_bounded() {
+        let mut queue = AcceptQueue::new(2, Duration::from_secs(10));
+        let a: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+        let b: SocketAddr = "127.0.0.1:1001".parse().unwrap();
+        let c: SocketAddr = "127.0.0.1:1002".parse().unwrap();
+
+        assert_eq!(queue.offer(a, 0, vec![1]), Enqueued::Queued);
+        assert_eq!(queue.offer(b, 0, vec![2]), Enqueued::Queued);
+        assert_eq!(queue.offer(c, 0, vec![3]), Enqueued::Full);
+        // A retransmit from a queued peer replaces its datagram
+        assert_eq!(queue.offer(a, 1, vec![4]), Enqueued::Refreshed);
+
+        assert_eq!(queue.pop(), Some((a, 1, vec![4])));
+        queue.remove(&b);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_accept_queue_expires_stale_peers() {
+        let mut queue = AcceptQueue::new(4, Duration::ZERO);
+        queue.offer("127.0.0.1:1000".parse().unwrap(), 0, vec![1]);
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn test_task_stats() {
+        let registry = ConnectionRegistry::new();
+        assert_eq!(registry.task_stats(), TaskStats::default());
+
+        registry.tasks.set_running(3);
+        registry.tasks.record_panic();
+        let stats = registry.clone().task_stats();
+        assert_eq!(stats.running, 3);
+        assert_eq!(stats.panicked, 1);
+    }
+
+    #[test]
+    fn test_notification_stats() {
+        let registry = ConnectionRegistry::new();
+        let stats = ConnectionStats::new();
+        registry.notifications.record_sent();
+        registry.notifications.record_failed();
+
+        // Queue drops are counted once, however often they are published
+        for published in [2, 2, 5] {
+            let dropped = stats.set_notifications_dropped(published);
+            registry.notifications.record_dropped(dropped);
+        }
+        assert_eq!(
+            registry.notification_stats(),
+            NotificationStats {
+                sent: 1,
+                dropped: 5,
+                failed: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_handshake_slot_released_on_drop() {
+        let registry = ConnectionRegistry::new();
+        let slot = HandshakeSlot::acquire(&registry.handshakes);
+        registry.handshakes.record_dropped();
+        assert_eq!(registry.handshake_stats().in_progress, 1);
+
+        drop(slot);
+        let stats = registry.handshake_stats();
+        assert_eq!(stats.in_progress, 0);
+        assert_eq!(stats.dropped, 1);
+    }
+}
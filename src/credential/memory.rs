@@ -1,31 +1,35 @@
 //! In-memory credential store implementation.
 
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, PoisonError, RwLock};
 
 use crate::router::{ClientEntry, ClientMetadata};
 
 use super::{ClientInfo, CredentialStore, PskEntry};
 
+type Clients = HashMap<String, ClientEntry>;
+
 /// In-memory credential store backed by a `HashMap`.
 ///
 /// Suitable for development, testing, and single-instance deployments.
 /// For persistent or shared credential storage, implement [`CredentialStore`]
 /// with your preferred backend.
 ///
-/// **Note:** Uses `std::sync::RwLock` so `lookup_psk()` can be called
-/// synchronously from within the DTLS PSK resolver callback.
+/// **Note:** `lookup_psk()` is called synchronously from within the DTLS PSK
+/// resolver callback, so lookups read a snapshot of the clients. The
+/// `std::sync::RwLock` around it is held only to clone an [`Arc`] or to
+/// apply a single change, never for a lookup, and a panic while it is held
+/// does not make later handshakes panic. A change copies the clients only
+/// while a lookup still reads the previous snapshot.
 #[derive(Clone, Debug)]
 pub struct MemoryCredentialStore {
-    store: Arc<RwLock<HashMap<String, ClientEntry>>>,
+    store: Arc<RwLock<Arc<Clients>>>,
 }
 
 impl MemoryCredentialStore {
     /// Create an empty credential store.
     pub fn new() -> Self {
-        Self {
-            store: Arc::new(RwLock::new(HashMap::new())),
-        }
+        Self::with_clients(HashMap::new())
     }
 
     /// Create a credential store pre-populated with clients.
@@ -46,10 +50,29 @@ impl MemoryCredentialStore {
                 },
             );
         }
+        Self::with_clients(store)
+    }
+
+    fn with_clients(clients: Clients) -> Self {
         Self {
-            store: Arc::new(RwLock::new(store)),
+            store: Arc::new(RwLock::new(Arc::new(clients))),
         }
     }
+
+    /// The current clients
+    fn snapshot(&self) -> Arc<Clients> {
+        self.store
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Change the clients with `f`, copying them first if a lookup still
+    /// holds the current snapshot
+    fn update<T>(&self, f: impl FnOnce(&mut Clients) -> T) -> T {
+        let mut current = self.store.write().unwrap_or_else(PoisonError::into_inner);
+        f(Arc::make_mut(&mut current))
+    }
 }
 
 impl Default for MemoryCredentialStore {
@@ -62,8 +85,7 @@ impl CredentialStore for MemoryCredentialStore {
     type Error = std::convert::Infallible;
 
     fn lookup_psk(&self, identity: &str) -> Result<Option<PskEntry>, Self::Error> {
-        let store = self.store.read().unwrap();
-        Ok(store.get(identity).map(|entry| PskEntry {
+        Ok(self.snapshot().get(identity).map(|entry| PskEntry {
             key: entry.key.clone(),
             enabled: entry.metadata.enabled,
         }))
@@ -75,7 +97,6 @@ impl CredentialStore for MemoryCredentialStore {
         key: Vec<u8>,
         metadata: Option<ClientMetadata>,
     ) -> Result<(), Self::Error> {
-        let mut store = self.store.write().unwrap();
         let entry = ClientEntry {
             key,
             metadata: metadata.unwrap_or(ClientMetadata {
@@ -83,14 +104,13 @@ impl CredentialStore for MemoryCredentialStore {
                 ..Default::default()
             }),
         };
-        store.insert(identity.to_string(), entry);
+        self.update(|clients| clients.insert(identity.to_string(), entry));
         tracing::info!("Added client: {}", identity);
         Ok(())
     }
 
     async fn remove_client(&self, identity: &str) -> Result<bool, Self::Error> {
-        let mut store = self.store.write().unwrap();
-        let existed = store.remove(identity).is_some();
+        let existed = self.update(|clients| clients.remove(identity).is_some());
         if existed {
             tracing::info!("Removed client: {}", identity);
         } else {
@@ -100,9 +120,13 @@ impl CredentialStore for MemoryCredentialStore {
     }
 
     async fn update_key(&self, identity: &str, key: Vec<u8>) -> Result<bool, Self::Error> {
-        let mut store = self.store.write().unwrap();
-        if let Some(entry) = store.get_mut(identity) {
-            entry.key = key;
+        let updated = self.update(|clients| {
+            clients
+                .get_mut(identity)
+                .map(|entry| entry.key = key)
+                .is_some()
+        });
+        if updated {
             tracing::info!("Updated key for client: {}", identity);
             Ok(true)
         } else {
@@ -116,9 +140,13 @@ impl CredentialStore for MemoryCredentialStore {
         identity: &str,
         metadata: ClientMetadata,
     ) -> Result<bool, Self::Error> {
-        let mut store = self.store.write().unwrap();
-        if let Some(entry) = store.get_mut(identity) {
-            entry.metadata = metadata;
+        let updated = self.update(|clients| {
+            clients
+                .get_mut(identity)
+                .map(|entry| entry.metadata = metadata)
+                .is_some()
+        });
+        if updated {
             tracing::info!("Updated metadata for client: {}", identity);
             Ok(true)
         } else {
@@ -128,9 +156,13 @@ impl CredentialStore for MemoryCredentialStore {
     }
 
     async fn set_enabled(&self, identity: &str, enabled: bool) -> Result<bool, Self::Error> {
-        let mut store = self.store.write().unwrap();
-        if let Some(entry) = store.get_mut(identity) {
-            entry.metadata.enabled = enabled;
+        let updated = self.update(|clients| {
+            clients
+                .get_mut(identity)
+                .map(|entry| entry.metadata.enabled = enabled)
+                .is_some()
+        });
+        if updated {
             tracing::info!("Set client {} enabled: {}", identity, enabled);
             Ok(true)
         } else {
@@ -140,13 +172,11 @@ impl CredentialStore for MemoryCredentialStore {
     }
 
     async fn list_clients(&self) -> Result<Vec<String>, Self::Error> {
-        let store = self.store.read().unwrap();
-        Ok(store.keys().cloned().collect())
+        Ok(self.snapshot().keys().cloned().collect())
     }
 
     async fn get_client(&self, identity: &str) -> Result<Option<ClientInfo>, Self::Error> {
-        let store = self.store.read().unwrap();
-        Ok(store.get(identity).map(|entry| ClientInfo {
+        Ok(self.snapshot().get(identity).map(|entry| ClientInfo {
             identity: identity.to_string(),
             enabled: entry.metadata.enabled,
             metadata: entry.metadata.clone(),
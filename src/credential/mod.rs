@@ -11,7 +11,9 @@
 //! See the `lookup_psk` documentation for safe patterns.
 
 pub mod memory;
+pub mod reload;
 pub mod resolver;
+pub mod tenant;
 
 use std::fmt::Debug;
 use std::future::Future;
@@ -99,6 +101,12 @@ pub trait CredentialStore: Clone + Debug + Send + Sync + 'static {
     ///     tokio::runtime::Handle::current().block_on(self.db.query(identity))
     /// }
     ///
+    /// // WRONG — panics when called from within the runtime, which the
+    /// // handshake always is:
+    /// fn lookup_psk(&self, identity: &str) -> Result<Option<PskEntry>, Self::Error> {
+    ///     Ok(self.clients.blocking_read().get(identity).cloned())
+    /// }
+    ///
     /// // WRONG — .await is not available in a sync context:
     /// fn lookup_psk(&self, identity: &str) -> Result<Option<PskEntry>, Self::Error> {
     ///     self.async_store.get(identity).await  // compile error
@@ -107,19 +115,28 @@ pub trait CredentialStore: Clone + Debug + Send + Sync + 'static {
     ///
     /// # Recommended Patterns
     ///
-    /// - **`std::sync::RwLock`** — used by [`memory::MemoryCredentialStore`].
-    ///   Simple and correct for in-memory stores.
+    /// - **Snapshots behind a `std::sync::RwLock`** — used by
+    ///   [`memory::MemoryCredentialStore`]. Lookups clone an `Arc` of the
+    ///   clients under the lock and read it without holding the lock.
     /// - **`DashMap`** — lock-free concurrent reads; best for database-backed
     ///   stores that maintain an in-memory cache refreshed by a background task.
     /// - **`parking_lot::RwLock`** — synchronous lock that does not interact
     ///   with tokio's cooperative scheduling.
-    /// - **`tokio::sync::RwLock::blocking_read()`** — works on **multi-threaded
-    ///   runtimes only**. Will deadlock on `current_thread` runtimes
-    ///   (e.g., `#[tokio::test]` defaults to `current_thread`).
     ///
     /// See [`memory::MemoryCredentialStore`] for a reference implementation.
+    /// A panicking `lookup_psk` fails the handshake; the panic is caught and
+    /// logged rather than unwinding into the DTLS stack.
     fn lookup_psk(&self, identity: &str) -> Result<Option<PskEntry>, Self::Error>;
 
+    /// PSK identity hint to announce in new handshakes.
+    ///
+    /// Asked once per connection; `Some` overrides
+    /// [`Config::psk_identity_hint`](crate::config::Config::psk_identity_hint).
+    /// The default implementation returns `None`.
+    fn psk_identity_hint(&self) -> Option<Vec<u8>> {
+        None
+    }
+
     /// Add a client with a PSK key and optional metadata.
     fn add_client(
         &self,
@@ -0,0 +1,216 @@
+//! Replacing the credentials of a running server.
+//!
+//! The server asks its credential store for a PSK on every handshake, so
+//! swapping the store swaps the credentials new handshakes are checked
+//! against. A [`ReloadableStore`] is served like any other store, and the
+//! [`CredentialReloader`] handle it hands out replaces the store, or the PSK
+//! identity hint the server announces, in one step while the server runs.
+//!
+//! Established sessions keep the keys they were negotiated with and are not
+//! touched by a reload. They pick up the new credentials when they
+//! handshake again: when the device reconnects, or when
+//! [`Config::max_session_lifetime`](crate::config::Config::max_session_lifetime)
+//! ends its session. Since sessions start at different times, a session
+//! lifetime spreads the re-handshakes of a fleet out instead of dropping
+//! every connection at once. Devices that must not keep their old session
+//! can be disconnected one by one with
+//! [`ClientManager::disconnect_client`](crate::router::ClientManager::disconnect_client).
+
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::router::ClientMetadata;
+
+use super::{ClientInfo, CredentialStore, PskEntry};
+
+#[derive(Debug)]
+struct Current<C> {
+    store: C,
+    psk_identity_hint: Option<Vec<u8>>,
+}
+
+/// A credential store that can be replaced while the server runs
+///
+/// Every operation goes to the store that is current when it starts, so a
+/// handshake or management command racing a reload sees either the old or
+/// the new store, never a mix. Clones share the current store.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::{CredentialStore, MemoryCredentialStore, credential::reload::ReloadableStore};
+///
+/// # async fn example() {
+/// let old = MemoryCredentialStore::new();
+/// old.add_client("sensor-42", b"old-key".to_vec(), None).await.unwrap();
+///
+/// let store = ReloadableStore::new(old);
+/// let reloader = store.reloader();
+/// // ... serve with `store.clone()` ...
+///
+/// let new = MemoryCredentialStore::new();
+/// new.add_client("sensor-42", b"new-key".to_vec(), None).await.unwrap();
+/// reloader.swap(new);
+/// assert_eq!(store.lookup_psk("sensor-42").unwrap().unwrap().key, b"new-key");
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ReloadableStore<C> {
+    current: Arc<RwLock<Current<C>>>,
+}
+
+impl<C: CredentialStore> ReloadableStore<C> {
+    /// A reloadable store starting out with `store`
+    pub fn new(store: C) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Current {
+                store,
+                psk_identity_hint: None,
+            })),
+        }
+    }
+
+    /// A handle for replacing the credentials of this store and its clones
+    pub fn reloader(&self) -> CredentialReloader<C> {
+        CredentialReloader {
+            current: self.current.clone(),
+        }
+    }
+
+    /// The current store
+    pub fn current(&self) -> C {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .store
+            .clone()
+    }
+}
+
+/// A handle for replacing the credentials of a running server, see the
+/// [module documentation](self)
+///
+/// Cheap to clone.
+#[derive(Clone, Debug)]
+pub struct CredentialReloader<C> {
+    current: Arc<RwLock<Current<C>>>,
+}
+
+impl<C: CredentialStore> CredentialReloader<C> {
+    /// Check handshakes against `store` from now on. Returns the store it
+    /// replaces.
+    pub fn swap(&self, store: C) -> C {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        tracing::info!("Reloaded credential store");
+        std::mem::replace(&mut current.store, store)
+    }
+
+    /// Announce `hint` in new handshakes, in place of the hint of the
+    /// [`Config`](crate::config::Config) the server was started with.
+    /// `None` goes back to the configured hint.
+    pub fn set_psk_identity_hint(&self, hint: Option<Vec<u8>>) {
+        self.current
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .psk_identity_hint = hint;
+    }
+
+    /// The current store
+    pub fn current(&self) -> C {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .store
+            .clone()
+    }
+}
+
+impl<C: CredentialStore> CredentialStore for ReloadableStore<C> {
+    type Error = C::Error;
+
+    fn lookup_psk(&self, identity: &str) -> Result<Option<PskEntry>, Self::Error> {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .store
+            .lookup_psk(identity)
+    }
+
+    fn psk_identity_hint(&self) -> Option<Vec<u8>> {
+        let current = self.current.read().unwrap_or_else(PoisonError::into_inner);
+        current
+            .psk_identity_hint
+            .clone()
+            .or_else(|| current.store.psk_identity_hint())
+    }
+
+    async fn add_client(
+        &self,
+        identity: &str,
+        key: Vec<u8>,
+        metadata: Option<ClientMetadata>,
+    ) -> Result<(), Self::Error> {
+        self.current().add_client(identity, key, metadata).await
+    }
+
+    async fn remove_client(&self, identity: &str) -> Result<bool, Self::Error> {
+        self.current().remove_client(identity).await
+    }
+
+    async fn update_key(&self, identity: &str, key: Vec<u8>) -> Result<bool, Self::Error> {
+        self.current().update_key(identity, key).await
+    }
+
+    async fn update_metadata(
+        &self,
+        identity: &str,
+        metadata: ClientMetadata,
+    ) -> Result<bool, Self::Error> {
+        self.current().update_metadata(identity, metadata).await
+    }
+
+    async fn set_enabled(&self, identity: &str, enabled: bool) -> Result<bool, Self::Error> {
+        self.current().set_enabled(identity, enabled).await
+    }
+
+    async fn list_clients(&self) -> Result<Vec<String>, Self::Error> {
+        self.current().list_clients().await
+    }
+
+    async fn get_client(&self, identity: &str) -> Result<Option<ClientInfo>, Self::Error> {
+        self.current().get_client(identity).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryCredentialStore;
+
+    #[tokio::test]
+    async fn test_swap_replaces_credentials() {
+        let old = MemoryCredentialStore::new();
+        old.add_client("s1", b"old".to_vec(), None).await.unwrap();
+        let store = ReloadableStore::new(old.clone());
+        let served = store.clone();
+        let reloader = store.reloader();
+        assert_eq!(served.lookup_psk("s1").unwrap().unwrap().key, b"old");
+        assert_eq!(served.psk_identity_hint(), None);
+
+        let new = MemoryCredentialStore::new();
+        new.add_client("s2", b"new".to_vec(), None).await.unwrap();
+        let replaced = reloader.swap(new.clone());
+        assert!(replaced.lookup_psk("s1").unwrap().is_some());
+        assert!(served.lookup_psk("s1").unwrap().is_none());
+        assert_eq!(served.lookup_psk("s2").unwrap().unwrap().key, b"new");
+
+        // Management goes to the current store
+        served.add_client("s3", b"k".to_vec(), None).await.unwrap();
+        assert!(new.lookup_psk("s3").unwrap().is_some());
+        assert!(old.lookup_psk("s3").unwrap().is_none());
+
+        reloader.set_psk_identity_hint(Some(b"fleet-2".to_vec()));
+        assert_eq!(served.psk_identity_hint(), Some(b"fleet-2".to_vec()));
+        reloader.set_psk_identity_hint(None);
+        assert_eq!(served.psk_identity_hint(), None);
+    }
+}
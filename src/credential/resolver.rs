@@ -1,8 +1,8 @@
 //! PSK resolver implementations bridging [`CredentialStore`] to dimpl's [`PskResolver`].
 
 use std::collections::HashMap;
-use std::panic::{RefUnwindSafe, UnwindSafe};
-use std::sync::Mutex;
+use std::panic::{AssertUnwindSafe, RefUnwindSafe, UnwindSafe};
+use std::sync::{Mutex, PoisonError};
 
 use dimpl::PskResolver;
 
@@ -20,6 +20,9 @@ use super::CredentialStore;
 /// is effectively uncontended — `resolve()` is called synchronously inside
 /// `handle_packet()`, then read via [`take_last_identity`](Self::take_last_identity)
 /// on `Output::Connected` within the same task.
+///
+/// A store whose `lookup_psk` panics fails the handshake instead of
+/// unwinding through dimpl; the panic is logged as `auth.failed.store_panic`.
 pub struct CapturingResolver<C> {
     store: C,
     last_identity: Mutex<Option<String>>,
@@ -42,7 +45,10 @@ impl<C: CredentialStore> CapturingResolver<C> {
     /// Returns `Some(identity)` if a PSK was resolved since the last call,
     /// or `None` if no resolution occurred.
     pub fn take_last_identity(&self) -> Option<String> {
-        self.last_identity.lock().unwrap().take()
+        self.last_identity
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
     }
 
     /// Get a reference to the underlying credential store.
@@ -55,24 +61,38 @@ impl<C: CredentialStore> PskResolver for CapturingResolver<C> {
     fn resolve(&self, identity: &[u8]) -> Option<Vec<u8>> {
         let hint_str = String::from_utf8(identity.to_vec()).ok()?;
 
-        match self.store.lookup_psk(&hint_str) {
-            Ok(Some(entry)) if entry.enabled => {
+        let lookup =
+            std::panic::catch_unwind(AssertUnwindSafe(|| self.store.lookup_psk(&hint_str)));
+        match lookup {
+            Ok(Ok(Some(entry))) if entry.enabled => {
                 tracing::info!(identity = %hint_str, "auth.psk_found");
-                *self.last_identity.lock().unwrap() = Some(hint_str);
+                *self
+                    .last_identity
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner) = Some(hint_str);
                 Some(entry.key)
             }
-            Ok(Some(_)) => {
+            Ok(Ok(Some(_))) => {
                 tracing::warn!(identity = %hint_str, "auth.failed.disabled");
                 None
             }
-            Ok(None) => {
+            Ok(Ok(None)) => {
                 tracing::warn!(identity = %hint_str, "auth.failed.not_found");
                 None
             }
-            Err(e) => {
+            Ok(Err(e)) => {
                 tracing::error!(identity = %hint_str, error = ?e, "auth.failed.store_error");
                 None
             }
+            Err(panic) => {
+                let message = panic
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("unknown");
+                tracing::error!(identity = %hint_str, panic = %message, "auth.failed.store_panic");
+                None
+            }
         }
     }
 }
@@ -104,7 +124,8 @@ impl PskResolver for MapResolver {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::credential::memory::MemoryCredentialStore;
+    use crate::credential::{PskEntry, memory::MemoryCredentialStore};
+    use crate::router::ClientMetadata;
 
     #[test]
     fn capturing_resolver_resolves_and_captures() {
@@ -135,6 +156,52 @@ mod tests {
         assert_eq!(resolver.take_last_identity(), None);
     }
 
+    #[derive(Clone, Debug)]
+    struct PanickingStore;
+
+    impl CredentialStore for PanickingStore {
+        type Error = std::convert::Infallible;
+
+        fn lookup_psk(&self, _identity: &str) -> Result<Option<PskEntry>, Self::Error> {
+            panic!("backend unavailable")
+        }
+        async fn add_client(
+            &self,
+            _identity: &str,
+            _key: Vec<u8>,
+            _metadata: Option<ClientMetadata>,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn remove_client(&self, _identity: &str) -> Result<bool, Self::Error> {
+            Ok(false)
+        }
+        async fn update_key(&self, _identity: &str, _key: Vec<u8>) -> Result<bool, Self::Error> {
+            Ok(false)
+        }
+        async fn update_metadata(
+            &self,
+            _identity: &str,
+            _metadata: ClientMetadata,
+        ) -> Result<bool, Self::Error> {
+            Ok(false)
+        }
+        async fn set_enabled(&self, _identity: &str, _enabled: bool) -> Result<bool, Self::Error> {
+            Ok(false)
+        }
+        async fn list_clients(&self) -> Result<Vec<String>, Self::Error> {
+            Ok(vec![])
+        }
+    }
+
+    #[test]
+    fn capturing_resolver_survives_store_panic() {
+        let resolver = CapturingResolver::new(PanickingStore);
+
+        assert!(resolver.resolve(b"device1").is_none());
+        assert_eq!(resolver.take_last_identity(), None);
+    }
+
     #[test]
     fn map_resolver_works() {
         let mut keys = HashMap::new();
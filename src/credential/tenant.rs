@@ -0,0 +1,155 @@
+//! Per-tenant views of a shared credential store.
+
+use crate::router::ClientMetadata;
+
+use super::{ClientInfo, CredentialStore, PskEntry};
+
+/// One tenant's partition of a credential store.
+///
+/// Clients of all tenants live in the same store under PSK identities like
+/// `acme:sensor-42`, which [`TenantSplit`](crate::identity::TenantSplit)
+/// splits back into tenant and device ID when they connect. A `TenantStore`
+/// exposes only the clients of one tenant, by their device IDs, so
+/// provisioning code for one tenant can't see or modify another tenant's
+/// clients.
+///
+/// Serve with the shared store; the partition is for managing clients.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::{CredentialStore, MemoryCredentialStore, credential::tenant::TenantStore};
+///
+/// # async fn example() {
+/// let store = MemoryCredentialStore::new();
+/// let acme = TenantStore::new(store.clone(), "acme", ':');
+///
+/// acme.add_client("sensor-42", b"secret".to_vec(), None).await.unwrap();
+/// assert!(store.lookup_psk("acme:sensor-42").unwrap().is_some());
+/// assert_eq!(acme.list_clients().await.unwrap(), vec!["sensor-42".to_string()]);
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct TenantStore<C> {
+    inner: C,
+    prefix: String,
+}
+
+impl<C: CredentialStore> TenantStore<C> {
+    /// The partition of `tenant` in `inner`, whose identities join tenant
+    /// and device ID with `separator`.
+    pub fn new(inner: C, tenant: &str, separator: char) -> Self {
+        Self {
+            inner,
+            prefix: format!("{}{}", tenant, separator),
+        }
+    }
+
+    /// The identity a device of this tenant has in the shared store.
+    pub fn identity(&self, device_id: &str) -> String {
+        format!("{}{}", self.prefix, device_id)
+    }
+}
+
+impl<C: CredentialStore> CredentialStore for TenantStore<C> {
+    type Error = C::Error;
+
+    fn lookup_psk(&self, identity: &str) -> Result<Option<PskEntry>, Self::Error> {
+        self.inner.lookup_psk(&self.identity(identity))
+    }
+
+    fn psk_identity_hint(&self) -> Option<Vec<u8>> {
+        self.inner.psk_identity_hint()
+    }
+
+    async fn add_client(
+        &self,
+        identity: &str,
+        key: Vec<u8>,
+        metadata: Option<ClientMetadata>,
+    ) -> Result<(), Self::Error> {
+        self.inner
+            .add_client(&self.identity(identity), key, metadata)
+            .await
+    }
+
+    async fn remove_client(&self, identity: &str) -> Result<bool, Self::Error> {
+        self.inner.remove_client(&self.identity(identity)).await
+    }
+
+    async fn update_key(&self, identity: &str, key: Vec<u8>) -> Result<bool, Self::Error> {
+        self.inner.update_key(&self.identity(identity), key).await
+    }
+
+    async fn update_metadata(
+        &self,
+        identity: &str,
+        metadata: ClientMetadata,
+    ) -> Result<bool, Self::Error> {
+        self.inner
+            .update_metadata(&self.identity(identity), metadata)
+            .await
+    }
+
+    async fn set_enabled(&self, identity: &str, enabled: bool) -> Result<bool, Self::Error> {
+        self.inner
+            .set_enabled(&self.identity(identity), enabled)
+            .await
+    }
+
+    async fn list_clients(&self) -> Result<Vec<String>, Self::Error> {
+        let clients = self.inner.list_clients().await?;
+        Ok(clients
+            .into_iter()
+            .filter_map(|identity| {
+                identity
+                    .strip_prefix(&self.prefix)
+                    .filter(|device_id| !device_id.is_empty())
+                    .map(str::to_string)
+            })
+            .collect())
+    }
+
+    async fn get_client(&self, identity: &str) -> Result<Option<ClientInfo>, Self::Error> {
+        let client = self.inner.get_client(&self.identity(identity)).await?;
+        Ok(client.map(|client| ClientInfo {
+            identity: identity.to_string(),
+            ..client
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryCredentialStore;
+
+    #[tokio::test]
+    async fn test_tenants_are_isolated() {
+        let store = MemoryCredentialStore::new();
+        let acme = TenantStore::new(store.clone(), "acme", ':');
+        let globex = TenantStore::new(store.clone(), "globex", ':');
+
+        acme.add_client("s1", b"acme-key".to_vec(), None)
+            .await
+            .unwrap();
+        globex
+            .add_client("s1", b"globex-key".to_vec(), None)
+            .await
+            .unwrap();
+        store.add_client("s2", b"key".to_vec(), None).await.unwrap();
+
+        assert_eq!(acme.lookup_psk("s1").unwrap().unwrap().key, b"acme-key");
+        assert_eq!(globex.lookup_psk("s1").unwrap().unwrap().key, b"globex-key");
+        assert_eq!(acme.list_clients().await.unwrap(), vec!["s1".to_string()]);
+        assert_eq!(acme.get_client("s1").await.unwrap().unwrap().identity, "s1");
+
+        // Clients outside the tenant are out of reach
+        assert!(acme.lookup_psk("s2").unwrap().is_none());
+        assert!(!acme.remove_client("s2").await.unwrap());
+
+        assert!(globex.remove_client("s1").await.unwrap());
+        assert!(store.lookup_psk("acme:s1").unwrap().is_some());
+        assert!(store.lookup_psk("globex:s1").unwrap().is_none());
+    }
+}
@@ -0,0 +1,368 @@
+//! Requests from the server to connected devices.
+//!
+//! DTLS sessions are opened by devices, but once established the server can
+//! use the same session to reach back: a [`DeviceClient`] sends a request to
+//! the device holding an identity and waits for its response. The request is
+//! handed to the identity's connection task, which assigns it a token and
+//! message ID, retransmits it like any other CON message, and matches the
+//! device's response by token, whether it comes piggybacked on the ACK or
+//! separately.
+//!
+//! A device that is not connected can't be reached; the request fails with
+//! [`DeviceRequestError::NotConnected`] instead of being queued.
+
+use std::{collections::HashMap, fmt, net::SocketAddr, time::Duration};
+
+use coap_lite::{CoapRequest, ContentFormat, MessageClass, Packet, RequestType};
+use tokio::sync::oneshot;
+
+use crate::{CoapResponse, ConnectionRegistry};
+
+/// Requests a connection task accepts before senders see
+/// [`DeviceRequestError::Busy`]
+pub(crate) const DEVICE_REQUEST_QUEUE: usize = 16;
+
+/// How long a request waits for its response by default
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Error returned by [`DeviceClient`] requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceRequestError {
+    /// The identity has no active connection
+    NotConnected,
+    /// The connection has too many requests waiting to be sent
+    Busy,
+    /// The device did not respond in time, or never acknowledged the request
+    Timeout,
+    /// The connection ended before the device responded
+    Disconnected,
+}
+
+impl fmt::Display for DeviceRequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceRequestError::NotConnected => write!(f, "device not connected"),
+            DeviceRequestError::Busy => write!(f, "device request queue full"),
+            DeviceRequestError::Timeout => write!(f, "device request timed out"),
+            DeviceRequestError::Disconnected => write!(f, "device disconnected"),
+        }
+    }
+}
+
+impl std::error::Error for DeviceRequestError {}
+
+type Reply = oneshot::Sender<Result<Packet, DeviceRequestError>>;
+
+/// A request handed to a connection task
+pub(crate) struct DeviceRequest {
+    pub(crate) packet: Packet,
+    pub(crate) reply: Reply,
+}
+
+impl fmt::Debug for DeviceRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeviceRequest")
+            .field("code", &self.packet.header.code)
+            .finish()
+    }
+}
+
+/// A request sent by the connection, awaiting its response
+struct Pending {
+    msg_id: u16,
+    reply: Reply,
+}
+
+/// Requests a connection task sent to its device, by token
+#[derive(Default)]
+pub(crate) struct PendingRequests {
+    by_token: HashMap<Vec<u8>, Pending>,
+}
+
+impl PendingRequests {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait for the response to the request sent with `token` and `msg_id`
+    pub(crate) fn insert(&mut self, token: Vec<u8>, msg_id: u16, reply: Reply) {
+        // Callers that timed out no longer wait
+        self.by_token
+            .retain(|_, pending| !pending.reply.is_closed());
+        self.by_token.insert(token, Pending { msg_id, reply });
+    }
+
+    /// Complete the request `packet` responds to. Returns false if the
+    /// packet is not a response or matches no request.
+    pub(crate) fn complete(&mut self, packet: &Packet) -> bool {
+        if !matches!(packet.header.code, MessageClass::Response(_)) {
+            return false;
+        }
+        let Some(pending) = self.by_token.remove(packet.get_token()) else {
+            return false;
+        };
+        let _ = pending.reply.send(Ok(packet.clone()));
+        true
+    }
+
+    /// Fail the request sent as `msg_id` after its retransmissions ran out
+    pub(crate) fn give_up(&mut self, msg_id: u16) {
+        let Some(token) = self
+            .by_token
+            .iter()
+            .find(|(_, pending)| pending.msg_id == msg_id)
+            .map(|(token, _)| token.clone())
+        else {
+            return;
+        };
+        if let Some(pending) = self.by_token.remove(&token) {
+            let _ = pending.reply.send(Err(DeviceRequestError::Timeout));
+        }
+    }
+}
+
+/// A handle for sending requests to the device connected as an identity
+///
+/// The identity is looked up for every request, so the handle keeps working
+/// across reconnects of the device. Cheap to clone.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use coapum::{ConnectionRegistry, config::Config};
+/// use coapum::device_client::DeviceClient;
+/// use std::time::Duration;
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let registry = ConnectionRegistry::new();
+/// let mut config = Config::default();
+/// config.set_connection_registry(registry.clone());
+/// // ... start the server with `config` ...
+///
+/// let device = DeviceClient::new(registry, "sensor-42").timeout(Duration::from_secs(10));
+/// let firmware = device.get("/fw/version").await?;
+/// println!("firmware: {:?}", String::from_utf8_lossy(&firmware.message.payload));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DeviceClient {
+    registry: ConnectionRegistry,
+    identity: String,
+    timeout: Duration,
+}
+
+impl DeviceClient {
+    /// A client for the device connected as `identity` to the server
+    /// sharing `registry`
+    pub fn new(registry: ConnectionRegistry, identity: impl Into<String>) -> Self {
+        Self {
+            registry,
+            identity: identity.into(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// How long to wait for a response (default: 30 seconds)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The identity requests are sent to
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    /// GET `path` from the device
+    pub async fn get(&self, path: &str) -> Result<CoapResponse, DeviceRequestError> {
+        let mut request: CoapRequest<SocketAddr> = CoapRequest::new();
+        request.set_method(RequestType::Get);
+        request.set_path(path);
+        self.send(request).await
+    }
+
+    /// PUT `payload` to `path` on the device
+    pub async fn put(
+        &self,
+        path: &str,
+        payload: Vec<u8>,
+        content_format: ContentFormat,
+    ) -> Result<CoapResponse, DeviceRequestError> {
+        let mut request: CoapRequest<SocketAddr> = CoapRequest::new();
+        request.set_method(RequestType::Put);
+        request.set_path(path);
+        request.message.set_content_format(content_format);
+        request.message.payload = payload;
+        self.send(request).await
+    }
+
+    /// Send `request` to the device and wait for its response
+    ///
+    /// The connection replaces the request's token and message ID. A
+    /// non-confirmable request is sent once; anything else is sent as CON
+    /// and retransmitted until acknowledged.
+    pub async fn send(
+        &self,
+        request: CoapRequest<SocketAddr>,
+    ) -> Result<CoapResponse, DeviceRequestError> {
+        let sender = self
+            .registry
+            .inner
+            .lock()
+            .await
+            .get(&self.identity)
+            .map(|info| info.requests.clone())
+            .ok_or(DeviceRequestError::NotConnected)?;
+
+        let (reply, response) = oneshot::channel();
+        sender
+            .try_send(DeviceRequest {
+                packet: request.message,
+                reply,
+            })
+            .map_err(|e| match e {
+                tokio::sync::mpsc::error::TrySendError::Full(_) => DeviceRequestError::Busy,
+                tokio::sync::mpsc::error::TrySendError::Closed(_) => {
+                    DeviceRequestError::Disconnected
+                }
+            })?;
+
+        match tokio::time::timeout(self.timeout, response).await {
+            Ok(Ok(Ok(message))) => Ok(CoapResponse { message }),
+            Ok(Ok(Err(e))) => Err(e),
+            Ok(Err(_)) => Err(DeviceRequestError::Disconnected),
+            Err(_) => {
+                tracing::debug!(identity = %self.identity, "device_request.timeout");
+                Err(DeviceRequestError::Timeout)
+            }
+        }
+    }
+}
+
+impl ConnectionRegistry {
+    /// A [`DeviceClient`] for the device connected as `identity`
+    pub fn device_client(&self, identity: impl Into<String>) -> DeviceClient {
+        DeviceClient::new(self.clone(), identity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::{ConnectionInfo, ConnectionStats};
+    use crate::tracker::{Claim, memory::MemoryConnectionTracker};
+    use coap_lite::{CoapOption, MessageType, ResponseType};
+    use std::sync::Arc;
+    use tokio::sync::mpsc;
+    use tokio::time::Instant;
+
+    fn response_to(packet: &Packet, status: ResponseType) -> Packet {
+        let mut response = Packet::new();
+        response.header.set_type(MessageType::Acknowledgement);
+        response.header.code = MessageClass::Response(status);
+        response.set_token(packet.get_token().to_vec());
+        response
+    }
+
+    #[test]
+    fn test_pending_requests_match_by_token() {
+        let mut pending = PendingRequests::new();
+        let (first, mut first_rx) = oneshot::channel();
+        let (second, mut second_rx) = oneshot::channel();
+        pending.insert(vec![1], 10, first);
+        pending.insert(vec![2], 11, second);
+
+        let mut request = Packet::new();
+        request.set_token(vec![2]);
+        assert!(pending.complete(&response_to(&request, ResponseType::Content)));
+        assert!(!pending.complete(&response_to(&request, ResponseType::Content)));
+        let response = second_rx.try_recv().unwrap().unwrap();
+        assert_eq!(
+            response.header.code,
+            MessageClass::Response(ResponseType::Content)
+        );
+
+        // An empty ACK is no response
+        let mut ack = Packet::new();
+        ack.set_token(vec![1]);
+        assert!(!pending.complete(&ack));
+
+        pending.give_up(10);
+        assert!(matches!(
+            first_rx.try_recv().unwrap(),
+            Err(DeviceRequestError::Timeout)
+        ));
+        assert!(pending.by_token.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_device_client_round_trip() {
+        let registry = ConnectionRegistry::new();
+        let client = registry
+            .device_client("sensor-42")
+            .timeout(Duration::from_secs(1));
+        assert_eq!(
+            client.get("/fw/version").await.unwrap_err(),
+            DeviceRequestError::NotConnected
+        );
+
+        let (sender, _disconnect_rx) = mpsc::channel(1);
+        let (requests, mut request_rx) = mpsc::channel(DEVICE_REQUEST_QUEUE);
+        registry.inner.lock().await.insert(
+            "sensor-42".to_string(),
+            ConnectionInfo {
+                sender,
+                requests,
+                established_at: Instant::now(),
+                source_addr: "127.0.0.1:5684".parse().unwrap(),
+                reconnect_count: 0,
+                stats: Arc::new(ConnectionStats::new()),
+                claim: Claim::new(
+                    MemoryConnectionTracker::NODE_ID,
+                    "127.0.0.1:5684".parse().unwrap(),
+                ),
+            },
+        );
+
+        // Stand-in for the connection task
+        tokio::spawn(async move {
+            let mut pending = PendingRequests::new();
+            let mut token = 0u8;
+            while let Some(DeviceRequest { mut packet, reply }) = request_rx.recv().await {
+                token += 1;
+                packet.set_token(vec![token]);
+                pending.insert(vec![token], u16::from(token), reply);
+
+                let mut response = response_to(&packet, ResponseType::Changed);
+                if packet.header.code == MessageClass::Request(RequestType::Get) {
+                    response.header.code = MessageClass::Response(ResponseType::Content);
+                    response.payload = packet
+                        .get_option(CoapOption::UriPath)
+                        .into_iter()
+                        .flatten()
+                        .flatten()
+                        .copied()
+                        .collect();
+                }
+                pending.complete(&response);
+            }
+        });
+
+        let response = client.get("/fw/version").await.unwrap();
+        assert_eq!(*response.get_status(), ResponseType::Content);
+        assert_eq!(response.message.payload, b"fwversion");
+
+        let response = client
+            .put("/config", b"{}".to_vec(), ContentFormat::ApplicationJSON)
+            .await
+            .unwrap();
+        assert_eq!(*response.get_status(), ResponseType::Changed);
+
+        registry.inner.lock().await.clear();
+        assert_eq!(
+            client.get("/fw/version").await.unwrap_err(),
+            DeviceRequestError::NotConnected
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! Transport-independent request handling.
+//!
+//! [`Engine`] runs a router on raw CoAP messages, for transports other than
+//! the DTLS server: LoRaWAN bridges, serial links, or a test harness. The
+//! transport authenticates the peer and passes its identity along with each
+//! message; the engine parses the message, routes it, and returns the
+//! encoded response.
+//!
+//! The engine applies the per-message rules of RFC 7252: pings are answered
+//! with RST, unrecognized critical options with 4.02, CON requests get a
+//! piggybacked ACK and NON requests a NON response. Deduplication, observe
+//! registrations and block-wise transfers span several messages of a
+//! connection and are left to the transport; an observe GET is answered
+//! like a plain GET.
+
+use std::net::{Ipv4Addr, SocketAddr};
+
+use coap_lite::{CoapRequest, MessageClass, MessageType, Packet, ResponseType};
+use tower::Service;
+use tracing::Instrument;
+
+use crate::{
+    allocator::IdAllocator,
+    extract::{DiagnosticFormat, PayloadLimits, RequestId, Session},
+    liveness::LivenessTracker,
+    no_response::NoResponse,
+    observer::Observer,
+    router::{CoapRouter, CoapumRequest},
+    serve::{
+        Incoming, classify_message, decode_datagram, empty_ack_for, mirror_message_type, reset_for,
+    },
+};
+
+/// Routes raw CoAP messages without a transport
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::engine::Engine;
+/// use coapum::extract::{Identity, Raw};
+/// use coapum::{CoapRequest, Packet, RequestType, RouterBuilder};
+/// use std::net::SocketAddr;
+///
+/// async fn whoami(Identity(id): Identity) -> Raw {
+///     Raw {
+///         payload: id.into_bytes(),
+///         content_format: None,
+///     }
+/// }
+///
+/// # async fn example() {
+/// let router = RouterBuilder::new((), ()).get("/whoami", whoami).build();
+/// let mut engine = Engine::new(router);
+///
+/// // Bytes received from a LoRaWAN uplink, say
+/// let mut request: CoapRequest<SocketAddr> = CoapRequest::new();
+/// request.set_method(RequestType::Get);
+/// request.set_path("/whoami");
+/// let uplink = request.message.to_bytes().unwrap();
+///
+/// let downlink = engine.handle(&uplink, "sensor-42").await;
+/// let response = Packet::from_bytes(&downlink).unwrap();
+/// assert_eq!(response.payload, b"sensor-42");
+/// # }
+/// ```
+pub struct Engine<O, S>
+where
+    S: std::fmt::Debug + Clone + Send + Sync + 'static,
+    O: Observer,
+{
+    router: CoapRouter<O, S>,
+    ids: IdAllocator,
+    payload_limits: PayloadLimits,
+    diagnostic_payloads: Option<DiagnosticFormat>,
+    liveness: Option<LivenessTracker>,
+    source: SocketAddr,
+}
+
+impl<O, S> Engine<O, S>
+where
+    S: std::fmt::Debug + Clone + Send + Sync + 'static,
+    O: Observer,
+{
+    /// Handle messages with `router`
+    pub fn new(router: CoapRouter<O, S>) -> Self {
+        Self {
+            router,
+            ids: IdAllocator::new(),
+            payload_limits: PayloadLimits::default(),
+            diagnostic_payloads: None,
+            liveness: None,
+            source: SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        }
+    }
+
+    /// Payload limits of the built-in extractors. Default: [`PayloadLimits::default`].
+    pub fn payload_limits(mut self, limits: PayloadLimits) -> Self {
+        self.payload_limits = limits;
+        self
+    }
+
+    /// Describe extractor rejections in their responses, see
+    /// [`Config::set_diagnostic_payloads`](crate::config::Config::set_diagnostic_payloads).
+    /// Default: no payload.
+    pub fn diagnostic_payloads(mut self, format: DiagnosticFormat) -> Self {
+        self.diagnostic_payloads = Some(format);
+        self
+    }
+
+    /// Record the identity of every message in `tracker`, see
+    /// [`liveness`](crate::liveness). Default: not recorded.
+    pub fn liveness(mut self, tracker: LivenessTracker) -> Self {
+        self.liveness = Some(tracker);
+        self
+    }
+
+    /// Address handlers see as the request's [`Source`](crate::extract::Source).
+    /// Default: `0.0.0.0:0`.
+    pub fn source(mut self, source: SocketAddr) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// The router messages are handed to
+    pub fn router(&self) -> &CoapRouter<O, S> {
+        &self.router
+    }
+
+    /// Handle a message from the peer with `identity` and return the
+    /// encoded reply
+    ///
+    /// The reply is empty when nothing is to be sent: for malformed
+    /// messages, ACKs, RSTs, NON responses, and NON requests whose response
+    /// the client suppressed with No-Response.
+    pub async fn handle(&mut self, bytes: &[u8], identity: &str) -> Vec<u8> {
+        if let Some(ref liveness) = self.liveness {
+            liveness.touch(identity);
+        }
+        let Some(packet) = decode_datagram(bytes) else {
+            return Vec::new();
+        };
+        let msg_type = packet.header.get_type();
+        let msg_id = packet.header.message_id;
+
+        match classify_message(&packet) {
+            Incoming::Request => {}
+            // RFC 7252 §4.3: A CON we can't process gets RST
+            Incoming::Reject => return encode(&reset_for(msg_id)),
+            Incoming::Reset | Incoming::Acknowledgement | Incoming::Ignore => return Vec::new(),
+        }
+
+        // RFC 7252 §5.4.1: Reject requests with unrecognized critical options
+        if let Some(option_num) = self.router.options().unrecognized_critical(&packet) {
+            tracing::warn!(
+                option_num,
+                "Rejecting request with unrecognized critical option"
+            );
+            let mut reply = Packet::new();
+            reply.set_token(packet.get_token().to_vec());
+            reply.header.code = MessageClass::Response(ResponseType::BadOption);
+            mirror_message_type(&mut reply, msg_type, msg_id, &mut self.ids);
+            return encode(&reply);
+        }
+
+        let token = packet.get_token().to_vec();
+        let no_response = NoResponse::from_packet(&packet);
+
+        let mut request: CoapumRequest<SocketAddr> =
+            CoapRequest::from_packet(packet, self.source).into();
+        request.identity = identity.to_string();
+        request.extensions_mut().insert(Session::new(identity));
+        request.extensions_mut().insert(self.payload_limits);
+        if let Some(format) = self.diagnostic_payloads {
+            request.extensions_mut().insert(format);
+        }
+
+        let span = tracing::info_span!("request", request_id = %RequestId::new(identity, &request.message));
+        let Ok(mut resp) = self.router.call(request).instrument(span).await;
+        resp.message.set_token(token);
+
+        if no_response.suppresses(*resp.get_status()) {
+            // RFC 7967 §2: A CON request is still acknowledged
+            return if msg_type == MessageType::Confirmable {
+                encode(&empty_ack_for(msg_id))
+            } else {
+                Vec::new()
+            };
+        }
+        mirror_message_type(&mut resp.message, msg_type, msg_id, &mut self.ids);
+        encode(&resp.message)
+    }
+}
+
+fn encode(packet: &Packet) -> Vec<u8> {
+    packet.to_bytes().unwrap_or_else(|e| {
+        tracing::error!("Failed to serialize response: {}", e);
+        Vec::new()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RouterBuilder;
+    use crate::extract::{Identity, StatusCode};
+    use coap_lite::{CoapOption, RequestType};
+
+    fn request(msg_type: MessageType, path: &str) -> Packet {
+        let mut request: CoapRequest<SocketAddr> = CoapRequest::new();
+        request.set_method(RequestType::Post);
+        request.set_path(path);
+        request.message.header.set_type(msg_type);
+        request.message.header.message_id = 0x1234;
+        request.message.set_token(vec![0xab, 0xcd]);
+        request.message
+    }
+
+    fn engine() -> Engine<(), ()> {
+        async fn check(Identity(id): Identity) -> StatusCode {
+            if id == "sensor-42" {
+                StatusCode::Changed
+            } else {
+                StatusCode::Forbidden
+            }
+        }
+        Engine::new(RouterBuilder::new((), ()).post("/check", check).build())
+    }
+
+    #[tokio::test]
+    async fn test_handle_request() {
+        let mut engine = engine();
+
+        let bytes = request(MessageType::Confirmable, "/check")
+            .to_bytes()
+            .unwrap();
+        let reply = Packet::from_bytes(&engine.handle(&bytes, "sensor-42").await).unwrap();
+        assert_eq!(
+            reply.header.code,
+            MessageClass::Response(ResponseType::Changed)
+        );
+        assert_eq!(reply.header.get_type(), MessageType::Acknowledgement);
+        assert_eq!(reply.header.message_id, 0x1234);
+        assert_eq!(reply.get_token(), &[0xab, 0xcd]);
+
+        // The identity comes from the caller, not the message
+        let bytes = request(MessageType::NonConfirmable, "/check")
+            .to_bytes()
+            .unwrap();
+        let reply = Packet::from_bytes(&engine.handle(&bytes, "other").await).unwrap();
+        assert_eq!(
+            reply.header.code,
+            MessageClass::Response(ResponseType::Forbidden)
+        );
+        assert_eq!(reply.header.get_type(), MessageType::NonConfirmable);
+    }
+
+    #[tokio::test]
+    async fn test_handle_non_requests() {
+        let liveness = LivenessTracker::new();
+        let mut engine = engine().liveness(liveness.clone());
+        assert!(engine.handle(b"\xff\x00", "sensor-42").await.is_empty());
+
+        // CON ping
+        let mut ping = Packet::new();
+        ping.header.set_type(MessageType::Confirmable);
+        ping.header.code = MessageClass::Empty;
+        ping.header.message_id = 7;
+        let reply = engine.handle(&ping.to_bytes().unwrap(), "sensor-42").await;
+        let reply = Packet::from_bytes(&reply).unwrap();
+        assert_eq!(reply.header.get_type(), MessageType::Reset);
+        assert_eq!(reply.header.message_id, 7);
+
+        // Unrecognized critical option
+        let mut packet = request(MessageType::Confirmable, "/check");
+        packet.add_option(CoapOption::Unknown(65001), vec![1]);
+        let reply = engine
+            .handle(&packet.to_bytes().unwrap(), "sensor-42")
+            .await;
+        let reply = Packet::from_bytes(&reply).unwrap();
+        assert_eq!(
+            reply.header.code,
+            MessageClass::Response(ResponseType::BadOption)
+        );
+
+        // Every message counts as a sign of life, even unanswerable ones
+        assert!(liveness.last_seen("sensor-42").is_some());
+    }
+}
@@ -0,0 +1,208 @@
+//! Batched writes to the requesting device's document
+//!
+//! A handler that updates several paths with one write each stores the
+//! document and notifies its observers once per path. A [`BackendBatch`]
+//! queues the writes instead; the router commits them with
+//! [`Observer::write_batch`](crate::observer::Observer::write_batch) after
+//! the handler returns, as a single update with a single notification pass.
+
+use super::typed::DocumentAccess;
+use super::{FromRequest, StatusCode};
+use crate::CoapResponse;
+use crate::router::CoapumRequest;
+use async_trait::async_trait;
+use coap_lite::{MessageClass, Packet, ResponseType};
+use serde_json::Value;
+use std::{
+    fmt,
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+/// Writes to the requesting device's document, committed together once the
+/// handler succeeded
+///
+/// The router attaches one to every request, for the device's PSK identity.
+/// Queued writes are committed when the handler responds with a 2.xx code
+/// and discarded otherwise, so a handler rejecting a request halfway leaves
+/// the document untouched. If the commit fails, the response is replaced
+/// with 5.00 Internal Server Error.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{BackendBatch, Json, StatusCode};
+/// use serde::Deserialize;
+/// use serde_json::json;
+///
+/// #[derive(Deserialize)]
+/// struct Report {
+///     battery: u8,
+///     rssi: i16,
+/// }
+///
+/// async fn report(Json(report): Json<Report>, batch: BackendBatch) -> StatusCode {
+///     batch.write("/battery", &json!(report.battery));
+///     batch.write("/radio/rssi", &json!(report.rssi));
+///     StatusCode::Changed
+/// }
+/// ```
+#[derive(Clone)]
+pub struct BackendBatch {
+    access: DocumentAccess,
+    updates: Arc<Mutex<Vec<(String, Value)>>>,
+}
+
+impl BackendBatch {
+    pub(crate) fn new(access: DocumentAccess) -> Self {
+        Self {
+            access,
+            updates: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Queue a write of `payload` at `path`, merged into the document like
+    /// [`Observer::write`](crate::observer::Observer::write)
+    pub fn write(&self, path: &str, payload: &Value) {
+        self.lock().push((path.to_string(), payload.clone()));
+    }
+
+    /// Number of queued writes
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether no writes are queued
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Drop the queued writes
+    pub fn discard(&self) {
+        self.lock().clear();
+    }
+
+    /// Commit the queued writes if `response` is a success
+    pub(crate) async fn finish(&self, response: CoapResponse) -> CoapResponse {
+        let updates = std::mem::take(&mut *self.lock());
+        if updates.is_empty() {
+            return response;
+        }
+
+        let class = u8::from(MessageClass::Response(*response.get_status())) >> 5;
+        if class != 2 {
+            tracing::debug!(
+                path = %self.access.path(),
+                discarded = updates.len(),
+                "batch.discarded"
+            );
+            return response;
+        }
+
+        match self.access.write_batch(updates).await {
+            Ok(()) => response,
+            Err(e) => {
+                tracing::error!(path = %self.access.path(), error = %e, "batch.commit_failed");
+                let mut failed = CoapResponse::new(&Packet::new()).unwrap();
+                failed.set_status(ResponseType::InternalServerError);
+                failed.message.header.message_id = response.message.header.message_id;
+                failed
+                    .message
+                    .set_token(response.message.get_token().to_vec());
+                failed
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<(String, Value)>> {
+        self.updates.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl fmt::Debug for BackendBatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BackendBatch")
+            .field("path", &self.access.path())
+            .field("queued", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for BackendBatch
+where
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        req.extensions()
+            .get::<BackendBatch>()
+            .cloned()
+            .ok_or_else(|| {
+                // Only the router attaches the batch, so this is a routing bug
+                tracing::error!("BackendBatch used outside a routed request");
+                StatusCode::InternalServerError
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observer::{Observer, memory::MemObserver, queue::notification_channel};
+    use crate::{CoapRequest, RequestType, RouterBuilder};
+    use serde_json::json;
+    use tower::Service;
+
+    async fn report(batch: BackendBatch) -> StatusCode {
+        batch.write("/status/battery", &json!(87));
+        batch.write("/status/rssi", &json!(-71));
+        StatusCode::Changed
+    }
+
+    async fn reject(batch: BackendBatch) -> StatusCode {
+        batch.write("/status/battery", &json!(0));
+        StatusCode::BadRequest
+    }
+
+    fn request(path: &str) -> CoapumRequest<SocketAddr> {
+        let mut req: CoapRequest<SocketAddr> = CoapRequest::new();
+        req.set_method(RequestType::Post);
+        req.set_path(path);
+        req.source = Some("127.0.0.1:5683".parse().unwrap());
+        let mut req: CoapumRequest<SocketAddr> = req.into();
+        req.identity = "dev".to_string();
+        req
+    }
+
+    #[tokio::test]
+    async fn test_backend_batch_commits_once() {
+        let mut observer = MemObserver::new();
+        let (tx, mut rx) = notification_channel(4, Default::default());
+        observer
+            .register("dev", "/status", Arc::new(tx))
+            .await
+            .unwrap();
+        let mut router = RouterBuilder::new((), observer)
+            .post("/report", report)
+            .post("/reject", reject)
+            .build();
+
+        let response = router.call(request("/report")).await.unwrap();
+        assert_eq!(*response.get_status(), ResponseType::Changed);
+        assert_eq!(
+            rx.recv().await.unwrap().value,
+            json!({"battery": 87, "rssi": -71})
+        );
+        assert!(rx.try_recv().is_none());
+
+        // A failing handler's writes are discarded
+        let response = router.call(request("/reject")).await.unwrap();
+        assert_eq!(*response.get_status(), ResponseType::BadRequest);
+        assert!(rx.try_recv().is_none());
+    }
+}
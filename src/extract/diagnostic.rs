@@ -0,0 +1,186 @@
+//! Diagnostic payloads on error responses
+//!
+//! RFC 7252 §5.5.2 lets error responses carry a brief diagnostic message.
+//! [`ErrorResponse`] pairs a status with such a message and an optional
+//! machine-readable kind, encoded as plain text or CBOR.
+//!
+//! Extractor rejections carry no payload by default. With
+//! [`Config::set_diagnostic_payloads`](crate::config::Config::set_diagnostic_payloads),
+//! the server attaches the rejection's kind and description to every
+//! rejection response, keeping its status and options, so a device that
+//! sends a malformed payload learns why it was refused. The payload also
+//! names the [`RequestId`](super::RequestId), for looking the request up in
+//! the server's logs.
+
+use coap_lite::{ContentFormat, Packet};
+use serde::Serialize;
+
+use super::{IntoResponse, Rejection, RequestId, ResponseError, StatusCode};
+
+/// Encoding of diagnostic payloads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiagnosticFormat {
+    /// UTF-8 text without a Content-Format, `<kind>: <message>`
+    #[default]
+    Text,
+    /// A CBOR map with the text keys `kind` and `message`, Content-Format
+    /// `application/cbor`
+    Cbor,
+}
+
+/// An error status with a diagnostic payload
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{ErrorResponse, IntoResponse, StatusCode};
+///
+/// let response = ErrorResponse::new(StatusCode::Forbidden, "door is in lockdown")
+///     .with_kind("door.lockdown")
+///     .into_response()
+///     .unwrap();
+/// assert_eq!(response.message.payload, b"door.lockdown: door is in lockdown");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorResponse {
+    /// Status of the response
+    pub status: StatusCode,
+    /// Machine-readable identifier of the error, e.g. `json.invalid_data`
+    pub kind: Option<String>,
+    /// Human-readable description of the error
+    pub message: String,
+    /// Correlation ID of the request the error answers
+    pub request_id: Option<String>,
+    /// How the payload is encoded
+    pub format: DiagnosticFormat,
+}
+
+#[derive(Serialize)]
+struct Diagnostic<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<&'a str>,
+    message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<&'a str>,
+}
+
+impl ErrorResponse {
+    /// Answer with `status` and the text `message`
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            kind: None,
+            message: message.into(),
+            request_id: None,
+            format: DiagnosticFormat::default(),
+        }
+    }
+
+    /// The status, kind and description of an extractor rejection
+    pub fn from_rejection(rejection: &dyn Rejection) -> Self {
+        Self::new(rejection.status(), rejection.to_string()).with_kind(rejection.kind())
+    }
+
+    /// Identify the error for machines
+    pub fn with_kind(mut self, kind: impl Into<String>) -> Self {
+        self.kind = Some(kind.into());
+        self
+    }
+
+    /// Name the request the error answers
+    pub fn with_request_id(mut self, request_id: &RequestId) -> Self {
+        self.request_id = Some(request_id.to_string());
+        self
+    }
+
+    /// Encode the payload with `format`
+    pub fn with_format(mut self, format: DiagnosticFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set the diagnostic payload of `message`
+    pub(crate) fn write_payload(&self, message: &mut Packet) -> Result<(), ResponseError> {
+        match self.format {
+            DiagnosticFormat::Text => {
+                let mut text = match &self.kind {
+                    Some(kind) => format!("{}: {}", kind, self.message),
+                    None => self.message.clone(),
+                };
+                if let Some(request_id) = &self.request_id {
+                    text.push_str(&format!(" (request {})", request_id));
+                }
+                message.payload = text.into_bytes();
+            }
+            DiagnosticFormat::Cbor => {
+                let diagnostic = Diagnostic {
+                    kind: self.kind.as_deref(),
+                    message: &self.message,
+                    request_id: self.request_id.as_deref(),
+                };
+                let mut payload = Vec::new();
+                ciborium::ser::into_writer(&diagnostic, &mut payload).map_err(|e| {
+                    ResponseError::SerializationError(format!("CBOR serialization failed: {}", e))
+                })?;
+                message.payload = payload;
+                message.set_content_format(ContentFormat::ApplicationCBOR);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl IntoResponse for ErrorResponse {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        let mut response = self.status.into_response()?;
+        self.write_payload(&mut response.message)?;
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coap_lite::ResponseType;
+
+    #[test]
+    fn test_error_response_formats() {
+        let error = ErrorResponse::new(StatusCode::BadRequest, "Empty payload");
+        let response = error.clone().into_response().unwrap();
+        assert_eq!(*response.get_status(), ResponseType::BadRequest);
+        assert_eq!(response.message.payload, b"Empty payload");
+        assert_eq!(response.message.get_content_format(), None);
+
+        let mut packet = Packet::new();
+        packet.header.message_id = 0x1a2b;
+        let request_id = RequestId::new("sensor-42", &packet);
+        let response = error
+            .clone()
+            .with_request_id(&request_id)
+            .into_response()
+            .unwrap();
+        assert_eq!(
+            response.message.payload,
+            b"Empty payload (request sensor-42#1a2b)"
+        );
+
+        let response = error
+            .with_kind("json.empty_payload")
+            .with_format(DiagnosticFormat::Cbor)
+            .into_response()
+            .unwrap();
+        assert_eq!(
+            response.message.get_content_format(),
+            Some(ContentFormat::ApplicationCBOR)
+        );
+        let value: ciborium::Value =
+            ciborium::de::from_reader(response.message.payload.as_slice()).unwrap();
+        assert_eq!(
+            value,
+            ciborium::Value::Map(vec![
+                ("kind".into(), "json.empty_payload".into()),
+                ("message".into(), "Empty payload".into()),
+            ])
+        );
+    }
+}
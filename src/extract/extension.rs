@@ -0,0 +1,269 @@
+//! Request extensions
+//!
+//! [`Extensions`] is a typemap carried by every [`CoapumRequest`]. Layers and
+//! extractors that run before a handler can insert values into it, for
+//! example an authenticated user resolved from the PSK identity, and the
+//! handler picks them up with the [`Extension`] extractor.
+
+use super::{FromRequest, IntoResponse, Rejection, ResponseError, StatusCode};
+use crate::router::CoapumRequest;
+use async_trait::async_trait;
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+    net::SocketAddr,
+};
+
+/// A value stored in [`Extensions`]
+trait AnyClone: Any + Send + Sync {
+    fn clone_box(&self) -> Box<dyn AnyClone>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Clone + Send + Sync + 'static> AnyClone for T {
+    fn clone_box(&self) -> Box<dyn AnyClone> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Clone for Box<dyn AnyClone> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
+
+/// A type map of request extensions
+///
+/// Holds at most one value per type. Values must be `Clone` so the request
+/// itself stays cloneable.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::Extensions;
+///
+/// #[derive(Clone, Debug, PartialEq)]
+/// struct User {
+///     name: String,
+/// }
+///
+/// let mut extensions = Extensions::new();
+/// extensions.insert(User { name: "alice".to_string() });
+///
+/// assert_eq!(extensions.get::<User>().unwrap().name, "alice");
+/// ```
+#[derive(Clone, Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<dyn AnyClone>>,
+}
+
+impl Extensions {
+    /// Create an empty extensions map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert a value, returning the previous value of the same type if any
+    pub fn insert<T: Clone + Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|prev| prev.into_any().downcast().ok().map(|boxed| *boxed))
+    }
+
+    /// Get a reference to the value of type `T`
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|value| (**value).as_any().downcast_ref())
+    }
+
+    /// Get a mutable reference to the value of type `T`
+    pub fn get_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+        self.map
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| (**value).as_any_mut().downcast_mut())
+    }
+
+    /// Remove and return the value of type `T`
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.into_any().downcast().ok().map(|boxed| *boxed))
+    }
+
+    /// Whether a value of type `T` is present
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Number of stored values
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Whether the map is empty
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Remove all values
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.map.len())
+            .finish()
+    }
+}
+
+/// Extract a value from the request [`Extensions`]
+///
+/// The value is cloned out of the request. If no value of type `T` was
+/// inserted the request is rejected with 5.00 Internal Server Error, since a
+/// missing extension means the server is misconfigured rather than the client
+/// sending a bad request.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::Extension;
+///
+/// #[derive(Clone)]
+/// struct User {
+///     name: String,
+/// }
+///
+/// async fn handle_request(Extension(user): Extension<User>) {
+///     println!("Request from user: {}", user.name);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Extension<T>(pub T);
+
+impl<T> std::ops::Deref for Extension<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> std::ops::DerefMut for Extension<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Rejection type for extension extraction failures
+#[derive(Debug)]
+pub struct ExtensionRejection {
+    type_name: &'static str,
+}
+
+impl fmt::Display for ExtensionRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Missing request extension `{}`", self.type_name)
+    }
+}
+
+impl std::error::Error for ExtensionRejection {}
+
+impl IntoResponse for ExtensionRejection {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        tracing::error!("{}", self);
+        self.status().into_response()
+    }
+}
+
+impl Rejection for ExtensionRejection {
+    fn kind(&self) -> &'static str {
+        "extension.missing"
+    }
+
+    fn status(&self) -> StatusCode {
+        StatusCode::InternalServerError
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Extension<T>
+where
+    T: Clone + Send + Sync + 'static,
+    S: Send + Sync,
+{
+    type Rejection = ExtensionRejection;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        req.extensions()
+            .get::<T>()
+            .cloned()
+            .map(Extension)
+            .ok_or(ExtensionRejection {
+                type_name: std::any::type_name::<T>(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CoapRequest, Packet, ResponseType};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct User(&'static str);
+
+    #[test]
+    fn test_extensions_typemap() {
+        let mut extensions = Extensions::new();
+        assert!(extensions.insert(User("alice")).is_none());
+        assert!(extensions.insert(42u32).is_none());
+        assert_eq!(extensions.insert(User("bob")), Some(User("alice")));
+
+        assert_eq!(extensions.len(), 2);
+        assert_eq!(extensions.get::<User>(), Some(&User("bob")));
+
+        *extensions.get_mut::<u32>().unwrap() += 1;
+        let cloned = extensions.clone();
+        assert_eq!(extensions.remove::<u32>(), Some(43));
+        assert!(!extensions.contains::<u32>());
+        assert_eq!(cloned.get::<u32>(), Some(&43));
+    }
+
+    #[tokio::test]
+    async fn test_extension_extractor() {
+        let raw = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+        let mut req: CoapumRequest<SocketAddr> = raw.into();
+
+        let missing = Extension::<User>::from_request(&req, &())
+            .await
+            .unwrap_err();
+        let response = missing.into_response().unwrap();
+        assert_eq!(*response.get_status(), ResponseType::InternalServerError);
+
+        req.extensions_mut().insert(User("alice"));
+        let Extension(user) = Extension::<User>::from_request(&req, &()).await.unwrap();
+        assert_eq!(user, User("alice"));
+    }
+}
@@ -0,0 +1,321 @@
+//! FETCH requests (RFC 8132)
+//!
+//! FETCH is a GET whose query is carried in the request body, so a client can
+//! ask for several fields of a resource in one round trip instead of issuing
+//! one GET per field. [`Fetch`] decodes that query. Routes registered with
+//! [`RouterBuilder::fetch`](crate::RouterBuilder::fetch) also receive the
+//! requesting device's observer document as a [`DeviceDocument`], so the
+//! handler can answer the query without its own copy of the device state.
+
+use super::typed::DocumentAccess;
+use super::{
+    FromRequest, IntoResponse, PayloadLimits, Rejection, ResponseError, StatusCode, TooLarge,
+};
+use crate::observer::path_to_pointer;
+use crate::router::CoapumRequest;
+use async_trait::async_trait;
+use coap_lite::{ContentFormat, RequestType};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::{fmt, net::SocketAddr};
+
+/// The query body of a FETCH request
+///
+/// The body is decoded as CBOR unless the request's Content-Format says
+/// JSON; other formats are rejected with 4.15 Unsupported Content-Format.
+/// Requests with a method other than FETCH are rejected with 4.05.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{Cbor, DeviceDocument, Fetch};
+/// use serde_json::Value;
+///
+/// // FETCH /device with the CBOR body ["battery", "config/interval"]
+/// async fn fetch_fields(
+///     Fetch(fields): Fetch<Vec<String>>,
+///     document: DeviceDocument,
+/// ) -> Cbor<Value> {
+///     Cbor(document.select(&fields))
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Fetch<T>(pub T);
+
+impl<T> std::ops::Deref for Fetch<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Rejection type for FETCH query extraction failures
+#[derive(Debug)]
+pub struct FetchRejection {
+    kind: FetchRejectionKind,
+}
+
+#[derive(Debug)]
+enum FetchRejectionKind {
+    NotAFetch,
+    EmptyPayload,
+    PayloadTooLarge { limit: usize },
+    UnsupportedContentFormat,
+    InvalidQuery { error: String },
+}
+
+impl fmt::Display for FetchRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            FetchRejectionKind::NotAFetch => write!(f, "Request is not a FETCH"),
+            FetchRejectionKind::EmptyPayload => write!(f, "Empty FETCH query"),
+            FetchRejectionKind::PayloadTooLarge { .. } => write!(f, "Payload too large"),
+            FetchRejectionKind::UnsupportedContentFormat => {
+                write!(f, "FETCH query must be CBOR or JSON")
+            }
+            FetchRejectionKind::InvalidQuery { error } => {
+                write!(f, "Invalid FETCH query: {}", error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FetchRejection {}
+
+impl IntoResponse for FetchRejection {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        match self.kind {
+            FetchRejectionKind::PayloadTooLarge { limit } => TooLarge(limit).into_response(),
+            _ => self.status().into_response(),
+        }
+    }
+}
+
+impl Rejection for FetchRejection {
+    fn kind(&self) -> &'static str {
+        match self.kind {
+            FetchRejectionKind::NotAFetch => "fetch.not_a_fetch",
+            FetchRejectionKind::EmptyPayload => "fetch.empty_payload",
+            FetchRejectionKind::PayloadTooLarge { .. } => "fetch.payload_too_large",
+            FetchRejectionKind::UnsupportedContentFormat => "fetch.unsupported_content_format",
+            FetchRejectionKind::InvalidQuery { .. } => "fetch.invalid_query",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self.kind {
+            FetchRejectionKind::NotAFetch => StatusCode::MethodNotAllowed,
+            FetchRejectionKind::EmptyPayload => StatusCode::BadRequest,
+            FetchRejectionKind::PayloadTooLarge { .. } => StatusCode::RequestEntityTooLarge,
+            FetchRejectionKind::UnsupportedContentFormat => StatusCode::UnsupportedContentFormat,
+            FetchRejectionKind::InvalidQuery { .. } => StatusCode::BadRequest,
+        }
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Fetch<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = FetchRejection;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let reject = |kind| FetchRejection { kind };
+
+        if *req.get_method() != RequestType::Fetch {
+            return Err(reject(FetchRejectionKind::NotAFetch));
+        }
+
+        let payload = &req.message.payload;
+        if payload.is_empty() {
+            return Err(reject(FetchRejectionKind::EmptyPayload));
+        }
+
+        let limits = PayloadLimits::for_request(req);
+        let invalid = |error: String| reject(FetchRejectionKind::InvalidQuery { error });
+
+        let query = match req.message.get_content_format() {
+            None | Some(ContentFormat::ApplicationCBOR) => {
+                let limit = limits.cbor_limit();
+                if payload.len() > limit {
+                    return Err(reject(FetchRejectionKind::PayloadTooLarge { limit }));
+                }
+                // Security: same nesting limit as the `Cbor` extractor
+                const MAX_CBOR_RECURSION_DEPTH: usize = 32;
+                ciborium::de::from_reader_with_recursion_limit(
+                    &payload[..],
+                    MAX_CBOR_RECURSION_DEPTH,
+                )
+                .map_err(|e| invalid(e.to_string()))?
+            }
+            Some(ContentFormat::ApplicationJSON) => {
+                let limit = limits.json_limit();
+                if payload.len() > limit {
+                    return Err(reject(FetchRejectionKind::PayloadTooLarge { limit }));
+                }
+                serde_json::from_slice(payload).map_err(|e| invalid(e.to_string()))?
+            }
+            Some(_) => return Err(reject(FetchRejectionKind::UnsupportedContentFormat)),
+        };
+
+        Ok(Fetch(query))
+    }
+}
+
+/// The observer document of the device making a FETCH request
+///
+/// Read from the observer backend under the request's PSK identity when the
+/// handler of a FETCH route takes it, so routes that don't are unaffected by
+/// backend failures; `null` if the device has not stored anything. A failed
+/// read, or using the extractor in any other handler, is rejected with 5.00
+/// Internal Server Error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceDocument(pub Value);
+
+impl DeviceDocument {
+    /// Collects the values at the given observer paths into one object
+    ///
+    /// Each requested path is a key of the result, spelled as requested.
+    /// Paths that don't exist in the document are left out.
+    ///
+    /// ```
+    /// use coapum::extract::DeviceDocument;
+    /// use serde_json::json;
+    ///
+    /// let document = DeviceDocument(json!({"battery": 87, "config": {"interval": 30}}));
+    /// assert_eq!(
+    ///     document.select(["battery", "/config/interval", "missing"]),
+    ///     json!({"battery": 87, "/config/interval": 30})
+    /// );
+    /// ```
+    pub fn select<I>(&self, paths: I) -> Value
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let mut selected = Map::new();
+        for path in paths {
+            let path = path.as_ref();
+            if let Some(value) = self.0.pointer(&path_to_pointer(path)) {
+                selected.insert(path.to_string(), value.clone());
+            }
+        }
+        Value::Object(selected)
+    }
+}
+
+impl std::ops::Deref for DeviceDocument {
+    type Target = Value;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for DeviceDocument
+where
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(document) = req.extensions().get::<DeviceDocument>() {
+            return Ok(document.clone());
+        }
+        let access = req
+            .extensions()
+            .get::<DocumentAccess>()
+            .filter(|_| *req.get_method() == RequestType::Fetch)
+            .ok_or_else(|| {
+                // Only the router attaches the document, so this is a routing bug
+                tracing::error!("DeviceDocument used outside a FETCH route");
+                StatusCode::InternalServerError
+            })?;
+        match access.read_root().await {
+            Ok(document) => Ok(DeviceDocument(document.unwrap_or(Value::Null))),
+            Err(e) => {
+                tracing::error!(error = %e, "fetch.document_read_failed");
+                Err(StatusCode::InternalServerError)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CoapRequest, Packet};
+    use serde_json::json;
+
+    fn request(
+        method: RequestType,
+        format: Option<ContentFormat>,
+        payload: Vec<u8>,
+    ) -> CoapumRequest<SocketAddr> {
+        let mut raw = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+        raw.set_method(method);
+        if let Some(format) = format {
+            raw.message.set_content_format(format);
+        }
+        raw.message.payload = payload;
+        raw.into()
+    }
+
+    #[tokio::test]
+    async fn test_fetch_query_formats() {
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&json!(["battery", "config"]), &mut cbor).unwrap();
+
+        let req = request(RequestType::Fetch, None, cbor.clone());
+        let Fetch(fields) = Fetch::<Vec<String>>::from_request(&req, &()).await.unwrap();
+        assert_eq!(fields, vec!["battery", "config"]);
+
+        let req = request(
+            RequestType::Fetch,
+            Some(ContentFormat::ApplicationJSON),
+            br#"["battery"]"#.to_vec(),
+        );
+        let Fetch(fields) = Fetch::<Vec<String>>::from_request(&req, &()).await.unwrap();
+        assert_eq!(fields, vec!["battery"]);
+
+        let req = request(
+            RequestType::Fetch,
+            Some(ContentFormat::TextPlain),
+            cbor.clone(),
+        );
+        let err = Fetch::<Value>::from_request(&req, &()).await.unwrap_err();
+        assert!(matches!(
+            err.kind,
+            FetchRejectionKind::UnsupportedContentFormat
+        ));
+
+        let req = request(RequestType::Get, None, cbor);
+        let err = Fetch::<Value>::from_request(&req, &()).await.unwrap_err();
+        assert!(matches!(err.kind, FetchRejectionKind::NotAFetch));
+
+        let req = request(RequestType::Fetch, None, Vec::new());
+        let err = Fetch::<Value>::from_request(&req, &()).await.unwrap_err();
+        assert!(matches!(err.kind, FetchRejectionKind::EmptyPayload));
+    }
+
+    #[tokio::test]
+    async fn test_device_document_requires_fetch_route() {
+        let mut req = request(RequestType::Fetch, None, Vec::new());
+        assert!(DeviceDocument::from_request(&req, &()).await.is_err());
+
+        req.extensions_mut()
+            .insert(DeviceDocument(json!({"fan": {"rpm": 1200}})));
+        let document = DeviceDocument::from_request(&req, &()).await.unwrap();
+        assert_eq!(document.select(["fan/rpm"]), json!({"fan/rpm": 1200}));
+    }
+}
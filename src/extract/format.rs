@@ -0,0 +1,489 @@
+//! Pluggable payload formats
+//!
+//! [`Payload<T, F>`] decodes the request body with any [`Format`] and encodes
+//! responses with the same one, so a project only has to describe a content
+//! format once to use it across all of its handlers.
+//!
+//! Built-in formats:
+//! - [`Json`] - `application/json` (50)
+//! - [`Cbor`] - `application/cbor` (60)
+//! - [`MessagePack`] - MessagePack (with `msgpack` feature)
+//! - [`Protobuf`] - Protocol Buffers via `prost` (with `protobuf` feature)
+//!
+//! # Custom formats
+//!
+//! ```rust
+//! use coapum::extract::{Format, Payload, format::Json};
+//!
+//! /// UTF-8 text, one line per reading
+//! struct Lines;
+//!
+//! impl Format<Vec<String>> for Lines {
+//!     const CONTENT_FORMAT: u16 = 0; // text/plain
+//!
+//!     fn decode(bytes: &[u8]) -> Result<Vec<String>, String> {
+//!         let text = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+//!         Ok(text.lines().map(str::to_owned).collect())
+//!     }
+//!
+//!     fn encode(value: &Vec<String>) -> Result<Vec<u8>, String> {
+//!         Ok(value.join("\n").into_bytes())
+//!     }
+//! }
+//!
+//! async fn count(Payload(lines, ..): Payload<Vec<String>, Lines>) -> Payload<usize, Json> {
+//!     Payload::new(lines.len())
+//! }
+//! ```
+
+use super::{FromRequest, IntoResponse, Rejection, ResponseError, StatusCode, TooLarge};
+use crate::router::CoapumRequest;
+use async_trait::async_trait;
+use coap_lite::{CoapOption, Packet, ResponseType};
+use serde::{Serialize, de::DeserializeOwned};
+use std::{fmt, marker::PhantomData, net::SocketAddr};
+
+/// A payload encoding identified by a CoAP Content-Format
+///
+/// Implemented per value type so formats that are not serde based, such as
+/// Protocol Buffers, can put their own bounds on `T`.
+pub trait Format<T>: Send + Sync + 'static {
+    /// The Content-Format ID (RFC 7252 §12.3) this format reads and writes
+    const CONTENT_FORMAT: u16;
+
+    /// Largest payload accepted before decoding, unless the route sets its
+    /// own [`PayloadLimits`](super::PayloadLimits). Default: 8 KB.
+    const MAX_PAYLOAD_SIZE: usize = 8192;
+
+    /// Decode a request payload
+    fn decode(bytes: &[u8]) -> Result<T, String>;
+
+    /// Encode a response payload
+    fn encode(value: &T) -> Result<Vec<u8>, String>;
+}
+
+/// JSON (`application/json`, 50)
+#[derive(Debug, Clone, Copy)]
+pub struct Json;
+
+impl<T> Format<T> for Json
+where
+    T: Serialize + DeserializeOwned,
+{
+    const CONTENT_FORMAT: u16 = 50;
+    const MAX_PAYLOAD_SIZE: usize = 1_048_576;
+
+    fn decode(bytes: &[u8]) -> Result<T, String> {
+        serde_json::from_slice(bytes).map_err(|e| e.to_string())
+    }
+
+    fn encode(value: &T) -> Result<Vec<u8>, String> {
+        serde_json::to_vec(value).map_err(|e| e.to_string())
+    }
+}
+
+/// CBOR (`application/cbor`, 60)
+#[derive(Debug, Clone, Copy)]
+pub struct Cbor;
+
+impl<T> Format<T> for Cbor
+where
+    T: Serialize + DeserializeOwned,
+{
+    const CONTENT_FORMAT: u16 = 60;
+
+    fn decode(bytes: &[u8]) -> Result<T, String> {
+        // Security: same nesting limit as the `Cbor` extractor
+        const MAX_CBOR_RECURSION_DEPTH: usize = 32;
+        ciborium::de::from_reader_with_recursion_limit(bytes, MAX_CBOR_RECURSION_DEPTH)
+            .map_err(|e| e.to_string())
+    }
+
+    fn encode(value: &T) -> Result<Vec<u8>, String> {
+        let mut buffer = Vec::new();
+        ciborium::ser::into_writer(value, &mut buffer).map_err(|e| e.to_string())?;
+        Ok(buffer)
+    }
+}
+
+/// MessagePack
+///
+/// MessagePack has no registered Content-Format, so this uses 65000 from the
+/// experimental range. Define your own [`Format`] if your clients expect a
+/// different number.
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy)]
+pub struct MessagePack;
+
+#[cfg(feature = "msgpack")]
+impl<T> Format<T> for MessagePack
+where
+    T: Serialize + DeserializeOwned,
+{
+    const CONTENT_FORMAT: u16 = 65000;
+
+    fn decode(bytes: &[u8]) -> Result<T, String> {
+        rmp_serde::from_slice(bytes).map_err(|e| e.to_string())
+    }
+
+    fn encode(value: &T) -> Result<Vec<u8>, String> {
+        rmp_serde::to_vec_named(value).map_err(|e| e.to_string())
+    }
+}
+
+/// Protocol Buffers
+///
+/// Works with any `prost` message. Like [`MessagePack`] this has no registered
+/// Content-Format and uses 65001 from the experimental range.
+#[cfg(feature = "protobuf")]
+#[derive(Debug, Clone, Copy)]
+pub struct Protobuf;
+
+#[cfg(feature = "protobuf")]
+impl<T> Format<T> for Protobuf
+where
+    T: prost::Message + Default,
+{
+    const CONTENT_FORMAT: u16 = 65001;
+
+    fn decode(bytes: &[u8]) -> Result<T, String> {
+        T::decode(bytes).map_err(|e| e.to_string())
+    }
+
+    fn encode(value: &T) -> Result<Vec<u8>, String> {
+        Ok(value.encode_to_vec())
+    }
+}
+
+/// Extract and serialize payloads in any [`Format`]
+///
+/// Requests without a Content-Format option are decoded as `F`; requests
+/// that carry a different one are rejected with 4.15.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{Payload, format::Cbor};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Deserialize, Serialize)]
+/// struct Reading {
+///     celsius: f32,
+/// }
+///
+/// async fn handle(Payload(reading, ..): Payload<Reading, Cbor>) -> Payload<Reading, Cbor> {
+///     Payload::new(reading)
+/// }
+/// ```
+pub struct Payload<T, F>(pub T, pub PhantomData<fn() -> F>);
+
+impl<T, F> Payload<T, F> {
+    /// Wrap a value to be encoded with `F`
+    pub fn new(value: T) -> Self {
+        Payload(value, PhantomData)
+    }
+
+    /// Returns the decoded value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T, F> fmt::Debug for Payload<T, F>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Payload").field(&self.0).finish()
+    }
+}
+
+impl<T, F> Clone for Payload<T, F>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        Payload::new(self.0.clone())
+    }
+}
+
+impl<T, F> std::ops::Deref for Payload<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T, F> std::ops::DerefMut for Payload<T, F> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Rejection type for [`Payload`] extraction failures
+#[derive(Debug)]
+pub struct PayloadRejection {
+    kind: PayloadRejectionKind,
+}
+
+#[derive(Debug)]
+enum PayloadRejectionKind {
+    InvalidData { error: String },
+    UnsupportedContentFormat { expected: u16, found: u16 },
+    EmptyPayload,
+    PayloadTooLarge { limit: usize },
+}
+
+impl fmt::Display for PayloadRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            PayloadRejectionKind::InvalidData { error } => {
+                write!(f, "Invalid payload: {}", error)
+            }
+            PayloadRejectionKind::UnsupportedContentFormat { expected, found } => {
+                write!(f, "Expected content format {}, got {}", expected, found)
+            }
+            PayloadRejectionKind::EmptyPayload => {
+                write!(f, "Empty payload")
+            }
+            PayloadRejectionKind::PayloadTooLarge { .. } => {
+                write!(f, "Payload too large")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PayloadRejection {}
+
+impl IntoResponse for PayloadRejection {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        match self.kind {
+            PayloadRejectionKind::PayloadTooLarge { limit } => TooLarge(limit).into_response(),
+            _ => self.status().into_response(),
+        }
+    }
+}
+
+impl Rejection for PayloadRejection {
+    fn kind(&self) -> &'static str {
+        match self.kind {
+            PayloadRejectionKind::InvalidData { .. } => "payload.invalid_data",
+            PayloadRejectionKind::UnsupportedContentFormat { .. } => {
+                "payload.unsupported_content_format"
+            }
+            PayloadRejectionKind::EmptyPayload => "payload.empty_payload",
+            PayloadRejectionKind::PayloadTooLarge { .. } => "payload.payload_too_large",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self.kind {
+            PayloadRejectionKind::InvalidData { .. } => StatusCode::BadRequest,
+            PayloadRejectionKind::UnsupportedContentFormat { .. } => {
+                StatusCode::UnsupportedContentFormat
+            }
+            PayloadRejectionKind::EmptyPayload => StatusCode::BadRequest,
+            PayloadRejectionKind::PayloadTooLarge { .. } => StatusCode::RequestEntityTooLarge,
+        }
+    }
+}
+
+/// Read the raw Content-Format option, including unregistered numbers
+pub(crate) fn content_format(packet: &Packet) -> Option<u16> {
+    let value = packet.get_option(CoapOption::ContentFormat)?.front()?;
+    Some(value.iter().fold(0u16, |acc, &b| (acc << 8) | b as u16))
+}
+
+fn set_content_format(packet: &mut Packet, format: u16) {
+    let bytes = format.to_be_bytes();
+    let start = bytes.iter().position(|&b| b != 0).unwrap_or(2);
+    packet.set_option(CoapOption::ContentFormat, [bytes[start..].to_vec()].into());
+}
+
+#[async_trait]
+impl<T, F, S> FromRequest<S> for Payload<T, F>
+where
+    T: Send,
+    F: Format<T>,
+    S: Send + Sync,
+{
+    type Rejection = PayloadRejection;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        if req.message.payload.is_empty() {
+            return Err(PayloadRejection {
+                kind: PayloadRejectionKind::EmptyPayload,
+            });
+        }
+
+        // Security: Check payload size to prevent memory exhaustion attacks
+        let limit = super::PayloadLimits::for_request(req)
+            .route
+            .unwrap_or(F::MAX_PAYLOAD_SIZE);
+        if req.message.payload.len() > limit {
+            return Err(PayloadRejection {
+                kind: PayloadRejectionKind::PayloadTooLarge { limit },
+            });
+        }
+
+        if let Some(found) = content_format(&req.message)
+            && found != F::CONTENT_FORMAT
+        {
+            return Err(PayloadRejection {
+                kind: PayloadRejectionKind::UnsupportedContentFormat {
+                    expected: F::CONTENT_FORMAT,
+                    found,
+                },
+            });
+        }
+
+        let value = F::decode(&req.message.payload).map_err(|error| PayloadRejection {
+            kind: PayloadRejectionKind::InvalidData { error },
+        })?;
+
+        Ok(Payload::new(value))
+    }
+}
+
+impl<T, F> IntoResponse for Payload<T, F>
+where
+    F: Format<T>,
+{
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        let packet = crate::Packet::new();
+        let mut response = crate::CoapResponse::new(&packet).ok_or_else(|| {
+            ResponseError::InvalidResponse("Failed to create response".to_string())
+        })?;
+
+        response.message.payload = F::encode(&self.0).map_err(|e| {
+            ResponseError::SerializationError(format!(
+                "Content-Format {} serialization failed: {}",
+                F::CONTENT_FORMAT,
+                e
+            ))
+        })?;
+        set_content_format(&mut response.message, F::CONTENT_FORMAT);
+        response.set_status(ResponseType::Content);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CoapRequest, Packet};
+    use coap_lite::ContentFormat;
+    use serde::Deserialize;
+    use std::net::{Ipv4Addr, SocketAddrV4};
+
+    #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+    struct TestData {
+        name: String,
+        value: i32,
+    }
+
+    fn request(payload: Vec<u8>) -> CoapumRequest<SocketAddr> {
+        let mut request = CoapRequest::from_packet(
+            Packet::new(),
+            SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 0)),
+        );
+        request.message.payload = payload;
+        request.into()
+    }
+
+    fn test_data() -> TestData {
+        TestData {
+            name: "test".to_string(),
+            value: 42,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_payload_roundtrip_cbor() {
+        let response = Payload::<_, Cbor>::new(test_data())
+            .into_response()
+            .unwrap();
+        assert_eq!(
+            response.message.get_content_format(),
+            Some(ContentFormat::ApplicationCBOR)
+        );
+
+        let mut req = request(response.message.payload);
+        req.message
+            .set_content_format(ContentFormat::ApplicationCBOR);
+        let Payload(data, ..) = Payload::<TestData, Cbor>::from_request(&req, &())
+            .await
+            .unwrap();
+        assert_eq!(data, test_data());
+    }
+
+    #[tokio::test]
+    async fn test_payload_rejects_other_content_format() {
+        let mut req = request(serde_json::to_vec(&test_data()).unwrap());
+        req.message
+            .set_content_format(ContentFormat::ApplicationCBOR);
+
+        let err = Payload::<TestData, Json>::from_request(&req, &())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.kind,
+            PayloadRejectionKind::UnsupportedContentFormat {
+                expected: 50,
+                found: 60
+            }
+        ));
+
+        // No Content-Format option means the format is assumed
+        let req = request(serde_json::to_vec(&test_data()).unwrap());
+        assert!(
+            Payload::<TestData, Json>::from_request(&req, &())
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_payload_custom_format_number() {
+        struct Experimental;
+
+        impl Format<TestData> for Experimental {
+            const CONTENT_FORMAT: u16 = 65100;
+            const MAX_PAYLOAD_SIZE: usize = 64;
+
+            fn decode(bytes: &[u8]) -> Result<TestData, String> {
+                serde_json::from_slice(bytes).map_err(|e| e.to_string())
+            }
+
+            fn encode(value: &TestData) -> Result<Vec<u8>, String> {
+                serde_json::to_vec(value).map_err(|e| e.to_string())
+            }
+        }
+
+        let response = Payload::<_, Experimental>::new(test_data())
+            .into_response()
+            .unwrap();
+        assert_eq!(content_format(&response.message), Some(65100));
+
+        let mut req = request(response.message.payload.clone());
+        set_content_format(&mut req.message, 65100);
+        assert!(
+            Payload::<TestData, Experimental>::from_request(&req, &())
+                .await
+                .is_ok()
+        );
+
+        let req = request(vec![b' '; 65]);
+        let err = Payload::<TestData, Experimental>::from_request(&req, &())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.kind,
+            PayloadRejectionKind::PayloadTooLarge { limit: 64 }
+        ));
+    }
+}
@@ -0,0 +1,404 @@
+//! Time-window queries over retained history
+//!
+//! With [history](crate::observer::history) enabled, the [`History`]
+//! extractor serves the requesting device's past values at the request path,
+//! selected with standard query parameters:
+//!
+//! | Parameter | Meaning                                                     |
+//! |-----------|-------------------------------------------------------------|
+//! | `start`   | Oldest time to include, in seconds since the Unix epoch     |
+//! | `end`     | Newest time to include, in seconds since the Unix epoch     |
+//! | `limit`   | Maximum number of entries in the response                   |
+//! | `after`   | Continue after the entry with this time, for the next page  |
+//!
+//! Entries are returned oldest first as a SenML pack, one record per value
+//! with its write time as `t`. A response longer than the block size is
+//! sent block-wise like any other. With `limit`, a client pages through a
+//! longer window by repeating the request with `after` set to the `t` of
+//! the last record it got, until a page has fewer than `limit` entries.
+
+use super::query::{Query, QueryRejection};
+use super::typed::DocumentAccess;
+use super::{FromRequest, IntoResponse, Rejection, ResponseError, SenML, StatusCode};
+use crate::observer::history::{HistoryEntry, HistoryRange};
+use crate::router::CoapumRequest;
+use crate::senml_gateway::{RELATIVE_TIME_THRESHOLD, value_records};
+use async_trait::async_trait;
+use coapum_senml::{NormalizedPack, SenMLPack};
+use serde::Deserialize;
+use std::{
+    fmt,
+    net::SocketAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Query parameters selecting a page of history, see the
+/// [module documentation](self)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub struct HistoryQuery {
+    /// Oldest write time to include, in seconds since the Unix epoch
+    pub start: Option<f64>,
+    /// Newest write time to include, in seconds since the Unix epoch
+    pub end: Option<f64>,
+    /// Maximum number of entries
+    pub limit: Option<usize>,
+    /// Only entries written after this time, the last one of the previous page
+    pub after: Option<f64>,
+}
+
+impl HistoryQuery {
+    /// The time window to read, `None` if a bound isn't a valid time or
+    /// `start` is after `end`
+    pub fn range(&self) -> Option<HistoryRange> {
+        let since = match self.start {
+            Some(start) => Some(to_system_time(start)?),
+            None => None,
+        };
+        let until = match self.end {
+            Some(end) => Some(to_system_time(end)?),
+            None => None,
+        };
+        if let (Some(since), Some(until)) = (since, until)
+            && since > until
+        {
+            return None;
+        }
+        Some(HistoryRange {
+            since,
+            until,
+            limit: None,
+        })
+    }
+
+    /// Cut the page out of `entries` of the window, oldest first
+    ///
+    /// Returns the page and whether entries remain after it.
+    pub fn page(&self, entries: Vec<HistoryEntry>) -> (Vec<HistoryEntry>, bool) {
+        let mut page: Vec<HistoryEntry> = entries
+            .into_iter()
+            .filter(|entry| {
+                self.after
+                    .is_none_or(|after| seconds(entry.timestamp) > after)
+            })
+            .collect();
+        let limit = self.limit.unwrap_or(usize::MAX);
+        let more = page.len() > limit;
+        page.truncate(limit);
+        (page, more)
+    }
+}
+
+/// A SenML pack of history entries, one record per value named after `name`
+///
+/// Values written as SenML packs keep their records; their relative times
+/// count from the write time. Other JSON values become one record per leaf.
+pub fn history_pack(name: &str, entries: &[HistoryEntry]) -> SenMLPack {
+    let records = entries
+        .iter()
+        .flat_map(|entry| {
+            let written = seconds(entry.timestamp);
+            value_records(name, &entry.value)
+                .into_iter()
+                .map(move |mut record| {
+                    record.time = Some(match record.time {
+                        Some(t) if t >= RELATIVE_TIME_THRESHOLD => t,
+                        Some(t) => written + t,
+                        None => written,
+                    });
+                    record
+                })
+        })
+        .collect();
+    NormalizedPack {
+        records,
+        version: None,
+    }
+    .to_pack()
+}
+
+/// The requesting device's history at the request path
+///
+/// Selected with the query parameters of the [module documentation](self)
+/// and read from the router's observer under the request's PSK identity.
+/// Responds with the entries as a SenML JSON pack, see [`history_pack`].
+/// Invalid parameters are rejected with 4.00 Bad Request, backend failures
+/// with 5.00 Internal Server Error.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::RouterBuilder;
+/// use coapum::extract::History;
+/// use coapum::observer::{history::HistoryConfig, memory::MemObserver};
+///
+/// // GET /telemetry/temp?start=1700000000&limit=100
+/// async fn temperature_history(history: History) -> History {
+///     history
+/// }
+///
+/// let observer = MemObserver::new().with_history(HistoryConfig::new(1000).path("/telemetry"));
+/// let router = RouterBuilder::new((), observer)
+///     .get("/telemetry/temp", temperature_history)
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct History {
+    name: String,
+    entries: Vec<HistoryEntry>,
+    more: bool,
+}
+
+impl History {
+    /// The entries of the page, oldest first
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// Take the entries of the page
+    pub fn into_entries(self) -> Vec<HistoryEntry> {
+        self.entries
+    }
+
+    /// Whether the window holds entries after this page
+    pub fn has_more(&self) -> bool {
+        self.more
+    }
+
+    /// The `after` parameter of the next page, `None` on the last page
+    pub fn next_after(&self) -> Option<f64> {
+        self.more
+            .then(|| self.entries.last().map(|entry| seconds(entry.timestamp)))
+            .flatten()
+    }
+
+    /// The page as a SenML pack, see [`history_pack`]
+    pub fn to_senml(&self) -> SenMLPack {
+        history_pack(&self.name, &self.entries)
+    }
+}
+
+impl IntoResponse for History {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        SenML(self.to_senml()).into_response()
+    }
+}
+
+/// Rejection type for [`History`] extraction failures
+#[derive(Debug)]
+pub struct HistoryRejection {
+    kind: HistoryRejectionKind,
+}
+
+#[derive(Debug)]
+enum HistoryRejectionKind {
+    InvalidQuery(QueryRejection),
+    InvalidRange,
+    Unavailable,
+    ReadFailed { error: String },
+}
+
+impl fmt::Display for HistoryRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            HistoryRejectionKind::InvalidQuery(rejection) => write!(f, "{}", rejection),
+            HistoryRejectionKind::InvalidRange => write!(f, "Invalid history time window"),
+            HistoryRejectionKind::Unavailable => write!(f, "History is unavailable"),
+            HistoryRejectionKind::ReadFailed { error } => {
+                write!(f, "Failed to read history: {}", error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HistoryRejection {}
+
+impl IntoResponse for HistoryRejection {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        self.status().into_response()
+    }
+}
+
+impl Rejection for HistoryRejection {
+    fn kind(&self) -> &'static str {
+        match self.kind {
+            HistoryRejectionKind::InvalidQuery(_) => "history.invalid_query",
+            HistoryRejectionKind::InvalidRange => "history.invalid_range",
+            HistoryRejectionKind::Unavailable => "history.unavailable",
+            HistoryRejectionKind::ReadFailed { .. } => "history.read_failed",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self.kind {
+            HistoryRejectionKind::InvalidQuery(_) | HistoryRejectionKind::InvalidRange => {
+                StatusCode::BadRequest
+            }
+            HistoryRejectionKind::Unavailable | HistoryRejectionKind::ReadFailed { .. } => {
+                StatusCode::InternalServerError
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for History
+where
+    S: Send + Sync,
+{
+    type Rejection = HistoryRejection;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let reject = |kind| HistoryRejection { kind };
+
+        let Query(query) = Query::<HistoryQuery>::from_request_parts(req)
+            .map_err(|e| reject(HistoryRejectionKind::InvalidQuery(e)))?;
+        let range = query
+            .range()
+            .ok_or(reject(HistoryRejectionKind::InvalidRange))?;
+
+        let Some(access) = req.extensions().get::<DocumentAccess>() else {
+            // Only the router attaches the access, so this is a routing bug
+            tracing::error!("History used outside a routed request");
+            return Err(reject(HistoryRejectionKind::Unavailable));
+        };
+        let entries = access
+            .read_history(range)
+            .await
+            .map_err(|error| reject(HistoryRejectionKind::ReadFailed { error }))?;
+
+        let (entries, more) = query.page(entries);
+        Ok(History {
+            name: access.path().trim_matches('/').to_string(),
+            entries,
+            more,
+        })
+    }
+}
+
+fn seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64())
+}
+
+fn to_system_time(seconds: f64) -> Option<SystemTime> {
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .map(|d| UNIX_EPOCH + d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RouterBuilder;
+    use crate::observer::{Observer, history::HistoryConfig, memory::MemObserver};
+    use crate::{CoapRequest, Packet};
+    use coap_lite::{CoapOption, RequestType, ResponseType};
+    use serde_json::json;
+    use tower::Service;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn entries() -> Vec<HistoryEntry> {
+        (0..5)
+            .map(|i| HistoryEntry {
+                timestamp: at(100 + i),
+                value: json!(i),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_history_query_pages() {
+        let query = HistoryQuery {
+            limit: Some(2),
+            ..Default::default()
+        };
+        let (page, more) = query.page(entries());
+        assert_eq!(page.len(), 2);
+        assert_eq!(page[0].timestamp, at(100));
+        assert!(more);
+
+        let query = HistoryQuery {
+            limit: Some(2),
+            after: Some(103.0),
+            ..Default::default()
+        };
+        let (page, more) = query.page(entries());
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].timestamp, at(104));
+        assert!(!more);
+
+        let query = HistoryQuery {
+            start: Some(5.0),
+            end: Some(1.0),
+            ..Default::default()
+        };
+        assert_eq!(query.range(), None);
+    }
+
+    #[test]
+    fn test_history_pack() {
+        let entries = vec![
+            HistoryEntry {
+                timestamp: at(100),
+                value: json!({"temp": 21.5, "unit": "Cel"}),
+            },
+            HistoryEntry {
+                timestamp: at(160),
+                value: json!([{"n": "temp", "v": 22, "t": -5}]),
+            },
+        ];
+        let pack = history_pack("sensor", &entries);
+        let records: Vec<_> = pack.iter().collect();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].n.as_deref(), Some("sensor/temp"));
+        assert_eq!(records[0].t, Some(100.0));
+        assert_eq!(records[2].n.as_deref(), Some("temp"));
+        assert_eq!(records[2].t, Some(155.0));
+    }
+
+    #[tokio::test]
+    async fn test_history_handler() {
+        async fn history(history: History) -> History {
+            history
+        }
+
+        let mut observer = MemObserver::new().with_history(HistoryConfig::new(10));
+        for i in 0..3 {
+            observer.write("dev", "/temp", &json!(i)).await.unwrap();
+        }
+        let mut router = RouterBuilder::new((), observer)
+            .get("/temp", history)
+            .build();
+
+        let request = |query: &[&str]| {
+            let mut raw =
+                CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+            raw.set_method(RequestType::Get);
+            raw.set_path("/temp");
+            for param in query {
+                raw.message
+                    .add_option(CoapOption::UriQuery, param.as_bytes().to_vec());
+            }
+            let mut request: CoapumRequest<SocketAddr> = raw.into();
+            request.identity = "dev".to_string();
+            request
+        };
+
+        let resp = router.call(request(&["limit=2"])).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Content);
+        let pack =
+            SenMLPack::from_json(std::str::from_utf8(&resp.message.payload).unwrap()).unwrap();
+        let values: Vec<_> = pack.iter().map(|r| r.value()).collect();
+        assert_eq!(values.len(), 2);
+        assert_eq!(pack.iter().next().unwrap().n.as_deref(), Some("temp"));
+
+        let resp = router.call(request(&["limit=lots"])).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::BadRequest);
+    }
+}
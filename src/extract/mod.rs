@@ -4,19 +4,60 @@
 //! similar to Axum's extraction system but tailored for CoAP.
 
 use async_trait::async_trait;
-use coap_lite::ResponseType;
+use coap_lite::{MessageClass, ResponseType};
 use std::{convert::Infallible, fmt, net::SocketAddr, sync::Arc};
 use tokio::sync::RwLock;
 
 use crate::router::CoapumRequest;
 
+pub mod batch;
+pub mod diagnostic;
+pub mod extension;
+pub mod fetch;
+pub mod format;
+pub mod history;
+pub mod notification;
+pub mod patch;
 pub mod path;
 pub mod payload;
+pub mod query;
+pub mod rejection;
+pub mod request;
+pub mod request_id;
+pub mod session;
 pub mod state;
+pub mod stream;
+pub mod trace;
+pub mod typed;
+pub mod version;
 
+pub use batch::BackendBatch;
+pub use diagnostic::{DiagnosticFormat, ErrorResponse};
+pub use extension::{Extension, ExtensionRejection, Extensions};
+pub use fetch::{DeviceDocument, Fetch, FetchRejection};
+pub use format::{Format, Payload, PayloadRejection};
+pub use history::{History, HistoryQuery, HistoryRejection, history_pack};
+pub use notification::{ChangedPaths, Notification, NotificationRejection};
+pub use patch::{DocumentPatcher, MergePatch, MergePatchRejection, apply_merge_patch};
 pub use path::{Path, extract_wildcard_param, extract_wildcard_path};
-pub use payload::{Bytes, Cbor, Json, Raw, SenML};
-pub use state::{Identity, ObserveFlag, Source, State};
+#[cfg(feature = "senml-validation")]
+pub use payload::senml_validators;
+pub use payload::{
+    Bytes, CanonicalCbor, Cbor, Json, NoValidation, NormalizedSenML, PayloadLimits, Raw, SenML,
+    SenMLCbor, SenMLValidation, ValidatedSenML,
+};
+pub use query::{Query, QueryRejection};
+pub use rejection::{Rejection, RejectionHandler};
+pub use request::Request;
+pub use request_id::RequestId;
+pub use session::{Session, Tenant, TenantRejection};
+pub use state::{FromRef, Identity, ObserveFlag, Source, State};
+pub use stream::NotificationStream;
+pub use trace::TraceContext;
+pub use typed::{TypedState, TypedStateRejection};
+pub use version::{ETag, IfMatch, IfMatchRejection};
+
+pub use crate::observer::metadata::ObservationMetadata;
 
 /// Trait for extracting data from CoAP requests
 ///
@@ -25,7 +66,7 @@ pub use state::{Identity, ObserveFlag, Source, State};
 #[async_trait]
 pub trait FromRequest<S>: Sized {
     /// The error type returned when extraction fails
-    type Rejection: IntoResponse;
+    type Rejection: rejection::Rejection;
 
     /// Extract this type from the request
     async fn from_request(
@@ -59,7 +100,7 @@ impl fmt::Display for ResponseError {
 impl std::error::Error for ResponseError {}
 
 /// Standard CoAP status codes for responses
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StatusCode {
     Created,
     Deleted,
@@ -120,6 +161,14 @@ impl From<StatusCode> for ResponseType {
     }
 }
 
+/// The dotted code, e.g. `4.04`
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = u8::from(MessageClass::Response((*self).into()));
+        write!(f, "{}.{:02}", code >> 5, code & 0x1f)
+    }
+}
+
 impl IntoResponse for StatusCode {
     fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
         let packet = crate::Packet::new();
@@ -131,6 +180,32 @@ impl IntoResponse for StatusCode {
     }
 }
 
+/// 4.13 Request Entity Too Large, with Size1 set to the largest payload the
+/// server accepts (RFC 7959 §4)
+///
+/// The built-in payload extractors reject oversized payloads with this, so
+/// clients can retry with a smaller payload or switch to Block1 transfers.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{IntoResponse, TooLarge};
+/// use coapum::options;
+///
+/// let response = TooLarge(1024).into_response().unwrap();
+/// assert_eq!(options::size(&response.message, coap_lite::CoapOption::Size1), Some(1024));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TooLarge(pub usize);
+
+impl IntoResponse for TooLarge {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        let mut response = StatusCode::RequestEntityTooLarge.into_response()?;
+        crate::options::set_size1(&mut response.message, self.0);
+        Ok(response)
+    }
+}
+
 impl IntoResponse for () {
     fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
         StatusCode::Valid.into_response()
@@ -143,18 +218,54 @@ impl IntoResponse for Infallible {
     }
 }
 
-impl<T> IntoResponse for Result<T, StatusCode>
+impl<T, E> IntoResponse for Result<T, E>
 where
     T: IntoResponse,
+    E: IntoResponse,
 {
     fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
         match self {
             Ok(value) => value.into_response(),
-            Err(status) => status.into_response(),
+            Err(err) => err.into_response(),
         }
     }
 }
 
+/// `None` is answered with 4.04 Not Found
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{IntoResponse, Json};
+///
+/// let response = None::<Json<u32>>.into_response().unwrap();
+/// assert_eq!(*response.get_status(), coap_lite::ResponseType::NotFound);
+/// ```
+impl<T> IntoResponse for Option<T>
+where
+    T: IntoResponse,
+{
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        match self {
+            Some(value) => value.into_response(),
+            None => StatusCode::NotFound.into_response(),
+        }
+    }
+}
+
+/// The response of `T` with its status replaced, e.g. `(StatusCode::Created, Json(item))`
+impl<T> IntoResponse for (StatusCode, T)
+where
+    T: IntoResponse,
+{
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        let (status, value) = self;
+        let mut response = value.into_response()?;
+        response.set_status(status.into());
+        Ok(response)
+    }
+}
+
 /// Helper trait for converting handler functions
 pub trait Handler<S, Args>: Clone + Send + Sized + 'static {
     /// The future returned by this handler
@@ -177,6 +288,13 @@ mod tests {
         assert_eq!(ResponseType::NotFound, StatusCode::NotFound.into());
     }
 
+    #[test]
+    fn test_status_code_display() {
+        assert_eq!(StatusCode::Content.to_string(), "2.05");
+        assert_eq!(StatusCode::NotFound.to_string(), "4.04");
+        assert_eq!(StatusCode::ProxyingNotSupported.to_string(), "5.05");
+    }
+
     #[tokio::test]
     async fn test_status_code_into_response() {
         let response = StatusCode::Valid.into_response().unwrap();
@@ -188,4 +306,24 @@ mod tests {
         let response = ().into_response().unwrap();
         assert_eq!(*response.get_status(), ResponseType::Valid);
     }
+
+    #[test]
+    fn test_combinators_into_response() {
+        let response = (StatusCode::Created, Json(42)).into_response().unwrap();
+        assert_eq!(*response.get_status(), ResponseType::Created);
+        assert_eq!(response.message.payload, b"42");
+
+        let response = Some(Json(42)).into_response().unwrap();
+        assert_eq!(*response.get_status(), ResponseType::Content);
+        let response = None::<Json<u32>>.into_response().unwrap();
+        assert_eq!(*response.get_status(), ResponseType::NotFound);
+
+        // Any response can be the error, including a rejection
+        let response = Err::<Json<u32>, _>(TooLarge(64)).into_response().unwrap();
+        assert_eq!(*response.get_status(), ResponseType::RequestEntityTooLarge);
+        let response = Ok::<_, StatusCode>(None::<Json<u32>>)
+            .into_response()
+            .unwrap();
+        assert_eq!(*response.get_status(), ResponseType::NotFound);
+    }
 }
@@ -0,0 +1,260 @@
+//! Access to the value that triggered an observe notification
+//!
+//! When a write changes an observed path, the server calls the route's notify
+//! handler to build the notification. [`Notification`] gives that handler the
+//! new value, so it can shape the notification without reading the state back
+//! from the observer backend. [`ChangedPaths`] tells a handler observing a
+//! prefix which sub-paths the write touched.
+
+use super::{FromRequest, IntoResponse, Rejection, ResponseError, StatusCode};
+use crate::observer::ObserverValue;
+use crate::router::CoapumRequest;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::{fmt, net::SocketAddr};
+
+/// The value that triggered the notification being built
+///
+/// Deserializes the new value at the observed path into `T` (a raw
+/// [`serde_json::Value`] by default). Pre-encoded payloads sent with
+/// [`NotificationTrigger::trigger_cbor`](crate::NotificationTrigger::trigger_cbor)
+/// or stored with [`Observer::write_raw`](crate::observer::Observer::write_raw)
+/// are decoded with [`EncodedPayload::decode`](crate::observer::EncodedPayload::decode).
+/// A removed path yields `null`.
+///
+/// Only notify handlers receive a value; using the extractor in a regular
+/// request handler is rejected with 5.00 Internal Server Error.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{Cbor, Notification};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Temperature {
+///     celsius: f64,
+/// }
+///
+/// async fn notify_temperature(Notification(temp): Notification<Temperature>) -> Cbor<Temperature> {
+///     Cbor(Temperature { celsius: (temp.celsius * 10.0).round() / 10.0 })
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Notification<T = Value>(pub T);
+
+impl<T> std::ops::Deref for Notification<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Rejection type for notification value extraction failures
+#[derive(Debug)]
+pub struct NotificationRejection {
+    kind: NotificationRejectionKind,
+}
+
+#[derive(Debug)]
+enum NotificationRejectionKind {
+    NotANotification,
+    InvalidValue { error: String },
+}
+
+impl fmt::Display for NotificationRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            NotificationRejectionKind::NotANotification => {
+                write!(f, "Request is not an observe notification")
+            }
+            NotificationRejectionKind::InvalidValue { error } => {
+                write!(f, "Invalid notification value: {}", error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NotificationRejection {}
+
+impl IntoResponse for NotificationRejection {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        // The value comes from our own state, so a mismatch is a server bug
+        tracing::error!("{}", self);
+        self.status().into_response()
+    }
+}
+
+impl Rejection for NotificationRejection {
+    fn kind(&self) -> &'static str {
+        match self.kind {
+            NotificationRejectionKind::NotANotification => "notification.not_a_notification",
+            NotificationRejectionKind::InvalidValue { .. } => "notification.invalid_value",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        StatusCode::InternalServerError
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Notification<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = NotificationRejection;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let notification =
+            req.extensions()
+                .get::<ObserverValue>()
+                .ok_or(NotificationRejection {
+                    kind: NotificationRejectionKind::NotANotification,
+                })?;
+
+        let invalid = |error: String| NotificationRejection {
+            kind: NotificationRejectionKind::InvalidValue { error },
+        };
+
+        let value = match &notification.encoded {
+            Some(encoded) => encoded.decode().map_err(invalid)?,
+            None => serde_json::from_value(notification.value.clone())
+                .map_err(|e| invalid(e.to_string()))?,
+        };
+        Ok(Notification(value))
+    }
+}
+
+/// The paths whose values changed in the write that triggered the notification
+///
+/// A client observing a prefix such as `/shadow` is notified of writes to any
+/// path beneath it. The paths listed are the shallowest ones that differ, as
+/// computed by [`changed_paths`](crate::observer::changed_paths); for an exact
+/// observation or a pre-encoded payload this is the observed path itself.
+///
+/// Like [`Notification`], the extractor is rejected outside notify handlers.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{ChangedPaths, Json, Notification};
+/// use serde_json::{Value, json};
+///
+/// // Notify handler of an observe route on /shadow
+/// async fn notify_shadow(
+///     Notification(shadow): Notification<Value>,
+///     ChangedPaths(changed): ChangedPaths,
+/// ) -> Json<Value> {
+///     Json(json!({"changed": changed, "shadow": shadow}))
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ChangedPaths(pub Vec<String>);
+
+impl std::ops::Deref for ChangedPaths {
+    type Target = [String];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for ChangedPaths
+where
+    S: Send + Sync,
+{
+    type Rejection = NotificationRejection;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        req.extensions()
+            .get::<ObserverValue>()
+            .map(|notification| ChangedPaths(notification.changed.clone()))
+            .ok_or(NotificationRejection {
+                kind: NotificationRejectionKind::NotANotification,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observer::EncodedPayload;
+    use crate::{CoapRequest, Packet};
+    use coap_lite::ContentFormat;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Reading {
+        celsius: f64,
+    }
+
+    fn request(notification: Option<ObserverValue>) -> CoapumRequest<SocketAddr> {
+        let raw = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+        let mut req: CoapumRequest<SocketAddr> = raw.into();
+        if let Some(notification) = notification {
+            req.extensions_mut().insert(notification);
+        }
+        req
+    }
+
+    #[tokio::test]
+    async fn test_notification_json_value() {
+        let req = request(Some(ObserverValue {
+            path: "/temp".to_string(),
+            value: json!({"celsius": 21.5}),
+            encoded: None,
+            changed: vec!["/temp/celsius".to_string()],
+        }));
+
+        let Notification(reading) = Notification::<Reading>::from_request(&req, &())
+            .await
+            .unwrap();
+        assert_eq!(reading, Reading { celsius: 21.5 });
+
+        assert!(
+            Notification::<Value>::from_request(&request(None), &())
+                .await
+                .is_err()
+        );
+
+        let ChangedPaths(changed) = ChangedPaths::from_request(&req, &()).await.unwrap();
+        assert_eq!(changed, vec!["/temp/celsius"]);
+        assert!(
+            ChangedPaths::from_request(&request(None), &())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_notification_encoded_cbor() {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&json!({"celsius": 19.0}), &mut bytes).unwrap();
+        let req = request(Some(ObserverValue {
+            path: "/temp".to_string(),
+            value: Value::Null,
+            encoded: Some(EncodedPayload {
+                content_format: ContentFormat::ApplicationCBOR,
+                bytes,
+            }),
+            changed: vec!["/temp".to_string()],
+        }));
+
+        let Notification(reading) = Notification::<Reading>::from_request(&req, &())
+            .await
+            .unwrap();
+        assert_eq!(reading, Reading { celsius: 19.0 });
+    }
+}
@@ -0,0 +1,328 @@
+//! Merge patches (RFC 7396) against the device's observer document
+//!
+//! Most applications answer PATCH and iPATCH the same way: decode the body,
+//! merge it into the device's stored state and notify observers. [`MergePatch`]
+//! decodes the body, [`DocumentPatcher`] applies it to the requesting device's
+//! document through [`Observer::patch`](crate::observer::Observer::patch), and
+//! [`apply_merge_patch`] combines the two into a ready-made handler mounted
+//! with [`RouterBuilder::merge_patch`](crate::RouterBuilder::merge_patch).
+
+use super::format::content_format;
+use super::{
+    FromRequest, IntoResponse, PayloadLimits, Rejection, ResponseError, StatusCode, TooLarge,
+};
+use crate::helper::convert_cbor_value_to_json;
+use crate::observer::Observer;
+use crate::router::CoapumRequest;
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use serde_json::Value;
+use std::{fmt, net::SocketAddr, sync::Arc};
+
+/// Content-Format of `application/merge-patch+json` (RFC 8132 §6)
+const MERGE_PATCH_JSON: u16 = 52;
+const JSON: u16 = 50;
+const CBOR: u16 = 60;
+
+/// A merge patch from the request body
+///
+/// `application/merge-patch+json` and `application/json` bodies are parsed
+/// as JSON. `application/cbor` bodies, and bodies without a Content-Format,
+/// are the CBOR equivalent: they are decoded and converted to JSON with
+/// [`convert_cbor_value_to_json`], so a `null` member still removes it.
+/// Other formats are rejected with 4.15 Unsupported Content-Format.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{DocumentPatcher, MergePatch, StatusCode};
+///
+/// async fn patch_config(MergePatch(patch): MergePatch, patcher: DocumentPatcher) -> StatusCode {
+///     if patch.get("interval").is_some_and(|interval| !interval.is_u64()) {
+///         return StatusCode::BadRequest;
+///     }
+///     match patcher.apply(&patch).await {
+///         Ok(()) => StatusCode::Changed,
+///         Err(_) => StatusCode::InternalServerError,
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergePatch(pub Value);
+
+impl std::ops::Deref for MergePatch {
+    type Target = Value;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Rejection type for merge patch extraction failures
+#[derive(Debug)]
+pub struct MergePatchRejection {
+    kind: MergePatchRejectionKind,
+}
+
+#[derive(Debug)]
+enum MergePatchRejectionKind {
+    EmptyPayload,
+    PayloadTooLarge { limit: usize },
+    UnsupportedContentFormat { found: u16 },
+    InvalidPatch { error: String },
+}
+
+impl fmt::Display for MergePatchRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            MergePatchRejectionKind::EmptyPayload => write!(f, "Empty merge patch"),
+            MergePatchRejectionKind::PayloadTooLarge { .. } => write!(f, "Payload too large"),
+            MergePatchRejectionKind::UnsupportedContentFormat { found } => {
+                write!(f, "Unsupported merge patch content format {}", found)
+            }
+            MergePatchRejectionKind::InvalidPatch { error } => {
+                write!(f, "Invalid merge patch: {}", error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MergePatchRejection {}
+
+impl IntoResponse for MergePatchRejection {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        match self.kind {
+            MergePatchRejectionKind::PayloadTooLarge { limit } => TooLarge(limit).into_response(),
+            _ => self.status().into_response(),
+        }
+    }
+}
+
+impl Rejection for MergePatchRejection {
+    fn kind(&self) -> &'static str {
+        match self.kind {
+            MergePatchRejectionKind::EmptyPayload => "merge_patch.empty_payload",
+            MergePatchRejectionKind::PayloadTooLarge { .. } => "merge_patch.payload_too_large",
+            MergePatchRejectionKind::UnsupportedContentFormat { .. } => {
+                "merge_patch.unsupported_content_format"
+            }
+            MergePatchRejectionKind::InvalidPatch { .. } => "merge_patch.invalid_patch",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self.kind {
+            MergePatchRejectionKind::EmptyPayload => StatusCode::BadRequest,
+            MergePatchRejectionKind::PayloadTooLarge { .. } => StatusCode::RequestEntityTooLarge,
+            MergePatchRejectionKind::UnsupportedContentFormat { .. } => {
+                StatusCode::UnsupportedContentFormat
+            }
+            MergePatchRejectionKind::InvalidPatch { .. } => StatusCode::BadRequest,
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for MergePatch
+where
+    S: Send + Sync,
+{
+    type Rejection = MergePatchRejection;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let reject = |kind| MergePatchRejection { kind };
+        let invalid = |error: String| reject(MergePatchRejectionKind::InvalidPatch { error });
+
+        let payload = &req.message.payload;
+        if payload.is_empty() {
+            return Err(reject(MergePatchRejectionKind::EmptyPayload));
+        }
+
+        let limits = PayloadLimits::for_request(req);
+        let patch = match content_format(&req.message) {
+            Some(JSON | MERGE_PATCH_JSON) => {
+                let limit = limits.json_limit();
+                if payload.len() > limit {
+                    return Err(reject(MergePatchRejectionKind::PayloadTooLarge { limit }));
+                }
+                serde_json::from_slice(payload).map_err(|e| invalid(e.to_string()))?
+            }
+            None | Some(CBOR) => {
+                let limit = limits.cbor_limit();
+                if payload.len() > limit {
+                    return Err(reject(MergePatchRejectionKind::PayloadTooLarge { limit }));
+                }
+                // Security: same nesting limit as the `Cbor` extractor
+                const MAX_CBOR_RECURSION_DEPTH: usize = 32;
+                let value: ciborium::Value = ciborium::de::from_reader_with_recursion_limit(
+                    &payload[..],
+                    MAX_CBOR_RECURSION_DEPTH,
+                )
+                .map_err(|e| invalid(e.to_string()))?;
+                convert_cbor_value_to_json(value)
+            }
+            Some(found) => {
+                return Err(reject(MergePatchRejectionKind::UnsupportedContentFormat {
+                    found,
+                }));
+            }
+        };
+
+        Ok(MergePatch(patch))
+    }
+}
+
+type PatchFn =
+    dyn Fn(String, String, Value) -> BoxFuture<'static, Result<(), String>> + Send + Sync;
+
+/// Applies merge patches to the observer document of the requesting device
+///
+/// The router attaches one to every PATCH and iPATCH request, for the
+/// device's PSK identity and the request path. Patches go through
+/// [`Observer::patch`](crate::observer::Observer::patch), so observers of
+/// the patched paths are notified. Using the extractor for any other method
+/// is rejected with 5.00 Internal Server Error.
+#[derive(Clone)]
+pub struct DocumentPatcher {
+    device_id: String,
+    path: String,
+    patch: Arc<PatchFn>,
+}
+
+impl DocumentPatcher {
+    pub(crate) fn new<O: Observer>(observer: O, device_id: String, path: String) -> Self {
+        let patch = move |device_id: String, path: String, patch: Value| {
+            let mut observer = observer.clone();
+            Box::pin(async move {
+                observer
+                    .patch(&device_id, &path, &patch)
+                    .await
+                    .map_err(|e| format!("{:?}", e))
+            }) as BoxFuture<'static, _>
+        };
+        Self {
+            device_id,
+            path,
+            patch: Arc::new(patch),
+        }
+    }
+
+    /// Patches the document at the request path
+    pub async fn apply(&self, patch: &Value) -> Result<(), String> {
+        self.apply_at(&self.path, patch).await
+    }
+
+    /// Patches the document at another path of the same device
+    pub async fn apply_at(&self, path: &str, patch: &Value) -> Result<(), String> {
+        (self.patch)(self.device_id.clone(), path.to_string(), patch.clone()).await
+    }
+}
+
+impl fmt::Debug for DocumentPatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DocumentPatcher")
+            .field("device_id", &self.device_id)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for DocumentPatcher
+where
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        req.extensions()
+            .get::<DocumentPatcher>()
+            .cloned()
+            .ok_or_else(|| {
+                // Only the router attaches the patcher, so this is a routing bug
+                tracing::error!("DocumentPatcher used outside a PATCH or iPATCH request");
+                StatusCode::InternalServerError
+            })
+    }
+}
+
+/// Handler applying the request body as a merge patch at the request path
+///
+/// Responds 2.04 Changed once the patch is stored. Mounted for PATCH and
+/// iPATCH by [`RouterBuilder::merge_patch`](crate::RouterBuilder::merge_patch).
+pub async fn apply_merge_patch(
+    MergePatch(patch): MergePatch,
+    patcher: DocumentPatcher,
+) -> StatusCode {
+    match patcher.apply(&patch).await {
+        Ok(()) => StatusCode::Changed,
+        Err(e) => {
+            tracing::error!("Failed to apply merge patch for {:?}: {}", patcher, e);
+            StatusCode::InternalServerError
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observer::memory::MemObserver;
+    use crate::{CoapRequest, Packet};
+    use coap_lite::ContentFormat;
+    use serde_json::json;
+
+    fn request(format: Option<ContentFormat>, payload: Vec<u8>) -> CoapumRequest<SocketAddr> {
+        let mut raw = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+        if let Some(format) = format {
+            raw.message.set_content_format(format);
+        }
+        raw.message.payload = payload;
+        raw.into()
+    }
+
+    #[tokio::test]
+    async fn test_merge_patch_formats() {
+        let req = request(
+            Some(ContentFormat::ApplicationJSON),
+            br#"{"led": null}"#.to_vec(),
+        );
+        let MergePatch(patch) = MergePatch::from_request(&req, &()).await.unwrap();
+        assert_eq!(patch, json!({"led": null}));
+
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&json!({"interval": 60, "led": null}), &mut cbor).unwrap();
+        let MergePatch(patch) = MergePatch::from_request(&request(None, cbor), &())
+            .await
+            .unwrap();
+        assert_eq!(patch, json!({"interval": 60, "led": null}));
+
+        let req = request(Some(ContentFormat::TextPlain), b"led=on".to_vec());
+        let err = MergePatch::from_request(&req, &()).await.unwrap_err();
+        assert!(matches!(
+            err.kind,
+            MergePatchRejectionKind::UnsupportedContentFormat { found: 0 }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_document_patcher_notifies() {
+        let mut observer = MemObserver::new();
+        let (tx, mut rx) = crate::observer::queue::notification_channel(4, Default::default());
+        observer
+            .register("dev", "/config", Arc::new(tx))
+            .await
+            .unwrap();
+
+        let patcher = DocumentPatcher::new(observer, "dev".to_string(), "/config".to_string());
+        patcher.apply(&json!({"led": "on"})).await.unwrap();
+
+        let notification = rx.recv().await.unwrap();
+        assert_eq!(notification.value, json!({"led": "on"}));
+    }
+}
@@ -3,7 +3,7 @@
 //! This module provides the `Path` extractor for extracting parameters from
 //! wildcard routes like ".d/*" and ".s/*" commonly used in IoT applications.
 
-use super::{FromRequest, IntoResponse, ResponseError, StatusCode};
+use super::{FromRequest, IntoResponse, Rejection, ResponseError, StatusCode};
 use crate::router::CoapumRequest;
 use async_trait::async_trait;
 
@@ -108,7 +108,21 @@ impl std::error::Error for PathRejection {}
 
 impl IntoResponse for PathRejection {
     fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
-        StatusCode::BadRequest.into_response()
+        self.status().into_response()
+    }
+}
+
+impl Rejection for PathRejection {
+    fn kind(&self) -> &'static str {
+        match self.kind {
+            PathRejectionKind::FailedToDeserializePathParams { .. } => "path.invalid_param",
+            PathRejectionKind::MissingPathParams => "path.missing_params",
+            PathRejectionKind::InvalidPathPattern => "path.invalid_pattern",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        StatusCode::BadRequest
     }
 }
 
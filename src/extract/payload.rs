@@ -3,7 +3,7 @@
 //! This module provides extractors for different payload formats commonly used
 //! in CoAP applications, including CBOR, JSON, and raw bytes.
 
-use super::{FromRequest, IntoResponse, ResponseError, StatusCode};
+use super::{FromRequest, IntoResponse, Rejection, ResponseError, StatusCode, TooLarge};
 use crate::router::CoapumRequest;
 use async_trait::async_trait;
 use coap_lite::{ContentFormat, ResponseType};
@@ -11,7 +11,73 @@ use serde::{Deserialize, Serialize};
 use std::{fmt, net::SocketAddr};
 
 // SenML support
-use coapum_senml::SenMLPack;
+use coapum_senml::{NormalizedPack, SenMLPack, SensMLStream};
+use std::marker::PhantomData;
+
+/// Payload size limits enforced by the built-in payload extractors
+///
+/// The server inserts the limits from [`Config`](crate::config::Config) into
+/// every request's extensions. Routes registered with a limit, such as
+/// [`RouterBuilder::post_with_limit`](crate::RouterBuilder::post_with_limit),
+/// override them for every extractor on that route. Requests without limits
+/// attached use the defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayloadLimits {
+    /// Limit for [`Json`]. Default: 1 MB.
+    pub json: usize,
+    /// Limit for [`Cbor`]. Default: 8 KB.
+    pub cbor: usize,
+    /// Limit for [`SenML`] and [`NormalizedSenML`]. Default: 1 MB.
+    pub senml: usize,
+    /// Per-route override applied to all payload extractors. Default: `None`.
+    pub route: Option<usize>,
+}
+
+impl PayloadLimits {
+    pub const DEFAULT_JSON: usize = 1_048_576;
+    pub const DEFAULT_CBOR: usize = 8192;
+    pub const DEFAULT_SENML: usize = 1_048_576;
+
+    /// Returns the limits attached to a request, or the defaults
+    pub fn for_request(req: &CoapumRequest<SocketAddr>) -> Self {
+        req.extensions()
+            .get::<PayloadLimits>()
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Override every limit with a route-specific one
+    pub fn with_route_limit(mut self, limit: usize) -> Self {
+        self.route = Some(limit);
+        self
+    }
+
+    /// Effective limit for [`Json`]
+    pub fn json_limit(&self) -> usize {
+        self.route.unwrap_or(self.json)
+    }
+
+    /// Effective limit for [`Cbor`]
+    pub fn cbor_limit(&self) -> usize {
+        self.route.unwrap_or(self.cbor)
+    }
+
+    /// Effective limit for [`SenML`] and [`NormalizedSenML`]
+    pub fn senml_limit(&self) -> usize {
+        self.route.unwrap_or(self.senml)
+    }
+}
+
+impl Default for PayloadLimits {
+    fn default() -> Self {
+        Self {
+            json: Self::DEFAULT_JSON,
+            cbor: Self::DEFAULT_CBOR,
+            senml: Self::DEFAULT_SENML,
+            route: None,
+        }
+    }
+}
 
 /// Extract raw bytes from the request payload
 ///
@@ -25,7 +91,8 @@ use coapum_senml::SenMLPack;
 /// - **DTLS record size**: limited by the DTLS implementation
 /// - **Block-wise transfer**: [`Config::max_message_size`](crate::config::Config) (default 1152 bytes per RFC 7252)
 ///
-/// For payloads requiring stricter limits, use [`Cbor`] (8 KB) or [`Json`] (1 MB) instead.
+/// For payloads requiring stricter limits, use [`Cbor`] or [`Json`] instead, which
+/// enforce [`PayloadLimits`].
 ///
 /// # Example
 ///
@@ -169,7 +236,7 @@ enum CborRejectionKind {
     InvalidCborData { error: String },
     MissingCborContentType,
     EmptyPayload,
-    PayloadTooLarge,
+    PayloadTooLarge { limit: usize },
     RecursionLimitExceeded,
 }
 
@@ -185,7 +252,7 @@ impl fmt::Display for CborRejection {
             CborRejectionKind::EmptyPayload => {
                 write!(f, "Empty payload")
             }
-            CborRejectionKind::PayloadTooLarge => {
+            CborRejectionKind::PayloadTooLarge { .. } => {
                 write!(f, "Payload too large")
             }
             CborRejectionKind::RecursionLimitExceeded => {
@@ -200,13 +267,30 @@ impl std::error::Error for CborRejection {}
 impl IntoResponse for CborRejection {
     fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
         match self.kind {
-            CborRejectionKind::InvalidCborData { .. } => StatusCode::BadRequest.into_response(),
-            CborRejectionKind::MissingCborContentType => {
-                StatusCode::UnsupportedContentFormat.into_response()
-            }
-            CborRejectionKind::EmptyPayload => StatusCode::BadRequest.into_response(),
-            CborRejectionKind::PayloadTooLarge => StatusCode::RequestEntityTooLarge.into_response(),
-            CborRejectionKind::RecursionLimitExceeded => StatusCode::BadRequest.into_response(),
+            CborRejectionKind::PayloadTooLarge { limit } => TooLarge(limit).into_response(),
+            _ => self.status().into_response(),
+        }
+    }
+}
+
+impl Rejection for CborRejection {
+    fn kind(&self) -> &'static str {
+        match self.kind {
+            CborRejectionKind::InvalidCborData { .. } => "cbor.invalid_data",
+            CborRejectionKind::MissingCborContentType => "cbor.unsupported_content_format",
+            CborRejectionKind::EmptyPayload => "cbor.empty_payload",
+            CborRejectionKind::PayloadTooLarge { .. } => "cbor.payload_too_large",
+            CborRejectionKind::RecursionLimitExceeded => "cbor.recursion_limit_exceeded",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self.kind {
+            CborRejectionKind::InvalidCborData { .. } => StatusCode::BadRequest,
+            CborRejectionKind::MissingCborContentType => StatusCode::UnsupportedContentFormat,
+            CborRejectionKind::EmptyPayload => StatusCode::BadRequest,
+            CborRejectionKind::PayloadTooLarge { .. } => StatusCode::RequestEntityTooLarge,
+            CborRejectionKind::RecursionLimitExceeded => StatusCode::BadRequest,
         }
     }
 }
@@ -230,10 +314,10 @@ where
         }
 
         // Security: Check payload size to prevent memory exhaustion attacks
-        const MAX_CBOR_PAYLOAD_SIZE: usize = 8192;
-        if req.message.payload.len() > MAX_CBOR_PAYLOAD_SIZE {
+        let limit = PayloadLimits::for_request(req).cbor_limit();
+        if req.message.payload.len() > limit {
             return Err(CborRejection {
-                kind: CborRejectionKind::PayloadTooLarge,
+                kind: CborRejectionKind::PayloadTooLarge { limit },
             });
         }
 
@@ -295,6 +379,48 @@ where
     }
 }
 
+impl<T> Cbor<T> {
+    /// Respond with deterministic CBOR instead, see [`CanonicalCbor`]
+    pub fn canonical(self) -> CanonicalCbor<T> {
+        CanonicalCbor(self.0)
+    }
+}
+
+/// Serialize responses as deterministic CBOR (RFC 8949 §4.2.1)
+///
+/// Like [`Cbor`], but map keys are sorted so equal values always encode to the
+/// same bytes, as needed for hashing, signing or comparing payloads across
+/// devices. Struct fields become map keys and are sorted too.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{Cbor, CanonicalCbor};
+/// use std::collections::HashMap;
+///
+/// async fn manifest() -> CanonicalCbor<HashMap<String, u32>> {
+///     let mut versions = HashMap::new();
+///     versions.insert("modem".to_string(), 3);
+///     versions.insert("app".to_string(), 7);
+///     Cbor(versions).canonical()
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CanonicalCbor<T>(pub T);
+
+impl<T> IntoResponse for CanonicalCbor<T>
+where
+    T: Serialize,
+{
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        let mut value = ciborium::Value::serialized(&self.0).map_err(|e| {
+            ResponseError::SerializationError(format!("CBOR serialization failed: {}", e))
+        })?;
+        coapum_senml::cbor::encoding::canonicalize(&mut value);
+        Cbor(value).into_response()
+    }
+}
+
 /// Extract and serialize JSON payloads
 ///
 /// This extractor automatically deserializes JSON payloads into the specified type
@@ -361,7 +487,7 @@ enum JsonRejectionKind {
     InvalidJsonData { error: String },
     MissingJsonContentType,
     EmptyPayload,
-    PayloadTooLarge,
+    PayloadTooLarge { limit: usize },
 }
 
 impl fmt::Display for JsonRejection {
@@ -376,7 +502,7 @@ impl fmt::Display for JsonRejection {
             JsonRejectionKind::EmptyPayload => {
                 write!(f, "Empty payload")
             }
-            JsonRejectionKind::PayloadTooLarge => {
+            JsonRejectionKind::PayloadTooLarge { .. } => {
                 write!(f, "Payload too large")
             }
         }
@@ -388,12 +514,28 @@ impl std::error::Error for JsonRejection {}
 impl IntoResponse for JsonRejection {
     fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
         match self.kind {
-            JsonRejectionKind::InvalidJsonData { .. } => StatusCode::BadRequest.into_response(),
-            JsonRejectionKind::MissingJsonContentType => {
-                StatusCode::UnsupportedContentFormat.into_response()
-            }
-            JsonRejectionKind::EmptyPayload => StatusCode::BadRequest.into_response(),
-            JsonRejectionKind::PayloadTooLarge => StatusCode::RequestEntityTooLarge.into_response(),
+            JsonRejectionKind::PayloadTooLarge { limit } => TooLarge(limit).into_response(),
+            _ => self.status().into_response(),
+        }
+    }
+}
+
+impl Rejection for JsonRejection {
+    fn kind(&self) -> &'static str {
+        match self.kind {
+            JsonRejectionKind::InvalidJsonData { .. } => "json.invalid_data",
+            JsonRejectionKind::MissingJsonContentType => "json.unsupported_content_format",
+            JsonRejectionKind::EmptyPayload => "json.empty_payload",
+            JsonRejectionKind::PayloadTooLarge { .. } => "json.payload_too_large",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self.kind {
+            JsonRejectionKind::InvalidJsonData { .. } => StatusCode::BadRequest,
+            JsonRejectionKind::MissingJsonContentType => StatusCode::UnsupportedContentFormat,
+            JsonRejectionKind::EmptyPayload => StatusCode::BadRequest,
+            JsonRejectionKind::PayloadTooLarge { .. } => StatusCode::RequestEntityTooLarge,
         }
     }
 }
@@ -417,10 +559,10 @@ where
         }
 
         // Security: Check payload size to prevent memory exhaustion attacks
-        const MAX_JSON_PAYLOAD_SIZE: usize = 1_048_576; // 1MB
-        if req.message.payload.len() > MAX_JSON_PAYLOAD_SIZE {
+        let limit = PayloadLimits::for_request(req).json_limit();
+        if req.message.payload.len() > limit {
             return Err(JsonRejection {
-                kind: JsonRejectionKind::PayloadTooLarge,
+                kind: JsonRejectionKind::PayloadTooLarge { limit },
             });
         }
 
@@ -534,6 +676,8 @@ impl IntoResponse for Raw {
 /// # Content Format Support
 /// - `application/senml+json` (Content-Format 110)
 /// - `application/senml+cbor` (Content-Format 112)
+/// - `application/sensml+json` and `application/sensml+cbor` (Content-Formats
+///   111 and 113), streams read into a pack record by record
 /// - `application/json` (falls back to JSON parsing)
 /// - `application/cbor` (falls back to CBOR parsing)
 ///
@@ -592,9 +736,10 @@ pub struct SenMLRejection {
 #[derive(Debug)]
 enum SenMLRejectionKind {
     InvalidSenMLData { error: String },
+    ValidationFailed { error: String },
     UnsupportedContentFormat,
     EmptyPayload,
-    PayloadTooLarge,
+    PayloadTooLarge { limit: usize },
 }
 
 impl fmt::Display for SenMLRejection {
@@ -603,13 +748,16 @@ impl fmt::Display for SenMLRejection {
             SenMLRejectionKind::InvalidSenMLData { error } => {
                 write!(f, "Invalid SenML data: {}", error)
             }
+            SenMLRejectionKind::ValidationFailed { error } => {
+                write!(f, "SenML validation failed: {}", error)
+            }
             SenMLRejectionKind::UnsupportedContentFormat => {
                 write!(f, "Unsupported content format for SenML")
             }
             SenMLRejectionKind::EmptyPayload => {
                 write!(f, "Empty payload")
             }
-            SenMLRejectionKind::PayloadTooLarge => {
+            SenMLRejectionKind::PayloadTooLarge { .. } => {
                 write!(f, "Payload too large")
             }
         }
@@ -621,45 +769,53 @@ impl std::error::Error for SenMLRejection {}
 impl IntoResponse for SenMLRejection {
     fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
         match self.kind {
-            SenMLRejectionKind::InvalidSenMLData { .. } => StatusCode::BadRequest.into_response(),
-            SenMLRejectionKind::UnsupportedContentFormat => {
-                StatusCode::UnsupportedContentFormat.into_response()
-            }
-            SenMLRejectionKind::EmptyPayload => StatusCode::BadRequest.into_response(),
-            SenMLRejectionKind::PayloadTooLarge => {
-                StatusCode::RequestEntityTooLarge.into_response()
-            }
+            SenMLRejectionKind::PayloadTooLarge { limit } => TooLarge(limit).into_response(),
+            _ => self.status().into_response(),
         }
     }
 }
 
-#[async_trait]
-impl<S> FromRequest<S> for SenML
-where
-    S: Send + Sync,
-{
-    type Rejection = SenMLRejection;
-
-    async fn from_request(
-        req: &CoapumRequest<SocketAddr>,
-        _state: &S,
-    ) -> Result<Self, Self::Rejection> {
-        if req.message.payload.is_empty() {
-            return Err(SenMLRejection {
-                kind: SenMLRejectionKind::EmptyPayload,
-            });
+impl Rejection for SenMLRejection {
+    fn kind(&self) -> &'static str {
+        match self.kind {
+            SenMLRejectionKind::InvalidSenMLData { .. } => "senml.invalid_data",
+            SenMLRejectionKind::ValidationFailed { .. } => "senml.validation_failed",
+            SenMLRejectionKind::UnsupportedContentFormat => "senml.unsupported_content_format",
+            SenMLRejectionKind::EmptyPayload => "senml.empty_payload",
+            SenMLRejectionKind::PayloadTooLarge { .. } => "senml.payload_too_large",
         }
+    }
 
-        // Security: Check payload size to prevent memory exhaustion attacks
-        const MAX_SENML_PAYLOAD_SIZE: usize = 1_048_576; // 1MB
-        if req.message.payload.len() > MAX_SENML_PAYLOAD_SIZE {
-            return Err(SenMLRejection {
-                kind: SenMLRejectionKind::PayloadTooLarge,
-            });
+    fn status(&self) -> StatusCode {
+        match self.kind {
+            SenMLRejectionKind::InvalidSenMLData { .. } => StatusCode::BadRequest,
+            SenMLRejectionKind::ValidationFailed { .. } => StatusCode::UnprocessableEntity,
+            SenMLRejectionKind::UnsupportedContentFormat => StatusCode::UnsupportedContentFormat,
+            SenMLRejectionKind::EmptyPayload => StatusCode::BadRequest,
+            SenMLRejectionKind::PayloadTooLarge { .. } => StatusCode::RequestEntityTooLarge,
         }
+    }
+}
+
+/// Parse a SenML payload using the request's content format, or auto-detect it
+fn parse_senml(req: &CoapumRequest<SocketAddr>) -> Result<SenMLPack, SenMLRejection> {
+    if req.message.payload.is_empty() {
+        return Err(SenMLRejection {
+            kind: SenMLRejectionKind::EmptyPayload,
+        });
+    }
+
+    // Security: Check payload size to prevent memory exhaustion attacks
+    let limit = PayloadLimits::for_request(req).senml_limit();
+    if req.message.payload.len() > limit {
+        return Err(SenMLRejection {
+            kind: SenMLRejectionKind::PayloadTooLarge { limit },
+        });
+    }
 
-        // Determine format and deserialize based on content format
-        let pack = if let Some(content_format) = req.message.get_content_format() {
+    // Determine format and deserialize based on content format
+    let pack =
+        if let Some(content_format) = req.message.get_content_format() {
             match content_format {
                 // Official SenML content formats (RFC 8428)
                 ContentFormat::ApplicationSenmlJSON => {
@@ -676,6 +832,13 @@ where
                     // application/senml+cbor
                     SenMLPack::from_cbor(&req.message.payload)
                 }
+                // Streams (application/sensml+json and +cbor), read record by record
+                ContentFormat::ApplicationSensmlJSON => {
+                    SensMLStream::json(&req.message.payload[..]).collect()
+                }
+                ContentFormat::ApplicationSensmlCBOR => {
+                    SensMLStream::cbor(&req.message.payload[..]).collect()
+                }
                 // Fallback to generic formats
                 ContentFormat::ApplicationJSON => SenMLPack::from_json(
                     std::str::from_utf8(&req.message.payload).map_err(|e| SenMLRejection {
@@ -707,17 +870,26 @@ where
             }
         };
 
-        let pack = pack.map_err(|e| SenMLRejection {
-            kind: SenMLRejectionKind::InvalidSenMLData {
-                error: e.to_string(),
-            },
-        })?;
+    pack.map_err(|e| SenMLRejection {
+        kind: SenMLRejectionKind::InvalidSenMLData {
+            error: e.to_string(),
+        },
+    })
+}
 
-        // Skip validation for now - SenML deserialization already ensures basic format correctness
-        // TODO: Implement context-aware validation that understands base records
-        // For now, if the pack deserializes successfully, we consider it valid
+#[async_trait]
+impl<S> FromRequest<S> for SenML
+where
+    S: Send + Sync,
+{
+    type Rejection = SenMLRejection;
 
-        Ok(SenML(pack))
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        // Any pack that deserializes is accepted, see ValidatedSenML for validation
+        Ok(SenML(parse_senml(req)?))
     }
 }
 
@@ -744,6 +916,256 @@ impl IntoResponse for SenML {
     }
 }
 
+impl SenML {
+    /// Respond with deterministic `application/senml+cbor` instead of JSON,
+    /// see [`SenMLCbor`]
+    pub fn cbor(self) -> SenMLCbor {
+        SenMLCbor(self.0)
+    }
+}
+
+/// Serialize a SenML pack as deterministic `application/senml+cbor`
+///
+/// Uses the RFC 8428 integer labels and the deterministic encoding of
+/// RFC 8949 §4.2.1, so equal packs always produce identical bytes.
+#[derive(Debug, Clone)]
+pub struct SenMLCbor(pub SenMLPack);
+
+impl IntoResponse for SenMLCbor {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        let packet = crate::Packet::new();
+        let mut response = crate::CoapResponse::new(&packet).ok_or_else(|| {
+            ResponseError::InvalidResponse("Failed to create response".to_string())
+        })?;
+
+        response.message.payload = self.0.to_canonical_cbor().map_err(|e| {
+            ResponseError::SerializationError(format!("SenML CBOR serialization failed: {}", e))
+        })?;
+        response
+            .message
+            .set_content_format(ContentFormat::ApplicationSenmlCBOR);
+        response.set_status(ResponseType::Content);
+        Ok(response)
+    }
+}
+
+/// Selects the validation applied by [`ValidatedSenML`], and by
+/// [`NormalizedSenML`] before normalization
+///
+/// Implemented by [`NoValidation`] and, with the `senml-validation` feature, by
+/// marker types for each of the `coapum_senml::validation::validators` presets.
+pub trait SenMLValidation: Send + Sync + 'static {
+    /// Validate the pack as received, returning a description of the problems
+    fn validate(pack: &SenMLPack) -> Result<(), String>;
+}
+
+/// Accept any pack that parses
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoValidation;
+
+impl SenMLValidation for NoValidation {
+    fn validate(_pack: &SenMLPack) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Validation presets from `coapum_senml::validation::validators`
+#[cfg(feature = "senml-validation")]
+pub mod senml_validators {
+    use super::SenMLValidation;
+    use coapum_senml::{SenMLPack, validation::validators};
+
+    macro_rules! preset {
+        ($(#[$doc:meta])* $name:ident => $validator:ident) => {
+            $(#[$doc])*
+            #[derive(Debug, Clone, Copy, Default)]
+            pub struct $name;
+
+            impl SenMLValidation for $name {
+                fn validate(pack: &SenMLPack) -> Result<(), String> {
+                    // Report every issue, so a device can fix them in one go
+                    let issues = validators::$validator().validate_all(pack);
+                    if issues.is_empty() {
+                        return Ok(());
+                    }
+                    let issues: Vec<String> = issues.iter().map(ToString::to_string).collect();
+                    Err(issues.join("; "))
+                }
+            }
+        };
+    }
+
+    preset!(
+        /// [`validators::iot_sensor`]
+        IotSensor => iot_sensor
+    );
+    preset!(
+        /// [`validators::energy_monitor`]
+        EnergyMonitor => energy_monitor
+    );
+    preset!(
+        /// [`validators::relaxed`]
+        Relaxed => relaxed
+    );
+    preset!(
+        /// [`validators::production`]
+        Production => production
+    );
+    preset!(
+        /// [`validators::rfc8428_compliant`]
+        Rfc8428 => rfc8428_compliant
+    );
+}
+
+/// Extract a SenML payload that passes validation
+///
+/// Parses the payload like [`SenML`], which accepts any pack that
+/// deserializes, and then validates it. The validation is chosen per route
+/// through the type parameter, so production routes can be strict while
+/// others stay lenient.
+///
+/// Validation failures are rejected with 4.22 Unprocessable Entity. The
+/// presets in `senml_validators` describe every issue found, e.g.
+/// `Record 0 field 'u': Measurement 'temperature' requires unit 'Cel', got 'F'`,
+/// which diagnostic payloads pass on to the device (see
+/// [`DiagnosticFormat`](super::DiagnosticFormat)).
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use coapum::extract::{ValidatedSenML, senml_validators::Rfc8428};
+///
+/// async fn handle_sensor(readings: ValidatedSenML<Rfc8428>) {
+///     println!("Received {} records", readings.len());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ValidatedSenML<V> {
+    /// The validated pack
+    pub pack: SenMLPack,
+    validation: PhantomData<fn() -> V>,
+}
+
+impl<V> ValidatedSenML<V> {
+    /// Returns the validated pack
+    pub fn into_inner(self) -> SenMLPack {
+        self.pack
+    }
+}
+
+impl<V> std::ops::Deref for ValidatedSenML<V> {
+    type Target = SenMLPack;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pack
+    }
+}
+
+impl<V> From<ValidatedSenML<V>> for SenML {
+    fn from(senml: ValidatedSenML<V>) -> Self {
+        SenML(senml.pack)
+    }
+}
+
+#[async_trait]
+impl<S, V> FromRequest<S> for ValidatedSenML<V>
+where
+    S: Send + Sync,
+    V: SenMLValidation,
+{
+    type Rejection = SenMLRejection;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let pack = parse_senml(req)?;
+
+        V::validate(&pack).map_err(|error| SenMLRejection {
+            kind: SenMLRejectionKind::ValidationFailed { error },
+        })?;
+
+        Ok(ValidatedSenML {
+            pack,
+            validation: PhantomData,
+        })
+    }
+}
+
+/// Extract a SenML payload in normalized form
+///
+/// Parses the payload like [`SenML`], optionally validates it, and resolves
+/// all base values so the handler only sees absolute names, times, units and
+/// values. The validation is chosen per route through the type parameter.
+///
+/// Validation failures are rejected with 4.22 Unprocessable Entity.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::NormalizedSenML;
+///
+/// async fn handle_readings(readings: NormalizedSenML) {
+///     for record in &readings.records {
+///         println!("{} @ {:?} = {:?}", record.name, record.time, record.value);
+///     }
+/// }
+/// ```
+///
+/// With the `senml-validation` feature, pick a validator preset:
+///
+/// ```rust,ignore
+/// use coapum::extract::{NormalizedSenML, senml_validators::IotSensor};
+///
+/// async fn handle_sensor(readings: NormalizedSenML<IotSensor>) { /* ... */ }
+/// ```
+#[derive(Debug, Clone)]
+pub struct NormalizedSenML<V = NoValidation> {
+    /// The resolved records
+    pub pack: NormalizedPack,
+    validation: PhantomData<fn() -> V>,
+}
+
+impl<V> NormalizedSenML<V> {
+    /// Returns the normalized pack
+    pub fn into_inner(self) -> NormalizedPack {
+        self.pack
+    }
+}
+
+impl<V> std::ops::Deref for NormalizedSenML<V> {
+    type Target = NormalizedPack;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pack
+    }
+}
+
+#[async_trait]
+impl<S, V> FromRequest<S> for NormalizedSenML<V>
+where
+    S: Send + Sync,
+    V: SenMLValidation,
+{
+    type Rejection = SenMLRejection;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let pack = parse_senml(req)?;
+
+        V::validate(&pack).map_err(|error| SenMLRejection {
+            kind: SenMLRejectionKind::ValidationFailed { error },
+        })?;
+
+        Ok(NormalizedSenML {
+            pack: pack.normalize(),
+            validation: PhantomData,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -797,6 +1219,33 @@ mod tests {
         assert_eq!(extracted.value, 42);
     }
 
+    #[tokio::test]
+    async fn test_cbor_extraction_respects_configured_limit() {
+        let mut buffer = Vec::new();
+        ciborium::ser::into_writer(&vec![1u8; 64], &mut buffer).unwrap();
+
+        let mut req = create_test_request_with_payload(buffer);
+        assert!(Cbor::<Vec<u8>>::from_request(&req, &()).await.is_ok());
+
+        req.extensions_mut().insert(PayloadLimits {
+            cbor: 32,
+            ..PayloadLimits::default()
+        });
+        let rejection = Cbor::<Vec<u8>>::from_request(&req, &()).await.unwrap_err();
+        assert!(matches!(
+            rejection.kind,
+            CborRejectionKind::PayloadTooLarge { limit: 32 }
+        ));
+
+        // RFC 7959 §4: the 4.13 tells the client the limit
+        let response = rejection.into_response().unwrap();
+        assert_eq!(*response.get_status(), ResponseType::RequestEntityTooLarge);
+        assert_eq!(
+            crate::options::size(&response.message, coap_lite::CoapOption::Size1),
+            Some(32)
+        );
+    }
+
     #[tokio::test]
     async fn test_cbor_extraction_invalid_data() {
         let req = create_test_request_with_payload(vec![0xFF, 0xFF, 0xFF]);
@@ -805,6 +1254,30 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_canonical_cbor_response_is_deterministic() {
+        let mut a = std::collections::HashMap::new();
+        let mut b = std::collections::HashMap::new();
+        for (i, key) in ["temp", "humidity", "co2", "pressure"].iter().enumerate() {
+            a.insert(key.to_string(), i);
+        }
+        for (i, key) in ["temp", "humidity", "co2", "pressure"]
+            .iter()
+            .enumerate()
+            .rev()
+        {
+            b.insert(key.to_string(), i);
+        }
+
+        let a = CanonicalCbor(a).into_response().unwrap();
+        let b = Cbor(b).canonical().into_response().unwrap();
+        assert_eq!(a.message.payload, b.message.payload);
+        assert_eq!(
+            a.message.get_content_format(),
+            Some(ContentFormat::ApplicationCBOR)
+        );
+    }
+
     #[tokio::test]
     async fn test_json_extraction_success() {
         let test_data = TestData {
@@ -954,6 +1427,105 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_normalized_senml_resolves_base_values() {
+        use coapum_senml::SenMLBuilder;
+
+        let pack = SenMLBuilder::new()
+            .base_name("device1/")
+            .base_time(1_700_000_000.0)
+            .base_unit("Cel")
+            .add_measurement("temperature", 22.5, 0.0)
+            .add_measurement("temperature", 22.7, 60.0)
+            .build();
+
+        let json = pack.to_json().unwrap();
+        let mut req = create_test_request_with_payload(json.into_bytes());
+        req.message
+            .set_content_format(ContentFormat::ApplicationSenmlJSON);
+
+        let extracted: NormalizedSenML = NormalizedSenML::from_request(&req, &()).await.unwrap();
+        assert_eq!(extracted.records.len(), 2);
+        assert_eq!(extracted.records[0].name, "device1/temperature");
+        assert_eq!(extracted.records[0].unit.as_deref(), Some("Cel"));
+        assert_eq!(extracted.records[1].time, Some(1_700_000_060.0));
+    }
+
+    #[tokio::test]
+    async fn test_normalized_senml_validation_rejection() {
+        use coapum_senml::SenMLBuilder;
+
+        #[derive(Debug)]
+        struct RequireTwoRecords;
+
+        impl SenMLValidation for RequireTwoRecords {
+            fn validate(pack: &SenMLPack) -> Result<(), String> {
+                if pack.len() >= 2 {
+                    Ok(())
+                } else {
+                    Err("expected at least two records".to_string())
+                }
+            }
+        }
+
+        let pack = SenMLBuilder::new().add_value("dev/temp", 22.5).build();
+        let req = create_test_request_with_payload(pack.to_json().unwrap().into_bytes());
+
+        let rejection = NormalizedSenML::<RequireTwoRecords>::from_request(&req, &())
+            .await
+            .unwrap_err();
+        assert!(rejection.to_string().contains("at least two records"));
+        let response = rejection.into_response().unwrap();
+        assert_eq!(*response.get_status(), ResponseType::UnprocessableEntity);
+    }
+
+    #[tokio::test]
+    async fn test_validated_senml() {
+        use coapum_senml::SenMLBuilder;
+
+        #[derive(Debug)]
+        struct RequireUnits;
+
+        impl SenMLValidation for RequireUnits {
+            fn validate(pack: &SenMLPack) -> Result<(), String> {
+                let missing: Vec<String> = pack
+                    .iter()
+                    .filter(|r| r.u.is_none())
+                    .filter_map(|r| r.n.clone())
+                    .collect();
+                if missing.is_empty() {
+                    Ok(())
+                } else {
+                    Err(format!("missing units: {}", missing.join(", ")))
+                }
+            }
+        }
+
+        let pack = SenMLBuilder::new()
+            .add_value("temp", 22.5)
+            .add_value("hum", 40.0)
+            .build();
+        let req = create_test_request_with_payload(pack.to_json().unwrap().into_bytes());
+
+        // The plain extractor accepts the pack as is
+        assert!(SenML::from_request(&req, &()).await.is_ok());
+
+        let rejection = ValidatedSenML::<RequireUnits>::from_request(&req, &())
+            .await
+            .unwrap_err();
+        assert_eq!(rejection.kind(), "senml.validation_failed");
+        assert!(rejection.to_string().contains("missing units: temp, hum"));
+
+        let pack = SenMLBuilder::new()
+            .add_record(coapum_senml::SenMLRecord::with_value("temp", 22.5).with_unit("Cel"))
+            .build();
+        let req = create_test_request_with_payload(pack.to_json().unwrap().into_bytes());
+        let extracted = ValidatedSenML::<RequireUnits>::from_request(&req, &())
+            .await
+            .unwrap();
+        assert_eq!(extracted.len(), 1);
+    }
+
     #[tokio::test]
     async fn test_senml_response() {
         use coapum_senml::SenMLBuilder;
@@ -995,6 +1567,37 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn test_senml_streams() {
+        use coapum_senml::{SenMLBuilder, SensMLWriter};
+
+        let pack = SenMLBuilder::new()
+            .base_name("urn:dev:ow:10e2073a01080063:")
+            .add_value("temp", 23.1)
+            .add_value("temp", 23.4)
+            .build();
+
+        let mut writer = SensMLWriter::cbor(Vec::new());
+        for record in pack.iter() {
+            writer.push(record).unwrap();
+        }
+        let mut req = create_test_request_with_payload(writer.finish().unwrap());
+        req.message
+            .set_content_format(ContentFormat::ApplicationSensmlCBOR);
+        let SenML(extracted) = SenML::from_request(&req, &()).await.unwrap();
+        assert_eq!(extracted, pack);
+
+        // JSON Lines without the enclosing array
+        let lines = br#"{"n":"temp","v":1}
+{"n":"temp","v":2}
+"#;
+        let mut req = create_test_request_with_payload(lines.to_vec());
+        req.message
+            .set_content_format(ContentFormat::ApplicationSensmlJSON);
+        let SenML(extracted) = SenML::from_request(&req, &()).await.unwrap();
+        assert_eq!(extracted.len(), 2);
+    }
+
     #[tokio::test]
     async fn test_senml_fallback_to_generic_formats() {
         use coapum_senml::SenMLBuilder;
@@ -0,0 +1,266 @@
+//! Query parameter extraction
+//!
+//! [`Query`] deserializes the request's Uri-Query options into a type, e.g.
+//! `GET /history?start=1700000000&limit=50` into a struct with `start` and
+//! `limit` fields. Values are parsed into the field types, so numbers and
+//! booleans need no conversion in the handler.
+
+use super::{FromRequest, IntoResponse, Rejection, ResponseError, StatusCode};
+use crate::router::CoapumRequest;
+use async_trait::async_trait;
+use coap_lite::CoapOption;
+use serde::de::{
+    self, DeserializeOwned, Deserializer, IntoDeserializer, Unexpected, Visitor,
+    value::{Error as ValueError, MapDeserializer},
+};
+use std::{collections::BTreeMap, fmt, net::SocketAddr, str::FromStr};
+
+/// Deserialize the request's query parameters into `T`
+///
+/// `key=value` options become fields; options without `=` have an empty
+/// value, and of a repeated key the last value wins. Missing keys are
+/// `None` for `Option` fields or take their `#[serde(default)]`. Parameters
+/// that don't fit the type are rejected with 4.00 Bad Request.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::Query;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Page {
+///     limit: Option<usize>,
+///     #[serde(default)]
+///     verbose: bool,
+/// }
+///
+/// // GET /readings?limit=10&verbose=true
+/// async fn list_readings(Query(page): Query<Page>) {
+///     println!("limit: {:?}, verbose: {}", page.limit, page.verbose);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Query<T>(pub T);
+
+impl<T> std::ops::Deref for Query<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned> Query<T> {
+    /// Deserialize the Uri-Query options of a request
+    pub fn from_request_parts(req: &CoapumRequest<SocketAddr>) -> Result<Self, QueryRejection> {
+        let mut params = BTreeMap::new();
+        for option in req
+            .message
+            .get_option(CoapOption::UriQuery)
+            .into_iter()
+            .flatten()
+        {
+            let param = std::str::from_utf8(option).map_err(|_| QueryRejection {
+                error: "query parameter is not UTF-8".to_string(),
+            })?;
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            params.insert(key.to_string(), value.to_string());
+        }
+
+        let map = MapDeserializer::<_, ValueError>::new(
+            params
+                .iter()
+                .map(|(key, value)| (key.as_str(), QueryValue(value))),
+        );
+        T::deserialize(map).map(Query).map_err(|e| QueryRejection {
+            error: e.to_string(),
+        })
+    }
+}
+
+/// Rejection type for query parameters that don't fit the type
+#[derive(Debug)]
+pub struct QueryRejection {
+    error: String,
+}
+
+impl fmt::Display for QueryRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid query parameters: {}", self.error)
+    }
+}
+
+impl std::error::Error for QueryRejection {}
+
+impl IntoResponse for QueryRejection {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        self.status().into_response()
+    }
+}
+
+impl Rejection for QueryRejection {
+    fn kind(&self) -> &'static str {
+        "query.invalid"
+    }
+
+    fn status(&self) -> StatusCode {
+        StatusCode::BadRequest
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = QueryRejection;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        Self::from_request_parts(req)
+    }
+}
+
+/// A query value, parsed into whatever type the field asks for
+struct QueryValue<'a>(&'a str);
+
+impl QueryValue<'_> {
+    fn parse<T: FromStr, V: de::Expected>(&self, expected: &V) -> Result<T, ValueError> {
+        self.0
+            .parse()
+            .map_err(|_| de::Error::invalid_value(Unexpected::Str(self.0), expected))
+    }
+}
+
+macro_rules! parse_value {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                let value = self.parse(&visitor)?;
+                visitor.$visit(value)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for QueryValue<'_> {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_str(self.0)
+    }
+
+    parse_value! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 char str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, ValueError> for QueryValue<'_> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CoapRequest, Packet};
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Order {
+        Asc,
+        Desc,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Params {
+        start: Option<f64>,
+        limit: Option<usize>,
+        name: Option<String>,
+        #[serde(default)]
+        verbose: bool,
+        order: Option<Order>,
+    }
+
+    fn request(query: &[&str]) -> CoapumRequest<SocketAddr> {
+        let mut packet = Packet::new();
+        for param in query {
+            packet.add_option(CoapOption::UriQuery, param.as_bytes().to_vec());
+        }
+        CoapRequest::from_packet(packet, "127.0.0.1:5683".parse().unwrap()).into()
+    }
+
+    #[tokio::test]
+    async fn test_query_extraction() {
+        let req = request(&[
+            "start=1700000000.5",
+            "limit=1",
+            "limit=20",
+            "name=42",
+            "verbose=true",
+            "order=desc",
+        ]);
+        let Query(params) = Query::<Params>::from_request(&req, &()).await.unwrap();
+        assert_eq!(params.start, Some(1_700_000_000.5));
+        assert_eq!(params.limit, Some(20));
+        assert_eq!(params.name.as_deref(), Some("42"));
+        assert!(params.verbose);
+        assert_eq!(params.order, Some(Order::Desc));
+
+        let Query(params) = Query::<Params>::from_request(&request(&[]), &())
+            .await
+            .unwrap();
+        assert_eq!(params.limit, None);
+        assert!(!params.verbose);
+
+        let err = Query::<Params>::from_request(&request(&["limit=many"]), &())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), "query.invalid");
+        assert_eq!(err.status(), StatusCode::BadRequest);
+    }
+}
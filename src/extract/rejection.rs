@@ -0,0 +1,179 @@
+//! Custom responses for extractor rejections
+//!
+//! When an extractor fails, the handler is not called and the rejection is
+//! answered with a fixed status, e.g. 4.00 Bad Request for malformed JSON.
+//! A [`RejectionHandler`] set with
+//! [`RouterBuilder::rejection_handler`](crate::router::RouterBuilder::rejection_handler)
+//! sees every rejection first and can answer it differently, for example
+//! with a diagnostic body telling a device in the field what it got wrong.
+
+use std::{convert::Infallible, fmt, net::SocketAddr, sync::Arc};
+
+use super::{DiagnosticFormat, ErrorResponse, IntoResponse, RequestId, ResponseError, StatusCode};
+use crate::{CoapResponse, router::CoapumRequest};
+
+/// The reason an extractor refused a request
+///
+/// Every [`FromRequest::Rejection`](super::FromRequest::Rejection) implements
+/// it. [`Display`](fmt::Display) describes the failure for humans,
+/// [`kind`](Self::kind) identifies it for machines.
+pub trait Rejection: IntoResponse + fmt::Display + Send + 'static {
+    /// Identifier of the failure, `<extractor>.<reason>`, e.g.
+    /// `json.invalid_data` or `path.missing_params`
+    fn kind(&self) -> &'static str;
+
+    /// Status the rejection is answered with by default
+    fn status(&self) -> StatusCode;
+}
+
+impl Rejection for StatusCode {
+    fn kind(&self) -> &'static str {
+        "status"
+    }
+
+    fn status(&self) -> StatusCode {
+        *self
+    }
+}
+
+impl Rejection for Infallible {
+    fn kind(&self) -> &'static str {
+        match *self {}
+    }
+
+    fn status(&self) -> StatusCode {
+        match *self {}
+    }
+}
+
+type RejectionFn =
+    dyn Fn(&dyn Rejection) -> Option<Result<CoapResponse, ResponseError>> + Send + Sync;
+
+/// Answers extractor rejections in place of their default response
+///
+/// The function returns `None` to keep the default response, which for
+/// payloads over the size limit also carries Size1.
+#[derive(Clone)]
+pub struct RejectionHandler(Arc<RejectionFn>);
+
+impl RejectionHandler {
+    /// Answer rejections with the response `f` returns
+    pub fn new<F, R>(f: F) -> Self
+    where
+        F: Fn(&dyn Rejection) -> Option<R> + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        Self(Arc::new(move |rejection: &dyn Rejection| {
+            f(rejection).map(IntoResponse::into_response)
+        }))
+    }
+}
+
+impl fmt::Debug for RejectionHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RejectionHandler").finish_non_exhaustive()
+    }
+}
+
+/// Answer `rejection` with the request's rejection handler, if any
+///
+/// Otherwise the rejection's own response is sent, with a diagnostic
+/// payload naming the request if the request carries a [`DiagnosticFormat`].
+pub(crate) fn reject<R: Rejection>(req: &CoapumRequest<SocketAddr>, rejection: R) -> CoapResponse {
+    tracing::debug!(kind = rejection.kind(), "Request rejected: {}", rejection);
+    let custom = req
+        .extensions()
+        .get::<RejectionHandler>()
+        .and_then(|handler| (handler.0)(&rejection));
+    let diagnostic = req.extensions().get::<DiagnosticFormat>().map(|format| {
+        ErrorResponse::from_rejection(&rejection)
+            .with_request_id(&RequestId::new(&req.identity, &req.message))
+            .with_format(*format)
+    });
+    custom
+        .unwrap_or_else(|| {
+            let mut response = rejection.into_response()?;
+            if let Some(diagnostic) = diagnostic {
+                diagnostic.write_payload(&mut response.message)?;
+            }
+            Ok(response)
+        })
+        .unwrap_or_else(|e| {
+            tracing::error!("Rejection response conversion failed: {}", e);
+            StatusCode::BadRequest.into_response().unwrap()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::{FromRequest, Json, PayloadLimits};
+    use crate::{CoapRequest, Packet};
+    use coap_lite::ResponseType;
+
+    fn request() -> CoapumRequest<SocketAddr> {
+        let mut packet = Packet::new();
+        packet.payload = b"{not json".to_vec();
+        CoapRequest::from_packet(packet, "127.0.0.1:5683".parse().unwrap()).into()
+    }
+
+    #[tokio::test]
+    async fn test_reject() {
+        let mut req = request();
+        let rejection = Json::<u32>::from_request(&req, &()).await.unwrap_err();
+        assert_eq!(rejection.kind(), "json.invalid_data");
+        assert_eq!(rejection.status(), StatusCode::BadRequest);
+        let response = reject(&req, rejection);
+        assert_eq!(*response.get_status(), ResponseType::BadRequest);
+        assert!(response.message.payload.is_empty());
+
+        req.extensions_mut()
+            .insert(RejectionHandler::new(|rejection: &dyn Rejection| {
+                (rejection.kind() != "status").then(|| (rejection.status(), Json(rejection.kind())))
+            }));
+        let rejection = Json::<u32>::from_request(&req, &()).await.unwrap_err();
+        let response = reject(&req, rejection);
+        assert_eq!(*response.get_status(), ResponseType::BadRequest);
+        assert_eq!(response.message.payload, br#""json.invalid_data""#);
+
+        // Returning None keeps the default response
+        let response = reject(&req, StatusCode::Forbidden);
+        assert_eq!(*response.get_status(), ResponseType::Forbidden);
+        assert!(response.message.payload.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reject_with_diagnostic() {
+        let mut req = request();
+        req.identity = "sensor-42".to_string();
+        req.message.header.message_id = 0x1a2b;
+        req.extensions_mut().insert(DiagnosticFormat::Text);
+
+        let rejection = Json::<u32>::from_request(&req, &()).await.unwrap_err();
+        let message = rejection.to_string();
+        let response = reject(&req, rejection);
+        assert_eq!(*response.get_status(), ResponseType::BadRequest);
+        assert_eq!(
+            response.message.payload,
+            format!("json.invalid_data: {} (request sensor-42#1a2b)", message).into_bytes()
+        );
+
+        // Status and Size1 of oversized payloads are kept
+        req.extensions_mut().insert(PayloadLimits {
+            json: 4,
+            ..PayloadLimits::default()
+        });
+        req.extensions_mut().insert(DiagnosticFormat::Cbor);
+        let rejection = Json::<u32>::from_request(&req, &()).await.unwrap_err();
+        let response = reject(&req, rejection);
+        assert_eq!(*response.get_status(), ResponseType::RequestEntityTooLarge);
+        assert_eq!(
+            crate::options::size(&response.message, coap_lite::CoapOption::Size1),
+            Some(4)
+        );
+        assert_eq!(
+            response.message.get_content_format(),
+            Some(coap_lite::ContentFormat::ApplicationCBOR)
+        );
+    }
+}
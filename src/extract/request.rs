@@ -0,0 +1,117 @@
+//! The request itself as an extractor
+//!
+//! Extractors pick single pieces out of a request. A handler that also needs
+//! what no extractor covers, such as the token or an application-specific
+//! option, takes a [`Request`] next to its other extractors. Extractors only
+//! borrow the request, so a `Request` and a payload extractor like
+//! [`Json`](super::Json) can be used together.
+
+use super::FromRequest;
+use crate::router::CoapumRequest;
+use async_trait::async_trait;
+use coap_lite::CoapOption;
+use std::{collections::LinkedList, fmt, net::SocketAddr, ops::Deref, sync::Arc};
+
+/// The request being handled
+///
+/// Dereferences to the [`CoapumRequest`], with the packet, path, method,
+/// identity and source. Like [`FullRequest`](super::state::FullRequest),
+/// extracting it copies the request, but clones share that copy, so it can
+/// be handed to spawned tasks cheaply. As an extractor it never fails.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{Json, Request, StatusCode};
+/// use coap_lite::CoapOption;
+/// use serde_json::Value;
+///
+/// async fn handle_request(request: Request, Json(body): Json<Value>) -> StatusCode {
+///     let signed = request.option(CoapOption::Unknown(65001)).is_some();
+///     println!("token {:02x?}, signed: {}, body: {}", request.token(), signed, body);
+///     StatusCode::Changed
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Request(pub Arc<CoapumRequest<SocketAddr>>);
+
+impl Request {
+    /// Token of the request
+    pub fn token(&self) -> &[u8] {
+        self.0.message.get_token()
+    }
+
+    /// Values of `option` in the request, if it is present
+    pub fn option(&self, option: CoapOption) -> Option<&LinkedList<Vec<u8>>> {
+        self.0.message.get_option(option)
+    }
+}
+
+impl fmt::Debug for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Request")
+            .field(&format!("CoapumRequest({})", self.0.get_path()))
+            .finish()
+    }
+}
+
+impl Deref for Request {
+    type Target = CoapumRequest<SocketAddr>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for Request {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(Request(Arc::new(req.clone())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::{Bytes, Raw};
+    use crate::{CoapRequest, RequestType, RouterBuilder};
+    use tower::Service;
+
+    async fn echo(request: Request, Bytes(body): Bytes) -> Raw {
+        let mut payload = request.token().to_vec();
+        let signature = request.option(CoapOption::Unknown(65001)).unwrap();
+        payload.extend_from_slice(signature.front().unwrap());
+        payload.extend_from_slice(&body);
+        Raw {
+            payload,
+            content_format: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_with_payload_extractor() {
+        let mut req: CoapRequest<SocketAddr> = CoapRequest::new();
+        req.set_method(RequestType::Post);
+        req.set_path("/echo");
+        req.message.set_token(vec![0xc0, 0xff]);
+        req.message
+            .add_option(CoapOption::Unknown(65001), vec![0x01]);
+        req.message.payload = b"body".to_vec();
+        req.source = Some("127.0.0.1:5683".parse().unwrap());
+        let mut req: CoapumRequest<SocketAddr> = req.into();
+        req.identity = "dev".to_string();
+
+        let request = Request::from_request(&req, &()).await.unwrap();
+        assert_eq!(*request.get_method(), RequestType::Post);
+        assert_eq!(request.identity, "dev");
+
+        let mut router = RouterBuilder::new((), ()).post("/echo", echo).build();
+        let response = router.call(req).await.unwrap();
+        assert_eq!(response.message.payload, b"\xc0\xff\x01body");
+    }
+}
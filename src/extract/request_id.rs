@@ -0,0 +1,115 @@
+//! Request correlation IDs
+//!
+//! A [`RequestId`] names a request by the identity of its connection and the
+//! message ID and token the device sent it with, e.g. `sensor-42#1a2b.c0ffee01`.
+//! Devices usually log message IDs and tokens in hex, so the ID can be
+//! matched against device logs without the device knowing about it. A
+//! retransmission of a CON request keeps its message ID and token and thus
+//! its request ID.
+//!
+//! The server records the ID in the span the request is routed in, and
+//! diagnostic payloads of rejections carry it, see
+//! [`ErrorResponse::with_request_id`](super::ErrorResponse::with_request_id).
+
+use super::FromRequest;
+use crate::router::CoapumRequest;
+use async_trait::async_trait;
+use coap_lite::Packet;
+use std::{fmt, net::SocketAddr};
+
+/// Correlation ID of a request
+///
+/// As an extractor it never fails.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{ErrorResponse, RequestId, StatusCode};
+///
+/// async fn handle_request(request_id: RequestId) -> ErrorResponse {
+///     tracing::warn!(request_id = %request_id, "actuator jammed");
+///     ErrorResponse::new(StatusCode::ServiceUnavailable, "actuator jammed")
+///         .with_request_id(&request_id)
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestId {
+    identity: String,
+    message_id: u16,
+    token: Vec<u8>,
+}
+
+impl RequestId {
+    /// The ID of `packet` received from `identity`
+    pub fn new(identity: &str, packet: &Packet) -> Self {
+        Self {
+            identity: identity.to_string(),
+            message_id: packet.header.message_id,
+            token: packet.get_token().to_vec(),
+        }
+    }
+
+    /// Identity of the connection the request arrived on
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    /// Message ID of the request
+    pub fn message_id(&self) -> u16 {
+        self.message_id
+    }
+
+    /// Token of the request
+    pub fn token(&self) -> &[u8] {
+        &self.token
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{:04x}", self.identity, self.message_id)?;
+        if !self.token.is_empty() {
+            f.write_str(".")?;
+            for byte in &self.token {
+                write!(f, "{:02x}", byte)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for RequestId {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(Self::new(&req.identity, &req.message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CoapRequest;
+
+    #[tokio::test]
+    async fn test_request_id() {
+        let mut packet = Packet::new();
+        packet.header.message_id = 0x1a2b;
+        packet.set_token(vec![0xc0, 0xff, 0xee, 0x01]);
+        let mut req: CoapumRequest<SocketAddr> =
+            CoapRequest::from_packet(packet, "127.0.0.1:5683".parse().unwrap()).into();
+        req.identity = "sensor-42".to_string();
+
+        let id = RequestId::from_request(&req, &()).await.unwrap();
+        assert_eq!(id.to_string(), "sensor-42#1a2b.c0ffee01");
+        assert_eq!(id.message_id(), 0x1a2b);
+
+        req.message.set_token(Vec::new());
+        let id = RequestId::from_request(&req, &()).await.unwrap();
+        assert_eq!(id.to_string(), "sensor-42#1a2b");
+    }
+}
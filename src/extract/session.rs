@@ -0,0 +1,388 @@
+//! Connection-local session storage
+//!
+//! Every DTLS connection owns a [`Session`] for the PSK identity that
+//! authenticated it. Handlers can use it to cache per-device data, such as a
+//! device profile loaded from a database, so later requests on the same
+//! connection skip the lookup. The session is dropped when the connection
+//! terminates; a reconnect starts with an empty session.
+
+use super::{
+    Extension, ExtensionRejection, Extensions, FromRequest, IntoResponse, Rejection, ResponseError,
+    StatusCode,
+};
+use crate::router::CoapumRequest;
+use async_trait::async_trait;
+use std::{
+    fmt,
+    future::Future,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+/// Per-connection storage keyed by type
+///
+/// Cloning a `Session` yields another handle to the same storage. Values are
+/// cloned out on read, so wrap large values in an [`Arc`].
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{Identity, Session};
+///
+/// #[derive(Clone)]
+/// struct DeviceProfile {
+///     firmware: String,
+/// }
+///
+/// async fn load_profile(_device: &str) -> DeviceProfile {
+///     DeviceProfile { firmware: "1.2.0".to_string() }
+/// }
+///
+/// async fn handle_request(Identity(device): Identity, session: Session) {
+///     let profile = session
+///         .get_or_insert_with(|| load_profile(&device))
+///         .await;
+///     println!("{} runs firmware {}", device, profile.firmware);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Session {
+    identity: Arc<str>,
+    psk_identity: Arc<str>,
+    tenant: Option<Arc<str>>,
+    tags: Arc<[String]>,
+    data: Arc<Mutex<Extensions>>,
+}
+
+impl Session {
+    /// Create an empty session for the given identity
+    pub fn new(identity: &str) -> Self {
+        Self {
+            identity: Arc::from(identity),
+            psk_identity: Arc::from(identity),
+            tenant: None,
+            tags: Arc::new([]),
+            data: Arc::new(Mutex::new(Extensions::new())),
+        }
+    }
+
+    /// Create an empty session for a device of `tenant`
+    ///
+    /// The session's identity is the [`tenant_key`](crate::identity::tenant_key)
+    /// of the device.
+    pub fn with_tenant(tenant: &str, device_id: &str) -> Self {
+        let identity: Arc<str> = Arc::from(crate::identity::tenant_key(tenant, device_id));
+        Self {
+            psk_identity: identity.clone(),
+            identity,
+            tenant: Some(Arc::from(tenant)),
+            tags: Arc::new([]),
+            data: Arc::new(Mutex::new(Extensions::new())),
+        }
+    }
+
+    /// The PSK identity that owns this session, or the device ID an
+    /// [`IdentityMapper`](crate::IdentityMapper) mapped it to, prefixed with
+    /// the tenant if the device has one
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    /// Set the PSK identity that authenticated the connection, when an
+    /// [`IdentityMapper`](crate::IdentityMapper) mapped it to another
+    /// device ID
+    pub fn with_psk_identity(mut self, psk_identity: &str) -> Self {
+        self.psk_identity = Arc::from(psk_identity);
+        self
+    }
+
+    /// The PSK identity that authenticated the connection, before any
+    /// mapping
+    pub fn psk_identity(&self) -> &str {
+        &self.psk_identity
+    }
+
+    /// The tenant the device belongs to, if any
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+
+    /// Set the client tags of the device, which routes registered with e.g.
+    /// [`RouterBuilder::post_with_tags`](crate::RouterBuilder::post_with_tags)
+    /// are restricted to
+    ///
+    /// The server sets them from the device's
+    /// [`ClientMetadata`](crate::router::ClientMetadata) when the connection
+    /// is established.
+    pub fn with_tags<I>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// The client tags of the device
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Whether the device has client tag `tag`
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    /// Get a copy of the value of type `T`
+    pub fn get<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.lock().get::<T>().cloned()
+    }
+
+    /// Store a value, returning the previous value of the same type if any
+    pub fn insert<T: Clone + Send + Sync + 'static>(&self, value: T) -> Option<T> {
+        self.lock().insert(value)
+    }
+
+    /// Remove and return the value of type `T`
+    pub fn remove<T: Send + Sync + 'static>(&self) -> Option<T> {
+        self.lock().remove::<T>()
+    }
+
+    /// Whether a value of type `T` is stored
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.lock().contains::<T>()
+    }
+
+    /// Remove all values
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Get the value of type `T`, computing and storing it if missing
+    ///
+    /// The lock is not held while `init` runs, so it may await freely.
+    pub async fn get_or_insert_with<T, F, Fut>(&self, init: F) -> T
+    where
+        T: Clone + Send + Sync + 'static,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        if let Some(value) = self.get::<T>() {
+            return value;
+        }
+        let value = init().await;
+        self.insert(value.clone());
+        value
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Extensions> {
+        // Values are plain data, so a poisoned lock is still usable
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("identity", &self.identity)
+            .field("psk_identity", &self.psk_identity)
+            .field("tenant", &self.tenant)
+            .field("tags", &self.tags)
+            .field("entries", &self.lock().len())
+            .finish()
+    }
+}
+
+/// Extract the connection's session
+///
+/// The server attaches the session to every request and notification on a
+/// connection. Requests that did not come through the server (e.g. calling the
+/// router directly) have no session and are rejected with 5.00 Internal Server
+/// Error.
+#[async_trait]
+impl<S> FromRequest<S> for Session
+where
+    S: Send + Sync,
+{
+    type Rejection = ExtensionRejection;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let Extension(session) = Extension::<Session>::from_request(req, state).await?;
+        Ok(session)
+    }
+}
+
+/// Extract the tenant of the connection's device
+///
+/// The tenant is assigned by the configured
+/// [`IdentityMapper`](crate::IdentityMapper), e.g.
+/// [`TenantSplit`](crate::identity::TenantSplit). Requests from devices
+/// without a tenant are rejected with 4.03 Forbidden, so routes taking a
+/// `Tenant` are only reachable by tenant devices.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{Identity, Tenant};
+///
+/// async fn handle_request(Tenant(tenant): Tenant, Identity(device): Identity) {
+///     println!("{} belongs to {}", device, tenant);
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tenant(pub String);
+
+impl std::ops::Deref for Tenant {
+    type Target = String;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Rejection type for [`Tenant`] extraction failures
+#[derive(Debug)]
+pub struct TenantRejection {
+    kind: TenantRejectionKind,
+}
+
+#[derive(Debug)]
+enum TenantRejectionKind {
+    MissingSession(ExtensionRejection),
+    NoTenant,
+}
+
+impl fmt::Display for TenantRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            TenantRejectionKind::MissingSession(rejection) => rejection.fmt(f),
+            TenantRejectionKind::NoTenant => write!(f, "Device has no tenant"),
+        }
+    }
+}
+
+impl std::error::Error for TenantRejection {}
+
+impl IntoResponse for TenantRejection {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        match self.kind {
+            TenantRejectionKind::MissingSession(rejection) => rejection.into_response(),
+            TenantRejectionKind::NoTenant => StatusCode::Forbidden.into_response(),
+        }
+    }
+}
+
+impl Rejection for TenantRejection {
+    fn kind(&self) -> &'static str {
+        match &self.kind {
+            TenantRejectionKind::MissingSession(rejection) => rejection.kind(),
+            TenantRejectionKind::NoTenant => "tenant.no_tenant",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match &self.kind {
+            TenantRejectionKind::MissingSession(rejection) => rejection.status(),
+            TenantRejectionKind::NoTenant => StatusCode::Forbidden,
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for Tenant
+where
+    S: Send + Sync,
+{
+    type Rejection = TenantRejection;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let session = Session::from_request(req, state)
+            .await
+            .map_err(|rejection| TenantRejection {
+                kind: TenantRejectionKind::MissingSession(rejection),
+            })?;
+        match session.tenant() {
+            Some(tenant) => Ok(Tenant(tenant.to_string())),
+            None => Err(TenantRejection {
+                kind: TenantRejectionKind::NoTenant,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CoapRequest, Packet};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Profile(u32);
+
+    #[tokio::test]
+    async fn test_session_shared_between_clones() {
+        let session = Session::new("device_1");
+        let other = session.clone();
+
+        assert_eq!(session.identity(), "device_1");
+        assert!(session.get::<Profile>().is_none());
+
+        let loaded = other.get_or_insert_with(|| async { Profile(7) }).await;
+        assert_eq!(loaded, Profile(7));
+
+        // Cached value is returned without running init again
+        let cached = session
+            .get_or_insert_with::<Profile, _, _>(|| async { panic!("init ran twice") })
+            .await;
+        assert_eq!(cached, Profile(7));
+
+        session.clear();
+        assert!(!other.contains::<Profile>());
+    }
+
+    #[test]
+    fn test_session_with_tenant() {
+        let session = Session::new("device_1");
+        assert_eq!(session.tenant(), None);
+
+        let session = Session::with_tenant("acme", "device_1");
+        assert_eq!(session.identity(), "acme/device_1");
+        assert_eq!(session.tenant(), Some("acme"));
+    }
+
+    #[tokio::test]
+    async fn test_session_extractor() {
+        let raw = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+        let mut req: CoapumRequest<SocketAddr> = raw.into();
+        assert!(Session::from_request(&req, &()).await.is_err());
+
+        let session = Session::new("device_1");
+        session.insert(Profile(1));
+        req.extensions_mut().insert(session);
+
+        let extracted = Session::from_request(&req, &()).await.unwrap();
+        assert_eq!(extracted.get::<Profile>(), Some(Profile(1)));
+    }
+
+    #[tokio::test]
+    async fn test_tenant_extractor() {
+        let raw = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+        let mut req: CoapumRequest<SocketAddr> = raw.into();
+        assert!(Tenant::from_request(&req, &()).await.is_err());
+
+        req.extensions_mut().insert(Session::new("device_1"));
+        let rejection = Tenant::from_request(&req, &()).await.unwrap_err();
+        let response = rejection.into_response().unwrap();
+        assert_eq!(*response.get_status(), coap_lite::ResponseType::Forbidden);
+
+        req.extensions_mut()
+            .insert(Session::with_tenant("acme", "device_1"));
+        let Tenant(tenant) = Tenant::from_request(&req, &()).await.unwrap();
+        assert_eq!(tenant, "acme");
+    }
+}
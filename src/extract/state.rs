@@ -3,7 +3,7 @@
 //! This module provides extractors for accessing request metadata and application state,
 //! including PSK identity, source address, observe flags, and shared application state.
 
-use super::{FromRequest, IntoResponse, ResponseError, StatusCode};
+use super::{FromRequest, IntoResponse, Rejection, ResponseError, StatusCode};
 use crate::router::CoapumRequest;
 use async_trait::async_trait;
 use coap_lite::ObserveOption;
@@ -13,7 +13,9 @@ use std::{fmt, net::SocketAddr};
 ///
 /// This extractor provides access to the Pre-Shared Key identity that was used
 /// to establish the DTLS connection. This is commonly used for client identification
-/// in IoT applications.
+/// in IoT applications. With an [`IdentityMapper`](crate::IdentityMapper)
+/// configured, it holds the device ID the identity was mapped to, prefixed
+/// with the device's tenant if it has one (see [`Tenant`](super::Tenant)).
 ///
 /// # Example
 ///
@@ -296,7 +298,8 @@ impl<S> FromRequest<S> for ObserveFlag {
 /// 2. **Keep state lightweight**: Large objects should be behind Arc
 /// 3. **Prefer connection pools**: For database connections, always use pools
 /// 4. **Clone should be cheap**: State is cloned per request, so make it efficient
-/// 5. **Consider read-heavy workloads**: Use `Arc<RwLock<T>>` for cached data that's read frequently
+/// 5. **Consider read-heavy workloads**: Use `Arc<RwLock<T>>` for cached data that's read frequently,
+///    or a [`ShardedMap`](crate::sharded::ShardedMap) for per-device data read by many connections
 ///
 /// ## Basic Example
 ///
@@ -363,7 +366,17 @@ impl std::error::Error for StateRejection {}
 
 impl IntoResponse for StateRejection {
     fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
-        StatusCode::InternalServerError.into_response()
+        self.status().into_response()
+    }
+}
+
+impl Rejection for StateRejection {
+    fn kind(&self) -> &'static str {
+        "state.unavailable"
+    }
+
+    fn status(&self) -> StatusCode {
+        StatusCode::InternalServerError
     }
 }
 
@@ -383,6 +396,43 @@ where
     }
 }
 
+/// Derive a state value from a parent state
+///
+/// Used by [`RouterBuilder::nest`](crate::RouterBuilder::nest) to give a group
+/// of routes its own state type, built from the router's state on every
+/// request. Every cloneable type converts from itself.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::FromRef;
+/// use std::sync::Arc;
+///
+/// #[derive(Clone, Debug)]
+/// struct FirmwareStore;
+///
+/// #[derive(Clone, Debug)]
+/// struct AppState {
+///     firmware: Arc<FirmwareStore>,
+/// }
+///
+/// impl FromRef<AppState> for Arc<FirmwareStore> {
+///     fn from_ref(state: &AppState) -> Self {
+///         state.firmware.clone()
+///     }
+/// }
+/// ```
+pub trait FromRef<T> {
+    /// Build `Self` from a reference to the parent state
+    fn from_ref(input: &T) -> Self;
+}
+
+impl<T: Clone> FromRef<T> for T {
+    fn from_ref(input: &T) -> Self {
+        input.clone()
+    }
+}
+
 /// Extract the full CoAP request for advanced use cases
 ///
 /// This extractor provides access to the complete CoAP request structure
@@ -0,0 +1,133 @@
+//! Streamed observe notifications
+//!
+//! An observe notify handler normally produces a single notification. Returning
+//! a [`NotificationStream`] instead lets one trigger emit several notifications,
+//! for example to replay buffered history after a reconnect. The connection
+//! pulls items from the stream one at a time, spaced by
+//! [`Config::notification_stream_pacing`](crate::config::Config), and sends each
+//! item's payload as-is.
+
+use std::{cell::RefCell, future::Future, pin::Pin};
+
+use futures::{Stream, StreamExt};
+
+use super::{IntoResponse, ResponseError, StatusCode};
+use crate::CoapResponse;
+
+/// Boxed stream of notification responses
+pub type BoxNotificationStream = Pin<Box<dyn Stream<Item = CoapResponse> + Send>>;
+
+tokio::task_local! {
+    static CAPTURED_STREAM: RefCell<Option<BoxNotificationStream>>;
+}
+
+/// A stream of notifications returned from an observe notify handler
+///
+/// Each item is converted with [`IntoResponse`]; items that fail to convert
+/// are logged and skipped. Outside of a notify handler the stream cannot be
+/// delivered and the response is 5.00 Internal Server Error.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{Cbor, NotificationStream};
+/// use futures::stream;
+///
+/// async fn replay_history() -> NotificationStream {
+///     let history = vec![20.5, 20.7, 21.0];
+///     NotificationStream::new(stream::iter(history.into_iter().map(Cbor)))
+/// }
+/// ```
+pub struct NotificationStream(BoxNotificationStream);
+
+impl NotificationStream {
+    /// Wrap a stream of responses
+    pub fn new<St, R>(stream: St) -> Self
+    where
+        St: Stream<Item = R> + Send + 'static,
+        R: IntoResponse + Send + 'static,
+    {
+        let responses = stream.filter_map(|item| async move {
+            match item.into_response() {
+                Ok(response) => Some(response),
+                Err(e) => {
+                    tracing::error!("Notification stream item conversion failed: {}", e);
+                    None
+                }
+            }
+        });
+        Self(Box::pin(responses))
+    }
+}
+
+impl std::fmt::Debug for NotificationStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("NotificationStream").finish_non_exhaustive()
+    }
+}
+
+impl IntoResponse for NotificationStream {
+    fn into_response(self) -> Result<CoapResponse, ResponseError> {
+        let mut stream = Some(self.0);
+        let captured = CAPTURED_STREAM
+            .try_with(|slot| *slot.borrow_mut() = stream.take())
+            .is_ok();
+
+        if captured {
+            StatusCode::Content.into_response()
+        } else {
+            tracing::error!("NotificationStream returned outside of an observe notify handler");
+            StatusCode::InternalServerError.into_response()
+        }
+    }
+}
+
+/// Run a notify handler, capturing any [`NotificationStream`] it returned
+pub(crate) async fn capture_stream<F>(future: F) -> (F::Output, Option<BoxNotificationStream>)
+where
+    F: Future,
+{
+    CAPTURED_STREAM
+        .scope(RefCell::new(None), async move {
+            let output = future.await;
+            let stream = CAPTURED_STREAM.with(|slot| slot.borrow_mut().take());
+            (output, stream)
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::Json;
+    use coap_lite::ResponseType;
+    use futures::stream;
+
+    #[tokio::test]
+    async fn test_capture_stream() {
+        let (response, captured) = capture_stream(async {
+            NotificationStream::new(stream::iter(vec![Json(1), Json(2)])).into_response()
+        })
+        .await;
+
+        assert_eq!(*response.unwrap().get_status(), ResponseType::Content);
+        let items: Vec<_> = captured.unwrap().collect().await;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].message.payload, b"2");
+    }
+
+    #[tokio::test]
+    async fn test_stream_outside_notify_handler() {
+        let response = NotificationStream::new(stream::iter(vec![Json(1)]))
+            .into_response()
+            .unwrap();
+        assert_eq!(*response.get_status(), ResponseType::InternalServerError);
+    }
+
+    #[tokio::test]
+    async fn test_capture_without_stream() {
+        let (output, captured) = capture_stream(async { 42 }).await;
+        assert_eq!(output, 42);
+        assert!(captured.is_none());
+    }
+}
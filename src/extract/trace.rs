@@ -0,0 +1,240 @@
+//! Distributed trace context propagation
+//!
+//! CoAP has no standard header for trace context, so coapum carries a W3C
+//! `traceparent` value (e.g. `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`)
+//! in the experimental-use option [`TRACE_CONTEXT_OPTION`]. The option is
+//! elective, so servers that don't know it simply ignore it.
+//!
+//! Handlers read the context with the [`TraceContext`] extractor and pass
+//! [`TraceContext::child`] along to downstream calls; clients attach it with
+//! [`DtlsClient::set_trace_context`](crate::client::DtlsClient::set_trace_context).
+
+use super::FromRequest;
+use crate::router::CoapumRequest;
+use async_trait::async_trait;
+use coap_lite::{CoapOption, Packet};
+use rand::RngExt;
+use std::{fmt, net::SocketAddr};
+
+/// Option number carrying the `traceparent` value (experimental range, elective)
+pub const TRACE_CONTEXT_OPTION: u16 = 65000;
+
+/// Flag bit marking the trace as sampled
+const FLAG_SAMPLED: u8 = 0x01;
+
+/// W3C trace context of a request
+///
+/// As an extractor it never fails: requests without a valid trace context
+/// option get a new root context, so handlers can always propagate one.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::TraceContext;
+///
+/// async fn handle_request(trace: TraceContext) {
+///     tracing::info!(trace_id = %trace.trace_id_hex(), "handling request");
+///     let downstream = trace.child();
+///     // pass `downstream.to_traceparent()` to the next service
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceContext {
+    trace_id: [u8; 16],
+    parent_id: [u8; 8],
+    flags: u8,
+    remote: bool,
+}
+
+impl TraceContext {
+    /// Start a new sampled trace
+    pub fn new_root() -> Self {
+        let mut rng = rand::rng();
+        Self {
+            trace_id: rng.random(),
+            parent_id: rng.random(),
+            flags: FLAG_SAMPLED,
+            remote: false,
+        }
+    }
+
+    /// Parse a W3C `traceparent` value
+    ///
+    /// Returns `None` for malformed values and the all-zero IDs the
+    /// specification declares invalid.
+    pub fn parse(traceparent: &str) -> Option<Self> {
+        let mut parts = traceparent.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let parent_id = parts.next()?;
+        let flags = parts.next()?;
+
+        // Version 00 has exactly four fields; later versions may append more
+        if version.len() != 2 || version == "ff" || (version == "00" && parts.next().is_some()) {
+            return None;
+        }
+
+        let context = Self {
+            trace_id: decode_hex(trace_id)?,
+            parent_id: decode_hex(parent_id)?,
+            flags: decode_hex::<1>(flags)?[0],
+            remote: true,
+        };
+
+        if context.trace_id == [0; 16] || context.parent_id == [0; 8] {
+            return None;
+        }
+        Some(context)
+    }
+
+    /// Read the trace context option of a packet, if present and valid
+    pub fn from_packet(packet: &Packet) -> Option<Self> {
+        let value = packet
+            .get_option(CoapOption::Unknown(TRACE_CONTEXT_OPTION))?
+            .front()?;
+        Self::parse(std::str::from_utf8(value).ok()?)
+    }
+
+    /// Set the trace context option on a packet
+    pub fn inject(&self, packet: &mut Packet) {
+        packet.set_option(
+            CoapOption::Unknown(TRACE_CONTEXT_OPTION),
+            [self.to_traceparent().into_bytes()].into(),
+        );
+    }
+
+    /// A context for an outgoing call: same trace, new parent ID
+    pub fn child(&self) -> Self {
+        Self {
+            parent_id: rand::rng().random(),
+            remote: false,
+            ..*self
+        }
+    }
+
+    /// Format as a W3C `traceparent` value
+    pub fn to_traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{:02x}",
+            encode_hex(&self.trace_id),
+            encode_hex(&self.parent_id),
+            self.flags
+        )
+    }
+
+    /// The 16-byte trace ID
+    pub fn trace_id(&self) -> [u8; 16] {
+        self.trace_id
+    }
+
+    /// The trace ID as lowercase hex, as used by most tracing backends
+    pub fn trace_id_hex(&self) -> String {
+        encode_hex(&self.trace_id)
+    }
+
+    /// The 8-byte ID of the calling span
+    pub fn parent_id(&self) -> [u8; 8] {
+        self.parent_id
+    }
+
+    /// Whether the caller sampled this trace
+    pub fn is_sampled(&self) -> bool {
+        self.flags & FLAG_SAMPLED != 0
+    }
+
+    /// Whether the context was received from the client rather than started here
+    pub fn is_remote(&self) -> bool {
+        self.remote
+    }
+}
+
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_traceparent())
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for TraceContext {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_packet(&req.message).unwrap_or_else(Self::new_root))
+    }
+}
+
+fn decode_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    if hex.len() != N * 2 || !hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        return None;
+    }
+    let mut bytes = [0u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CoapRequest;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_parse_roundtrip() {
+        let context = TraceContext::parse(TRACEPARENT).unwrap();
+        assert_eq!(context.to_traceparent(), TRACEPARENT);
+        assert_eq!(context.trace_id_hex(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert!(context.is_sampled());
+        assert!(context.is_remote());
+
+        let child = context.child();
+        assert_eq!(child.trace_id(), context.trace_id());
+        assert_ne!(child.parent_id(), context.parent_id());
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid() {
+        assert!(TraceContext::parse("").is_none());
+        assert!(
+            TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7").is_none()
+        );
+        assert!(
+            TraceContext::parse("00-00000000000000000000000000000000-00f067aa0ba902b7-01")
+                .is_none()
+        );
+        assert!(
+            TraceContext::parse("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01")
+                .is_none()
+        );
+        assert!(
+            TraceContext::parse("ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extractor() {
+        let mut packet = Packet::new();
+        TraceContext::parse(TRACEPARENT)
+            .unwrap()
+            .inject(&mut packet);
+        let req: CoapumRequest<SocketAddr> =
+            CoapRequest::from_packet(packet, "127.0.0.1:5683".parse().unwrap()).into();
+        let context = TraceContext::from_request(&req, &()).await.unwrap();
+        assert_eq!(context.to_traceparent(), TRACEPARENT);
+
+        let req: CoapumRequest<SocketAddr> =
+            CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap()).into();
+        let context = TraceContext::from_request(&req, &()).await.unwrap();
+        assert!(!context.is_remote());
+    }
+}
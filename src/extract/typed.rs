@@ -0,0 +1,421 @@
+//! Typed device state for handlers
+//!
+//! [`TypedState`] reads the requesting device's document at the request path
+//! into a Rust type and writes it back through the router's observer, the
+//! handler-side counterpart of
+//! [`TypedObserver`](crate::observer::typed::TypedObserver).
+
+use super::ETag;
+use super::{FromRequest, IntoResponse, Rejection, ResponseError, StatusCode};
+use crate::observer::history::{HistoryEntry, HistoryRange};
+use crate::observer::{Observer, VersionedWrite};
+use crate::router::CoapumRequest;
+use async_trait::async_trait;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::{fmt, net::SocketAddr, sync::Arc};
+
+/// Object-safe view of the router's observer
+#[async_trait]
+trait DocumentStore: Send + Sync {
+    async fn read(&self, device_id: &str, path: &str) -> Result<Option<Value>, String>;
+    async fn read_root(&self, device_id: &str) -> Result<Option<Value>, String>;
+    async fn write(&self, device_id: &str, path: &str, value: &Value) -> Result<(), String>;
+    async fn write_batch(
+        &self,
+        device_id: &str,
+        updates: Vec<(String, Value)>,
+    ) -> Result<(), String>;
+    async fn version(&self, device_id: &str) -> Result<Option<u64>, String>;
+    async fn write_if_version(
+        &self,
+        device_id: &str,
+        path: &str,
+        value: &Value,
+        expected: u64,
+    ) -> Result<VersionedWrite, String>;
+    async fn read_history(
+        &self,
+        device_id: &str,
+        path: &str,
+        range: HistoryRange,
+    ) -> Result<Vec<HistoryEntry>, String>;
+}
+
+#[async_trait]
+impl<O: Observer> DocumentStore for O {
+    async fn read(&self, device_id: &str, path: &str) -> Result<Option<Value>, String> {
+        Observer::read(&mut self.clone(), device_id, path)
+            .await
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    async fn read_root(&self, device_id: &str) -> Result<Option<Value>, String> {
+        Observer::read_root(&mut self.clone(), device_id)
+            .await
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    async fn write(&self, device_id: &str, path: &str, value: &Value) -> Result<(), String> {
+        Observer::write(&mut self.clone(), device_id, path, value)
+            .await
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    async fn write_batch(
+        &self,
+        device_id: &str,
+        updates: Vec<(String, Value)>,
+    ) -> Result<(), String> {
+        Observer::write_batch(&mut self.clone(), device_id, updates)
+            .await
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    async fn version(&self, device_id: &str) -> Result<Option<u64>, String> {
+        Observer::version(&mut self.clone(), device_id)
+            .await
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    async fn write_if_version(
+        &self,
+        device_id: &str,
+        path: &str,
+        value: &Value,
+        expected: u64,
+    ) -> Result<VersionedWrite, String> {
+        Observer::write_if_version(&mut self.clone(), device_id, path, value, expected)
+            .await
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    async fn read_history(
+        &self,
+        device_id: &str,
+        path: &str,
+        range: HistoryRange,
+    ) -> Result<Vec<HistoryEntry>, String> {
+        Observer::read_history(&mut self.clone(), device_id, path, range)
+            .await
+            .map_err(|e| format!("{:?}", e))
+    }
+}
+
+/// Access to the requesting device's document, attached by the router
+#[derive(Clone)]
+pub(crate) struct DocumentAccess {
+    device_id: String,
+    path: String,
+    store: Arc<dyn DocumentStore>,
+}
+
+impl DocumentAccess {
+    pub(crate) fn new<O: Observer>(observer: O, device_id: String, path: String) -> Self {
+        Self {
+            device_id,
+            path,
+            store: Arc::new(observer),
+        }
+    }
+
+    /// The device's whole document
+    pub(crate) async fn read_root(&self) -> Result<Option<Value>, String> {
+        self.store.read_root(&self.device_id).await
+    }
+
+    /// The device's history at the request path
+    pub(crate) async fn read_history(
+        &self,
+        range: HistoryRange,
+    ) -> Result<Vec<HistoryEntry>, String> {
+        self.store
+            .read_history(&self.device_id, &self.path, range)
+            .await
+    }
+
+    /// Write several paths of the device's document as a single update
+    pub(crate) async fn write_batch(&self, updates: Vec<(String, Value)>) -> Result<(), String> {
+        self.store.write_batch(&self.device_id, updates).await
+    }
+
+    /// The request path
+    pub(crate) fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// The requesting device's state at the request path, as `T`
+///
+/// Read from the router's observer under the request's PSK identity before
+/// the handler runs; [`get`](Self::get) is `None` if nothing is stored yet.
+/// [`save`](Self::save) writes a new value and notifies observers of the
+/// path, [`save_if`](Self::save_if) only if the document is still at a
+/// given version, e.g. the one in the client's [`IfMatch`](super::IfMatch).
+/// A stored value that doesn't deserialize into `T` is rejected with 5.00
+/// Internal Server Error, as are backend failures.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{Cbor, StatusCode, TypedState};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Config {
+///     interval: u32,
+/// }
+///
+/// async fn get_config(config: TypedState<Config>) -> Option<Cbor<Config>> {
+///     config.into_inner().map(Cbor)
+/// }
+///
+/// async fn put_config(Cbor(new): Cbor<Config>, config: TypedState<Config>) -> StatusCode {
+///     match config.save(&new).await {
+///         Ok(()) => StatusCode::Changed,
+///         Err(_) => StatusCode::InternalServerError,
+///     }
+/// }
+/// ```
+pub struct TypedState<T> {
+    value: Option<T>,
+    version: Option<u64>,
+    access: DocumentAccess,
+}
+
+impl<T> TypedState<T> {
+    /// The stored value, `None` if the device has none at the path
+    pub fn get(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+
+    /// Take the stored value
+    pub fn into_inner(self) -> Option<T> {
+        self.value
+    }
+
+    /// Version of the device's document when the value was read, `None` if
+    /// the observer tracks no versions
+    pub fn version(&self) -> Option<u64> {
+        self.version
+    }
+
+    /// The [`version`](Self::version) as an ETag for the response
+    pub fn etag(&self) -> Option<ETag> {
+        self.version.map(ETag)
+    }
+
+    /// Store `value` at the request path
+    ///
+    /// The value merges into the document like
+    /// [`Observer::write`](crate::observer::Observer::write).
+    pub async fn save(&self, value: &T) -> Result<(), String>
+    where
+        T: Serialize,
+    {
+        let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+        self.access
+            .store
+            .write(&self.access.device_id, &self.access.path, &value)
+            .await
+    }
+
+    /// Store `value` at the request path if the device's document is still
+    /// at version `expected`, see
+    /// [`Observer::write_if_version`](crate::observer::Observer::write_if_version)
+    pub async fn save_if(&self, value: &T, expected: u64) -> Result<VersionedWrite, String>
+    where
+        T: Serialize,
+    {
+        let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+        self.access
+            .store
+            .write_if_version(&self.access.device_id, &self.access.path, &value, expected)
+            .await
+    }
+}
+
+impl<T> std::ops::Deref for TypedState<T> {
+    type Target = Option<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for TypedState<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypedState")
+            .field("value", &self.value)
+            .field("version", &self.version)
+            .field("device_id", &self.access.device_id)
+            .field("path", &self.access.path)
+            .finish()
+    }
+}
+
+/// Rejection type for [`TypedState`] extraction failures
+#[derive(Debug)]
+pub struct TypedStateRejection {
+    kind: TypedStateRejectionKind,
+}
+
+#[derive(Debug)]
+enum TypedStateRejectionKind {
+    Unavailable,
+    ReadFailed { error: String },
+    InvalidData { error: String },
+}
+
+impl fmt::Display for TypedStateRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            TypedStateRejectionKind::Unavailable => write!(f, "Device state is unavailable"),
+            TypedStateRejectionKind::ReadFailed { error } => {
+                write!(f, "Failed to read device state: {}", error)
+            }
+            TypedStateRejectionKind::InvalidData { error } => {
+                write!(f, "Stored device state does not match the type: {}", error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TypedStateRejection {}
+
+impl IntoResponse for TypedStateRejection {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        self.status().into_response()
+    }
+}
+
+impl Rejection for TypedStateRejection {
+    fn kind(&self) -> &'static str {
+        match self.kind {
+            TypedStateRejectionKind::Unavailable => "typed_state.unavailable",
+            TypedStateRejectionKind::ReadFailed { .. } => "typed_state.read_failed",
+            TypedStateRejectionKind::InvalidData { .. } => "typed_state.invalid_data",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        StatusCode::InternalServerError
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for TypedState<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = TypedStateRejection;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let reject = |kind| TypedStateRejection { kind };
+
+        let Some(access) = req.extensions().get::<DocumentAccess>().cloned() else {
+            // Only the router attaches the access, so this is a routing bug
+            tracing::error!("TypedState used outside a routed request");
+            return Err(reject(TypedStateRejectionKind::Unavailable));
+        };
+        // Read the version first: a write in between makes it stale, so a
+        // later `save_if` conflicts instead of overwriting unseen changes
+        let version = access
+            .store
+            .version(&access.device_id)
+            .await
+            .map_err(|error| reject(TypedStateRejectionKind::ReadFailed { error }))?;
+        let value = access
+            .store
+            .read(&access.device_id, &access.path)
+            .await
+            .map_err(|error| reject(TypedStateRejectionKind::ReadFailed { error }))?
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(|e| {
+                reject(TypedStateRejectionKind::InvalidData {
+                    error: e.to_string(),
+                })
+            })?;
+
+        Ok(TypedState {
+            value,
+            version,
+            access,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observer::memory::MemObserver;
+    use crate::{CoapRequest, Packet};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        interval: u32,
+    }
+
+    #[tokio::test]
+    async fn test_typed_state() {
+        let mut req: CoapumRequest<SocketAddr> =
+            CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap()).into();
+        let err = TypedState::<Config>::from_request(&req, &())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), "typed_state.unavailable");
+
+        let (tx, mut rx) = crate::observer::queue::notification_channel(4, Default::default());
+        let mut observer = MemObserver::new();
+        observer
+            .register("dev", "/config", Arc::new(tx))
+            .await
+            .unwrap();
+        // `DocumentStore::write` would write to a clone
+        Observer::write(&mut observer, "dev", "/config", &json!({"interval": 30}))
+            .await
+            .unwrap();
+        rx.recv().await.unwrap();
+        req.extensions_mut().insert(DocumentAccess::new(
+            observer.clone(),
+            "dev".to_string(),
+            "/config".to_string(),
+        ));
+
+        let config = TypedState::<Config>::from_request(&req, &()).await.unwrap();
+        assert_eq!(config.get(), Some(&Config { interval: 30 }));
+        config.save(&Config { interval: 60 }).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap().value, json!({"interval": 60}));
+
+        assert_eq!(config.version(), Some(1));
+        assert_eq!(
+            config.save_if(&Config { interval: 90 }, 0).await.unwrap(),
+            VersionedWrite::Conflict { current: Some(1) }
+        );
+        assert_eq!(
+            config.save_if(&Config { interval: 90 }, 1).await.unwrap(),
+            VersionedWrite::Written(2)
+        );
+
+        let err = TypedState::<String>::from_request(&req, &())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), "typed_state.invalid_data");
+
+        // Nothing stored at the path
+        req.extensions_mut().insert(DocumentAccess::new(
+            observer,
+            "other".to_string(),
+            "/config".to_string(),
+        ));
+        let config = TypedState::<Config>::from_request(&req, &()).await.unwrap();
+        assert!(config.is_none());
+    }
+}
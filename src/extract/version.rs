@@ -0,0 +1,219 @@
+//! Document versions as ETags (RFC 7252 §5.10.6, §5.10.8.1)
+//!
+//! Observer backends that track versions count every change of a device's
+//! document. Handlers hand the version to clients as an [`ETag`] and read
+//! it back from [`IfMatch`], so a client updating state it read earlier
+//! only succeeds if nobody changed the document in between:
+//! [`TypedState::save_if`](super::TypedState::save_if) answers a
+//! conflicting write with 4.12 Precondition Failed.
+
+use super::{FromRequest, IntoResponse, Rejection, ResponseError, StatusCode};
+use crate::observer::VersionedWrite;
+use crate::router::CoapumRequest;
+use async_trait::async_trait;
+use coap_lite::CoapOption;
+use std::{fmt, net::SocketAddr};
+
+/// A document version in an ETag option
+///
+/// Encoded as a big-endian integer without leading zero bytes. Returned
+/// together with a response as `(ETag(version), body)`.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::ETag;
+///
+/// assert_eq!(ETag(258).to_bytes(), vec![1, 2]);
+/// assert_eq!(ETag::from_bytes(&[1, 2]), Some(ETag(258)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ETag(pub u64);
+
+impl ETag {
+    /// The option value, 1 to 8 bytes
+    pub fn to_bytes(self) -> Vec<u8> {
+        let bytes = self.0.to_be_bytes();
+        let start = bytes.iter().position(|&b| b != 0).unwrap_or(7);
+        bytes[start..].to_vec()
+    }
+
+    /// Decode an option value, `None` if it is empty or longer than 8 bytes
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.is_empty() || bytes.len() > 8 {
+            return None;
+        }
+        let mut buffer = [0u8; 8];
+        buffer[8 - bytes.len()..].copy_from_slice(bytes);
+        Some(ETag(u64::from_be_bytes(buffer)))
+    }
+}
+
+/// The response of `T` with an ETag option
+impl<T> IntoResponse for (ETag, T)
+where
+    T: IntoResponse,
+{
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        let (etag, value) = self;
+        let mut response = value.into_response()?;
+        response
+            .message
+            .set_option(CoapOption::ETag, [etag.to_bytes()].into());
+        Ok(response)
+    }
+}
+
+/// 2.04 Changed with the new version as ETag, or 4.12 Precondition Failed
+impl IntoResponse for VersionedWrite {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        match self {
+            VersionedWrite::Written(version) => {
+                (ETag(version), StatusCode::Changed).into_response()
+            }
+            VersionedWrite::Conflict { .. } => StatusCode::PreconditionFailed.into_response(),
+        }
+    }
+}
+
+/// The version a request expects the document to have, from If-Match
+///
+/// `None` if the request carries no If-Match with an ETag; an empty
+/// If-Match, which only asks for the resource to exist, imposes no version.
+/// Of several ETags the first is used. ETags longer than 8 bytes can't be a
+/// version and are rejected with 4.12 Precondition Failed.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{Cbor, IfMatch, StatusCode, TypedState};
+/// use coapum::observer::VersionedWrite;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Config {
+///     interval: u32,
+/// }
+///
+/// async fn put_config(
+///     IfMatch(etag): IfMatch,
+///     config: TypedState<Config>,
+///     Cbor(new): Cbor<Config>,
+/// ) -> Result<VersionedWrite, StatusCode> {
+///     let Some(etag) = etag else {
+///         return Err(StatusCode::PreconditionFailed);
+///     };
+///     config
+///         .save_if(&new, etag.0)
+///         .await
+///         .map_err(|_| StatusCode::InternalServerError)
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IfMatch(pub Option<ETag>);
+
+/// Rejection type for If-Match values that can't be a version
+#[derive(Debug)]
+pub struct IfMatchRejection {
+    len: usize,
+}
+
+impl fmt::Display for IfMatchRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "If-Match ETag of {} bytes is not a version", self.len)
+    }
+}
+
+impl std::error::Error for IfMatchRejection {}
+
+impl IntoResponse for IfMatchRejection {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        self.status().into_response()
+    }
+}
+
+impl Rejection for IfMatchRejection {
+    fn kind(&self) -> &'static str {
+        "if_match.unknown_etag"
+    }
+
+    fn status(&self) -> StatusCode {
+        StatusCode::PreconditionFailed
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for IfMatch
+where
+    S: Send + Sync,
+{
+    type Rejection = IfMatchRejection;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(value) = req
+            .message
+            .get_option(CoapOption::IfMatch)
+            .and_then(|values| values.iter().find(|value| !value.is_empty()))
+        else {
+            return Ok(IfMatch(None));
+        };
+        ETag::from_bytes(value)
+            .map(|etag| IfMatch(Some(etag)))
+            .ok_or(IfMatchRejection { len: value.len() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CoapRequest, Packet};
+    use coap_lite::ResponseType;
+
+    fn request(if_match: &[&[u8]]) -> CoapumRequest<SocketAddr> {
+        let mut packet = Packet::new();
+        for value in if_match {
+            packet.add_option(CoapOption::IfMatch, value.to_vec());
+        }
+        CoapRequest::from_packet(packet, "127.0.0.1:5683".parse().unwrap()).into()
+    }
+
+    #[test]
+    fn test_etag_bytes() {
+        assert_eq!(ETag(0).to_bytes(), vec![0]);
+        assert_eq!(ETag(u64::MAX).to_bytes(), vec![0xff; 8]);
+        assert_eq!(ETag::from_bytes(&[0, 0, 5]), Some(ETag(5)));
+        assert_eq!(ETag::from_bytes(&[]), None);
+        assert_eq!(ETag::from_bytes(&[1; 9]), None);
+    }
+
+    #[tokio::test]
+    async fn test_if_match_extraction() {
+        let IfMatch(etag) = IfMatch::from_request(&request(&[]), &()).await.unwrap();
+        assert_eq!(etag, None);
+
+        let req = request(&[b"", &[0x01, 0x00]]);
+        let IfMatch(etag) = IfMatch::from_request(&req, &()).await.unwrap();
+        assert_eq!(etag, Some(ETag(256)));
+
+        let err = IfMatch::from_request(&request(&[&[1; 9]]), &())
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::PreconditionFailed);
+    }
+
+    #[test]
+    fn test_versioned_write_response() {
+        let response = VersionedWrite::Written(7).into_response().unwrap();
+        assert_eq!(*response.get_status(), ResponseType::Changed);
+        let etag = response.message.get_option(CoapOption::ETag).unwrap();
+        assert_eq!(etag.front(), Some(&vec![7]));
+
+        let response = VersionedWrite::Conflict { current: Some(8) }
+            .into_response()
+            .unwrap();
+        assert_eq!(*response.get_status(), ResponseType::PreconditionFailed);
+    }
+}
@@ -5,7 +5,7 @@
 //! extraction of parameters from requests and conversion of return values to responses.
 
 use crate::CoapResponse;
-use crate::extract::{FromRequest, IntoResponse};
+use crate::extract::{FromRef, FromRequest, IntoResponse, rejection::reject};
 use crate::router::CoapumRequest;
 use async_trait::async_trait;
 use std::{convert::Infallible, future::Future, marker::PhantomData, net::SocketAddr, sync::Arc};
@@ -78,6 +78,11 @@ where
 }
 
 /// Implementation for handlers with one extractor
+///
+/// Extractors run against a snapshot of the state rather than under the
+/// state's read lock: an extractor that awaits, e.g. on the observer
+/// backend, would otherwise hold the lock, stalling state updates and every
+/// request queued behind them. The same goes for the impls below.
 #[async_trait]
 impl<F, Fut, Res, T1, S> Handler<(T1,), S> for HandlerFn<F, S>
 where
@@ -86,25 +91,17 @@ where
     Res: IntoResponse + Send + 'static,
     T1: FromRequest<S> + Send + 'static,
     T1::Rejection: Send + 'static,
-    S: Send + Sync + 'static,
+    S: Clone + Send + Sync + 'static,
 {
     type Future = std::pin::Pin<Box<dyn Future<Output = Result<CoapResponse, Infallible>> + Send>>;
 
     fn call(self, req: CoapumRequest<SocketAddr>, state: Arc<RwLock<S>>) -> Self::Future {
         Box::pin(async move {
-            let state_guard = state.read().await;
-            let t1 = match T1::from_request(&req, &*state_guard).await {
+            let state = state.read().await.clone();
+            let t1 = match T1::from_request(&req, &state).await {
                 Ok(val) => val,
-                Err(rejection) => {
-                    return Ok(rejection.into_response().unwrap_or_else(|e| {
-                        tracing::error!("Rejection response conversion failed: {}", e);
-                        crate::extract::StatusCode::BadRequest
-                            .into_response()
-                            .unwrap()
-                    }));
-                }
+                Err(rejection) => return Ok(reject(&req, rejection)),
             };
-            drop(state_guard);
 
             let result = (self.f)(t1).await;
             Ok(result.into_response().unwrap_or_else(|e| {
@@ -128,40 +125,24 @@ where
     T2: FromRequest<S> + Send + 'static,
     T1::Rejection: Send + 'static,
     T2::Rejection: Send + 'static,
-    S: Send + Sync + 'static,
+    S: Clone + Send + Sync + 'static,
 {
     type Future = std::pin::Pin<Box<dyn Future<Output = Result<CoapResponse, Infallible>> + Send>>;
 
     fn call(self, req: CoapumRequest<SocketAddr>, state: Arc<RwLock<S>>) -> Self::Future {
         Box::pin(async move {
-            let state_guard = state.read().await;
+            let state = state.read().await.clone();
 
-            let t1 = match T1::from_request(&req, &*state_guard).await {
+            let t1 = match T1::from_request(&req, &state).await {
                 Ok(val) => val,
-                Err(rejection) => {
-                    return Ok(rejection.into_response().unwrap_or_else(|e| {
-                        tracing::error!("Rejection response conversion failed: {}", e);
-                        crate::extract::StatusCode::BadRequest
-                            .into_response()
-                            .unwrap()
-                    }));
-                }
+                Err(rejection) => return Ok(reject(&req, rejection)),
             };
 
-            let t2 = match T2::from_request(&req, &*state_guard).await {
+            let t2 = match T2::from_request(&req, &state).await {
                 Ok(val) => val,
-                Err(rejection) => {
-                    return Ok(rejection.into_response().unwrap_or_else(|e| {
-                        tracing::error!("Rejection response conversion failed: {}", e);
-                        crate::extract::StatusCode::BadRequest
-                            .into_response()
-                            .unwrap()
-                    }));
-                }
+                Err(rejection) => return Ok(reject(&req, rejection)),
             };
 
-            drop(state_guard);
-
             let result = (self.f)(t1, t2).await;
             Ok(result.into_response().unwrap_or_else(|e| {
                 tracing::error!("Response conversion failed: {}", e);
@@ -186,52 +167,29 @@ where
     T1::Rejection: Send + 'static,
     T2::Rejection: Send + 'static,
     T3::Rejection: Send + 'static,
-    S: Send + Sync + 'static,
+    S: Clone + Send + Sync + 'static,
 {
     type Future = std::pin::Pin<Box<dyn Future<Output = Result<CoapResponse, Infallible>> + Send>>;
 
     fn call(self, req: CoapumRequest<SocketAddr>, state: Arc<RwLock<S>>) -> Self::Future {
         Box::pin(async move {
-            let state_guard = state.read().await;
+            let state = state.read().await.clone();
 
-            let t1 = match T1::from_request(&req, &*state_guard).await {
+            let t1 = match T1::from_request(&req, &state).await {
                 Ok(val) => val,
-                Err(rejection) => {
-                    return Ok(rejection.into_response().unwrap_or_else(|e| {
-                        tracing::error!("Rejection response conversion failed: {}", e);
-                        crate::extract::StatusCode::BadRequest
-                            .into_response()
-                            .unwrap()
-                    }));
-                }
+                Err(rejection) => return Ok(reject(&req, rejection)),
             };
 
-            let t2 = match T2::from_request(&req, &*state_guard).await {
+            let t2 = match T2::from_request(&req, &state).await {
                 Ok(val) => val,
-                Err(rejection) => {
-                    return Ok(rejection.into_response().unwrap_or_else(|e| {
-                        tracing::error!("Rejection response conversion failed: {}", e);
-                        crate::extract::StatusCode::BadRequest
-                            .into_response()
-                            .unwrap()
-                    }));
-                }
+                Err(rejection) => return Ok(reject(&req, rejection)),
             };
 
-            let t3 = match T3::from_request(&req, &*state_guard).await {
+            let t3 = match T3::from_request(&req, &state).await {
                 Ok(val) => val,
-                Err(rejection) => {
-                    return Ok(rejection.into_response().unwrap_or_else(|e| {
-                        tracing::error!("Rejection response conversion failed: {}", e);
-                        crate::extract::StatusCode::BadRequest
-                            .into_response()
-                            .unwrap()
-                    }));
-                }
+                Err(rejection) => return Ok(reject(&req, rejection)),
             };
 
-            drop(state_guard);
-
             let result = (self.f)(t1, t2, t3).await;
             Ok(result.into_response().unwrap_or_else(|e| {
                 tracing::error!("Response conversion failed: {}", e);
@@ -258,64 +216,34 @@ where
     T2::Rejection: Send + 'static,
     T3::Rejection: Send + 'static,
     T4::Rejection: Send + 'static,
-    S: Send + Sync + 'static,
+    S: Clone + Send + Sync + 'static,
 {
     type Future = std::pin::Pin<Box<dyn Future<Output = Result<CoapResponse, Infallible>> + Send>>;
 
     fn call(self, req: CoapumRequest<SocketAddr>, state: Arc<RwLock<S>>) -> Self::Future {
         Box::pin(async move {
-            let state_guard = state.read().await;
+            let state = state.read().await.clone();
 
-            let t1 = match T1::from_request(&req, &*state_guard).await {
+            let t1 = match T1::from_request(&req, &state).await {
                 Ok(val) => val,
-                Err(rejection) => {
-                    return Ok(rejection.into_response().unwrap_or_else(|e| {
-                        tracing::error!("Rejection response conversion failed: {}", e);
-                        crate::extract::StatusCode::BadRequest
-                            .into_response()
-                            .unwrap()
-                    }));
-                }
+                Err(rejection) => return Ok(reject(&req, rejection)),
             };
 
-            let t2 = match T2::from_request(&req, &*state_guard).await {
+            let t2 = match T2::from_request(&req, &state).await {
                 Ok(val) => val,
-                Err(rejection) => {
-                    return Ok(rejection.into_response().unwrap_or_else(|e| {
-                        tracing::error!("Rejection response conversion failed: {}", e);
-                        crate::extract::StatusCode::BadRequest
-                            .into_response()
-                            .unwrap()
-                    }));
-                }
+                Err(rejection) => return Ok(reject(&req, rejection)),
             };
 
-            let t3 = match T3::from_request(&req, &*state_guard).await {
+            let t3 = match T3::from_request(&req, &state).await {
                 Ok(val) => val,
-                Err(rejection) => {
-                    return Ok(rejection.into_response().unwrap_or_else(|e| {
-                        tracing::error!("Rejection response conversion failed: {}", e);
-                        crate::extract::StatusCode::BadRequest
-                            .into_response()
-                            .unwrap()
-                    }));
-                }
+                Err(rejection) => return Ok(reject(&req, rejection)),
             };
 
-            let t4 = match T4::from_request(&req, &*state_guard).await {
+            let t4 = match T4::from_request(&req, &state).await {
                 Ok(val) => val,
-                Err(rejection) => {
-                    return Ok(rejection.into_response().unwrap_or_else(|e| {
-                        tracing::error!("Rejection response conversion failed: {}", e);
-                        crate::extract::StatusCode::BadRequest
-                            .into_response()
-                            .unwrap()
-                    }));
-                }
+                Err(rejection) => return Ok(reject(&req, rejection)),
             };
 
-            drop(state_guard);
-
             let result = (self.f)(t1, t2, t3, t4).await;
             Ok(result.into_response().unwrap_or_else(|e| {
                 tracing::error!("Response conversion failed: {}", e);
@@ -445,6 +373,50 @@ where
     })
 }
 
+/// Adapter that runs a handler written for state `S2` under a router with state `S`
+struct WithStateHandler<S, S2> {
+    inner: Box<dyn ErasedHandler<S2>>,
+    _state: PhantomData<fn() -> S>,
+}
+
+#[async_trait]
+impl<S, S2> ErasedHandler<S> for WithStateHandler<S, S2>
+where
+    S: Send + Sync + 'static,
+    S2: FromRef<S> + Send + Sync + 'static,
+{
+    async fn call_erased(
+        &self,
+        req: CoapumRequest<SocketAddr>,
+        state: Arc<RwLock<S>>,
+    ) -> Result<CoapResponse, Infallible> {
+        // Derived per request so updates to the parent state are visible
+        let derived = S2::from_ref(&*state.read().await);
+        self.inner
+            .call_erased(req, Arc::new(RwLock::new(derived)))
+            .await
+    }
+
+    fn clone_erased(&self) -> Box<dyn ErasedHandler<S>> {
+        Box::new(WithStateHandler {
+            inner: self.inner.clone_erased(),
+            _state: PhantomData,
+        })
+    }
+}
+
+/// Convert a handler using state `S2` into one using the parent state `S`
+pub fn with_state<S, S2>(handler: Box<dyn ErasedHandler<S2>>) -> Box<dyn ErasedHandler<S>>
+where
+    S: Send + Sync + 'static,
+    S2: FromRef<S> + Send + Sync + 'static,
+{
+    Box::new(WithStateHandler {
+        inner: handler,
+        _state: PhantomData,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
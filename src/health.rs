@@ -0,0 +1,333 @@
+//! Health and readiness resources.
+//!
+//! [`RouterBuilder::health`](crate::RouterBuilder::health) serves two GET
+//! resources for monitoring and orchestration:
+//!
+//! | Resource  | 2.05 Content when                                      |
+//! |-----------|--------------------------------------------------------|
+//! | `/health` | every check passes                                     |
+//! | `/ready`  | every check passes and the server has warmed up        |
+//!
+//! Otherwise they answer 5.03 Service Unavailable. Both carry a CBOR
+//! [`HealthReport`] with the uptime and the outcome of every check: the
+//! observer backend's [`ping`](crate::observer::Observer::ping) and the
+//! checks an application registers on its [`HealthRegistry`].
+//!
+//! Any authenticated client can read the resources, so keep the errors
+//! reported by checks free of secrets.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc, PoisonError, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use coap_lite::RequestType;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    extract::{Cbor, StatusCode},
+    observer::Observer,
+    router::RouterBuilder,
+};
+
+type CheckFuture = Pin<Box<dyn Future<Output = Result<(), String>> + Send>>;
+type Check = Arc<dyn Fn() -> CheckFuture + Send + Sync>;
+
+/// Name of the check that pings the router's observer backend
+pub const OBSERVER_CHECK: &str = "observer";
+
+#[derive(Default)]
+struct Shared {
+    checks: RwLock<BTreeMap<String, Check>>,
+    warming_up: AtomicBool,
+}
+
+/// Health checks and warmup state reported by the health resources
+///
+/// Clones share their checks and state, so keep one to register checks or
+/// end the warmup after mounting it with
+/// [`RouterBuilder::health`](crate::RouterBuilder::health).
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::{RouterBuilder, health::HealthRegistry};
+/// use coapum::observer::memory::MemObserver;
+///
+/// # async fn example() {
+/// let health = HealthRegistry::new().warming_up();
+/// health.register("firmware_store", || async {
+///     std::fs::metadata("/var/lib/firmware")
+///         .map(|_| ())
+///         .map_err(|e| e.to_string())
+/// });
+///
+/// let router = RouterBuilder::new((), MemObserver::new())
+///     .health(health.clone())
+///     .build();
+///
+/// // ... fill caches, then let /ready answer 2.05
+/// health.set_ready();
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct HealthRegistry {
+    shared: Arc<Shared>,
+    started_at: Instant,
+    check_timeout: Duration,
+}
+
+impl HealthRegistry {
+    /// A registry without checks, ready from the start
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::default(),
+            started_at: Instant::now(),
+            check_timeout: Duration::from_secs(2),
+        }
+    }
+
+    /// Report the server as not ready until [`set_ready`](Self::set_ready)
+    /// is called, e.g. while caches are filled at startup
+    pub fn warming_up(self) -> Self {
+        self.shared.warming_up.store(true, Ordering::Relaxed);
+        self
+    }
+
+    /// Fail checks that take longer than `timeout` (default: 2 seconds)
+    pub fn check_timeout(mut self, timeout: Duration) -> Self {
+        self.check_timeout = timeout;
+        self
+    }
+
+    /// Add a check, replacing any check of the same name
+    ///
+    /// The check runs on every request for the health resources and
+    /// returns an error message when it fails.
+    pub fn register<F, Fut>(&self, name: impl Into<String>, check: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let check: Check = Arc::new(move || Box::pin(check()));
+        self.write().insert(name.into(), check);
+    }
+
+    /// Remove a check, returning whether it existed
+    pub fn remove(&self, name: &str) -> bool {
+        self.write().remove(name).is_some()
+    }
+
+    /// End the warmup
+    pub fn set_ready(&self) {
+        self.shared.warming_up.store(false, Ordering::Relaxed);
+    }
+
+    /// Whether the warmup has ended
+    pub fn is_warmed_up(&self) -> bool {
+        !self.shared.warming_up.load(Ordering::Relaxed)
+    }
+
+    /// Run every check concurrently and report the outcome
+    pub async fn report(&self) -> HealthReport {
+        let checks: Vec<(String, Check)> = self
+            .shared
+            .checks
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(name, check)| (name.clone(), check.clone()))
+            .collect();
+
+        let results = join_all(checks.into_iter().map(|(name, check)| async move {
+            let started = Instant::now();
+            let outcome = match tokio::time::timeout(self.check_timeout, check()).await {
+                Ok(outcome) => outcome,
+                Err(_) => Err(format!(
+                    "timed out after {} ms",
+                    self.check_timeout.as_millis()
+                )),
+            };
+            if let Err(error) = &outcome {
+                tracing::warn!(check = %name, error = %error, "health.check_failed");
+            }
+            let result = CheckResult {
+                healthy: outcome.is_ok(),
+                error: outcome.err(),
+                duration_ms: started.elapsed().as_millis() as u64,
+            };
+            (name, result)
+        }))
+        .await;
+
+        let checks: BTreeMap<String, CheckResult> = results.into_iter().collect();
+        let healthy = checks.values().all(|check| check.healthy);
+        HealthReport {
+            healthy,
+            ready: healthy && self.is_warmed_up(),
+            uptime_secs: self.started_at.elapsed().as_secs(),
+            checks,
+        }
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, BTreeMap<String, Check>> {
+        self.shared
+            .checks
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for HealthRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for HealthRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let checks = self
+            .shared
+            .checks
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        f.debug_struct("HealthRegistry")
+            .field("checks", &checks.keys().collect::<Vec<_>>())
+            .field("warmed_up", &self.is_warmed_up())
+            .field("check_timeout", &self.check_timeout)
+            .finish()
+    }
+}
+
+/// Outcome of the health checks, the payload of the health resources
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthReport {
+    /// Whether every check passed
+    pub healthy: bool,
+    /// Whether the server is healthy and has warmed up
+    pub ready: bool,
+    /// Seconds since the registry was created
+    pub uptime_secs: u64,
+    /// Outcome of each check by name
+    pub checks: BTreeMap<String, CheckResult>,
+}
+
+/// Outcome of one health check
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckResult {
+    /// Whether the check passed
+    pub healthy: bool,
+    /// Why the check failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// How long the check took
+    pub duration_ms: u64,
+}
+
+/// Register the health routes and the observer check
+pub(crate) fn mount<O, S>(builder: &mut RouterBuilder<O, S>, health: HealthRegistry, observer: O)
+where
+    S: Clone + fmt::Debug + Send + Sync + 'static,
+    O: Observer + Send + Sync + Clone + 'static,
+{
+    health.register(OBSERVER_CHECK, move || {
+        let mut observer = observer.clone();
+        async move { observer.ping().await.map_err(|e| format!("{:?}", e)) }
+    });
+
+    let registry = health.clone();
+    builder.add_route("/health", RequestType::Get, move || {
+        let registry = registry.clone();
+        async move {
+            let report = registry.report().await;
+            let status = if report.healthy {
+                StatusCode::Content
+            } else {
+                StatusCode::ServiceUnavailable
+            };
+            (status, Cbor(report))
+        }
+    });
+
+    builder.add_route("/ready", RequestType::Get, move || {
+        let registry = health.clone();
+        async move {
+            let report = registry.report().await;
+            let status = if report.ready {
+                StatusCode::Content
+            } else {
+                StatusCode::ServiceUnavailable
+            };
+            (status, Cbor(report))
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::CoapumRequest;
+    use crate::{CoapRequest, Packet, ResponseType};
+    use std::net::SocketAddr;
+    use tower::Service;
+
+    fn request(path: &str) -> CoapumRequest<SocketAddr> {
+        let mut raw = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+        raw.set_method(RequestType::Get);
+        raw.set_path(path);
+        raw.into()
+    }
+
+    #[tokio::test]
+    async fn test_health_and_ready() {
+        let health = HealthRegistry::new().warming_up();
+        let mut router = RouterBuilder::new((), ()).health(health.clone()).build();
+
+        let response = router.call(request("/health")).await.unwrap();
+        assert_eq!(*response.get_status(), ResponseType::Content);
+        let report: HealthReport = ciborium::from_reader(&response.message.payload[..]).unwrap();
+        assert!(report.healthy);
+        assert!(report.checks[OBSERVER_CHECK].healthy);
+
+        // Healthy, but not ready until the warmup ends
+        let response = router.call(request("/ready")).await.unwrap();
+        assert_eq!(*response.get_status(), ResponseType::ServiceUnavailable);
+        health.set_ready();
+        let response = router.call(request("/ready")).await.unwrap();
+        assert_eq!(*response.get_status(), ResponseType::Content);
+
+        health.register("cache", || async { Err("cache offline".to_string()) });
+        let response = router.call(request("/ready")).await.unwrap();
+        assert_eq!(*response.get_status(), ResponseType::ServiceUnavailable);
+        let report: HealthReport = ciborium::from_reader(&response.message.payload[..]).unwrap();
+        assert!(!report.healthy && !report.ready);
+        assert_eq!(
+            report.checks["cache"].error.as_deref(),
+            Some("cache offline")
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_check_timeout() {
+        let health = HealthRegistry::new().check_timeout(Duration::from_millis(100));
+        health.register("slow", || async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Ok(())
+        });
+
+        let report = health.report().await;
+        assert!(!report.healthy);
+        assert_eq!(
+            report.checks["slow"].error.as_deref(),
+            Some("timed out after 100 ms")
+        );
+    }
+}
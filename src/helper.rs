@@ -42,8 +42,85 @@ pub fn convert_cbor_to_json(cbor_data: &[u8]) -> serde_json::Result<JsonValue> {
         MAX_CBOR_RECURSION_DEPTH,
     )
     .map_err(|e| serde_json::Error::custom(format!("CBOR deserialization failed: {}", e)))?;
-    let json_value: JsonValue = serde_json::to_value(cbor_value)?;
-    Ok(json_value)
+    Ok(convert_cbor_value_to_json(cbor_value))
+}
+
+/// Converts a decoded CBOR value to JSON.
+///
+/// CBOR has types JSON lacks, so the conversion is fixed rather than lossless:
+/// byte strings become base64 strings (the encoding SenML uses for `vd`),
+/// tags are dropped in favour of their content, non-text map keys become
+/// their JSON text, and integers or floats JSON can't hold become strings
+/// and `null` respectively.
+///
+/// # Examples
+///
+/// ```
+/// use ciborium::value::Value as CborValue;
+/// use serde_json::json;
+/// use coapum::helper::convert_cbor_value_to_json;
+///
+/// let cbor = CborValue::Map(vec![
+///     (CborValue::Integer(1.into()), CborValue::Bytes(vec![0xde, 0xad, 0xbe, 0xef])),
+/// ]);
+/// assert_eq!(convert_cbor_value_to_json(cbor), json!({"1": "3q2+7w=="}));
+/// ```
+pub fn convert_cbor_value_to_json(value: CborValue) -> JsonValue {
+    match value {
+        CborValue::Integer(i) => {
+            let i = i128::from(i);
+            if let Ok(i) = i64::try_from(i) {
+                JsonValue::from(i)
+            } else if let Ok(u) = u64::try_from(i) {
+                JsonValue::from(u)
+            } else {
+                JsonValue::String(i.to_string())
+            }
+        }
+        CborValue::Bytes(bytes) => JsonValue::String(base64_encode(&bytes)),
+        CborValue::Float(f) => serde_json::Number::from_f64(f)
+            .map(JsonValue::Number)
+            .unwrap_or(JsonValue::Null),
+        CborValue::Text(s) => JsonValue::String(s),
+        CborValue::Bool(b) => JsonValue::Bool(b),
+        CborValue::Null => JsonValue::Null,
+        CborValue::Tag(_, inner) => convert_cbor_value_to_json(*inner),
+        CborValue::Array(items) => items.into_iter().map(convert_cbor_value_to_json).collect(),
+        CborValue::Map(entries) => JsonValue::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = match convert_cbor_value_to_json(key) {
+                        JsonValue::String(s) => s,
+                        other => other.to_string(),
+                    };
+                    (key, convert_cbor_value_to_json(value))
+                })
+                .collect(),
+        ),
+        _ => JsonValue::Null,
+    }
+}
+
+/// Standard base64 with padding
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut result = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let combined = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |acc, (i, &b)| acc | ((b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(ALPHABET[((combined >> (18 - 6 * i)) & 0x3F) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
 }
 
 /// Converts a JSON string to CBOR format.
@@ -110,4 +187,34 @@ mod tests {
         let result = convert_json_to_cbor(json).unwrap();
         assert_eq!(result, expected_cbor);
     }
+
+    #[test]
+    fn test_convert_cbor_value_to_json() {
+        use serde_json::json;
+
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+
+        let cbor = CborValue::Map(vec![
+            (
+                CborValue::Text("data".into()),
+                CborValue::Bytes(b"foo".to_vec()),
+            ),
+            (
+                CborValue::Text("time".into()),
+                CborValue::Tag(1, Box::new(CborValue::Integer(1_700_000_000.into()))),
+            ),
+            (CborValue::Bool(true), CborValue::Float(f64::NAN)),
+            (
+                CborValue::Text("big".into()),
+                CborValue::Integer(u64::MAX.into()),
+            ),
+        ]);
+        assert_eq!(
+            convert_cbor_value_to_json(cbor),
+            json!({"data": "Zm9v", "time": 1_700_000_000, "true": null, "big": u64::MAX})
+        );
+    }
 }
@@ -0,0 +1,159 @@
+//! Mapping PSK identities to device IDs
+//!
+//! By default the PSK identity a device authenticates with is also its device
+//! ID: handlers see it through [`Identity`](crate::extract::Identity) and the
+//! observer stores the device's document under it. When identities carry a
+//! tenant prefix, or a device was re-provisioned under a new identity, set an
+//! [`IdentityMapper`] with
+//! [`Config::set_identity_mapper`](crate::config::Config::set_identity_mapper)
+//! to give the connection a canonical device ID instead.
+//!
+//! The mapper runs once per connection, after the DTLS handshake and before
+//! the first request is routed. The connection registry, reconnect rate
+//! limiting and [`ClientManager`](crate::ClientManager) disconnects keep
+//! using the PSK identity.
+//!
+//! # Tenants
+//!
+//! A mapper can also assign the connection to a tenant through
+//! [`IdentityMapper::tenant`]; [`TenantSplit`] reads it from identities like
+//! `acme:sensor-42`. Devices of different tenants may then share a device ID:
+//! the connection's identity becomes the [`tenant_key`] `acme/sensor-42`, so
+//! observer storage and [`Identity`](crate::extract::Identity) are scoped to
+//! the tenant, and handlers get the tenant itself with
+//! [`Tenant`](crate::extract::Tenant).
+
+use async_trait::async_trait;
+
+/// Maps the PSK identity of a new connection to its device ID
+///
+/// Returning `None` refuses the connection. The device ID must pass the same
+/// checks as a PSK identity (printable ASCII without path separators, at
+/// most 256 bytes), otherwise the connection is refused as well.
+///
+/// Closures `Fn(&str) -> Option<String>` implement the trait, which covers
+/// mappings that need no I/O:
+///
+/// ```rust
+/// use coapum::config::Config;
+///
+/// let mut config = Config::default();
+/// // "Sensor-42" -> "sensor-42"
+/// config.set_identity_mapper(|identity: &str| Some(identity.to_ascii_lowercase()));
+/// ```
+///
+/// Implement the trait directly to look identities up elsewhere:
+///
+/// ```rust
+/// use coapum::IdentityMapper;
+/// use std::collections::HashMap;
+///
+/// struct Aliases {
+///     // Identities of re-provisioned devices, pointing at their original ID
+///     previous: HashMap<String, String>,
+/// }
+///
+/// #[async_trait::async_trait]
+/// impl IdentityMapper for Aliases {
+///     async fn map_identity(&self, identity: &str) -> Option<String> {
+///         let device_id = self.previous.get(identity).map_or(identity, String::as_str);
+///         Some(device_id.to_string())
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait IdentityMapper: Send + Sync + 'static {
+    /// The device ID for a connection authenticated as `identity`
+    async fn map_identity(&self, identity: &str) -> Option<String>;
+
+    /// The tenant a connection authenticated as `identity` belongs to
+    ///
+    /// Called after [`map_identity`](Self::map_identity) accepted the
+    /// identity. The tenant is validated like the device ID. Default: no
+    /// tenant.
+    async fn tenant(&self, _identity: &str) -> Option<String> {
+        None
+    }
+}
+
+#[async_trait]
+impl<F> IdentityMapper for F
+where
+    F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+{
+    async fn map_identity(&self, identity: &str) -> Option<String> {
+        self(identity)
+    }
+}
+
+/// Splits `tenant<separator>device` identities into tenant and device ID
+///
+/// The identity is split at the first separator. Identities without one, or
+/// with an empty tenant or device part, are refused.
+///
+/// ```rust
+/// use coapum::{config::Config, identity::TenantSplit};
+///
+/// let mut config = Config::default();
+/// // "acme:sensor-42" -> tenant "acme", device "sensor-42"
+/// config.set_identity_mapper(TenantSplit::new(':'));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct TenantSplit {
+    separator: char,
+}
+
+impl TenantSplit {
+    /// Split identities at `separator`
+    pub fn new(separator: char) -> Self {
+        Self { separator }
+    }
+
+    fn split<'a>(&self, identity: &'a str) -> Option<(&'a str, &'a str)> {
+        identity
+            .split_once(self.separator)
+            .filter(|(tenant, device)| !tenant.is_empty() && !device.is_empty())
+    }
+}
+
+#[async_trait]
+impl IdentityMapper for TenantSplit {
+    async fn map_identity(&self, identity: &str) -> Option<String> {
+        self.split(identity).map(|(_, device)| device.to_string())
+    }
+
+    async fn tenant(&self, identity: &str) -> Option<String> {
+        self.split(identity).map(|(tenant, _)| tenant.to_string())
+    }
+}
+
+/// The identity a tenant's device is known by on the server
+///
+/// Device IDs and tenants can't contain `/`, so keys of different tenants
+/// never collide, and never collide with the ID of a device without a tenant.
+pub fn tenant_key(tenant: &str, device_id: &str) -> String {
+    format!("{}/{}", tenant, device_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tenant_split() {
+        let split = TenantSplit::new(':');
+        assert_eq!(
+            split.map_identity("acme:sensor:1").await.as_deref(),
+            Some("sensor:1")
+        );
+        assert_eq!(split.tenant("acme:sensor:1").await.as_deref(), Some("acme"));
+
+        assert_eq!(split.map_identity("sensor-1").await, None);
+        assert_eq!(split.map_identity(":sensor-1").await, None);
+        assert_eq!(split.map_identity("acme:").await, None);
+
+        // Closures have no tenant
+        let mapper = |identity: &str| Some(identity.to_string());
+        assert_eq!(mapper.tenant("acme:sensor-1").await, None);
+    }
+}
@@ -1,13 +1,35 @@
+#[cfg(feature = "admin")]
+pub mod admin;
+pub mod allocator;
+pub mod audit;
 pub mod client;
+pub mod compression;
 pub mod config;
+pub mod connection;
 pub mod credential;
+pub mod device_client;
+pub mod engine;
 pub mod extract;
 pub mod handler;
+pub mod health;
 pub mod helper;
+pub mod identity;
+pub mod liveness;
+pub mod mtu;
+pub mod no_response;
 pub mod observer;
+pub mod options;
 pub mod reliability;
 pub mod router;
+pub mod scheduler;
+pub mod senml_gateway;
 pub mod serve;
+pub mod sharded;
+pub mod snapshot;
+pub mod task;
+pub mod time_sync;
+pub mod tracker;
+pub mod transport;
 
 #[cfg(test)]
 mod tests;
@@ -16,21 +38,28 @@ mod tests;
 pub mod test_utils;
 
 // Re-export commonly used types from the ergonomic API
+pub use connection::{
+    ConnectionRegistry, ConnectionSnapshot, HandshakeStats, NotificationStats, TakeoverPolicy,
+    TaskStats,
+};
 pub use credential::memory::MemoryCredentialStore;
 pub use credential::{ClientInfo, CredentialStore, PskEntry};
 pub use extract::state::FullRequest;
 pub use extract::{
-    Bytes, Cbor, FromRequest, Identity, IntoResponse, Json, ObserveFlag, Path, Raw, Source, State,
-    StatusCode,
+    Bytes, Cbor, Extension, Extensions, FromRequest, Identity, IntoResponse, Json, Notification,
+    NotificationStream, ObserveFlag, Path, Payload, Raw, Session, Source, State, StatusCode,
 };
 pub use handler::{Handler, HandlerFn, into_handler};
+pub use identity::IdentityMapper;
 pub use observer::{
-    Observer, ObserverChannels, ObserverRequest, ObserverValue, PathValidationError, merge_json,
-    path_to_json, validate_observer_path,
+    EncodedPayload, Observer, ObserverChannels, ObserverRequest, ObserverValue, PathPolicy,
+    PathValidationError, VersionedWrite, merge_json, path_to_json, path_to_pointer,
+    validate_observer_path,
 };
+pub use router::priority::Priority;
 pub use router::{
-    ClientManager, ClientManagerError, ClientMetadata, NotificationTrigger, RouterBuilder,
-    StateUpdateError, StateUpdateHandle,
+    ClientManager, ClientManagerError, ClientMetadata, DuplicateRoute, NotificationTrigger,
+    ObserveFallback, RouterBuilder, StateUpdateError, StateUpdateHandle, TriggerError,
 };
 
 // Re-export CoAP types
@@ -40,6 +69,25 @@ pub use coap_lite::{
 };
 pub use dimpl as dtls;
 
+#[cfg(feature = "macros")]
+pub use coapum_macros::coap_route;
+
+/// Collect routes declared with `#[coap_route]` for
+/// [`RouterBuilder::mount`]
+///
+/// Each handler's `register` function is called in order, so routes
+/// sharing a path are resolved as if added with the builder directly.
+#[cfg(feature = "macros")]
+#[macro_export]
+macro_rules! routes {
+    ($($($route:ident)::+),* $(,)?) => {
+        |builder| {
+            $( let builder = $($route)::+::register(builder); )*
+            builder
+        }
+    };
+}
+
 #[cfg(test)]
 #[macro_use]
 extern crate lazy_static;
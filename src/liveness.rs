@@ -0,0 +1,373 @@
+//! Device liveness tracking.
+//!
+//! A [`LivenessTracker`] records when each identity was last heard from. Share
+//! it with the server via
+//! [`Config::set_liveness_tracker`](crate::config::Config::set_liveness_tracker)
+//! and every datagram of an established session updates the identity's
+//! last-seen time. Unlike the [`ConnectionRegistry`](crate::ConnectionRegistry),
+//! the tracker keeps an identity's entry after its connection ends, so it
+//! answers "when did sensor-42 last check in?" for sleepy devices too.
+//!
+//! [`LivenessTracker::on_silent`] registers callbacks that run once a device
+//! has been silent for a given time, and
+//! [`RouterBuilder::last_seen_route`](crate::RouterBuilder::last_seen_route)
+//! serves the last-seen times as `/devices/:id/last-seen`.
+//!
+//! Silence is measured on tokio's clock, so tests can pause and advance time.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Debug},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use coap_lite::RequestType;
+use serde_json::json;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::{
+    extract::{Json, StatusCode, state::FullRequest},
+    observer::Observer,
+    router::RouterBuilder,
+    task::BackgroundTask,
+};
+
+type SilenceCallback = Arc<dyn Fn(&str, SystemTime) + Send + Sync>;
+
+/// A callback for devices silent for at least `after`
+struct SilenceWatch {
+    after: Duration,
+    callback: SilenceCallback,
+}
+
+/// When a device was last seen
+#[derive(Debug, Clone, Copy)]
+struct Seen {
+    at: SystemTime,
+    instant: Instant,
+    /// Number of watches, shortest first, that fired since the device was seen
+    reported: usize,
+}
+
+#[derive(Default)]
+struct LivenessState {
+    devices: HashMap<String, Seen>,
+    /// Sorted by `after`
+    watches: Vec<SilenceWatch>,
+}
+
+/// Last-seen times per identity, with callbacks for silent devices
+///
+/// Clones share their state. Silence callbacks only run while the task
+/// returned by [`start`](Self::start) is alive.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use coapum::config::Config;
+/// use coapum::liveness::LivenessTracker;
+/// use std::time::Duration;
+///
+/// # async fn example() {
+/// let liveness = LivenessTracker::new().on_silent(Duration::from_secs(3600), |identity, _| {
+///     tracing::warn!(identity, "device missed its hourly check-in");
+/// });
+/// let _task = liveness.start();
+///
+/// let mut config = Config::default();
+/// config.set_liveness_tracker(liveness.clone());
+/// // ... serve, then later:
+/// let last_seen = liveness.last_seen("sensor-42");
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct LivenessTracker {
+    state: Arc<Mutex<LivenessState>>,
+    check_interval: Duration,
+}
+
+impl LivenessTracker {
+    /// A tracker that has seen no device yet
+    pub fn new() -> Self {
+        Self {
+            state: Arc::default(),
+            check_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Call `callback` with the identity and its last-seen time once a
+    /// device has been silent for `after`
+    ///
+    /// The callback runs once per silence and again after the device has
+    /// been seen and falls silent anew. It runs on the check task and
+    /// should not block.
+    pub fn on_silent<F>(self, after: Duration, callback: F) -> Self
+    where
+        F: Fn(&str, SystemTime) + Send + Sync + 'static,
+    {
+        {
+            let mut state = self.lock();
+            let index = state.watches.partition_point(|w| w.after <= after);
+            state.watches.insert(
+                index,
+                SilenceWatch {
+                    after,
+                    callback: Arc::new(callback),
+                },
+            );
+        }
+        self
+    }
+
+    /// How often the check task looks for silent devices (default: 1 second)
+    pub fn check_interval(mut self, interval: Duration) -> Self {
+        self.check_interval = interval.max(Duration::from_millis(1));
+        self
+    }
+
+    /// Record that `identity` was seen now
+    ///
+    /// The server calls this for every datagram; transports driving an
+    /// [`Engine`](crate::engine::Engine) get the same through
+    /// [`Engine::liveness`](crate::engine::Engine::liveness).
+    pub fn touch(&self, identity: &str) {
+        let seen = Seen {
+            at: SystemTime::now(),
+            instant: Instant::now(),
+            reported: 0,
+        };
+        let mut state = self.lock();
+        match state.devices.get_mut(identity) {
+            Some(entry) => *entry = seen,
+            None => {
+                state.devices.insert(identity.to_string(), seen);
+            }
+        }
+    }
+
+    /// When `identity` was last seen, `None` if never
+    pub fn last_seen(&self, identity: &str) -> Option<SystemTime> {
+        self.lock().devices.get(identity).map(|seen| seen.at)
+    }
+
+    /// How long `identity` has been silent, `None` if never seen
+    pub fn idle(&self, identity: &str) -> Option<Duration> {
+        self.lock()
+            .devices
+            .get(identity)
+            .map(|seen| seen.instant.elapsed())
+    }
+
+    /// Last-seen times of every identity seen
+    pub fn list(&self) -> BTreeMap<String, SystemTime> {
+        self.lock()
+            .devices
+            .iter()
+            .map(|(identity, seen)| (identity.clone(), seen.at))
+            .collect()
+    }
+
+    /// Identities silent for at least `after`
+    pub fn silent_for(&self, after: Duration) -> Vec<String> {
+        let mut silent: Vec<String> = self
+            .lock()
+            .devices
+            .iter()
+            .filter(|(_, seen)| seen.instant.elapsed() >= after)
+            .map(|(identity, _)| identity.clone())
+            .collect();
+        silent.sort();
+        silent
+    }
+
+    /// Drop `identity`, e.g. when the device is decommissioned. Returns
+    /// false if it was never seen.
+    pub fn forget(&self, identity: &str) -> bool {
+        self.lock().devices.remove(identity).is_some()
+    }
+
+    /// Spawn the task running the [`on_silent`](Self::on_silent) callbacks
+    ///
+    /// Dropping or shutting down the returned task stops the checks; the
+    /// last-seen times are still recorded.
+    pub fn start(&self) -> BackgroundTask {
+        let tracker = self.clone();
+        BackgroundTask::spawn(|mut stop| async move {
+            let mut interval = tokio::time::interval(tracker.check_interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = &mut stop => break,
+                    _ = interval.tick() => tracker.check(),
+                }
+            }
+        })
+    }
+
+    /// Run the callbacks of watches that silent devices passed since the
+    /// last check
+    fn check(&self) {
+        let mut due = Vec::new();
+        {
+            let mut state = self.lock();
+            let LivenessState { devices, watches } = &mut *state;
+            for (identity, seen) in devices.iter_mut() {
+                let idle = seen.instant.elapsed();
+                while let Some(watch) = watches.get(seen.reported)
+                    && idle >= watch.after
+                {
+                    due.push((watch.callback.clone(), identity.clone(), seen.at));
+                    seen.reported += 1;
+                }
+            }
+        }
+
+        // Outside the lock, so callbacks may query the tracker
+        for (callback, identity, last_seen) in due {
+            tracing::debug!(identity = %identity, "liveness.silent");
+            callback(&identity, last_seen);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LivenessState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for LivenessTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for LivenessTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock();
+        f.debug_struct("LivenessTracker")
+            .field("devices", &state.devices.len())
+            .field("watches", &state.watches.len())
+            .field("check_interval", &self.check_interval)
+            .finish()
+    }
+}
+
+/// Register the last-seen route on the builder
+pub(crate) fn mount<O, S>(builder: &mut RouterBuilder<O, S>, tracker: LivenessTracker)
+where
+    S: Clone + Debug + Send + Sync + 'static,
+    O: Observer + Send + Sync + Clone + 'static,
+{
+    builder.add_route(
+        "/devices/:id/last-seen",
+        RequestType::Get,
+        move |FullRequest(req): FullRequest| {
+            let tracker = tracker.clone();
+            async move {
+                // The path matched the route, so the ID is the second segment
+                let identity = req
+                    .get_path()
+                    .split('/')
+                    .filter(|segment| !segment.is_empty())
+                    .nth(1)
+                    .unwrap_or_default()
+                    .to_string();
+                let seen = tracker.last_seen(&identity).ok_or(StatusCode::NotFound)?;
+                let idle = tracker.idle(&identity).unwrap_or_default();
+                let last_seen = seen.duration_since(UNIX_EPOCH).unwrap_or_default();
+                Ok::<_, StatusCode>(Json(json!({
+                    "identity": identity,
+                    "last_seen": last_seen.as_secs(),
+                    "idle_secs": idle.as_secs(),
+                })))
+            }
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::CoapumRequest;
+    use crate::{CoapRequest, Packet, ResponseType};
+    use serde_json::Value;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::Service;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_silence_callbacks() {
+        let warned = Arc::new(AtomicUsize::new(0));
+        let lost = Arc::new(Mutex::new(Vec::new()));
+        let tracker = {
+            let warned = warned.clone();
+            let lost = lost.clone();
+            LivenessTracker::new()
+                .on_silent(Duration::from_secs(60), move |_, _| {
+                    warned.fetch_add(1, Ordering::Relaxed);
+                })
+                .on_silent(Duration::from_secs(10), move |identity, _| {
+                    lost.lock().unwrap().push(identity.to_string());
+                })
+        };
+        let _task = tracker.start();
+
+        tracker.touch("dev-1");
+        tracker.touch("dev-2");
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        tracker.touch("dev-2");
+        tokio::time::sleep(Duration::from_secs(8)).await;
+        assert_eq!(*lost.lock().unwrap(), ["dev-1"]);
+        assert_eq!(tracker.silent_for(Duration::from_secs(10)), ["dev-1"]);
+
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(*lost.lock().unwrap(), ["dev-1", "dev-2"]);
+        assert_eq!(warned.load(Ordering::Relaxed), 2);
+
+        // Seen again, so the callbacks run again on the next silence
+        tracker.touch("dev-1");
+        tokio::time::sleep(Duration::from_secs(12)).await;
+        assert_eq!(*lost.lock().unwrap(), ["dev-1", "dev-2", "dev-1"]);
+        assert_eq!(warned.load(Ordering::Relaxed), 2);
+
+        assert!(tracker.forget("dev-1"));
+        assert_eq!(tracker.last_seen("dev-1"), None);
+        assert_eq!(tracker.list().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_last_seen_route() {
+        let tracker = LivenessTracker::new();
+        tracker.touch("sensor-42");
+        let mut router = RouterBuilder::new((), ())
+            .last_seen_route(tracker.clone())
+            .build();
+
+        let request = |path: &str| {
+            let mut raw =
+                CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+            raw.set_method(RequestType::Get);
+            raw.set_path(path);
+            CoapumRequest::<SocketAddr>::from(raw)
+        };
+
+        let response = router
+            .call(request("/devices/sensor-42/last-seen"))
+            .await
+            .unwrap();
+        assert_eq!(*response.get_status(), ResponseType::Content);
+        let body: Value = serde_json::from_slice(&response.message.payload).unwrap();
+        assert_eq!(body["identity"], "sensor-42");
+        let last_seen = tracker.last_seen("sensor-42").unwrap();
+        assert_eq!(
+            body["last_seen"],
+            last_seen.duration_since(UNIX_EPOCH).unwrap().as_secs()
+        );
+
+        let response = router
+            .call(request("/devices/sensor-43/last-seen"))
+            .await
+            .unwrap();
+        assert_eq!(*response.get_status(), ResponseType::NotFound);
+    }
+}
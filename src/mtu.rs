@@ -0,0 +1,203 @@
+//! Fitting responses into the path MTU.
+//!
+//! A DTLS record is sent in a single UDP datagram, so a response is only
+//! delivered if the CoAP message, the DTLS record overhead and the IP and
+//! UDP headers fit into the MTU of the path to the device. IP fragmentation
+//! is unreliable on constrained networks, and records that don't fit are
+//! dropped without notice.
+//!
+//! The server therefore sizes Block2 (RFC 7959) blocks by
+//! [`Config::mtu`](crate::config::Config::mtu): a response that doesn't fit
+//! into one datagram is sent block-wise, in blocks smaller than those the
+//! client asked for if need be (RFC 7959 §2.4). A response whose options
+//! alone leave no room for the smallest block is answered with 5.00 instead.
+//!
+//! [`max_payload`] estimates how much payload a response can carry without
+//! being split.
+
+use coap_lite::{CoapOption, Packet};
+
+/// Default path MTU, the minimum MTU of IPv6 (RFC 8200 §5)
+pub const DEFAULT_MTU: usize = 1280;
+
+/// Smallest MTU that leaves room for a 16-byte block with a few options,
+/// see [`Config::validate`](crate::config::Config::validate)
+pub const MIN_MTU: usize = 192;
+
+/// IPv6 and UDP headers; IPv4 headers are smaller
+const IP_UDP_OVERHEAD: usize = 40 + 8;
+
+/// DTLS 1.2 record header, explicit nonce and AEAD tag of the largest
+/// supported cipher suite (AES-128-GCM)
+const DTLS_RECORD_OVERHEAD: usize = 13 + 8 + 16;
+
+/// Block2 and Size2 options added to a block, with room for longer option
+/// deltas of the options following them
+const BLOCK_OPTIONS_OVERHEAD: usize = 12;
+
+/// Smallest and largest Block2 sizes (RFC 7959 §2.2)
+const MIN_BLOCK_SIZE: usize = 16;
+const MAX_BLOCK_SIZE: usize = 1024;
+
+/// The largest CoAP message a DTLS record in one datagram can hold on a
+/// path with `mtu`
+pub fn max_message_size(mtu: usize) -> usize {
+    mtu.saturating_sub(IP_UDP_OVERHEAD + DTLS_RECORD_OVERHEAD)
+}
+
+/// The largest payload `response` can carry on a path with `mtu` without
+/// being split into blocks
+///
+/// The estimate accounts for the response's header, token and options as
+/// they are; a handler can compare its payload against it to decide on a
+/// more compact representation.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::mtu::{DEFAULT_MTU, max_payload};
+/// use coapum::{ContentFormat, Packet};
+///
+/// let mut response = Packet::new();
+/// response.set_content_format(ContentFormat::ApplicationCBOR);
+/// assert!(max_payload(DEFAULT_MTU, &response) > 1024);
+/// assert_eq!(max_payload(64, &response), 0);
+/// ```
+pub fn max_payload(mtu: usize, response: &Packet) -> usize {
+    max_message_size(mtu).saturating_sub(header_len(response) + 1)
+}
+
+/// A response that can't be fitted into the MTU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ExceedsMtu {
+    /// Encoded size of the response
+    pub(crate) size: usize,
+    /// Largest message that fits
+    pub(crate) limit: usize,
+}
+
+/// Make `request` ask for Block2 blocks small enough for `response` to fit
+/// a path with `mtu`
+///
+/// Block sizes the client asked for are kept if they fit, otherwise the
+/// block number is scaled to the smaller size so the client's offset stays
+/// the same.
+pub(crate) fn fit_block2(
+    request: &mut Packet,
+    response: &Packet,
+    mtu: usize,
+) -> Result<(), ExceedsMtu> {
+    let requested = block2(request);
+    let size =
+        header_len(response) + response.payload.len() + usize::from(!response.payload.is_empty());
+    let limit = max_message_size(mtu);
+    if requested.is_none() && size <= limit {
+        return Ok(());
+    }
+
+    let room = max_payload(mtu, response).saturating_sub(BLOCK_OPTIONS_OVERHEAD);
+    let Some(block_size) = block_size(room) else {
+        return Err(ExceedsMtu { size, limit });
+    };
+    match requested {
+        Some((_, requested_size)) if requested_size <= block_size => {}
+        Some((num, requested_size)) => {
+            set_block2(request, num * requested_size / block_size, block_size);
+        }
+        None => set_block2(request, 0, block_size),
+    }
+    Ok(())
+}
+
+/// Encoded size of `packet` without its payload
+fn header_len(packet: &Packet) -> usize {
+    // Responses to be split are larger than `to_bytes` allows
+    match packet.to_bytes_unlimited() {
+        Ok(bytes) => bytes.len() - packet.payload.len() - usize::from(!packet.payload.is_empty()),
+        Err(_) => usize::MAX / 2,
+    }
+}
+
+/// The largest block size that fits into `room` payload bytes
+fn block_size(room: usize) -> Option<usize> {
+    let size = MAX_BLOCK_SIZE.min(room);
+    if size < MIN_BLOCK_SIZE {
+        return None;
+    }
+    // Block sizes are powers of two
+    Some(1 << size.ilog2())
+}
+
+/// The block number and size of a Block2 option
+fn block2(packet: &Packet) -> Option<(usize, usize)> {
+    let value = packet.get_option(CoapOption::Block2)?.front()?;
+    if value.len() > 3 {
+        return None;
+    }
+    let value = value.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize);
+    let szx = value & 0x07;
+    // SZX 7 is reserved
+    (szx < 7).then_some((value >> 4, 1 << (szx + 4)))
+}
+
+/// Set the Block2 option of a request for block `num` of `size` bytes
+fn set_block2(packet: &mut Packet, num: usize, size: usize) {
+    let szx = size.ilog2() as usize - 4;
+    let value = u32::try_from((num << 4) | szx)
+        .unwrap_or(u32::MAX)
+        .to_be_bytes();
+    // RFC 7252 §3.2: uint options use the shortest encoding
+    let start = value.iter().position(|&b| b != 0).unwrap_or(4);
+    packet.set_option(CoapOption::Block2, [value[start..].to_vec()].into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coap_lite::ContentFormat;
+
+    fn response(payload: usize) -> Packet {
+        let mut packet = Packet::new();
+        packet.set_token(vec![1, 2, 3, 4]);
+        packet.set_content_format(ContentFormat::ApplicationJSON);
+        packet.payload = vec![b'x'; payload];
+        packet
+    }
+
+    #[test]
+    fn test_max_payload() {
+        let response = response(0);
+        // 4-byte header, 4-byte token, Content-Format and the payload marker
+        assert_eq!(max_payload(DEFAULT_MTU, &response), 1280 - 48 - 37 - 11);
+        assert_eq!(max_message_size(50), 0);
+    }
+
+    #[test]
+    fn test_fit_block2() {
+        // Fits into one datagram: left alone
+        let mut request = Packet::new();
+        fit_block2(&mut request, &response(1000), DEFAULT_MTU).unwrap();
+        assert_eq!(block2(&request), None);
+
+        // Too large: blocks sized for the MTU
+        fit_block2(&mut request, &response(4000), 400).unwrap();
+        assert_eq!(block2(&request), Some((0, 256)));
+
+        // Requested blocks that don't fit are split further at the same offset
+        let mut request = Packet::new();
+        set_block2(&mut request, 3, 1024);
+        fit_block2(&mut request, &response(8000), 600).unwrap();
+        assert_eq!(block2(&request), Some((12, 256)));
+
+        // Requested blocks that fit are kept
+        let mut request = Packet::new();
+        set_block2(&mut request, 2, 64);
+        fit_block2(&mut request, &response(8000), 600).unwrap();
+        assert_eq!(block2(&request), Some((2, 64)));
+
+        // No room for even the smallest block
+        let mut request = Packet::new();
+        let err = fit_block2(&mut request, &response(100), 100).unwrap_err();
+        assert_eq!(err.limit, 15);
+    }
+}
@@ -0,0 +1,129 @@
+//! No-Response option (RFC 7967).
+//!
+//! Clients sending fire-and-forget requests, typically telemetry over a
+//! constrained uplink, can use the No-Response option to tell the server which
+//! response classes they are not interested in. The server still processes the
+//! request but skips sending suppressed responses; a CON request whose
+//! response is suppressed is answered with an empty ACK so the client stops
+//! retransmitting.
+
+use coap_lite::{CoapOption, MessageClass, Packet, ResponseType};
+
+/// Option number of No-Response (RFC 7967 §2)
+pub const NO_RESPONSE_OPTION: u16 = 258;
+
+/// Suppress 2.xx responses
+const SUPPRESS_SUCCESS: u8 = 0x02;
+/// Suppress 4.xx responses
+const SUPPRESS_CLIENT_ERROR: u8 = 0x08;
+/// Suppress 5.xx responses
+const SUPPRESS_SERVER_ERROR: u8 = 0x10;
+
+/// The response classes a request asked not to receive
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::{Packet, ResponseType};
+/// use coapum::no_response::NoResponse;
+///
+/// let mut packet = Packet::new();
+/// NoResponse::success().apply(&mut packet);
+///
+/// let no_response = NoResponse::from_packet(&packet);
+/// assert!(no_response.suppresses(ResponseType::Changed));
+/// assert!(!no_response.suppresses(ResponseType::BadRequest));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NoResponse {
+    mask: u8,
+}
+
+impl NoResponse {
+    /// Interested in all responses (no option, or an empty option value)
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Not interested in 2.xx responses
+    pub fn success() -> Self {
+        Self {
+            mask: SUPPRESS_SUCCESS,
+        }
+    }
+
+    /// Not interested in any response
+    pub fn all() -> Self {
+        Self {
+            mask: SUPPRESS_SUCCESS | SUPPRESS_CLIENT_ERROR | SUPPRESS_SERVER_ERROR,
+        }
+    }
+
+    /// Create from the raw option value
+    pub fn from_bits(mask: u8) -> Self {
+        Self { mask }
+    }
+
+    /// The raw option value
+    pub fn bits(&self) -> u8 {
+        self.mask
+    }
+
+    /// Parse the No-Response option of a packet
+    ///
+    /// A missing option means the client wants every response.
+    pub fn from_packet(packet: &Packet) -> Self {
+        let mask = packet
+            .get_option(CoapOption::Unknown(NO_RESPONSE_OPTION))
+            .and_then(|values| values.front())
+            .and_then(|value| value.last().copied())
+            .unwrap_or(0);
+        Self { mask }
+    }
+
+    /// Set the No-Response option on a packet
+    pub fn apply(&self, packet: &mut Packet) {
+        let value = if self.mask == 0 {
+            Vec::new()
+        } else {
+            vec![self.mask]
+        };
+        packet.set_option(CoapOption::Unknown(NO_RESPONSE_OPTION), [value].into());
+    }
+
+    /// Whether a response with this status should not be sent
+    pub fn suppresses(&self, status: ResponseType) -> bool {
+        let class = u8::from(MessageClass::Response(status)) >> 5;
+        let bit = match class {
+            2 => SUPPRESS_SUCCESS,
+            4 => SUPPRESS_CLIENT_ERROR,
+            5 => SUPPRESS_SERVER_ERROR,
+            _ => return false,
+        };
+        self.mask & bit != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_option_suppresses_nothing() {
+        let no_response = NoResponse::from_packet(&Packet::new());
+        assert_eq!(no_response, NoResponse::none());
+        assert!(!no_response.suppresses(ResponseType::Content));
+    }
+
+    #[test]
+    fn test_suppressed_classes() {
+        let mut packet = Packet::new();
+        NoResponse::from_bits(SUPPRESS_SUCCESS | SUPPRESS_SERVER_ERROR).apply(&mut packet);
+
+        let no_response = NoResponse::from_packet(&packet);
+        assert!(no_response.suppresses(ResponseType::Changed));
+        assert!(!no_response.suppresses(ResponseType::NotFound));
+        assert!(no_response.suppresses(ResponseType::InternalServerError));
+        assert!(NoResponse::all().suppresses(ResponseType::BadRequest));
+    }
+}
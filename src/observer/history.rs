@@ -0,0 +1,284 @@
+//! Retained history of written values.
+//!
+//! With history enabled, an observer backend keeps the last few values written
+//! to each path together with the time they were written, so handlers can serve
+//! recent time series without an external database. History is a ring buffer
+//! per device and path: once [`HistoryConfig::new`]'s depth is reached, the
+//! oldest entry is dropped for every new one.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::path_to_pointer;
+
+/// A value written to a path at a point in time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub timestamp: SystemTime,
+    pub value: Value,
+}
+
+/// Selects entries from a path's history
+///
+/// Entries are returned oldest first. With a limit, only the most recent
+/// entries within the time bounds are returned.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::{Duration, SystemTime};
+/// use coapum::observer::history::HistoryRange;
+///
+/// // The 10 most recent values from the last hour
+/// let range = HistoryRange::since(SystemTime::now() - Duration::from_secs(3600)).limit(10);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HistoryRange {
+    pub since: Option<SystemTime>,
+    pub until: Option<SystemTime>,
+    pub limit: Option<usize>,
+}
+
+impl HistoryRange {
+    /// All retained entries
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Entries written at or after `since`
+    pub fn since(since: SystemTime) -> Self {
+        Self {
+            since: Some(since),
+            ..Self::default()
+        }
+    }
+
+    /// Entries written between `since` and `until`, inclusive
+    pub fn between(since: SystemTime, until: SystemTime) -> Self {
+        Self {
+            since: Some(since),
+            until: Some(until),
+            limit: None,
+        }
+    }
+
+    /// The `count` most recent entries
+    pub fn last(count: usize) -> Self {
+        Self::all().limit(count)
+    }
+
+    /// Return at most `count` entries, keeping the most recent
+    pub fn limit(mut self, count: usize) -> Self {
+        self.limit = Some(count);
+        self
+    }
+
+    fn contains(&self, timestamp: SystemTime) -> bool {
+        self.since.is_none_or(|since| timestamp >= since)
+            && self.until.is_none_or(|until| timestamp <= until)
+    }
+
+    /// Pick the matching entries from a history, oldest first
+    pub(crate) fn select<'a>(
+        &self,
+        entries: impl DoubleEndedIterator<Item = &'a HistoryEntry>,
+    ) -> Vec<HistoryEntry> {
+        let mut selected: Vec<HistoryEntry> = entries
+            .rev()
+            .filter(|entry| self.contains(entry.timestamp))
+            .take(self.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect();
+        selected.reverse();
+        selected
+    }
+}
+
+/// Which paths keep history and how much of it
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::observer::history::HistoryConfig;
+/// use coapum::observer::memory::MemObserver;
+///
+/// // Keep the last 100 values written at or below /telemetry
+/// let observer = MemObserver::new().with_history(HistoryConfig::new(100).path("/telemetry"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct HistoryConfig {
+    depth: usize,
+    paths: Vec<String>,
+}
+
+impl HistoryConfig {
+    /// Keep the last `depth` values of every written path
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            paths: Vec::new(),
+        }
+    }
+
+    /// Only keep history for writes at or below `path`
+    ///
+    /// May be called several times; without any path every write is recorded.
+    pub fn path(mut self, path: &str) -> Self {
+        self.paths.push(path_to_pointer(path));
+        self
+    }
+
+    /// Number of entries retained per path
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Whether writes to the JSON pointer are recorded
+    pub(crate) fn records(&self, pointer: &str) -> bool {
+        self.depth > 0
+            && (self.paths.is_empty()
+                || self.paths.iter().any(|rule| {
+                    pointer == rule
+                        || pointer
+                            .strip_prefix(rule.as_str())
+                            .is_some_and(|rest| rest.starts_with('/'))
+                }))
+    }
+
+    /// Append an entry, dropping the oldest ones beyond the configured depth
+    pub(crate) fn push(&self, buffer: &mut VecDeque<HistoryEntry>, entry: HistoryEntry) {
+        buffer.push_back(entry);
+        while buffer.len() > self.depth {
+            buffer.pop_front();
+        }
+    }
+}
+
+/// Buffered entries of one device, keyed by JSON pointer
+type DeviceHistory = HashMap<String, VecDeque<HistoryEntry>>;
+
+/// In-memory history storage
+#[derive(Debug, Clone)]
+pub(crate) struct HistoryStore {
+    config: HistoryConfig,
+    entries: Arc<Mutex<HashMap<String, DeviceHistory>>>,
+}
+
+impl HistoryStore {
+    pub(crate) fn new(config: HistoryConfig) -> Self {
+        Self {
+            config,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Record a value written to `path` at `timestamp`
+    pub(crate) fn record(&self, device_id: &str, path: &str, value: &Value, timestamp: SystemTime) {
+        let pointer = path_to_pointer(path);
+        if !self.config.records(&pointer) {
+            return;
+        }
+
+        let mut entries = self.lock();
+        let buffer = entries
+            .entry(device_id.to_string())
+            .or_default()
+            .entry(pointer)
+            .or_default();
+        self.config.push(
+            buffer,
+            HistoryEntry {
+                timestamp,
+                value: value.clone(),
+            },
+        );
+    }
+
+    pub(crate) fn read(
+        &self,
+        device_id: &str,
+        path: &str,
+        range: HistoryRange,
+    ) -> Vec<HistoryEntry> {
+        self.lock()
+            .get(device_id)
+            .and_then(|paths| paths.get(&path_to_pointer(path)))
+            .map(|buffer| range.select(buffer.iter()))
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn forget(&self, device_id: &str) {
+        self.lock().remove(device_id);
+    }
+
+    fn lock(
+        &self,
+    ) -> std::sync::MutexGuard<'_, HashMap<String, HashMap<String, VecDeque<HistoryEntry>>>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_ring_buffer_depth() {
+        let store = HistoryStore::new(HistoryConfig::new(3));
+        for i in 0..5 {
+            store.record("dev", "/temp", &json!(i), at(i));
+        }
+
+        let values: Vec<Value> = store
+            .read("dev", "temp", HistoryRange::all())
+            .into_iter()
+            .map(|entry| entry.value)
+            .collect();
+        assert_eq!(values, [json!(2), json!(3), json!(4)]);
+    }
+
+    #[test]
+    fn test_range_selection() {
+        let store = HistoryStore::new(HistoryConfig::new(10));
+        for i in 0..5 {
+            store.record("dev", "/temp", &json!(i), at(i));
+        }
+
+        let between = store.read("dev", "/temp", HistoryRange::between(at(1), at(3)));
+        assert_eq!(between.len(), 3);
+        assert_eq!(between[0].timestamp, at(1));
+
+        let last = store.read("dev", "/temp", HistoryRange::since(at(1)).limit(2));
+        assert_eq!(
+            last.iter().map(|e| e.timestamp).collect::<Vec<_>>(),
+            [at(3), at(4)]
+        );
+    }
+
+    #[test]
+    fn test_path_filter() {
+        let store = HistoryStore::new(HistoryConfig::new(10).path("/telemetry"));
+        store.record("dev", "/telemetry/temp", &json!(1), at(0));
+        store.record("dev", "/config", &json!(1), at(0));
+
+        assert_eq!(
+            store
+                .read("dev", "/telemetry/temp", HistoryRange::all())
+                .len(),
+            1
+        );
+        assert!(store.read("dev", "/config", HistoryRange::all()).is_empty());
+    }
+}
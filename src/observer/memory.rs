@@ -1,17 +1,27 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, time::SystemTime};
 
 use async_trait::async_trait;
 use serde_json::Value;
-use tokio::sync::mpsc::Sender;
 
-use super::{Observer, ObserverChannels, ObserverValue};
+use super::{
+    EncodedPayload, Observer, ObserverChannels, ObserverSender, Registration,
+    history::{HistoryConfig, HistoryEntry, HistoryRange, HistoryStore},
+    metadata::ObservationMetadata,
+    policy::{QuotaExceeded, StoragePolicy},
+};
 
 /// A memory-based observer that stores data in a HashMap.
 #[derive(Clone, Debug)]
 pub struct MemObserver {
     db: HashMap<String, Value>,
+    /// Payloads written with `write_raw`, by device ID and JSON pointer
+    raw: HashMap<String, HashMap<String, EncodedPayload>>,
+    /// Document versions by device ID, see [`Observer::version`]
+    versions: HashMap<String, u64>,
     /// Shared channel management for observer notifications.
     pub channels: ObserverChannels,
+    policy: StoragePolicy,
+    history: Option<HistoryStore>,
 }
 
 impl MemObserver {
@@ -19,9 +29,87 @@ impl MemObserver {
     pub fn new() -> Self {
         Self {
             db: HashMap::new(),
+            raw: HashMap::new(),
+            versions: HashMap::new(),
             channels: ObserverChannels::new(),
+            policy: StoragePolicy::new(),
+            history: None,
         }
     }
+
+    /// Enforces size limits and path expiry on every write.
+    pub fn with_policy(mut self, policy: StoragePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Keeps a history of written values that can be read back with
+    /// [`Observer::read_history`].
+    pub fn with_history(mut self, config: HistoryConfig) -> Self {
+        self.history = Some(HistoryStore::new(config));
+        self
+    }
+
+    /// Merges values into the device's document with `merge`, keeping
+    /// `raw` as the payload the update at its path was converted from
+    async fn store(
+        &mut self,
+        device_id: &str,
+        updates: &[(String, Value)],
+        raw: Option<(&str, EncodedPayload)>,
+        merge: fn(&mut Value, &Value),
+    ) -> Result<(), MemObserverError> {
+        let current_value = self.db.get(device_id).cloned().unwrap_or(Value::Null);
+
+        let mut value = current_value.clone();
+        for (path, payload) in updates {
+            let new_value = super::path_to_json(path, payload);
+            tracing::debug!("New value: {:?} for path: {}", new_value, path);
+            merge(&mut value, &new_value);
+        }
+        tracing::debug!("Merged value: {:?}", value);
+
+        let paths: Vec<String> = updates.iter().map(|(path, _)| path.clone()).collect();
+        let enforcement = self
+            .policy
+            .enforce(device_id, &mut value, &paths)
+            .map_err(MemObserverError::QuotaExceeded)?;
+
+        // Notify observers of changes
+        match &raw {
+            Some((path, raw)) => {
+                self.channels
+                    .notify_raw(device_id, &current_value, &value, path, raw)
+                    .await
+            }
+            None => {
+                self.channels
+                    .notify(device_id, &current_value, &value)
+                    .await
+            }
+        }
+
+        // Write merged value
+        self.db.insert(device_id.to_string(), value);
+        *self.versions.entry(device_id.to_string()).or_default() += 1;
+        self.policy.commit(device_id, enforcement);
+
+        if let Some((path, raw)) = raw {
+            self.raw
+                .entry(device_id.to_string())
+                .or_default()
+                .insert(super::path_to_pointer(path), raw);
+        }
+
+        if let Some(history) = &self.history {
+            let now = SystemTime::now();
+            for (path, payload) in updates {
+                history.record(device_id, path, payload, now);
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Default for MemObserver {
@@ -36,6 +124,7 @@ use std::fmt;
 pub enum MemObserverError {
     IoError(std::io::Error),
     IdNotSet,
+    QuotaExceeded(QuotaExceeded),
 }
 
 impl fmt::Display for MemObserverError {
@@ -43,6 +132,7 @@ impl fmt::Display for MemObserverError {
         match self {
             MemObserverError::IoError(err) => write!(f, "IO error: {}", err),
             MemObserverError::IdNotSet => write!(f, "Device ID must be set before use!"),
+            MemObserverError::QuotaExceeded(err) => write!(f, "Quota exceeded: {}", err),
         }
     }
 }
@@ -52,6 +142,7 @@ impl std::error::Error for MemObserverError {
         match self {
             MemObserverError::IoError(err) => Some(err),
             MemObserverError::IdNotSet => None,
+            MemObserverError::QuotaExceeded(err) => Some(err),
         }
     }
 }
@@ -71,9 +162,22 @@ impl Observer for MemObserver {
         &mut self,
         device_id: &str,
         path: &str,
-        sender: Arc<Sender<ObserverValue>>,
+        sender: ObserverSender,
     ) -> Result<(), Self::Error> {
-        self.channels.register(device_id, path, sender).await;
+        self.register_with_metadata(device_id, path, sender, ObservationMetadata::new())
+            .await
+    }
+
+    async fn register_with_metadata(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        sender: ObserverSender,
+        metadata: ObservationMetadata,
+    ) -> Result<(), Self::Error> {
+        self.channels
+            .register_with_metadata(device_id, path, sender, metadata)
+            .await;
         Ok(())
     }
 
@@ -92,43 +196,85 @@ impl Observer for MemObserver {
         Ok(())
     }
 
-    async fn write(
+    async fn unregister_sender(
         &mut self,
         device_id: &str,
         path: &str,
-        payload: &Value,
+        sender: &ObserverSender,
     ) -> Result<(), Self::Error> {
-        let new_value = super::path_to_json(path, payload);
+        self.channels
+            .unregister_sender(device_id, path, sender)
+            .await;
+        Ok(())
+    }
 
-        tracing::debug!("New value: {:?} for path: {}", new_value, path);
+    async fn unregister_connection(
+        &mut self,
+        device_id: &str,
+        sender: &ObserverSender,
+    ) -> Result<(), Self::Error> {
+        self.channels.unregister_connection(device_id, sender).await;
+        Ok(())
+    }
 
-        let current_value = self.db.get(device_id).cloned().unwrap_or(Value::Null);
+    async fn write(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: &Value,
+    ) -> Result<(), Self::Error> {
+        let updates = [(path.to_string(), payload.clone())];
+        self.store(device_id, &updates, None, super::merge_json)
+            .await
+    }
 
-        let value = if current_value != Value::Null {
-            let mut merged_value = current_value.clone();
-            super::merge_json(&mut merged_value, &new_value);
-            tracing::debug!("Merged value: {:?}", merged_value);
-            merged_value
-        } else {
-            new_value
-        };
+    /// Merges all updates into the document at once, so observers are
+    /// notified once for the whole batch.
+    async fn write_batch(
+        &mut self,
+        device_id: &str,
+        updates: Vec<(String, Value)>,
+    ) -> Result<(), Self::Error> {
+        self.store(device_id, &updates, None, super::merge_json)
+            .await
+    }
 
-        // Notify observers of changes
-        self.channels
-            .notify(device_id, &current_value, &value)
-            .await;
+    async fn patch(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        patch: &Value,
+    ) -> Result<(), Self::Error> {
+        let updates = [(path.to_string(), patch.clone())];
+        self.store(device_id, &updates, None, super::merge_patch)
+            .await
+    }
 
-        // Write merged value
-        self.db.insert(device_id.to_string(), value);
+    async fn write_raw(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: EncodedPayload,
+    ) -> Result<(), Self::Error> {
+        let updates = [(path.to_string(), payload.to_json())];
+        self.store(
+            device_id,
+            &updates,
+            Some((path, payload)),
+            super::merge_json,
+        )
+        .await
+    }
 
-        Ok(())
+    async fn version(&mut self, device_id: &str) -> Result<Option<u64>, Self::Error> {
+        Ok(Some(self.versions.get(device_id).copied().unwrap_or(0)))
     }
 
     async fn read(&mut self, device_id: &str, path: &str) -> Result<Option<Value>, Self::Error> {
         match self.db.get(device_id) {
             Some(value) => {
                 tracing::debug!("Got value: {:?}", value);
-                let pointer_value = value.pointer(path).cloned();
+                let pointer_value = value.pointer(&super::path_to_pointer(path)).cloned();
                 tracing::debug!("Pointer value: {:?}", pointer_value);
                 Ok(pointer_value)
             }
@@ -136,19 +282,77 @@ impl Observer for MemObserver {
         }
     }
 
+    async fn read_raw(
+        &mut self,
+        device_id: &str,
+        path: &str,
+    ) -> Result<Option<EncodedPayload>, Self::Error> {
+        let pointer = super::path_to_pointer(path);
+        let raw = self
+            .raw
+            .get(device_id)
+            .and_then(|paths| paths.get(&pointer));
+        Ok(match (raw, self.db.get(device_id)) {
+            (Some(raw), Some(document)) if raw.is_current(document, &pointer) => Some(raw.clone()),
+            _ => None,
+        })
+    }
+
     async fn clear(&mut self, device_id: &str) -> Result<(), Self::Error> {
         let _ = self.db.remove(device_id);
+        let _ = self.raw.remove(device_id);
+        // Keep counting, so versions read before the clear don't match again
+        *self.versions.entry(device_id.to_string()).or_default() += 1;
+        self.policy.forget(device_id);
+        if let Some(history) = &self.history {
+            history.forget(device_id);
+        }
         Ok(())
     }
 
+    async fn notify_encoded(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: EncodedPayload,
+    ) -> Result<(), Self::Error> {
+        self.channels.notify_encoded(device_id, path, payload).await;
+        Ok(())
+    }
+
+    async fn read_history(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        range: HistoryRange,
+    ) -> Result<Vec<HistoryEntry>, Self::Error> {
+        Ok(self
+            .history
+            .as_ref()
+            .map(|history| history.read(device_id, path, range))
+            .unwrap_or_default())
+    }
+
     async fn observer_count(&self, device_id: &str) -> usize {
         self.channels.device_observer_count(device_id).await
     }
+
+    async fn list(&self, device_id: &str) -> Vec<Registration> {
+        self.channels.list(device_id).await
+    }
+
+    async fn list_all(&self) -> Vec<Registration> {
+        self.channels.list_all().await
+    }
+
+    async fn observing_devices(&self, path: &str) -> Vec<String> {
+        self.channels.observing_devices(path).await
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::{sync::Arc, time::Duration};
 
     use serde_json::json;
     use tokio::time::sleep;
@@ -218,7 +422,7 @@ mod tests {
         observer.clear("123").await.unwrap();
 
         // Channel and register
-        let (tx, mut rx) = tokio::sync::mpsc::channel::<ObserverValue>(10);
+        let (tx, mut rx) = crate::observer::queue::notification_channel(10, Default::default());
 
         let fut = tokio::spawn(async move {
             if let Some(r) = rx.recv().await {
@@ -267,4 +471,240 @@ mod tests {
         observer.unregister_all().await.unwrap();
         assert!(observer.channels.is_empty().await);
     }
+
+    #[tokio::test]
+    async fn test_mem_observer_read_pointer_and_root() {
+        let mut observer = MemObserver::new();
+
+        observer
+            .write("456", "/sensors/temp", &json!(21.5))
+            .await
+            .unwrap();
+
+        // Paths resolve the same with or without leading/trailing slashes
+        let result = observer.read("456", "sensors/temp/").await.unwrap();
+        assert_eq!(result, Some(json!(21.5)));
+        assert_eq!(
+            observer.read("456", "/sensors/missing").await.unwrap(),
+            None
+        );
+
+        let root = observer.read_root("456").await.unwrap();
+        assert_eq!(root, Some(json!({"sensors": {"temp": 21.5}})));
+        assert_eq!(observer.read("456", "/").await.unwrap(), root);
+        assert_eq!(observer.read_root("unknown").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_mem_observer_policy() {
+        use crate::observer::policy::{OverflowAction, StoragePolicy};
+
+        let policy = StoragePolicy::new()
+            .max_document_size(40)
+            .overflow(OverflowAction::EvictOldest);
+        let mut observer = MemObserver::new().with_policy(policy);
+
+        let (tx, mut rx) = crate::observer::queue::notification_channel(10, Default::default());
+        observer
+            .register("789", "/old", Arc::new(tx))
+            .await
+            .unwrap();
+
+        observer
+            .write("789", "/old", &json!("0123456789"))
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap().value, json!("0123456789"));
+
+        // Exceeding the limit evicts the oldest path and notifies its observer
+        observer
+            .write("789", "/new", &json!("0123456789abcdef"))
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap().value, Value::Null);
+        assert_eq!(observer.read("789", "/old").await.unwrap(), None);
+
+        // A single value larger than the limit is rejected
+        let err = observer
+            .write("789", "/huge", &json!("x".repeat(64)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, MemObserverError::QuotaExceeded(_)));
+        assert_eq!(observer.read("789", "/huge").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_mem_observer_raw_payload() {
+        use coap_lite::ContentFormat;
+
+        let mut observer = MemObserver::new();
+        let (tx, mut rx) = crate::observer::queue::notification_channel(10, Default::default());
+        let tx = Arc::new(tx);
+        observer.register("raw", "/fw", tx.clone()).await.unwrap();
+        observer.register("raw", "/", tx).await.unwrap();
+
+        // {"hash": h'cafe'}
+        let payload = EncodedPayload::new(
+            ContentFormat::ApplicationCBOR,
+            vec![0xa1, 0x64, b'h', b'a', b's', b'h', 0x42, 0xca, 0xfe],
+        );
+        observer
+            .write_raw("raw", "/fw", payload.clone())
+            .await
+            .unwrap();
+
+        // JSON readers see the converted value, raw readers the exact bytes
+        assert_eq!(
+            observer.read("raw", "/fw").await.unwrap(),
+            Some(json!({"hash": "yv4="}))
+        );
+        assert_eq!(
+            observer.read_raw("raw", "fw").await.unwrap(),
+            Some(payload.clone())
+        );
+
+        // Observers of the path get the bytes, parents the merged JSON
+        let mut notifications = [rx.recv().await.unwrap(), rx.recv().await.unwrap()];
+        notifications.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(notifications[0].encoded, None);
+        assert_eq!(notifications[0].value, json!({"fw": {"hash": "yv4="}}));
+        assert_eq!(notifications[1].encoded, Some(payload));
+
+        // A later JSON write replaces the raw payload
+        observer
+            .write("raw", "/fw/hash", &json!("beef"))
+            .await
+            .unwrap();
+        assert_eq!(observer.read_raw("raw", "/fw").await.unwrap(), None);
+        assert_eq!(observer.read_raw("raw", "/other").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_mem_observer_history() {
+        use crate::observer::history::{HistoryConfig, HistoryRange};
+
+        let mut observer = MemObserver::new().with_history(HistoryConfig::new(2).path("/sensors"));
+        for i in 0..3 {
+            observer
+                .write("hist", "/sensors/temp", &json!(i))
+                .await
+                .unwrap();
+        }
+        observer
+            .write("hist", "/config", &json!("untracked"))
+            .await
+            .unwrap();
+
+        let history = observer
+            .read_history("hist", "/sensors/temp", HistoryRange::all())
+            .await
+            .unwrap();
+        let values: Vec<Value> = history.into_iter().map(|entry| entry.value).collect();
+        assert_eq!(values, [json!(1), json!(2)]);
+
+        let last = observer
+            .read_history("hist", "sensors/temp", HistoryRange::last(1))
+            .await
+            .unwrap();
+        assert_eq!(last[0].value, json!(2));
+
+        let untracked = observer
+            .read_history("hist", "/config", HistoryRange::all())
+            .await
+            .unwrap();
+        assert!(untracked.is_empty());
+
+        observer.clear("hist").await.unwrap();
+        let cleared = observer
+            .read_history("hist", "/sensors/temp", HistoryRange::all())
+            .await
+            .unwrap();
+        assert!(cleared.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_mem_observer_patch() {
+        let mut observer = MemObserver::new();
+        observer
+            .write("dev", "/config", &json!({"led": "on", "interval": 30}))
+            .await
+            .unwrap();
+
+        let (tx, mut rx) = crate::observer::queue::notification_channel(10, Default::default());
+        observer
+            .register("dev", "/config", Arc::new(tx))
+            .await
+            .unwrap();
+
+        observer
+            .patch("dev", "/config", &json!({"led": null, "interval": 60}))
+            .await
+            .unwrap();
+        assert_eq!(
+            observer.read("dev", "/config").await.unwrap(),
+            Some(json!({"interval": 60}))
+        );
+
+        let notification = rx.recv().await.unwrap();
+        assert_eq!(notification.value, json!({"interval": 60}));
+        assert_eq!(
+            notification.changed,
+            vec!["/config/interval", "/config/led"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mem_observer_versions() {
+        use crate::observer::VersionedWrite;
+
+        let mut observer = MemObserver::new();
+        assert_eq!(observer.version("dev").await.unwrap(), Some(0));
+
+        observer
+            .write("dev", "/config", &json!({"interval": 30}))
+            .await
+            .unwrap();
+        assert_eq!(observer.version("dev").await.unwrap(), Some(1));
+
+        let written = observer
+            .write_if_version("dev", "/config", &json!({"interval": 60}), 1)
+            .await
+            .unwrap();
+        assert_eq!(written, VersionedWrite::Written(2));
+
+        // A second writer still holding version 1 loses
+        let conflict = observer
+            .write_if_version("dev", "/config", &json!({"interval": 90}), 1)
+            .await
+            .unwrap();
+        assert_eq!(conflict, VersionedWrite::Conflict { current: Some(2) });
+        assert_eq!(
+            observer.read("dev", "/config").await.unwrap(),
+            Some(json!({"interval": 60}))
+        );
+
+        observer.clear("dev").await.unwrap();
+        assert_eq!(observer.version("dev").await.unwrap(), Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_mem_observer_list_registrations() {
+        let mut observer = MemObserver::new();
+        let (tx, _rx) = crate::observer::queue::notification_channel(4, Default::default());
+        let metadata = ObservationMetadata::new();
+        metadata.insert("unit", "F");
+        observer
+            .register_with_metadata("123", "/temp", Arc::new(tx), metadata)
+            .await
+            .unwrap();
+
+        let registrations = observer.list("123").await;
+        assert_eq!(registrations.len(), 1);
+        assert_eq!(registrations[0].path, "/temp");
+        assert_eq!(registrations[0].metadata.get("unit").as_deref(), Some("F"));
+        assert_eq!(observer.list_all().await.len(), 1);
+
+        observer.unregister_device("123").await.unwrap();
+        assert!(observer.list_all().await.is_empty());
+    }
 }
@@ -0,0 +1,189 @@
+//! Key-value metadata attached to an observation
+//!
+//! An observation often needs more than its path: the `pmin`/`pmax`
+//! attributes a client asked for, a preferred unit, a firmware channel.
+//! [`ObservationMetadata`] starts out with the Uri-Query parameters of the
+//! registering GET, the handler of that GET can add its own entries, and
+//! every notification built for the observation sees the same metadata.
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use coap_lite::{CoapOption, Packet};
+
+use crate::extract::FromRequest;
+use crate::router::CoapumRequest;
+
+/// Metadata of one observation
+///
+/// Cloning shares the entries, so values inserted by the registering GET
+/// handler, or by a notify handler, are seen by later notifications of the
+/// same observation. Each observation has its own metadata, even when one
+/// connection observes several paths.
+///
+/// As an extractor it yields the observation's metadata in the registering
+/// GET handler and in notify handlers. In any other handler it holds the
+/// request's query parameters and changes are not kept.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{Json, Notification, ObservationMetadata};
+/// use serde_json::Value;
+///
+/// // GET /sensors/temp?unit=F with Observe: 0
+/// async fn get_temperature(metadata: ObservationMetadata) -> Json<Value> {
+///     metadata.insert("registered", "true");
+///     Json(Value::Null)
+/// }
+///
+/// async fn notify_temperature(
+///     Notification(celsius): Notification<f64>,
+///     metadata: ObservationMetadata,
+/// ) -> Json<f64> {
+///     match metadata.get("unit").as_deref() {
+///         Some("F") => Json(celsius * 9.0 / 5.0 + 32.0),
+///         _ => Json(celsius),
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ObservationMetadata {
+    entries: Arc<RwLock<BTreeMap<String, String>>>,
+}
+
+impl ObservationMetadata {
+    /// Create empty metadata
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Metadata holding the packet's Uri-Query parameters
+    ///
+    /// `key=value` options become entries; options without `=` are stored
+    /// with an empty value. Options that aren't UTF-8 are skipped.
+    pub fn from_packet(packet: &Packet) -> Self {
+        let metadata = Self::new();
+        for option in packet
+            .get_option(CoapOption::UriQuery)
+            .into_iter()
+            .flatten()
+        {
+            let Ok(param) = std::str::from_utf8(option) else {
+                continue;
+            };
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            metadata.insert(key, value);
+        }
+        metadata
+    }
+
+    /// Returns the value stored under `key`
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.read().get(key).cloned()
+    }
+
+    /// Returns the value stored under `key` parsed as `T`, or `None` if it
+    /// is missing or fails to parse
+    pub fn get_parsed<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.read().get(key)?.parse().ok()
+    }
+
+    /// Returns true if a value is stored under `key`
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.read().contains_key(key)
+    }
+
+    /// Stores a value, returning the one it replaced
+    pub fn insert(&self, key: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        self.write().insert(key.into(), value.into())
+    }
+
+    /// Removes the value stored under `key`
+    pub fn remove(&self, key: &str) -> Option<String> {
+        self.write().remove(key)
+    }
+
+    /// Returns a copy of all entries
+    pub fn to_map(&self) -> BTreeMap<String, String> {
+        self.read().clone()
+    }
+
+    /// The minimum period between notifications the client asked for, from
+    /// the `pmin` conditional attribute in seconds
+    pub fn pmin(&self) -> Option<Duration> {
+        self.get_parsed("pmin").map(Duration::from_secs)
+    }
+
+    /// The maximum period between notifications the client asked for, from
+    /// the `pmax` conditional attribute in seconds
+    pub fn pmax(&self) -> Option<Duration> {
+        self.get_parsed("pmax").map(Duration::from_secs)
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, String>> {
+        self.entries.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, BTreeMap<String, String>> {
+        self.entries.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for ObservationMetadata
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(req
+            .extensions()
+            .get::<ObservationMetadata>()
+            .cloned()
+            .unwrap_or_else(|| Self::from_packet(&req.message)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_from_query() {
+        let mut packet = Packet::new();
+        packet.add_option(CoapOption::UriQuery, b"pmin=10".to_vec());
+        packet.add_option(CoapOption::UriQuery, b"pmax=60".to_vec());
+        packet.add_option(CoapOption::UriQuery, b"verbose".to_vec());
+        packet.add_option(CoapOption::UriQuery, vec![0xff]);
+
+        let metadata = ObservationMetadata::from_packet(&packet);
+        assert_eq!(metadata.pmin(), Some(Duration::from_secs(10)));
+        assert_eq!(metadata.pmax(), Some(Duration::from_secs(60)));
+        assert_eq!(metadata.get("verbose").as_deref(), Some(""));
+        assert_eq!(metadata.to_map().len(), 3);
+    }
+
+    #[test]
+    fn test_metadata_shared_between_clones() {
+        let metadata = ObservationMetadata::new();
+        let notification = metadata.clone();
+
+        metadata.insert("unit", "F");
+        assert_eq!(notification.get("unit").as_deref(), Some("F"));
+        assert_eq!(notification.get_parsed::<u32>("unit"), None);
+
+        assert_eq!(notification.remove("unit").as_deref(), Some("F"));
+        assert!(!metadata.contains_key("unit"));
+    }
+}
@@ -1,21 +1,154 @@
-use std::{collections::HashMap, fmt::Debug, sync::Arc, time::Duration};
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
 
 use async_trait::async_trait;
-use serde_json::{Value, map::Entry};
-use tokio::sync::{RwLock, mpsc::Sender};
+use coap_lite::ContentFormat;
+use history::{HistoryEntry, HistoryRange};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value, map::Entry};
+use tokio::sync::RwLock;
+use tracing::Instrument;
 
+use crate::extract::Extensions;
+
+pub mod history;
 pub mod memory;
+pub mod metadata;
+pub mod policy;
+pub mod queue;
 #[cfg(feature = "redb-observer")]
 pub mod redb;
+pub mod resilience;
 #[cfg(feature = "sled-observer")]
 pub mod sled;
 pub mod subscriber;
+pub mod tiered;
+pub mod typed;
 
 /// A struct representing an observer value.
 #[derive(Debug, Clone)]
 pub struct ObserverValue {
     pub value: Value,
     pub path: String,
+    /// Pre-encoded payload sent instead of `value`, see [`Observer::notify_encoded`]
+    pub encoded: Option<EncodedPayload>,
+    /// Paths at or below `path` whose values changed, see [`changed_paths`]
+    pub changed: Vec<String>,
+}
+
+/// A notification payload encoded by the caller and sent unchanged
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodedPayload {
+    pub content_format: ContentFormat,
+    pub bytes: Vec<u8>,
+}
+
+impl EncodedPayload {
+    /// Wrap bytes in the given content format
+    pub fn new(content_format: ContentFormat, bytes: Vec<u8>) -> Self {
+        Self {
+            content_format,
+            bytes,
+        }
+    }
+
+    /// Converts the payload to the JSON stored in the device's document.
+    ///
+    /// JSON is parsed as is, CBOR goes through
+    /// [`convert_cbor_value_to_json`](crate::helper::convert_cbor_value_to_json)
+    /// and SenML CBOR packs become their SenML JSON form. Text becomes a
+    /// string; other formats, and payloads that fail to decode, become a
+    /// base64 string of the bytes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use coapum::{ContentFormat, observer::EncodedPayload};
+    /// use serde_json::json;
+    ///
+    /// // {"fw": h'cafe'}
+    /// let bytes = vec![0xa1, 0x62, b'f', b'w', 0x42, 0xca, 0xfe];
+    /// let payload = EncodedPayload::new(ContentFormat::ApplicationCBOR, bytes);
+    /// assert_eq!(payload.to_json(), json!({"fw": "yv4="}));
+    /// ```
+    pub fn to_json(&self) -> Value {
+        let converted = match self.content_format {
+            ContentFormat::ApplicationJSON | ContentFormat::ApplicationSenmlJSON => {
+                serde_json::from_slice(&self.bytes).ok()
+            }
+            ContentFormat::ApplicationSenmlCBOR => coapum_senml::SenMLPack::from_cbor(&self.bytes)
+                .ok()
+                .and_then(|pack| serde_json::to_value(pack).ok()),
+            ContentFormat::ApplicationCBOR => ciborium::from_reader(self.bytes.as_slice())
+                .ok()
+                .map(crate::helper::convert_cbor_value_to_json),
+            ContentFormat::TextPlain => std::str::from_utf8(&self.bytes)
+                .ok()
+                .map(|text| Value::String(text.to_string())),
+            _ => None,
+        };
+        converted.unwrap_or_else(|| {
+            crate::helper::convert_cbor_value_to_json(ciborium::Value::Bytes(self.bytes.clone()))
+        })
+    }
+
+    /// Decodes the payload into `T`.
+    ///
+    /// JSON and CBOR are deserialized directly, so CBOR byte strings stay
+    /// byte strings. Other formats, including SenML CBOR with its integer
+    /// labels, are deserialized from [`to_json`](Self::to_json).
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, String> {
+        match self.content_format {
+            ContentFormat::ApplicationJSON | ContentFormat::ApplicationSenmlJSON => {
+                serde_json::from_slice(&self.bytes).map_err(|e| e.to_string())
+            }
+            ContentFormat::ApplicationCBOR => {
+                ciborium::from_reader(self.bytes.as_slice()).map_err(|e| e.to_string())
+            }
+            _ => serde_json::from_value(self.to_json()).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Returns true while `document` still holds this payload's converted
+    /// value at `pointer`, i.e. no later write replaced it
+    pub(crate) fn is_current(&self, document: &Value, pointer: &str) -> bool {
+        document.pointer(pointer) == Some(&self.to_json())
+    }
+
+    /// Encodes the payload for storage: the content format number as two
+    /// big-endian bytes, followed by the payload bytes
+    #[cfg_attr(
+        not(any(feature = "sled-observer", feature = "redb-observer")),
+        allow(dead_code)
+    )]
+    pub(crate) fn to_stored(&self) -> Vec<u8> {
+        let format = u16::try_from(usize::from(self.content_format)).unwrap_or(u16::MAX);
+        let mut stored = Vec::with_capacity(2 + self.bytes.len());
+        stored.extend_from_slice(&format.to_be_bytes());
+        stored.extend_from_slice(&self.bytes);
+        stored
+    }
+
+    /// Decodes a payload stored with [`to_stored`](Self::to_stored)
+    #[cfg_attr(
+        not(any(feature = "sled-observer", feature = "redb-observer")),
+        allow(dead_code)
+    )]
+    pub(crate) fn from_stored(stored: &[u8]) -> Option<Self> {
+        let (format, bytes) = stored.split_first_chunk::<2>()?;
+        let content_format =
+            ContentFormat::try_from(usize::from(u16::from_be_bytes(*format))).ok()?;
+        Some(Self::new(content_format, bytes.to_vec()))
+    }
+}
+
+/// Outcome of [`Observer::write_if_version`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionedWrite {
+    /// The value was stored; the document now has this version
+    Written(u64),
+    /// The document had another version, or the backend tracks none, and
+    /// was left unchanged
+    Conflict { current: Option<u64> },
 }
 
 /// A struct representing an observer request.
@@ -23,7 +156,26 @@ pub struct ObserverValue {
 pub struct ObserverRequest<E> {
     pub value: Value,
     pub path: String,
+    pub encoded: Option<EncodedPayload>,
+    pub changed: Vec<String>,
     pub source: E,
+    /// PSK identity of the connection that registered the observation
+    pub identity: String,
+    /// Token of the registering GET request
+    pub token: Vec<u8>,
+    pub(crate) extensions: Extensions,
+}
+
+impl<E> ObserverRequest<E> {
+    /// Returns the request extensions, passed on to the notify handler.
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Returns the request extensions mutably.
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
 }
 
 impl ObserverValue {
@@ -32,11 +184,25 @@ impl ObserverValue {
         ObserverRequest {
             value: self.value,
             path: self.path,
+            encoded: self.encoded,
+            changed: self.changed,
             source,
+            identity: String::new(),
+            token: Vec::new(),
+            extensions: Extensions::new(),
         }
     }
 }
 
+/// An observation registered with an observer, see [`Observer::list`]
+#[derive(Debug, Clone)]
+pub struct Registration {
+    pub device_id: String,
+    pub path: String,
+    /// Metadata of the observation, shared with its connection
+    pub metadata: metadata::ObservationMetadata,
+}
+
 /// A trait for pluggable device state storage backends.
 ///
 /// Implement this trait to provide a custom storage backend (e.g., PostgreSQL,
@@ -51,14 +217,51 @@ pub trait Observer: Clone + Debug + Send + Sync + 'static {
         &mut self,
         device_id: &str,
         path: &str,
-        sender: Arc<Sender<ObserverValue>>,
+        sender: ObserverSender,
     ) -> Result<(), Self::Error>;
+    /// Registers a path with the observer along with the metadata of the
+    /// observation, which [`list`](Self::list) returns.
+    ///
+    /// The default registers the path without its metadata; backends built
+    /// on [`ObserverChannels`] should override it.
+    async fn register_with_metadata(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        sender: ObserverSender,
+        _metadata: metadata::ObservationMetadata,
+    ) -> Result<(), Self::Error> {
+        self.register(device_id, path, sender).await
+    }
     /// Unregisters a path from the observer.
     async fn unregister(&mut self, device_id: &str, path: &str) -> Result<(), Self::Error>;
     /// Unregisters all paths from the observer.
     async fn unregister_all(&mut self) -> Result<(), Self::Error>;
     /// Unregisters all paths for a specific device.
     async fn unregister_device(&mut self, device_id: &str) -> Result<(), Self::Error>;
+    /// Unregisters a path for the connection owning `sender` only.
+    ///
+    /// Other connections authenticated as the same device keep their
+    /// registration. The default unregisters the path for every connection;
+    /// backends built on [`ObserverChannels`] should override it.
+    async fn unregister_sender(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        _sender: &ObserverSender,
+    ) -> Result<(), Self::Error> {
+        self.unregister(device_id, path).await
+    }
+    /// Unregisters all paths of the connection owning `sender`.
+    ///
+    /// The default unregisters the whole device, see [`unregister_sender`](Self::unregister_sender).
+    async fn unregister_connection(
+        &mut self,
+        device_id: &str,
+        _sender: &ObserverSender,
+    ) -> Result<(), Self::Error> {
+        self.unregister_device(device_id).await
+    }
     /// Writes a value to a path.
     async fn write(
         &mut self,
@@ -66,19 +269,181 @@ pub trait Observer: Clone + Debug + Send + Sync + 'static {
         path: &str,
         payload: &Value,
     ) -> Result<(), Self::Error>;
-    /// Reads a value from a path.
+    /// Writes several paths for one device as a single update.
+    ///
+    /// Observers should see at most one notification per registered path for
+    /// the whole batch. The default implementation writes each path in turn;
+    /// backends that can apply the merge atomically should override it.
+    async fn write_batch(
+        &mut self,
+        device_id: &str,
+        updates: Vec<(String, Value)>,
+    ) -> Result<(), Self::Error> {
+        for (path, payload) in &updates {
+            self.write(device_id, path, payload).await?;
+        }
+        Ok(())
+    }
+    /// Writes a payload in the format the device sent it.
+    ///
+    /// The payload is converted with [`EncodedPayload::to_json`] and merged
+    /// into the device's document like [`write`](Self::write), so readers and
+    /// observers of parent paths see JSON. Backends that keep raw payloads
+    /// also store the original bytes for [`read_raw`](Self::read_raw) and send
+    /// them unchanged to the observers of exactly `path`. The default only
+    /// writes the converted value.
+    async fn write_raw(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: EncodedPayload,
+    ) -> Result<(), Self::Error> {
+        self.write(device_id, path, &payload.to_json()).await
+    }
+    /// Applies a JSON merge patch (RFC 7396) at a path.
+    ///
+    /// Members of the patch set to `null` are removed from the document;
+    /// everything else is merged as in [`write`](Self::write), and observers
+    /// are notified of the result. The default writes the patch with `write`,
+    /// which stores `null` members instead of removing them; the built-in
+    /// backends override it.
+    async fn patch(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        patch: &Value,
+    ) -> Result<(), Self::Error> {
+        self.write(device_id, path, patch).await
+    }
+    /// Returns the version of the device's document.
+    ///
+    /// Backends that track versions count every change of a document,
+    /// starting from 0 for a device that was never written, so a client
+    /// can detect that the document changed since it last read it. Returns
+    /// `None` if the backend tracks no versions, the default.
+    async fn version(&mut self, _device_id: &str) -> Result<Option<u64>, Self::Error> {
+        Ok(None)
+    }
+    /// Writes a value like [`write`](Self::write) if the device's document
+    /// is still at version `expected`.
+    ///
+    /// Otherwise nothing is written and the current version is returned in
+    /// [`VersionedWrite::Conflict`]. The default compares
+    /// [`version`](Self::version) and writes in two steps, which is only
+    /// safe for backends that aren't shared between tasks; backends without
+    /// versions always conflict.
+    async fn write_if_version(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: &Value,
+        expected: u64,
+    ) -> Result<VersionedWrite, Self::Error> {
+        match self.version(device_id).await? {
+            Some(current) if current == expected => {
+                self.write(device_id, path, payload).await?;
+                let version = self.version(device_id).await?.unwrap_or(current + 1);
+                Ok(VersionedWrite::Written(version))
+            }
+            current => Ok(VersionedWrite::Conflict { current }),
+        }
+    }
+    /// Reads the value at a path in the device's document.
+    ///
+    /// The path is addressed the same way as in [`write`](Self::write) and
+    /// resolved with [`path_to_pointer`], so reading back a written path
+    /// returns exactly the written value. Returns `None` if nothing is stored
+    /// at the path.
     async fn read(&mut self, device_id: &str, path: &str) -> Result<Option<Value>, Self::Error>;
+    /// Reads the retained history of values written to a path.
+    ///
+    /// Only backends with history enabled keep any; the default returns an
+    /// empty list. See [`history`] for details.
+    async fn read_history(
+        &mut self,
+        _device_id: &str,
+        _path: &str,
+        _range: HistoryRange,
+    ) -> Result<Vec<HistoryEntry>, Self::Error> {
+        Ok(Vec::new())
+    }
+    /// Reads the payload last written to exactly `path` with
+    /// [`write_raw`](Self::write_raw), as the device sent it.
+    ///
+    /// Returns `None` if no raw payload was written there, or if a later
+    /// write, eviction or merge changed the value at the path. The default
+    /// returns `None`.
+    async fn read_raw(
+        &mut self,
+        _device_id: &str,
+        _path: &str,
+    ) -> Result<Option<EncodedPayload>, Self::Error> {
+        Ok(None)
+    }
+    /// Reads the device's whole document.
+    async fn read_root(&mut self, device_id: &str) -> Result<Option<Value>, Self::Error> {
+        self.read(device_id, "/").await
+    }
     /// Clears all values from the observer.
     async fn clear(&mut self, device_id: &str) -> Result<(), Self::Error>;
 
+    /// Sends a pre-encoded payload to the observers of exactly `path`
+    /// without storing anything.
+    ///
+    /// Used for notify-only resources whose payloads are not JSON documents,
+    /// such as CBOR or SenML. The default does nothing; backends built on
+    /// [`ObserverChannels`] should override it.
+    async fn notify_encoded(
+        &mut self,
+        _device_id: &str,
+        _path: &str,
+        _payload: EncodedPayload,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
     /// Returns the number of observer registrations for a device.
     /// Used by the server to enforce per-device observer limits.
     /// Default returns 0 (no limit enforcement).
     async fn observer_count(&self, _device_id: &str) -> usize {
         0
     }
+
+    /// Returns the registrations of a device, sorted by path, one per
+    /// observing connection.
+    /// Used by admin tooling to show who is observing what.
+    /// Default returns no registrations.
+    async fn list(&self, _device_id: &str) -> Vec<Registration> {
+        Vec::new()
+    }
+
+    /// Returns the registrations of all devices, sorted by device ID and path.
+    /// Default returns no registrations.
+    async fn list_all(&self) -> Vec<Registration> {
+        Vec::new()
+    }
+
+    /// Returns the devices with an observer registered for exactly `path`,
+    /// sorted. Used to notify every device observing a resource, see
+    /// [`NotificationTrigger::observers`](crate::router::NotificationTrigger::observers).
+    /// Default returns no devices.
+    async fn observing_devices(&self, _path: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Checks that the backend is reachable, for the
+    /// [`health`](crate::health) resources.
+    /// Default reads the document of [`PING_DEVICE`], which no device can
+    /// authenticate as.
+    async fn ping(&mut self) -> Result<(), Self::Error> {
+        self.read_root(PING_DEVICE).await.map(|_| ())
+    }
 }
 
+/// Device ID read by the default [`Observer::ping`]. Device IDs can't
+/// contain spaces, so no device has this document.
+pub const PING_DEVICE: &str = "coapum ping";
+
 #[async_trait]
 impl Observer for () {
     type Error = ();
@@ -87,7 +452,7 @@ impl Observer for () {
         &mut self,
         _device_id: &str,
         _path: &str,
-        _sender: Arc<Sender<ObserverValue>>,
+        _sender: ObserverSender,
     ) -> Result<(), Self::Error> {
         Ok(())
     }
@@ -121,8 +486,10 @@ impl Observer for () {
 pub enum PathValidationError {
     /// Path contains traversal patterns (`..`, `./`, `\`)
     TraversalAttempt,
-    /// Path exceeds maximum depth (10 components)
+    /// Path has more components than allowed (10 by default)
     PathTooDeep,
+    /// Path is longer than allowed (256 bytes by default)
+    PathTooLong,
     /// Path contains non-ASCII or disallowed characters
     InvalidCharacters,
     /// Path is empty
@@ -133,7 +500,8 @@ impl std::fmt::Display for PathValidationError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             PathValidationError::TraversalAttempt => write!(f, "Path traversal attempt detected"),
-            PathValidationError::PathTooDeep => write!(f, "Path too deep (max 10 components)"),
+            PathValidationError::PathTooDeep => write!(f, "Path has too many components"),
+            PathValidationError::PathTooLong => write!(f, "Path is too long"),
             PathValidationError::InvalidCharacters => {
                 write!(f, "Path contains invalid characters")
             }
@@ -144,21 +512,148 @@ impl std::fmt::Display for PathValidationError {
 
 impl std::error::Error for PathValidationError {}
 
-/// Maximum allowed depth for observer paths.
-const MAX_PATH_DEPTH: usize = 10;
+/// Rules request and observer paths are held to
+///
+/// Paths are split into components at `/`. By default a path may have up to
+/// 10 components and 256 bytes, and components may only contain ASCII
+/// alphanumerics, `_` and `-`. Traversal patterns (`..`, `./`, `\`) are
+/// always rejected, whatever characters are allowed.
+///
+/// The server checks the path of every request against
+/// [`Config::path_policy`](crate::config::Config::path_policy) and answers
+/// 4.00 Bad Request to those that fail. Turning off
+/// [`Config::validate_request_paths`](crate::config::Config::validate_request_paths)
+/// limits the check to observe requests.
+///
+/// # Example
+///
+/// ```
+/// use coapum::observer::PathPolicy;
+///
+/// // Firmware images and UUID-with-colon device IDs
+/// let policy = PathPolicy::new().allow_chars(".~:").max_depth(16);
+/// assert_eq!(
+///     policy.validate("fw/app-1.2.bin").unwrap(),
+///     "/fw/app-1.2.bin"
+/// );
+/// assert!(policy.validate("devices/urn:uuid:4a1f/state").is_ok());
+/// assert!(policy.validate("fw/../secrets").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathPolicy {
+    pub(crate) max_depth: usize,
+    pub(crate) max_length: usize,
+    extra_chars: Vec<char>,
+}
+
+impl PathPolicy {
+    /// The default rules
+    pub fn new() -> Self {
+        Self {
+            max_depth: 10,
+            max_length: 256,
+            extra_chars: Vec::new(),
+        }
+    }
+
+    /// Allow up to `depth` components (default: 10)
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Allow paths of up to `length` bytes (default: 256)
+    pub fn max_length(mut self, length: usize) -> Self {
+        self.max_length = length;
+        self
+    }
+
+    /// Also allow the ASCII characters of `chars` in components, e.g. `.`,
+    /// `~` or `:`
+    ///
+    /// # Panics
+    ///
+    /// If `chars` contains `/`, `\` or a non-ASCII character.
+    pub fn allow_chars(mut self, chars: &str) -> Self {
+        for c in chars.chars() {
+            assert!(
+                c.is_ascii_graphic() && c != '/' && c != '\\',
+                "cannot allow {:?} in path components",
+                c
+            );
+            if !self.extra_chars.contains(&c) {
+                self.extra_chars.push(c);
+            }
+        }
+        self
+    }
+
+    /// Validate `path` and normalize it to have a leading `/` and no empty
+    /// components
+    pub fn validate(&self, path: &str) -> Result<String, PathValidationError> {
+        if path.is_empty() {
+            return Err(PathValidationError::EmptyPath);
+        }
+
+        if path.len() > self.max_length {
+            return Err(PathValidationError::PathTooLong);
+        }
+
+        // Reject paths containing dangerous patterns
+        if path.contains("..") || path.contains("./") || path.contains('\\') {
+            return Err(PathValidationError::TraversalAttempt);
+        }
+
+        // Normalize and validate path components
+        let components: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        if components.len() > self.max_depth {
+            return Err(PathValidationError::PathTooDeep);
+        }
+
+        // Only a trailing `.` component escapes the checks above
+        if components.contains(&".") {
+            return Err(PathValidationError::TraversalAttempt);
+        }
+
+        // Validate each path component for safe characters only
+        for component in &components {
+            if !component.chars().all(|c| {
+                c.is_ascii_alphanumeric() || c == '_' || c == '-' || self.extra_chars.contains(&c)
+            }) {
+                return Err(PathValidationError::InvalidCharacters);
+            }
+        }
+
+        // Return normalized path
+        if components.is_empty() {
+            Ok("/".to_string())
+        } else {
+            Ok(format!("/{}", components.join("/")))
+        }
+    }
+}
+
+impl Default for PathPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 /// Validate and normalize an observer path to prevent injection attacks.
 ///
-/// This function is called by the server before registering observers.
-/// Observer backend implementations do **not** need to perform their own
-/// path validation — paths passed to [`Observer::register`] and
-/// [`Observer::write`] have already been validated.
+/// This function checks `path` against the default [`PathPolicy`]; the
+/// server uses the policy of its [`Config`](crate::config::Config) before
+/// registering observers. Observer backend implementations do **not** need
+/// to perform their own path validation — paths passed to
+/// [`Observer::register`] and [`Observer::write`] have already been
+/// validated.
 ///
 /// # Rules
 ///
 /// - Rejects empty paths
 /// - Rejects traversal patterns (`..`, `./`, `\`)
-/// - Limits path depth to 10 components
+/// - Limits path depth to 10 components and length to 256 bytes
 /// - Only allows ASCII alphanumeric characters, `_`, and `-` in path components
 /// - Returns a normalized path with a leading `/`
 ///
@@ -172,38 +667,7 @@ const MAX_PATH_DEPTH: usize = 10;
 /// assert!(validate_observer_path("").is_err());
 /// ```
 pub fn validate_observer_path(path: &str) -> Result<String, PathValidationError> {
-    if path.is_empty() {
-        return Err(PathValidationError::EmptyPath);
-    }
-
-    // Reject paths containing dangerous patterns
-    if path.contains("..") || path.contains("./") || path.contains('\\') {
-        return Err(PathValidationError::TraversalAttempt);
-    }
-
-    // Normalize and validate path components
-    let components: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
-
-    if components.len() > MAX_PATH_DEPTH {
-        return Err(PathValidationError::PathTooDeep);
-    }
-
-    // Validate each path component for safe characters only
-    for component in &components {
-        if !component
-            .chars()
-            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
-        {
-            return Err(PathValidationError::InvalidCharacters);
-        }
-    }
-
-    // Return normalized path
-    if components.is_empty() {
-        Ok("/".to_string())
-    } else {
-        Ok(format!("/{}", components.join("/")))
-    }
+    PathPolicy::new().validate(path)
 }
 
 /// Converts a path and value to a JSON object.
@@ -230,6 +694,82 @@ pub fn path_to_json(path: &str, value: &Value) -> Value {
     current_value
 }
 
+/// Converts an observer path to a JSON pointer into the device document.
+///
+/// Empty components are skipped as in [`path_to_json`], so `""` and `"/"`
+/// address the whole document and `"sensors/temp/"` becomes `"/sensors/temp"`.
+///
+/// # Example
+///
+/// ```
+/// use coapum::observer::path_to_pointer;
+///
+/// assert_eq!(path_to_pointer("sensors/temp/"), "/sensors/temp");
+/// assert_eq!(path_to_pointer("/"), "");
+/// ```
+pub fn path_to_pointer(path: &str) -> String {
+    path.split('/')
+        .filter(|s| !s.is_empty())
+        .fold(String::new(), |mut pointer, component| {
+            pointer.push('/');
+            pointer.push_str(component);
+            pointer
+        })
+}
+
+/// Lists the paths at or below `path` where two documents differ.
+///
+/// Objects are compared key by key and only the shallowest differing paths
+/// are returned, so observers of a prefix such as `/shadow` learn which
+/// sub-paths a write touched. Any other difference, including a value
+/// changing type or being removed, is reported at the node itself.
+///
+/// # Example
+///
+/// ```
+/// use coapum::observer::changed_paths;
+/// use serde_json::json;
+///
+/// let old = json!({"shadow": {"led": "off", "fan": {"rpm": 100}}});
+/// let new = json!({"shadow": {"led": "on", "fan": {"rpm": 200}}});
+/// assert_eq!(
+///     changed_paths("/shadow", &old, &new),
+///     vec!["/shadow/fan/rpm", "/shadow/led"]
+/// );
+/// ```
+pub fn changed_paths(path: &str, old: &Value, new: &Value) -> Vec<String> {
+    fn collect(path: String, old: Option<&Value>, new: Option<&Value>, out: &mut Vec<String>) {
+        match (old, new) {
+            (Some(Value::Object(old)), Some(Value::Object(new))) => {
+                let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+                keys.sort();
+                keys.dedup();
+                for key in keys {
+                    collect(format!("{}/{}", path, key), old.get(key), new.get(key), out);
+                }
+            }
+            (old, new) if old != new => {
+                out.push(if path.is_empty() {
+                    "/".to_string()
+                } else {
+                    path
+                });
+            }
+            _ => {}
+        }
+    }
+
+    let pointer = path_to_pointer(path);
+    let mut out = Vec::new();
+    collect(
+        pointer.clone(),
+        old.pointer(&pointer),
+        new.pointer(&pointer),
+        &mut out,
+    );
+    out
+}
+
 /// Merges two JSON objects.
 ///
 /// # Arguments
@@ -252,22 +792,63 @@ pub fn merge_json(a: &mut Value, b: &Value) {
     }
 }
 
+/// Applies a JSON merge patch (RFC 7396) to a document.
+///
+/// Unlike [`merge_json`], members set to `null` in the patch are removed
+/// from the target instead of being stored.
+///
+/// # Example
+///
+/// ```
+/// use coapum::observer::merge_patch;
+/// use serde_json::json;
+///
+/// let mut document = json!({"led": "on", "fan": {"rpm": 100, "mode": "auto"}});
+/// merge_patch(&mut document, &json!({"led": null, "fan": {"rpm": 200}}));
+/// assert_eq!(document, json!({"fan": {"rpm": 200, "mode": "auto"}}));
+/// ```
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
 // Type aliases for observer channel management.
-/// Sender wrapped in Arc for shared ownership across tasks.
-pub type ObserverSender = Arc<Sender<ObserverValue>>;
-/// Maps observer path → sender channel.
-pub type PathChannels = HashMap<String, ObserverSender>;
+/// Notification queue sender wrapped in Arc for shared ownership across tasks.
+pub type ObserverSender = Arc<queue::NotificationSender>;
+/// The channel of one observing connection, with the metadata of its observation.
+#[derive(Debug, Clone)]
+pub struct ObserverChannel {
+    pub sender: ObserverSender,
+    pub metadata: metadata::ObservationMetadata,
+}
+/// Maps observer path → sender channels, one per observing connection.
+pub type PathChannels = HashMap<String, Vec<ObserverChannel>>;
 /// Maps device ID → path channels.
 pub type DeviceChannels = HashMap<String, PathChannels>;
 
-/// Default notification send timeout.
-const DEFAULT_NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(1);
-
 /// Shared observer channel management for register/unregister/notify operations.
 ///
 /// This struct encapsulates the common logic shared across all observer backends:
 /// channel registration, unregistration, and notification dispatch with value diffing.
 ///
+/// Registrations are scoped to the connection that made them, identified by
+/// its sender: a second connection with the same identity observing the same
+/// path adds its own channel instead of replacing the first one.
+///
 /// Backend implementations should embed this struct and delegate channel operations
 /// to it, only handling their own persistence logic.
 ///
@@ -285,7 +866,6 @@ const DEFAULT_NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(1);
 #[derive(Clone, Debug)]
 pub struct ObserverChannels {
     channels: Arc<RwLock<DeviceChannels>>,
-    notification_timeout: Duration,
 }
 
 impl Default for ObserverChannels {
@@ -295,29 +875,42 @@ impl Default for ObserverChannels {
 }
 
 impl ObserverChannels {
-    /// Create a new channel manager with the default notification timeout (1 second).
+    /// Create a new channel manager.
     pub fn new() -> Self {
         Self {
             channels: Arc::new(RwLock::new(HashMap::new())),
-            notification_timeout: DEFAULT_NOTIFICATION_TIMEOUT,
         }
     }
 
-    /// Create a new channel manager with a custom notification timeout.
-    pub fn with_timeout(timeout: Duration) -> Self {
-        Self {
-            channels: Arc::new(RwLock::new(HashMap::new())),
-            notification_timeout: timeout,
-        }
+    /// Register an observer channel for a device/path pair.
+    ///
+    /// Registering the same sender again for a path is a no-op.
+    pub async fn register(&self, device_id: &str, path: &str, sender: ObserverSender) {
+        self.register_with_metadata(device_id, path, sender, Default::default())
+            .await
     }
 
-    /// Register an observer channel for a device/path pair.
-    pub async fn register(&self, device_id: &str, path: &str, sender: Arc<Sender<ObserverValue>>) {
+    /// Register an observer channel for a device/path pair along with the
+    /// metadata of the observation.
+    ///
+    /// Registering the same sender again for a path replaces its metadata.
+    pub async fn register_with_metadata(
+        &self,
+        device_id: &str,
+        path: &str,
+        sender: ObserverSender,
+        metadata: metadata::ObservationMetadata,
+    ) {
         let mut channels = self.channels.write().await;
-        channels
+        let senders = channels
             .entry(device_id.to_string())
             .or_default()
-            .insert(path.to_string(), sender);
+            .entry(path.to_string())
+            .or_default();
+        match senders.iter_mut().find(|c| Arc::ptr_eq(&c.sender, &sender)) {
+            Some(channel) => channel.metadata = metadata,
+            None => senders.push(ObserverChannel { sender, metadata }),
+        }
 
         tracing::debug!(
             "Registered observer for device '{}' at path '{}'",
@@ -339,6 +932,46 @@ impl ObserverChannels {
         channels.is_empty()
     }
 
+    /// Unregister one connection's observer for a device/path pair, leaving
+    /// other connections observing the same path registered.
+    /// Returns `true` if all observers for all devices are now empty.
+    pub async fn unregister_sender(
+        &self,
+        device_id: &str,
+        path: &str,
+        sender: &ObserverSender,
+    ) -> bool {
+        let mut channels = self.channels.write().await;
+        if let Some(device_channels) = channels.get_mut(device_id) {
+            if let Some(senders) = device_channels.get_mut(path) {
+                senders.retain(|c| !Arc::ptr_eq(&c.sender, sender));
+                if senders.is_empty() {
+                    device_channels.remove(path);
+                }
+            }
+            if device_channels.is_empty() {
+                channels.remove(device_id);
+            }
+        }
+        channels.is_empty()
+    }
+
+    /// Unregister every observer a connection holds for a device.
+    /// Returns `true` if all observers for all devices are now empty.
+    pub async fn unregister_connection(&self, device_id: &str, sender: &ObserverSender) -> bool {
+        let mut channels = self.channels.write().await;
+        if let Some(device_channels) = channels.get_mut(device_id) {
+            device_channels.retain(|_, senders| {
+                senders.retain(|c| !Arc::ptr_eq(&c.sender, sender));
+                !senders.is_empty()
+            });
+            if device_channels.is_empty() {
+                channels.remove(device_id);
+            }
+        }
+        channels.is_empty()
+    }
+
     /// Unregister all observers across all devices.
     pub async fn unregister_all(&self) {
         self.channels.write().await.clear();
@@ -363,16 +996,89 @@ impl ObserverChannels {
             .read()
             .await
             .get(device_id)
-            .map_or(0, |c| c.len())
+            .map_or(0, |c| c.values().map(Vec::len).sum())
+    }
+
+    /// List the registrations of a device, sorted by path, one per
+    /// observing connection.
+    pub async fn list(&self, device_id: &str) -> Vec<Registration> {
+        let channels = self.channels.read().await;
+        let mut registrations: Vec<_> = channels
+            .get(device_id)
+            .into_iter()
+            .flat_map(|paths| registrations(device_id, paths))
+            .collect();
+        registrations.sort_by(|a, b| a.path.cmp(&b.path));
+        registrations
+    }
+
+    /// List the registrations of all devices, sorted by device ID and path.
+    pub async fn list_all(&self) -> Vec<Registration> {
+        let channels = self.channels.read().await;
+        let mut registrations: Vec<_> = channels
+            .iter()
+            .flat_map(|(device_id, paths)| registrations(device_id, paths))
+            .collect();
+        registrations.sort_by(|a, b| (&a.device_id, &a.path).cmp(&(&b.device_id, &b.path)));
+        registrations
+    }
+
+    /// Get the devices with an observer registered for exactly `path`, sorted.
+    pub async fn observing_devices(&self, path: &str) -> Vec<String> {
+        let mut devices: Vec<String> = self
+            .channels
+            .read()
+            .await
+            .iter()
+            .filter(|(_, paths)| paths.contains_key(path))
+            .map(|(device_id, _)| device_id.clone())
+            .collect();
+        devices.sort();
+        devices
     }
 
     /// Notify observers of value changes for a device.
     ///
     /// Compares `current_value` (before write) with `new_value` (after write)
     /// at each registered observer path. Only sends notifications when values
-    /// actually changed. Uses a configurable timeout to prevent slow clients
-    /// from blocking other notifications.
+    /// actually changed. Sends never wait, so slow clients can't hold up
+    /// notifications to other observers.
     pub async fn notify(&self, device_id: &str, current_value: &Value, new_value: &Value) {
+        self.notify_changes(device_id, current_value, new_value, None)
+            .instrument(tracing::debug_span!("observer.notify", device = %device_id))
+            .await;
+    }
+
+    /// Notify observers of value changes written with [`Observer::write_raw`].
+    ///
+    /// Works like [`notify`](Self::notify), except that observers of exactly
+    /// `path` are sent `payload` unchanged instead of the converted JSON.
+    pub async fn notify_raw(
+        &self,
+        device_id: &str,
+        current_value: &Value,
+        new_value: &Value,
+        path: &str,
+        payload: &EncodedPayload,
+    ) {
+        let pointer = path_to_pointer(path);
+        self.notify_changes(
+            device_id,
+            current_value,
+            new_value,
+            Some((&pointer, payload)),
+        )
+        .instrument(tracing::debug_span!("observer.notify", device = %device_id))
+        .await;
+    }
+
+    async fn notify_changes(
+        &self,
+        device_id: &str,
+        current_value: &Value,
+        new_value: &Value,
+        raw: Option<(&str, &EncodedPayload)>,
+    ) {
         let channels = self.channels.read().await;
 
         let device_channels = match channels.get(device_id) {
@@ -389,8 +1095,9 @@ impl ObserverChannels {
             device_channels.len()
         );
 
-        for (obs_path, sender) in device_channels.iter() {
-            let json_pointer = normalize_json_pointer(obs_path);
+        let mut dispatch = Dispatch::default();
+        for (obs_path, senders) in device_channels.iter() {
+            let json_pointer = path_to_pointer(obs_path);
             let current_at_path = current_value.pointer(&json_pointer);
             let incoming_at_path = new_value.pointer(&json_pointer);
 
@@ -406,51 +1113,274 @@ impl ObserverChannels {
                     None => Value::Null,
                 };
 
+                let encoded = raw
+                    .filter(|(pointer, _)| *pointer == json_pointer)
+                    .map(|(_, payload)| payload.clone());
+
                 let notification = ObserverValue {
                     path: obs_path.clone(),
                     value: notification_value,
+                    encoded,
+                    changed: changed_paths(obs_path, current_value, new_value),
                 };
 
-                match tokio::time::timeout(self.notification_timeout, sender.send(notification))
-                    .await
-                {
-                    Ok(Ok(())) => {}
-                    Ok(Err(e)) => {
-                        tracing::warn!(
-                            "Failed to send observer notification for device {} path {}: {}",
-                            device_id,
-                            obs_path,
-                            e
-                        );
-                    }
-                    Err(_) => {
-                        tracing::warn!(
-                            "Notification timeout for device {} path {} ({}ms)",
-                            device_id,
-                            obs_path,
-                            self.notification_timeout.as_millis()
-                        );
-                    }
+                for channel in senders {
+                    let result = self.send(device_id, &channel.sender, notification.clone());
+                    dispatch.record(result);
                 }
             }
         }
+        dispatch.trace(device_id);
+    }
+
+    /// Send a pre-encoded payload to the observers registered on exactly `path`.
+    pub async fn notify_encoded(&self, device_id: &str, path: &str, payload: EncodedPayload) {
+        let channels = self.channels.read().await;
+        let Some(device_channels) = channels.get(device_id) else {
+            tracing::debug!("No observers found for device '{}'", device_id);
+            return;
+        };
+
+        let pointer = path_to_pointer(path);
+        let mut dispatch = Dispatch::default();
+        for (obs_path, senders) in device_channels.iter() {
+            if path_to_pointer(obs_path) != pointer {
+                continue;
+            }
+            let notification = ObserverValue {
+                path: obs_path.clone(),
+                value: Value::Null,
+                encoded: Some(payload.clone()),
+                changed: vec![pointer.clone()],
+            };
+            for channel in senders {
+                let result = self.send(device_id, &channel.sender, notification.clone());
+                dispatch.record(result);
+            }
+        }
+        dispatch.trace(device_id);
+    }
+
+    /// Queue a notification for one connection.
+    ///
+    /// Never waits: a full queue applies the connection's
+    /// [`OverflowPolicy`](queue::OverflowPolicy) instead.
+    fn send(
+        &self,
+        device_id: &str,
+        sender: &ObserverSender,
+        notification: ObserverValue,
+    ) -> Result<(), queue::SendError> {
+        let obs_path = notification.path.clone();
+        let result = sender.send(notification);
+        if let Err(e) = result {
+            tracing::warn!(
+                "Failed to send observer notification for device {} path {}: {}",
+                device_id,
+                obs_path,
+                e
+            );
+        }
+        result
     }
 }
 
-/// Normalizes a path to JSON pointer format by ensuring it starts with '/'.
-fn normalize_json_pointer(path: &str) -> String {
-    if path.starts_with('/') {
-        path.to_string()
-    } else if path.is_empty() {
-        "/".to_string()
-    } else {
-        format!("/{}", path)
+/// Notifications handed to connection queues by one write
+#[derive(Default)]
+struct Dispatch {
+    queued: usize,
+    dropped: usize,
+    closed: usize,
+}
+
+impl Dispatch {
+    fn record(&mut self, result: Result<(), queue::SendError>) {
+        match result {
+            Ok(()) => self.queued += 1,
+            Err(queue::SendError::Full) => self.dropped += 1,
+            Err(queue::SendError::Closed) => self.closed += 1,
+        }
     }
+
+    fn trace(&self, device_id: &str) {
+        tracing::debug!(
+            device = %device_id,
+            queued = self.queued,
+            dropped = self.dropped,
+            closed = self.closed,
+            "observer.dispatched"
+        );
+    }
+}
+
+fn registrations<'a>(
+    device_id: &'a str,
+    paths: &'a PathChannels,
+) -> impl Iterator<Item = Registration> + 'a {
+    paths.iter().flat_map(move |(path, senders)| {
+        senders.iter().map(move |channel| Registration {
+            device_id: device_id.to_string(),
+            path: path.clone(),
+            metadata: channel.metadata.clone(),
+        })
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use queue::OverflowPolicy;
+
+    #[tokio::test]
+    async fn test_channels_scoped_to_connection() {
+        use serde_json::json;
+
+        let channels = ObserverChannels::new();
+        let (tx_a, mut rx_a) = queue::notification_channel(4, OverflowPolicy::default());
+        let (tx_b, mut rx_b) = queue::notification_channel(4, OverflowPolicy::default());
+        let (tx_a, tx_b) = (Arc::new(tx_a), Arc::new(tx_b));
+
+        // Same identity, two connections; re-registering is a no-op
+        channels.register("dev", "/temp", tx_a.clone()).await;
+        channels.register("dev", "/temp", tx_a.clone()).await;
+        channels.register("dev", "/temp", tx_b.clone()).await;
+        assert_eq!(channels.device_observer_count("dev").await, 2);
+
+        channels
+            .notify("dev", &json!({}), &json!({"temp": 21}))
+            .await;
+        assert_eq!(rx_a.recv().await.unwrap().value, json!(21));
+        assert_eq!(rx_b.recv().await.unwrap().value, json!(21));
+
+        // The first connection closing leaves the second one registered
+        assert!(!channels.unregister_connection("dev", &tx_a).await);
+        assert_eq!(channels.device_observer_count("dev").await, 1);
+        assert!(channels.unregister_sender("dev", "/temp", &tx_b).await);
+    }
+
+    #[tokio::test]
+    async fn test_channels_list_registrations() {
+        let channels = ObserverChannels::new();
+        let (tx_a, _rx_a) = queue::notification_channel(4, OverflowPolicy::default());
+        let (tx_b, _rx_b) = queue::notification_channel(4, OverflowPolicy::default());
+        let (tx_a, tx_b) = (Arc::new(tx_a), Arc::new(tx_b));
+
+        let metadata = metadata::ObservationMetadata::new();
+        metadata.insert("pmin", "10");
+        channels
+            .register_with_metadata("dev-b", "/temp", tx_a.clone(), metadata)
+            .await;
+        channels.register("dev-b", "/humidity", tx_a.clone()).await;
+        channels.register("dev-a", "/temp", tx_b.clone()).await;
+
+        let listed: Vec<_> = channels
+            .list_all()
+            .await
+            .into_iter()
+            .map(|r| (r.device_id, r.path, r.metadata.get("pmin")))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("dev-a".to_string(), "/temp".to_string(), None),
+                ("dev-b".to_string(), "/humidity".to_string(), None),
+                (
+                    "dev-b".to_string(),
+                    "/temp".to_string(),
+                    Some("10".to_string())
+                ),
+            ]
+        );
+
+        channels.unregister_sender("dev-b", "/temp", &tx_a).await;
+        let paths: Vec<_> = channels
+            .list("dev-b")
+            .await
+            .into_iter()
+            .map(|r| r.path)
+            .collect();
+        assert_eq!(paths, ["/humidity"]);
+        assert!(channels.list("dev-c").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_notify_encoded_exact_path() {
+        let channels = ObserverChannels::new();
+        let (tx, mut rx) = queue::notification_channel(4, OverflowPolicy::default());
+        let tx = Arc::new(tx);
+        channels.register("dev", "/sensors", tx.clone()).await;
+        channels.register("dev", "/sensors/temp", tx).await;
+
+        let payload = EncodedPayload {
+            content_format: ContentFormat::ApplicationCBOR,
+            bytes: vec![0x18, 0x2a],
+        };
+        channels
+            .notify_encoded("dev", "sensors/temp", payload.clone())
+            .await;
+
+        let notification = rx.recv().await.unwrap();
+        assert_eq!(notification.path, "/sensors/temp");
+        assert_eq!(notification.encoded, Some(payload));
+        // Parents don't get binary payloads that can't be merged into their document
+        assert!(rx.try_recv().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_notify_prefix_changed_paths() {
+        use serde_json::json;
+
+        let channels = ObserverChannels::new();
+        let (tx, mut rx) = queue::notification_channel(4, OverflowPolicy::default());
+        channels.register("dev", "/shadow/", Arc::new(tx)).await;
+
+        let current = json!({"shadow": {"led": "off", "fan": {"rpm": 100}}});
+        let new = json!({"shadow": {"led": "off", "fan": {"rpm": 200, "mode": "auto"}}});
+        channels.notify("dev", &current, &new).await;
+
+        let notification = rx.recv().await.unwrap();
+        assert_eq!(notification.path, "/shadow/");
+        assert_eq!(notification.value, new["shadow"]);
+        assert_eq!(
+            notification.changed,
+            vec!["/shadow/fan/mode", "/shadow/fan/rpm"]
+        );
+
+        // Replacing the whole subtree with a scalar is reported at the root
+        assert_eq!(
+            changed_paths("/shadow", &current, &json!({"shadow": 1})),
+            vec!["/shadow"]
+        );
+        assert_eq!(changed_paths("/", &json!(1), &json!(2)), vec!["/"]);
+        assert!(changed_paths("/shadow", &current, &current).is_empty());
+    }
+
+    #[test]
+    fn test_encoded_payload_conversion() {
+        use serde_json::json;
+
+        let json = EncodedPayload::new(ContentFormat::ApplicationJSON, br#"{"a":1}"#.to_vec());
+        assert_eq!(json.to_json(), json!({"a": 1}));
+
+        let pack = coapum_senml::SenMLBuilder::new()
+            .add_value("temp", 21)
+            .build();
+        let senml =
+            EncodedPayload::new(ContentFormat::ApplicationSenmlCBOR, pack.to_cbor().unwrap());
+        assert_eq!(senml.to_json(), json!([{"n": "temp", "v": 21}]));
+        assert_eq!(senml.decode::<coapum_senml::SenMLPack>().unwrap(), pack);
+
+        let text = EncodedPayload::new(ContentFormat::TextPlain, b"on".to_vec());
+        assert_eq!(text.to_json(), json!("on"));
+
+        // Bytes that don't decode are kept as base64
+        let invalid = EncodedPayload::new(ContentFormat::ApplicationJSON, vec![0xff]);
+        assert_eq!(invalid.to_json(), json!("/w=="));
+
+        let stored = senml.to_stored();
+        assert_eq!(EncodedPayload::from_stored(&stored), Some(senml));
+        assert_eq!(EncodedPayload::from_stored(&[0x00]), None);
+    }
 
     #[test]
     fn test_validate_observer_path_valid() {
@@ -514,6 +1444,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_path_policy() {
+        let policy = PathPolicy::new()
+            .allow_chars(".~:")
+            .max_depth(3)
+            .max_length(32);
+        assert_eq!(
+            policy.validate("devices/urn:uuid:4a1f/~cfg.v2").unwrap(),
+            "/devices/urn:uuid:4a1f/~cfg.v2"
+        );
+        assert_eq!(
+            policy.validate("a/b/c/d").unwrap_err(),
+            PathValidationError::PathTooDeep
+        );
+        assert_eq!(
+            policy.validate(&"a".repeat(33)).unwrap_err(),
+            PathValidationError::PathTooLong
+        );
+
+        // Allowing `.` doesn't allow traversal
+        for path in ["a/../b", "./a", "a/."] {
+            assert_eq!(
+                policy.validate(path).unwrap_err(),
+                PathValidationError::TraversalAttempt
+            );
+        }
+        assert_eq!(
+            validate_observer_path("fw/app.bin").unwrap_err(),
+            PathValidationError::InvalidCharacters
+        );
+    }
+
     #[test]
     fn test_path_to_json() {
         let value = serde_json::json!({"test_key": "test_value"});
@@ -522,6 +1484,21 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_path_to_pointer() {
+        assert_eq!(path_to_pointer(""), "");
+        assert_eq!(path_to_pointer("/"), "");
+        assert_eq!(path_to_pointer("sensors"), "/sensors");
+        assert_eq!(path_to_pointer("/sensors/temp/"), "/sensors/temp");
+
+        let doc = path_to_json("/sensors/temp", &serde_json::json!(21));
+        assert_eq!(
+            doc.pointer(&path_to_pointer("sensors/temp")),
+            Some(&serde_json::json!(21))
+        );
+        assert_eq!(doc.pointer(&path_to_pointer("/")), Some(&doc));
+    }
+
     #[test]
     fn test_merge_json() {
         let mut a = serde_json::json!({"test_key": "test_value"});
@@ -0,0 +1,294 @@
+//! Size quotas and expiry for stored device documents.
+//!
+//! A [`StoragePolicy`] bounds how large a device's document may grow and lets
+//! selected paths expire, e.g. telemetry that is only interesting for a few
+//! minutes. The policy is enforced on every write: expired paths are removed
+//! first, then the size limit is checked. Removed paths change to `null` for
+//! anyone observing them, so observers are notified of evictions the same way
+//! as of regular writes.
+//!
+//! Write times are tracked in memory, so after a restart previously stored
+//! paths only expire once they are written again.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde_json::Value;
+
+use super::path_to_pointer;
+
+/// What to do when a write would make a document exceed its size limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowAction {
+    /// Reject the write with [`QuotaExceeded`]
+    #[default]
+    Reject,
+    /// Evict the least recently written paths until the document fits
+    EvictOldest,
+}
+
+/// Error returned when a write would exceed the document size limit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaExceeded {
+    /// Serialized size of the document after the write, in bytes
+    pub size: usize,
+    /// Configured limit, in bytes
+    pub limit: usize,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Document size {} bytes exceeds limit of {} bytes",
+            self.size, self.limit
+        )
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+/// Per-device size limits and path expiry for an observer backend
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use coapum::observer::memory::MemObserver;
+/// use coapum::observer::policy::{OverflowAction, StoragePolicy};
+///
+/// let policy = StoragePolicy::new()
+///     .max_document_size(16 * 1024)
+///     .overflow(OverflowAction::EvictOldest)
+///     .ttl("/telemetry", Duration::from_secs(300));
+///
+/// let observer = MemObserver::new().with_policy(policy);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StoragePolicy {
+    max_document_size: Option<usize>,
+    overflow: OverflowAction,
+    ttls: Vec<(String, Duration)>,
+    /// Last write time per device and tracked path (as JSON pointer)
+    written: Arc<Mutex<HashMap<String, HashMap<String, Instant>>>>,
+}
+
+/// Outcome of enforcing the policy on a document, applied with
+/// [`StoragePolicy::commit`] once the document has been stored.
+#[derive(Debug)]
+pub(crate) struct Enforcement {
+    evicted: Vec<String>,
+    timestamps: HashMap<String, Instant>,
+}
+
+impl StoragePolicy {
+    /// Create a policy without limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit each device document to `bytes` of serialized JSON
+    pub fn max_document_size(mut self, bytes: usize) -> Self {
+        self.max_document_size = Some(bytes);
+        self
+    }
+
+    /// Set what happens when a write exceeds the size limit (default: reject)
+    pub fn overflow(mut self, action: OverflowAction) -> Self {
+        self.overflow = action;
+        self
+    }
+
+    /// Expire values written at or below `path` after `ttl`
+    ///
+    /// When rules overlap, the most specific path wins.
+    pub fn ttl(mut self, path: &str, ttl: Duration) -> Self {
+        self.ttls.push((path_to_pointer(path), ttl));
+        self
+    }
+
+    /// The TTL that applies to a JSON pointer, if any
+    fn ttl_for(&self, pointer: &str) -> Option<Duration> {
+        self.ttls
+            .iter()
+            .filter(|(rule, _)| is_within(pointer, rule))
+            .max_by_key(|(rule, _)| rule.len())
+            .map(|(_, ttl)| *ttl)
+    }
+
+    /// Whether write times must be kept for a JSON pointer
+    fn tracks(&self, pointer: &str) -> bool {
+        self.ttl_for(pointer).is_some()
+            || (self.max_document_size.is_some() && self.overflow == OverflowAction::EvictOldest)
+    }
+
+    /// Apply the policy to `doc`, after `written` paths were merged into it.
+    ///
+    /// Nothing is recorded until [`commit`](Self::commit) is called, so a
+    /// rejected or retried write leaves the policy state untouched.
+    pub(crate) fn enforce(
+        &self,
+        device_id: &str,
+        doc: &mut Value,
+        written: &[String],
+    ) -> Result<Enforcement, QuotaExceeded> {
+        let now = Instant::now();
+        let written: Vec<String> = written.iter().map(|p| path_to_pointer(p)).collect();
+
+        let mut timestamps = self.lock().get(device_id).cloned().unwrap_or_default();
+        for pointer in &written {
+            if self.tracks(pointer) {
+                timestamps.insert(pointer.clone(), now);
+            }
+        }
+
+        let mut evicted = Vec::new();
+        timestamps.retain(|pointer, written_at| match self.ttl_for(pointer) {
+            Some(ttl) if now.duration_since(*written_at) >= ttl => {
+                remove_pointer(doc, pointer);
+                evicted.push(pointer.clone());
+                false
+            }
+            _ => true,
+        });
+
+        if let Some(limit) = self.max_document_size {
+            let mut size = encoded_size(doc);
+
+            if size > limit && self.overflow == OverflowAction::EvictOldest {
+                let mut oldest: Vec<(Instant, String)> = timestamps
+                    .iter()
+                    .filter(|(pointer, _)| !written.iter().any(|w| overlaps(pointer, w)))
+                    .map(|(pointer, written_at)| (*written_at, pointer.clone()))
+                    .collect();
+                oldest.sort();
+
+                for (_, pointer) in oldest {
+                    if size <= limit {
+                        break;
+                    }
+                    remove_pointer(doc, &pointer);
+                    timestamps.remove(&pointer);
+                    evicted.push(pointer);
+                    size = encoded_size(doc);
+                }
+            }
+
+            if size > limit {
+                return Err(QuotaExceeded { size, limit });
+            }
+        }
+
+        Ok(Enforcement {
+            evicted,
+            timestamps,
+        })
+    }
+
+    /// Record the outcome of [`enforce`](Self::enforce) after the document was stored
+    pub(crate) fn commit(&self, device_id: &str, enforcement: Enforcement) {
+        for pointer in &enforcement.evicted {
+            tracing::info!(device = %device_id, path = %pointer, "observer.evicted");
+        }
+
+        let mut written = self.lock();
+        if enforcement.timestamps.is_empty() {
+            written.remove(device_id);
+        } else {
+            written.insert(device_id.to_string(), enforcement.timestamps);
+        }
+    }
+
+    /// Forget all write times for a device, e.g. after it was cleared
+    pub(crate) fn forget(&self, device_id: &str) {
+        self.lock().remove(device_id);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, HashMap<String, Instant>>> {
+        self.written.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Whether `pointer` is `rule` or lies below it
+fn is_within(pointer: &str, rule: &str) -> bool {
+    pointer == rule
+        || pointer
+            .strip_prefix(rule)
+            .is_some_and(|r| r.starts_with('/'))
+}
+
+/// Whether one pointer contains the other
+fn overlaps(a: &str, b: &str) -> bool {
+    is_within(a, b) || is_within(b, a)
+}
+
+/// Remove the value at a JSON pointer, if present
+fn remove_pointer(doc: &mut Value, pointer: &str) {
+    let Some((parent, key)) = pointer.rsplit_once('/') else {
+        *doc = Value::Null;
+        return;
+    };
+    if let Some(Value::Object(map)) = doc.pointer_mut(parent) {
+        map.remove(key);
+    }
+}
+
+fn encoded_size(doc: &Value) -> usize {
+    serde_json::to_vec(doc).map_or(0, |bytes| bytes.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_size_limit_rejects() {
+        let policy = StoragePolicy::new().max_document_size(16);
+        let mut doc = json!({"payload": "far too long for the limit"});
+
+        let err = policy
+            .enforce("dev", &mut doc, &["/payload".to_string()])
+            .unwrap_err();
+        assert_eq!(err.limit, 16);
+        assert!(err.size > 16);
+    }
+
+    #[test]
+    fn test_evict_oldest_keeps_new_write() {
+        let policy = StoragePolicy::new()
+            .max_document_size(24)
+            .overflow(OverflowAction::EvictOldest);
+
+        let mut doc = json!({"a": "0123456789"});
+        let enforcement = policy
+            .enforce("dev", &mut doc, &["/a".to_string()])
+            .unwrap();
+        policy.commit("dev", enforcement);
+
+        doc["b"] = json!("0123456789");
+        let enforcement = policy
+            .enforce("dev", &mut doc, &["/b".to_string()])
+            .unwrap();
+        assert_eq!(enforcement.evicted, ["/a"]);
+        assert_eq!(doc, json!({"b": "0123456789"}));
+    }
+
+    #[test]
+    fn test_ttl_expires_paths() {
+        let policy = StoragePolicy::new()
+            .ttl("/telemetry", Duration::ZERO)
+            .ttl("/telemetry/keep", Duration::from_secs(3600));
+
+        let mut doc = json!({"telemetry": {"temp": 21, "keep": 1}, "config": 5});
+        let written = ["/telemetry/temp".to_string(), "/telemetry/keep".to_string()];
+        let enforcement = policy.enforce("dev", &mut doc, &written).unwrap();
+
+        assert_eq!(enforcement.evicted, ["/telemetry/temp"]);
+        assert_eq!(doc, json!({"telemetry": {"keep": 1}, "config": 5}));
+    }
+}
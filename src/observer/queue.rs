@@ -0,0 +1,488 @@
+//! Per-connection notification queues
+//!
+//! Observers hand notifications to a connection through a bounded queue
+//! created with [`notification_channel`]. Writers never wait for a slow
+//! connection: when its queue is full, the queue's [`OverflowPolicy`] decides
+//! which notification is lost, and the loss is counted.
+//!
+//! A queue created with [`prioritized_channel`] orders notifications by the
+//! [`Priority`] of their path: higher priorities are received first, and a
+//! full queue makes room for a notification by dropping a queued one of
+//! lower priority before the overflow policy applies.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+};
+
+use tokio::sync::Notify;
+
+use super::ObserverValue;
+use crate::router::priority::{Priorities, Priority};
+
+/// What a full notification queue does with a new notification
+///
+/// In a [prioritized](prioritized_channel) queue the policy applies among
+/// notifications of the new one's priority; queued notifications of higher
+/// priority are never dropped for it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drop the oldest queued notification to make room for the new one
+    DropOldest,
+    /// Drop the new notification
+    #[default]
+    DropNewest,
+    /// Close the connection. The client re-registers its observations when
+    /// it reconnects.
+    Disconnect,
+    /// Drop the new notification but mark its path as missed. Once the queue
+    /// has drained, the connection is sent the latest value of every missed
+    /// path, so clients end up in sync without seeing every intermediate
+    /// value.
+    Resync,
+}
+
+/// Error returned by [`NotificationSender::send`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError {
+    /// The queue was full and the overflow policy dropped a notification
+    Full,
+    /// The receiving connection is gone, or was closed by
+    /// [`OverflowPolicy::Disconnect`]
+    Closed,
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Full => write!(f, "notification queue full"),
+            SendError::Closed => write!(f, "notification queue closed"),
+        }
+    }
+}
+
+impl std::error::Error for SendError {}
+
+/// Create a notification queue holding up to `capacity` notifications
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn notification_channel(
+    capacity: usize,
+    overflow: OverflowPolicy,
+) -> (NotificationSender, NotificationReceiver) {
+    prioritized_channel(capacity, overflow, Priorities::default())
+}
+
+/// Create a notification queue that orders notifications by the priority
+/// `priorities` gives their path
+///
+/// Notifications of equal priority are received in the order they were
+/// sent.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn prioritized_channel(
+    capacity: usize,
+    overflow: OverflowPolicy,
+    priorities: Priorities,
+) -> (NotificationSender, NotificationReceiver) {
+    assert!(capacity > 0, "notification queue capacity must be positive");
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::with_capacity(capacity),
+            missed: Vec::new(),
+            overflowed: false,
+            receiver_alive: true,
+        }),
+        notify: Notify::new(),
+        capacity,
+        overflow,
+        priorities,
+        senders: AtomicUsize::new(1),
+        dropped: AtomicU64::new(0),
+    });
+    (
+        NotificationSender {
+            shared: shared.clone(),
+        },
+        NotificationReceiver { shared },
+    )
+}
+
+/// A notification with the priority of its path
+struct Queued {
+    priority: Priority,
+    value: ObserverValue,
+}
+
+struct State {
+    queue: VecDeque<Queued>,
+    /// Latest notification of every path dropped under [`OverflowPolicy::Resync`]
+    missed: Vec<Queued>,
+    /// Set when [`OverflowPolicy::Disconnect`] closed the queue
+    overflowed: bool,
+    receiver_alive: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    notify: Notify,
+    capacity: usize,
+    overflow: OverflowPolicy,
+    priorities: Priorities,
+    senders: AtomicUsize,
+    dropped: AtomicU64,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // The state is plain data, so a poisoned lock is still usable
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl State {
+    /// Remember a dropped notification under [`OverflowPolicy::Resync`],
+    /// merging it into a missed one of the same path
+    fn miss(&mut self, queued: Queued) {
+        match self
+            .missed
+            .iter_mut()
+            .find(|m| m.value.path == queued.value.path)
+        {
+            Some(missed) => {
+                let mut value = queued.value;
+                for pointer in missed.value.changed.drain(..) {
+                    if !value.changed.contains(&pointer) {
+                        value.changed.push(pointer);
+                    }
+                }
+                missed.value = value;
+            }
+            None => self.missed.push(queued),
+        }
+    }
+}
+
+/// Index of the first entry of the highest priority
+fn most_urgent(entries: impl Iterator<Item = Priority>) -> Option<usize> {
+    let mut best: Option<(usize, Priority)> = None;
+    for (i, priority) in entries.enumerate() {
+        if best.is_none_or(|(_, p)| priority > p) {
+            best = Some((i, priority));
+        }
+    }
+    best.map(|(i, _)| i)
+}
+
+/// Sending half of a notification queue
+///
+/// Cloning yields another sender for the same queue.
+pub struct NotificationSender {
+    shared: Arc<Shared>,
+}
+
+impl NotificationSender {
+    /// Queue a notification without waiting
+    ///
+    /// Returns [`SendError::Full`] when the overflow policy had to drop a
+    /// notification, either this one or an older one.
+    pub fn send(&self, value: ObserverValue) -> Result<(), SendError> {
+        let shared = &self.shared;
+        let mut state = shared.lock();
+        if !state.receiver_alive || state.overflowed {
+            return Err(SendError::Closed);
+        }
+
+        let queued = Queued {
+            priority: shared.priorities.get(&value.path),
+            value,
+        };
+        if state.queue.len() < shared.capacity {
+            // A newer value supersedes a missed one
+            state
+                .missed
+                .retain(|missed| missed.value.path != queued.value.path);
+            state.queue.push_back(queued);
+            drop(state);
+            shared.notify.notify_one();
+            return Ok(());
+        }
+
+        shared.dropped.fetch_add(1, Ordering::Relaxed);
+
+        // The newest of the least urgent queued notifications makes room
+        // for a more urgent one
+        let lowest = state.queue.iter().map(|q| q.priority).min();
+        if let Some(lowest) = lowest
+            && lowest < queued.priority
+        {
+            let victim = state.queue.iter().rposition(|q| q.priority == lowest);
+            if let Some(displaced) = victim.and_then(|i| state.queue.remove(i)) {
+                if shared.overflow == OverflowPolicy::Resync {
+                    state.miss(displaced);
+                }
+                state.queue.push_back(queued);
+                drop(state);
+                shared.notify.notify_one();
+            }
+            return Err(SendError::Full);
+        }
+
+        match shared.overflow {
+            OverflowPolicy::DropOldest => {
+                let oldest = state
+                    .queue
+                    .iter()
+                    .position(|q| q.priority == queued.priority);
+                // Only more urgent notifications are queued: drop the new one
+                if let Some(i) = oldest {
+                    state.queue.remove(i);
+                    state.queue.push_back(queued);
+                }
+            }
+            OverflowPolicy::DropNewest => {}
+            OverflowPolicy::Disconnect => {
+                state.overflowed = true;
+                drop(state);
+                shared.notify.notify_one();
+            }
+            OverflowPolicy::Resync => state.miss(queued),
+        }
+        Err(SendError::Full)
+    }
+
+    /// Capacity of the queue
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+}
+
+impl Clone for NotificationSender {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for NotificationSender {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.notify.notify_one();
+        }
+    }
+}
+
+impl fmt::Debug for NotificationSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NotificationSender")
+            .field("capacity", &self.shared.capacity)
+            .field("overflow", &self.shared.overflow)
+            .finish()
+    }
+}
+
+/// Receiving half of a notification queue
+pub struct NotificationReceiver {
+    shared: Arc<Shared>,
+}
+
+impl NotificationReceiver {
+    /// Wait for the next notification
+    ///
+    /// Returns `None` once every sender is gone and the queue is drained,
+    /// or right away when [`OverflowPolicy::Disconnect`] closed the queue.
+    /// Cancel safe.
+    pub async fn recv(&mut self) -> Option<ObserverValue> {
+        loop {
+            if let Some(value) = self.try_recv() {
+                return Some(value);
+            }
+            if self.overflowed() || self.shared.senders.load(Ordering::Acquire) == 0 {
+                return None;
+            }
+            self.shared.notify.notified().await;
+        }
+    }
+
+    /// Take the next notification if one is queued
+    pub fn try_recv(&mut self) -> Option<ObserverValue> {
+        let mut state = self.shared.lock();
+        if state.overflowed {
+            return None;
+        }
+        if let Some(i) = most_urgent(state.queue.iter().map(|q| q.priority)) {
+            return state.queue.remove(i).map(|q| q.value);
+        }
+        let i = most_urgent(state.missed.iter().map(|q| q.priority))?;
+        Some(state.missed.remove(i).value)
+    }
+
+    /// Whether [`OverflowPolicy::Disconnect`] closed the queue
+    pub fn overflowed(&self) -> bool {
+        self.shared.lock().overflowed
+    }
+
+    /// Number of times the overflow policy dropped a notification
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for NotificationReceiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.receiver_alive = false;
+        state.queue.clear();
+        state.missed.clear();
+    }
+}
+
+impl fmt::Debug for NotificationReceiver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NotificationReceiver")
+            .field("capacity", &self.shared.capacity)
+            .field("overflow", &self.shared.overflow)
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    fn value(path: &str, value: Value) -> ObserverValue {
+        ObserverValue {
+            path: path.to_string(),
+            value,
+            encoded: None,
+            changed: vec![path.to_string()],
+        }
+    }
+
+    fn drain(rx: &mut NotificationReceiver) -> Vec<(String, Value)> {
+        std::iter::from_fn(|| rx.try_recv())
+            .map(|v| (v.path, v.value))
+            .collect()
+    }
+
+    #[test]
+    fn test_drop_policies() {
+        let (tx, mut rx) = notification_channel(2, OverflowPolicy::DropNewest);
+        assert!(tx.send(value("/a", json!(1))).is_ok());
+        assert!(tx.send(value("/a", json!(2))).is_ok());
+        assert_eq!(tx.send(value("/a", json!(3))), Err(SendError::Full));
+        assert_eq!(
+            drain(&mut rx),
+            vec![("/a".into(), json!(1)), ("/a".into(), json!(2))]
+        );
+        assert_eq!(rx.dropped(), 1);
+
+        let (tx, mut rx) = notification_channel(2, OverflowPolicy::DropOldest);
+        for i in 1..=3 {
+            let _ = tx.send(value("/a", json!(i)));
+        }
+        assert_eq!(
+            drain(&mut rx),
+            vec![("/a".into(), json!(2)), ("/a".into(), json!(3))]
+        );
+        assert_eq!(rx.dropped(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_policy() {
+        let (tx, mut rx) = notification_channel(1, OverflowPolicy::Disconnect);
+        assert!(tx.send(value("/a", json!(1))).is_ok());
+        assert_eq!(tx.send(value("/a", json!(2))), Err(SendError::Full));
+        assert_eq!(tx.send(value("/a", json!(3))), Err(SendError::Closed));
+
+        assert!(rx.overflowed());
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[test]
+    fn test_resync_policy() {
+        let (tx, mut rx) = notification_channel(1, OverflowPolicy::Resync);
+        let _ = tx.send(value("/a", json!(1)));
+        let _ = tx.send(value("/a", json!(2)));
+        let _ = tx.send(value("/b", json!(1)));
+        let _ = tx.send(value("/a", json!(3)));
+
+        // The latest value of each missed path follows the queued ones
+        assert_eq!(
+            drain(&mut rx),
+            vec![
+                ("/a".into(), json!(1)),
+                ("/a".into(), json!(3)),
+                ("/b".into(), json!(1)),
+            ]
+        );
+        assert_eq!(rx.dropped(), 3);
+
+        // A value queued normally supersedes a missed one
+        let _ = tx.send(value("/a", json!(4)));
+        let _ = tx.send(value("/b", json!(2)));
+        assert_eq!(rx.try_recv().unwrap().value, json!(4));
+        let _ = tx.send(value("/b", json!(3)));
+        assert_eq!(drain(&mut rx), vec![("/b".into(), json!(3))]);
+    }
+
+    #[test]
+    fn test_priorities() {
+        let mut priorities = Priorities::default();
+        priorities.set("/alarm", Priority::High);
+        priorities.set("/telemetry", Priority::Low);
+
+        let (tx, mut rx) = prioritized_channel(3, OverflowPolicy::DropNewest, priorities);
+        let _ = tx.send(value("/telemetry", json!(1)));
+        let _ = tx.send(value("/config", json!(1)));
+        let _ = tx.send(value("/telemetry", json!(2)));
+        // Full: the newest telemetry makes room for the alarm
+        assert_eq!(tx.send(value("/alarm", json!(1))), Err(SendError::Full));
+        // Nothing less urgent left to drop
+        assert_eq!(tx.send(value("/telemetry", json!(3))), Err(SendError::Full));
+        assert_eq!(
+            drain(&mut rx),
+            vec![
+                ("/alarm".into(), json!(1)),
+                ("/config".into(), json!(1)),
+                ("/telemetry".into(), json!(1)),
+            ]
+        );
+        assert_eq!(rx.dropped(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_recv_ends_with_senders() {
+        let (tx, mut rx) = notification_channel(4, OverflowPolicy::default());
+        let other = tx.clone();
+        drop(tx);
+        let _ = other.send(value("/a", json!(1)));
+
+        let task = tokio::spawn(async move {
+            let mut received = Vec::new();
+            while let Some(value) = rx.recv().await {
+                received.push(value.value);
+            }
+            received
+        });
+        tokio::task::yield_now().await;
+        drop(other);
+        assert_eq!(task.await.unwrap(), vec![json!(1)]);
+
+        let (tx, rx) = notification_channel(4, OverflowPolicy::default());
+        drop(rx);
+        assert_eq!(tx.send(value("/a", json!(1))), Err(SendError::Closed));
+    }
+}
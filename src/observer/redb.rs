@@ -6,11 +6,22 @@ use redb::ReadableDatabase;
 use serde_json::Value;
 use tokio::sync::mpsc::{Sender, channel};
 
-use super::{Observer, ObserverChannels, ObserverValue};
+use super::{
+    EncodedPayload, Observer, ObserverChannels, ObserverSender, Registration,
+    metadata::ObservationMetadata,
+};
 
 // Table definition for storing device data
 const DATA_TABLE: redb::TableDefinition<&str, &str> = redb::TableDefinition::new("device_data");
 
+// Table definition for payloads written with `write_raw`, keyed by `raw_key`
+const RAW_TABLE: redb::TableDefinition<&str, &[u8]> = redb::TableDefinition::new("device_raw");
+
+/// Key of a raw payload: device ID and JSON pointer, NUL separated
+fn raw_key(device_id: &str, pointer: &str) -> String {
+    format!("{}\0{}", device_id, pointer)
+}
+
 #[derive(Clone, Debug)]
 pub struct RedbObserver {
     pub db: Arc<redb::Database>,
@@ -23,11 +34,12 @@ impl RedbObserver {
     pub fn new(path: &str) -> Result<Self, RedbObserverError> {
         let db = redb::Database::create(path)?;
 
-        // Initialize the table
+        // Initialize the tables
         {
             let write_txn = db.begin_write()?;
             {
                 let _table = write_txn.open_table(DATA_TABLE)?;
+                let _raw = write_txn.open_table(RAW_TABLE)?;
             }
             write_txn.commit()?;
         }
@@ -124,6 +136,96 @@ impl From<serde_json::Error> for RedbObserverError {
     }
 }
 
+impl RedbObserver {
+    /// Merges values into the device's document with `merge`, keeping
+    /// `raw` as the payload the update at its path was converted from
+    async fn store(
+        &mut self,
+        device_id: &str,
+        updates: &[(String, Value)],
+        raw: Option<(&str, &EncodedPayload)>,
+        merge: fn(&mut Value, &Value),
+    ) -> Result<(), RedbObserverError> {
+        let new_values: Vec<Value> = updates
+            .iter()
+            .map(|(path, payload)| {
+                let new_value = super::path_to_json(path, payload);
+                tracing::debug!("New value: {:?} for path: {}", new_value, path);
+                new_value
+            })
+            .collect();
+
+        // Phase 1: Read existing value and merge (blocking DB read)
+        let db = self.db.clone();
+        let did = device_id.to_string();
+        let (value, current_value) =
+            tokio::task::spawn_blocking(move || -> Result<(Value, Value), RedbObserverError> {
+                let mut current_value = Value::Null;
+                {
+                    let read_txn = db.begin_read()?;
+                    let table = read_txn.open_table(DATA_TABLE)?;
+
+                    if let Some(stored_value) = table.get(did.as_str())? {
+                        match serde_json::from_str::<Value>(stored_value.value()) {
+                            Ok(stored_value) => current_value = stored_value,
+                            Err(e) => tracing::warn!("Unable to deserialize. Err: {}", e),
+                        }
+                    }
+                }
+
+                let mut value = current_value.clone();
+                for new_value in &new_values {
+                    merge(&mut value, new_value);
+                }
+                tracing::debug!("Merged value: {:?}", value);
+                Ok((value, current_value))
+            })
+            .await??;
+
+        // Phase 2: Notify observers of changes
+        match raw {
+            Some((path, raw)) => {
+                self.channels
+                    .notify_raw(device_id, &current_value, &value, path, raw)
+                    .await
+            }
+            None => {
+                self.channels
+                    .notify(device_id, &current_value, &value)
+                    .await
+            }
+        }
+
+        // Phase 3: Write merged value back (blocking DB write)
+        let db = self.db.clone();
+        let did = device_id.to_string();
+        let value_str = serde_json::to_string(&value)?;
+        let raw = raw.map(|(path, raw)| {
+            (
+                raw_key(device_id, &super::path_to_pointer(path)),
+                raw.to_stored(),
+            )
+        });
+        tokio::task::spawn_blocking(move || -> Result<(), RedbObserverError> {
+            let write_txn = db.begin_write()?;
+            {
+                let mut table = write_txn.open_table(DATA_TABLE)?;
+                table.insert(did.as_str(), value_str.as_str())?;
+                if let Some((key, stored)) = raw {
+                    let mut raw_table = write_txn.open_table(RAW_TABLE)?;
+                    raw_table.insert(key.as_str(), stored.as_slice())?;
+                }
+            }
+            write_txn.commit()?;
+            tracing::debug!("Value successfully written to redb");
+            Ok(())
+        })
+        .await??;
+
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl Observer for RedbObserver {
     type Error = RedbObserverError;
@@ -132,9 +234,22 @@ impl Observer for RedbObserver {
         &mut self,
         device_id: &str,
         path: &str,
-        sender: Arc<Sender<ObserverValue>>,
+        sender: ObserverSender,
+    ) -> Result<(), Self::Error> {
+        self.register_with_metadata(device_id, path, sender, ObservationMetadata::new())
+            .await
+    }
+
+    async fn register_with_metadata(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        sender: ObserverSender,
+        metadata: ObservationMetadata,
     ) -> Result<(), Self::Error> {
-        self.channels.register(device_id, path, sender).await;
+        self.channels
+            .register_with_metadata(device_id, path, sender, metadata)
+            .await;
 
         // Spawn watcher task if not already running.
         // Note: redb doesn't have built-in change watching like sled,
@@ -198,78 +313,99 @@ impl Observer for RedbObserver {
         Ok(())
     }
 
-    async fn write(
+    async fn unregister_sender(
         &mut self,
         device_id: &str,
         path: &str,
-        payload: &Value,
+        sender: &ObserverSender,
     ) -> Result<(), Self::Error> {
-        let new_value = super::path_to_json(path, payload);
-
-        tracing::debug!("New value: {:?} for path: {}", new_value, path);
+        let all_empty = self
+            .channels
+            .unregister_sender(device_id, path, sender)
+            .await;
 
-        // Phase 1: Read existing value and merge (blocking DB read)
-        let db = self.db.clone();
-        let did = device_id.to_string();
-        let nv = new_value.clone();
-        let (value, current_value) =
-            tokio::task::spawn_blocking(move || -> Result<(Value, Value), RedbObserverError> {
-                let mut current_value = Value::Null;
-                let value = {
-                    let read_txn = db.begin_read()?;
-                    let table = read_txn.open_table(DATA_TABLE)?;
+        if all_empty {
+            if let Some(channel) = &self.channel {
+                let _ = channel.send(()).await;
+            }
+            self.channel = None;
+        }
 
-                    if let Some(stored_value) = table.get(did.as_str())? {
-                        let stored_str = stored_value.value();
-                        match serde_json::from_str::<Value>(stored_str) {
-                            Ok(stored_value) => {
-                                current_value = stored_value.clone();
-                                let mut merged_value = stored_value;
-                                super::merge_json(&mut merged_value, &nv);
-                                tracing::debug!("Merged value: {:?}", merged_value);
-                                merged_value
-                            }
-                            Err(e) => {
-                                tracing::warn!("Unable to deserialize. Err: {}", e);
-                                nv
-                            }
-                        }
-                    } else {
-                        nv
-                    }
-                };
-                Ok((value, current_value))
-            })
-            .await??;
+        Ok(())
+    }
 
-        // Notify observers of changes
-        self.channels
-            .notify(device_id, &current_value, &value)
-            .await;
+    async fn unregister_connection(
+        &mut self,
+        device_id: &str,
+        sender: &ObserverSender,
+    ) -> Result<(), Self::Error> {
+        let all_empty = self.channels.unregister_connection(device_id, sender).await;
 
-        // Phase 3: Write merged value back (blocking DB write)
-        let db = self.db.clone();
-        let did = device_id.to_string();
-        let value_str = serde_json::to_string(&value)?;
-        tokio::task::spawn_blocking(move || -> Result<(), RedbObserverError> {
-            let write_txn = db.begin_write()?;
-            {
-                let mut table = write_txn.open_table(DATA_TABLE)?;
-                table.insert(did.as_str(), value_str.as_str())?;
+        if all_empty {
+            if let Some(channel) = &self.channel {
+                let _ = channel.send(()).await;
             }
-            write_txn.commit()?;
-            tracing::debug!("Value successfully written to redb");
-            Ok(())
-        })
-        .await??;
+            self.channel = None;
+        }
 
         Ok(())
     }
 
+    async fn write(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: &Value,
+    ) -> Result<(), Self::Error> {
+        let updates = [(path.to_string(), payload.clone())];
+        self.store(device_id, &updates, None, super::merge_json)
+            .await
+    }
+
+    /// Merges all updates into the document in a single write
+    /// transaction, so observers are notified once for the whole batch.
+    async fn write_batch(
+        &mut self,
+        device_id: &str,
+        updates: Vec<(String, Value)>,
+    ) -> Result<(), Self::Error> {
+        self.store(device_id, &updates, None, super::merge_json)
+            .await
+    }
+
+    async fn patch(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        patch: &Value,
+    ) -> Result<(), Self::Error> {
+        let updates = [(path.to_string(), patch.clone())];
+        self.store(device_id, &updates, None, super::merge_patch)
+            .await
+    }
+
+    /// Stores the payload bytes in the `device_raw` table next to the
+    /// merged document.
+    async fn write_raw(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: EncodedPayload,
+    ) -> Result<(), Self::Error> {
+        let updates = [(path.to_string(), payload.to_json())];
+        self.store(
+            device_id,
+            &updates,
+            Some((path, &payload)),
+            super::merge_json,
+        )
+        .await
+    }
+
     async fn read(&mut self, device_id: &str, path: &str) -> Result<Option<Value>, Self::Error> {
         let db = self.db.clone();
         let did = device_id.to_string();
-        let p = path.to_string();
+        let p = super::path_to_pointer(path);
         tokio::task::spawn_blocking(move || -> Result<Option<Value>, RedbObserverError> {
             let read_txn = db.begin_read()?;
             let table = read_txn.open_table(DATA_TABLE)?;
@@ -289,6 +425,36 @@ impl Observer for RedbObserver {
         .await?
     }
 
+    async fn read_raw(
+        &mut self,
+        device_id: &str,
+        path: &str,
+    ) -> Result<Option<EncodedPayload>, Self::Error> {
+        let db = self.db.clone();
+        let did = device_id.to_string();
+        let pointer = super::path_to_pointer(path);
+        tokio::task::spawn_blocking(
+            move || -> Result<Option<EncodedPayload>, RedbObserverError> {
+                let read_txn = db.begin_read()?;
+                let raw_table = read_txn.open_table(RAW_TABLE)?;
+                let Some(raw) = raw_table
+                    .get(raw_key(&did, &pointer).as_str())?
+                    .and_then(|stored| EncodedPayload::from_stored(stored.value()))
+                else {
+                    return Ok(None);
+                };
+
+                let table = read_txn.open_table(DATA_TABLE)?;
+                let Some(document) = table.get(did.as_str())? else {
+                    return Ok(None);
+                };
+                let document: Value = serde_json::from_str(document.value())?;
+                Ok(raw.is_current(&document, &pointer).then_some(raw))
+            },
+        )
+        .await?
+    }
+
     async fn clear(&mut self, device_id: &str) -> Result<(), Self::Error> {
         let db = self.db.clone();
         let did = device_id.to_string();
@@ -297,6 +463,9 @@ impl Observer for RedbObserver {
             {
                 let mut table = write_txn.open_table(DATA_TABLE)?;
                 table.remove(did.as_str())?;
+                let prefix = raw_key(&did, "");
+                let mut raw_table = write_txn.open_table(RAW_TABLE)?;
+                raw_table.retain(|key, _| !key.starts_with(prefix.as_str()))?;
             }
             write_txn.commit()?;
             Ok(())
@@ -306,9 +475,31 @@ impl Observer for RedbObserver {
         Ok(())
     }
 
+    async fn notify_encoded(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: EncodedPayload,
+    ) -> Result<(), Self::Error> {
+        self.channels.notify_encoded(device_id, path, payload).await;
+        Ok(())
+    }
+
     async fn observer_count(&self, device_id: &str) -> usize {
         self.channels.device_observer_count(device_id).await
     }
+
+    async fn list(&self, device_id: &str) -> Vec<Registration> {
+        self.channels.list(device_id).await
+    }
+
+    async fn list_all(&self) -> Vec<Registration> {
+        self.channels.list_all().await
+    }
+
+    async fn observing_devices(&self, path: &str) -> Vec<String> {
+        self.channels.observing_devices(path).await
+    }
 }
 
 #[cfg(test)]
@@ -374,7 +565,7 @@ mod tests {
 
         observer.clear("123").await.unwrap();
 
-        let (tx, mut rx) = tokio::sync::mpsc::channel::<ObserverValue>(10);
+        let (tx, mut rx) = crate::observer::queue::notification_channel(10, Default::default());
 
         let fut = tokio::spawn(async move {
             if let Some(r) = rx.recv().await {
@@ -424,4 +615,81 @@ mod tests {
         assert!(observer.channels.is_empty().await);
         assert!(observer.channel.is_none());
     }
+
+    #[tokio::test]
+    async fn test_redb_observer_raw_payload() {
+        use coap_lite::ContentFormat;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("test_raw.redb");
+        let mut observer = RedbObserver::new(db_path.to_str().unwrap()).unwrap();
+
+        // {"hash": h'cafe'}
+        let payload = EncodedPayload::new(
+            ContentFormat::ApplicationCBOR,
+            vec![0xa1, 0x64, b'h', b'a', b's', b'h', 0x42, 0xca, 0xfe],
+        );
+        observer
+            .write_raw("raw", "/fw", payload.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            observer.read("raw", "/fw").await.unwrap(),
+            Some(json!({"hash": "yv4="}))
+        );
+        assert_eq!(
+            observer.read_raw("raw", "/fw").await.unwrap(),
+            Some(payload)
+        );
+
+        observer.clear("raw").await.unwrap();
+        assert_eq!(observer.read_raw("raw", "/fw").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_redb_observer_read_pointer_and_root() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("test_read_root.redb");
+        let mut observer = RedbObserver::new(db_path.to_str().unwrap()).unwrap();
+
+        observer
+            .write("456", "/sensors/temp", &json!(21.5))
+            .await
+            .unwrap();
+
+        // Paths resolve the same with or without leading/trailing slashes
+        let result = observer.read("456", "sensors/temp/").await.unwrap();
+        assert_eq!(result, Some(json!(21.5)));
+        assert_eq!(
+            observer.read("456", "/sensors/missing").await.unwrap(),
+            None
+        );
+
+        let root = observer.read_root("456").await.unwrap();
+        assert_eq!(root, Some(json!({"sensors": {"temp": 21.5}})));
+        assert_eq!(observer.read("456", "/").await.unwrap(), root);
+        assert_eq!(observer.read_root("unknown").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_redb_observer_patch() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("redb_patch.db");
+        let mut observer = RedbObserver::new(db_path.to_str().unwrap()).unwrap();
+
+        observer
+            .write("dev", "/config", &json!({"led": "on", "interval": 30}))
+            .await
+            .unwrap();
+        observer
+            .patch("dev", "/config", &json!({"led": null, "interval": 60}))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            observer.read("dev", "/config").await.unwrap(),
+            Some(json!({"interval": 60}))
+        );
+    }
 }
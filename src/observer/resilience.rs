@@ -0,0 +1,869 @@
+//! Retries and circuit breaking for observer backends.
+//!
+//! Handlers and the notification path wait on the observer backend, so a
+//! flaky or stalled Redis or sled backend stalls requests with it. Two
+//! decorators wrap any [`Observer`] to contain that:
+//!
+//! - [`RetryObserver`] retries failed storage operations with exponential
+//!   backoff, riding out short outages.
+//! - [`CircuitBreakerObserver`] stops calling a backend after a run of
+//!   failures and fails fast with [`CircuitError::Open`] until the backend
+//!   has had time to recover, then lets a single probe through to test it.
+//!
+//! Both only guard storage operations: reads, writes, patches, history and
+//! clearing. Registrations and [`notify_encoded`](Observer::notify_encoded)
+//! are passed through, since the built-in backends keep them in memory, and
+//! [`write_if_version`](Observer::write_if_version) is not retried, since a
+//! retry after a write that failed late would report a conflict with itself.
+//!
+//! To retry within the breaker, so that exhausted retries count as a single
+//! failure, put the retries inside:
+//!
+//! ```rust
+//! use coapum::observer::memory::MemObserver;
+//! use coapum::observer::resilience::{CircuitBreakerObserver, RetryObserver};
+//! use std::time::Duration;
+//!
+//! let observer = CircuitBreakerObserver::new(RetryObserver::new(MemObserver::new()).max_retries(2))
+//!     .failure_threshold(5)
+//!     .open_duration(Duration::from_secs(10))
+//!     .call_timeout(Duration::from_secs(2));
+//! ```
+
+use std::{
+    fmt,
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::time::Instant;
+
+use super::history::{HistoryEntry, HistoryRange};
+use super::metadata::ObservationMetadata;
+use super::{EncodedPayload, Observer, ObserverSender, Registration, VersionedWrite};
+
+/// Counters of a [`RetryObserver`], shared by its clones
+#[derive(Debug, Default)]
+struct RetryCounters {
+    retries: AtomicU64,
+    exhausted: AtomicU64,
+}
+
+/// Snapshot of the counters of a [`RetryObserver`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetryStats {
+    /// Operations repeated after a failure
+    pub retries: u64,
+    /// Operations that still failed after the last retry
+    pub exhausted: u64,
+}
+
+/// An observer that retries failed storage operations of `O`
+///
+/// A failed operation is repeated up to [`max_retries`](Self::max_retries)
+/// times, waiting [`backoff`](Self::backoff) before the first retry and
+/// twice as long before each further one, up to a maximum. The error of the
+/// last attempt is returned.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::observer::memory::MemObserver;
+/// use coapum::observer::resilience::RetryObserver;
+/// use std::time::Duration;
+///
+/// let observer = RetryObserver::new(MemObserver::new())
+///     .max_retries(3)
+///     .backoff(Duration::from_millis(20), Duration::from_millis(500));
+/// assert_eq!(observer.stats().retries, 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetryObserver<O> {
+    inner: O,
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    counters: Arc<RetryCounters>,
+}
+
+impl<O: Observer> RetryObserver<O> {
+    /// Retry operations of `inner` up to 3 times, backing off from 50 ms to
+    /// at most 1 second
+    pub fn new(inner: O) -> Self {
+        Self {
+            inner,
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+            counters: Arc::new(RetryCounters::default()),
+        }
+    }
+
+    /// Repeat a failed operation at most `retries` times (default: 3)
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// Wait `initial` before the first retry, doubling up to `max` for each
+    /// further one (default: 50 ms and 1 second)
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// The wrapped observer
+    pub fn inner(&self) -> &O {
+        &self.inner
+    }
+
+    /// Retry counters of this observer and its clones
+    pub fn stats(&self) -> RetryStats {
+        RetryStats {
+            retries: self.counters.retries.load(Ordering::Relaxed),
+            exhausted: self.counters.exhausted.load(Ordering::Relaxed),
+        }
+    }
+
+    /// How long to wait before retry number `attempt`, counting from 0
+    fn backoff_for(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max_backoff)
+    }
+}
+
+/// Run `$call` on `$self.inner` until it succeeds or the retries run out
+macro_rules! retry {
+    ($self:ident, $inner:ident => $call:expr) => {{
+        let mut attempt = 0;
+        loop {
+            let $inner = &mut $self.inner;
+            match $call.await {
+                Ok(value) => break Ok(value),
+                Err(error) if attempt < $self.max_retries => {
+                    tracing::debug!(?error, attempt, "observer.retry");
+                    $self.counters.retries.fetch_add(1, Ordering::Relaxed);
+                    tokio::time::sleep($self.backoff_for(attempt)).await;
+                    attempt += 1;
+                }
+                Err(error) => {
+                    tracing::warn!(?error, "observer.retries_exhausted");
+                    $self.counters.exhausted.fetch_add(1, Ordering::Relaxed);
+                    break Err(error);
+                }
+            }
+        }
+    }};
+}
+
+#[async_trait]
+impl<O: Observer> Observer for RetryObserver<O> {
+    type Error = O::Error;
+
+    async fn register(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        sender: ObserverSender,
+    ) -> Result<(), Self::Error> {
+        self.inner.register(device_id, path, sender).await
+    }
+
+    async fn register_with_metadata(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        sender: ObserverSender,
+        metadata: ObservationMetadata,
+    ) -> Result<(), Self::Error> {
+        self.inner
+            .register_with_metadata(device_id, path, sender, metadata)
+            .await
+    }
+
+    async fn unregister(&mut self, device_id: &str, path: &str) -> Result<(), Self::Error> {
+        self.inner.unregister(device_id, path).await
+    }
+
+    async fn unregister_all(&mut self) -> Result<(), Self::Error> {
+        self.inner.unregister_all().await
+    }
+
+    async fn unregister_device(&mut self, device_id: &str) -> Result<(), Self::Error> {
+        self.inner.unregister_device(device_id).await
+    }
+
+    async fn unregister_sender(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        sender: &ObserverSender,
+    ) -> Result<(), Self::Error> {
+        self.inner.unregister_sender(device_id, path, sender).await
+    }
+
+    async fn unregister_connection(
+        &mut self,
+        device_id: &str,
+        sender: &ObserverSender,
+    ) -> Result<(), Self::Error> {
+        self.inner.unregister_connection(device_id, sender).await
+    }
+
+    async fn write(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: &Value,
+    ) -> Result<(), Self::Error> {
+        retry!(self, inner => inner.write(device_id, path, payload))
+    }
+
+    async fn write_batch(
+        &mut self,
+        device_id: &str,
+        updates: Vec<(String, Value)>,
+    ) -> Result<(), Self::Error> {
+        retry!(self, inner => inner.write_batch(device_id, updates.clone()))
+    }
+
+    async fn write_raw(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: EncodedPayload,
+    ) -> Result<(), Self::Error> {
+        retry!(self, inner => inner.write_raw(device_id, path, payload.clone()))
+    }
+
+    async fn patch(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        patch: &Value,
+    ) -> Result<(), Self::Error> {
+        retry!(self, inner => inner.patch(device_id, path, patch))
+    }
+
+    async fn version(&mut self, device_id: &str) -> Result<Option<u64>, Self::Error> {
+        retry!(self, inner => inner.version(device_id))
+    }
+
+    async fn write_if_version(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: &Value,
+        expected: u64,
+    ) -> Result<VersionedWrite, Self::Error> {
+        self.inner
+            .write_if_version(device_id, path, payload, expected)
+            .await
+    }
+
+    async fn read(&mut self, device_id: &str, path: &str) -> Result<Option<Value>, Self::Error> {
+        retry!(self, inner => inner.read(device_id, path))
+    }
+
+    async fn read_history(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        range: HistoryRange,
+    ) -> Result<Vec<HistoryEntry>, Self::Error> {
+        retry!(self, inner => inner.read_history(device_id, path, range))
+    }
+
+    async fn read_raw(
+        &mut self,
+        device_id: &str,
+        path: &str,
+    ) -> Result<Option<EncodedPayload>, Self::Error> {
+        retry!(self, inner => inner.read_raw(device_id, path))
+    }
+
+    async fn read_root(&mut self, device_id: &str) -> Result<Option<Value>, Self::Error> {
+        retry!(self, inner => inner.read_root(device_id))
+    }
+
+    async fn clear(&mut self, device_id: &str) -> Result<(), Self::Error> {
+        retry!(self, inner => inner.clear(device_id))
+    }
+
+    async fn notify_encoded(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: EncodedPayload,
+    ) -> Result<(), Self::Error> {
+        self.inner.notify_encoded(device_id, path, payload).await
+    }
+
+    async fn observer_count(&self, device_id: &str) -> usize {
+        self.inner.observer_count(device_id).await
+    }
+
+    async fn list(&self, device_id: &str) -> Vec<Registration> {
+        self.inner.list(device_id).await
+    }
+
+    async fn list_all(&self) -> Vec<Registration> {
+        self.inner.list_all().await
+    }
+
+    async fn observing_devices(&self, path: &str) -> Vec<String> {
+        self.inner.observing_devices(path).await
+    }
+
+    async fn ping(&mut self) -> Result<(), Self::Error> {
+        // A health check reports the backend as it is, without retries
+        self.inner.ping().await
+    }
+}
+
+/// State of a [`CircuitBreakerObserver`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Operations go to the backend
+    #[default]
+    Closed,
+    /// Operations fail with [`CircuitError::Open`] without reaching the
+    /// backend
+    Open,
+    /// One probe operation tests whether the backend recovered; others fail
+    /// fast until it completes
+    HalfOpen,
+}
+
+/// Error returned by a [`CircuitBreakerObserver`]
+#[derive(Debug)]
+pub enum CircuitError<E> {
+    /// The circuit is open; the backend was not called
+    Open,
+    /// The backend did not complete the operation within the call timeout
+    Timeout,
+    /// The backend failed
+    Backend(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for CircuitError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CircuitError::Open => write!(f, "Observer circuit is open"),
+            CircuitError::Timeout => write!(f, "Observer operation timed out"),
+            CircuitError::Backend(e) => write!(f, "Observer error: {:?}", e),
+        }
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for CircuitError<E> {}
+
+/// Snapshot of the state and counters of a [`CircuitBreakerObserver`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CircuitStats {
+    /// Current state of the circuit
+    pub state: CircuitState,
+    /// Times the circuit opened
+    pub opened: u64,
+    /// Operations failed fast while the circuit was open
+    pub rejected: u64,
+    /// Operations that failed or timed out in the backend
+    pub failures: u64,
+}
+
+/// Circuit state shared by the clones of a [`CircuitBreakerObserver`]
+#[derive(Debug, Default)]
+struct Breaker {
+    stats: CircuitStats,
+    consecutive_failures: u32,
+    /// When the circuit opened, or when the current probe started
+    since: Option<Instant>,
+}
+
+impl Breaker {
+    /// Whether an operation may call the backend
+    fn admit(&mut self, open_duration: Duration) -> bool {
+        let waited = self
+            .since
+            .is_none_or(|since| since.elapsed() >= open_duration);
+        match self.stats.state {
+            CircuitState::Closed => true,
+            // A probe that never completed, e.g. because it was cancelled,
+            // is replaced after another `open_duration`
+            CircuitState::Open | CircuitState::HalfOpen if waited => {
+                self.stats.state = CircuitState::HalfOpen;
+                self.since = Some(Instant::now());
+                true
+            }
+            CircuitState::Open | CircuitState::HalfOpen => {
+                self.stats.rejected += 1;
+                false
+            }
+        }
+    }
+
+    fn record_success(&mut self) {
+        if self.stats.state != CircuitState::Closed {
+            tracing::info!("observer.circuit_closed");
+        }
+        self.stats.state = CircuitState::Closed;
+        self.consecutive_failures = 0;
+        self.since = None;
+    }
+
+    fn record_failure(&mut self, threshold: u32) {
+        self.stats.failures += 1;
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        let open = match self.stats.state {
+            CircuitState::Closed => self.consecutive_failures >= threshold,
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+        if open {
+            tracing::warn!(
+                failures = self.consecutive_failures,
+                "observer.circuit_opened"
+            );
+            self.stats.state = CircuitState::Open;
+            self.stats.opened += 1;
+            self.since = Some(Instant::now());
+        }
+    }
+}
+
+/// An observer that stops calling `O` while it keeps failing
+///
+/// After [`failure_threshold`](Self::failure_threshold) consecutive failed
+/// or timed out storage operations the circuit opens: operations fail with
+/// [`CircuitError::Open`] without waiting on the backend. Once
+/// [`open_duration`](Self::open_duration) has passed, the next operation is
+/// let through as a probe. If it succeeds the circuit closes, otherwise it
+/// opens for another `open_duration`.
+///
+/// A backend that hangs instead of failing only counts as failing with a
+/// [`call_timeout`](Self::call_timeout).
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::observer::memory::MemObserver;
+/// use coapum::observer::resilience::{CircuitBreakerObserver, CircuitState};
+/// use std::time::Duration;
+///
+/// let observer = CircuitBreakerObserver::new(MemObserver::new())
+///     .call_timeout(Duration::from_millis(500));
+/// assert_eq!(observer.stats().state, CircuitState::Closed);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerObserver<O> {
+    inner: O,
+    failure_threshold: u32,
+    open_duration: Duration,
+    call_timeout: Option<Duration>,
+    breaker: Arc<Mutex<Breaker>>,
+}
+
+impl<O: Observer> CircuitBreakerObserver<O> {
+    /// Open the circuit of `inner` after 5 consecutive failures, for 30
+    /// seconds at a time
+    pub fn new(inner: O) -> Self {
+        Self {
+            inner,
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(30),
+            call_timeout: None,
+            breaker: Arc::new(Mutex::new(Breaker::default())),
+        }
+    }
+
+    /// Open the circuit after `failures` consecutive failures (default: 5)
+    pub fn failure_threshold(mut self, failures: u32) -> Self {
+        self.failure_threshold = failures.max(1);
+        self
+    }
+
+    /// Fail fast for `duration` before probing the backend again
+    /// (default: 30 seconds)
+    pub fn open_duration(mut self, duration: Duration) -> Self {
+        self.open_duration = duration;
+        self
+    }
+
+    /// Count operations taking longer than `timeout` as failures and stop
+    /// waiting for them (default: wait as long as the backend takes)
+    pub fn call_timeout(mut self, timeout: Duration) -> Self {
+        self.call_timeout = Some(timeout);
+        self
+    }
+
+    /// The wrapped observer
+    pub fn inner(&self) -> &O {
+        &self.inner
+    }
+
+    /// State and counters of the circuit shared by this observer and its
+    /// clones
+    pub fn stats(&self) -> CircuitStats {
+        self.breaker().stats
+    }
+
+    fn breaker(&self) -> std::sync::MutexGuard<'_, Breaker> {
+        self.breaker.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Run `$call` on `$self.inner` if the circuit admits it, and record the
+/// outcome
+macro_rules! guarded {
+    ($self:ident, $inner:ident => $call:expr) => {{
+        if !$self.breaker().admit($self.open_duration) {
+            return Err(CircuitError::Open);
+        }
+        let $inner = &mut $self.inner;
+        let result = match $self.call_timeout {
+            Some(limit) => match tokio::time::timeout(limit, $call).await {
+                Ok(result) => result.map_err(CircuitError::Backend),
+                Err(_) => Err(CircuitError::Timeout),
+            },
+            None => $call.await.map_err(CircuitError::Backend),
+        };
+        match &result {
+            Ok(_) => $self.breaker().record_success(),
+            Err(_) => $self.breaker().record_failure($self.failure_threshold),
+        }
+        result
+    }};
+}
+
+#[async_trait]
+impl<O: Observer> Observer for CircuitBreakerObserver<O> {
+    type Error = CircuitError<O::Error>;
+
+    async fn register(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        sender: ObserverSender,
+    ) -> Result<(), Self::Error> {
+        self.inner
+            .register(device_id, path, sender)
+            .await
+            .map_err(CircuitError::Backend)
+    }
+
+    async fn register_with_metadata(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        sender: ObserverSender,
+        metadata: ObservationMetadata,
+    ) -> Result<(), Self::Error> {
+        self.inner
+            .register_with_metadata(device_id, path, sender, metadata)
+            .await
+            .map_err(CircuitError::Backend)
+    }
+
+    async fn unregister(&mut self, device_id: &str, path: &str) -> Result<(), Self::Error> {
+        self.inner
+            .unregister(device_id, path)
+            .await
+            .map_err(CircuitError::Backend)
+    }
+
+    async fn unregister_all(&mut self) -> Result<(), Self::Error> {
+        self.inner
+            .unregister_all()
+            .await
+            .map_err(CircuitError::Backend)
+    }
+
+    async fn unregister_device(&mut self, device_id: &str) -> Result<(), Self::Error> {
+        self.inner
+            .unregister_device(device_id)
+            .await
+            .map_err(CircuitError::Backend)
+    }
+
+    async fn unregister_sender(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        sender: &ObserverSender,
+    ) -> Result<(), Self::Error> {
+        self.inner
+            .unregister_sender(device_id, path, sender)
+            .await
+            .map_err(CircuitError::Backend)
+    }
+
+    async fn unregister_connection(
+        &mut self,
+        device_id: &str,
+        sender: &ObserverSender,
+    ) -> Result<(), Self::Error> {
+        self.inner
+            .unregister_connection(device_id, sender)
+            .await
+            .map_err(CircuitError::Backend)
+    }
+
+    async fn write(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: &Value,
+    ) -> Result<(), Self::Error> {
+        guarded!(self, inner => inner.write(device_id, path, payload))
+    }
+
+    async fn write_batch(
+        &mut self,
+        device_id: &str,
+        updates: Vec<(String, Value)>,
+    ) -> Result<(), Self::Error> {
+        guarded!(self, inner => inner.write_batch(device_id, updates))
+    }
+
+    async fn write_raw(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: EncodedPayload,
+    ) -> Result<(), Self::Error> {
+        guarded!(self, inner => inner.write_raw(device_id, path, payload))
+    }
+
+    async fn patch(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        patch: &Value,
+    ) -> Result<(), Self::Error> {
+        guarded!(self, inner => inner.patch(device_id, path, patch))
+    }
+
+    async fn version(&mut self, device_id: &str) -> Result<Option<u64>, Self::Error> {
+        guarded!(self, inner => inner.version(device_id))
+    }
+
+    async fn write_if_version(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: &Value,
+        expected: u64,
+    ) -> Result<VersionedWrite, Self::Error> {
+        guarded!(self, inner => inner.write_if_version(device_id, path, payload, expected))
+    }
+
+    async fn read(&mut self, device_id: &str, path: &str) -> Result<Option<Value>, Self::Error> {
+        guarded!(self, inner => inner.read(device_id, path))
+    }
+
+    async fn read_history(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        range: HistoryRange,
+    ) -> Result<Vec<HistoryEntry>, Self::Error> {
+        guarded!(self, inner => inner.read_history(device_id, path, range))
+    }
+
+    async fn read_raw(
+        &mut self,
+        device_id: &str,
+        path: &str,
+    ) -> Result<Option<EncodedPayload>, Self::Error> {
+        guarded!(self, inner => inner.read_raw(device_id, path))
+    }
+
+    async fn read_root(&mut self, device_id: &str) -> Result<Option<Value>, Self::Error> {
+        guarded!(self, inner => inner.read_root(device_id))
+    }
+
+    async fn clear(&mut self, device_id: &str) -> Result<(), Self::Error> {
+        guarded!(self, inner => inner.clear(device_id))
+    }
+
+    async fn notify_encoded(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: EncodedPayload,
+    ) -> Result<(), Self::Error> {
+        self.inner
+            .notify_encoded(device_id, path, payload)
+            .await
+            .map_err(CircuitError::Backend)
+    }
+
+    async fn observer_count(&self, device_id: &str) -> usize {
+        self.inner.observer_count(device_id).await
+    }
+
+    async fn list(&self, device_id: &str) -> Vec<Registration> {
+        self.inner.list(device_id).await
+    }
+
+    async fn list_all(&self) -> Vec<Registration> {
+        self.inner.list_all().await
+    }
+
+    async fn observing_devices(&self, path: &str) -> Vec<String> {
+        self.inner.observing_devices(path).await
+    }
+
+    async fn ping(&mut self) -> Result<(), Self::Error> {
+        guarded!(self, inner => inner.ping())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observer::memory::MemObserver;
+    use serde_json::json;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Fails the next `failures` reads and writes, then stores in memory
+    #[derive(Clone, Debug)]
+    struct Flaky {
+        inner: MemObserver,
+        failures: Arc<AtomicUsize>,
+    }
+
+    impl Flaky {
+        fn new(failures: usize) -> Self {
+            Self {
+                inner: MemObserver::new(),
+                failures: Arc::new(AtomicUsize::new(failures)),
+            }
+        }
+
+        fn fail(&self) -> Result<(), &'static str> {
+            match self
+                .failures
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            {
+                Ok(_) => Err("backend unavailable"),
+                Err(_) => Ok(()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Observer for Flaky {
+        type Error = &'static str;
+
+        async fn register(
+            &mut self,
+            _device_id: &str,
+            _path: &str,
+            _sender: ObserverSender,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn unregister(&mut self, _device_id: &str, _path: &str) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn unregister_all(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn unregister_device(&mut self, _device_id: &str) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn write(
+            &mut self,
+            device_id: &str,
+            path: &str,
+            payload: &Value,
+        ) -> Result<(), Self::Error> {
+            self.fail()?;
+            self.inner
+                .write(device_id, path, payload)
+                .await
+                .map_err(|_| "write failed")
+        }
+        async fn read(
+            &mut self,
+            device_id: &str,
+            path: &str,
+        ) -> Result<Option<Value>, Self::Error> {
+            self.fail()?;
+            self.inner
+                .read(device_id, path)
+                .await
+                .map_err(|_| "read failed")
+        }
+        async fn clear(&mut self, _device_id: &str) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_rides_out_failures() {
+        let mut observer = RetryObserver::new(Flaky::new(2));
+        observer.write("dev", "/temp", &json!(21)).await.unwrap();
+        assert_eq!(
+            observer.read("dev", "/temp").await.unwrap(),
+            Some(json!(21))
+        );
+        assert_eq!(
+            observer.stats(),
+            RetryStats {
+                retries: 2,
+                exhausted: 0
+            }
+        );
+
+        let mut observer = RetryObserver::new(Flaky::new(10)).max_retries(1);
+        assert!(observer.write("dev", "/temp", &json!(21)).await.is_err());
+        assert_eq!(observer.stats().exhausted, 1);
+        assert_eq!(observer.backoff_for(0), Duration::from_millis(50));
+        assert_eq!(observer.backoff_for(10), Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_circuit_opens_and_recovers() {
+        let mut observer = CircuitBreakerObserver::new(Flaky::new(3))
+            .failure_threshold(2)
+            .open_duration(Duration::from_secs(10));
+
+        for _ in 0..2 {
+            assert!(matches!(
+                observer.write("dev", "/temp", &json!(1)).await,
+                Err(CircuitError::Backend(_))
+            ));
+        }
+        // Open: the backend is not called, so its failures are not used up
+        assert!(matches!(
+            observer.read("dev", "/temp").await,
+            Err(CircuitError::Open)
+        ));
+        let stats = observer.stats();
+        assert_eq!(stats.state, CircuitState::Open);
+        assert_eq!((stats.opened, stats.rejected, stats.failures), (1, 1, 2));
+
+        // The probe fails and the circuit opens again
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(matches!(
+            observer.write("dev", "/temp", &json!(1)).await,
+            Err(CircuitError::Backend(_))
+        ));
+        assert_eq!(observer.stats().opened, 2);
+
+        // The next probe succeeds and closes it
+        tokio::time::advance(Duration::from_secs(10)).await;
+        observer.write("dev", "/temp", &json!(2)).await.unwrap();
+        assert_eq!(observer.stats().state, CircuitState::Closed);
+        assert_eq!(observer.read("dev", "/temp").await.unwrap(), Some(json!(2)));
+    }
+}
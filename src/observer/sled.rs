@@ -1,10 +1,17 @@
-use std::{fmt, sync::Arc};
+use std::{collections::VecDeque, fmt, time::SystemTime};
 
 use async_trait::async_trait;
 use serde_json::Value;
+use sled::Transactional;
+use sled::transaction::{ConflictableTransactionError, TransactionError};
 use tokio::sync::mpsc::{Sender, channel};
 
-use super::{Observer, ObserverChannels, ObserverValue};
+use super::{
+    EncodedPayload, Observer, ObserverChannels, ObserverSender, Registration, VersionedWrite,
+    history::{HistoryConfig, HistoryEntry, HistoryRange},
+    metadata::ObservationMetadata,
+    policy::{Enforcement, QuotaExceeded, StoragePolicy},
+};
 
 #[derive(Clone, Debug)]
 pub struct SledObserver {
@@ -12,6 +19,8 @@ pub struct SledObserver {
     channel: Option<Sender<()>>,
     /// Shared channel management for observer notifications.
     pub channels: ObserverChannels,
+    policy: StoragePolicy,
+    history: Option<HistoryConfig>,
 }
 
 impl SledObserver {
@@ -20,8 +29,23 @@ impl SledObserver {
             db: sled::open(path).unwrap(),
             channel: None,
             channels: ObserverChannels::new(),
+            policy: StoragePolicy::new(),
+            history: None,
         }
     }
+
+    /// Enforces size limits and path expiry on every write.
+    pub fn with_policy(mut self, policy: StoragePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Keeps a history of written values in the `history` tree, readable
+    /// with [`Observer::read_history`].
+    pub fn with_history(mut self, config: HistoryConfig) -> Self {
+        self.history = Some(config);
+        self
+    }
 }
 
 #[derive(Debug)]
@@ -30,6 +54,11 @@ pub enum SledObserverError {
     JsonError(serde_json::Error),
     IdNotSet,
     TaskJoinError(String),
+    QuotaExceeded(QuotaExceeded),
+    /// A conditional write found the document at another version
+    VersionConflict {
+        current: u64,
+    },
 }
 
 impl fmt::Display for SledObserverError {
@@ -39,6 +68,10 @@ impl fmt::Display for SledObserverError {
             SledObserverError::JsonError(err) => write!(f, "JSON error: {}", err),
             SledObserverError::IdNotSet => write!(f, "Device ID must be set before use!"),
             SledObserverError::TaskJoinError(msg) => write!(f, "Task join error: {}", msg),
+            SledObserverError::QuotaExceeded(err) => write!(f, "Quota exceeded: {}", err),
+            SledObserverError::VersionConflict { current } => {
+                write!(f, "Document is at version {}", current)
+            }
         }
     }
 }
@@ -50,6 +83,8 @@ impl std::error::Error for SledObserverError {
             SledObserverError::JsonError(err) => Some(err),
             SledObserverError::IdNotSet => None,
             SledObserverError::TaskJoinError(_) => None,
+            SledObserverError::QuotaExceeded(err) => Some(err),
+            SledObserverError::VersionConflict { .. } => None,
         }
     }
 }
@@ -66,12 +101,164 @@ impl From<sled::Error> for SledObserverError {
     }
 }
 
+impl From<QuotaExceeded> for SledObserverError {
+    fn from(err: QuotaExceeded) -> SledObserverError {
+        SledObserverError::QuotaExceeded(err)
+    }
+}
+
 impl From<serde_json::Error> for SledObserverError {
     fn from(err: serde_json::Error) -> SledObserverError {
         SledObserverError::JsonError(err)
     }
 }
 
+/// Name of the sled tree holding value history
+const HISTORY_TREE: &str = "history";
+
+/// Name of the sled tree holding payloads written with `write_raw`
+const RAW_TREE: &str = "raw";
+
+/// Name of the sled tree holding document versions by device ID
+const VERSION_TREE: &str = "versions";
+
+/// Key of a device path in the history and raw trees: device ID and JSON
+/// pointer, NUL separated
+fn path_key(device_id: &str, pointer: &str) -> String {
+    format!("{}\0{}", device_id, pointer)
+}
+
+/// Append the written values to their paths' history ring buffers
+fn append_history(
+    db: &sled::Db,
+    config: &HistoryConfig,
+    device_id: &str,
+    updates: &[(String, Value)],
+    timestamp: SystemTime,
+) -> Result<(), SledObserverError> {
+    let tree = db.open_tree(HISTORY_TREE)?;
+    for (path, payload) in updates {
+        let pointer = super::path_to_pointer(path);
+        if !config.records(&pointer) {
+            continue;
+        }
+
+        let entry = HistoryEntry {
+            timestamp,
+            value: payload.clone(),
+        };
+        tree.update_and_fetch(path_key(device_id, &pointer), |stored| {
+            let mut buffer: VecDeque<HistoryEntry> = stored
+                .and_then(|bytes| serde_json::from_slice(bytes).ok())
+                .unwrap_or_default();
+            config.push(&mut buffer, entry.clone());
+            serde_json::to_vec(&buffer).ok()
+        })?;
+    }
+    Ok(())
+}
+
+/// Merge `updates` in order into the document stored for `device_id` with
+/// `merge` and bump its version, in one transaction that is retried if
+/// another writer got there first.
+/// The storage policy is applied to every attempt before it is stored. With
+/// `expected` set, the document is only changed at that version.
+///
+/// Returns the document before and after the merge and its new version.
+fn merge_and_swap(
+    db: &sled::Db,
+    policy: &StoragePolicy,
+    device_id: &str,
+    updates: &[(String, Value)],
+    merge: fn(&mut Value, &Value),
+    expected: Option<u64>,
+) -> Result<(Value, Value, Enforcement, u64), SledObserverError> {
+    let key = device_id.as_bytes();
+    let paths: Vec<String> = updates.iter().map(|(path, _)| path.clone()).collect();
+    let documents: &sled::Tree = db;
+    let versions = db.open_tree(VERSION_TREE)?;
+
+    let abort = |err: SledObserverError| ConflictableTransactionError::Abort(err);
+    (documents, &versions)
+        .transaction(|(documents, versions)| {
+            let version = versions.get(key)?.map_or(0, |bytes| decode_version(&bytes));
+            if let Some(expected) = expected
+                && expected != version
+            {
+                return Err(abort(SledObserverError::VersionConflict {
+                    current: version,
+                }));
+            }
+
+            let current_value = match documents.get(key)? {
+                Some(bytes) => serde_json::from_slice::<Value>(&bytes).unwrap_or_else(|e| {
+                    tracing::warn!("Unable to serialize. Err: {}", e);
+                    Value::Null
+                }),
+                None => Value::Null,
+            };
+
+            let mut value = current_value.clone();
+            for (path, payload) in updates {
+                merge(&mut value, &super::path_to_json(path, payload));
+            }
+            tracing::debug!("Merged value: {:?}", value);
+
+            let enforcement = policy
+                .enforce(device_id, &mut value, &paths)
+                .map_err(|e| abort(e.into()))?;
+
+            let encoded = serde_json::to_vec(&value).map_err(|e| abort(e.into()))?;
+            documents.insert(key, encoded)?;
+            versions.insert(key, (version + 1).to_be_bytes().to_vec())?;
+            Ok((current_value, value, enforcement, version + 1))
+        })
+        .map_err(|e| match e {
+            TransactionError::Abort(err) => err,
+            TransactionError::Storage(err) => err.into(),
+        })
+}
+
+/// Decode a version stored in the versions tree
+fn decode_version(bytes: &[u8]) -> u64 {
+    bytes.try_into().map_or(0, u64::from_be_bytes)
+}
+
+impl SledObserver {
+    /// Merges the updates into the stored document with `merge`, recording
+    /// history and the raw payload if one is given. Returns the document
+    /// before and after the merge and its new version.
+    async fn store(
+        &mut self,
+        device_id: &str,
+        updates: Vec<(String, Value)>,
+        raw: Option<(String, Vec<u8>)>,
+        merge: fn(&mut Value, &Value),
+        expected: Option<u64>,
+    ) -> Result<(Value, Value, u64), SledObserverError> {
+        tracing::debug!("New values: {:?} for device: {}", updates, device_id);
+
+        let db = self.db.clone();
+        let policy = self.policy.clone();
+        let did = device_id.to_string();
+        let history = self.history.clone();
+        let (current_value, value, enforcement, version) = tokio::task::spawn_blocking(move || {
+            let merged = merge_and_swap(&db, &policy, &did, &updates, merge, expected)?;
+            if let Some(config) = history {
+                append_history(&db, &config, &did, &updates, SystemTime::now())?;
+            }
+            if let Some((key, stored)) = raw {
+                db.open_tree(RAW_TREE)?.insert(key, stored)?;
+            }
+            Ok::<_, SledObserverError>(merged)
+        })
+        .await??;
+        self.policy.commit(device_id, enforcement);
+
+        Ok((current_value, value, version))
+    }
+}
+
 #[async_trait]
 impl Observer for SledObserver {
     type Error = SledObserverError;
@@ -80,9 +267,22 @@ impl Observer for SledObserver {
         &mut self,
         device_id: &str,
         path: &str,
-        sender: Arc<Sender<ObserverValue>>,
+        sender: ObserverSender,
     ) -> Result<(), Self::Error> {
-        self.channels.register(device_id, path, sender).await;
+        self.register_with_metadata(device_id, path, sender, ObservationMetadata::new())
+            .await
+    }
+
+    async fn register_with_metadata(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        sender: ObserverSender,
+        metadata: ObservationMetadata,
+    ) -> Result<(), Self::Error> {
+        self.channels
+            .register_with_metadata(device_id, path, sender, metadata)
+            .await;
 
         // Spawn watcher task if not already running.
         // All change notifications are handled in write().
@@ -145,67 +345,162 @@ impl Observer for SledObserver {
         Ok(())
     }
 
+    async fn unregister_sender(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        sender: &ObserverSender,
+    ) -> Result<(), Self::Error> {
+        let all_empty = self
+            .channels
+            .unregister_sender(device_id, path, sender)
+            .await;
+
+        if all_empty {
+            if let Some(channel) = &self.channel {
+                let _ = channel.send(()).await;
+            }
+            self.channel = None;
+        }
+
+        Ok(())
+    }
+
+    async fn unregister_connection(
+        &mut self,
+        device_id: &str,
+        sender: &ObserverSender,
+    ) -> Result<(), Self::Error> {
+        let all_empty = self.channels.unregister_connection(device_id, sender).await;
+
+        if all_empty {
+            if let Some(channel) = &self.channel {
+                let _ = channel.send(()).await;
+            }
+            self.channel = None;
+        }
+
+        Ok(())
+    }
+
     async fn write(
         &mut self,
         device_id: &str,
         path: &str,
         payload: &Value,
     ) -> Result<(), Self::Error> {
-        let new_value = super::path_to_json(path, payload);
-
-        tracing::debug!("New value: {:?} for path: {}", new_value, path);
+        self.write_batch(device_id, vec![(path.to_string(), payload.clone())])
+            .await
+    }
 
-        // Phase 1: Read existing value and merge (blocking DB read)
-        let db = self.db.clone();
-        let did = device_id.to_string();
-        let nv = new_value.clone();
-        let (value, current_value) = tokio::task::spawn_blocking(move || {
-            let mut current_value = Value::Null;
-            let value = if let Ok(Some(stored_value)) = db.get(did.as_bytes()) {
-                match serde_json::from_slice::<Value>(&stored_value) {
-                    Ok(stored_value) => {
-                        current_value = stored_value.clone();
-                        let mut merged_value = stored_value;
-                        super::merge_json(&mut merged_value, &nv);
-                        tracing::debug!("Merged value: {:?}", merged_value);
-                        merged_value
-                    }
-                    Err(e) => {
-                        tracing::warn!("Unable to serialize. Err: {}", e);
-                        nv
-                    }
-                }
-            } else {
-                nv
-            };
-            (value, current_value)
-        })
-        .await?;
+    /// Merges all updates into the stored document in a single
+    /// transaction, so concurrent writers never lose each other's changes.
+    /// Observers are notified once, after the write succeeds.
+    async fn write_batch(
+        &mut self,
+        device_id: &str,
+        updates: Vec<(String, Value)>,
+    ) -> Result<(), Self::Error> {
+        let (current_value, value, _) = self
+            .store(device_id, updates, None, super::merge_json, None)
+            .await?;
 
         // Notify observers of changes
         self.channels
             .notify(device_id, &current_value, &value)
             .await;
 
-        // Phase 3: Write merged value back (blocking DB write)
+        Ok(())
+    }
+
+    /// Applies the patch in the same transaction as
+    /// [`write_batch`](Observer::write_batch).
+    async fn patch(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        patch: &Value,
+    ) -> Result<(), Self::Error> {
+        let updates = vec![(path.to_string(), patch.clone())];
+        let (current_value, value, _) = self
+            .store(device_id, updates, None, super::merge_patch, None)
+            .await?;
+
+        self.channels
+            .notify(device_id, &current_value, &value)
+            .await;
+
+        Ok(())
+    }
+
+    /// Stores the payload bytes in the `raw` tree next to the merged
+    /// document.
+    async fn write_raw(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: EncodedPayload,
+    ) -> Result<(), Self::Error> {
+        let updates = vec![(path.to_string(), payload.to_json())];
+        let raw = (
+            path_key(device_id, &super::path_to_pointer(path)),
+            payload.to_stored(),
+        );
+        let (current_value, value, _) = self
+            .store(device_id, updates, Some(raw), super::merge_json, None)
+            .await?;
+
+        self.channels
+            .notify_raw(device_id, &current_value, &value, path, &payload)
+            .await;
+
+        Ok(())
+    }
+
+    /// Checks the version in the same transaction as the write, so of two
+    /// writers expecting the same version only one succeeds.
+    async fn write_if_version(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: &Value,
+        expected: u64,
+    ) -> Result<VersionedWrite, Self::Error> {
+        let updates = vec![(path.to_string(), payload.clone())];
+        let (current_value, value, version) = match self
+            .store(device_id, updates, None, super::merge_json, Some(expected))
+            .await
+        {
+            Ok(stored) => stored,
+            Err(SledObserverError::VersionConflict { current }) => {
+                return Ok(VersionedWrite::Conflict {
+                    current: Some(current),
+                });
+            }
+            Err(e) => return Err(e),
+        };
+
+        self.channels
+            .notify(device_id, &current_value, &value)
+            .await;
+
+        Ok(VersionedWrite::Written(version))
+    }
+
+    async fn version(&mut self, device_id: &str) -> Result<Option<u64>, Self::Error> {
         let db = self.db.clone();
         let did = device_id.to_string();
-        let val = value.clone();
-        tokio::task::spawn_blocking(move || -> Result<(), SledObserverError> {
-            let v = serde_json::to_vec(&val)?;
-            db.insert(did.as_bytes(), v)?;
-            tracing::debug!("Value successfully written to sled");
-            Ok(())
+        tokio::task::spawn_blocking(move || -> Result<Option<u64>, SledObserverError> {
+            let stored = db.open_tree(VERSION_TREE)?.get(did.as_bytes())?;
+            Ok(Some(stored.map_or(0, |bytes| decode_version(&bytes))))
         })
-        .await??;
-
-        Ok(())
+        .await?
     }
 
     async fn read(&mut self, device_id: &str, path: &str) -> Result<Option<Value>, Self::Error> {
         let db = self.db.clone();
         let did = device_id.to_string();
-        let p = path.to_string();
+        let p = super::path_to_pointer(path);
         tokio::task::spawn_blocking(move || -> Result<Option<Value>, SledObserverError> {
             match db.get(did.as_bytes()) {
                 Ok(Some(value)) => {
@@ -225,26 +520,115 @@ impl Observer for SledObserver {
         .await?
     }
 
+    async fn read_raw(
+        &mut self,
+        device_id: &str,
+        path: &str,
+    ) -> Result<Option<EncodedPayload>, Self::Error> {
+        let db = self.db.clone();
+        let did = device_id.to_string();
+        let pointer = super::path_to_pointer(path);
+        tokio::task::spawn_blocking(
+            move || -> Result<Option<EncodedPayload>, SledObserverError> {
+                let tree = db.open_tree(RAW_TREE)?;
+                let Some(raw) = tree
+                    .get(path_key(&did, &pointer))?
+                    .and_then(|stored| EncodedPayload::from_stored(&stored))
+                else {
+                    return Ok(None);
+                };
+                let Some(document) = db.get(did.as_bytes())? else {
+                    return Ok(None);
+                };
+                let document: Value = serde_json::from_slice(&document)?;
+                Ok(raw.is_current(&document, &pointer).then_some(raw))
+            },
+        )
+        .await?
+    }
+
     async fn clear(&mut self, device_id: &str) -> Result<(), Self::Error> {
         let db = self.db.clone();
         let did = device_id.to_string();
         tokio::task::spawn_blocking(move || {
             let _ = db.remove(did.as_bytes());
+            // Keep counting, so versions read before the clear don't match again
+            if let Ok(versions) = db.open_tree(VERSION_TREE) {
+                let _ = versions.update_and_fetch(did.as_bytes(), |stored| {
+                    let version = stored.map_or(0, decode_version);
+                    Some((version + 1).to_be_bytes().to_vec())
+                });
+            }
+            for name in [HISTORY_TREE, RAW_TREE] {
+                if let Ok(tree) = db.open_tree(name) {
+                    for key in tree.scan_prefix(path_key(&did, "")).keys().flatten() {
+                        let _ = tree.remove(key);
+                    }
+                }
+            }
         })
         .await
         .map_err(SledObserverError::from)?;
 
+        self.policy.forget(device_id);
         Ok(())
     }
 
+    async fn notify_encoded(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: EncodedPayload,
+    ) -> Result<(), Self::Error> {
+        self.channels.notify_encoded(device_id, path, payload).await;
+        Ok(())
+    }
+
+    async fn read_history(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        range: HistoryRange,
+    ) -> Result<Vec<HistoryEntry>, Self::Error> {
+        if self.history.is_none() {
+            return Ok(Vec::new());
+        }
+
+        let db = self.db.clone();
+        let key = path_key(device_id, &super::path_to_pointer(path));
+        tokio::task::spawn_blocking(move || -> Result<Vec<HistoryEntry>, SledObserverError> {
+            let tree = db.open_tree(HISTORY_TREE)?;
+            match tree.get(key)? {
+                Some(bytes) => {
+                    let buffer: VecDeque<HistoryEntry> = serde_json::from_slice(&bytes)?;
+                    Ok(range.select(buffer.iter()))
+                }
+                None => Ok(Vec::new()),
+            }
+        })
+        .await?
+    }
+
     async fn observer_count(&self, device_id: &str) -> usize {
         self.channels.device_observer_count(device_id).await
     }
+
+    async fn list(&self, device_id: &str) -> Vec<Registration> {
+        self.channels.list(device_id).await
+    }
+
+    async fn list_all(&self) -> Vec<Registration> {
+        self.channels.list_all().await
+    }
+
+    async fn observing_devices(&self, path: &str) -> Vec<String> {
+        self.channels.observing_devices(path).await
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::{sync::Arc, time::Duration};
 
     use serde_json::json;
     use tokio::time::sleep;
@@ -305,7 +689,7 @@ mod tests {
 
         observer.clear("123").await.unwrap();
 
-        let (tx, mut rx) = tokio::sync::mpsc::channel::<ObserverValue>(10);
+        let (tx, mut rx) = crate::observer::queue::notification_channel(10, Default::default());
 
         let fut = tokio::spawn(async move {
             if let Some(r) = rx.recv().await {
@@ -355,4 +739,221 @@ mod tests {
         assert!(observer.channels.is_empty().await);
         assert!(observer.channel.is_none());
     }
+
+    #[tokio::test]
+    async fn test_sled_observer_write_batch_notifies_once() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("sled_db");
+        let mut observer = SledObserver::new(db_path.to_str().unwrap());
+
+        let (tx, mut rx) = crate::observer::queue::notification_channel(10, Default::default());
+        observer
+            .register("123", "/sensors", Arc::new(tx))
+            .await
+            .unwrap();
+
+        observer
+            .write_batch(
+                "123",
+                vec![
+                    ("/sensors/temp".to_string(), json!(21.5)),
+                    ("/sensors/humidity".to_string(), json!(40)),
+                    ("/config/interval".to_string(), json!(60)),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let notification = rx.recv().await.unwrap();
+        assert_eq!(notification.value, json!({"temp": 21.5, "humidity": 40}));
+        assert!(rx.try_recv().is_none());
+
+        let result = observer.read("123", "/config/interval").await.unwrap();
+        assert_eq!(result, Some(json!(60)));
+    }
+
+    #[tokio::test]
+    async fn test_sled_observer_concurrent_writes_not_lost() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("sled_db");
+        let observer = SledObserver::new(db_path.to_str().unwrap());
+
+        let writers: Vec<_> = (0..16)
+            .map(|i| {
+                let mut observer = observer.clone();
+                tokio::spawn(async move {
+                    observer
+                        .write("123", &format!("/key_{}", i), &json!(i))
+                        .await
+                        .unwrap();
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.await.unwrap();
+        }
+
+        let mut observer = observer;
+        for i in 0..16 {
+            let result = observer.read("123", &format!("/key_{}", i)).await.unwrap();
+            assert_eq!(result, Some(json!(i)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sled_observer_read_pointer_and_root() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("sled_db");
+        let mut observer = SledObserver::new(db_path.to_str().unwrap());
+
+        observer
+            .write("456", "/sensors/temp", &json!(21.5))
+            .await
+            .unwrap();
+
+        // Paths resolve the same with or without leading/trailing slashes
+        let result = observer.read("456", "sensors/temp/").await.unwrap();
+        assert_eq!(result, Some(json!(21.5)));
+        assert_eq!(
+            observer.read("456", "/sensors/missing").await.unwrap(),
+            None
+        );
+
+        let root = observer.read_root("456").await.unwrap();
+        assert_eq!(root, Some(json!({"sensors": {"temp": 21.5}})));
+        assert_eq!(observer.read("456", "/").await.unwrap(), root);
+        assert_eq!(observer.read_root("unknown").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_sled_observer_raw_payload() {
+        use coap_lite::ContentFormat;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("sled_db");
+        let mut observer = SledObserver::new(db_path.to_str().unwrap());
+
+        let (tx, mut rx) = crate::observer::queue::notification_channel(10, Default::default());
+        observer.register("raw", "/fw", Arc::new(tx)).await.unwrap();
+
+        // {"hash": h'cafe'}
+        let payload = EncodedPayload::new(
+            ContentFormat::ApplicationCBOR,
+            vec![0xa1, 0x64, b'h', b'a', b's', b'h', 0x42, 0xca, 0xfe],
+        );
+        observer
+            .write_raw("raw", "/fw", payload.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(rx.recv().await.unwrap().encoded, Some(payload.clone()));
+        assert_eq!(
+            observer.read("raw", "/fw").await.unwrap(),
+            Some(json!({"hash": "yv4="}))
+        );
+        assert_eq!(
+            observer.read_raw("raw", "/fw").await.unwrap(),
+            Some(payload)
+        );
+
+        observer.clear("raw").await.unwrap();
+        assert_eq!(observer.read_raw("raw", "/fw").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_sled_observer_history() {
+        use crate::observer::history::{HistoryConfig, HistoryRange};
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("sled_db");
+        let mut observer = SledObserver::new(db_path.to_str().unwrap())
+            .with_history(HistoryConfig::new(2).path("/sensors"));
+        for i in 0..3 {
+            observer
+                .write("hist", "/sensors/temp", &json!(i))
+                .await
+                .unwrap();
+        }
+        observer
+            .write("hist", "/config", &json!("untracked"))
+            .await
+            .unwrap();
+
+        let history = observer
+            .read_history("hist", "/sensors/temp", HistoryRange::all())
+            .await
+            .unwrap();
+        let values: Vec<Value> = history.into_iter().map(|entry| entry.value).collect();
+        assert_eq!(values, [json!(1), json!(2)]);
+
+        let last = observer
+            .read_history("hist", "sensors/temp", HistoryRange::last(1))
+            .await
+            .unwrap();
+        assert_eq!(last[0].value, json!(2));
+
+        let untracked = observer
+            .read_history("hist", "/config", HistoryRange::all())
+            .await
+            .unwrap();
+        assert!(untracked.is_empty());
+
+        observer.clear("hist").await.unwrap();
+        let cleared = observer
+            .read_history("hist", "/sensors/temp", HistoryRange::all())
+            .await
+            .unwrap();
+        assert!(cleared.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sled_observer_patch() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("sled_patch_db");
+        let mut observer = SledObserver::new(db_path.to_str().unwrap());
+
+        observer
+            .write("dev", "/config", &json!({"led": "on", "interval": 30}))
+            .await
+            .unwrap();
+        observer
+            .patch("dev", "/config", &json!({"led": null, "interval": 60}))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            observer.read("dev", "/config").await.unwrap(),
+            Some(json!({"interval": 60}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sled_observer_versions() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("sled_version_db");
+        let mut observer = SledObserver::new(db_path.to_str().unwrap());
+        assert_eq!(observer.version("dev").await.unwrap(), Some(0));
+
+        observer
+            .write("dev", "/config", &json!({"interval": 30}))
+            .await
+            .unwrap();
+        assert_eq!(observer.version("dev").await.unwrap(), Some(1));
+
+        // Two writers that both read version 1: exactly one wins
+        let mut first = observer.clone();
+        let mut second = observer.clone();
+        let (sixty, ninety) = (json!({"interval": 60}), json!({"interval": 90}));
+        let (a, b) = tokio::join!(
+            first.write_if_version("dev", "/config", &sixty, 1),
+            second.write_if_version("dev", "/config", &ninety, 1),
+        );
+        let mut outcomes = [a.unwrap(), b.unwrap()];
+        outcomes.sort_by_key(|outcome| matches!(outcome, VersionedWrite::Conflict { .. }));
+        assert_eq!(outcomes[0], VersionedWrite::Written(2));
+        assert_eq!(outcomes[1], VersionedWrite::Conflict { current: Some(2) });
+
+        observer.clear("dev").await.unwrap();
+        assert_eq!(observer.version("dev").await.unwrap(), Some(3));
+    }
 }
@@ -0,0 +1,760 @@
+//! An in-memory cache in front of a durable observer backend.
+//!
+//! A [`TieredObserver`] keeps device documents in a fast layer, typically a
+//! [`MemObserver`](super::memory::MemObserver), and persists them in a
+//! durable layer such as sled or Redis. Reads, registrations and
+//! notifications are served by the fast layer; the durable layer only
+//! stores. A device's document is loaded from the durable layer the first
+//! time the device is accessed, so the cache survives restarts.
+//!
+//! Writes reach the durable layer in one of two ways:
+//!
+//! - **Write-through** ([`TieredObserver::new`]): the durable layer is
+//!   written first and the fast layer, which notifies observers, only once
+//!   that succeeded. A write that returns `Ok` is persisted, and a failed
+//!   write is seen by no one.
+//! - **Write-behind** ([`TieredObserver::write_behind`]): the fast layer is
+//!   written and observers notified right away, and the write is queued for
+//!   a background task that applies it to the durable layer in order.
+//!   Notifications no longer wait for the durable backend, but writes still
+//!   queued when the process dies are lost, and failures of the durable
+//!   layer are only logged and counted in [`TieredStats::failed`].
+//!
+//! The durable layer is cloned for the background writer, so its clones
+//! must share storage, as those of the sled and redb backends do.
+//! Operations on one device are applied to both layers in the same order.
+//! Versions and raw payloads come from the fast layer, so versions count
+//! changes since the device was loaded. History is read from the durable
+//! layer, which keeps it across restarts.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use super::history::{HistoryEntry, HistoryRange};
+use super::metadata::ObservationMetadata;
+use super::{EncodedPayload, Observer, ObserverSender, Registration, VersionedWrite};
+use crate::task::BackgroundTask;
+
+/// Error returned by a [`TieredObserver`]
+#[derive(Debug)]
+pub enum TieredError<F, D> {
+    /// The fast layer failed
+    Fast(F),
+    /// The durable layer failed
+    Durable(D),
+}
+
+impl<F: fmt::Debug, D: fmt::Debug> fmt::Display for TieredError<F, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TieredError::Fast(e) => write!(f, "Fast observer layer error: {:?}", e),
+            TieredError::Durable(e) => write!(f, "Durable observer layer error: {:?}", e),
+        }
+    }
+}
+
+impl<F: fmt::Debug, D: fmt::Debug> std::error::Error for TieredError<F, D> {}
+
+/// Snapshot of the write-behind queue of a [`TieredObserver`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TieredStats {
+    /// Writes waiting to be applied to the durable layer
+    pub queued: usize,
+    /// Queued writes the durable layer failed to apply
+    pub failed: u64,
+}
+
+/// A write waiting to be applied to the durable layer
+#[derive(Debug)]
+enum Pending {
+    Write {
+        device_id: String,
+        path: String,
+        value: Value,
+    },
+    Batch {
+        device_id: String,
+        updates: Vec<(String, Value)>,
+    },
+    Raw {
+        device_id: String,
+        path: String,
+        payload: EncodedPayload,
+    },
+    Patch {
+        device_id: String,
+        path: String,
+        patch: Value,
+    },
+    Clear {
+        device_id: String,
+    },
+}
+
+impl Pending {
+    async fn apply<D: Observer>(self, durable: &mut D) -> Result<(), D::Error> {
+        match self {
+            Pending::Write {
+                device_id,
+                path,
+                value,
+            } => durable.write(&device_id, &path, &value).await,
+            Pending::Batch { device_id, updates } => durable.write_batch(&device_id, updates).await,
+            Pending::Raw {
+                device_id,
+                path,
+                payload,
+            } => durable.write_raw(&device_id, &path, payload).await,
+            Pending::Patch {
+                device_id,
+                path,
+                patch,
+            } => durable.patch(&device_id, &path, &patch).await,
+            Pending::Clear { device_id } => durable.clear(&device_id).await,
+        }
+    }
+
+    /// Apply the write in the background, counting failures in `failed`
+    async fn persist<D: Observer>(self, durable: &mut D, failed: &AtomicU64) {
+        if let Err(error) = self.apply(durable).await {
+            tracing::warn!(?error, "observer.write_behind_failed");
+            failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Per-device lock, holding whether the device was loaded into the fast
+/// layer
+type DeviceLock = Arc<tokio::sync::Mutex<bool>>;
+
+/// An observer caching a durable backend in a fast one, see the
+/// [module documentation](self)
+///
+/// Clones share the fast layer, so every connection sees the same cache.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use coapum::observer::memory::MemObserver;
+/// use coapum::observer::tiered::TieredObserver;
+/// use coapum::RouterBuilder;
+///
+/// # async fn example<D: coapum::observer::Observer>(durable: D) {
+/// // Persist in the background; `writer` must be kept for as long as the
+/// // server runs, and flushes the queue when shut down
+/// let (observer, writer) = TieredObserver::write_behind(MemObserver::new(), durable, 1024);
+/// let router = RouterBuilder::new((), observer).build();
+/// // ... serve ...
+/// writer.shutdown().await;
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TieredObserver<F, D> {
+    fast: Arc<tokio::sync::Mutex<F>>,
+    durable: D,
+    behind: Option<mpsc::Sender<Pending>>,
+    devices: Arc<Mutex<HashMap<String, DeviceLock>>>,
+    failed: Arc<AtomicU64>,
+}
+
+impl<F: Observer, D: Observer> TieredObserver<F, D> {
+    /// Cache `durable` in `fast`, writing through to `durable`
+    pub fn new(fast: F, durable: D) -> Self {
+        Self {
+            fast: Arc::new(tokio::sync::Mutex::new(fast)),
+            durable,
+            behind: None,
+            devices: Arc::default(),
+            failed: Arc::default(),
+        }
+    }
+
+    /// Cache `durable` in `fast`, persisting writes in the background
+    ///
+    /// Up to `queue` writes wait for the durable layer; further writes wait
+    /// for room. Shutting down the returned task applies the writes already
+    /// queued and makes later writes go through to the durable layer.
+    /// Dropping it does the same without waiting for the queue to drain.
+    pub fn write_behind(fast: F, durable: D, queue: usize) -> (Self, BackgroundTask) {
+        let (tx, mut rx) = mpsc::channel::<Pending>(queue.max(1));
+        let mut observer = Self::new(fast, durable);
+        observer.behind = Some(tx);
+
+        let mut durable = observer.durable.clone();
+        let failed = observer.failed.clone();
+        let task = BackgroundTask::spawn(|mut stop| async move {
+            loop {
+                tokio::select! {
+                    _ = &mut stop => break,
+                    pending = rx.recv() => match pending {
+                        Some(pending) => pending.persist(&mut durable, &failed).await,
+                        None => return,
+                    },
+                }
+            }
+            // Flush what was queued before the stop
+            rx.close();
+            while let Some(pending) = rx.recv().await {
+                pending.persist(&mut durable, &failed).await;
+            }
+        });
+        (observer, task)
+    }
+
+    /// State of the write-behind queue, shared by this observer and its
+    /// clones
+    pub fn stats(&self) -> TieredStats {
+        TieredStats {
+            queued: self
+                .behind
+                .as_ref()
+                .map_or(0, |tx| tx.max_capacity() - tx.capacity()),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The durable layer
+    pub fn durable(&self) -> &D {
+        &self.durable
+    }
+
+    fn device(&self, device_id: &str) -> DeviceLock {
+        self.devices
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(device_id.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Load the device's document into the fast layer unless `loaded`
+    async fn load(
+        &mut self,
+        device_id: &str,
+        loaded: &mut bool,
+    ) -> Result<(), TieredError<F::Error, D::Error>> {
+        if *loaded {
+            return Ok(());
+        }
+        let document = self
+            .durable
+            .read_root(device_id)
+            .await
+            .map_err(TieredError::Durable)?;
+        if let Some(document) = document {
+            self.fast
+                .lock()
+                .await
+                .write(device_id, "/", &document)
+                .await
+                .map_err(TieredError::Fast)?;
+        }
+        *loaded = true;
+        Ok(())
+    }
+
+    /// Whether writes go to the durable layer before the fast one
+    fn writes_through(&self) -> bool {
+        self.behind.is_none()
+    }
+
+    /// Queue a write for the durable layer, if writing behind
+    async fn queue(&mut self, pending: Pending) -> Result<(), TieredError<F::Error, D::Error>> {
+        let Some(behind) = &self.behind else {
+            return Ok(());
+        };
+        match behind.send(pending).await {
+            Ok(()) => Ok(()),
+            // The writer was shut down: write through instead
+            Err(mpsc::error::SendError(pending)) => pending
+                .apply(&mut self.durable)
+                .await
+                .map_err(TieredError::Durable),
+        }
+    }
+}
+
+#[async_trait]
+impl<F: Observer, D: Observer> Observer for TieredObserver<F, D> {
+    type Error = TieredError<F::Error, D::Error>;
+
+    async fn register(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        sender: ObserverSender,
+    ) -> Result<(), Self::Error> {
+        self.register_with_metadata(device_id, path, sender, ObservationMetadata::new())
+            .await
+    }
+
+    async fn register_with_metadata(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        sender: ObserverSender,
+        metadata: ObservationMetadata,
+    ) -> Result<(), Self::Error> {
+        let device = self.device(device_id);
+        let mut loaded = device.lock().await;
+        self.load(device_id, &mut loaded).await?;
+        self.fast
+            .lock()
+            .await
+            .register_with_metadata(device_id, path, sender, metadata)
+            .await
+            .map_err(TieredError::Fast)
+    }
+
+    async fn unregister(&mut self, device_id: &str, path: &str) -> Result<(), Self::Error> {
+        self.fast
+            .lock()
+            .await
+            .unregister(device_id, path)
+            .await
+            .map_err(TieredError::Fast)
+    }
+
+    async fn unregister_all(&mut self) -> Result<(), Self::Error> {
+        self.fast
+            .lock()
+            .await
+            .unregister_all()
+            .await
+            .map_err(TieredError::Fast)
+    }
+
+    async fn unregister_device(&mut self, device_id: &str) -> Result<(), Self::Error> {
+        self.fast
+            .lock()
+            .await
+            .unregister_device(device_id)
+            .await
+            .map_err(TieredError::Fast)
+    }
+
+    async fn unregister_sender(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        sender: &ObserverSender,
+    ) -> Result<(), Self::Error> {
+        self.fast
+            .lock()
+            .await
+            .unregister_sender(device_id, path, sender)
+            .await
+            .map_err(TieredError::Fast)
+    }
+
+    async fn unregister_connection(
+        &mut self,
+        device_id: &str,
+        sender: &ObserverSender,
+    ) -> Result<(), Self::Error> {
+        self.fast
+            .lock()
+            .await
+            .unregister_connection(device_id, sender)
+            .await
+            .map_err(TieredError::Fast)
+    }
+
+    async fn write(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: &Value,
+    ) -> Result<(), Self::Error> {
+        let device = self.device(device_id);
+        let mut loaded = device.lock().await;
+        self.load(device_id, &mut loaded).await?;
+        if self.writes_through() {
+            self.durable
+                .write(device_id, path, payload)
+                .await
+                .map_err(TieredError::Durable)?;
+        }
+        self.fast
+            .lock()
+            .await
+            .write(device_id, path, payload)
+            .await
+            .map_err(TieredError::Fast)?;
+        self.queue(Pending::Write {
+            device_id: device_id.to_string(),
+            path: path.to_string(),
+            value: payload.clone(),
+        })
+        .await
+    }
+
+    async fn write_batch(
+        &mut self,
+        device_id: &str,
+        updates: Vec<(String, Value)>,
+    ) -> Result<(), Self::Error> {
+        let device = self.device(device_id);
+        let mut loaded = device.lock().await;
+        self.load(device_id, &mut loaded).await?;
+        if self.writes_through() {
+            self.durable
+                .write_batch(device_id, updates.clone())
+                .await
+                .map_err(TieredError::Durable)?;
+        }
+        self.fast
+            .lock()
+            .await
+            .write_batch(device_id, updates.clone())
+            .await
+            .map_err(TieredError::Fast)?;
+        self.queue(Pending::Batch {
+            device_id: device_id.to_string(),
+            updates,
+        })
+        .await
+    }
+
+    async fn write_raw(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: EncodedPayload,
+    ) -> Result<(), Self::Error> {
+        let device = self.device(device_id);
+        let mut loaded = device.lock().await;
+        self.load(device_id, &mut loaded).await?;
+        if self.writes_through() {
+            self.durable
+                .write_raw(device_id, path, payload.clone())
+                .await
+                .map_err(TieredError::Durable)?;
+        }
+        self.fast
+            .lock()
+            .await
+            .write_raw(device_id, path, payload.clone())
+            .await
+            .map_err(TieredError::Fast)?;
+        self.queue(Pending::Raw {
+            device_id: device_id.to_string(),
+            path: path.to_string(),
+            payload,
+        })
+        .await
+    }
+
+    async fn patch(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        patch: &Value,
+    ) -> Result<(), Self::Error> {
+        let device = self.device(device_id);
+        let mut loaded = device.lock().await;
+        self.load(device_id, &mut loaded).await?;
+        if self.writes_through() {
+            self.durable
+                .patch(device_id, path, patch)
+                .await
+                .map_err(TieredError::Durable)?;
+        }
+        self.fast
+            .lock()
+            .await
+            .patch(device_id, path, patch)
+            .await
+            .map_err(TieredError::Fast)?;
+        self.queue(Pending::Patch {
+            device_id: device_id.to_string(),
+            path: path.to_string(),
+            patch: patch.clone(),
+        })
+        .await
+    }
+
+    async fn version(&mut self, device_id: &str) -> Result<Option<u64>, Self::Error> {
+        let device = self.device(device_id);
+        let mut loaded = device.lock().await;
+        self.load(device_id, &mut loaded).await?;
+        self.fast
+            .lock()
+            .await
+            .version(device_id)
+            .await
+            .map_err(TieredError::Fast)
+    }
+
+    async fn write_if_version(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: &Value,
+        expected: u64,
+    ) -> Result<VersionedWrite, Self::Error> {
+        let device = self.device(device_id);
+        let mut loaded = device.lock().await;
+        self.load(device_id, &mut loaded).await?;
+        // The version lives in the fast layer, so it decides; the device
+        // lock keeps it from changing before the durable write
+        let current = self
+            .fast
+            .lock()
+            .await
+            .version(device_id)
+            .await
+            .map_err(TieredError::Fast)?;
+        if current != Some(expected) {
+            return Ok(VersionedWrite::Conflict { current });
+        }
+        if self.writes_through() {
+            self.durable
+                .write(device_id, path, payload)
+                .await
+                .map_err(TieredError::Durable)?;
+        }
+        let written = self
+            .fast
+            .lock()
+            .await
+            .write_if_version(device_id, path, payload, expected)
+            .await
+            .map_err(TieredError::Fast)?;
+        if matches!(written, VersionedWrite::Written(_)) {
+            self.queue(Pending::Write {
+                device_id: device_id.to_string(),
+                path: path.to_string(),
+                value: payload.clone(),
+            })
+            .await?;
+        }
+        Ok(written)
+    }
+
+    async fn read(&mut self, device_id: &str, path: &str) -> Result<Option<Value>, Self::Error> {
+        let device = self.device(device_id);
+        let mut loaded = device.lock().await;
+        self.load(device_id, &mut loaded).await?;
+        self.fast
+            .lock()
+            .await
+            .read(device_id, path)
+            .await
+            .map_err(TieredError::Fast)
+    }
+
+    async fn read_history(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        range: HistoryRange,
+    ) -> Result<Vec<HistoryEntry>, Self::Error> {
+        self.durable
+            .read_history(device_id, path, range)
+            .await
+            .map_err(TieredError::Durable)
+    }
+
+    async fn read_raw(
+        &mut self,
+        device_id: &str,
+        path: &str,
+    ) -> Result<Option<EncodedPayload>, Self::Error> {
+        let device = self.device(device_id);
+        let mut loaded = device.lock().await;
+        self.load(device_id, &mut loaded).await?;
+        self.fast
+            .lock()
+            .await
+            .read_raw(device_id, path)
+            .await
+            .map_err(TieredError::Fast)
+    }
+
+    async fn read_root(&mut self, device_id: &str) -> Result<Option<Value>, Self::Error> {
+        let device = self.device(device_id);
+        let mut loaded = device.lock().await;
+        self.load(device_id, &mut loaded).await?;
+        self.fast
+            .lock()
+            .await
+            .read_root(device_id)
+            .await
+            .map_err(TieredError::Fast)
+    }
+
+    async fn clear(&mut self, device_id: &str) -> Result<(), Self::Error> {
+        let device = self.device(device_id);
+        let mut loaded = device.lock().await;
+        if self.writes_through() {
+            self.durable
+                .clear(device_id)
+                .await
+                .map_err(TieredError::Durable)?;
+        }
+        self.fast
+            .lock()
+            .await
+            .clear(device_id)
+            .await
+            .map_err(TieredError::Fast)?;
+        // Both layers are empty now, there is nothing left to load
+        *loaded = true;
+        self.queue(Pending::Clear {
+            device_id: device_id.to_string(),
+        })
+        .await
+    }
+
+    async fn notify_encoded(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: EncodedPayload,
+    ) -> Result<(), Self::Error> {
+        self.fast
+            .lock()
+            .await
+            .notify_encoded(device_id, path, payload)
+            .await
+            .map_err(TieredError::Fast)
+    }
+
+    async fn observer_count(&self, device_id: &str) -> usize {
+        self.fast.lock().await.observer_count(device_id).await
+    }
+
+    async fn list(&self, device_id: &str) -> Vec<Registration> {
+        self.fast.lock().await.list(device_id).await
+    }
+
+    async fn list_all(&self) -> Vec<Registration> {
+        self.fast.lock().await.list_all().await
+    }
+
+    async fn observing_devices(&self, path: &str) -> Vec<String> {
+        self.fast.lock().await.observing_devices(path).await
+    }
+
+    async fn ping(&mut self) -> Result<(), Self::Error> {
+        self.fast
+            .lock()
+            .await
+            .ping()
+            .await
+            .map_err(TieredError::Fast)?;
+        self.durable.ping().await.map_err(TieredError::Durable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observer::memory::{MemObserver, MemObserverError};
+    use serde_json::json;
+
+    /// A durable layer whose clones share one store, unlike `MemObserver`'s
+    #[derive(Clone, Debug)]
+    struct Shared(Arc<tokio::sync::Mutex<MemObserver>>);
+
+    impl Shared {
+        fn new() -> Self {
+            Self(Arc::new(tokio::sync::Mutex::new(MemObserver::new())))
+        }
+    }
+
+    #[async_trait]
+    impl Observer for Shared {
+        type Error = MemObserverError;
+
+        async fn register(
+            &mut self,
+            _device_id: &str,
+            _path: &str,
+            _sender: ObserverSender,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn unregister(&mut self, _device_id: &str, _path: &str) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn unregister_all(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn unregister_device(&mut self, _device_id: &str) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn write(
+            &mut self,
+            device_id: &str,
+            path: &str,
+            payload: &Value,
+        ) -> Result<(), Self::Error> {
+            self.0.lock().await.write(device_id, path, payload).await
+        }
+        async fn read(
+            &mut self,
+            device_id: &str,
+            path: &str,
+        ) -> Result<Option<Value>, Self::Error> {
+            self.0.lock().await.read(device_id, path).await
+        }
+        async fn clear(&mut self, device_id: &str) -> Result<(), Self::Error> {
+            self.0.lock().await.clear(device_id).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_through_loads_and_persists() {
+        let mut durable = Shared::new();
+        durable
+            .write("dev", "/config", &json!({"rate": 10}))
+            .await
+            .unwrap();
+
+        let mut observer = TieredObserver::new(MemObserver::new(), durable.clone());
+        assert_eq!(
+            observer.read("dev", "/config/rate").await.unwrap(),
+            Some(json!(10))
+        );
+
+        // Clones share the cache
+        let mut clone = observer.clone();
+        clone.write("dev", "/temp", &json!(21)).await.unwrap();
+        assert_eq!(
+            observer.read("dev", "/temp").await.unwrap(),
+            Some(json!(21))
+        );
+        assert_eq!(durable.read("dev", "/temp").await.unwrap(), Some(json!(21)));
+    }
+
+    #[tokio::test]
+    async fn test_write_behind_flushes_on_shutdown() {
+        let mut durable = Shared::new();
+        let (mut observer, writer) =
+            TieredObserver::write_behind(MemObserver::new(), durable.clone(), 8);
+        for i in 0..3 {
+            observer.write("dev", "/temp", &json!(i)).await.unwrap();
+        }
+        assert_eq!(observer.read("dev", "/temp").await.unwrap(), Some(json!(2)));
+
+        writer.shutdown().await;
+        assert_eq!(observer.stats(), TieredStats::default());
+        assert_eq!(durable.read("dev", "/temp").await.unwrap(), Some(json!(2)));
+
+        // Once the writer is gone, writes go through
+        observer.write("dev", "/hum", &json!(40)).await.unwrap();
+        assert_eq!(durable.read("dev", "/hum").await.unwrap(), Some(json!(40)));
+    }
+}
@@ -0,0 +1,235 @@
+//! Typed access to device documents
+//!
+//! Observer backends store each device's state as one JSON document.
+//! [`TypedObserver`] binds a path of that document to a Rust type, so
+//! application code reads and writes `T` instead of converting
+//! [`Value`](serde_json::Value)s by hand. Handlers get the same typing
+//! through the [`TypedState`](crate::extract::TypedState) extractor.
+
+use std::{fmt, marker::PhantomData};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use super::{Observer, VersionedWrite};
+
+/// Reads and writes values of type `T` at one path of device documents
+///
+/// Values are converted with `serde_json` and stored through the wrapped
+/// observer, so observers of the path are notified as for any other write.
+/// Writes merge into the document like [`Observer::write`]: fields left out
+/// of the serialized value, e.g. by `skip_serializing_if`, keep their stored
+/// value.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::observer::{memory::MemObserver, typed::TypedObserver};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+/// struct Config {
+///     interval: u32,
+///     led: bool,
+/// }
+///
+/// # async fn example() {
+/// let mut config = TypedObserver::<_, Config>::new(MemObserver::new(), "/config");
+/// config.write("sensor-42", &Config { interval: 30, led: true }).await.unwrap();
+///
+/// let updated = config.update("sensor-42", |c| c.interval = 60).await.unwrap();
+/// assert_eq!(updated, Config { interval: 60, led: true });
+/// assert_eq!(config.read("sensor-43").await.unwrap(), None);
+/// # }
+/// ```
+pub struct TypedObserver<O, T> {
+    observer: O,
+    path: String,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<O, T> TypedObserver<O, T>
+where
+    O: Observer,
+    T: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Access `T` at `path` of the documents stored in `observer`
+    pub fn new(observer: O, path: impl Into<String>) -> Self {
+        Self {
+            observer,
+            path: path.into(),
+            _type: PhantomData,
+        }
+    }
+
+    /// The path the values are stored at
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The wrapped observer
+    pub fn inner(&self) -> &O {
+        &self.observer
+    }
+
+    /// Unwrap the observer
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+
+    /// Read the value stored for `device_id`, `None` if there is none
+    pub async fn read(
+        &mut self,
+        device_id: &str,
+    ) -> Result<Option<T>, TypedObserverError<O::Error>> {
+        match self
+            .observer
+            .read(device_id, &self.path)
+            .await
+            .map_err(TypedObserverError::Observer)?
+        {
+            Some(value) => serde_json::from_value(value)
+                .map(Some)
+                .map_err(TypedObserverError::Deserialize),
+            None => Ok(None),
+        }
+    }
+
+    /// Store `value` for `device_id` and notify observers of the path
+    pub async fn write(
+        &mut self,
+        device_id: &str,
+        value: &T,
+    ) -> Result<(), TypedObserverError<O::Error>> {
+        let value = serde_json::to_value(value).map_err(TypedObserverError::Serialize)?;
+        self.observer
+            .write(device_id, &self.path, &value)
+            .await
+            .map_err(TypedObserverError::Observer)
+    }
+
+    /// Store `value` for `device_id` if its document is still at version
+    /// `expected`, see [`Observer::write_if_version`]
+    pub async fn write_if_version(
+        &mut self,
+        device_id: &str,
+        value: &T,
+        expected: u64,
+    ) -> Result<VersionedWrite, TypedObserverError<O::Error>> {
+        let value = serde_json::to_value(value).map_err(TypedObserverError::Serialize)?;
+        self.observer
+            .write_if_version(device_id, &self.path, &value, expected)
+            .await
+            .map_err(TypedObserverError::Observer)
+    }
+
+    /// Change the value stored for `device_id` with `f`, starting from the
+    /// default if none is stored, and return the new value
+    ///
+    /// The value is read and written in two steps; concurrent updates of
+    /// the same device can overwrite each other.
+    pub async fn update<F>(
+        &mut self,
+        device_id: &str,
+        f: F,
+    ) -> Result<T, TypedObserverError<O::Error>>
+    where
+        T: Default,
+        F: FnOnce(&mut T) + Send,
+    {
+        let mut value = self.read(device_id).await?.unwrap_or_default();
+        f(&mut value);
+        self.write(device_id, &value).await?;
+        Ok(value)
+    }
+}
+
+impl<O: Clone, T> Clone for TypedObserver<O, T> {
+    fn clone(&self) -> Self {
+        Self {
+            observer: self.observer.clone(),
+            path: self.path.clone(),
+            _type: PhantomData,
+        }
+    }
+}
+
+impl<O: fmt::Debug, T> fmt::Debug for TypedObserver<O, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypedObserver")
+            .field("observer", &self.observer)
+            .field("path", &self.path)
+            .field("type", &std::any::type_name::<T>())
+            .finish()
+    }
+}
+
+/// Errors from [`TypedObserver`]
+#[derive(Debug)]
+pub enum TypedObserverError<E> {
+    /// The observer backend failed
+    Observer(E),
+    /// The value could not be converted to JSON
+    Serialize(serde_json::Error),
+    /// The stored value does not match the type
+    Deserialize(serde_json::Error),
+}
+
+impl<E: fmt::Debug> fmt::Display for TypedObserverError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypedObserverError::Observer(err) => write!(f, "Observer error: {:?}", err),
+            TypedObserverError::Serialize(err) => write!(f, "Failed to serialize value: {}", err),
+            TypedObserverError::Deserialize(err) => {
+                write!(f, "Stored value does not match the type: {}", err)
+            }
+        }
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for TypedObserverError<E> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observer::memory::MemObserver;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        interval: u32,
+        led: bool,
+    }
+
+    #[tokio::test]
+    async fn test_typed_observer() {
+        let mut config = TypedObserver::<_, Config>::new(MemObserver::new(), "/config");
+        assert_eq!(config.read("dev").await.unwrap(), None);
+
+        let updated = config.update("dev", |c| c.interval = 60).await.unwrap();
+        assert_eq!(
+            updated,
+            Config {
+                interval: 60,
+                led: false
+            }
+        );
+
+        // Stored as plain JSON in the device document
+        let mut observer = config.into_inner();
+        assert_eq!(
+            observer.read("dev", "/config").await.unwrap(),
+            Some(json!({"interval": 60, "led": false}))
+        );
+
+        observer
+            .write("dev", "/config", &json!({"interval": "soon"}))
+            .await
+            .unwrap();
+        let mut config = TypedObserver::<_, Config>::new(observer, "/config");
+        assert!(matches!(
+            config.read("dev").await,
+            Err(TypedObserverError::Deserialize(_))
+        ));
+    }
+}
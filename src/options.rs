@@ -0,0 +1,211 @@
+//! CoAP option classification and validation (RFC 7252 §5.4).
+//!
+//! Every option number encodes how an endpoint that does not understand the
+//! option must treat it. Unrecognized elective options are silently ignored,
+//! but an unrecognized critical option in a request must be answered with
+//! 4.02 Bad Option (RFC 7252 §5.4.1). The router checks each request against
+//! an [`OptionRegistry`] before dispatching it to a handler.
+
+use std::collections::HashSet;
+
+use coap_lite::{CoapOption, Packet};
+
+use crate::no_response::NO_RESPONSE_OPTION;
+
+/// Options understood by the server itself or exposed to handlers
+///
+/// If-None-Match (5) is left out: nothing evaluates it, so a conditional
+/// request must get 4.02 rather than run unconditionally. Applications that
+/// check it register it with `recognize_option`.
+const BUILTIN_OPTIONS: &[u16] = &[
+    1,  // If-Match
+    3,  // Uri-Host
+    4,  // ETag
+    6,  // Observe (RFC 7641)
+    7,  // Uri-Port
+    8,  // Location-Path
+    11, // Uri-Path
+    12, // Content-Format
+    14, // Max-Age
+    15, // Uri-Query
+    17, // Accept
+    20, // Location-Query
+    23, // Block2 (RFC 7959)
+    27, // Block1 (RFC 7959)
+    28, // Size2 (RFC 7959)
+    60, // Size1
+    NO_RESPONSE_OPTION,
+];
+
+/// Whether an option is critical: odd option numbers (RFC 7252 §5.4.1)
+pub fn is_critical(number: u16) -> bool {
+    number & 0x01 != 0
+}
+
+/// Whether an option is unsafe to forward by a proxy that does not
+/// understand it (RFC 7252 §5.4.2)
+pub fn is_unsafe(number: u16) -> bool {
+    number & 0x02 != 0
+}
+
+/// Whether a safe-to-forward option is excluded from the cache key
+/// (RFC 7252 §5.4.6)
+pub fn is_no_cache_key(number: u16) -> bool {
+    number & 0x1e == 0x1c
+}
+
+/// The option numbers a server recognizes
+///
+/// Starts with the options coapum handles itself. Applications that read
+/// custom critical options in their handlers register them through
+/// [`RouterBuilder::recognize_option`](crate::RouterBuilder::recognize_option),
+/// otherwise requests carrying them are rejected with 4.02.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::options::OptionRegistry;
+///
+/// let mut registry = OptionRegistry::default();
+/// assert!(!registry.is_recognized(65001));
+///
+/// registry.register(65001);
+/// assert!(registry.is_recognized(65001));
+/// ```
+#[derive(Debug, Clone)]
+pub struct OptionRegistry {
+    recognized: HashSet<u16>,
+}
+
+impl OptionRegistry {
+    /// Mark an option number as understood by the application
+    pub fn register(&mut self, number: u16) {
+        self.recognized.insert(number);
+    }
+
+    /// Returns true if the option number is recognized
+    pub fn is_recognized(&self, number: u16) -> bool {
+        self.recognized.contains(&number)
+    }
+
+    /// Returns the first critical option in the packet that is not recognized
+    pub fn unrecognized_critical(&self, packet: &Packet) -> Option<u16> {
+        packet
+            .options()
+            .map(|(&number, _)| number)
+            .find(|&number| is_critical(number) && !self.is_recognized(number))
+    }
+}
+
+/// Set the Size1 option: in a 4.13 response, the largest request payload
+/// the server accepts (RFC 7959 §4)
+pub fn set_size1(packet: &mut Packet, size: usize) {
+    set_size(packet, CoapOption::Size1, size);
+}
+
+/// Set the Size2 option: in a response, the total size of the
+/// representation its blocks are part of (RFC 7959 §4)
+pub fn set_size2(packet: &mut Packet, size: usize) {
+    set_size(packet, CoapOption::Size2, size);
+}
+
+/// Read the Size1 or Size2 option
+pub fn size(packet: &Packet, option: CoapOption) -> Option<usize> {
+    let value = packet.get_option(option)?.front()?;
+    if value.len() > 4 {
+        return None;
+    }
+    Some(value.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize))
+}
+
+fn set_size(packet: &mut Packet, option: CoapOption, size: usize) {
+    // RFC 7252 §3.2: uint options use the shortest encoding
+    let bytes = u32::try_from(size).unwrap_or(u32::MAX).to_be_bytes();
+    let start = bytes.iter().position(|&b| b != 0).unwrap_or(4);
+    packet.set_option(option, [bytes[start..].to_vec()].into());
+}
+
+impl Default for OptionRegistry {
+    fn default() -> Self {
+        Self {
+            recognized: BUILTIN_OPTIONS.iter().copied().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_option_classification() {
+        // Uri-Path: critical, unsafe
+        assert!(is_critical(11));
+        assert!(is_unsafe(11));
+        // ETag: elective, safe to forward
+        assert!(!is_critical(4));
+        assert!(!is_unsafe(4));
+        // Size1: elective, safe, NoCacheKey
+        assert!(is_no_cache_key(60));
+        assert!(!is_no_cache_key(4));
+    }
+
+    #[test]
+    fn test_unrecognized_critical_option() {
+        let registry = OptionRegistry::default();
+
+        let mut packet = Packet::new();
+        packet.add_option(CoapOption::UriPath, b"sensors".to_vec());
+        assert_eq!(registry.unrecognized_critical(&packet), None);
+
+        // Unknown elective options are ignored
+        packet.add_option(CoapOption::Unknown(65000), vec![1]);
+        assert_eq!(registry.unrecognized_critical(&packet), None);
+
+        // Proxy-Uri is critical and coapum is not a proxy
+        packet.add_option(CoapOption::ProxyUri, b"coap://example.com".to_vec());
+        assert_eq!(registry.unrecognized_critical(&packet), Some(35));
+
+        let mut registry = registry;
+        registry.register(35);
+        assert_eq!(registry.unrecognized_critical(&packet), None);
+
+        // If-None-Match is not evaluated unless the application says so
+        packet.add_option(CoapOption::IfNoneMatch, Vec::new());
+        assert_eq!(registry.unrecognized_critical(&packet), Some(5));
+        registry.register(5);
+        assert_eq!(registry.unrecognized_critical(&packet), None);
+    }
+
+    #[test]
+    fn test_size_options() {
+        let mut packet = Packet::new();
+        assert_eq!(size(&packet, CoapOption::Size1), None);
+
+        set_size1(&mut packet, 1024);
+        assert_eq!(
+            packet
+                .get_option(CoapOption::Size1)
+                .unwrap()
+                .front()
+                .unwrap(),
+            &vec![0x04, 0x00]
+        );
+        assert_eq!(size(&packet, CoapOption::Size1), Some(1024));
+
+        // Setting again replaces the value; zero is the empty uint
+        set_size2(&mut packet, 0);
+        set_size2(&mut packet, 70_000);
+        assert_eq!(packet.get_option(CoapOption::Size2).unwrap().len(), 1);
+        assert_eq!(size(&packet, CoapOption::Size2), Some(70_000));
+        set_size2(&mut packet, 0);
+        assert!(
+            packet
+                .get_option(CoapOption::Size2)
+                .unwrap()
+                .front()
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -73,7 +73,7 @@ const MAX_DEDUP_ENTRIES: usize = 256;
 /// Per-connection RFC 7252 reliability state.
 ///
 /// Manages retransmission of outgoing CON messages and deduplication of
-/// incoming CON requests. Lives inside each `connection_task` — no
+/// incoming CON requests. Lives inside each `run_connection` — no
 /// synchronization needed.
 pub struct ReliabilityState {
     params: RetransmitParams,
@@ -0,0 +1,494 @@
+//! Client import/export for provisioning fleets from config files and backups.
+//!
+//! [`ClientManager::export_clients`] snapshots every client in the credential
+//! store into a JSON (or, with the `toml` feature, TOML) document, and
+//! [`ClientManager::import_clients`] loads such a document back. Keys are
+//! written hex-encoded, and can be left out or passed through a
+//! [`KeyWrapper`] (e.g. to encrypt them at rest) via [`KeyExport`].
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::{ClientCommand, ClientManager, ClientManagerError, ClientMetadata};
+
+/// Serialization format for client import/export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientExportFormat {
+    /// JSON document
+    Json,
+    /// TOML document (requires the `toml` feature)
+    #[cfg(feature = "toml")]
+    Toml,
+}
+
+/// Wraps and unwraps PSK key material during export/import.
+///
+/// Implement this to encrypt keys with a key-encryption-key held outside the
+/// exported file. coapum does not ship an implementation.
+pub trait KeyWrapper: Send + Sync {
+    /// Wrap (e.g. encrypt) a client's key for export
+    fn wrap_key(&self, identity: &str, key: &[u8]) -> Result<Vec<u8>, String>;
+
+    /// Unwrap a key previously produced by [`wrap_key`](KeyWrapper::wrap_key)
+    fn unwrap_key(&self, identity: &str, wrapped: &[u8]) -> Result<Vec<u8>, String>;
+}
+
+/// How PSK keys are handled during export/import
+#[derive(Clone)]
+pub enum KeyExport {
+    /// Keys are written hex-encoded in plain text
+    Plain,
+    /// Keys are left out of the export. On import, entries without a key
+    /// only update the metadata of existing clients.
+    Exclude,
+    /// Keys are passed through a [`KeyWrapper`] before being hex-encoded
+    Wrapped(Arc<dyn KeyWrapper>),
+}
+
+impl std::fmt::Debug for KeyExport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyExport::Plain => write!(f, "Plain"),
+            KeyExport::Exclude => write!(f, "Exclude"),
+            KeyExport::Wrapped(_) => write!(f, "Wrapped(..)"),
+        }
+    }
+}
+
+/// A single client as it appears in an export document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedClient {
+    /// The client's PSK identity
+    pub identity: String,
+    /// Hex-encoded key, absent when keys were excluded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Whether `key` was produced by a [`KeyWrapper`]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub wrapped: bool,
+    /// Client metadata
+    #[serde(default = "default_metadata")]
+    pub metadata: ClientMetadata,
+}
+
+/// Top-level export document
+///
+/// Clients are nested under a `clients` key since TOML documents must be tables.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClientExport {
+    /// All exported clients
+    #[serde(default)]
+    pub clients: Vec<ExportedClient>,
+}
+
+fn default_metadata() -> ClientMetadata {
+    ClientMetadata {
+        enabled: true,
+        ..Default::default()
+    }
+}
+
+impl ClientExport {
+    /// Serialize this export in the given format
+    pub fn encode(&self, format: ClientExportFormat) -> Result<String, ClientManagerError> {
+        match format {
+            ClientExportFormat::Json => serde_json::to_string_pretty(self)
+                .map_err(|e| ClientManagerError::Serialization(e.to_string())),
+            #[cfg(feature = "toml")]
+            ClientExportFormat::Toml => toml::to_string_pretty(self)
+                .map_err(|e| ClientManagerError::Serialization(e.to_string())),
+        }
+    }
+
+    /// Parse an export document in the given format
+    pub fn decode(data: &str, format: ClientExportFormat) -> Result<Self, ClientManagerError> {
+        match format {
+            ClientExportFormat::Json => serde_json::from_str(data)
+                .map_err(|e| ClientManagerError::Serialization(e.to_string())),
+            #[cfg(feature = "toml")]
+            ClientExportFormat::Toml => {
+                toml::from_str(data).map_err(|e| ClientManagerError::Serialization(e.to_string()))
+            }
+        }
+    }
+}
+
+impl ClientManager {
+    /// Export all clients from the credential store
+    ///
+    /// Fails with [`ClientManagerError::Store`] if the store fails for any
+    /// client, rather than leaving the client out.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use coapum::router::ClientManager;
+    /// # use coapum::router::export::{ClientExportFormat, KeyExport};
+    /// # async fn example(client_manager: ClientManager) -> Result<(), Box<dyn std::error::Error>> {
+    /// // Back up the fleet without key material
+    /// let backup = client_manager
+    ///     .export_clients(ClientExportFormat::Json, KeyExport::Exclude)
+    ///     .await?;
+    /// std::fs::write("clients.json", backup)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn export_clients(
+        &self,
+        format: ClientExportFormat,
+        keys: KeyExport,
+    ) -> Result<String, ClientManagerError> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        self.sender
+            .send(ClientCommand::ExportClients { response: tx })
+            .await
+            .map_err(|_| ClientManagerError::ChannelClosed)?;
+
+        let mut entries = rx
+            .await
+            .map_err(|_| ClientManagerError::ResponseFailed)?
+            .map_err(ClientManagerError::Store)?;
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let clients = entries
+            .into_iter()
+            .map(
+                |(identity, entry)| -> Result<ExportedClient, ClientManagerError> {
+                    let (key, wrapped) = match &keys {
+                        KeyExport::Plain => (Some(hex_encode(&entry.key)), false),
+                        KeyExport::Exclude => (None, false),
+                        KeyExport::Wrapped(wrapper) => {
+                            let wrapped = wrapper
+                                .wrap_key(&identity, &entry.key)
+                                .map_err(ClientManagerError::KeyWrap)?;
+                            (Some(hex_encode(&wrapped)), true)
+                        }
+                    };
+
+                    Ok(ExportedClient {
+                        identity,
+                        key,
+                        wrapped,
+                        metadata: entry.metadata,
+                    })
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+
+        ClientExport { clients }.encode(format)
+    }
+
+    /// Import clients from an export document
+    ///
+    /// Clients with a key are added (replacing any existing entry with the same
+    /// identity). Clients without a key, or any client when `keys` is
+    /// [`KeyExport::Exclude`], only have their metadata updated. The whole
+    /// document is parsed and all keys decoded before any client is touched.
+    ///
+    /// Returns the number of clients imported.
+    pub async fn import_clients(
+        &self,
+        data: &str,
+        format: ClientExportFormat,
+        keys: KeyExport,
+    ) -> Result<usize, ClientManagerError> {
+        let export = ClientExport::decode(data, format)?;
+
+        let mut decoded = Vec::with_capacity(export.clients.len());
+        for client in export.clients {
+            let key = match (&client.key, &keys) {
+                (None, _) | (Some(_), KeyExport::Exclude) => None,
+                (Some(hex), _) => {
+                    let bytes = hex_decode(hex).ok_or_else(|| {
+                        ClientManagerError::Serialization(format!(
+                            "invalid hex key for client {}",
+                            client.identity
+                        ))
+                    })?;
+                    match (&keys, client.wrapped) {
+                        (KeyExport::Wrapped(wrapper), true) => Some(
+                            wrapper
+                                .unwrap_key(&client.identity, &bytes)
+                                .map_err(ClientManagerError::KeyWrap)?,
+                        ),
+                        (_, true) => {
+                            return Err(ClientManagerError::KeyWrap(format!(
+                                "key for client {} is wrapped but no KeyWrapper was given",
+                                client.identity
+                            )));
+                        }
+                        (_, false) => Some(bytes),
+                    }
+                }
+            };
+            decoded.push((client.identity, key, client.metadata));
+        }
+
+        let count = decoded.len();
+        for (identity, key, metadata) in decoded {
+            match key {
+                Some(key) => {
+                    self.add_client_with_metadata(&identity, &key, metadata)
+                        .await?
+                }
+                None => self.update_metadata(&identity, metadata).await?,
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+pub(crate) fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Serde helper encoding key bytes as a hex string
+pub(crate) mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::hex_encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        super::hex_decode(&s).ok_or_else(|| serde::de::Error::custom("invalid hex string"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryCredentialStore;
+    use crate::credential::{CredentialStore, PskEntry};
+    use crate::serve::create_client_manager_with_task;
+    use crate::task::BackgroundTask;
+
+    struct XorWrapper(u8);
+
+    impl KeyWrapper for XorWrapper {
+        fn wrap_key(&self, _identity: &str, key: &[u8]) -> Result<Vec<u8>, String> {
+            Ok(key.iter().map(|b| b ^ self.0).collect())
+        }
+
+        fn unwrap_key(&self, identity: &str, wrapped: &[u8]) -> Result<Vec<u8>, String> {
+            self.wrap_key(identity, wrapped)
+        }
+    }
+
+    /// A store that fails to look up the key of `broken`
+    #[derive(Clone, Debug)]
+    struct BrokenStore;
+
+    impl CredentialStore for BrokenStore {
+        type Error = String;
+
+        fn lookup_psk(&self, identity: &str) -> Result<Option<PskEntry>, Self::Error> {
+            match identity {
+                "broken" => Err("backend unavailable".to_string()),
+                _ => Ok(Some(PskEntry {
+                    key: b"key".to_vec(),
+                    enabled: true,
+                })),
+            }
+        }
+        async fn add_client(
+            &self,
+            _identity: &str,
+            _key: Vec<u8>,
+            _metadata: Option<ClientMetadata>,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn remove_client(&self, _identity: &str) -> Result<bool, Self::Error> {
+            Ok(false)
+        }
+        async fn update_key(&self, _identity: &str, _key: Vec<u8>) -> Result<bool, Self::Error> {
+            Ok(false)
+        }
+        async fn update_metadata(
+            &self,
+            _identity: &str,
+            _metadata: ClientMetadata,
+        ) -> Result<bool, Self::Error> {
+            Ok(false)
+        }
+        async fn set_enabled(&self, _identity: &str, _enabled: bool) -> Result<bool, Self::Error> {
+            Ok(false)
+        }
+        async fn list_clients(&self) -> Result<Vec<String>, Self::Error> {
+            Ok(vec!["device_001".to_string(), "broken".to_string()])
+        }
+    }
+
+    async fn populated_manager() -> (ClientManager, BackgroundTask) {
+        let store = MemoryCredentialStore::new();
+        let (manager, processor) = create_client_manager_with_task(store.clone(), 16);
+
+        manager.add_client("device_001", b"key_one").await.unwrap();
+        let metadata = ClientMetadata {
+            name: Some("Sensor".to_string()),
+            enabled: false,
+            tags: vec!["outdoor".to_string()],
+            ..Default::default()
+        };
+        manager
+            .add_client_with_metadata("device_002", b"key_two", metadata)
+            .await
+            .unwrap();
+
+        (manager, processor)
+    }
+
+    #[test]
+    fn test_hex_roundtrip() {
+        let bytes = vec![0x00, 0x7f, 0xff, 0x10];
+        assert_eq!(hex_encode(&bytes), "007fff10");
+        assert_eq!(hex_decode("007fff10"), Some(bytes));
+        assert_eq!(hex_decode("abc"), None);
+        assert_eq!(hex_decode("zz"), None);
+    }
+
+    #[tokio::test]
+    async fn test_export_import_json_roundtrip() {
+        let (manager, _processor) = populated_manager().await;
+
+        let exported = manager
+            .export_clients(ClientExportFormat::Json, KeyExport::Plain)
+            .await
+            .unwrap();
+        assert!(exported.contains(&hex_encode(b"key_one")));
+
+        let target = MemoryCredentialStore::new();
+        let (target_manager, _target_processor) =
+            create_client_manager_with_task(target.clone(), 16);
+        let count = target_manager
+            .import_clients(&exported, ClientExportFormat::Json, KeyExport::Plain)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+
+        // Round-trip through the command channel so imports are applied
+        let mut identities = target_manager.list_clients().await.unwrap();
+        identities.sort();
+        assert_eq!(identities, vec!["device_001", "device_002"]);
+
+        let psk = target.lookup_psk("device_002").unwrap().unwrap();
+        assert_eq!(psk.key, b"key_two");
+        assert!(!psk.enabled);
+        let info = target.get_client("device_002").await.unwrap().unwrap();
+        assert_eq!(info.metadata.name.as_deref(), Some("Sensor"));
+        assert_eq!(info.metadata.tags, vec!["outdoor"]);
+    }
+
+    #[tokio::test]
+    async fn test_export_fails_on_store_error() {
+        let (manager, _processor) = create_client_manager_with_task(BrokenStore, 16);
+        let err = manager
+            .export_clients(ClientExportFormat::Json, KeyExport::Exclude)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, ClientManagerError::Store(ref e) if e.contains("broken")),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_excludes_keys() {
+        let (manager, _processor) = populated_manager().await;
+
+        let exported = manager
+            .export_clients(ClientExportFormat::Json, KeyExport::Exclude)
+            .await
+            .unwrap();
+        let export = ClientExport::decode(&exported, ClientExportFormat::Json).unwrap();
+        assert_eq!(export.clients.len(), 2);
+        assert!(export.clients.iter().all(|c| c.key.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_export_import_wrapped_keys() {
+        let (manager, _processor) = populated_manager().await;
+        let wrapper: Arc<dyn KeyWrapper> = Arc::new(XorWrapper(0x5a));
+
+        let exported = manager
+            .export_clients(
+                ClientExportFormat::Json,
+                KeyExport::Wrapped(wrapper.clone()),
+            )
+            .await
+            .unwrap();
+        assert!(!exported.contains(&hex_encode(b"key_one")));
+
+        let target = MemoryCredentialStore::new();
+        let (target_manager, _target_processor) =
+            create_client_manager_with_task(target.clone(), 16);
+
+        // Wrapped keys can't be imported without the wrapper
+        let err = target_manager
+            .import_clients(&exported, ClientExportFormat::Json, KeyExport::Plain)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ClientManagerError::KeyWrap(_)));
+
+        target_manager
+            .import_clients(
+                &exported,
+                ClientExportFormat::Json,
+                KeyExport::Wrapped(wrapper),
+            )
+            .await
+            .unwrap();
+        target_manager.list_clients().await.unwrap();
+        assert_eq!(
+            target.lookup_psk("device_001").unwrap().unwrap().key,
+            b"key_one"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_provisioning_file_defaults() {
+        let data = r#"{ "clients": [ { "identity": "device_003", "key": "6b6579" } ] }"#;
+
+        let store = MemoryCredentialStore::new();
+        let (manager, _processor) = create_client_manager_with_task(store.clone(), 16);
+        manager
+            .import_clients(data, ClientExportFormat::Json, KeyExport::Plain)
+            .await
+            .unwrap();
+        manager.list_clients().await.unwrap();
+
+        let psk = store.lookup_psk("device_003").unwrap().unwrap();
+        assert_eq!(psk.key, b"key");
+        assert!(psk.enabled);
+    }
+
+    #[cfg(feature = "toml")]
+    #[tokio::test]
+    async fn test_export_import_toml_roundtrip() {
+        let (manager, _processor) = populated_manager().await;
+
+        let exported = manager
+            .export_clients(ClientExportFormat::Toml, KeyExport::Plain)
+            .await
+            .unwrap();
+        let parsed = ClientExport::decode(&exported, ClientExportFormat::Toml).unwrap();
+        assert_eq!(parsed.clients.len(), 2);
+        assert_eq!(parsed.clients[0].identity, "device_001");
+        assert_eq!(parsed.clients[0].key, Some(hex_encode(b"key_one")));
+    }
+}
@@ -0,0 +1,229 @@
+//! Notifying groups of devices at once.
+//!
+//! A [`NotificationTrigger`](super::NotificationTrigger) notifies one device
+//! at a time. A [`GroupTrigger`] sends the same notification to many: a list
+//! of devices, the clients with a tag in the credential store, or every
+//! device currently observing a path. Devices are notified concurrently, up
+//! to a limit, and the [`GroupSummary`] reports which ones failed.
+//!
+//! ```rust
+//! use coapum::observer::memory::MemObserver;
+//! use coapum::router::NotificationTrigger;
+//! use serde_json::json;
+//!
+//! # async fn example() {
+//! let trigger = NotificationTrigger::new(MemObserver::new());
+//!
+//! let summary = trigger
+//!     .observers("/config")
+//!     .await
+//!     .concurrency(32)
+//!     .trigger_notification("/config", &json!({"interval": 60}))
+//!     .await;
+//! for (device, error) in &summary.failed {
+//!     eprintln!("{device}: {error}");
+//! }
+//! # }
+//! ```
+
+use futures::{StreamExt, stream};
+use serde_json::Value;
+
+use crate::observer::{EncodedPayload, Observer};
+
+/// Devices notified at the same time by default
+const DEFAULT_CONCURRENCY: usize = 16;
+
+/// A notification sent to a group of devices, created with
+/// [`NotificationTrigger::devices`](super::NotificationTrigger::devices),
+/// [`tagged`](super::NotificationTrigger::tagged) or
+/// [`observers`](super::NotificationTrigger::observers)
+#[derive(Clone, Debug)]
+pub struct GroupTrigger<O> {
+    observer: O,
+    devices: Vec<String>,
+    concurrency: usize,
+}
+
+impl<O: Observer> GroupTrigger<O> {
+    pub(crate) fn new(observer: O, devices: Vec<String>) -> Self {
+        Self {
+            observer,
+            devices,
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    /// Set how many devices are notified at the same time (default: 16)
+    pub fn concurrency(mut self, limit: usize) -> Self {
+        self.concurrency = limit.max(1);
+        self
+    }
+
+    /// The devices of the group
+    pub fn devices(&self) -> &[String] {
+        &self.devices
+    }
+
+    /// Write `payload` at `path` of every device in the group, like
+    /// [`NotificationTrigger::trigger_notification`](super::NotificationTrigger::trigger_notification)
+    pub async fn trigger_notification(
+        &self,
+        path: &str,
+        payload: &Value,
+    ) -> GroupSummary<O::Error> {
+        self.run(|mut observer, device| async move {
+            let result = observer.write(&device, path, payload).await;
+            (device, result)
+        })
+        .await
+    }
+
+    /// Notify the observers of `path` of every device in the group with a
+    /// payload encoded by the caller, like
+    /// [`NotificationTrigger::trigger_encoded`](super::NotificationTrigger::trigger_encoded)
+    pub async fn trigger_encoded(
+        &self,
+        path: &str,
+        payload: EncodedPayload,
+    ) -> GroupSummary<O::Error> {
+        let payload = &payload;
+        self.run(|mut observer, device| async move {
+            let result = observer
+                .notify_encoded(&device, path, payload.clone())
+                .await;
+            (device, result)
+        })
+        .await
+    }
+
+    async fn run<F, Fut>(&self, notify: F) -> GroupSummary<O::Error>
+    where
+        F: Fn(O, String) -> Fut,
+        Fut: Future<Output = (String, Result<(), O::Error>)>,
+    {
+        let mut results = stream::iter(&self.devices)
+            .map(|device| notify(self.observer.clone(), device.clone()))
+            .buffer_unordered(self.concurrency);
+
+        let mut summary = GroupSummary {
+            succeeded: Vec::new(),
+            failed: Vec::new(),
+        };
+        while let Some((device, result)) = results.next().await {
+            match result {
+                Ok(()) => summary.succeeded.push(device),
+                Err(e) => {
+                    tracing::warn!(device = %device, error = ?e, "trigger.group.failed");
+                    summary.failed.push((device, e));
+                }
+            }
+        }
+        // Completion order varies from run to run
+        summary.succeeded.sort();
+        summary.failed.sort_by(|a, b| a.0.cmp(&b.0));
+        summary
+    }
+}
+
+/// Outcome of a [`GroupTrigger`], with devices sorted by ID
+#[derive(Debug)]
+pub struct GroupSummary<E> {
+    /// Devices that were notified
+    pub succeeded: Vec<String>,
+    /// Devices whose notification failed, with the observer's error
+    pub failed: Vec<(String, E)>,
+}
+
+impl<E> GroupSummary<E> {
+    /// Whether every device was notified
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Number of devices in the group
+    pub fn total(&self) -> usize {
+        self.succeeded.len() + self.failed.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use crate::{
+        credential::{CredentialStore, memory::MemoryCredentialStore},
+        observer::{
+            Observer,
+            memory::{MemObserver, MemObserverError},
+            policy::StoragePolicy,
+            queue::notification_channel,
+        },
+        router::{ClientMetadata, NotificationTrigger},
+    };
+
+    #[tokio::test]
+    async fn test_group_trigger() {
+        let mut observer =
+            MemObserver::new().with_policy(StoragePolicy::new().max_document_size(32));
+        let mut receivers = Vec::new();
+        for (device, path) in [
+            ("dev-b", "/config"),
+            ("dev-a", "/config"),
+            ("dev-c", "/other"),
+        ] {
+            let (tx, rx) = notification_channel(4, Default::default());
+            observer.register(device, path, Arc::new(tx)).await.unwrap();
+            receivers.push(rx);
+        }
+        observer
+            .write("full", "/blob", &json!("x".repeat(20)))
+            .await
+            .unwrap();
+        let trigger = NotificationTrigger::new(observer);
+
+        let group = trigger.observers("/config").await;
+        assert_eq!(group.devices(), ["dev-a", "dev-b"]);
+        let summary = group
+            .concurrency(1)
+            .trigger_notification("/config", &json!({"interval": 60}))
+            .await;
+        assert!(summary.is_success());
+        assert_eq!(summary.succeeded, ["dev-a", "dev-b"]);
+        for rx in &mut receivers[..2] {
+            assert_eq!(rx.recv().await.unwrap().value, json!({"interval": 60}));
+        }
+
+        // A device over its storage quota fails without affecting the others
+        let summary = trigger
+            .devices(["full", "dev-c"])
+            .trigger_notification("/config", &json!({"interval": 60}))
+            .await;
+        assert_eq!(summary.total(), 2);
+        assert_eq!(summary.succeeded, ["dev-c"]);
+        assert!(matches!(
+            summary.failed[..],
+            [(ref device, MemObserverError::QuotaExceeded(_))] if device == "full"
+        ));
+
+        let store = MemoryCredentialStore::new();
+        for (identity, tags) in [
+            ("dev-a", vec!["fleet"]),
+            ("dev-b", vec![]),
+            ("dev-c", vec!["fleet", "beta"]),
+        ] {
+            let metadata = ClientMetadata {
+                tags: tags.into_iter().map(String::from).collect(),
+                ..Default::default()
+            };
+            store
+                .add_client(identity, b"key".to_vec(), Some(metadata))
+                .await
+                .unwrap();
+        }
+        let group = trigger.tagged(&store, "fleet").await.unwrap();
+        assert_eq!(group.devices(), ["dev-a", "dev-c"]);
+    }
+}
@@ -3,8 +3,12 @@
 //! This module provides both the core router functionality and an improved routing API
 //! that allows for more ergonomic registration of handlers with automatic parameter extraction.
 
-use coap_lite::{CoapRequest, CoapResponse, ObserveOption, Packet, RequestType, ResponseType};
+use coap_lite::{
+    CoapRequest, CoapResponse, ContentFormat, MessageType, ObserveOption, Packet, RequestType,
+    ResponseType,
+};
 use route_recognizer::Router;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::convert::Infallible;
@@ -14,15 +18,36 @@ use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tokio::sync::mpsc::{self, Sender};
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
 use tower::Service;
 
-use crate::handler::{ErasedHandler, Handler, HandlerFn, into_erased_handler, into_handler};
-use crate::observer::{Observer, ObserverRequest, ObserverValue};
+use crate::credential::CredentialStore;
+use crate::extract::typed::DocumentAccess;
+use crate::extract::{
+    BackendBatch, DocumentPatcher, Extensions, FromRef, PayloadLimits, Rejection, RejectionHandler,
+    Session, apply_merge_patch,
+};
+use crate::handler::{
+    ErasedHandler, Handler, HandlerFn, into_erased_handler, into_handler, with_state,
+};
+use crate::no_response::NoResponse;
+use crate::observer::history::{HistoryEntry, HistoryRange};
+use crate::observer::metadata::ObservationMetadata;
+use crate::observer::{EncodedPayload, Observer, ObserverRequest, ObserverSender, ObserverValue};
+use crate::options::OptionRegistry;
 use crate::router::wrapper::IntoCoapResponse;
+use crate::task::BackgroundTask;
 
+use self::group::GroupTrigger;
+use self::priority::{Priorities, Priority};
 use self::wrapper::{RequestTypeWrapper, RouteHandler};
 
+pub mod export;
+pub mod group;
+pub mod nest;
+pub mod priority;
+pub mod resource;
 pub mod wrapper;
 
 pub type RouterError = Box<dyn std::error::Error + Send + Sync + 'static>;
@@ -38,6 +63,9 @@ type StateUpdateReceiver<S> = mpsc::Receiver<StateUpdateFn<S>>;
 
 /// A handle that allows external code to trigger observer notifications
 /// without having direct access to the router.
+///
+/// Groups of devices are notified through [`devices`](Self::devices),
+/// [`tagged`](Self::tagged) and [`observers`](Self::observers).
 #[derive(Clone)]
 pub struct NotificationTrigger<O>
 where
@@ -64,8 +92,132 @@ where
     ) -> Result<(), O::Error> {
         self.observer.write(device_id, path, payload).await
     }
+
+    /// Notify observers of `path` with a CBOR-encoded value.
+    ///
+    /// Unlike [`trigger_notification`](Self::trigger_notification) nothing is
+    /// stored and only observers of exactly `path` are notified, which suits
+    /// notify-only resources. The notification is sent as `application/cbor`
+    /// without a JSON round trip, so byte strings stay byte strings.
+    pub async fn trigger_cbor<T: Serialize>(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        value: &T,
+    ) -> Result<(), TriggerError<O::Error>> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes)
+            .map_err(|e| TriggerError::Encode(e.to_string()))?;
+        self.trigger_encoded(
+            device_id,
+            path,
+            EncodedPayload {
+                content_format: ContentFormat::ApplicationCBOR,
+                bytes,
+            },
+        )
+        .await
+    }
+
+    /// Notify observers of `path` with a SenML pack, sent as `application/senml+cbor`.
+    ///
+    /// The pack is encoded deterministically (RFC 8949 §4.2.1), so equal packs
+    /// always produce the same notification bytes. See
+    /// [`trigger_cbor`](Self::trigger_cbor) for delivery semantics.
+    pub async fn trigger_senml(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        pack: &coapum_senml::SenMLPack,
+    ) -> Result<(), TriggerError<O::Error>> {
+        let bytes = pack
+            .to_canonical_cbor()
+            .map_err(|e| TriggerError::Encode(e.to_string()))?;
+        self.trigger_encoded(
+            device_id,
+            path,
+            EncodedPayload {
+                content_format: ContentFormat::ApplicationSenmlCBOR,
+                bytes,
+            },
+        )
+        .await
+    }
+
+    /// Notify observers of `path` with a payload encoded by the caller
+    pub async fn trigger_encoded(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: EncodedPayload,
+    ) -> Result<(), TriggerError<O::Error>> {
+        self.observer
+            .notify_encoded(device_id, path, payload)
+            .await
+            .map_err(TriggerError::Observer)
+    }
+
+    /// Notify a list of devices, see [`GroupTrigger`]
+    pub fn devices<I>(&self, devices: I) -> GroupTrigger<O>
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        GroupTrigger::new(
+            self.observer.clone(),
+            devices.into_iter().map(Into::into).collect(),
+        )
+    }
+
+    /// Notify the clients in `store` tagged with `tag` in their
+    /// [`ClientMetadata`], sorted, see [`GroupTrigger`]
+    ///
+    /// Client identities are used as device IDs, as they are without an
+    /// [`IdentityMapper`](crate::IdentityMapper).
+    pub async fn tagged<C: CredentialStore>(
+        &self,
+        store: &C,
+        tag: &str,
+    ) -> Result<GroupTrigger<O>, C::Error> {
+        let mut devices = Vec::new();
+        for identity in store.list_clients().await? {
+            if let Some(client) = store.get_client(&identity).await?
+                && client.metadata.tags.iter().any(|t| t == tag)
+            {
+                devices.push(identity);
+            }
+        }
+        devices.sort();
+        Ok(self.devices(devices))
+    }
+
+    /// Notify every device currently observing exactly `path`, see
+    /// [`GroupTrigger`] and [`Observer::observing_devices`]
+    pub async fn observers(&self, path: &str) -> GroupTrigger<O> {
+        self.devices(self.observer.observing_devices(path).await)
+    }
+}
+
+/// Errors from the typed [`NotificationTrigger`] methods
+#[derive(Debug)]
+pub enum TriggerError<E> {
+    /// The payload could not be encoded
+    Encode(String),
+    /// The observer backend failed
+    Observer(E),
+}
+
+impl<E: Debug> std::fmt::Display for TriggerError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TriggerError::Encode(e) => write!(f, "Failed to encode notification: {}", e),
+            TriggerError::Observer(e) => write!(f, "Observer error: {:?}", e),
+        }
+    }
 }
 
+impl<E: Debug> std::error::Error for TriggerError<E> {}
+
 /// A handle that allows external code to update the application state
 /// without having direct access to the router.
 #[derive(Clone)]
@@ -126,6 +278,68 @@ where
             .map_err(|_| StateUpdateError::ChannelClosed)
     }
 
+    /// Run a closure against the state and return its result
+    ///
+    /// The query is queued behind pending updates, so it observes every update
+    /// sent through this handle before it.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use coapum::StateUpdateHandle;
+    /// # #[derive(Clone)]
+    /// # struct MyAppState {
+    /// #     counter: i32,
+    /// # }
+    /// # async fn example(state_handle: StateUpdateHandle<MyAppState>) -> Result<(), Box<dyn std::error::Error>> {
+    /// let counter = state_handle.query(|state: &MyAppState| state.counter).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query<F, R>(&self, query: F) -> Result<R, StateUpdateError>
+    where
+        F: FnOnce(&S) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.update_and_get(move |state| query(state)).await
+    }
+
+    /// Update the state and return a value computed by the update
+    ///
+    /// Waits until the update has been applied.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use coapum::StateUpdateHandle;
+    /// # #[derive(Clone)]
+    /// # struct MyAppState {
+    /// #     counter: i32,
+    /// # }
+    /// # async fn example(state_handle: StateUpdateHandle<MyAppState>) -> Result<(), Box<dyn std::error::Error>> {
+    /// let counter = state_handle
+    ///     .update_and_get(|state: &mut MyAppState| {
+    ///         state.counter += 1;
+    ///         state.counter
+    ///     })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn update_and_get<F, R>(&self, updater: F) -> Result<R, StateUpdateError>
+    where
+        F: FnOnce(&mut S) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.update(move |state| {
+            let _ = tx.send(updater(state));
+        })
+        .await?;
+        // Dropped without a reply if the processor stops before applying it
+        rx.await.map_err(|_| StateUpdateError::ChannelClosed)
+    }
+
     /// Attempt to update the state without blocking
     ///
     /// Returns an error if the update channel is full or closed.
@@ -195,16 +409,27 @@ pub enum ClientCommand {
     },
     /// Force-disconnect a client by identity
     DisconnectClient { identity: String },
+    /// Get every client with its key and metadata (response via oneshot channel)
+    ExportClients {
+        response: tokio::sync::oneshot::Sender<Result<Vec<(String, ClientEntry)>, String>>,
+    },
 }
 
 /// Metadata associated with a client
-#[derive(Debug, Clone, Default)]
+///
+/// When deserializing, missing fields take their defaults except `enabled`,
+/// which defaults to `true` so provisioning files don't need to spell it out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ClientMetadata {
     /// Optional friendly name for the client
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
     /// Optional description
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     /// Whether the client is enabled
+    #[serde(default = "default_enabled")]
     pub enabled: bool,
     /// Optional tags for categorization
     pub tags: Vec<String>,
@@ -212,6 +437,10 @@ pub struct ClientMetadata {
     pub custom: HashMap<String, String>,
 }
 
+fn default_enabled() -> bool {
+    true
+}
+
 impl ClientManager {
     /// Create a new client manager
     pub fn new(sender: mpsc::Sender<ClientCommand>) -> Self {
@@ -351,6 +580,12 @@ pub enum ClientManagerError {
     ChannelClosed,
     /// Failed to receive response
     ResponseFailed,
+    /// Failed to serialize or parse a client export
+    Serialization(String),
+    /// A [`KeyWrapper`](export::KeyWrapper) failed, or a wrapped key was given without one
+    KeyWrap(String),
+    /// The credential store failed
+    Store(String),
 }
 
 impl std::fmt::Display for ClientManagerError {
@@ -360,6 +595,9 @@ impl std::fmt::Display for ClientManagerError {
             ClientManagerError::ResponseFailed => {
                 write!(f, "Failed to receive response from client manager")
             }
+            ClientManagerError::Serialization(e) => write!(f, "Client export error: {}", e),
+            ClientManagerError::KeyWrap(e) => write!(f, "Key wrapping error: {}", e),
+            ClientManagerError::Store(e) => write!(f, "Credential store error: {}", e),
         }
     }
 }
@@ -367,9 +605,10 @@ impl std::fmt::Display for ClientManagerError {
 impl std::error::Error for ClientManagerError {}
 
 /// Internal client store entry
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClientEntry {
-    /// The PSK key
+    /// The PSK key, hex-encoded when serialized
+    #[serde(with = "export::hex_bytes")]
     pub key: Vec<u8>,
     /// Client metadata
     pub metadata: ClientMetadata,
@@ -378,6 +617,73 @@ pub struct ClientEntry {
 /// Shared client store type
 pub type ClientStore = Arc<RwLock<HashMap<String, ClientEntry>>>;
 
+/// Description of a registered route, as returned by [`CoapRouter::routes`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteInfo {
+    /// Route pattern as registered (e.g. `/device/:id`)
+    pub path: String,
+    /// Request method; `RequestType::UnKnown` for routes registered with `any`
+    pub method: RequestType,
+    /// Whether the route has an observe handler
+    pub observable: bool,
+    /// Whether notifications for the route are sent as Confirmable messages
+    pub confirmable_notifications: bool,
+}
+
+/// A route registered twice for the same method, see
+/// [`CoapRouter::try_add`] and [`RouterBuilder::try_build`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateRoute {
+    /// Route pattern as registered
+    pub path: String,
+    /// Request method registered twice
+    pub method: RequestType,
+}
+
+impl std::fmt::Display for DuplicateRoute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Route {} already has a handler for {:?}",
+            self.path, self.method
+        )
+    }
+}
+
+impl std::error::Error for DuplicateRoute {}
+
+/// How observations of a path without an observe route of its own find one
+///
+/// Set with [`RouterBuilder::observe_fallback`]. The fallback route handles
+/// both the registering GET and the notifications, and sees the observed
+/// path rather than its own pattern.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ObserveFallback {
+    /// Only an observe route matching the path itself is used
+    #[default]
+    Exact,
+    /// Use the observe route of the nearest ancestor, e.g. `/device/:id`
+    /// handles observations of `/device/abc/config`
+    Parent,
+    /// Use the first observe route registered below the path, e.g.
+    /// `/device/config/:key` handles observations of the whole
+    /// `/device/config` subtree
+    Subtree,
+}
+
+/// Returns true if `pattern` has more segments than `path` and its leading
+/// segments match `path`, with `:param` segments matching any segment
+fn pattern_extends(pattern: &str, path: &str) -> bool {
+    let mut pattern = pattern.split('/').filter(|s| !s.is_empty());
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        match pattern.next() {
+            Some(p) if p == segment || p.starts_with(':') => {}
+            _ => return false,
+        }
+    }
+    pattern.next().is_some()
+}
+
 /// The CoapRouter is a struct responsible for managing routes, shared state and an observer database.
 ///
 /// It provides methods for registering and unregistering observers, reading and writing to the backend,
@@ -394,8 +700,9 @@ pub type ClientStore = Arc<RwLock<HashMap<String, ClientEntry>>>;
 ///
 /// Result of looking up a handler for a request.
 pub(crate) enum LookupResult<S: Send + Sync + 'static> {
-    /// Handler found for the path and method.
-    Found(Box<dyn ErasedHandler<S>>),
+    /// Handler found for the path and method, with the route's payload limit
+    /// and required client tags.
+    Found(Box<dyn ErasedHandler<S>>, Option<usize>, Vec<String>),
     /// Path does not match any registered route (4.04).
     NotFound,
     /// Path matched but the method is not registered (4.05).
@@ -411,10 +718,28 @@ where
     O: Observer,
 {
     inner: Router<HashMap<RequestTypeWrapper, RouteHandler<S>>>,
+    // Registered routes in insertion order, for introspection
+    routes: Vec<RouteInfo>,
     state: Arc<RwLock<S>>, // Shared state
     db: O,
+    // Option numbers accepted in requests (RFC 7252 §5.4.1)
+    options: OptionRegistry,
+    // Alias paths rewritten to their target before matching, without slashes
+    aliases: HashMap<String, String>,
+    // Where observations without an exact observe route look for one
+    observe_fallback: ObserveFallback,
+    // Answer registrations the GET handler doesn't know with the stored value
+    observe_from_backend: bool,
+    // Paths whose handlers manage the Observe option themselves
+    manual_observe: Router<()>,
+    // Send priorities of notifications by route
+    priorities: Priorities,
+    // Answers extractor rejections in place of their default response
+    rejection_handler: Option<RejectionHandler>,
     // Channel for external state updates
     state_update_sender: Option<StateUpdateSender<S>>,
+    // Processor for the state update channel, shared by every clone
+    state_update_task: Arc<std::sync::Mutex<Option<BackgroundTask>>>,
 }
 
 /// Provides methods for creating a new CoapRouter, registering and unregistering observers,
@@ -433,9 +758,18 @@ where
     pub fn new(state: S, db: O) -> Self {
         Self {
             inner: Router::new(),
+            routes: Vec::new(),
             state: Arc::new(RwLock::new(state)),
             db,
+            options: OptionRegistry::default(),
+            aliases: HashMap::new(),
+            observe_fallback: ObserveFallback::default(),
+            observe_from_backend: false,
+            manual_observe: Router::new(),
+            priorities: Priorities::default(),
+            rejection_handler: None,
             state_update_sender: None,
+            state_update_task: Arc::default(),
         }
     }
 
@@ -449,11 +783,25 @@ where
         &mut self,
         device_id: &str,
         path: &str,
-        sender: Arc<Sender<ObserverValue>>,
+        sender: ObserverSender,
     ) -> Result<(), O::Error> {
         self.db.register(device_id, path, sender).await
     }
 
+    /// Registers an observer for a given path along with the metadata of
+    /// the observation, see [`Observer::list`].
+    pub async fn register_observer_with_metadata(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        sender: ObserverSender,
+        metadata: ObservationMetadata,
+    ) -> Result<(), O::Error> {
+        self.db
+            .register_with_metadata(device_id, path, sender, metadata)
+            .await
+    }
+
     /// Unregisters an observer from a given path.
     pub async fn unregister_observer(
         &mut self,
@@ -473,6 +821,25 @@ where
         self.db.unregister_device(device_id).await
     }
 
+    /// Unregisters the observer a single connection holds on a path.
+    pub async fn unregister_connection_observer(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        sender: &ObserverSender,
+    ) -> Result<(), O::Error> {
+        self.db.unregister_sender(device_id, path, sender).await
+    }
+
+    /// Unregisters all observers held by a single connection.
+    pub async fn unregister_connection(
+        &mut self,
+        device_id: &str,
+        sender: &ObserverSender,
+    ) -> Result<(), O::Error> {
+        self.db.unregister_connection(device_id, sender).await
+    }
+
     /// Returns the number of observer registrations for a device.
     pub async fn observer_count(&self, device_id: &str) -> usize {
         self.db.observer_count(device_id).await
@@ -488,6 +855,17 @@ where
         self.db.write(device_id, path, payload).await
     }
 
+    /// Writes a payload to a path in the backend in the format the device
+    /// sent it, see [`Observer::write_raw`].
+    pub async fn backend_write_raw(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        payload: EncodedPayload,
+    ) -> Result<(), O::Error> {
+        self.db.write_raw(device_id, path, payload).await
+    }
+
     /// Triggers observer notifications for a specific device and path.
     /// This is useful when the application needs to notify observers
     /// about changes that happened outside of the normal request flow.
@@ -510,6 +888,31 @@ where
         self.db.read(device_id, path).await
     }
 
+    /// Reads the payload last written to a path with
+    /// [`backend_write_raw`](Self::backend_write_raw), as the device sent it.
+    pub async fn backend_read_raw(
+        &mut self,
+        device_id: &str,
+        path: &str,
+    ) -> Result<Option<EncodedPayload>, O::Error> {
+        self.db.read_raw(device_id, path).await
+    }
+
+    /// Reads the retained history of a path from the backend.
+    pub async fn backend_read_history(
+        &mut self,
+        device_id: &str,
+        path: &str,
+        range: HistoryRange,
+    ) -> Result<Vec<HistoryEntry>, O::Error> {
+        self.db.read_history(device_id, path, range).await
+    }
+
+    /// Reads a device's whole document from the backend.
+    pub async fn backend_read_root(&mut self, device_id: &str) -> Result<Option<Value>, O::Error> {
+        self.db.read_root(device_id).await
+    }
+
     /// Enable external state updates and return a handle for external components
     ///
     /// This creates a channel that allows external components to safely update
@@ -524,6 +927,9 @@ where
     ///
     /// Returns a StateUpdateHandle that external components can use to queue state updates.
     ///
+    /// The background task runs until [`shutdown_state_updates`](Self::shutdown_state_updates)
+    /// is called or the router and all of its clones are dropped.
+    ///
     /// # Example
     ///
     /// ```rust,no_run
@@ -550,11 +956,14 @@ where
         let (sender, receiver) = mpsc::channel(buffer_size);
         self.state_update_sender = Some(sender.clone());
 
-        // Spawn background task to process state updates
+        // Spawn background task to process state updates. Replacing an
+        // earlier task drops it, which stops it.
         let state = Arc::clone(&self.state);
-        tokio::spawn(async move {
-            Self::process_state_updates(state, receiver).await;
-        });
+        let task = BackgroundTask::spawn(|stop| Self::process_state_updates(state, receiver, stop));
+        *self
+            .state_update_task
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(task);
 
         StateUpdateHandle::new(sender)
     }
@@ -563,10 +972,38 @@ where
     ///
     /// This runs in a background task and applies state updates sequentially
     /// to maintain consistency.
-    async fn process_state_updates(state: Arc<RwLock<S>>, mut receiver: StateUpdateReceiver<S>) {
-        while let Some(update) = receiver.recv().await {
-            let mut state_guard = state.write().await;
-            update(&mut *state_guard);
+    async fn process_state_updates(
+        state: Arc<RwLock<S>>,
+        mut receiver: StateUpdateReceiver<S>,
+        mut stop: oneshot::Receiver<()>,
+    ) {
+        loop {
+            tokio::select! {
+                update = receiver.recv() => match update {
+                    Some(update) => {
+                        let mut state_guard = state.write().await;
+                        update(&mut *state_guard);
+                    }
+                    None => break,
+                },
+                _ = &mut stop => break,
+            }
+        }
+    }
+
+    /// Stop the state update task and wait for it to exit
+    ///
+    /// Updates already applied are kept; handles return
+    /// [`StateUpdateError::ChannelClosed`] afterwards. Does nothing if state
+    /// updates are not enabled.
+    pub async fn shutdown_state_updates(&self) {
+        let task = self
+            .state_update_task
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        if let Some(task) = task {
+            task.shutdown().await;
         }
     }
 
@@ -579,84 +1016,269 @@ where
             .map(|sender| StateUpdateHandle::new(sender.clone()))
     }
 
-    /// Adds a route handler for a given route.
+    /// Adds a route handler for a given route, replacing the route's
+    /// handler for the same method if there is one.
+    #[deprecated(note = "use `try_add` to catch duplicate routes, or `replace` to override one")]
     pub fn add(&mut self, route: &str, handler: RouteHandler<S>) {
-        // Check if route already exists
-        match self.inner.recognize(route) {
-            Ok(r) => {
-                let mut r = (**r.handler()).clone();
-                r.insert(handler.method.into(), handler);
-                self.inner.add(route, r);
-            }
-            Err(_) => {
-                let mut r = HashMap::new();
-                r.insert(handler.method.into(), handler);
-                self.inner.add(route, r);
-            }
+        self.replace(route, handler);
+    }
+
+    /// Adds a route handler for a given route.
+    ///
+    /// Fails if the route already has a handler for the method;
+    /// [`replace`](Self::replace) overrides it instead. A GET handler and an
+    /// observe handler of the same path make up one route, in either order.
+    pub fn try_add(&mut self, route: &str, handler: RouteHandler<S>) -> Result<(), DuplicateRoute> {
+        if let Some(duplicate) = self.duplicate_of(route, &handler) {
+            return Err(duplicate);
+        }
+        self.replace(route, handler);
+        Ok(())
+    }
+
+    /// Returns the registration `handler` would overwrite at `route`, if any
+    fn duplicate_of(&self, route: &str, handler: &RouteHandler<S>) -> Option<DuplicateRoute> {
+        let observable = handler.observe_handler.is_some();
+        self.routes
+            .iter()
+            .any(|r| r.path == route && r.method == handler.method && r.observable == observable)
+            .then(|| DuplicateRoute {
+                path: route.to_string(),
+                method: handler.method,
+            })
+    }
+
+    /// Adds a route handler for a given route, replacing the route's
+    /// handler for the same method if there is one.
+    ///
+    /// A handler without an observe handler keeps the observe handler of the
+    /// route it replaces, so observations of the path carry on.
+    pub fn replace(&mut self, route: &str, mut handler: RouteHandler<S>) {
+        let observed = self
+            .routes
+            .iter()
+            .any(|r| r.path == route && r.method == handler.method && r.observable);
+        let mut methods = match self.inner.recognize(route) {
+            Ok(r) => (**r.handler()).clone(),
+            Err(_) => HashMap::new(),
+        };
+        let key = handler.method.into();
+        if observed
+            && handler.observe_handler.is_none()
+            && let Some(previous) = methods.remove(&key)
+        {
+            handler.observe_handler = previous.observe_handler;
+            handler.confirmable_notifications = previous.confirmable_notifications;
+        }
+
+        let info = RouteInfo {
+            path: route.to_string(),
+            method: handler.method,
+            observable: handler.observe_handler.is_some(),
+            confirmable_notifications: handler.confirmable_notifications,
         };
+        match self
+            .routes
+            .iter_mut()
+            .find(|r| r.path == info.path && r.method == info.method)
+        {
+            Some(existing) => *existing = info,
+            None => self.routes.push(info),
+        }
+
+        methods.insert(key, handler);
+        self.inner.add(route, methods);
     }
 
-    /// Looks up an observer handler for a given path.
-    pub fn lookup_observer_handler(&self, path: &str) -> Option<Box<dyn ErasedHandler<S>>> {
-        tracing::debug!("Looking up observer handler for path: '{}'", path);
-        match self.inner.recognize(path) {
-            Ok(matched) => {
-                let handler = matched.handler();
+    /// Sets the payload limit of the route's handler for `method`.
+    /// Returns `false` if the route has no handler for the method.
+    pub(crate) fn set_payload_limit(
+        &mut self,
+        route: &str,
+        method: RequestType,
+        limit: usize,
+    ) -> bool {
+        let mut methods = match self.inner.recognize(route) {
+            Ok(r) => (**r.handler()).clone(),
+            Err(_) => return false,
+        };
+        let Some(handler) = methods.get_mut(&method.into()) else {
+            return false;
+        };
+        handler.payload_limit = Some(limit);
+        self.inner.add(route, methods);
+        true
+    }
 
-                // If it's an observe, get by default
-                let reqtype: RequestTypeWrapper = RequestType::Get.into();
+    /// Marks a critical option number as handled by the application, so
+    /// requests carrying it are no longer rejected with 4.02 Bad Option.
+    pub fn recognize_option(&mut self, number: u16) {
+        self.options.register(number);
+    }
 
-                tracing::debug!("Matched route: {:?}", matched);
-                match handler.get(&reqtype) {
-                    Some(h) => {
-                        tracing::debug!(
-                            "Matched handler, has observe_handler: {}",
-                            h.observe_handler.is_some()
-                        );
-                        h.observe_handler
-                            .as_ref()
-                            .map(|handler| handler.clone_erased())
-                    }
-                    None => {
-                        tracing::debug!("No handler found for GET method");
-                        None
+    /// Returns the option numbers this router accepts.
+    pub fn options(&self) -> &OptionRegistry {
+        &self.options
+    }
+
+    /// Routes requests for `alias` as if they were sent to `target`.
+    ///
+    /// Paths are compared without leading and trailing slashes. Aliases are
+    /// not followed further: a target that is itself an alias is routed as is.
+    pub fn alias(&mut self, alias: &str, target: &str) {
+        self.aliases.insert(
+            alias.trim_matches('/').to_string(),
+            target.trim_matches('/').to_string(),
+        );
+    }
+
+    /// Returns the path a request for `path` is routed as.
+    pub fn resolve_alias<'a>(&'a self, path: &'a str) -> &'a str {
+        self.aliases
+            .get(path.trim_matches('/'))
+            .map_or(path, String::as_str)
+    }
+
+    /// Rewrites the request's path if it is an alias.
+    ///
+    /// Only the routed path changes; the packet keeps the Uri-Path options
+    /// the client sent.
+    pub(crate) fn rewrite_alias(&self, request: &mut CoapumRequest<SocketAddr>) {
+        if let Some(target) = self.aliases.get(request.path.trim_matches('/')) {
+            tracing::debug!("Rewriting alias '{}' to '{}'", request.path, target);
+            request.path = target.clone();
+        }
+    }
+
+    /// Sends notifications of paths matching `pattern` with `priority`
+    /// when a connection's notification queue backs up.
+    pub fn set_priority(&mut self, pattern: &str, priority: Priority) {
+        self.priorities.set(pattern, priority);
+    }
+
+    /// Returns the send priorities of routes.
+    pub fn priorities(&self) -> &Priorities {
+        &self.priorities
+    }
+
+    /// Answers requests an extractor rejects with `handler`, for every route.
+    pub fn set_rejection_handler(&mut self, handler: RejectionHandler) {
+        self.rejection_handler = Some(handler);
+    }
+
+    /// Returns the registered routes in the order they were added.
+    pub fn routes(&self) -> &[RouteInfo] {
+        &self.routes
+    }
+
+    /// Sets where observations of paths without their own observe route
+    /// look for one.
+    pub fn set_observe_fallback(&mut self, fallback: ObserveFallback) {
+        self.observe_fallback = fallback;
+    }
+
+    /// Sets whether observe registrations the GET handler answers with 4.04
+    /// Not Found are answered with the value stored at the path instead.
+    pub fn set_observe_from_backend(&mut self, enabled: bool) {
+        self.observe_from_backend = enabled;
+    }
+
+    /// Leaves the Observe option of requests for paths matching `pattern`
+    /// to their handlers instead of registering observers automatically.
+    pub fn set_manual_observe(&mut self, pattern: &str) {
+        self.manual_observe.add(pattern, ());
+    }
+
+    /// Returns true if the Observe option of requests for `path` is left to
+    /// its handler.
+    pub fn is_manual_observe(&self, path: &str) -> bool {
+        self.manual_observe.recognize(path).is_ok()
+    }
+
+    /// Returns the GET route handling observations of `path` if it has an
+    /// observe handler, falling back to a related route as configured with
+    /// [`set_observe_fallback`](Self::set_observe_fallback).
+    fn observe_route(&self, path: &str) -> Option<&RouteHandler<S>> {
+        let observable = |pattern: &str| {
+            let matched = self.inner.recognize(pattern).ok()?;
+            let reqtype: RequestTypeWrapper = RequestType::Get.into();
+            tracing::debug!("Matched route: {:?}", matched);
+            // Copy the reference out so it outlives `matched`
+            let handlers: &HashMap<RequestTypeWrapper, RouteHandler<S>> = matched.handler();
+            handlers
+                .get(&reqtype)
+                .filter(|h| h.observe_handler.is_some())
+        };
+
+        if let Some(route) = observable(path) {
+            return Some(route);
+        }
+
+        match self.observe_fallback {
+            ObserveFallback::Exact => None,
+            ObserveFallback::Parent => {
+                let mut parent = path.trim_end_matches('/');
+                while let Some((ancestor, _)) = parent.rsplit_once('/') {
+                    parent = ancestor;
+                    if let Some(route) = observable(parent) {
+                        tracing::debug!("Observing '{}' with parent route '{}'", path, parent);
+                        return Some(route);
                     }
                 }
-            }
-            Err(e) => {
-                tracing::warn!(
-                    "Unable to recognize observer handler path '{}'. Err: {}",
-                    path,
-                    e
-                );
                 None
             }
+            ObserveFallback::Subtree => self
+                .routes
+                .iter()
+                .filter(|r| r.observable && pattern_extends(&r.path, path))
+                .find_map(|r| {
+                    tracing::debug!("Observing '{}' with subtree route '{}'", path, r.path);
+                    observable(&r.path)
+                }),
+        }
+    }
+
+    /// Looks up an observer handler for a given path.
+    pub fn lookup_observer_handler(&self, path: &str) -> Option<Box<dyn ErasedHandler<S>>> {
+        tracing::debug!("Looking up observer handler for path: '{}'", path);
+        let handler = self
+            .observe_route(path)
+            .and_then(|route| route.observe_handler.as_ref())
+            .map(|handler| handler.clone_erased());
+        if handler.is_none() {
+            tracing::debug!("No observe handler for path '{}'", path);
         }
+        handler
     }
 
     /// Returns true if the given path has a registered observe handler.
     pub fn has_observe_route(&self, path: &str) -> bool {
-        self.lookup_observer_handler(path).is_some()
+        self.observe_route(path).is_some()
     }
 
     /// Returns true if the given observe path uses Confirmable notifications.
     pub fn is_confirmable_notify(&self, path: &str) -> bool {
-        match self.inner.recognize(path) {
-            Ok(matched) => {
-                let handler = matched.handler();
-                let reqtype: RequestTypeWrapper = RequestType::Get.into();
-                handler
-                    .get(&reqtype)
-                    .is_some_and(|h| h.confirmable_notifications)
-            }
-            Err(_) => false,
-        }
+        self.observe_route(path)
+            .is_some_and(|route| route.confirmable_notifications)
     }
 
     /// Looks up a handler for a given request.
     /// Returns `Found(handler)` on match, `NotFound` for unknown paths,
     /// or `MethodNotAllowed` when the path exists but the method doesn't.
     pub(crate) fn lookup(&self, r: &CoapumRequest<SocketAddr>) -> LookupResult<S> {
+        // The registering GET goes to the route that will send the notifications
+        if *r.get_observe_flag() == Some(ObserveOption::Register)
+            && *r.get_method() == RequestType::Get
+            && !self.is_manual_observe(r.get_path())
+            && let Some(route) = self.observe_route(r.get_path())
+        {
+            return LookupResult::Found(
+                route.handler.clone_erased(),
+                route.payload_limit,
+                route.required_tags.clone(),
+            );
+        }
+
         match self.inner.recognize(r.get_path()) {
             Ok(matched) => {
                 let handler = matched.handler();
@@ -667,7 +1289,11 @@ where
                 match handler.get(&reqtype) {
                     Some(h) => {
                         tracing::debug!("Matched handler: {:?}", h);
-                        LookupResult::Found(h.handler.clone_erased())
+                        LookupResult::Found(
+                            h.handler.clone_erased(),
+                            h.payload_limit,
+                            h.required_tags.clone(),
+                        )
                     }
                     None => {
                         tracing::debug!("No handler for method");
@@ -690,6 +1316,10 @@ where
     O: Observer + Send + Sync + Clone + 'static,
 {
     router: CoapRouter<O, S>,
+    /// Routes registered twice, reported by [`build`](Self::build)
+    duplicates: Vec<DuplicateRoute>,
+    #[cfg(feature = "admin")]
+    admin: Option<crate::admin::AdminConfig>,
 }
 
 impl<O, S> RouterBuilder<O, S>
@@ -701,48 +1331,191 @@ where
     pub fn new(state: S, observer: O) -> Self {
         Self {
             router: CoapRouter::new(state, observer),
+            duplicates: Vec::new(),
+            #[cfg(feature = "admin")]
+            admin: None,
         }
     }
 
     /// Generic method to add a route with any HTTP method
-    fn add_route<F, T>(&mut self, path: &str, method: RequestType, handler: F)
+    pub(crate) fn add_route<F, T>(&mut self, path: &str, method: RequestType, handler: F)
     where
         HandlerFn<F, S>: Handler<T, S>,
         F: Send + Sync + Clone,
         T: Send + Sync + 'static,
+    {
+        self.add_route_with_limit(path, method, handler, None);
+    }
+
+    /// Add a route with an optional payload limit override
+    pub(crate) fn add_route_with_limit<F, T>(
+        &mut self,
+        path: &str,
+        method: RequestType,
+        handler: F,
+        payload_limit: Option<usize>,
+    ) where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
     {
         let route_handler = RouteHandler {
             handler: into_erased_handler(into_handler(handler)),
             observe_handler: None,
             method,
             confirmable_notifications: false,
+            payload_limit,
+            required_tags: Vec::new(),
         };
-        self.router.add(path, route_handler);
+        self.insert(path, route_handler);
     }
 
-    /// Add a GET route with an ergonomic handler
-    pub fn get<F, T>(mut self, path: &str, handler: F) -> Self
-    where
+    /// Add a route restricted to clients with every one of `tags`
+    pub(crate) fn add_route_with_tags<F, T, I>(
+        &mut self,
+        path: &str,
+        method: RequestType,
+        handler: F,
+        tags: I,
+    ) where
         HandlerFn<F, S>: Handler<T, S>,
         F: Send + Sync + Clone,
         T: Send + Sync + 'static,
+        I: IntoIterator,
+        I::Item: Into<String>,
     {
-        self.add_route(path, RequestType::Get, handler);
-        self
+        let route_handler = RouteHandler {
+            handler: into_erased_handler(into_handler(handler)),
+            observe_handler: None,
+            method,
+            confirmable_notifications: false,
+            payload_limit: None,
+            required_tags: tags.into_iter().map(Into::into).collect(),
+        };
+        self.insert(path, route_handler);
     }
 
-    /// Add a POST route with an ergonomic handler
-    pub fn post<F, T>(mut self, path: &str, handler: F) -> Self
-    where
-        HandlerFn<F, S>: Handler<T, S>,
-        F: Send + Sync + Clone,
-        T: Send + Sync + 'static,
-    {
-        self.add_route(path, RequestType::Post, handler);
-        self
+    /// Add a route, remembering it if the path already has a handler for
+    /// the method
+    fn insert(&mut self, path: &str, route_handler: RouteHandler<S>) {
+        if let Some(duplicate) = self.router.duplicate_of(path, &route_handler) {
+            self.duplicates.push(duplicate);
+        }
+        self.router.replace(path, route_handler);
     }
 
-    /// Add a PUT route with an ergonomic handler
+    /// Add a route for `method`, replacing a handler registered earlier
+    ///
+    /// Registering a path twice for the same method is logged by
+    /// [`build`](Self::build) and refused by [`try_build`](Self::try_build);
+    /// this is the way to replace a handler on purpose, e.g. one of a
+    /// [`nest`](Self::nest)ed group or a [`resource`](Self::resource).
+    /// Replacing the GET handler of an [`observe`](Self::observe) route keeps
+    /// its observe handler.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use coapum::{RequestType, RouterBuilder, extract::StatusCode};
+    /// use coapum::router::nest::Routes;
+    ///
+    /// async fn reboot() -> StatusCode {
+    ///     StatusCode::Changed
+    /// }
+    ///
+    /// async fn reboot_disabled() -> StatusCode {
+    ///     StatusCode::Forbidden
+    /// }
+    ///
+    /// let router = RouterBuilder::new((), ())
+    ///     .nest("/device", Routes::<()>::new().post("/reboot", reboot))
+    ///     .override_route("/device/reboot", RequestType::Post, reboot_disabled)
+    ///     .build();
+    /// ```
+    pub fn override_route<F, T>(mut self, path: &str, method: RequestType, handler: F) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        let route_handler = RouteHandler {
+            handler: into_erased_handler(into_handler(handler)),
+            observe_handler: None,
+            method,
+            confirmable_notifications: false,
+            payload_limit: None,
+            required_tags: Vec::new(),
+        };
+        self.router.replace(path, route_handler);
+        self
+    }
+
+    /// Add a GET route with an ergonomic handler
+    pub fn get<F, T>(mut self, path: &str, handler: F) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        self.add_route(path, RequestType::Get, handler);
+        self
+    }
+
+    /// Add a POST route with an ergonomic handler
+    pub fn post<F, T>(mut self, path: &str, handler: F) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        self.add_route(path, RequestType::Post, handler);
+        self
+    }
+
+    /// Add a POST route that accepts payloads up to `limit` bytes
+    ///
+    /// The limit replaces the server-wide [`PayloadLimits`](crate::extract::PayloadLimits)
+    /// for every payload extractor on this route, e.g. to allow firmware
+    /// uploads larger than the default 8 KB CBOR limit.
+    pub fn post_with_limit<F, T>(mut self, path: &str, handler: F, limit: usize) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        self.add_route_with_limit(path, RequestType::Post, handler, Some(limit));
+        self
+    }
+
+    /// Accept payloads up to `limit` bytes on a route added earlier
+    ///
+    /// Works like [`post_with_limit`](Self::post_with_limit) for any route,
+    /// e.g. one added with [`post_with_tags`](Self::post_with_tags). Routes
+    /// that are not registered yet for `method` are left alone with a
+    /// warning.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use coapum::{RequestType, RouterBuilder, extract::StatusCode};
+    ///
+    /// async fn upload() -> StatusCode {
+    ///     StatusCode::Changed
+    /// }
+    ///
+    /// let router = RouterBuilder::new((), ())
+    ///     .post_with_tags("/fw", upload, ["admin"])
+    ///     .payload_limit("/fw", RequestType::Post, 64 * 1024)
+    ///     .build();
+    /// ```
+    pub fn payload_limit(mut self, path: &str, method: RequestType, limit: usize) -> Self {
+        if !self.router.set_payload_limit(path, method, limit) {
+            tracing::warn!(path, method = ?method, "route.payload_limit_missing");
+        }
+        self
+    }
+
+    /// Add a PUT route with an ergonomic handler
     pub fn put<F, T>(mut self, path: &str, handler: F) -> Self
     where
         HandlerFn<F, S>: Handler<T, S>,
@@ -753,6 +1526,95 @@ where
         self
     }
 
+    /// Add a PUT route that accepts payloads up to `limit` bytes
+    ///
+    /// See [`post_with_limit`](Self::post_with_limit).
+    pub fn put_with_limit<F, T>(mut self, path: &str, handler: F, limit: usize) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        self.add_route_with_limit(path, RequestType::Put, handler, Some(limit));
+        self
+    }
+
+    /// Add a FETCH route (RFC 8132)
+    ///
+    /// The handler reads the query from the request body with
+    /// [`Fetch`](crate::extract::Fetch) and can answer it from the requesting
+    /// device's observer document with
+    /// [`DeviceDocument`](crate::extract::DeviceDocument).
+    ///
+    /// ```rust
+    /// use coapum::{RouterBuilder, extract::{Cbor, DeviceDocument, Fetch}, observer::memory::MemObserver};
+    /// use serde_json::Value;
+    ///
+    /// async fn fetch_fields(Fetch(fields): Fetch<Vec<String>>, document: DeviceDocument) -> Cbor<Value> {
+    ///     Cbor(document.select(&fields))
+    /// }
+    ///
+    /// let router = RouterBuilder::new((), MemObserver::new())
+    ///     .fetch("/device", fetch_fields)
+    ///     .build();
+    /// ```
+    pub fn fetch<F, T>(mut self, path: &str, handler: F) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        self.add_route(path, RequestType::Fetch, handler);
+        self
+    }
+
+    /// Add a PATCH route with an ergonomic handler
+    ///
+    /// Handlers can apply the body to the device's observer document with the
+    /// [`MergePatch`](crate::extract::MergePatch) and
+    /// [`DocumentPatcher`](crate::extract::DocumentPatcher) extractors.
+    pub fn patch<F, T>(mut self, path: &str, handler: F) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        self.add_route(path, RequestType::Patch, handler);
+        self
+    }
+
+    /// Add an iPATCH route with an ergonomic handler, see [`patch`](Self::patch)
+    pub fn ipatch<F, T>(mut self, path: &str, handler: F) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        self.add_route(path, RequestType::IPatch, handler);
+        self
+    }
+
+    /// Accept PATCH and iPATCH at `path` and apply the body as a JSON merge
+    /// patch (RFC 7396) to the requesting device's observer document
+    ///
+    /// The patch is applied at the request path, so with a pattern such as
+    /// `/config/:key` each key is patched in place. Observers of the patched
+    /// paths are notified. See [`apply_merge_patch`] for the handler.
+    ///
+    /// ```rust
+    /// use coapum::{RouterBuilder, observer::memory::MemObserver};
+    ///
+    /// // iPATCH /config {"interval": 60, "led": null}
+    /// let router = RouterBuilder::new((), MemObserver::new())
+    ///     .merge_patch("/config")
+    ///     .build();
+    /// ```
+    pub fn merge_patch(mut self, path: &str) -> Self {
+        self.add_route(path, RequestType::Patch, apply_merge_patch);
+        self.add_route(path, RequestType::IPatch, apply_merge_patch);
+        self
+    }
+
     /// Add a DELETE route with an ergonomic handler
     pub fn delete<F, T>(mut self, path: &str, handler: F) -> Self
     where
@@ -764,6 +1626,87 @@ where
         self
     }
 
+    /// Add a GET route for clients with every one of `tags`
+    ///
+    /// See [`post_with_tags`](Self::post_with_tags).
+    pub fn get_with_tags<F, T, I>(mut self, path: &str, handler: F, tags: I) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.add_route_with_tags(path, RequestType::Get, handler, tags);
+        self
+    }
+
+    /// Add a POST route for clients with every one of `tags`
+    ///
+    /// Tags come from the [`ClientMetadata`] of the device's PSK identity in
+    /// the credential store, read when its connection is established, so
+    /// changed tags apply from the next connection. Devices missing a tag
+    /// get 4.03 Forbidden before the handler runs. Connections without
+    /// credential store metadata, such as those of a
+    /// [`Transport`](crate::transport::Transport), have no tags. Use
+    /// [`payload_limit`](Self::payload_limit) to raise the route's payload
+    /// limit.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use coapum::{RouterBuilder, extract::StatusCode};
+    ///
+    /// async fn reboot() -> StatusCode {
+    ///     StatusCode::Changed
+    /// }
+    ///
+    /// let router = RouterBuilder::new((), ())
+    ///     .post_with_tags("/admin/reboot", reboot, ["admin"])
+    ///     .build();
+    /// ```
+    pub fn post_with_tags<F, T, I>(mut self, path: &str, handler: F, tags: I) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.add_route_with_tags(path, RequestType::Post, handler, tags);
+        self
+    }
+
+    /// Add a PUT route for clients with every one of `tags`
+    ///
+    /// See [`post_with_tags`](Self::post_with_tags).
+    pub fn put_with_tags<F, T, I>(mut self, path: &str, handler: F, tags: I) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.add_route_with_tags(path, RequestType::Put, handler, tags);
+        self
+    }
+
+    /// Add a DELETE route for clients with every one of `tags`
+    ///
+    /// See [`post_with_tags`](Self::post_with_tags).
+    pub fn delete_with_tags<F, T, I>(mut self, path: &str, handler: F, tags: I) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.add_route_with_tags(path, RequestType::Delete, handler, tags);
+        self
+    }
+
     /// Add a route that handles any HTTP method
     pub fn any<F, T>(mut self, path: &str, handler: F) -> Self
     where
@@ -776,6 +1719,13 @@ where
     }
 
     /// Add an observable GET route with separate handlers for GET and notifications
+    ///
+    /// The notify handler runs for every notification sent to an observer of
+    /// the route. It sees a request with the observed path (so [`Path`](crate::Path)
+    /// works), the token, [`Identity`](crate::Identity) and [`Source`](crate::Source)
+    /// of the connection that registered the observation, that connection's
+    /// [`Session`](crate::Session), and the new value through
+    /// [`Notification`](crate::Notification). It has no payload.
     pub fn observe<F1, T1, F2, T2>(
         mut self,
         path: &str,
@@ -795,8 +1745,10 @@ where
             observe_handler: Some(into_erased_handler(into_handler(notify_handler))),
             method: RequestType::Get,
             confirmable_notifications: false,
+            payload_limit: None,
+            required_tags: Vec::new(),
         };
-        self.router.add(path, route_handler);
+        self.insert(path, route_handler);
         self
     }
 
@@ -821,14 +1773,377 @@ where
             observe_handler: Some(into_erased_handler(into_handler(notify_handler))),
             method: RequestType::Get,
             confirmable_notifications: true,
+            payload_limit: None,
+            required_tags: Vec::new(),
         };
-        self.router.add(path, route_handler);
+        self.insert(path, route_handler);
+        self
+    }
+
+    /// Mount a group of routes with its own state type under `prefix`
+    ///
+    /// The group's state is derived from this router's state with
+    /// [`FromRef`] on every request, so handlers in the group extract only
+    /// what their subsystem needs.
+    ///
+    /// See [`Routes`](nest::Routes) for an example.
+    pub fn nest<S2>(mut self, prefix: &str, routes: nest::Routes<S2>) -> Self
+    where
+        S2: FromRef<S> + Send + Sync + 'static,
+    {
+        for (path, route) in routes.into_routes() {
+            let route_handler = RouteHandler {
+                handler: with_state(route.handler),
+                observe_handler: route.observe_handler.map(with_state),
+                method: route.method,
+                confirmable_notifications: route.confirmable_notifications,
+                payload_limit: route.payload_limit,
+                required_tags: route.required_tags,
+            };
+            self.insert(&nest::join_path(prefix, &path), route_handler);
+        }
+        self
+    }
+
+    /// Mount a [`Resource`](resource::Resource) at `path`
+    ///
+    /// GET, PUT, POST and DELETE requests are passed to the resource's
+    /// `on_*` methods. Observable resources are registered like
+    /// [`observe`](Self::observe), with notifications built by
+    /// [`on_observe`](resource::Resource::on_observe).
+    pub fn resource<R: resource::Resource>(self, path: &str, resource: R) -> Self {
+        let shared = Arc::new(resource);
+        let builder = if shared.observable() {
+            self.observe(
+                path,
+                resource::get_handler(shared.clone()),
+                resource::observe_handler(shared.clone()),
+            )
+        } else {
+            self.get(path, resource::get_handler(shared.clone()))
+        };
+        builder
+            .put(path, resource::put_handler(shared.clone()))
+            .post(path, resource::post_handler(shared.clone()))
+            .delete(path, resource::delete_handler(shared))
+    }
+
+    /// Add routes declared with `#[coap_route]`, collected by
+    /// [`routes!`](crate::routes)
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "macros")]
+    /// # {
+    /// use coapum::{RouterBuilder, StatusCode, coap_route, extract::Path};
+    ///
+    /// #[coap_route(get, "/sensors/:id")]
+    /// async fn get_sensor(Path(id): Path<String>) -> StatusCode {
+    ///     tracing::info!("GET sensor {}", id);
+    ///     StatusCode::Content
+    /// }
+    ///
+    /// #[coap_route(delete, "/sensors/:id")]
+    /// async fn delete_sensor(Path(id): Path<String>) -> StatusCode {
+    ///     tracing::info!("DELETE sensor {}", id);
+    ///     StatusCode::Deleted
+    /// }
+    ///
+    /// let router = RouterBuilder::new((), ())
+    ///     .mount(coapum::routes![get_sensor, delete_sensor])
+    ///     .build();
+    /// # }
+    /// ```
+    pub fn mount(self, routes: impl FnOnce(Self) -> Self) -> Self {
+        routes(self)
+    }
+
+    /// Accept requests carrying a custom critical option
+    ///
+    /// Requests with a critical option (odd option number) the server does
+    /// not recognize are rejected with 4.02 Bad Option before reaching a
+    /// handler. Register the options your handlers read from
+    /// [`Raw`](crate::Raw) here.
+    pub fn recognize_option(mut self, number: u16) -> Self {
+        self.router.recognize_option(number);
+        self
+    }
+
+    /// Choose how observations of a path without its own observe route
+    /// find one, see [`ObserveFallback`]
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use coapum::{ObserveFallback, RouterBuilder, extract::StatusCode};
+    ///
+    /// async fn get_key() -> StatusCode {
+    ///     StatusCode::Content
+    /// }
+    ///
+    /// async fn notify_key() -> StatusCode {
+    ///     StatusCode::Content
+    /// }
+    ///
+    /// let router = RouterBuilder::new((), ())
+    ///     .observe("/device/config/:key", get_key, notify_key)
+    ///     .observe_fallback(ObserveFallback::Subtree)
+    ///     .build();
+    /// assert!(router.has_observe_route("/device/config"));
+    /// ```
+    pub fn observe_fallback(mut self, fallback: ObserveFallback) -> Self {
+        self.router.set_observe_fallback(fallback);
+        self
+    }
+
+    /// Answer observe registrations from the backend when the GET handler
+    /// has nothing to return
+    ///
+    /// If the handler of a registering GET responds 4.04 Not Found, the
+    /// value stored for the device at the path is returned instead as 2.05
+    /// Content: the payload as the device sent it if the backend kept it
+    /// (see [`Observer::read_raw`](crate::observer::Observer::read_raw)),
+    /// otherwise the stored JSON. The registration then succeeds. If
+    /// nothing is stored either, the 4.04 stands. Plain GETs are unaffected.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use coapum::{RouterBuilder, extract::StatusCode};
+    ///
+    /// // Pure storage: values come from writes, reads from the backend
+    /// async fn not_here() -> StatusCode {
+    ///     StatusCode::NotFound
+    /// }
+    ///
+    /// async fn notify() -> StatusCode {
+    ///     StatusCode::Content
+    /// }
+    ///
+    /// let router = RouterBuilder::new((), ())
+    ///     .observe("/shadow/:key", not_here, notify)
+    ///     .observe_from_backend(true)
+    ///     .build();
+    /// ```
+    pub fn observe_from_backend(mut self, enabled: bool) -> Self {
+        self.router.set_observe_from_backend(enabled);
         self
     }
 
-    /// Build the final router
-    pub fn build(self) -> CoapRouter<O, S> {
-        self.router
+    /// Let the handlers of the paths matching `pattern` handle the Observe
+    /// option themselves
+    ///
+    /// GETs to these paths with Observe set are routed like plain GETs and
+    /// never register or deregister an observer with the backend, so no
+    /// notifications are sent for them. The handler reads the option with
+    /// [`ObserveFlag`](crate::extract::ObserveFlag) and answers as it sees
+    /// fit, e.g. a proxy route forwarding the observation upstream.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use coapum::{RouterBuilder, extract::{ObserveFlag, StatusCode}};
+    ///
+    /// async fn proxy(ObserveFlag(observe): ObserveFlag) -> StatusCode {
+    ///     // Forward the request upstream, keeping its Observe option
+    ///     println!("observe: {:?}", observe);
+    ///     StatusCode::Content
+    /// }
+    ///
+    /// let router = RouterBuilder::new((), ())
+    ///     .get("/proxy/*target", proxy)
+    ///     .manual_observe("/proxy/*target")
+    ///     .build();
+    /// assert!(router.is_manual_observe("/proxy/sensors/temp"));
+    /// ```
+    pub fn manual_observe(mut self, pattern: &str) -> Self {
+        self.router.set_manual_observe(pattern);
+        self
+    }
+
+    /// Route requests for a legacy path to the handlers of another one
+    ///
+    /// The alias is resolved before matching, so handlers, extractors such
+    /// as [`Path`](crate::extract::Path) and observer registrations all see
+    /// the target path.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use coapum::{RouterBuilder, extract::StatusCode};
+    ///
+    /// async fn temperature() -> StatusCode {
+    ///     StatusCode::Content
+    /// }
+    ///
+    /// // Older firmware still requests /t
+    /// let router = RouterBuilder::new((), ())
+    ///     .get("/sensors/temp", temperature)
+    ///     .alias("/t", "/sensors/temp")
+    ///     .build();
+    /// assert_eq!(router.resolve_alias("/t"), "sensors/temp");
+    /// ```
+    pub fn alias(mut self, alias: &str, target: &str) -> Self {
+        self.router.alias(alias, target);
+        self
+    }
+
+    /// Tag the routes matching `pattern` with a send priority
+    ///
+    /// When notifications back up on a congested link, those of
+    /// higher-priority routes are sent first and lower-priority ones are
+    /// dropped first. See [`priority`] for details.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use coapum::{Priority, RouterBuilder, extract::StatusCode};
+    ///
+    /// async fn reading() -> StatusCode {
+    ///     StatusCode::Content
+    /// }
+    ///
+    /// let router = RouterBuilder::new((), ())
+    ///     .observe("/alarms/:id", reading, reading)
+    ///     .observe("/telemetry/:sensor", reading, reading)
+    ///     .priority("/alarms/:id", Priority::High)
+    ///     .priority("/telemetry/:sensor", Priority::Low)
+    ///     .build();
+    /// assert_eq!(router.priorities().get("/alarms/door"), Priority::High);
+    /// ```
+    pub fn priority(mut self, pattern: &str, priority: Priority) -> Self {
+        self.router.set_priority(pattern, priority);
+        self
+    }
+
+    /// Answer requests an extractor rejects with the response `f` returns
+    ///
+    /// `f` sees every rejection before the handler would have run, with its
+    /// [`kind`](Rejection::kind), default [`status`](Rejection::status) and
+    /// description, and returns `None` to keep the default response. See
+    /// [`rejection`](crate::extract::rejection) for details.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use coapum::RouterBuilder;
+    /// use coapum::extract::{Cbor, Json, Rejection, StatusCode};
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// #[derive(Serialize)]
+    /// struct Diagnostic {
+    ///     kind: &'static str,
+    ///     message: String,
+    /// }
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Reading {
+    ///     temp: f32,
+    /// }
+    ///
+    /// async fn store(Json(_reading): Json<Reading>) -> StatusCode {
+    ///     StatusCode::Changed
+    /// }
+    ///
+    /// let router = RouterBuilder::new((), ())
+    ///     .post("/readings", store)
+    ///     .rejection_handler(|rejection: &dyn Rejection| {
+    ///         let diagnostic = Diagnostic {
+    ///             kind: rejection.kind(),
+    ///             message: rejection.to_string(),
+    ///         };
+    ///         Some((rejection.status(), Cbor(diagnostic)))
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn rejection_handler<F, R>(mut self, f: F) -> Self
+    where
+        F: Fn(&dyn Rejection) -> Option<R> + Send + Sync + 'static,
+        R: crate::extract::IntoResponse,
+    {
+        self.router.set_rejection_handler(RejectionHandler::new(f));
+        self
+    }
+
+    /// Mount the built-in admin resources when the router is built
+    ///
+    /// See [`AdminConfig`](crate::admin::AdminConfig) for the resources served
+    /// and how access is restricted.
+    #[cfg(feature = "admin")]
+    pub fn admin(mut self, config: crate::admin::AdminConfig) -> Self {
+        self.admin = Some(config);
+        self
+    }
+
+    /// Serve `GET /health` and `GET /ready` with the checks of `health`
+    ///
+    /// A check pinging the router's observer backend is added to the
+    /// registry. See [`health`](crate::health) for the responses.
+    pub fn health(mut self, health: crate::health::HealthRegistry) -> Self {
+        let observer = self.router.db.clone();
+        crate::health::mount(&mut self, health, observer);
+        self
+    }
+
+    /// Serve the last-seen times of `tracker` as `GET /devices/:id/last-seen`
+    ///
+    /// The response is JSON with the identity, its last-seen time in
+    /// seconds since the Unix epoch and the seconds since then; identities
+    /// the tracker hasn't seen get 4.04 Not Found. Any client may read any
+    /// identity's entry, so restrict the route with your own checks if
+    /// devices shouldn't see each other. See
+    /// [`LivenessTracker`](crate::liveness::LivenessTracker).
+    pub fn last_seen_route(mut self, tracker: crate::liveness::LivenessTracker) -> Self {
+        crate::liveness::mount(&mut self, tracker);
+        self
+    }
+
+    /// Serve the server's clock as `GET /time` for devices without one
+    ///
+    /// The response carries the time in seconds since the Unix epoch and
+    /// echoes the request's `t` query parameter, so devices can estimate
+    /// the round trip. See [`time_sync`](crate::time_sync) for the formats.
+    pub fn time_sync(mut self, config: crate::time_sync::TimeSync) -> Self {
+        crate::time_sync::mount(&mut self, config);
+        self
+    }
+
+    /// Build the final router
+    ///
+    /// # Panics
+    ///
+    /// If a path was registered twice for the same method. Use
+    /// [`override_route`](Self::override_route) to replace a handler on
+    /// purpose, or [`try_build`](Self::try_build) to handle duplicates as an
+    /// error.
+    pub fn build(self) -> CoapRouter<O, S> {
+        match self.try_build() {
+            Ok(router) => router,
+            Err(duplicate) => panic!("{}", duplicate),
+        }
+    }
+
+    /// Build the router, failing if a path was registered twice for the
+    /// same method
+    ///
+    /// The error names the first such route. Use
+    /// [`override_route`](Self::override_route) to replace a handler on
+    /// purpose.
+    pub fn try_build(self) -> Result<CoapRouter<O, S>, DuplicateRoute> {
+        let (router, duplicates) = self.finish();
+        match duplicates.into_iter().next() {
+            Some(duplicate) => Err(duplicate),
+            None => Ok(router),
+        }
+    }
+
+    #[cfg_attr(not(feature = "admin"), allow(unused_mut))]
+    fn finish(mut self) -> (CoapRouter<O, S>, Vec<DuplicateRoute>) {
+        // Mounted last so the admin route listing includes every route
+        #[cfg(feature = "admin")]
+        if let Some(config) = self.admin.take() {
+            crate::admin::mount(&mut self, config);
+        }
+        (self.router, self.duplicates)
     }
 
     /// Create a notification trigger handle for external code to trigger observer notifications
@@ -907,6 +2222,7 @@ pub struct CoapumRequest<Endpoint> {
     pub response: Option<CoapResponse>,
     pub source: Option<Endpoint>,
     pub identity: String,
+    extensions: Extensions,
 }
 
 /// An implementation block that provides methods to convert `CoapRequest` into `CoapumRequest` and get various details of the request.
@@ -927,6 +2243,7 @@ impl<Endpoint> From<CoapRequest<Endpoint>> for CoapumRequest<Endpoint> {
             code,
             observe_flag,
             identity: String::new(),
+            extensions: Extensions::new(),
         }
     }
 }
@@ -946,6 +2263,84 @@ impl<Endpoint> CoapumRequest<Endpoint> {
     pub fn get_observe_flag(&self) -> &Option<ObserveOption> {
         &self.observe_flag
     }
+
+    /// Returns the message type (CON, NON, ACK or RST) of the `CoapumRequest`.
+    pub fn get_message_type(&self) -> MessageType {
+        self.message.header.get_type()
+    }
+
+    /// Returns true if the request was sent as a Confirmable message.
+    ///
+    /// The server answers CON requests with a piggybacked ACK and NON
+    /// requests with a NON response (RFC 7252 §5.2).
+    pub fn is_confirmable(&self) -> bool {
+        self.get_message_type() == MessageType::Confirmable
+    }
+
+    /// Returns the response classes the client asked not to receive (RFC 7967).
+    pub fn no_response(&self) -> NoResponse {
+        NoResponse::from_packet(&self.message)
+    }
+
+    /// Returns the request extensions.
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Returns the request extensions mutably, for layers that attach data
+    /// for handlers to extract with [`Extension`](crate::extract::Extension).
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+}
+
+/// The first of `required` the requesting client's session has no tag for
+fn missing_tag<'a>(request: &CoapumRequest<SocketAddr>, required: &'a [String]) -> Option<&'a str> {
+    let session = request.extensions().get::<Session>();
+    required
+        .iter()
+        .find(|tag| !session.is_some_and(|session| session.has_tag(tag)))
+        .map(String::as_str)
+}
+
+/// The value stored at `path` as a 2.05 Content response, `None` if there
+/// is none
+async fn stored_value_response<O: Observer>(
+    db: &mut O,
+    identity: &str,
+    path: &str,
+) -> Option<CoapResponse> {
+    // Prefer the payload as the device sent it over its JSON conversion
+    let raw = db.read_raw(identity, path).await.unwrap_or_else(|e| {
+        tracing::error!("Failed to read raw value for observe registration: {:?}", e);
+        None
+    });
+    let (content_format, payload) = match raw {
+        Some(raw) => (raw.content_format, raw.bytes),
+        None => {
+            let value = match db.read(identity, path).await {
+                Ok(value) => value?,
+                Err(e) => {
+                    tracing::error!("Failed to read value for observe registration: {:?}", e);
+                    return None;
+                }
+            };
+            (
+                ContentFormat::ApplicationJSON,
+                serde_json::to_vec(&value).ok()?,
+            )
+        }
+    };
+
+    tracing::debug!(
+        "Answering observe registration of '{}' from the backend",
+        path
+    );
+    let mut response = CoapResponse::new(&Packet::new())?;
+    response.message.payload = payload;
+    response.message.set_content_format(content_format);
+    response.set_status(ResponseType::Content);
+    Some(response)
 }
 
 /// Implementation of the `Service` trait for `CoapRouter` with `CoapumRequest` as the request type.
@@ -972,15 +2367,81 @@ where
     }
 
     /// Handles a `CoapumRequest` and returns a future that resolves to a `CoapResponse`.
-    fn call(&mut self, request: CoapumRequest<SocketAddr>) -> Self::Future {
+    fn call(&mut self, mut request: CoapumRequest<SocketAddr>) -> Self::Future {
         let state = self.state.clone(); // Clone the state so it can be moved into the async block
 
+        self.rewrite_alias(&mut request);
         match self.lookup(&request) {
-            LookupResult::Found(handler) => {
+            LookupResult::Found(handler, payload_limit, required_tags) => {
                 let path = request.get_path();
                 tracing::debug!("Handler found for route: {:?}", &path);
 
-                Box::pin(async move { handler.call_erased(request, state).await })
+                if let Some(missing) = missing_tag(&request, &required_tags) {
+                    tracing::info!(
+                        identity = %request.identity,
+                        path = %request.get_path(),
+                        tag = %missing,
+                        "route.forbidden"
+                    );
+                    return Box::pin(
+                        async move { (ResponseType::Forbidden, &request).into_response() },
+                    );
+                }
+
+                if let Some(limit) = payload_limit {
+                    let limits = PayloadLimits::for_request(&request).with_route_limit(limit);
+                    request.extensions_mut().insert(limits);
+                }
+
+                if let Some(rejection_handler) = &self.rejection_handler {
+                    request.extensions_mut().insert(rejection_handler.clone());
+                }
+
+                let access = DocumentAccess::new(
+                    self.db.clone(),
+                    request.identity.clone(),
+                    request.get_path().to_string(),
+                );
+                let batch = BackendBatch::new(access.clone());
+                request.extensions_mut().insert(access);
+                request.extensions_mut().insert(batch.clone());
+
+                if matches!(
+                    request.get_method(),
+                    RequestType::Patch | RequestType::IPatch
+                ) {
+                    let patcher = DocumentPatcher::new(
+                        self.db.clone(),
+                        request.identity.clone(),
+                        request.get_path().to_string(),
+                    );
+                    request.extensions_mut().insert(patcher);
+                }
+
+                if self.observe_from_backend
+                    && *request.get_observe_flag() == Some(ObserveOption::Register)
+                    && *request.get_method() == RequestType::Get
+                    && !self.is_manual_observe(request.get_path())
+                {
+                    let mut db = self.db.clone();
+                    let identity = request.identity.clone();
+                    let path = request.get_path().to_string();
+                    return Box::pin(async move {
+                        let Ok(response) = handler.call_erased(request, state).await;
+                        let response = batch.finish(response).await;
+                        if *response.get_status() != ResponseType::NotFound {
+                            return Ok(response);
+                        }
+                        Ok(stored_value_response(&mut db, &identity, &path)
+                            .await
+                            .unwrap_or(response))
+                    });
+                }
+
+                Box::pin(async move {
+                    let Ok(response) = handler.call_erased(request, state).await;
+                    Ok(batch.finish(response).await)
+                })
             }
             LookupResult::NotFound => {
                 tracing::info!("No route for path: {:?}", request.get_path());
@@ -1030,14 +2491,43 @@ where
             Some(handler) => {
                 tracing::debug!("Handler found for route: {:?}", &request.path);
 
-                let packet = Packet::default();
+                // The synthetic request mirrors the registering GET: same
+                // path, token, identity and source
+                let mut packet = Packet::default();
+                packet.set_token(request.token.clone());
                 let mut raw = CoapRequest::from_packet(packet, request.source);
-                // Set the path in the request for proper parameter extraction
                 raw.set_path(&request.path);
 
+                let ObserverRequest {
+                    value,
+                    path,
+                    encoded,
+                    changed,
+                    identity,
+                    extensions,
+                    ..
+                } = request;
+
                 let mut coap_request: CoapumRequest<SocketAddr> = raw.into();
-                // Identity should be empty or properly set - not the path
-                coap_request.identity = String::new();
+                coap_request.identity = identity;
+
+                // Hand the triggering value to the `Notification` extractor
+                coap_request.extensions = extensions;
+                coap_request.extensions.insert(ObserverValue {
+                    value,
+                    path,
+                    encoded,
+                    changed,
+                });
+                if let Some(rejection_handler) = &self.rejection_handler {
+                    coap_request.extensions.insert(rejection_handler.clone());
+                }
+                let access = DocumentAccess::new(
+                    self.db.clone(),
+                    coap_request.identity.clone(),
+                    coap_request.get_path().to_string(),
+                );
+                coap_request.extensions.insert(access);
 
                 Box::pin(async move { handler.call_erased(coap_request, state).await })
             }
@@ -1073,7 +2563,8 @@ mod tests {
         let state = TestState { counter: 0 };
         let mut router = CoapRouter::new(state, ());
 
-        let (sender, _receiver) = tokio::sync::mpsc::channel(10);
+        let (sender, _receiver) =
+            crate::observer::queue::notification_channel(10, Default::default());
         let sender = Arc::new(sender);
 
         let result = router
@@ -1116,9 +2607,11 @@ mod tests {
             observe_handler: None,
             method: RequestType::Get,
             confirmable_notifications: false,
+            payload_limit: None,
+            required_tags: Vec::new(),
         };
 
-        router.add("/test", handler);
+        router.try_add("/test", handler).unwrap();
 
         // Create a test request
         let packet = Packet::new();
@@ -1128,7 +2621,23 @@ mod tests {
         request.code = RequestType::Get;
 
         let result = router.lookup(&request);
-        assert!(matches!(result, LookupResult::Found(_)));
+        assert!(matches!(result, LookupResult::Found(..)));
+    }
+
+    #[test]
+    fn test_request_message_type() {
+        let mut packet = Packet::new();
+        packet.header.set_type(MessageType::NonConfirmable);
+        let raw = CoapRequest::from_packet(packet, "127.0.0.1:5683".parse().unwrap());
+        let request: CoapumRequest<SocketAddr> = raw.into();
+        assert_eq!(request.get_message_type(), MessageType::NonConfirmable);
+        assert!(!request.is_confirmable());
+
+        let mut packet = Packet::new();
+        packet.header.set_type(MessageType::Confirmable);
+        let raw = CoapRequest::from_packet(packet, "127.0.0.1:5683".parse().unwrap());
+        let request: CoapumRequest<SocketAddr> = raw.into();
+        assert!(request.is_confirmable());
     }
 
     #[tokio::test]
@@ -1155,8 +2664,10 @@ mod tests {
             observe_handler: None,
             method: RequestType::Get,
             confirmable_notifications: false,
+            payload_limit: None,
+            required_tags: Vec::new(),
         };
-        router.add("/test", handler);
+        router.try_add("/test", handler).unwrap();
 
         let packet = Packet::new();
         let raw = CoapRequest::from_packet(packet, "127.0.0.1:5683".parse().unwrap());
@@ -1183,9 +2694,11 @@ mod tests {
             }))),
             method: RequestType::Get,
             confirmable_notifications: false,
+            payload_limit: None,
+            required_tags: Vec::new(),
         };
 
-        router.add("/observable", handler);
+        router.try_add("/observable", handler).unwrap();
 
         let result = router.lookup_observer_handler("/observable");
         assert!(result.is_some());
@@ -1253,6 +2766,98 @@ mod tests {
         // Test the convenience builder method
     }
 
+    #[tokio::test]
+    async fn test_duplicate_route_rejected() {
+        async fn first() -> StatusCode {
+            StatusCode::Content
+        }
+        async fn second() -> StatusCode {
+            StatusCode::Forbidden
+        }
+
+        let err = RouterBuilder::new(TestState { counter: 0 }, ())
+            .get("/test", first)
+            .post("/test", first)
+            .get("/test", second)
+            .try_build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            DuplicateRoute {
+                path: "/test".to_string(),
+                method: RequestType::Get,
+            }
+        );
+        assert_eq!(err.to_string(), "Route /test already has a handler for Get");
+
+        let mut router = RouterBuilder::new(TestState { counter: 0 }, ())
+            .get("/test", first)
+            .override_route("/test", RequestType::Get, second)
+            .build();
+        assert_eq!(router.routes().len(), 1);
+        let mut raw = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+        raw.set_path("/test");
+        raw.set_method(RequestType::Get);
+        let request: CoapumRequest<SocketAddr> = raw.into();
+        let resp = router.call(request).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Forbidden);
+    }
+
+    #[test]
+    #[should_panic(expected = "Route /test already has a handler for Post")]
+    fn test_duplicate_route_panics_on_build() {
+        async fn first() -> StatusCode {
+            StatusCode::Changed
+        }
+        async fn second() -> StatusCode {
+            StatusCode::Forbidden
+        }
+
+        RouterBuilder::new(TestState { counter: 0 }, ())
+            .post("/test", first)
+            .post("/test", second)
+            .build();
+    }
+
+    #[test]
+    fn test_get_and_observe_are_one_route() {
+        async fn get_handler() -> StatusCode {
+            StatusCode::Content
+        }
+        async fn notify_handler() -> StatusCode {
+            StatusCode::Content
+        }
+
+        // In either order, the route stays observable
+        let router = RouterBuilder::new(TestState { counter: 0 }, ())
+            .get("/a", get_handler)
+            .observe("/a", get_handler, notify_handler)
+            .observe_confirmable("/b", get_handler, notify_handler)
+            .get("/b", get_handler)
+            .try_build()
+            .unwrap();
+        assert_eq!(router.routes().len(), 2);
+        assert!(router.routes().iter().all(|route| route.observable));
+        assert!(router.routes()[1].confirmable_notifications);
+
+        let err = RouterBuilder::new(TestState { counter: 0 }, ())
+            .observe("/a", get_handler, notify_handler)
+            .observe("/a", get_handler, notify_handler)
+            .try_build()
+            .err()
+            .unwrap();
+        assert_eq!(err.method, RequestType::Get);
+
+        // Overriding the GET handler keeps the observe handler
+        let router = RouterBuilder::new(TestState { counter: 0 }, ())
+            .observe("/a", get_handler, notify_handler)
+            .override_route("/a", RequestType::Get, get_handler)
+            .build();
+        assert!(router.has_observe_route("/a"));
+        assert!(router.lookup_observer_handler("/a").is_some());
+    }
+
     #[tokio::test]
     async fn test_has_observe_route() {
         async fn get_handler() -> StatusCode {
@@ -1272,4 +2877,569 @@ mod tests {
         assert!(router.has_observe_route("/with_observe"));
         assert!(!router.has_observe_route("/nonexistent"));
     }
+
+    #[tokio::test]
+    async fn test_manual_observe() {
+        async fn plain_get() -> StatusCode {
+            StatusCode::Valid
+        }
+        async fn observe_get() -> StatusCode {
+            StatusCode::Content
+        }
+
+        let mut router = RouterBuilder::new(TestState { counter: 0 }, ())
+            .observe("/sensors/:id", observe_get, observe_get)
+            .get("/proxy/*target", plain_get)
+            .observe_fallback(ObserveFallback::Parent)
+            .observe("/proxy", observe_get, observe_get)
+            .manual_observe("/proxy/*target")
+            .build();
+        assert!(router.is_manual_observe("/proxy/sensors/temp"));
+        assert!(!router.is_manual_observe("/sensors/temp"));
+
+        // The registering GET isn't redirected to the observable parent
+        let mut raw = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+        raw.set_path("/proxy/sensors/temp");
+        raw.set_method(RequestType::Get);
+        raw.set_observe_flag(ObserveOption::Register);
+        let request: CoapumRequest<SocketAddr> = raw.into();
+        let resp = router.call(request).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Valid);
+    }
+
+    #[tokio::test]
+    async fn test_notify_handler_sees_registering_connection() {
+        use crate::extract::{Notification, Path};
+
+        async fn get_handler() -> StatusCode {
+            StatusCode::Content
+        }
+        async fn notify_handler(
+            Identity(identity): Identity,
+            Path(sensor): Path<String>,
+            Notification(value): Notification,
+        ) -> StatusCode {
+            if identity == "device_1" && sensor == "sensor1" && value == 21 {
+                StatusCode::Content
+            } else {
+                StatusCode::BadRequest
+            }
+        }
+
+        let state = TestState { counter: 0 };
+        let mut router = RouterBuilder::new(state, ())
+            .observe("/sensors/:id", get_handler, notify_handler)
+            .build();
+
+        let value = ObserverValue {
+            path: "/sensors/sensor1".to_string(),
+            value: serde_json::json!(21),
+            encoded: None,
+            changed: vec!["/sensors/sensor1".to_string()],
+        };
+        let mut req = value.to_request("127.0.0.1:5683".parse().unwrap());
+        req.identity = "device_1".to_string();
+        req.token = vec![0xAB];
+
+        let resp = router.call(req).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Content);
+    }
+
+    #[tokio::test]
+    async fn test_route_payload_limit_overrides_default() {
+        use crate::extract::Cbor;
+
+        async fn upload(Cbor(image): Cbor<Vec<u8>>) -> StatusCode {
+            assert!(!image.is_empty());
+            StatusCode::Changed
+        }
+
+        let state = TestState { counter: 0 };
+        let mut router = RouterBuilder::new(state, ())
+            .post("/small", upload)
+            .post_with_limit("/fw", upload, 64 * 1024)
+            .post_with_tags("/admin/fw", upload, ["admin"])
+            .payload_limit("/admin/fw", RequestType::Post, 64 * 1024)
+            .build();
+
+        // Larger than the default 8 KB CBOR limit
+        let mut payload = Vec::new();
+        ciborium::ser::into_writer(&vec![0xAAu8; 10_000], &mut payload).unwrap();
+
+        let request = |path: &str| -> CoapumRequest<SocketAddr> {
+            let mut packet = Packet::new();
+            packet.payload = payload.clone();
+            let mut raw = CoapRequest::from_packet(packet, "127.0.0.1:5683".parse().unwrap());
+            raw.set_path(path);
+            raw.set_method(RequestType::Post);
+            let mut request: CoapumRequest<SocketAddr> = raw.into();
+            request
+                .extensions_mut()
+                .insert(Session::new("ops").with_tags(["admin"]));
+            request
+        };
+
+        let resp = router.call(request("/small")).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::RequestEntityTooLarge);
+
+        let resp = router.call(request("/fw")).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Changed);
+
+        // Tags and a limit combine
+        let resp = router.call(request("/admin/fw")).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Changed);
+    }
+
+    #[tokio::test]
+    async fn test_route_required_tags() {
+        async fn reboot() -> StatusCode {
+            StatusCode::Changed
+        }
+
+        let state = TestState { counter: 0 };
+        let mut router = RouterBuilder::new(state, ())
+            .post_with_tags("/admin/reboot", reboot, ["admin", "fleet"])
+            .build();
+
+        let request = |session: Option<Session>| -> CoapumRequest<SocketAddr> {
+            let mut raw = CoapRequest::new();
+            raw.set_path("/admin/reboot");
+            raw.set_method(RequestType::Post);
+            raw.source = Some("127.0.0.1:5683".parse().unwrap());
+            let mut request: CoapumRequest<SocketAddr> = raw.into();
+            if let Some(session) = session {
+                request.extensions_mut().insert(session);
+            }
+            request
+        };
+
+        let admin = Session::new("ops").with_tags(["fleet", "admin"]);
+        let resp = router.call(request(Some(admin))).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Changed);
+
+        // Every tag is required, and requests without a session have none
+        let partial = Session::new("dev").with_tags(["fleet"]);
+        let resp = router.call(request(Some(partial))).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Forbidden);
+        let resp = router.call(request(None)).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Forbidden);
+    }
+
+    #[tokio::test]
+    async fn test_rejection_handler() {
+        use crate::extract::{Json, Rejection};
+
+        async fn store(Json(_reading): Json<u32>) -> StatusCode {
+            StatusCode::Changed
+        }
+
+        let mut router = RouterBuilder::new(TestState { counter: 0 }, ())
+            .post("/readings", store)
+            .rejection_handler(|rejection: &dyn Rejection| {
+                Some((rejection.status(), Json(rejection.kind())))
+            })
+            .build();
+
+        let mut packet = Packet::new();
+        packet.payload = b"oops".to_vec();
+        let mut raw = CoapRequest::from_packet(packet, "127.0.0.1:5683".parse().unwrap());
+        raw.set_path("/readings");
+        raw.set_method(RequestType::Post);
+
+        let request: CoapumRequest<SocketAddr> = raw.into();
+        let resp = router.call(request).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::BadRequest);
+        assert_eq!(resp.message.payload, br#""json.invalid_data""#);
+    }
+
+    #[tokio::test]
+    async fn test_typed_state_handler() {
+        use crate::extract::{Json, TypedState};
+        use crate::observer::memory::MemObserver;
+
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Config {
+            interval: u32,
+        }
+
+        async fn get_config(config: TypedState<Config>) -> Option<Json<u32>> {
+            config.get().map(|config| Json(config.interval))
+        }
+
+        let mut db = MemObserver::new();
+        db.write("dev", "/config", &serde_json::json!({"interval": 30}))
+            .await
+            .unwrap();
+        let mut router = RouterBuilder::new(TestState { counter: 0 }, db)
+            .get("/config", get_config)
+            .build();
+
+        let mut raw = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+        raw.set_path("/config");
+        raw.set_method(RequestType::Get);
+        let mut request: CoapumRequest<SocketAddr> = raw.into();
+        request.identity = "dev".to_string();
+        let resp = router.call(request.clone()).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Content);
+        assert_eq!(resp.message.payload, b"30");
+
+        request.identity = "other".to_string();
+        let resp = router.call(request).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_observe_from_backend() {
+        use crate::observer::memory::MemObserver;
+
+        async fn not_found() -> StatusCode {
+            StatusCode::NotFound
+        }
+
+        let mut db = MemObserver::new();
+        db.write("dev", "/shadow/led", &serde_json::json!({"on": true}))
+            .await
+            .unwrap();
+        let mut router = RouterBuilder::new(TestState { counter: 0 }, db)
+            .observe("/shadow/:key", not_found, not_found)
+            .observe_from_backend(true)
+            .build();
+
+        let request = |identity: &str, observe: bool| {
+            let mut raw =
+                CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+            raw.set_path("/shadow/led");
+            raw.set_method(RequestType::Get);
+            if observe {
+                raw.set_observe_flag(ObserveOption::Register);
+            }
+            let mut request: CoapumRequest<SocketAddr> = raw.into();
+            request.identity = identity.to_string();
+            request
+        };
+
+        let resp = router.call(request("dev", true)).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Content);
+        assert_eq!(
+            resp.message.get_content_format(),
+            Some(ContentFormat::ApplicationJSON)
+        );
+        assert_eq!(resp.message.payload, br#"{"on":true}"#);
+
+        // Only registrations fall back, and only to stored values
+        let resp = router.call(request("dev", false)).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::NotFound);
+        let resp = router.call(request("other", true)).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::NotFound);
+    }
+
+    #[test]
+    fn test_recognize_option() {
+        let mut packet = Packet::new();
+        packet.add_option(coap_lite::CoapOption::Unknown(65001), vec![1]);
+
+        let router = RouterBuilder::new(TestState { counter: 0 }, ()).build();
+        assert_eq!(router.options().unrecognized_critical(&packet), Some(65001));
+
+        let router = RouterBuilder::new(TestState { counter: 0 }, ())
+            .recognize_option(65001)
+            .build();
+        assert_eq!(router.options().unrecognized_critical(&packet), None);
+    }
+
+    #[tokio::test]
+    async fn test_nested_routes_derive_state() {
+        use crate::extract::{FromRef, State};
+        use crate::router::nest::Routes;
+
+        #[derive(Clone, Debug)]
+        struct FirmwareStore {
+            version: u32,
+        }
+
+        impl AsRef<FirmwareStore> for FirmwareStore {
+            fn as_ref(&self) -> &FirmwareStore {
+                self
+            }
+        }
+
+        #[derive(Clone, Debug)]
+        struct AppState {
+            firmware: FirmwareStore,
+        }
+
+        impl FromRef<AppState> for FirmwareStore {
+            fn from_ref(state: &AppState) -> Self {
+                state.firmware.clone()
+            }
+        }
+
+        async fn latest(State(store): State<FirmwareStore>) -> StatusCode {
+            if store.version == 3 {
+                StatusCode::Content
+            } else {
+                StatusCode::NotFound
+            }
+        }
+
+        let state = AppState {
+            firmware: FirmwareStore { version: 3 },
+        };
+        let mut router = RouterBuilder::new(state, ())
+            .nest("/fw", Routes::<FirmwareStore>::new().get("/latest", latest))
+            .build();
+
+        assert!(
+            router
+                .routes()
+                .iter()
+                .any(|r| r.path == "/fw/latest" && r.method == RequestType::Get)
+        );
+
+        let mut raw = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+        raw.set_path("/fw/latest");
+        raw.set_method(RequestType::Get);
+        let req: CoapumRequest<SocketAddr> = raw.into();
+        let resp = router.call(req).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Content);
+    }
+
+    #[test]
+    fn test_pattern_extends() {
+        assert!(pattern_extends("/device/config/:key", "/device/config"));
+        assert!(pattern_extends("/device/:id/config", "device/abc"));
+        assert!(!pattern_extends("/device/config", "/device/config"));
+        assert!(!pattern_extends("/device/status/:key", "/device/config"));
+    }
+
+    #[tokio::test]
+    async fn test_observe_fallback() {
+        async fn get_handler() -> StatusCode {
+            StatusCode::Content
+        }
+
+        async fn notify_handler() -> StatusCode {
+            StatusCode::Valid
+        }
+
+        let state = TestState { counter: 0 };
+        let mut router = RouterBuilder::new(state, ())
+            .observe("/device/:id", get_handler, notify_handler)
+            .observe("/device/:id/config/:key", get_handler, notify_handler)
+            .build();
+
+        // Exact matching by default
+        assert!(router.has_observe_route("/device/abc"));
+        assert!(!router.has_observe_route("/device/abc/config"));
+        assert!(!router.has_observe_route("/device/abc/status/battery"));
+
+        router.set_observe_fallback(ObserveFallback::Subtree);
+        assert!(router.has_observe_route("/device/abc/config"));
+        assert!(!router.has_observe_route("/device/abc/status/battery"));
+        assert!(
+            router
+                .lookup_observer_handler("/device/abc/config")
+                .is_some()
+        );
+
+        // The registering GET is routed to the fallback route too
+        let mut packet = Packet::new();
+        packet.set_observe_value(0);
+        let mut raw = CoapRequest::from_packet(packet, "127.0.0.1:5683".parse().unwrap());
+        raw.set_path("/device/abc/config");
+        raw.set_method(RequestType::Get);
+        let req: CoapumRequest<SocketAddr> = raw.into();
+        let resp = router.call(req).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Content);
+
+        router.set_observe_fallback(ObserveFallback::Parent);
+        assert!(router.has_observe_route("/device/abc/status/battery"));
+        assert!(!router.has_observe_route("/other"));
+    }
+
+    #[tokio::test]
+    async fn test_alias_routes_to_target() {
+        use crate::extract::Path;
+
+        async fn sensor(Path(name): Path<String>) -> StatusCode {
+            if name == "temp" {
+                StatusCode::Content
+            } else {
+                StatusCode::NotFound
+            }
+        }
+
+        let state = TestState { counter: 0 };
+        let mut router = RouterBuilder::new(state, ())
+            .get("/sensors/:name", sensor)
+            .alias("/t", "/sensors/temp")
+            .build();
+
+        assert_eq!(router.resolve_alias("t/"), "sensors/temp");
+        assert_eq!(router.resolve_alias("/sensors/temp"), "/sensors/temp");
+
+        for path in ["/t", "/sensors/temp"] {
+            let mut raw =
+                CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+            raw.set_path(path);
+            raw.set_method(RequestType::Get);
+            let req: CoapumRequest<SocketAddr> = raw.into();
+            let resp = router.call(req).await.unwrap();
+            assert_eq!(*resp.get_status(), ResponseType::Content, "{}", path);
+        }
+    }
+    #[tokio::test]
+    async fn test_fetch_selects_from_device_document() {
+        use crate::extract::{Cbor, DeviceDocument, Fetch};
+        use crate::observer::memory::MemObserver;
+
+        async fn fetch_fields(
+            Fetch(fields): Fetch<Vec<String>>,
+            document: DeviceDocument,
+        ) -> Cbor<Value> {
+            Cbor(document.select(&fields))
+        }
+
+        let mut observer = MemObserver::new();
+        observer
+            .write(
+                "device_1",
+                "/",
+                &serde_json::json!({"battery": 87, "fw": "1.2"}),
+            )
+            .await
+            .unwrap();
+        let mut router = RouterBuilder::new(TestState { counter: 0 }, observer)
+            .fetch("/device", fetch_fields)
+            .build();
+
+        let mut query = Vec::new();
+        ciborium::into_writer(&["battery", "missing"], &mut query).unwrap();
+        let mut raw = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+        raw.set_path("/device");
+        raw.set_method(RequestType::Fetch);
+        raw.message.payload = query;
+        let mut req: CoapumRequest<SocketAddr> = raw.into();
+        req.identity = "device_1".to_string();
+
+        let resp = router.call(req).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Content);
+        let selected: Value = ciborium::from_reader(&resp.message.payload[..]).unwrap();
+        assert_eq!(selected, serde_json::json!({"battery": 87}));
+    }
+
+    /// A backend whose documents can't be read
+    #[derive(Clone, Debug)]
+    struct Unreadable;
+
+    #[async_trait::async_trait]
+    impl Observer for Unreadable {
+        type Error = &'static str;
+
+        async fn register(
+            &mut self,
+            _device_id: &str,
+            _path: &str,
+            _sender: crate::observer::ObserverSender,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn unregister(&mut self, _device_id: &str, _path: &str) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn unregister_all(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn unregister_device(&mut self, _device_id: &str) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn write(
+            &mut self,
+            _device_id: &str,
+            _path: &str,
+            _payload: &Value,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn read(
+            &mut self,
+            _device_id: &str,
+            _path: &str,
+        ) -> Result<Option<Value>, Self::Error> {
+            Err("backend down")
+        }
+        async fn clear(&mut self, _device_id: &str) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_reads_document_only_when_taken() {
+        use crate::extract::{Cbor, DeviceDocument, Fetch};
+
+        async fn echo(Fetch(fields): Fetch<Vec<String>>) -> Cbor<Vec<String>> {
+            Cbor(fields)
+        }
+
+        async fn select(
+            Fetch(fields): Fetch<Vec<String>>,
+            document: DeviceDocument,
+        ) -> Cbor<Value> {
+            Cbor(document.select(&fields))
+        }
+
+        let mut router = RouterBuilder::new(TestState { counter: 0 }, Unreadable)
+            .fetch("/echo", echo)
+            .fetch("/device", select)
+            .build();
+
+        let request = |path: &str| -> CoapumRequest<SocketAddr> {
+            let mut query = Vec::new();
+            ciborium::into_writer(&["battery"], &mut query).unwrap();
+            let mut raw =
+                CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+            raw.set_path(path);
+            raw.set_method(RequestType::Fetch);
+            raw.message.payload = query;
+            let mut req: CoapumRequest<SocketAddr> = raw.into();
+            req.identity = "device_1".to_string();
+            req
+        };
+
+        let resp = router.call(request("/echo")).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Content);
+
+        let resp = router.call(request("/device")).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::InternalServerError);
+    }
+
+    #[tokio::test]
+    async fn test_merge_patch_route_notifies() {
+        use crate::observer::memory::MemObserver;
+
+        let mut observer = MemObserver::new();
+        let (tx, mut rx) = crate::observer::queue::notification_channel(4, Default::default());
+        observer
+            .register("device_1", "/config", Arc::new(tx))
+            .await
+            .unwrap();
+        let mut router = RouterBuilder::new(TestState { counter: 0 }, observer)
+            .merge_patch("/config")
+            .build();
+
+        let mut raw = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+        raw.set_path("/config");
+        raw.set_method(RequestType::IPatch);
+        raw.message
+            .set_content_format(coap_lite::ContentFormat::ApplicationJSON);
+        raw.message.payload = br#"{"interval": 60, "led": null}"#.to_vec();
+        let mut req: CoapumRequest<SocketAddr> = raw.into();
+        req.identity = "device_1".to_string();
+
+        let resp = router.call(req).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Changed);
+
+        let notification = rx.recv().await.unwrap();
+        assert_eq!(notification.value, serde_json::json!({"interval": 60}));
+    }
 }
@@ -0,0 +1,238 @@
+//! Route groups with their own state type
+//!
+//! [`Routes`] collects handlers written against a subsystem's state, such as
+//! a firmware store or a telemetry database pool. Mounting the group with
+//! [`RouterBuilder::nest`](super::RouterBuilder::nest) derives that state from
+//! the router's state through [`FromRef`](crate::extract::FromRef), so the
+//! application does not need one state struct that every handler extracts from.
+
+use coap_lite::RequestType;
+
+use super::wrapper::RouteHandler;
+use crate::handler::{Handler, HandlerFn, into_erased_handler, into_handler};
+
+/// A group of routes sharing the state type `S`
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::{RouterBuilder, extract::{FromRef, Json, State}, router::nest::Routes};
+///
+/// #[derive(Clone, Debug)]
+/// struct FirmwareStore {
+///     version: String,
+/// }
+///
+/// impl AsRef<FirmwareStore> for FirmwareStore {
+///     fn as_ref(&self) -> &FirmwareStore {
+///         self
+///     }
+/// }
+///
+/// #[derive(Clone, Debug)]
+/// struct AppState {
+///     firmware: FirmwareStore,
+/// }
+///
+/// impl FromRef<AppState> for FirmwareStore {
+///     fn from_ref(state: &AppState) -> Self {
+///         state.firmware.clone()
+///     }
+/// }
+///
+/// async fn latest(State(store): State<FirmwareStore>) -> Json<String> {
+///     Json(store.version)
+/// }
+///
+/// let firmware = Routes::<FirmwareStore>::new().get("/latest", latest);
+///
+/// let state = AppState { firmware: FirmwareStore { version: "1.2.0".into() } };
+/// let router = RouterBuilder::new(state, ())
+///     .nest("/fw", firmware)
+///     .build();
+/// ```
+pub struct Routes<S>
+where
+    S: Send + Sync + 'static,
+{
+    routes: Vec<(String, RouteHandler<S>)>,
+}
+
+impl<S> Routes<S>
+where
+    S: Send + Sync + 'static,
+{
+    /// Create an empty route group
+    pub fn new() -> Self {
+        Self { routes: Vec::new() }
+    }
+
+    fn route<F, T>(mut self, path: &str, method: RequestType, handler: F) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        self.routes.push((
+            path.to_string(),
+            RouteHandler {
+                handler: into_erased_handler(into_handler(handler)),
+                observe_handler: None,
+                method,
+                confirmable_notifications: false,
+                payload_limit: None,
+                required_tags: Vec::new(),
+            },
+        ));
+        self
+    }
+
+    /// Add a GET route
+    pub fn get<F, T>(self, path: &str, handler: F) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        self.route(path, RequestType::Get, handler)
+    }
+
+    /// Add a POST route
+    pub fn post<F, T>(self, path: &str, handler: F) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        self.route(path, RequestType::Post, handler)
+    }
+
+    /// Add a PUT route
+    pub fn put<F, T>(self, path: &str, handler: F) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        self.route(path, RequestType::Put, handler)
+    }
+
+    /// Add a FETCH route, see [`RouterBuilder::fetch`](super::RouterBuilder::fetch)
+    pub fn fetch<F, T>(self, path: &str, handler: F) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        self.route(path, RequestType::Fetch, handler)
+    }
+
+    /// Add a PATCH route
+    pub fn patch<F, T>(self, path: &str, handler: F) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        self.route(path, RequestType::Patch, handler)
+    }
+
+    /// Add an iPATCH route
+    pub fn ipatch<F, T>(self, path: &str, handler: F) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        self.route(path, RequestType::IPatch, handler)
+    }
+
+    /// Add a DELETE route
+    pub fn delete<F, T>(self, path: &str, handler: F) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        self.route(path, RequestType::Delete, handler)
+    }
+
+    /// Add a route that handles any method
+    pub fn any<F, T>(self, path: &str, handler: F) -> Self
+    where
+        HandlerFn<F, S>: Handler<T, S>,
+        F: Send + Sync + Clone,
+        T: Send + Sync + 'static,
+    {
+        self.route(path, RequestType::UnKnown, handler)
+    }
+
+    /// Add an observable GET route with separate handlers for GET and notifications
+    pub fn observe<F1, T1, F2, T2>(
+        mut self,
+        path: &str,
+        get_handler: F1,
+        notify_handler: F2,
+    ) -> Self
+    where
+        HandlerFn<F1, S>: Handler<T1, S>,
+        HandlerFn<F2, S>: Handler<T2, S>,
+        F1: Send + Sync + Clone,
+        F2: Send + Sync + Clone,
+        T1: Send + Sync + 'static,
+        T2: Send + Sync + 'static,
+    {
+        self.routes.push((
+            path.to_string(),
+            RouteHandler {
+                handler: into_erased_handler(into_handler(get_handler)),
+                observe_handler: Some(into_erased_handler(into_handler(notify_handler))),
+                method: RequestType::Get,
+                confirmable_notifications: false,
+                payload_limit: None,
+                required_tags: Vec::new(),
+            },
+        ));
+        self
+    }
+
+    /// Consume the group, returning each route pattern with its handler
+    pub(crate) fn into_routes(self) -> Vec<(String, RouteHandler<S>)> {
+        self.routes
+    }
+}
+
+impl<S> Default for Routes<S>
+where
+    S: Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Join a mount prefix and a route pattern into one path
+pub(crate) fn join_path(prefix: &str, path: &str) -> String {
+    let prefix = prefix.trim_end_matches('/');
+    let path = path.trim_start_matches('/');
+    match (prefix.is_empty(), path.is_empty()) {
+        (true, true) => "/".to_string(),
+        (true, false) => format!("/{}", path),
+        (false, true) => prefix.to_string(),
+        (false, false) => format!("{}/{}", prefix, path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_path() {
+        assert_eq!(join_path("/fw", "/latest"), "/fw/latest");
+        assert_eq!(join_path("/fw/", "latest"), "/fw/latest");
+        assert_eq!(join_path("/fw", "/"), "/fw");
+        assert_eq!(join_path("", "/latest"), "/latest");
+        assert_eq!(join_path("/fw", "/:id"), "/fw/:id");
+    }
+}
@@ -0,0 +1,69 @@
+//! Send priorities of routes
+//!
+//! Every connection queues observer notifications before sending them. While
+//! a constrained uplink keeps up, notifications leave in the order they were
+//! triggered. Once they back up, routes tagged with a [`Priority`] decide
+//! which go first: a door alarm queued behind a burst of telemetry is sent
+//! before it, and when the queue is full, telemetry is dropped to make room
+//! for the alarm. Routes without a tag have [`Priority::Normal`].
+//!
+//! Responses are sent as soon as their request is handled, ahead of any
+//! queued notification.
+
+use route_recognizer::Router;
+
+/// How urgently notifications of a route are sent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Sent once nothing more urgent is queued, and dropped first when the
+    /// queue is full; e.g. periodic telemetry
+    Low,
+    /// The priority of untagged routes
+    #[default]
+    Normal,
+    /// Sent ahead of everything else queued; e.g. alarms
+    High,
+}
+
+/// Route patterns tagged with a [`Priority`]
+///
+/// Set with [`RouterBuilder::priority`](super::RouterBuilder::priority) and
+/// looked up by the connection's notification queue. Patterns use the route
+/// syntax, so `/alarms/:id` covers every alarm.
+#[derive(Debug, Clone, Default)]
+pub struct Priorities {
+    routes: Router<Priority>,
+}
+
+impl Priorities {
+    /// Tag the paths matching `pattern` with `priority`, replacing an
+    /// earlier tag of the same pattern
+    pub fn set(&mut self, pattern: &str, priority: Priority) {
+        self.routes.add(pattern, priority);
+    }
+
+    /// The priority of `path`; [`Priority::Normal`] if no pattern matches
+    pub fn get(&self, path: &str) -> Priority {
+        self.routes
+            .recognize(path)
+            .map_or(Priority::Normal, |matched| **matched.handler())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priority_lookup() {
+        let mut priorities = Priorities::default();
+        priorities.set("/alarms/:id", Priority::High);
+        priorities.set("/telemetry", Priority::Low);
+
+        assert_eq!(priorities.get("/alarms/door"), Priority::High);
+        assert_eq!(priorities.get("alarms/door"), Priority::High);
+        assert_eq!(priorities.get("/telemetry"), Priority::Low);
+        assert_eq!(priorities.get("/config"), Priority::Normal);
+        assert!(Priority::High > Priority::Normal && Priority::Normal > Priority::Low);
+    }
+}
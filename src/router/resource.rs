@@ -0,0 +1,319 @@
+//! Resources as types instead of free functions
+//!
+//! A [`Resource`] keeps a CoAP resource's storage, validation and method
+//! handlers together in one type, with typed request payloads and
+//! representations. Mount it with
+//! [`RouterBuilder::resource`](super::RouterBuilder::resource); the router
+//! calls the resource's `on_*` methods through ordinary handlers, so
+//! resources and function handlers can be mixed freely.
+
+use std::{fmt, future::Future, net::SocketAddr, pin::Pin, sync::Arc};
+
+use async_trait::async_trait;
+use coap_lite::ContentFormat;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use super::CoapumRequest;
+use crate::extract::{
+    Cbor, FromRequest, IntoResponse, Json, Notification, Rejection, ResponseError, StatusCode,
+    payload::{CborRejection, JsonRejection},
+    state::FullRequest,
+};
+
+/// A CoAP resource mounted at a path
+///
+/// Request payloads are decoded into [`Payload`](Self::Payload) from CBOR,
+/// or from JSON when the request says so, and representations are sent as
+/// CBOR. Every method defaults to 4.05 Method Not Allowed, so a resource
+/// implements only the methods it supports.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::{RouterBuilder, StatusCode, observer::memory::MemObserver};
+/// use coapum::router::{CoapumRequest, resource::Resource};
+/// use std::{net::SocketAddr, sync::Mutex};
+///
+/// #[derive(Default)]
+/// struct Setpoint {
+///     celsius: Mutex<f32>,
+/// }
+///
+/// #[async_trait::async_trait]
+/// impl Resource for Setpoint {
+///     type Payload = f32;
+///     type Representation = f32;
+///
+///     async fn on_get(&self, _req: &CoapumRequest<SocketAddr>) -> Result<f32, StatusCode> {
+///         Ok(*self.celsius.lock().unwrap())
+///     }
+///
+///     async fn on_put(
+///         &self,
+///         _req: &CoapumRequest<SocketAddr>,
+///         celsius: f32,
+///     ) -> Result<StatusCode, StatusCode> {
+///         if !(5.0..=30.0).contains(&celsius) {
+///             return Err(StatusCode::BadRequest);
+///         }
+///         *self.celsius.lock().unwrap() = celsius;
+///         Ok(StatusCode::Changed)
+///     }
+/// }
+///
+/// let router = RouterBuilder::new((), MemObserver::new())
+///     .resource("/thermostat/setpoint", Setpoint::default())
+///     .build();
+/// ```
+#[async_trait]
+pub trait Resource: Send + Sync + 'static {
+    /// Payload of PUT and POST requests
+    type Payload: DeserializeOwned + Send + Sync + 'static;
+    /// Representation returned by GET and sent in notifications
+    type Representation: Serialize + Send + 'static;
+
+    /// Handle GET
+    async fn on_get(
+        &self,
+        _req: &CoapumRequest<SocketAddr>,
+    ) -> Result<Self::Representation, StatusCode> {
+        Err(StatusCode::MethodNotAllowed)
+    }
+
+    /// Handle PUT
+    async fn on_put(
+        &self,
+        _req: &CoapumRequest<SocketAddr>,
+        _payload: Self::Payload,
+    ) -> Result<StatusCode, StatusCode> {
+        Err(StatusCode::MethodNotAllowed)
+    }
+
+    /// Handle POST
+    async fn on_post(
+        &self,
+        _req: &CoapumRequest<SocketAddr>,
+        _payload: Self::Payload,
+    ) -> Result<StatusCode, StatusCode> {
+        Err(StatusCode::MethodNotAllowed)
+    }
+
+    /// Handle DELETE
+    async fn on_delete(&self, _req: &CoapumRequest<SocketAddr>) -> Result<StatusCode, StatusCode> {
+        Err(StatusCode::MethodNotAllowed)
+    }
+
+    /// Build a notification after `value` was written to the observed path
+    ///
+    /// Only called for resources that are [`observable`](Self::observable).
+    /// The default answers with [`on_get`](Self::on_get).
+    async fn on_observe(
+        &self,
+        req: &CoapumRequest<SocketAddr>,
+        _value: Value,
+    ) -> Result<Self::Representation, StatusCode> {
+        self.on_get(req).await
+    }
+
+    /// Whether clients can observe the resource. Default: `false`.
+    fn observable(&self) -> bool {
+        false
+    }
+}
+
+/// A resource payload decoded as JSON or CBOR depending on the request
+pub(crate) struct ResourcePayload<T>(T);
+
+pub(crate) enum ResourcePayloadRejection {
+    Json(JsonRejection),
+    Cbor(CborRejection),
+}
+
+impl IntoResponse for ResourcePayloadRejection {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        match self {
+            ResourcePayloadRejection::Json(rejection) => rejection.into_response(),
+            ResourcePayloadRejection::Cbor(rejection) => rejection.into_response(),
+        }
+    }
+}
+
+impl fmt::Display for ResourcePayloadRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourcePayloadRejection::Json(rejection) => fmt::Display::fmt(rejection, f),
+            ResourcePayloadRejection::Cbor(rejection) => fmt::Display::fmt(rejection, f),
+        }
+    }
+}
+
+impl Rejection for ResourcePayloadRejection {
+    fn kind(&self) -> &'static str {
+        match self {
+            ResourcePayloadRejection::Json(rejection) => rejection.kind(),
+            ResourcePayloadRejection::Cbor(rejection) => rejection.kind(),
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            ResourcePayloadRejection::Json(rejection) => rejection.status(),
+            ResourcePayloadRejection::Cbor(rejection) => rejection.status(),
+        }
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ResourcePayload<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = ResourcePayloadRejection;
+
+    async fn from_request(
+        req: &CoapumRequest<SocketAddr>,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        if req.message.get_content_format() == Some(ContentFormat::ApplicationJSON) {
+            let Json(payload) = Json::from_request(req, state)
+                .await
+                .map_err(ResourcePayloadRejection::Json)?;
+            Ok(ResourcePayload(payload))
+        } else {
+            let Cbor(payload) = Cbor::from_request(req, state)
+                .await
+                .map_err(ResourcePayloadRejection::Cbor)?;
+            Ok(ResourcePayload(payload))
+        }
+    }
+}
+
+type BoxedResponse<T> = Pin<Box<dyn Future<Output = Result<T, StatusCode>> + Send + 'static>>;
+
+pub(crate) fn get_handler<R: Resource>(
+    resource: Arc<R>,
+) -> impl Fn(FullRequest) -> BoxedResponse<Cbor<R::Representation>> + Clone + Send + Sync {
+    move |FullRequest(req): FullRequest| {
+        let resource = resource.clone();
+        Box::pin(async move { resource.on_get(&req).await.map(Cbor) }) as BoxedResponse<_>
+    }
+}
+
+pub(crate) fn put_handler<R: Resource>(
+    resource: Arc<R>,
+) -> impl Fn(FullRequest, ResourcePayload<R::Payload>) -> BoxedResponse<StatusCode> + Clone + Send + Sync
+{
+    move |FullRequest(req): FullRequest, ResourcePayload(payload): ResourcePayload<R::Payload>| {
+        let resource = resource.clone();
+        Box::pin(async move { resource.on_put(&req, payload).await }) as BoxedResponse<_>
+    }
+}
+
+pub(crate) fn post_handler<R: Resource>(
+    resource: Arc<R>,
+) -> impl Fn(FullRequest, ResourcePayload<R::Payload>) -> BoxedResponse<StatusCode> + Clone + Send + Sync
+{
+    move |FullRequest(req): FullRequest, ResourcePayload(payload): ResourcePayload<R::Payload>| {
+        let resource = resource.clone();
+        Box::pin(async move { resource.on_post(&req, payload).await }) as BoxedResponse<_>
+    }
+}
+
+pub(crate) fn delete_handler<R: Resource>(
+    resource: Arc<R>,
+) -> impl Fn(FullRequest) -> BoxedResponse<StatusCode> + Clone + Send + Sync {
+    move |FullRequest(req): FullRequest| {
+        let resource = resource.clone();
+        Box::pin(async move { resource.on_delete(&req).await }) as BoxedResponse<_>
+    }
+}
+
+pub(crate) fn observe_handler<R: Resource>(
+    resource: Arc<R>,
+) -> impl Fn(FullRequest, Notification<Value>) -> BoxedResponse<Cbor<R::Representation>>
++ Clone
++ Send
++ Sync {
+    move |FullRequest(req): FullRequest, Notification(value): Notification<Value>| {
+        let resource = resource.clone();
+        Box::pin(async move { resource.on_observe(&req, value).await.map(Cbor) })
+            as BoxedResponse<_>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CoapRequest, Packet, RequestType, RouterBuilder};
+    use coap_lite::ResponseType;
+    use std::sync::Mutex;
+    use tower::Service;
+
+    #[derive(Default)]
+    struct Counter {
+        value: Mutex<u32>,
+    }
+
+    #[async_trait]
+    impl Resource for Counter {
+        type Payload = u32;
+        type Representation = u32;
+
+        async fn on_get(&self, _req: &CoapumRequest<SocketAddr>) -> Result<u32, StatusCode> {
+            Ok(*self.value.lock().unwrap())
+        }
+
+        async fn on_put(
+            &self,
+            _req: &CoapumRequest<SocketAddr>,
+            value: u32,
+        ) -> Result<StatusCode, StatusCode> {
+            *self.value.lock().unwrap() = value;
+            Ok(StatusCode::Changed)
+        }
+    }
+
+    fn request(method: RequestType, payload: Vec<u8>) -> CoapumRequest<SocketAddr> {
+        let mut raw = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+        raw.set_path("/counter");
+        raw.set_method(method);
+        raw.message.payload = payload;
+        raw.into()
+    }
+
+    #[tokio::test]
+    async fn test_resource_methods() {
+        let mut router = RouterBuilder::new((), ())
+            .resource("/counter", Counter::default())
+            .build();
+
+        let mut body = Vec::new();
+        ciborium::into_writer(&7u32, &mut body).unwrap();
+        let resp = router.call(request(RequestType::Put, body)).await.unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Changed);
+
+        let resp = router
+            .call(request(RequestType::Get, Vec::new()))
+            .await
+            .unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::Content);
+        let value: u32 = ciborium::from_reader(&resp.message.payload[..]).unwrap();
+        assert_eq!(value, 7);
+
+        // Methods the resource doesn't implement
+        let resp = router
+            .call(request(RequestType::Delete, Vec::new()))
+            .await
+            .unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::MethodNotAllowed);
+
+        // Payloads that don't decode into `Payload`
+        let resp = router
+            .call(request(RequestType::Post, b"not cbor".to_vec()))
+            .await
+            .unwrap();
+        assert_eq!(*resp.get_status(), ResponseType::BadRequest);
+    }
+}
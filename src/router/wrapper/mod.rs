@@ -51,6 +51,20 @@ impl From<&RequestType> for RequestTypeWrapper {
     }
 }
 
+/// The name of a request method, `ANY` for routes matching every method.
+pub(crate) fn method_name(method: RequestType) -> &'static str {
+    match method {
+        RequestType::Get => "GET",
+        RequestType::Post => "POST",
+        RequestType::Put => "PUT",
+        RequestType::Delete => "DELETE",
+        RequestType::Fetch => "FETCH",
+        RequestType::Patch => "PATCH",
+        RequestType::IPatch => "iPATCH",
+        RequestType::UnKnown => "ANY",
+    }
+}
+
 /// A struct that represents a route handler.
 pub struct RouteHandler<S>
 where
@@ -66,6 +80,13 @@ where
     /// When true, notifications are sent as CON and retransmitted until ACK'd.
     /// Default: false (NonConfirmable).
     pub confirmable_notifications: bool,
+    /// Payload size limit for this route, overriding the server-wide limits
+    /// in [`PayloadLimits`](crate::extract::PayloadLimits).
+    /// Default: `None`.
+    pub payload_limit: Option<usize>,
+    /// Client tags a device needs, all of them, to use the route; others get
+    /// 4.03 Forbidden. Default: empty, open to every device.
+    pub required_tags: Vec<String>,
 }
 
 impl<S> Debug for RouteHandler<S>
@@ -88,6 +109,8 @@ where
             observe_handler: self.observe_handler.as_ref().map(|h| h.clone_erased()),
             method: self.method,
             confirmable_notifications: self.confirmable_notifications,
+            payload_limit: self.payload_limit,
+            required_tags: self.required_tags.clone(),
         }
     }
 }
@@ -0,0 +1,415 @@
+//! Scheduled notifications
+//!
+//! A [`Scheduler`] delivers notifications at or after a given time, e.g. to
+//! tell a device to check in at 02:00. Each schedule has a key: scheduling
+//! again under the same key replaces it, and [`Scheduler::cancel`] removes
+//! it. When a schedule is due its payload is written to the device's
+//! document like [`NotificationTrigger::trigger_notification`](crate::NotificationTrigger::trigger_notification),
+//! so observers of the path are notified.
+//!
+//! Schedules are persisted in the observer backend, in the document of the
+//! reserved device ID [`SCHEDULE_DOCUMENT`], and [`Scheduler::load`] reads
+//! them back on startup. Schedules that fell due while the server was down
+//! are delivered as soon as the scheduler is started.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::sync::Notify;
+
+use crate::observer::Observer;
+use crate::task::BackgroundTask;
+
+/// Device ID whose document holds the persisted schedules
+pub const SCHEDULE_DOCUMENT: &str = "$schedules";
+
+/// A notification to deliver at a given time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    /// Device whose document is written
+    pub device_id: String,
+    /// Path the payload is written to
+    pub path: String,
+    /// Value written, and sent to observers of the path
+    pub payload: Value,
+    /// When the notification is due, in milliseconds since the Unix epoch
+    pub due_ms: u64,
+}
+
+impl Schedule {
+    /// Deliver `payload` at `time`
+    pub fn at(
+        device_id: impl Into<String>,
+        path: impl Into<String>,
+        payload: Value,
+        time: SystemTime,
+    ) -> Self {
+        Self {
+            device_id: device_id.into(),
+            path: path.into(),
+            payload,
+            due_ms: time
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX)),
+        }
+    }
+
+    /// Deliver `payload` once `delay` has passed
+    pub fn after(
+        device_id: impl Into<String>,
+        path: impl Into<String>,
+        payload: Value,
+        delay: Duration,
+    ) -> Self {
+        Self::at(device_id, path, payload, SystemTime::now() + delay)
+    }
+
+    /// When the notification is due
+    pub fn due(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.due_ms)
+    }
+}
+
+/// Errors from [`Scheduler`]
+#[derive(Debug)]
+pub enum SchedulerError<E> {
+    /// Keys may only contain ASCII letters, digits, `_` and `-`
+    InvalidKey(String),
+    /// The observer backend failed
+    Observer(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for SchedulerError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchedulerError::InvalidKey(key) => write!(f, "Invalid schedule key: {:?}", key),
+            SchedulerError::Observer(e) => write!(f, "Observer error: {:?}", e),
+        }
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for SchedulerError<E> {}
+
+/// Delivers notifications at scheduled times
+///
+/// Clones share their schedules, so a scheduler can be handed to handlers
+/// through the application state and to external code alike. Nothing is
+/// delivered until [`start`](Self::start) has spawned the delivery task.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use coapum::observer::memory::MemObserver;
+/// use coapum::scheduler::{Schedule, Scheduler};
+/// use serde_json::json;
+/// use std::time::Duration;
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let observer = MemObserver::new();
+/// let scheduler = Scheduler::load(observer).await?;
+/// let _task = scheduler.start();
+///
+/// let check_in = Schedule::after(
+///     "sensor-42",
+///     "/commands",
+///     json!({"action": "check_in"}),
+///     Duration::from_secs(3600),
+/// );
+/// scheduler.schedule("sensor-42-check-in", check_in).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Scheduler<O> {
+    observer: O,
+    schedules: Arc<Mutex<BTreeMap<String, Schedule>>>,
+    changed: Arc<Notify>,
+}
+
+impl<O: Observer> Scheduler<O> {
+    /// A scheduler without schedules, persisting new ones in `observer`
+    pub fn new(observer: O) -> Self {
+        Self {
+            observer,
+            schedules: Arc::default(),
+            changed: Arc::new(Notify::new()),
+        }
+    }
+
+    /// A scheduler with the schedules persisted in `observer`
+    ///
+    /// Entries that don't parse as a [`Schedule`] are skipped with a warning.
+    pub async fn load(mut observer: O) -> Result<Self, SchedulerError<O::Error>> {
+        let document = observer
+            .read_root(SCHEDULE_DOCUMENT)
+            .await
+            .map_err(SchedulerError::Observer)?;
+
+        let mut schedules = BTreeMap::new();
+        if let Some(Value::Object(entries)) = document {
+            for (key, entry) in entries {
+                if entry.is_null() {
+                    continue;
+                }
+                match serde_json::from_value(entry) {
+                    Ok(schedule) => {
+                        schedules.insert(key, schedule);
+                    }
+                    Err(e) => tracing::warn!(key = %key, "Skipping invalid schedule: {}", e),
+                }
+            }
+        }
+
+        let scheduler = Self::new(observer);
+        *scheduler
+            .schedules
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = schedules;
+        Ok(scheduler)
+    }
+
+    /// Deliver `schedule` when it is due, replacing the schedule under `key`
+    pub async fn schedule(
+        &self,
+        key: &str,
+        schedule: Schedule,
+    ) -> Result<(), SchedulerError<O::Error>> {
+        validate_key(key)?;
+        let entry = serde_json::to_value(&schedule).unwrap_or(Value::Null);
+        // Writes merge, so remove a previous schedule under the key first
+        self.forget(key).await.map_err(SchedulerError::Observer)?;
+        self.observer
+            .clone()
+            .write(SCHEDULE_DOCUMENT, &format!("/{}", key), &entry)
+            .await
+            .map_err(SchedulerError::Observer)?;
+
+        self.schedules
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key.to_string(), schedule);
+        self.changed.notify_one();
+        Ok(())
+    }
+
+    /// Cancel the schedule under `key`. Returns false if there was none.
+    pub async fn cancel(&self, key: &str) -> Result<bool, SchedulerError<O::Error>> {
+        validate_key(key)?;
+        let removed = self
+            .schedules
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(key)
+            .is_some();
+        if removed {
+            self.forget(key).await.map_err(SchedulerError::Observer)?;
+            self.changed.notify_one();
+        }
+        Ok(removed)
+    }
+
+    /// The schedule under `key`, if it is still pending
+    pub fn get(&self, key: &str) -> Option<Schedule> {
+        self.schedules
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(key)
+            .cloned()
+    }
+
+    /// Pending schedules by key
+    pub fn pending(&self) -> BTreeMap<String, Schedule> {
+        self.schedules
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Spawn the task delivering schedules as they fall due
+    ///
+    /// Dropping or shutting down the returned task stops delivery; the
+    /// schedules stay persisted.
+    pub fn start(&self) -> BackgroundTask {
+        let scheduler = self.clone();
+        BackgroundTask::spawn(|mut stop| async move {
+            loop {
+                let next_due = scheduler
+                    .schedules
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .values()
+                    .map(Schedule::due)
+                    .min();
+                let wait =
+                    next_due.map(|due| due.duration_since(SystemTime::now()).unwrap_or_default());
+
+                tokio::select! {
+                    _ = &mut stop => break,
+                    _ = scheduler.changed.notified() => {}
+                    _ = tokio::time::sleep(wait.unwrap_or_default()), if wait.is_some() => {
+                        scheduler.deliver_due().await;
+                    }
+                }
+            }
+        })
+    }
+
+    /// Deliver and remove every schedule that is due
+    ///
+    /// A failed delivery is logged and the schedule dropped, so a broken
+    /// schedule can't block the ones after it.
+    async fn deliver_due(&self) {
+        let now = SystemTime::now();
+        let due: Vec<(String, Schedule)> = {
+            let mut schedules = self
+                .schedules
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let keys: Vec<String> = schedules
+                .iter()
+                .filter(|(_, schedule)| schedule.due() <= now)
+                .map(|(key, _)| key.clone())
+                .collect();
+            keys.into_iter()
+                .filter_map(|key| schedules.remove(&key).map(|schedule| (key, schedule)))
+                .collect()
+        };
+
+        for (key, schedule) in due {
+            tracing::debug!(
+                key = %key,
+                device = %schedule.device_id,
+                path = %schedule.path,
+                "Delivering scheduled notification"
+            );
+            let mut observer = self.observer.clone();
+            if let Err(e) = observer
+                .write(&schedule.device_id, &schedule.path, &schedule.payload)
+                .await
+            {
+                tracing::error!(
+                    key = %key,
+                    "Failed to deliver scheduled notification: {:?}",
+                    e
+                );
+            }
+            if let Err(e) = self.forget(&key).await {
+                tracing::error!(key = %key, "Failed to remove delivered schedule: {:?}", e);
+            }
+        }
+    }
+
+    /// Remove the persisted schedule under `key`
+    async fn forget(&self, key: &str) -> Result<(), O::Error> {
+        let removal = Value::Object(Map::from_iter([(key.to_string(), Value::Null)]));
+        self.observer
+            .clone()
+            .patch(SCHEDULE_DOCUMENT, "/", &removal)
+            .await
+    }
+}
+
+impl<O: fmt::Debug> fmt::Debug for Scheduler<O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("observer", &self.observer)
+            .field(
+                "pending",
+                &self
+                    .schedules
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .len(),
+            )
+            .finish()
+    }
+}
+
+/// Keys become object keys of the schedule document, one path component
+fn validate_key<E>(key: &str) -> Result<(), SchedulerError<E>> {
+    let valid = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(SchedulerError::InvalidKey(key.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observer::{memory::MemObserver, queue::notification_channel};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_scheduled_delivery() {
+        let mut observer = MemObserver::new();
+        let (tx, mut rx) = notification_channel(4, Default::default());
+        observer
+            .register("dev", "/commands", Arc::new(tx))
+            .await
+            .unwrap();
+
+        let scheduler = Scheduler::new(observer);
+        let _task = scheduler.start();
+
+        let later = Schedule::after("dev", "/commands", json!("reboot"), Duration::from_secs(60));
+        scheduler.schedule("reboot", later).await.unwrap();
+        let soon = Schedule::after(
+            "dev",
+            "/commands",
+            json!("check_in"),
+            Duration::from_millis(20),
+        );
+        scheduler.schedule("check-in", soon).await.unwrap();
+        let invalid = Schedule::after("dev", "/commands", json!(1), Duration::ZERO);
+        assert!(matches!(
+            scheduler.schedule("bad/key", invalid).await,
+            Err(SchedulerError::InvalidKey(_))
+        ));
+
+        let notification = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(notification.value, json!("check_in"));
+        assert_eq!(scheduler.get("check-in"), None);
+
+        assert!(scheduler.cancel("reboot").await.unwrap());
+        assert!(!scheduler.cancel("reboot").await.unwrap());
+        assert!(scheduler.pending().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_schedules_survive_restart() {
+        let mut observer = MemObserver::new();
+        let schedule = Schedule::at(
+            "dev",
+            "/commands",
+            json!({"action": "check_in"}),
+            UNIX_EPOCH + Duration::from_secs(2_000_000_000),
+        );
+        let entry = serde_json::to_value(&schedule).unwrap();
+        observer
+            .write(SCHEDULE_DOCUMENT, "/nightly", &entry)
+            .await
+            .unwrap();
+        observer
+            .write(SCHEDULE_DOCUMENT, "/broken", &json!({"due_ms": "soon"}))
+            .await
+            .unwrap();
+
+        let scheduler = Scheduler::load(observer).await.unwrap();
+        assert_eq!(scheduler.get("nightly"), Some(schedule));
+        assert_eq!(scheduler.pending().len(), 1);
+    }
+}
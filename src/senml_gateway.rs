@@ -0,0 +1,508 @@
+//! SenML gateway aggregation built on observer notifications.
+//!
+//! A common gateway pattern is to collect readings from many devices and
+//! forward them upstream as a single SenML document. [`SenMLGateway`]
+//! registers as the observer for a set of devices and paths, turns every
+//! notification into normalized SenML records named `<device>/<name>` (the
+//! device identity acts as the base name), and publishes the merged pack
+//! periodically through a [`NotificationTrigger`] or a channel that can feed
+//! an outbound [`DtlsClient`](crate::client::DtlsClient).
+//!
+//! Notification values that are SenML packs are normalized as-is. Other JSON
+//! values are flattened into one record per leaf, named after the observed
+//! path. Records without a time, or with a relative time (RFC 8428 §4.5.3),
+//! are stamped relative to when the notification arrived.
+//!
+//! The gateway registers its own observer channels next to any observations
+//! the devices' clients hold, and on shutdown removes only those channels.
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use coapum_senml::{NormalizedPack, NormalizedRecord, SenMLPack};
+use serde_json::Value;
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+use crate::{
+    observer::{
+        Observer, ObserverSender, ObserverValue,
+        queue::{OverflowPolicy, notification_channel},
+    },
+    router::NotificationTrigger,
+};
+
+/// Times below this value are relative to "now" (RFC 8428 §4.5.3, 2**28)
+pub(crate) const RELATIVE_TIME_THRESHOLD: f64 = 268_435_456.0;
+
+/// Destination for aggregated packs
+pub enum GatewayOutput<O>
+where
+    O: Observer + Send + Sync + Clone + 'static,
+{
+    /// Write each pack (as SenML JSON) to a device path, notifying its observers
+    Trigger {
+        trigger: NotificationTrigger<O>,
+        device_id: String,
+        path: String,
+    },
+    /// Send each pack to a channel, e.g. to forward it with an outbound client
+    Channel(mpsc::Sender<SenMLPack>),
+}
+
+/// Builder for a SenML aggregation gateway
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use coapum::observer::memory::MemObserver;
+/// # use coapum::senml_gateway::{GatewayOutput, SenMLGateway};
+/// # use std::time::Duration;
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let observer = MemObserver::new();
+/// let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+///
+/// let gateway = SenMLGateway::new(observer)
+///     .devices(["sensor_1", "sensor_2"])
+///     .path("/telemetry")
+///     .interval(Duration::from_secs(30))
+///     .output(GatewayOutput::Channel(tx))
+///     .spawn()
+///     .await
+///     .map_err(|e| format!("{:?}", e))?;
+///
+/// while let Some(pack) = rx.recv().await {
+///     println!("{}", pack.to_json()?);
+/// }
+/// gateway.shutdown().await;
+/// # Ok(())
+/// # }
+/// ```
+pub struct SenMLGateway<O>
+where
+    O: Observer + Send + Sync + Clone + 'static,
+{
+    observer: O,
+    devices: Vec<String>,
+    paths: Vec<String>,
+    interval: Duration,
+    max_records: usize,
+    buffer_size: usize,
+    output: Option<GatewayOutput<O>>,
+}
+
+impl<O> SenMLGateway<O>
+where
+    O: Observer + Send + Sync + Clone + 'static,
+{
+    /// Create a gateway reading notifications from the given observer backend
+    pub fn new(observer: O) -> Self {
+        Self {
+            observer,
+            devices: Vec::new(),
+            paths: Vec::new(),
+            interval: Duration::from_secs(60),
+            max_records: 1000,
+            buffer_size: 100,
+            output: None,
+        }
+    }
+
+    /// Aggregate notifications from a device
+    pub fn device(mut self, device_id: impl Into<String>) -> Self {
+        self.devices.push(device_id.into());
+        self
+    }
+
+    /// Aggregate notifications from several devices
+    pub fn devices<I, D>(mut self, device_ids: I) -> Self
+    where
+        I: IntoIterator<Item = D>,
+        D: Into<String>,
+    {
+        self.devices.extend(device_ids.into_iter().map(Into::into));
+        self
+    }
+
+    /// Observe a path on every device
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+
+    /// How often aggregated packs are published (default: 60 seconds)
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Publish early once this many records are buffered (default: 1000)
+    pub fn max_records(mut self, max_records: usize) -> Self {
+        self.max_records = max_records.max(1);
+        self
+    }
+
+    /// Notification channel capacity per device (default: 100)
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    /// Where aggregated packs are published
+    pub fn output(mut self, output: GatewayOutput<O>) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// Register the observers and start aggregating in a background task
+    ///
+    /// Without an output the packs are only logged.
+    pub async fn spawn(mut self) -> Result<GatewayHandle, O::Error> {
+        let (merged_tx, merged_rx) = mpsc::channel(self.buffer_size);
+        let mut senders = Vec::with_capacity(self.devices.len());
+
+        for device in &self.devices {
+            let (tx, mut rx) = notification_channel(self.buffer_size, OverflowPolicy::default());
+            let tx = Arc::new(tx);
+            for path in &self.paths {
+                self.observer.register(device, path, tx.clone()).await?;
+            }
+            senders.push((device.clone(), tx));
+
+            // Tag notifications with the device they came from
+            let device = device.clone();
+            let merged_tx = merged_tx.clone();
+            tokio::spawn(async move {
+                while let Some(value) = rx.recv().await {
+                    if merged_tx.send((device.clone(), value)).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(merged_tx);
+
+        tracing::info!(
+            devices = self.devices.len(),
+            paths = self.paths.len(),
+            "senml_gateway.started"
+        );
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let aggregator = Aggregator {
+            observer: self.observer,
+            senders,
+            paths: self.paths,
+            interval: self.interval,
+            max_records: self.max_records,
+            output: self.output,
+            records: Vec::new(),
+        };
+        let task = tokio::spawn(aggregator.run(merged_rx, shutdown_rx));
+
+        Ok(GatewayHandle {
+            shutdown: shutdown_tx,
+            task,
+        })
+    }
+}
+
+/// Handle to a running gateway
+pub struct GatewayHandle {
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl GatewayHandle {
+    /// Publish any buffered records, unregister the observers and stop the gateway
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.task.await;
+    }
+}
+
+struct Aggregator<O>
+where
+    O: Observer + Send + Sync + Clone + 'static,
+{
+    observer: O,
+    /// The gateway's own channel for each device
+    senders: Vec<(String, ObserverSender)>,
+    paths: Vec<String>,
+    interval: Duration,
+    max_records: usize,
+    output: Option<GatewayOutput<O>>,
+    records: Vec<NormalizedRecord>,
+}
+
+impl<O> Aggregator<O>
+where
+    O: Observer + Send + Sync + Clone + 'static,
+{
+    async fn run(
+        mut self,
+        mut notifications: mpsc::Receiver<(String, ObserverValue)>,
+        mut shutdown: oneshot::Receiver<()>,
+    ) {
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        ticker.tick().await;
+
+        loop {
+            tokio::select! {
+                notification = notifications.recv() => match notification {
+                    Some((device, value)) => {
+                        self.records.extend(device_records(&device, &value, unix_now()));
+                        if self.records.len() >= self.max_records {
+                            self.publish().await;
+                        }
+                    }
+                    None => break,
+                },
+                _ = ticker.tick() => self.publish().await,
+                _ = &mut shutdown => break,
+            }
+        }
+
+        self.publish().await;
+        for (device, sender) in &self.senders {
+            for path in &self.paths {
+                let _ = self.observer.unregister_sender(device, path, sender).await;
+            }
+        }
+        tracing::info!("senml_gateway.stopped");
+    }
+
+    async fn publish(&mut self) {
+        if self.records.is_empty() {
+            return;
+        }
+
+        let pack = NormalizedPack {
+            records: std::mem::take(&mut self.records),
+            version: None,
+        }
+        .to_pack();
+        tracing::debug!(records = pack.len(), "senml_gateway.publish");
+
+        match &mut self.output {
+            Some(GatewayOutput::Trigger {
+                trigger,
+                device_id,
+                path,
+            }) => {
+                let value = match serde_json::to_value(&pack) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        tracing::error!(error = %e, "senml_gateway.encode_failed");
+                        return;
+                    }
+                };
+                if let Err(e) = trigger.trigger_notification(device_id, path, &value).await {
+                    tracing::error!(error = ?e, "senml_gateway.publish_failed");
+                }
+            }
+            Some(GatewayOutput::Channel(tx)) => {
+                if tx.send(pack).await.is_err() {
+                    tracing::warn!("senml_gateway.output_closed");
+                }
+            }
+            None => tracing::info!(records = pack.len(), "senml_gateway.aggregated"),
+        }
+    }
+}
+
+/// Convert a notification into normalized records tagged with the device identity
+fn device_records(device: &str, notification: &ObserverValue, now: f64) -> Vec<NormalizedRecord> {
+    let mut records = value_records(notification.path.trim_matches('/'), &notification.value);
+    for record in &mut records {
+        record.name = if record.name.is_empty() {
+            device.to_string()
+        } else {
+            format!("{}/{}", device, record.name)
+        };
+        record.time = Some(match record.time {
+            Some(t) if t >= RELATIVE_TIME_THRESHOLD => t,
+            Some(t) => now + t,
+            None => now,
+        });
+    }
+    records
+}
+
+/// Records of a stored value: SenML packs are normalized as-is, other JSON
+/// is flattened into one record per leaf named after `name`
+pub(crate) fn value_records(name: &str, value: &Value) -> Vec<NormalizedRecord> {
+    match serde_json::from_value::<SenMLPack>(value.clone()) {
+        Ok(pack) if !pack.is_empty() => pack.normalize().records,
+        _ => {
+            let mut records = Vec::new();
+            flatten(name, value, &mut records);
+            records
+        }
+    }
+}
+
+/// Flatten a plain JSON value into one record per scalar leaf
+fn flatten(name: &str, value: &Value, records: &mut Vec<NormalizedRecord>) {
+    let mut record = NormalizedRecord {
+        name: name.to_string(),
+        unit: None,
+        value: None,
+        string_value: None,
+        bool_value: None,
+        data_value: None,
+        sum: None,
+        time: None,
+        update_time: None,
+    };
+
+    match value {
+        Value::Number(n) => {
+            record.value = n
+                .as_i64()
+                .map(Into::into)
+                .or_else(|| n.as_u64().map(Into::into))
+                .or_else(|| n.as_f64().map(Into::into))
+        }
+        Value::Bool(b) => record.bool_value = Some(*b),
+        Value::String(s) => record.string_value = Some(s.clone()),
+        Value::Object(map) => {
+            for (key, child) in map {
+                let child_name = if name.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}/{}", name, key)
+                };
+                flatten(&child_name, child, records);
+            }
+            return;
+        }
+        Value::Array(_) | Value::Null => return,
+    }
+
+    records.push(record);
+}
+
+fn unix_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64())
+}
+
+/// Group records by device, for callers splitting an aggregate back up
+pub fn records_by_device(pack: &SenMLPack) -> HashMap<String, Vec<NormalizedRecord>> {
+    let mut groups: HashMap<String, Vec<NormalizedRecord>> = HashMap::new();
+    for record in pack.normalize().records {
+        let device = record
+            .name
+            .split_once('/')
+            .map_or(record.name.as_str(), |(device, _)| device)
+            .to_string();
+        groups.entry(device).or_default().push(record);
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observer::memory::MemObserver;
+    use coapum_senml::{SenMLBuilder, SenMLValue};
+    use serde_json::json;
+
+    fn notification(path: &str, value: Value) -> ObserverValue {
+        ObserverValue {
+            path: path.to_string(),
+            value,
+            encoded: None,
+            changed: vec![path.to_string()],
+        }
+    }
+
+    #[test]
+    fn test_device_records_from_senml() {
+        let pack = SenMLBuilder::new()
+            .base_name("env/")
+            .add_measurement("temp", 21.5, 1_700_000_000.0)
+            .add_value("hum", 40.0)
+            .build();
+        let value = serde_json::to_value(&pack).unwrap();
+
+        let records = device_records("dev1", &notification("/telemetry", value), 1_800_000_000.0);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].name, "dev1/env/temp");
+        assert_eq!(records[0].time, Some(1_700_000_000.0));
+        assert_eq!(records[1].name, "dev1/env/hum");
+        assert_eq!(records[1].time, Some(1_800_000_000.0));
+    }
+
+    #[test]
+    fn test_device_records_from_plain_json() {
+        let value = json!({"temp": 22.0, "door": true, "mode": "eco", "tags": [1, 2]});
+        let records = device_records("dev2", &notification("/state", value), 100.0);
+
+        let mut names: Vec<_> = records.iter().map(|r| r.name.as_str()).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            ["dev2/state/door", "dev2/state/mode", "dev2/state/temp"]
+        );
+        assert!(records.iter().all(|r| r.time == Some(100.0)));
+    }
+
+    #[tokio::test]
+    async fn test_gateway_aggregates_devices() {
+        let mut observer = MemObserver::new();
+        let (tx, mut rx) = mpsc::channel(4);
+
+        let gateway = SenMLGateway::new(observer.clone())
+            .devices(["dev1", "dev2"])
+            .path("/temp")
+            .interval(Duration::from_secs(3600))
+            .output(GatewayOutput::Channel(tx))
+            .spawn()
+            .await
+            .unwrap();
+
+        observer.write("dev1", "/temp", &json!(20.5)).await.unwrap();
+        observer.write("dev2", "/temp", &json!(19.0)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        gateway.shutdown().await;
+
+        let pack = rx.recv().await.unwrap();
+        let groups = records_by_device(&pack);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(pack.latest("dev1/temp"), Some(SenMLValue::Number(20.5)));
+        assert_eq!(pack.latest("dev2/temp"), Some(SenMLValue::Number(19.0)));
+        assert_eq!(observer.observer_count("dev1").await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_gateway_shutdown_keeps_device_observations() {
+        let mut observer = MemObserver::new();
+        let (tx, mut rx) = notification_channel(4, OverflowPolicy::default());
+        let device_sender = Arc::new(tx);
+        observer
+            .register("dev1", "/temp", device_sender.clone())
+            .await
+            .unwrap();
+
+        let gateway = SenMLGateway::new(observer.clone())
+            .devices(["dev1"])
+            .path("/temp")
+            .spawn()
+            .await
+            .unwrap();
+        gateway.shutdown().await;
+
+        assert_eq!(observer.observer_count("dev1").await, 1);
+        observer.write("dev1", "/temp", &json!(20.5)).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap().value, json!(20.5));
+    }
+}
@@ -1,23 +1,28 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     fmt::Debug,
     net::SocketAddr,
+    panic::AssertUnwindSafe,
+    pin::Pin,
     sync::{
         Arc,
         atomic::{AtomicUsize, Ordering},
     },
+    task::Poll,
     time::{Duration, Instant},
 };
 
 use dimpl::{Dtls, Output};
+use futures::{FutureExt, StreamExt};
+use socket2::{Domain, Protocol, Socket, Type};
 use tokio::{
+    io::ReadBuf,
     net::UdpSocket,
-    sync::{
-        Mutex,
-        mpsc::{self, Sender, channel},
-    },
+    sync::mpsc::{self, Sender, channel},
+    task::JoinSet,
 };
 use tower::Service;
+use tracing::Instrument;
 
 use coap_lite::{
     BlockHandler, BlockHandlerConfig, CoapOption, CoapRequest, ContentFormat, MessageClass,
@@ -25,45 +30,265 @@ use coap_lite::{
 };
 
 use crate::{
-    config::Config,
+    allocator::IdAllocator,
+    audit::AuditStart,
+    compression::CompressionConfig,
+    config::{Config, ConfigError},
+    connection::{
+        AcceptQueue, ConnectionInfo, ConnectionRegistry, ConnectionStats, DisconnectReason,
+        Enqueued, HandshakeSlot, NotificationCounters, ObservedPath, TakeoverPolicy,
+    },
     credential::{CredentialStore, memory::MemoryCredentialStore, resolver::CapturingResolver},
-    observer::{Observer, ObserverValue, validate_observer_path},
+    device_client::{DEVICE_REQUEST_QUEUE, DeviceRequest, PendingRequests},
+    extract::{
+        DiagnosticFormat, ErrorResponse, PayloadLimits, RequestId, Session, StatusCode,
+        TraceContext,
+        stream::{BoxNotificationStream, capture_stream},
+    },
+    identity::IdentityMapper,
+    mtu,
+    no_response::NoResponse,
+    observer::{
+        Observer, ObserverSender, ObserverValue, PathPolicy, PathValidationError,
+        metadata::ObservationMetadata, queue::prioritized_channel,
+    },
+    options::{set_size1, set_size2},
     reliability::{DedupResult, ReliabilityState, RetransmitAction, RetransmitParams},
-    router::{ClientCommand, ClientManager, CoapRouter, CoapumRequest},
+    router::{
+        ClientCommand, ClientEntry, ClientManager, ClientMetadata, CoapRouter, CoapumRequest,
+    },
+    snapshot::{ConnectionRecord, SnapshotConfig, StateSnapshot},
+    task::BackgroundTask,
+    tracker::Claim,
 };
 
-/// Connection information for security tracking and rate limiting
-#[derive(Debug, Clone)]
-struct ConnectionInfo {
-    sender: Sender<()>,
-    established_at: Instant,
-    #[allow(dead_code)] // Reserved for future security features
-    source_addr: SocketAddr,
-    reconnect_count: u32,
+/// An observation registered by the connection.
+struct Observation {
+    /// RFC 7252 §5.3.1: token from the original OBSERVE GET, echoed in
+    /// notifications
+    token: Vec<u8>,
+    /// Metadata shared by the registering GET and the notify handler
+    metadata: ObservationMetadata,
 }
 
 /// Per-connection RFC 7641 observe state.
-struct ObserveState {
+pub(crate) struct ObserveState {
     sequence: u32,
-    next_msg_id: u16,
+    /// Message IDs for notifications and NON responses
+    ids: IdAllocator,
     /// Maps message IDs to observer paths for RST-based deregistration.
     notification_msg_ids: HashMap<u16, String>,
-    /// RFC 7252 §5.3.1: Maps observer paths to the token from the original
-    /// OBSERVE GET so notifications echo the correct token.
-    observer_tokens: HashMap<String, Vec<u8>>,
+    /// Observations by observed path.
+    observations: HashMap<String, Observation>,
+    /// Notification streams returned by notify handlers, drained in order.
+    streams: VecDeque<(String, BoxNotificationStream)>,
+    /// Earliest time the next streamed notification may be sent.
+    next_stream_at: tokio::time::Instant,
+    /// Bumped whenever an observation starts or ends.
+    revision: u64,
+    /// Server-wide counters of sent and failed notifications.
+    notifications: Arc<NotificationCounters>,
 }
 
 impl ObserveState {
-    fn new() -> Self {
+    fn new(notifications: Arc<NotificationCounters>) -> Self {
         Self {
             sequence: 0,
-            next_msg_id: 1,
+            ids: IdAllocator::new(),
             notification_msg_ids: HashMap::new(),
-            observer_tokens: HashMap::new(),
+            observations: HashMap::new(),
+            streams: VecDeque::new(),
+            next_stream_at: tokio::time::Instant::now(),
+            revision: 0,
+            notifications,
+        }
+    }
+
+    /// Start, or replace, the observation of a path.
+    fn observe(&mut self, path: String, observation: Observation) {
+        self.observations.insert(path, observation);
+        self.revision += 1;
+    }
+
+    /// Whether `path` is observed with `token`, i.e. a registration with
+    /// them renews the observation instead of starting one.
+    fn is_observing(&self, path: &str, token: &[u8]) -> bool {
+        self.observations
+            .get(path)
+            .is_some_and(|o| o.token == token)
+    }
+
+    /// Drop everything queued for an observation that has ended.
+    fn end_observation(&mut self, path: &str) {
+        if self.observations.remove(path).is_some() {
+            self.revision += 1;
+        }
+        self.streams.retain(|(p, _)| p != path);
+    }
+
+    /// The observations as published to the connection's stats.
+    fn observed(&self) -> Vec<ObservedPath> {
+        self.observations
+            .iter()
+            .map(|(path, o)| ObservedPath {
+                path: path.clone(),
+                token: o.token.clone(),
+                metadata: o.metadata.clone(),
+            })
+            .collect()
+    }
+
+    /// Cancel the observation a notification message ID belongs to.
+    ///
+    /// Returns the observed path along with any other outstanding
+    /// notification message IDs for it, so their retransmissions can be
+    /// stopped as well.
+    fn cancel_by_msg_id(&mut self, msg_id: u16) -> Option<(String, Vec<u16>)> {
+        let path = self.notification_msg_ids.remove(&msg_id)?;
+        let mut stale = Vec::new();
+        self.notification_msg_ids.retain(|&id, p| {
+            let keep = *p != path;
+            if !keep {
+                stale.push(id);
+            }
+            keep
+        });
+        self.end_observation(&path);
+        Some((path, stale))
+    }
+}
+
+/// How an incoming message is handled before it reaches the router.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Incoming {
+    /// A request to route
+    Request,
+    /// RST, cancelling the exchange it refers to
+    Reset,
+    /// ACK for a CON we sent
+    Acknowledgement,
+    /// CON that is not a request (ping or stray response); answered with RST
+    Reject,
+    /// NON that is not a request; silently ignored
+    Ignore,
+}
+
+/// Classify a message by type and code (RFC 7252 §4.2, §4.3).
+///
+/// Unknown request codes are still routed so the router can answer 4.05.
+pub(crate) fn classify_message(packet: &Packet) -> Incoming {
+    match (packet.header.get_type(), packet.header.code) {
+        (MessageType::Reset, _) => Incoming::Reset,
+        (MessageType::Acknowledgement, _) => Incoming::Acknowledgement,
+        (MessageType::Confirmable, MessageClass::Empty | MessageClass::Response(_)) => {
+            Incoming::Reject
+        }
+        (_, MessageClass::Empty | MessageClass::Response(_)) => Incoming::Ignore,
+        _ => Incoming::Request,
+    }
+}
+
+/// Parse the decrypted payload of a DTLS record as a CoAP message.
+pub(crate) fn decode_datagram(data: &[u8]) -> Option<Packet> {
+    match Packet::from_bytes(data) {
+        Ok(packet) => Some(packet),
+        Err(e) => {
+            tracing::error!("Failed to parse packet: {}", e);
+            None
         }
     }
 }
 
+/// Decode a datagram into the request the router would see.
+///
+/// Runs the stateless part of request handling on the decrypted payload of
+/// a DTLS record: CoAP parsing, message classification, and path validation
+/// against the default [`PathPolicy`]. Returns `None` for what the
+/// connection task does not route: malformed messages, ACKs, RSTs, pings,
+/// stray responses and requests with an invalid path.
+///
+/// Block-wise reassembly, deduplication and the critical option check need
+/// connection or router state and are not applied. Exposed so the parsing
+/// path can be fuzzed without a DTLS session.
+///
+/// ```
+/// use coapum::{CoapRequest, RequestType, serve::process_datagram};
+/// use std::net::SocketAddr;
+///
+/// let source: SocketAddr = "127.0.0.1:5684".parse().unwrap();
+/// let mut request: CoapRequest<SocketAddr> = CoapRequest::new();
+/// request.set_method(RequestType::Get);
+/// request.set_path("/sensors/temp");
+///
+/// let decoded = process_datagram(&request.message.to_bytes().unwrap(), source).unwrap();
+/// assert_eq!(decoded.get_path(), "sensors/temp");
+/// assert!(process_datagram(b"\xff\x00", source).is_none());
+/// ```
+pub fn process_datagram(datagram: &[u8], source: SocketAddr) -> Option<CoapumRequest<SocketAddr>> {
+    let packet = decode_datagram(datagram)?;
+    if classify_message(&packet) != Incoming::Request {
+        return None;
+    }
+
+    let request: CoapumRequest<SocketAddr> = CoapRequest::from_packet(packet, source).into();
+    if let Err(e) = validate_path(&PathPolicy::default(), true, &request) {
+        tracing::error!(
+            "Invalid path '{}' from {}: {}",
+            request.get_path(),
+            source,
+            e
+        );
+        return None;
+    }
+    Some(request)
+}
+
+/// Build the RST answering a rejected CON message.
+pub(crate) fn reset_for(msg_id: u16) -> Packet {
+    let mut rst = Packet::new();
+    rst.header.set_type(MessageType::Reset);
+    rst.header.code = MessageClass::Empty;
+    rst.header.message_id = msg_id;
+    rst
+}
+
+/// Build the empty CON the server pings an idle device with (RFC 7252 §4.3).
+pub(crate) fn ping_with(msg_id: u16) -> Packet {
+    let mut ping = Packet::new();
+    ping.header.set_type(MessageType::Confirmable);
+    ping.header.code = MessageClass::Empty;
+    ping.header.message_id = msg_id;
+    ping
+}
+
+/// Build the empty ACK for a CON request whose response is not sent.
+pub(crate) fn empty_ack_for(msg_id: u16) -> Packet {
+    let mut ack = Packet::new();
+    ack.header.set_type(MessageType::Acknowledgement);
+    ack.header.code = MessageClass::Empty;
+    ack.header.message_id = msg_id;
+    ack
+}
+
+/// RFC 7252 §5.2: Mirror the request's confirmability in its response.
+///
+/// CON requests get a piggybacked ACK carrying the request's message ID;
+/// NON requests get a NON response with a fresh message ID.
+pub(crate) fn mirror_message_type(
+    response: &mut Packet,
+    request_type: MessageType,
+    request_msg_id: u16,
+    ids: &mut IdAllocator,
+) {
+    if request_type == MessageType::Confirmable {
+        response.header.set_type(MessageType::Acknowledgement);
+        response.header.message_id = request_msg_id;
+    } else {
+        response.header.set_type(MessageType::NonConfirmable);
+        response.header.message_id = ids.next_message_id();
+    }
+}
+
 /// Extract and validate PSK identity from raw bytes.
 ///
 /// Validates length, UTF-8 encoding, and sanitizes to safe characters only.
@@ -105,20 +330,76 @@ pub(crate) fn extract_identity(identity_hint: &[u8]) -> Option<String> {
     }
 }
 
+/// Client tags of a PSK identity in the credential store, for routes
+/// restricted to tags.
+async fn client_tags<C: CredentialStore>(store: &C, identity: &str) -> Vec<String> {
+    match store.get_client(identity).await {
+        Ok(info) => info.map(|info| info.metadata.tags).unwrap_or_default(),
+        Err(e) => {
+            // Without its tags the device is denied tagged routes only
+            tracing::error!(identity = %identity, error = ?e, "connection.tags_failed");
+            Vec::new()
+        }
+    }
+}
+
+/// Map a validated PSK identity to the connection's session.
+///
+/// Without a mapper the identity is the device ID. A mapped device ID and
+/// tenant must pass the same checks as the identity.
+async fn map_identity(identity: &str, mapper: Option<&dyn IdentityMapper>) -> Option<Session> {
+    let Some(mapper) = mapper else {
+        return Some(Session::new(identity));
+    };
+    let Some(device_id) = mapper.map_identity(identity).await else {
+        tracing::warn!(identity = %identity, "connection.rejected.unmapped_identity");
+        return None;
+    };
+    let Some(device_id) = extract_identity(device_id.as_bytes()) else {
+        tracing::warn!(identity = %identity, "connection.rejected.invalid_device_id");
+        return None;
+    };
+    let session = match mapper.tenant(identity).await {
+        None => Session::new(&device_id),
+        Some(tenant) => match extract_identity(tenant.as_bytes()) {
+            Some(tenant) => Session::with_tenant(&tenant, &device_id),
+            None => {
+                tracing::warn!(identity = %identity, "connection.rejected.invalid_tenant");
+                return None;
+            }
+        },
+    };
+    Some(session.with_psk_identity(identity))
+}
+
 /// Validate connection and implement rate limiting for reconnections.
 ///
 /// Returns `true` if the connection is allowed, `false` if rate-limited or blocked.
+#[allow(clippy::too_many_arguments)]
 async fn manage_connection(
     identity: &str,
     socket_addr: SocketAddr,
-    tx: Sender<()>,
-    connections: &Mutex<HashMap<String, ConnectionInfo>>,
+    tx: Sender<DisconnectReason>,
+    requests: Sender<DeviceRequest>,
+    stats: Arc<ConnectionStats>,
+    connections: &ConnectionRegistry,
+    takeover_policy: TakeoverPolicy,
     min_reconnect_interval: Duration,
     max_reconnect_attempts: usize,
 ) -> bool {
-    let mut guard = connections.lock().await;
+    let mut guard = connections.inner.lock().await;
 
     if let Some(old_conn) = guard.get(identity) {
+        if takeover_policy == TakeoverPolicy::RejectNew {
+            tracing::warn!(
+                identity = %identity,
+                addr = %socket_addr,
+                active_addr = %old_conn.source_addr,
+                "connection.rejected.identity_in_use"
+            );
+            return false;
+        }
+
         if old_conn.established_at.elapsed() < min_reconnect_interval {
             tracing::warn!(
                 identity = %identity,
@@ -139,20 +420,78 @@ async fn manage_connection(
             return false;
         }
 
-        let _ = old_conn.sender.send(()).await;
+        if takeover_policy == TakeoverPolicy::EvictOld {
+            tracing::warn!(
+                identity = %identity,
+                addr = %socket_addr,
+                evicted_addr = %old_conn.source_addr,
+                "connection.takeover"
+            );
+            // A full channel means a disconnect is already pending
+            let _ = old_conn.sender.try_send(DisconnectReason::TakenOver);
+        }
+
+        // The new connection continues the Observe sequence of the old one
+        connections.keep_sequence(identity, &old_conn.stats);
     }
 
+    let tracker = connections.tracker.clone();
+    let claim = Claim::new(tracker.node_id(), socket_addr);
     let conn_info = ConnectionInfo {
         sender: tx,
-        established_at: Instant::now(),
+        requests,
+        established_at: tokio::time::Instant::now(),
         source_addr: socket_addr,
         reconnect_count: guard
             .get(identity)
             .map(|c| c.reconnect_count + 1)
             .unwrap_or(0),
+        stats: stats.clone(),
+        claim: claim.clone(),
     };
 
     guard.insert(identity.to_string(), conn_info);
+    drop(guard);
+
+    // Claims held by this node were settled above; the tracker decides
+    // against connections on other nodes
+    let replace = takeover_policy != TakeoverPolicy::RejectNew;
+    match tracker.claim(identity, &claim, replace).await {
+        Ok(Some(owner)) if owner.node_id != claim.node_id => {
+            if !replace {
+                tracing::warn!(
+                    identity = %identity,
+                    addr = %socket_addr,
+                    node = %owner.node_id,
+                    "connection.rejected.identity_in_use"
+                );
+                connections.remove(identity, &stats).await;
+                return false;
+            }
+            if takeover_policy == TakeoverPolicy::EvictOld {
+                tracing::warn!(
+                    identity = %identity,
+                    addr = %socket_addr,
+                    node = %owner.node_id,
+                    evicted_addr = %owner.remote_addr,
+                    "connection.takeover"
+                );
+                if let Err(e) = tracker.request_takeover(identity, &owner).await {
+                    tracing::error!(identity = %identity, error = %e, "tracker.takeover_failed");
+                }
+            }
+        }
+        // A leftover claim of this node without a local connection is stale
+        Ok(Some(_)) if !replace => {
+            if let Err(e) = tracker.claim(identity, &claim, true).await {
+                tracing::error!(identity = %identity, error = %e, "tracker.claim_failed");
+            }
+        }
+        Ok(_) => {}
+        // Fail open: an unavailable tracker must not lock every device out
+        Err(e) => tracing::error!(identity = %identity, error = %e, "tracker.claim_failed"),
+    }
+
     tracing::info!(
         identity = %identity,
         addr = %socket_addr,
@@ -181,44 +520,155 @@ async fn drain_packets(
     }
 }
 
-/// Send a CoAP response over a DTLS connection.
-async fn send_response(
-    dtls: &mut Dtls,
-    out_buf: &mut [u8],
-    socket: &UdpSocket,
+/// The channel a connection sends its CoAP messages to the peer over
+///
+/// Request handling, observe, block-wise transfers and retransmissions only
+/// need to send encoded messages, so they run over DTLS sessions and
+/// [transports](crate::transport::Transport) alike.
+pub(crate) trait Link: Send {
+    /// The address requests of the peer are routed with
+    fn remote(&self) -> SocketAddr;
+
+    /// Send one encoded CoAP message. Returns false if it could not be sent.
+    fn send(&mut self, message: &[u8]) -> impl Future<Output = bool> + Send;
+}
+
+/// A DTLS session, borrowed from its connection task for sending
+pub(crate) struct DtlsLink<'a> {
+    dtls: &'a mut Dtls,
+    out_buf: &'a mut [u8],
+    socket: &'a UdpSocket,
     remote: SocketAddr,
-    resp: &crate::CoapResponse,
-) {
+}
+
+impl<'a> DtlsLink<'a> {
+    fn new(
+        dtls: &'a mut Dtls,
+        out_buf: &'a mut [u8],
+        socket: &'a UdpSocket,
+        remote: SocketAddr,
+    ) -> Self {
+        Self {
+            dtls,
+            out_buf,
+            socket,
+            remote,
+        }
+    }
+}
+
+impl Link for DtlsLink<'_> {
+    fn remote(&self) -> SocketAddr {
+        self.remote
+    }
+
+    async fn send(&mut self, message: &[u8]) -> bool {
+        if let Err(e) = self.dtls.send_application_data(message) {
+            tracing::error!(error = %e, "dtls.send_failed");
+            return false;
+        }
+        drain_packets(self.dtls, self.out_buf, self.socket, self.remote).await;
+        true
+    }
+}
+
+impl<L: Link> Link for &mut L {
+    fn remote(&self) -> SocketAddr {
+        (**self).remote()
+    }
+
+    fn send(&mut self, message: &[u8]) -> impl Future<Output = bool> + Send {
+        (**self).send(message)
+    }
+}
+
+/// What an authenticated record from a peer carried
+pub(crate) enum Inbound {
+    /// The peer authenticated with this identity
+    Authenticated(String),
+    /// The peer moved to this address
+    Migrated(SocketAddr),
+    /// A CoAP message
+    Message(Packet),
+}
+
+/// A record from a peer, once opened
+pub(crate) enum Opened {
+    /// Not from the peer, so it is dropped
+    Ignored,
+    /// An authenticated record with what it carried, if anything
+    Record(Vec<Inbound>),
+    /// The connection is broken
+    Closed,
+}
+
+/// The far end of a connection: a DTLS session or a
+/// [transport](crate::transport::Transport) peer
+///
+/// [`run_connection`] drives both through this, so everything above the
+/// secure channel behaves the same for either.
+pub(crate) trait Peer: Send {
+    /// A record as received, before it is authenticated and decoded
+    type Record: Send;
+
+    /// Wait for the next record. `None` ends the connection.
+    ///
+    /// Dropped when other events come first, so it must not lose a record
+    /// when dropped.
+    fn recv(&mut self) -> impl Future<Output = Option<Self::Record>> + Send;
+
+    /// Authenticate and decode a record
+    fn open(&mut self, record: Self::Record) -> impl Future<Output = Opened> + Send;
+
+    /// The link to send messages to the peer over
+    fn link(&mut self) -> impl Link + '_;
+
+    /// Client tags of the authenticated `identity`, for routes restricted
+    /// to tags
+    fn client_tags(&self, identity: &str) -> impl Future<Output = Vec<String>> + Send;
+
+    /// Called after every event of the connection. Returns false if the
+    /// connection is broken.
+    fn flush(&mut self) -> impl Future<Output = bool> + Send {
+        std::future::ready(true)
+    }
+}
+
+/// Send a CoAP response over a connection, returning whether it was sent.
+async fn send_response(link: &mut impl Link, resp: &crate::CoapResponse) -> bool {
     match resp.message.to_bytes() {
-        Ok(bytes) => {
-            if let Err(e) = dtls.send_application_data(&bytes) {
-                tracing::error!(error = %e, "dtls.send_failed");
-                return;
-            }
-            drain_packets(dtls, out_buf, socket, remote).await;
+        Ok(bytes) => link.send(&bytes).await,
+        Err(e) => {
+            tracing::error!("Failed to serialize response: {}", e);
+            false
         }
-        Err(e) => tracing::error!("Failed to serialize response: {}", e),
     }
 }
 
-/// RFC 7959 §2.9.1: Add Size1 option to indicate max acceptable payload size.
-fn add_size1_option(message: &mut Packet, max_message_size: usize) {
-    let bytes = (max_message_size as u32).to_be_bytes();
-    let start = bytes.iter().position(|&b| b != 0).unwrap_or(3);
-    message.add_option(CoapOption::Size1, bytes[start..].to_vec());
+/// RFC 7959 §4: Announce the total size of a response that was split into
+/// blocks, or of any response to a request asking for it with Size2
+fn announce_size2(block_req: &mut CoapRequest<SocketAddr>, size: usize) {
+    let requested = block_req.message.get_option(CoapOption::Size2).is_some();
+    if let Some(ref mut resp) = block_req.response
+        && (requested || resp.message.get_option(CoapOption::Block2).is_some())
+    {
+        set_size2(&mut resp.message, size);
+    }
 }
 
 /// Handle an observer notification: route, set RFC 7641 headers, and send.
+///
+/// If the notify handler returned a [`NotificationStream`](crate::extract::NotificationStream),
+/// the stream is queued on the connection instead and drained by the connection loop.
 #[allow(clippy::too_many_arguments)]
 async fn handle_notification<O, S>(
     value: ObserverValue,
     router: &mut CoapRouter<O, S>,
-    dtls: &mut Dtls,
-    out_buf: &mut [u8],
-    socket: &UdpSocket,
-    remote: SocketAddr,
+    link: &mut impl Link,
+    session: Option<&Session>,
     obs: &mut ObserveState,
     block_handler: &mut BlockHandler<SocketAddr>,
+    mtu: usize,
     reliability: &mut ReliabilityState,
 ) where
     S: Debug + Clone + Send + Sync + 'static,
@@ -228,142 +678,359 @@ async fn handle_notification<O, S>(
 
     let notification_path = value.path.clone();
     let notification_value = value.value.clone();
-    let req = value.to_request(remote);
+    let encoded = value.encoded.clone();
+    let mut req = value.to_request(link.remote());
+    if let Some(observation) = obs.observations.get(&notification_path) {
+        req.token = observation.token.clone();
+        req.extensions_mut().insert(observation.metadata.clone());
+    }
+    if let Some(session) = session {
+        req.identity = session.identity().to_string();
+        req.extensions_mut().insert(session.clone());
+    }
 
-    match router.call(req).await {
-        Ok(mut resp) => {
+    let started = Instant::now();
+    match capture_stream(router.call(req)).await {
+        (Ok(_), Some(stream)) => {
+            tracing::debug!(path = %notification_path, "notification.stream_queued");
+            // A newer stream for the same path supersedes one still in progress
+            obs.streams.retain(|(path, _)| *path != notification_path);
+            obs.streams.push_back((notification_path, stream));
+        }
+        (Ok(mut resp), None) => {
             if *resp.get_status() == ResponseType::BadRequest {
                 tracing::error!("Error: {:?}", resp.message);
+                obs.notifications.record_failed();
                 return;
             }
+            let routed = Instant::now();
+
+            resp.message.payload = if let Some(encoded) = encoded {
+                // Pre-encoded payloads keep their own content format
+                resp.message.set_content_format(encoded.content_format);
+                encoded.bytes
+            } else if resp.message.get_content_format() == Some(ContentFormat::ApplicationCBOR) {
+                let mut buf = Vec::new();
+                ciborium::into_writer(&notification_value, &mut buf).ok();
+                buf
+            } else {
+                serde_json::to_vec(&notification_value).unwrap_or_default()
+            };
+            tracing::debug!(
+                route_us = (routed - started).as_micros() as u64,
+                encode_us = routed.elapsed().as_micros() as u64,
+                bytes = resp.message.payload.len(),
+                "notification.encoded"
+            );
 
-            resp.message.payload =
-                if resp.message.get_content_format() == Some(ContentFormat::ApplicationCBOR) {
-                    let mut buf = Vec::new();
-                    ciborium::into_writer(&notification_value, &mut buf).ok();
-                    buf
-                } else {
-                    serde_json::to_vec(&notification_value).unwrap_or_default()
-                };
+            send_notification(
+                resp,
+                notification_path,
+                router,
+                link,
+                obs,
+                block_handler,
+                mtu,
+                reliability,
+            )
+            .await;
+        }
+        (Err(e), _) => {
+            tracing::error!("Error: {}", e);
+            obs.notifications.record_failed();
+        }
+    }
+}
 
-            // RFC 7252 §5.3.1: Echo the token from the original OBSERVE GET
-            if let Some(token) = obs.observer_tokens.get(&notification_path) {
-                resp.message.set_token(token.clone());
+/// Pull the next item from the first queued notification stream.
+///
+/// Finished streams are dropped. Returns `None` once no stream has items left.
+async fn next_stream_item(
+    streams: &mut VecDeque<(String, BoxNotificationStream)>,
+) -> Option<(String, crate::CoapResponse)> {
+    while let Some((path, stream)) = streams.front_mut() {
+        match stream.next().await {
+            Some(resp) => return Some((path.clone(), resp)),
+            None => {
+                streams.pop_front();
             }
+        }
+    }
+    None
+}
+
+/// Set RFC 7641 headers on a notification response and send it.
+#[allow(clippy::too_many_arguments)]
+async fn send_notification<O, S>(
+    mut resp: crate::CoapResponse,
+    notification_path: String,
+    router: &CoapRouter<O, S>,
+    link: &mut impl Link,
+    obs: &mut ObserveState,
+    block_handler: &mut BlockHandler<SocketAddr>,
+    mtu: usize,
+    reliability: &mut ReliabilityState,
+) where
+    S: Debug + Clone + Send + Sync + 'static,
+    O: Observer + Send + Sync + 'static,
+{
+    // RFC 7252 §5.3.1: Echo the token from the original OBSERVE GET
+    if let Some(observation) = obs.observations.get(&notification_path) {
+        resp.message.set_token(observation.token.clone());
+    }
 
-            // RFC 7641 §3.3: Set observe sequence number (24-bit per §3.4)
-            obs.sequence = obs.sequence.wrapping_add(1) & 0x00FF_FFFF;
-            resp.message.set_observe_value(obs.sequence);
+    // RFC 7641 §3.3: Set observe sequence number (24-bit per §3.4)
+    obs.sequence = obs.sequence.wrapping_add(1) & 0x00FF_FFFF;
+    resp.message.set_observe_value(obs.sequence);
 
-            // Assign unique message ID for RST tracking
-            let msg_id = obs.next_msg_id;
-            obs.next_msg_id = obs.next_msg_id.wrapping_add(1);
-            resp.message.header.message_id = msg_id;
+    // Assign unique message ID for RST tracking
+    let msg_id = obs.ids.next_message_id();
+    resp.message.header.message_id = msg_id;
 
-            // RFC 7252 §4.2 / RFC 7641 §4.5: Use CON or NON based on route config
-            let confirmable = router.is_confirmable_notify(&notification_path);
-            if confirmable {
-                resp.message.header.set_type(MessageType::Confirmable);
-            } else {
-                resp.message.header.set_type(MessageType::NonConfirmable);
-            }
+    // RFC 7252 §4.2 / RFC 7641 §4.5: Use CON or NON based on route config
+    let confirmable = router.is_confirmable_notify(&notification_path);
+    if confirmable {
+        resp.message.header.set_type(MessageType::Confirmable);
+    } else {
+        resp.message.header.set_type(MessageType::NonConfirmable);
+    }
 
-            obs.notification_msg_ids.insert(msg_id, notification_path);
+    obs.notification_msg_ids.insert(msg_id, notification_path);
 
-            // Bound tracking map to prevent unbounded growth
-            if obs.notification_msg_ids.len() > 256 {
-                let cutoff = msg_id.wrapping_sub(128);
-                obs.notification_msg_ids
-                    .retain(|&id, _| id.wrapping_sub(cutoff) < 256);
-            }
+    // Bound tracking map to prevent unbounded growth
+    if obs.notification_msg_ids.len() > 256 {
+        let cutoff = msg_id.wrapping_sub(128);
+        obs.notification_msg_ids
+            .retain(|&id, _| id.wrapping_sub(cutoff) < 256);
+    }
 
-            tracing::trace!(
-                "Sending notification (seq={}, con={}) to: {}",
-                obs.sequence,
-                confirmable,
-                remote
-            );
+    tracing::trace!(
+        "Sending notification (seq={}, con={}) to: {}",
+        obs.sequence,
+        confirmable,
+        link.remote()
+    );
 
-            // RFC 7959: Fragment large notification payloads using Block2
-            let mut block_req = CoapRequest::from_packet(resp.message.clone(), remote);
-            block_req.response = Some(resp);
-            if let Err(e) = block_handler.intercept_response(&mut block_req) {
-                tracing::error!("Block notification error: {}", e.message);
-            }
-            if let Some(ref resp) = block_req.response {
-                send_response(dtls, out_buf, socket, remote, resp).await;
+    // RFC 7959: Fragment large notification payloads using Block2
+    let size = resp.message.payload.len();
+    let mut block_req = CoapRequest::from_packet(resp.message.clone(), link.remote());
+    if let Err(e) = mtu::fit_block2(&mut block_req.message, &resp.message, mtu) {
+        tracing::error!(
+            msg_id,
+            size = e.size,
+            limit = e.limit,
+            "notification.exceeds_mtu"
+        );
+        obs.notifications.record_failed();
+        return;
+    }
+    block_req.response = Some(resp);
+    if let Err(e) = block_handler.intercept_response(&mut block_req) {
+        tracing::error!("Block notification error: {}", e.message);
+    }
+    announce_size2(&mut block_req, size);
+    let Some(ref resp) = block_req.response else {
+        obs.notifications.record_failed();
+        return;
+    };
 
-                // Track for retransmission if CON
-                if confirmable && let Ok(bytes) = resp.message.to_bytes() {
-                    reliability.track_outgoing_con(msg_id, bytes);
-                }
+    let started = Instant::now();
+    if !send_response(link, resp).await {
+        tracing::warn!(msg_id, "notification.send_failed");
+        obs.notifications.record_failed();
+        return;
+    }
+    obs.notifications.record_sent();
+    tracing::debug!(
+        msg_id,
+        confirmable,
+        send_us = started.elapsed().as_micros() as u64,
+        "notification.sent"
+    );
+
+    // Track for retransmission if CON
+    if confirmable && let Ok(bytes) = resp.message.to_bytes() {
+        reliability.track_outgoing_con(msg_id, bytes);
+    }
+}
+
+/// Send a request from a [`DeviceClient`](crate::device_client::DeviceClient)
+/// to the device and wait for its response by token.
+#[allow(clippy::too_many_arguments)]
+async fn send_device_request(
+    request: DeviceRequest,
+    link: &mut impl Link,
+    ids: &mut IdAllocator,
+    pending: &mut PendingRequests,
+    reliability: &mut ReliabilityState,
+) {
+    let DeviceRequest { mut packet, reply } = request;
+    let token = ids.next_token();
+    let msg_id = ids.next_message_id();
+    let confirmable = packet.header.get_type() != MessageType::NonConfirmable;
+    if confirmable {
+        packet.header.set_type(MessageType::Confirmable);
+    }
+    packet.header.message_id = msg_id;
+    packet.set_token(token.clone());
+
+    let bytes = match packet.to_bytes() {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!(error = %e, "device_request.encode_failed");
+            return;
+        }
+    };
+    if !link.send(&bytes).await {
+        return;
+    }
+
+    tracing::debug!(msg_id, "device_request.sent");
+    if confirmable {
+        reliability.track_outgoing_con(msg_id, bytes);
+    }
+    pending.insert(token, msg_id, reply);
+}
+
+/// Ping the device and track the ping for retransmission.
+///
+/// Returns the message ID of the ping, unless it could not be sent.
+async fn send_ping(
+    link: &mut impl Link,
+    ids: &mut IdAllocator,
+    reliability: &mut ReliabilityState,
+) -> Option<u16> {
+    let msg_id = ids.next_message_id();
+    let bytes = match ping_with(msg_id).to_bytes() {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!(error = %e, "keepalive.encode_failed");
+            return None;
+        }
+    };
+    if !link.send(&bytes).await {
+        return None;
+    }
+
+    tracing::debug!(addr = %link.remote(), msg_id, "keepalive.ping_sent");
+    reliability.track_outgoing_con(msg_id, bytes);
+    Some(msg_id)
+}
+
+/// Hand a response from the device to the request it answers.
+///
+/// Returns false if the packet is no response to a pending request, so it
+/// is handled as usual.
+async fn handle_device_response(
+    packet: &Packet,
+    pending: &mut PendingRequests,
+    link: &mut impl Link,
+    reliability: &mut ReliabilityState,
+) -> bool {
+    let msg_id = packet.header.message_id;
+    let msg_type = packet.header.get_type();
+
+    // RFC 7252 §4.5: a retransmitted separate response gets the same ACK
+    if msg_type == MessageType::Confirmable
+        && matches!(packet.header.code, MessageClass::Response(_))
+        && let DedupResult::Duplicate(ack) = reliability.check_dedup(msg_id)
+    {
+        link.send(&ack).await;
+        return true;
+    }
+
+    if !pending.complete(packet) {
+        return false;
+    }
+    tracing::debug!(msg_id, "device_request.completed");
+
+    match msg_type {
+        // Piggybacked response: the ACK for our request
+        MessageType::Acknowledgement => {
+            reliability.handle_ack(msg_id);
+        }
+        // RFC 7252 §5.2.2: a separate CON response is acknowledged
+        MessageType::Confirmable => {
+            if let Ok(ack) = empty_ack_for(msg_id).to_bytes() {
+                link.send(&ack).await;
+                reliability.record_response(msg_id, ack);
             }
         }
-        Err(e) => tracing::error!("Error: {}", e),
+        _ => {}
     }
+    true
 }
 
 /// Handle an incoming CoAP request: block-wise transfer, observe management, routing, and response.
+///
+/// Returns the status of the response to a request, unless none was due.
 #[allow(clippy::too_many_arguments)]
 async fn handle_request<O, S>(
     packet: Packet,
-    socket_addr: SocketAddr,
-    identity: &str,
+    session: &Session,
     router: &mut CoapRouter<O, S>,
-    dtls: &mut Dtls,
-    out_buf: &mut [u8],
-    socket: &UdpSocket,
-    obs_tx: &Arc<Sender<ObserverValue>>,
+    link: &mut impl Link,
+    obs_tx: &ObserverSender,
     obs: &mut ObserveState,
     block_handler: &mut BlockHandler<SocketAddr>,
     max_message_size: usize,
+    mtu: usize,
     max_observers_per_device: usize,
+    path_policy: &PathPolicy,
+    validate_request_paths: bool,
+    payload_limits: PayloadLimits,
+    diagnostic_payloads: Option<DiagnosticFormat>,
+    compression: Option<&CompressionConfig>,
     reliability: &mut ReliabilityState,
-) where
+) -> Option<ResponseType>
+where
     S: Debug + Clone + Send + Sync + 'static,
     O: Observer + Send + Sync + 'static,
 {
+    let identity = session.identity();
+    let socket_addr = link.remote();
     let msg_type = packet.header.get_type();
     let msg_id = packet.header.message_id;
 
-    // RFC 7641 §3.2: RST deregisters observer + stops CON retransmission
-    if msg_type == MessageType::Reset {
-        if let Some(path) = obs.notification_msg_ids.remove(&msg_id) {
-            tracing::info!("RST deregistration for '{}' path '{}'", identity, path);
-            obs.observer_tokens.remove(&path);
-            let _ = router.unregister_observer(identity, &path).await;
+    match classify_message(&packet) {
+        Incoming::Request => {}
+        // RFC 7641 §3.6: RST in reply to a notification cancels the observation
+        Incoming::Reset => {
+            reliability.handle_rst(msg_id);
+            if let Some((path, stale)) = obs.cancel_by_msg_id(msg_id) {
+                tracing::info!("RST deregistration for '{}' path '{}'", identity, path);
+                for id in stale {
+                    reliability.handle_rst(id);
+                }
+                let _ = router
+                    .unregister_connection_observer(identity, &path, obs_tx)
+                    .await;
+            }
+            return None;
         }
-        reliability.handle_rst(msg_id);
-        return;
-    }
-
-    // RFC 7252 §4.2: ACK for a CON we sent — stop retransmitting
-    if msg_type == MessageType::Acknowledgement {
-        if reliability.handle_ack(msg_id) {
-            tracing::debug!(msg_id, "reliability.ack_received");
+        // RFC 7252 §4.2: ACK for a CON we sent — stop retransmitting
+        Incoming::Acknowledgement => {
+            if reliability.handle_ack(msg_id) {
+                tracing::debug!(msg_id, "reliability.ack_received");
+            }
+            return None;
         }
-        return;
-    }
-
-    // RFC 7252 §4.3: Empty message handling (code 0.00)
-    // CON Empty = ping → respond with RST; NON Empty = silently ignore
-    if packet.header.code == MessageClass::Empty {
-        if msg_type == MessageType::Confirmable {
-            tracing::debug!(msg_id, "ping received, responding with RST");
-            let mut rst = Packet::new();
-            rst.header.set_type(MessageType::Reset);
-            rst.header.code = MessageClass::Empty;
-            rst.header.message_id = msg_id;
-            if let Ok(bytes) = rst.to_bytes() {
-                if let Err(e) = dtls.send_application_data(&bytes) {
-                    tracing::error!(error = %e, "dtls.send_failed");
-                }
-                drain_packets(dtls, out_buf, socket, socket_addr).await;
+        // RFC 7252 §4.3: CON Empty is a ping; a CON we can't process gets RST
+        Incoming::Reject => {
+            if packet.header.code == MessageClass::Empty {
+                tracing::debug!(msg_id, "ping received, responding with RST");
+            } else {
+                tracing::debug!(msg_id, "rejecting non-request CON with RST");
             }
-        } else {
-            tracing::debug!(msg_id, "ignoring NON empty message");
+            if let Ok(bytes) = reset_for(msg_id).to_bytes() {
+                link.send(&bytes).await;
+            }
+            return None;
+        }
+        Incoming::Ignore => {
+            tracing::debug!(msg_id, "ignoring non-request NON message");
+            return None;
         }
-        return;
     }
 
     // RFC 7252 §4.5: Deduplication for incoming CON requests
@@ -372,49 +1039,46 @@ async fn handle_request<O, S>(
         match reliability.check_dedup(msg_id) {
             DedupResult::Duplicate(cached_bytes) => {
                 tracing::debug!(msg_id, "reliability.dedup_hit");
-                if let Err(e) = dtls.send_application_data(&cached_bytes) {
-                    tracing::error!(error = %e, "dtls.send_failed");
-                }
-                drain_packets(dtls, out_buf, socket, socket_addr).await;
-                return;
+                link.send(&cached_bytes).await;
+                return None;
             }
             DedupResult::NewMessage => {}
         }
     }
 
     // RFC 7252 §5.4.1: Reject requests with unrecognized critical options (4.02 Bad Option).
-    // Critical options have odd option numbers. Options known to coap-lite are accepted;
-    // only truly unknown critical options trigger rejection.
-    for (&option_num, _) in packet.options() {
-        if let CoapOption::Unknown(_) = CoapOption::from(option_num)
-            && option_num % 2 == 1
-        {
-            tracing::warn!(
-                option_num,
-                "Rejecting request with unrecognized critical option"
-            );
-            let mut rst = Packet::new();
-            rst.header.message_id = msg_id;
-            rst.set_token(packet.get_token().to_vec());
-            rst.header.code = MessageClass::Response(ResponseType::BadOption);
+    // Applications declare the custom options they handle on the router.
+    if let Some(option_num) = router.options().unrecognized_critical(&packet) {
+        tracing::warn!(
+            option_num,
+            "Rejecting request with unrecognized critical option"
+        );
+        let mut rst = Packet::new();
+        rst.set_token(packet.get_token().to_vec());
+        rst.header.code = MessageClass::Response(ResponseType::BadOption);
+        mirror_message_type(&mut rst, msg_type, msg_id, &mut obs.ids);
+        if let Ok(bytes) = rst.to_bytes() {
             if is_confirmable {
-                rst.header.set_type(MessageType::Acknowledgement);
-            }
-            if let Ok(bytes) = rst.to_bytes() {
-                if is_confirmable {
-                    reliability.record_response(msg_id, bytes.clone());
-                }
-                if let Err(e) = dtls.send_application_data(&bytes) {
-                    tracing::error!(error = %e, "dtls.send_failed");
-                }
-                drain_packets(dtls, out_buf, socket, socket_addr).await;
+                reliability.record_response(msg_id, bytes.clone());
             }
-            return;
+            link.send(&bytes).await;
         }
+        return Some(ResponseType::BadOption);
     }
 
     // RFC 7252 §5.3.1: Save request token for echoing into the response
     let request_token = packet.get_token().to_vec();
+    let request_id = RequestId::new(identity, &packet);
+    if let Some(trace) = TraceContext::from_packet(&packet) {
+        tracing::debug!(
+            request_id = %request_id,
+            trace_id = %trace.trace_id_hex(),
+            "request.trace_context"
+        );
+    }
+
+    // RFC 7967: Response classes the client is not interested in
+    let no_response = NoResponse::from_packet(&packet);
 
     let mut coap_request = CoapRequest::from_packet(packet, socket_addr);
 
@@ -427,22 +1091,19 @@ async fn handle_request<O, S>(
                 if resp.message.header.code
                     == MessageClass::Response(ResponseType::RequestEntityTooLarge)
                 {
-                    add_size1_option(&mut resp.message, max_message_size);
+                    set_size1(&mut resp.message, max_message_size);
                 }
                 // RFC 7252 §5.3.1: Echo request token in block transfer responses
                 resp.message.set_token(request_token.clone());
-                resp.message.header.message_id = msg_id;
                 // RFC 7252 §5.2.1: Piggybacked ACK for CON block transfer responses
-                if is_confirmable {
-                    resp.message.header.set_type(MessageType::Acknowledgement);
-                }
-                send_response(dtls, out_buf, socket, socket_addr, resp).await;
+                mirror_message_type(&mut resp.message, msg_type, msg_id, &mut obs.ids);
+                send_response(link, resp).await;
                 // RFC 7252 §4.5: Cache response for deduplication
                 if is_confirmable && let Ok(bytes) = resp.message.to_bytes() {
                     reliability.record_response(msg_id, bytes);
                 }
             }
-            return;
+            return coap_request.response.map(|resp| *resp.get_status());
         }
         Err(e) => {
             tracing::error!("Block transfer error: {}", e.message);
@@ -451,40 +1112,81 @@ async fn handle_request<O, S>(
                 if resp.message.header.code
                     == MessageClass::Response(ResponseType::RequestEntityTooLarge)
                 {
-                    add_size1_option(&mut resp.message, max_message_size);
+                    set_size1(&mut resp.message, max_message_size);
                 }
                 resp.message.set_token(request_token.clone());
-                resp.message.header.message_id = msg_id;
                 // RFC 7252 §5.2.1: Piggybacked ACK for CON block transfer responses
-                if is_confirmable {
-                    resp.message.header.set_type(MessageType::Acknowledgement);
-                }
-                send_response(dtls, out_buf, socket, socket_addr, resp).await;
+                mirror_message_type(&mut resp.message, msg_type, msg_id, &mut obs.ids);
+                send_response(link, resp).await;
                 // RFC 7252 §4.5: Cache response for deduplication
                 if is_confirmable && let Ok(bytes) = resp.message.to_bytes() {
                     reliability.record_response(msg_id, bytes);
                 }
             }
-            return;
+            return coap_request.response.map(|resp| *resp.get_status());
         }
         Ok(false) => {} // Not a block request, or Block1 fully reassembled — proceed
     }
 
+    // Compressed payloads are decoded after Block1 reassembly, so handlers
+    // see the plain payload
+    let mut accepted_coding = None;
+    if let Some(compression) = compression {
+        if let Err(e) = compression.decode(&mut coap_request.message) {
+            tracing::warn!(msg_id, error = %e, "request.decompress_failed");
+            let status = e.status();
+            let mut reply = Packet::new();
+            reply.set_token(request_token);
+            reply.header.code = MessageClass::Response(status);
+            mirror_message_type(&mut reply, msg_type, msg_id, &mut obs.ids);
+            if let Ok(bytes) = reply.to_bytes() {
+                if is_confirmable {
+                    reliability.record_response(msg_id, bytes.clone());
+                }
+                link.send(&bytes).await;
+            }
+            return Some(status);
+        }
+        accepted_coding = compression.accepted(&coap_request.message);
+    }
+
     // Save packet for Block2 intercept_response later
     let packet_for_block2 = coap_request.message.clone();
 
     let mut request: CoapumRequest<SocketAddr> = coap_request.into();
     request.identity = identity.to_string();
+    request.extensions_mut().insert(session.clone());
+    request.extensions_mut().insert(payload_limits);
+    if let Some(format) = diagnostic_payloads {
+        request.extensions_mut().insert(format);
+    }
+
+    // Legacy alias paths register observers under the path they resolve to
+    router.rewrite_alias(&mut request);
 
     let path = request.get_path();
     let observe_flag = *request.get_observe_flag();
     let method = *request.get_method();
 
+    // Requests with an invalid path, or whose observer deregistration the
+    // backend failed, are answered without running the handler
+    let mut rejected = None;
+    if let Err(e) = validate_path(path_policy, validate_request_paths, &request) {
+        tracing::error!("Invalid path '{}' from {}: {}", path, socket_addr, e);
+        rejected = Some(invalid_path(e));
+    }
+
     // Validate observe request and prepare for deferred registration.
     // Registration is deferred until after handler succeeds (RFC 7641 §3.1:
     // the observe option in the response confirms registration).
     let pending_observe = match (observe_flag, method) {
-        (Some(ObserveOption::Register), RequestType::Get) => match validate_observer_path(path) {
+        _ if rejected.is_some() => None,
+        // The handler manages the observation itself
+        (Some(_), RequestType::Get) if router.is_manual_observe(path) => {
+            tracing::debug!(identity = %identity, path = %path, "observer.manual");
+            None
+        }
+        (Some(ObserveOption::Register), RequestType::Get) => match path_policy.validate(path) {
             Ok(normalized_path) => {
                 if !router.has_observe_route(&normalized_path) {
                     tracing::warn!(
@@ -493,7 +1195,9 @@ async fn handle_request<O, S>(
                         normalized_path
                     );
                     None
-                } else if router.observer_count(identity).await >= max_observers_per_device {
+                } else if !obs.observations.contains_key(&normalized_path)
+                    && router.observer_count(identity).await >= max_observers_per_device
+                {
                     tracing::warn!(
                         "Observer registration rejected for '{}' on '{}': limit of {} exceeded",
                         identity,
@@ -512,15 +1216,20 @@ async fn handle_request<O, S>(
                     socket_addr,
                     e
                 );
-                return;
+                rejected = Some(invalid_path(e));
+                None
             }
         },
         (Some(ObserveOption::Deregister), RequestType::Get) => {
-            match validate_observer_path(path) {
+            match path_policy.validate(path) {
                 Ok(normalized_path) => {
-                    obs.observer_tokens.remove(&normalized_path);
-                    if let Err(e) = router.unregister_observer(identity, &normalized_path).await {
-                        tracing::error!("Failed to unregister observer: {:?}", e);
+                    obs.end_observation(&normalized_path);
+                    if let Err(e) = router
+                        .unregister_connection_observer(identity, &normalized_path, obs_tx)
+                        .await
+                    {
+                        tracing::error!(identity = %identity, path = %normalized_path, error = ?e, "observer.unregister.failed");
+                        rejected = Some(observer_unavailable("observer.unregister_failed"));
                     }
                 }
                 Err(e) => {
@@ -530,7 +1239,7 @@ async fn handle_request<O, S>(
                         socket_addr,
                         e
                     );
-                    return;
+                    rejected = Some(invalid_path(e));
                 }
             }
             None
@@ -538,326 +1247,962 @@ async fn handle_request<O, S>(
         _ => None,
     };
 
-    // Route the request
-    match router.call(request).await {
+    // A device re-sending its registration, e.g. after waking up, renews
+    // the observation it already has
+    let renewal = pending_observe
+        .as_ref()
+        .is_some_and(|path| obs.is_observing(path, &request_token));
+
+    // The registering GET handler sees the metadata the notifications will get
+    let metadata = pending_observe.as_ref().map(|path| {
+        let metadata = match obs.observations.get(path) {
+            Some(observation) if renewal => observation.metadata.clone(),
+            _ => ObservationMetadata::from_packet(&request.message),
+        };
+        request.extensions_mut().insert(metadata.clone());
+        metadata
+    });
+
+    // Route the request; handler logs carry its correlation ID
+    let routed = match rejected {
+        Some(error) => Ok(error_response(
+            error.with_request_id(&request_id),
+            diagnostic_payloads,
+        )),
+        None => {
+            router
+                .call(request)
+                .instrument(tracing::info_span!("request", request_id = %request_id))
+                .await
+        }
+    };
+    match routed {
         Ok(mut resp) => {
+            let mut status = *resp.get_status();
             // RFC 7252 §5.3.1: Echo the request token in the response
             resp.message.set_token(request_token.clone());
-            resp.message.header.message_id = msg_id;
 
             // RFC 7641 §3.1: Register observer only after handler succeeds
             if let Some(ref normalized_path) = pending_observe
                 && !resp.get_status().is_error()
             {
-                if let Err(e) = router
-                    .register_observer(identity, normalized_path, obs_tx.clone())
+                let metadata = metadata.unwrap_or_default();
+                if renewal {
+                    tracing::debug!(identity = %identity, path = %normalized_path, "observer.renewed");
+                    // The sequence continues, so the response is fresher
+                    // than every notification sent before
+                    obs.sequence = obs.sequence.wrapping_add(1) & 0x00FF_FFFF;
+                    resp.message.set_observe_value(obs.sequence);
+                } else if let Err(e) = router
+                    .register_observer_with_metadata(
+                        identity,
+                        normalized_path,
+                        obs_tx.clone(),
+                        metadata.clone(),
+                    )
                     .await
                 {
                     tracing::error!(identity = %identity, path = %normalized_path, error = ?e, "observer.register.failed");
+                    // Without notifications to come, the device should retry
+                    // rather than take the response as a registration
+                    let error = observer_unavailable("observer.register_failed")
+                        .with_request_id(&request_id);
+                    resp = error_response(error, diagnostic_payloads);
+                    resp.message.set_token(request_token.clone());
+                    status = *resp.get_status();
                 } else {
                     tracing::info!(identity = %identity, path = %normalized_path, "observer.registered");
                     // RFC 7252 §5.3.1: Store token for future notifications
-                    obs.observer_tokens
-                        .insert(normalized_path.clone(), request_token);
+                    obs.observe(
+                        normalized_path.clone(),
+                        Observation {
+                            token: request_token,
+                            metadata,
+                        },
+                    );
                     obs.sequence = obs.sequence.wrapping_add(1) & 0x00FF_FFFF;
                     resp.message.set_observe_value(obs.sequence);
                 }
+            } else if let Some(ref normalized_path) = pending_observe
+                && obs.observations.contains_key(normalized_path)
+            {
+                // RFC 7641 §4.1: A registration answered with an error also
+                // ends the observation the connection already had
+                obs.end_observation(normalized_path);
+                if let Err(e) = router
+                    .unregister_connection_observer(identity, normalized_path, obs_tx)
+                    .await
+                {
+                    tracing::error!(identity = %identity, path = %normalized_path, error = ?e, "observer.unregister.failed");
+                }
+                tracing::info!(identity = %identity, path = %normalized_path, status = ?status, "observer.ended");
+            }
+
+            // Compress before Block2 so the blocks carry the compressed payload
+            if let (Some(compression), Some(coding)) = (compression, accepted_coding) {
+                compression.encode(&mut resp.message, coding);
             }
 
-            // RFC 7959: Fragment large responses using Block2
+            // RFC 7959: Fragment large responses using Block2, in blocks
+            // that fit the MTU
             let mut block_req = CoapRequest::from_packet(packet_for_block2, socket_addr);
+            if let Err(e) = mtu::fit_block2(&mut block_req.message, &resp.message, mtu) {
+                tracing::error!(
+                    request_id = %request_id,
+                    size = e.size,
+                    limit = e.limit,
+                    "response.exceeds_mtu"
+                );
+                let error = ErrorResponse::new(
+                    StatusCode::InternalServerError,
+                    format!("response of {} bytes exceeds the MTU", e.size),
+                )
+                .with_kind("response.exceeds_mtu")
+                .with_request_id(&request_id);
+                let token = resp.message.get_token().to_vec();
+                resp = error_response(error, diagnostic_payloads);
+                resp.message.set_token(token);
+                status = ResponseType::InternalServerError;
+            }
+            let size = resp.message.payload.len();
             block_req.response = Some(resp);
             if let Err(e) = block_handler.intercept_response(&mut block_req) {
                 tracing::error!("Block transfer response error: {}", e.message);
             }
+            announce_size2(&mut block_req, size);
 
-            if let Some(ref mut resp) = block_req.response {
-                // RFC 7252 §5.2.1: Piggybacked ACK for Confirmable requests
-                if is_confirmable {
-                    resp.message.header.set_type(MessageType::Acknowledgement);
+            if let Some(ref resp) = block_req.response
+                && no_response.suppresses(*resp.get_status())
+            {
+                tracing::debug!(msg_id, status = ?resp.get_status(), "response.suppressed");
+                // RFC 7967 §2: A CON request is still acknowledged
+                if is_confirmable && let Ok(bytes) = empty_ack_for(msg_id).to_bytes() {
+                    link.send(&bytes).await;
+                    reliability.record_response(msg_id, bytes);
                 }
+            } else if let Some(ref mut resp) = block_req.response {
+                // RFC 7252 §5.2: Piggybacked ACK for CON, NON response for NON
+                mirror_message_type(&mut resp.message, msg_type, msg_id, &mut obs.ids);
 
                 tracing::debug!("Got response: {:?}", resp.message);
-                send_response(dtls, out_buf, socket, socket_addr, resp).await;
+                send_response(link, resp).await;
 
                 // Cache serialized response for deduplication
                 if is_confirmable && let Ok(bytes) = resp.message.to_bytes() {
                     reliability.record_response(msg_id, bytes);
                 }
             }
+            Some(status)
+        }
+        Err(e) => {
+            tracing::error!("Error: {}", e);
+            None
         }
-        Err(e) => tracing::error!("Error: {}", e),
     }
 }
 
-/// Process DTLS outputs after handle_packet(), handling Connected and ApplicationData events.
+/// The response to an error the server answers itself, with a diagnostic
+/// payload if those are enabled.
+fn error_response(
+    error: ErrorResponse,
+    diagnostic_payloads: Option<DiagnosticFormat>,
+) -> crate::CoapResponse {
+    let mut resp = crate::CoapResponse {
+        message: Packet::new(),
+    };
+    resp.set_status(error.status.into());
+    if let Some(format) = diagnostic_payloads {
+        let _ = error.with_format(format).write_payload(&mut resp.message);
+    }
+    resp
+}
+
+/// Path of the resource discovery interface (RFC 6690 §4).
+const WELL_KNOWN_CORE: &str = ".well-known/core";
+
+/// Check the path of `request` against `policy`.
+///
+/// Other requests than observations are only checked with `all_requests`,
+/// and never for resource discovery at [`WELL_KNOWN_CORE`]. Only observe
+/// requests need a path: the others may address the root resource.
+fn validate_path(
+    policy: &PathPolicy,
+    all_requests: bool,
+    request: &CoapumRequest<SocketAddr>,
+) -> Result<(), PathValidationError> {
+    let observe = request.get_observe_flag().is_some() && *request.get_method() == RequestType::Get;
+    if !observe && (!all_requests || request.get_path() == WELL_KNOWN_CORE) {
+        return Ok(());
+    }
+    match policy.validate(request.get_path()) {
+        Err(PathValidationError::EmptyPath) if !observe => Ok(()),
+        result => result.map(|_| ()),
+    }
+}
+
+/// 4.00 Bad Request for a request with an invalid path.
+fn invalid_path(e: PathValidationError) -> ErrorResponse {
+    ErrorResponse::new(StatusCode::BadRequest, e.to_string()).with_kind("request.invalid_path")
+}
+
+/// 5.03 Service Unavailable for an observe request the backend failed.
 ///
-/// Returns `false` if the connection should be terminated.
+/// Without a Max-Age option, the device retries after the default of 60
+/// seconds (RFC 7252 §5.9.3.4).
+fn observer_unavailable(kind: &str) -> ErrorResponse {
+    ErrorResponse::new(
+        StatusCode::ServiceUnavailable,
+        "observer backend unavailable",
+    )
+    .with_kind(kind)
+}
+
+/// Handle a CoAP message from an established connection: a response to a
+/// request the server sent the device, or a request to route.
 #[allow(clippy::too_many_arguments)]
-async fn process_outputs<O, S>(
-    dtls: &mut Dtls,
-    out_buf: &mut [u8],
-    socket: &UdpSocket,
-    remote: SocketAddr,
-    resolver: &CapturingResolver<impl CredentialStore>,
-    connected: &mut bool,
-    identity: &mut Option<String>,
+pub(crate) async fn handle_message<O, S>(
+    packet: Packet,
+    link: &mut impl Link,
+    session: &Session,
     router: &mut CoapRouter<O, S>,
-    obs_tx: &Arc<Sender<ObserverValue>>,
+    obs_tx: &ObserverSender,
     obs: &mut ObserveState,
     block_handler: &mut BlockHandler<SocketAddr>,
-    max_observers_per_device: usize,
-    connections: &Mutex<HashMap<String, ConnectionInfo>>,
-    disconnect_tx: Sender<()>,
+    pending: &mut PendingRequests,
     config: &Config,
     reliability: &mut ReliabilityState,
-) -> bool
-where
+) where
     S: Debug + Clone + Send + Sync + 'static,
     O: Observer + Send + Sync + 'static,
 {
-    loop {
-        match dtls.poll_output(out_buf) {
-            Output::Packet(p) => {
-                if let Err(e) = socket.send_to(p, remote).await {
-                    tracing::error!(addr = %remote, error = %e, "udp.send_failed");
+    if handle_device_response(&packet, pending, link, reliability).await {
+        return;
+    }
+    let audit = if config.audit_sinks.is_empty() {
+        None
+    } else {
+        AuditStart::from_packet(&packet)
+    };
+    let status = handle_request(
+        packet,
+        session,
+        router,
+        link,
+        obs_tx,
+        obs,
+        block_handler,
+        config.max_message_size,
+        config.mtu,
+        config.max_observers_per_device,
+        &config.path_policy,
+        config.validate_request_paths,
+        config.payload_limits(),
+        config.diagnostic_payloads,
+        config.compression.as_ref(),
+        reliability,
+    )
+    .await;
+    if let (Some(audit), Some(status)) = (audit, status) {
+        audit.finish(
+            &config.audit_sinks,
+            session.identity(),
+            link.remote(),
+            status,
+        );
+    }
+}
+
+/// Re-register the observations a snapshot recorded for a reconnecting device.
+///
+/// Notifications carry the tokens of the original registrations, so the
+/// device doesn't have to observe its resources again.
+async fn restore_observations<O, S>(
+    record: ConnectionRecord,
+    session: &Session,
+    router: &mut CoapRouter<O, S>,
+    obs_tx: &ObserverSender,
+    obs: &mut ObserveState,
+    config: &Config,
+) where
+    S: Debug + Clone + Send + Sync + 'static,
+    O: Observer + Send + Sync + 'static,
+{
+    let identity = session.identity();
+    for observation in record.observations {
+        let Ok(path) = config.path_policy.validate(&observation.path) else {
+            continue;
+        };
+        let metadata = ObservationMetadata::new();
+        for (key, value) in observation.metadata {
+            metadata.insert(key, value);
+        }
+        if let Err(e) = router
+            .register_observer_with_metadata(identity, &path, obs_tx.clone(), metadata.clone())
+            .await
+        {
+            tracing::error!(identity = %identity, path = %path, error = ?e, "observer.restore.failed");
+            continue;
+        }
+        tracing::info!(identity = %identity, path = %path, "observer.restored");
+        obs.observe(
+            path,
+            Observation {
+                token: observation.token,
+                metadata,
+            },
+        );
+    }
+}
+
+/// Most connections a datagram from an unknown address is offered to.
+const MAX_MIGRATION_CANDIDATES: usize = 8;
+
+/// Whether a datagram is a DTLS application data record (RFC 6347 §4.1).
+///
+/// Such records only make sense within an established session, so when one
+/// arrives from an unknown address the peer has moved rather than started
+/// a new session.
+fn is_application_data(datagram: &[u8]) -> bool {
+    datagram.first() == Some(&23)
+}
+
+/// A datagram dispatched to a connection task.
+struct Datagram {
+    /// The address it was received from; differs from the connection's
+    /// address when the peer may have moved
+    from: SocketAddr,
+    data: Vec<u8>,
+}
+
+/// Notices from connection tasks to the dispatch loop.
+#[derive(Debug)]
+enum DispatchUpdate {
+    /// The connection ended
+    Closed(SocketAddr),
+    /// The peer of the connection moved to a new address
+    Migrated { from: SocketAddr, to: SocketAddr },
+}
+
+/// A DTLS session over UDP, as the peer of a connection.
+///
+/// Each connection owns its own Dtls instance and its own
+/// `CapturingResolver`, so identity capture is race-free.
+struct DtlsPeer<C> {
+    dtls: Dtls,
+    out_buf: Vec<u8>,
+    socket: Arc<UdpSocket>,
+    remote: SocketAddr,
+    resolver: Arc<CapturingResolver<C>>,
+    packet_rx: mpsc::Receiver<Datagram>,
+    cleanup_tx: mpsc::Sender<DispatchUpdate>,
+    connections: ConnectionRegistry,
+    /// The PSK identity the peer authenticated with, for logging
+    identity: Option<String>,
+    connected: bool,
+    /// Held until the handshake completes so slow or abandoned handshakes
+    /// count against `max_concurrent_handshakes`
+    handshake_slot: Option<HandshakeSlot>,
+    handshake_deadline: tokio::time::Instant,
+    /// One-shot session lifetime timer (DTLS 1.2 key wear-out mitigation).
+    /// Not reset on activity.
+    session_deadline: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+
+impl<C: CredentialStore> DtlsPeer<C> {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        remote: SocketAddr,
+        packet_rx: mpsc::Receiver<Datagram>,
+        socket: Arc<UdpSocket>,
+        credential_store: C,
+        psk_identity_hint: Option<Vec<u8>>,
+        config: &Config,
+        connections: ConnectionRegistry,
+        handshake_slot: HandshakeSlot,
+        cleanup_tx: mpsc::Sender<DispatchUpdate>,
+    ) -> Self {
+        // The store is asked for its hint here so reloaded hints apply to
+        // new connections
+        let psk_identity_hint = credential_store.psk_identity_hint().or(psk_identity_hint);
+        let resolver = Arc::new(CapturingResolver::new(credential_store));
+        let dimpl_config = Arc::new(
+            dimpl::Config::builder()
+                .with_psk_server(
+                    psk_identity_hint,
+                    resolver.clone() as Arc<dyn dimpl::PskResolver>,
+                )
+                .build()
+                .expect("valid DTLS config"),
+        );
+
+        Self {
+            dtls: Dtls::new_12_psk(dimpl_config, Instant::now()),
+            out_buf: vec![0u8; 2048],
+            socket,
+            remote,
+            resolver,
+            packet_rx,
+            cleanup_tx,
+            connections,
+            identity: None,
+            connected: false,
+            handshake_slot: Some(handshake_slot),
+            handshake_deadline: tokio::time::Instant::now() + config.handshake_timeout,
+            session_deadline: config
+                .max_session_lifetime
+                .map(|lifetime| Box::pin(tokio::time::sleep(lifetime))),
+        }
+    }
+}
+
+impl<C: CredentialStore> Peer for DtlsPeer<C> {
+    type Record = Datagram;
+
+    async fn recv(&mut self) -> Option<Datagram> {
+        tokio::select! {
+            datagram = self.packet_rx.recv() => {
+                if datagram.is_none() {
+                    // Channel closed — dispatch removed us
+                    tracing::debug!(addr = %self.remote, "connection.channel_closed");
                 }
+                datagram
             }
-            Output::Connected => {
-                tracing::debug!(addr = %remote, "dtls.connected");
-
-                let raw_identity = match resolver.take_last_identity() {
-                    Some(id) => id,
-                    None => {
-                        tracing::error!(addr = %remote, "dtls.no_identity");
-                        return false;
-                    }
-                };
-
-                let validated = match extract_identity(raw_identity.as_bytes()) {
-                    Some(id) => id,
-                    None => return false,
-                };
-
-                if !manage_connection(
-                    &validated,
-                    remote,
-                    disconnect_tx.clone(),
-                    connections,
-                    config.min_reconnect_interval,
-                    config.max_reconnect_attempts,
-                )
-                .await
-                {
-                    return false;
+
+            // Handshake did not complete in time
+            () = tokio::time::sleep_until(self.handshake_deadline), if !self.connected => {
+                tracing::warn!(addr = %self.remote, "connection.handshake_timeout");
+                self.connections.handshakes.record_timeout();
+                None
+            }
+
+            // Session lifetime limit (DTLS 1.2 key wear-out mitigation)
+            () = async {
+                match self.session_deadline.as_mut() {
+                    Some(deadline) => deadline.await,
+                    None => std::future::pending().await,
                 }
+            } => {
+                tracing::info!(
+                    addr = %self.remote,
+                    identity = ?self.identity,
+                    "connection.session_lifetime_exceeded"
+                );
+                None
+            }
+        }
+    }
 
-                tracing::info!(identity = %validated, addr = %remote, "connection.accepted");
-                *identity = Some(validated);
-                *connected = true;
+    async fn open(&mut self, Datagram { from, data }: Datagram) -> Opened {
+        // A record from another address is adopted only if it
+        // authenticates; anyone can send one, so failures are not fatal
+        let candidate = (from != self.remote).then_some(from);
+        if candidate.is_some() && !self.connected {
+            return Opened::Ignored;
+        }
+        if let Err(e) = self.dtls.handle_packet(&data) {
+            if candidate.is_some() {
+                tracing::debug!(addr = %from, error = %e, "connection.migration_rejected");
+                return Opened::Ignored;
             }
-            Output::ApplicationData(data) => {
-                if let Some(id) = identity.as_ref() {
-                    let packet = match Packet::from_bytes(data) {
-                        Ok(p) => p,
-                        Err(e) => {
-                            tracing::error!("Failed to parse packet: {}", e);
-                            continue;
-                        }
+            tracing::error!(addr = %self.remote, error = %e, "dtls.packet_error");
+            return Opened::Closed;
+        }
+
+        let mut inbound = Vec::new();
+        loop {
+            match self.dtls.poll_output(&mut self.out_buf) {
+                Output::Packet(p) => {
+                    if let Err(e) = self.socket.send_to(p, self.remote).await {
+                        tracing::error!(addr = %self.remote, error = %e, "udp.send_failed");
+                    }
+                }
+                Output::Connected => {
+                    tracing::debug!(addr = %self.remote, "dtls.connected");
+                    let Some(identity) = self.resolver.take_last_identity() else {
+                        tracing::error!(addr = %self.remote, "dtls.no_identity");
+                        return Opened::Closed;
                     };
-                    handle_request(
-                        packet,
-                        remote,
-                        id,
-                        router,
-                        dtls,
-                        out_buf,
-                        socket,
-                        obs_tx,
-                        obs,
-                        block_handler,
-                        config.max_message_size,
-                        max_observers_per_device,
-                        reliability,
-                    )
-                    .await;
+                    self.identity = Some(identity.clone());
+                    self.connected = true;
+                    self.handshake_slot.take();
+                    inbound.push(Inbound::Authenticated(identity));
+                }
+                Output::ApplicationData(data) if self.connected => {
+                    // The record authenticated, so the peer moved to the
+                    // address it came from (NAT rebinding)
+                    if let Some(candidate) = candidate
+                        && candidate != self.remote
+                    {
+                        tracing::info!(
+                            identity = ?self.identity,
+                            from = %self.remote,
+                            to = %candidate,
+                            "connection.migrated"
+                        );
+                        let from = std::mem::replace(&mut self.remote, candidate);
+                        let _ = self
+                            .cleanup_tx
+                            .send(DispatchUpdate::Migrated {
+                                from,
+                                to: candidate,
+                            })
+                            .await;
+                        inbound.push(Inbound::Migrated(candidate));
+                    }
+                    if let Some(packet) = decode_datagram(data) {
+                        inbound.push(Inbound::Message(packet));
+                    }
                 }
+                Output::Timeout(_) => break,
+                _ => {} // PeerCert, KeyingMaterial — not used for PSK
             }
-            Output::Timeout(_) => break,
-            _ => {} // PeerCert, KeyingMaterial — not used for PSK
         }
+        Opened::Record(inbound)
+    }
+
+    fn link(&mut self) -> impl Link + '_ {
+        DtlsLink::new(&mut self.dtls, &mut self.out_buf, &self.socket, self.remote)
+    }
+
+    async fn client_tags(&self, identity: &str) -> Vec<String> {
+        client_tags(self.resolver.store(), identity).await
+    }
+
+    async fn flush(&mut self) -> bool {
+        // Drive DTLS retransmit timers after every event
+        if let Err(e) = self.dtls.handle_timeout(Instant::now()) {
+            tracing::error!(addr = %self.remote, error = %e, "dtls.timeout_error");
+            return false;
+        }
+        drain_packets(&mut self.dtls, &mut self.out_buf, &self.socket, self.remote).await;
+        true
     }
-    true
 }
 
-/// Per-connection task. Each spawned task owns its own Dtls instance and
-/// its own `CapturingResolver`, so identity capture is race-free.
-#[allow(clippy::too_many_arguments)]
-async fn connection_task<O, S, C>(
-    remote: SocketAddr,
-    mut packet_rx: mpsc::Receiver<Vec<u8>>,
-    socket: Arc<UdpSocket>,
-    credential_store: C,
-    psk_identity_hint: Option<Vec<u8>>,
-    mut router: CoapRouter<O, S>,
-    config: Config,
-    connections: Arc<Mutex<HashMap<String, ConnectionInfo>>>,
-    conn_count: Arc<AtomicUsize>,
-    cleanup_tx: mpsc::Sender<SocketAddr>,
+/// RFC 7641 §3.2: End every observation of a connection that is taken over
+/// with a non-2.xx notification.
+async fn end_observations<O, S>(
+    router: &CoapRouter<O, S>,
+    link: &mut impl Link,
+    obs: &mut ObserveState,
+    block_handler: &mut BlockHandler<SocketAddr>,
+    mtu: usize,
+    reliability: &mut ReliabilityState,
 ) where
     S: Debug + Clone + Send + Sync + 'static,
     O: Observer + Send + Sync + 'static,
-    C: CredentialStore,
 {
-    // Build per-connection resolver + dimpl config so identity capture is race-free
-    let resolver = Arc::new(CapturingResolver::new(credential_store));
-    let dimpl_config = Arc::new(
-        dimpl::Config::builder()
-            .with_psk_server(
-                psk_identity_hint.clone(),
-                resolver.clone() as Arc<dyn dimpl::PskResolver>,
-            )
-            .build()
-            .expect("valid DTLS config"),
+    let paths: Vec<String> = obs.observations.keys().cloned().collect();
+    for path in paths {
+        let mut resp = crate::CoapResponse {
+            message: Packet::new(),
+        };
+        resp.set_status(ResponseType::ServiceUnavailable);
+        send_notification(
+            resp,
+            path,
+            router,
+            link,
+            obs,
+            block_handler,
+            mtu,
+            reliability,
+        )
+        .await;
+    }
+}
+
+/// RFC 7252 §4.2: Resend the CONs whose retransmission timer fired and
+/// give up on those out of retransmissions, ending the observations whose
+/// notifications went unacknowledged.
+///
+/// Returns false if the keepalive `ping` went unanswered, so the peer is
+/// gone.
+#[allow(clippy::too_many_arguments)]
+async fn retransmit<O, S>(
+    link: &mut impl Link,
+    reliability: &mut ReliabilityState,
+    pending: &mut PendingRequests,
+    obs: &mut ObserveState,
+    session: Option<&Session>,
+    router: &mut CoapRouter<O, S>,
+    obs_tx: &ObserverSender,
+    ping: Option<u16>,
+) -> bool
+where
+    S: Debug + Clone + Send + Sync + 'static,
+    O: Observer + Send + Sync + 'static,
+{
+    let mut reachable = true;
+    for action in reliability.process_retransmits() {
+        match action {
+            RetransmitAction::Resend { msg_id, ref bytes } => {
+                tracing::debug!(msg_id, "reliability.retransmit");
+                link.send(bytes).await;
+            }
+            RetransmitAction::GiveUp { msg_id } if ping == Some(msg_id) => {
+                tracing::info!(
+                    addr = %link.remote(),
+                    identity = ?session.map(Session::identity),
+                    "connection.keepalive_timeout"
+                );
+                reachable = false;
+            }
+            RetransmitAction::GiveUp { msg_id } => {
+                tracing::warn!(msg_id, "reliability.give_up");
+                pending.give_up(msg_id);
+                if let Some((path, stale)) = obs.cancel_by_msg_id(msg_id)
+                    && let Some(session) = session
+                {
+                    for stale_id in stale {
+                        reliability.handle_rst(stale_id);
+                    }
+                    let id = session.identity();
+                    let _ = router
+                        .unregister_connection_observer(id, &path, obs_tx)
+                        .await;
+                    tracing::info!(identity = %id, path = %path, "reliability.observer_deregistered");
+                }
+            }
+        }
+    }
+    reachable
+}
+
+/// Accept a peer that authenticated as `identity`, or refuse it.
+///
+/// Returns the validated identity and the connection's session once the
+/// connection is registered, with the observations a snapshot recorded for
+/// it restored.
+#[allow(clippy::too_many_arguments)]
+async fn accept_peer<O, S>(
+    identity: &str,
+    peer: &mut impl Peer,
+    router: &mut CoapRouter<O, S>,
+    obs_tx: &ObserverSender,
+    obs: &mut ObserveState,
+    stats: &Arc<ConnectionStats>,
+    disconnect_tx: &Sender<DisconnectReason>,
+    request_tx: &Sender<DeviceRequest>,
+    connections: &ConnectionRegistry,
+    config: &Config,
+) -> Option<(String, Session)>
+where
+    S: Debug + Clone + Send + Sync + 'static,
+    O: Observer + Send + Sync + 'static,
+{
+    let validated = extract_identity(identity.as_bytes())?;
+    let mapped = map_identity(&validated, config.identity_mapper.as_deref()).await?;
+    let remote = peer.link().remote();
+    if !manage_connection(
+        &validated,
+        remote,
+        disconnect_tx.clone(),
+        request_tx.clone(),
+        stats.clone(),
+        connections,
+        config.takeover_policy,
+        config.min_reconnect_interval,
+        config.max_reconnect_attempts,
+    )
+    .await
+    {
+        return None;
+    }
+
+    tracing::info!(
+        identity = %validated,
+        device_id = %mapped.identity(),
+        addr = %remote,
+        "connection.accepted"
     );
+    // RFC 7641 §3.4: Notifications after a reconnect stay fresh
+    if let Some(sequence) = connections.take_sequence(&validated) {
+        obs.sequence = sequence;
+    }
+    let mapped = mapped.with_tags(peer.client_tags(&validated).await);
+    if let Some(record) = connections.take_restored(&validated).await {
+        restore_observations(record, &mapped, router, obs_tx, obs, config).await;
+    }
+    Some((validated, mapped))
+}
 
-    let mut dtls = Dtls::new_12_psk(dimpl_config, Instant::now());
-    let mut out_buf = vec![0u8; 2048];
-    let mut connected = false;
+/// Run a connection until it ends.
+///
+/// Handles the peer's messages, sends it notifications and
+/// [`DeviceClient`](crate::device_client::DeviceClient) requests, pings it
+/// when it falls silent and ends the connection on idle timeout. DTLS
+/// sessions and [transport](crate::transport::Transport) peers both run
+/// here; their differences stay behind [`Peer`].
+pub(crate) async fn run_connection<O, S>(
+    peer: &mut impl Peer,
+    mut router: CoapRouter<O, S>,
+    config: &Config,
+    connections: &ConnectionRegistry,
+) where
+    S: Debug + Clone + Send + Sync + 'static,
+    O: Observer + Send + Sync + 'static,
+{
     let mut identity: Option<String> = None;
+    let mut session: Option<Session> = None;
 
-    let (obs_tx, mut obs_rx) = channel::<ObserverValue>(10);
+    // Backed-up notifications go out by the priority of their route
+    let (obs_tx, mut obs_rx) = prioritized_channel(
+        config.notification_queue_size.max(1),
+        config.notification_overflow,
+        router.priorities().clone(),
+    );
     let obs_tx = Arc::new(obs_tx);
-    let mut obs = ObserveState::new();
-    let mut reliability = ReliabilityState::new(RetransmitParams::from_config(&config));
+    let mut obs = ObserveState::new(connections.notifications.clone());
+    let mut reliability = ReliabilityState::new(RetransmitParams::from_config(config));
+    let stats = Arc::new(ConnectionStats::new());
     let mut block_handler = BlockHandler::new(BlockHandlerConfig {
         max_total_message_size: config.max_message_size,
         cache_expiry_duration: config.block_cache_expiry,
     });
 
-    let (disconnect_tx, mut disconnect_rx) = channel::<()>(1);
+    let (disconnect_tx, mut disconnect_rx) = channel::<DisconnectReason>(1);
+    let (request_tx, mut request_rx) = channel::<DeviceRequest>(DEVICE_REQUEST_QUEUE);
+    let mut pending = PendingRequests::new();
     let timeout_duration = Duration::from_secs(config.timeout);
+    let mut published_revision = 0;
+
+    // Keepalive: the device is pinged once it has been silent for the
+    // interval, and the connection ends if the ping is never answered
+    let mut keepalive_at = config
+        .keepalive_interval
+        .map(|interval| tokio::time::Instant::now() + interval);
+    let mut ping: Option<u16> = None;
+
+    // A panic ends the connection like any other error, so the cleanup
+    // below still runs
+    let outcome = AssertUnwindSafe(async {
+        'connection: loop {
+            let connected = session.is_some();
+            let idle_timeout = tokio::time::sleep(timeout_duration);
+            tokio::pin!(idle_timeout);
+
+            tokio::select! {
+                // Record from the peer
+                record = peer.recv() => {
+                    let Some(record) = record else {
+                        break;
+                    };
+                    let inbound = match peer.open(record).await {
+                        Opened::Record(inbound) => inbound,
+                        Opened::Ignored => continue,
+                        Opened::Closed => break,
+                    };
+                    stats.touch();
+                    if let Some(interval) = config.keepalive_interval {
+                        keepalive_at = Some(tokio::time::Instant::now() + interval);
+                    }
+                    // Any authenticated record shows the device is still there
+                    if let Some(msg_id) = ping.take() {
+                        reliability.handle_ack(msg_id);
+                    }
 
-    // One-shot session lifetime timer (DTLS 1.2 key wear-out mitigation).
-    // Created once before the loop so it is NOT reset on activity.
-    let session_deadline = config.max_session_lifetime.map(tokio::time::sleep);
-    tokio::pin!(session_deadline);
+                    for inbound in inbound {
+                        match inbound {
+                            Inbound::Authenticated(raw) => {
+                                let Some((validated, mapped)) = accept_peer(
+                                    &raw, peer, &mut router, &obs_tx, &mut obs, &stats,
+                                    &disconnect_tx, &request_tx, connections, config,
+                                ).await else {
+                                    break 'connection;
+                                };
+                                session = Some(mapped);
+                                identity = Some(validated);
+                            }
+                            Inbound::Migrated(to) => {
+                                if let Some(identity) = identity.as_deref() {
+                                    connections.migrate(identity, &stats, to).await;
+                                }
+                            }
+                            Inbound::Message(packet) => {
+                                if let Some(session) = session.as_ref() {
+                                    handle_message(
+                                        packet, &mut peer.link(), session, &mut router, &obs_tx,
+                                        &mut obs, &mut block_handler, &mut pending, config,
+                                        &mut reliability,
+                                    ).await;
+                                }
+                            }
+                        }
+                    }
+                    if let Some(ref liveness) = config.liveness
+                        && let Some(ref identity) = identity
+                    {
+                        liveness.touch(identity);
+                    }
+                }
 
-    loop {
-        // Compute next DTLS retransmit deadline
-        let dtls_timeout = tokio::time::sleep(timeout_duration);
-        tokio::pin!(dtls_timeout);
+                // Observer notification
+                value = obs_rx.recv(), if connected => {
+                    let Some(value) = value else {
+                        tracing::warn!(
+                            addr = %peer.link().remote(),
+                            identity = ?identity,
+                            dropped = obs_rx.dropped(),
+                            "connection.notification_overflow"
+                        );
+                        break;
+                    };
+                    let span = tracing::debug_span!("notification", path = %value.path);
+                    handle_notification(
+                        value, &mut router, &mut peer.link(), session.as_ref(), &mut obs,
+                        &mut block_handler, config.mtu, &mut reliability,
+                    ).instrument(span).await;
+                }
 
-        tokio::select! {
-            // Incoming DTLS packet from dispatch
-            packet = packet_rx.recv() => {
-                let Some(raw) = packet else {
-                    // Channel closed — dispatch removed us
-                    tracing::debug!(addr = %remote, "connection.channel_closed");
-                    break;
-                };
+                // Request to the device from a DeviceClient
+                Some(request) = request_rx.recv(), if connected => {
+                    send_device_request(
+                        request, &mut peer.link(), &mut obs.ids, &mut pending, &mut reliability,
+                    ).await;
+                }
 
-                if let Err(e) = dtls.handle_packet(&raw) {
-                    tracing::error!(addr = %remote, error = %e, "dtls.packet_error");
-                    break;
+                // Streamed notifications, paced so one trigger can't flood the peer
+                Some((path, resp)) = async {
+                    tokio::time::sleep_until(obs.next_stream_at).await;
+                    next_stream_item(&mut obs.streams).await
+                }, if connected && !obs.streams.is_empty() => {
+                    send_notification(
+                        resp, path, &router, &mut peer.link(), &mut obs, &mut block_handler,
+                        config.mtu, &mut reliability,
+                    ).await;
+                    obs.next_stream_at = tokio::time::Instant::now() + config.notification_stream_pacing;
                 }
 
-                if !process_outputs(
-                    &mut dtls, &mut out_buf, &socket, remote,
-                    &resolver, &mut connected, &mut identity,
-                    &mut router, &obs_tx, &mut obs, &mut block_handler,
-                    config.max_observers_per_device,
-                    &connections, disconnect_tx.clone(), &config,
-                    &mut reliability,
-                ).await {
+                // Disconnect signal
+                reason = disconnect_rx.recv() => {
+                    let addr = peer.link().remote();
+                    if reason == Some(DisconnectReason::TakenOver) {
+                        tracing::info!(addr = %addr, identity = ?identity, "connection.evicted");
+                        end_observations(
+                            &router, &mut peer.link(), &mut obs, &mut block_handler, config.mtu,
+                            &mut reliability,
+                        ).await;
+                    } else {
+                        tracing::info!(addr = %addr, identity = ?identity, "connection.terminating");
+                    }
                     break;
                 }
-            }
-
-            // Observer notification
-            Some(value) = obs_rx.recv(), if connected => {
-                handle_notification(
-                    value, &mut router, &mut dtls, &mut out_buf,
-                    &socket, remote, &mut obs, &mut block_handler,
-                    &mut reliability,
-                ).await;
-            }
-
-            // Disconnect signal
-            _ = disconnect_rx.recv() => {
-                tracing::info!(addr = %remote, identity = ?identity, "connection.terminating");
-                break;
-            }
-
-            // Idle timeout
-            () = &mut dtls_timeout => {
-                tracing::info!(addr = %remote, "connection.timeout");
-                break;
-            }
 
-            // Session lifetime limit (DTLS 1.2 key wear-out mitigation)
-            Some(()) = async {
-                match session_deadline.as_mut().as_pin_mut() {
-                    Some(f) => { f.await; Some(()) }
-                    None => std::future::pending().await,
+                // Keepalive ping to a silent device
+                () = async {
+                    match keepalive_at {
+                        Some(at) => tokio::time::sleep_until(at).await,
+                        None => std::future::pending::<()>().await,
+                    }
+                }, if connected && ping.is_none() => {
+                    ping = send_ping(&mut peer.link(), &mut obs.ids, &mut reliability).await;
+                    // A ping that could not be sent is retried an interval later
+                    if let Some(interval) = config.keepalive_interval {
+                        keepalive_at = Some(tokio::time::Instant::now() + interval);
+                    }
                 }
-            } => {
-                tracing::info!(
-                    addr = %remote,
-                    identity = ?identity,
-                    "connection.session_lifetime_exceeded"
-                );
-                break;
-            }
 
-            // RFC 7252 §4.2: CON retransmission timer
-            () = async {
-                match reliability.next_retransmit_deadline() {
-                    Some(d) => tokio::time::sleep_until(d).await,
-                    None => std::future::pending::<()>().await,
+                // Idle timeout
+                () = &mut idle_timeout => {
+                    tracing::info!(addr = %peer.link().remote(), identity = ?identity, "connection.timeout");
+                    break;
                 }
-            } => {
-                for action in reliability.process_retransmits() {
-                    match action {
-                        RetransmitAction::Resend { msg_id, ref bytes } => {
-                            tracing::debug!(msg_id, "reliability.retransmit");
-                            if let Err(e) = dtls.send_application_data(bytes) {
-                                tracing::error!(error = %e, "reliability.retransmit.send_failed");
-                                continue;
-                            }
-                            drain_packets(&mut dtls, &mut out_buf, &socket, remote).await;
-                        }
-                        RetransmitAction::GiveUp { msg_id } => {
-                            tracing::warn!(msg_id, "reliability.give_up");
-                            if let Some(path) = obs.notification_msg_ids.remove(&msg_id)
-                                && let Some(ref id) = identity
-                            {
-                                obs.observer_tokens.remove(&path);
-                                let _ = router.unregister_observer(id, &path).await;
-                                tracing::info!(identity = %id, path = %path, "reliability.observer_deregistered");
-                            }
-                        }
+
+                // RFC 7252 §4.2: CON retransmission timer
+                () = async {
+                    match reliability.next_retransmit_deadline() {
+                        Some(d) => tokio::time::sleep_until(d).await,
+                        None => std::future::pending::<()>().await,
+                    }
+                } => {
+                    if !retransmit(
+                        &mut peer.link(), &mut reliability, &mut pending, &mut obs,
+                        session.as_ref(), &mut router, &obs_tx, ping,
+                    ).await {
+                        break;
                     }
                 }
             }
-        }
 
-        // Drive DTLS retransmit timers after every event
-        if let Err(e) = dtls.handle_timeout(Instant::now()) {
-            tracing::error!(addr = %remote, error = %e, "dtls.timeout_error");
-            break;
+            stats.set_observations(obs.observations.len());
+            stats.set_observe_sequence(obs.sequence);
+            if obs.revision != published_revision {
+                published_revision = obs.revision;
+                stats.set_observed(obs.observed());
+            }
+            let dropped = stats.set_notifications_dropped(obs_rx.dropped());
+            connections.notifications.record_dropped(dropped);
+
+            if !peer.flush().await {
+                break;
+            }
         }
-        drain_packets(&mut dtls, &mut out_buf, &socket, remote).await;
+    })
+    .catch_unwind()
+    .await;
+    let remote = peer.link().remote();
+    if let Err(panic) = outcome {
+        connections.tasks.record_panic();
+        let message = panic
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown");
+        tracing::error!(
+            addr = %remote,
+            identity = ?identity,
+            panic = %message,
+            "connection.panicked"
+        );
     }
 
-    // Cleanup
-    conn_count.fetch_sub(1, Ordering::Relaxed);
     if let Some(ref id) = identity {
-        connections.lock().await.remove(id);
-        let _ = router.unregister_device(id).await;
+        connections.remove(id, &stats).await;
         tracing::info!(identity = %id, addr = %remote, "connection.terminated");
     }
-    let _ = cleanup_tx.send(remote).await;
+    // Observers are registered under the device ID
+    if let Some(ref session) = session {
+        let _ = router
+            .unregister_connection(session.identity(), &obs_tx)
+            .await;
+    }
+}
+
+/// Per-connection task of the DTLS server.
+#[allow(clippy::too_many_arguments)]
+async fn connection_task<O, S, C>(
+    remote: SocketAddr,
+    packet_rx: mpsc::Receiver<Datagram>,
+    socket: Arc<UdpSocket>,
+    credential_store: C,
+    psk_identity_hint: Option<Vec<u8>>,
+    router: CoapRouter<O, S>,
+    config: Config,
+    connections: ConnectionRegistry,
+    conn_count: Arc<AtomicUsize>,
+    handshake_slot: HandshakeSlot,
+    cleanup_tx: mpsc::Sender<DispatchUpdate>,
+) where
+    S: Debug + Clone + Send + Sync + 'static,
+    O: Observer + Send + Sync + 'static,
+    C: CredentialStore,
+{
+    let mut peer = DtlsPeer::new(
+        remote,
+        packet_rx,
+        socket,
+        credential_store,
+        psk_identity_hint,
+        &config,
+        connections.clone(),
+        handshake_slot,
+        cleanup_tx.clone(),
+    );
+    run_connection(&mut peer, router, &config, &connections).await;
+
+    conn_count.fetch_sub(1, Ordering::Relaxed);
+    let _ = cleanup_tx.send(DispatchUpdate::Closed(peer.remote)).await;
 }
 
 /// Start basic CoAP server with quinn-style dispatch + per-connection tasks.
@@ -870,44 +2215,257 @@ pub async fn serve_basic<O, S, C>(
     router: CoapRouter<O, S>,
     credential_store: C,
     psk_identity_hint: Option<Vec<u8>>,
-    mut disconnect_rx: Option<mpsc::Receiver<String>>,
+    disconnect_rx: Option<mpsc::Receiver<String>>,
 ) -> Result<(), Box<dyn std::error::Error>>
 where
     S: Debug + Clone + Send + Sync + 'static,
     O: Observer + Send + Sync + 'static,
     C: CredentialStore,
 {
-    let socket = Arc::new(UdpSocket::bind(&addr).await?);
-    tracing::info!(addr = %addr, "server.started");
+    let socket = UdpSocket::bind(&addr).await?;
+    serve_listeners(
+        vec![socket],
+        config,
+        router,
+        credential_store,
+        psk_identity_hint,
+        disconnect_rx,
+    )
+    .await
+}
+
+/// Bind a UDP socket for each address, see [`serve_with_listeners`].
+///
+/// Binding an IPv6 wildcard address usually accepts IPv4 traffic as well,
+/// which then conflicts with a listener on the same port for IPv4. When
+/// `addrs` mixes IPv4 and IPv6 addresses, the IPv6 sockets are therefore
+/// restricted to IPv6, so `0.0.0.0:5684` and `[::]:5684` can be bound side
+/// by side.
+///
+/// Must be called from within a Tokio runtime.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use coapum::serve::bind_listeners;
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let listeners = bind_listeners(&["0.0.0.0:5684".parse()?, "[::]:5684".parse()?])?;
+/// assert_eq!(listeners.len(), 2);
+/// # Ok(())
+/// # }
+/// ```
+pub fn bind_listeners(addrs: &[SocketAddr]) -> std::io::Result<Vec<UdpSocket>> {
+    let dual_stack = addrs.iter().any(SocketAddr::is_ipv4) && addrs.iter().any(SocketAddr::is_ipv6);
+    addrs
+        .iter()
+        .map(|addr| {
+            let socket = Socket::new(Domain::for_address(*addr), Type::DGRAM, Some(Protocol::UDP))?;
+            if dual_stack && addr.is_ipv6() {
+                socket.set_only_v6(true)?;
+            }
+            socket.set_nonblocking(true)?;
+            socket.bind(&(*addr).into())?;
+            UdpSocket::from_std(socket.into())
+        })
+        .collect()
+}
+
+/// How long shutdown waits for connection tasks to clean up before aborting
+/// them.
+pub(crate) const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// The address a peer's connection is keyed by.
+///
+/// IPv6 addresses keep their scope ID, so devices sharing a link-local
+/// address behind different interfaces get separate connections, and replies
+/// leave through the right interface. The flow label is cleared: it may
+/// differ between datagrams of the same peer.
+fn peer_addr(remote: SocketAddr) -> SocketAddr {
+    match remote {
+        SocketAddr::V6(mut v6) => {
+            v6.set_flowinfo(0);
+            SocketAddr::V6(v6)
+        }
+        v4 => v4,
+    }
+}
+
+/// Receive a datagram from whichever listener has one ready.
+///
+/// Returns the datagram's length and source, see [`peer_addr`], along with
+/// the index of the listener. Listeners are polled round-robin, starting
+/// after the one that was read last, so a busy listener can't starve the
+/// others. Cancel safe.
+async fn recv_from_any(
+    listeners: &[Arc<UdpSocket>],
+    buf: &mut [u8],
+    next: &mut usize,
+) -> std::io::Result<(usize, SocketAddr, usize)> {
+    std::future::poll_fn(|cx| {
+        for offset in 0..listeners.len() {
+            let index = (*next + offset) % listeners.len();
+            let mut read = ReadBuf::new(buf);
+            if let Poll::Ready(result) = listeners[index].poll_recv_from(cx, &mut read) {
+                *next = (index + 1) % listeners.len();
+                return Poll::Ready(
+                    result.map(|remote| (read.filled().len(), peer_addr(remote), index)),
+                );
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// The dispatch loop of [`serve_basic`], serving every listener.
+///
+/// The router's state updates stop with the server, however it exits.
+async fn serve_listeners<O, S, C>(
+    listeners: Vec<UdpSocket>,
+    config: Config,
+    router: CoapRouter<O, S>,
+    credential_store: C,
+    psk_identity_hint: Option<Vec<u8>>,
+    disconnect_rx: Option<mpsc::Receiver<String>>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: Debug + Clone + Send + Sync + 'static,
+    O: Observer + Send + Sync + 'static,
+    C: CredentialStore,
+{
+    let state_updates = router.clone();
+    let result = dispatch_listeners(
+        listeners,
+        config,
+        router,
+        credential_store,
+        psk_identity_hint,
+        disconnect_rx,
+    )
+    .await;
+    state_updates.shutdown_state_updates().await;
+    result.map_err(|e| e as Box<dyn std::error::Error>)
+}
+
+async fn dispatch_listeners<O, S, C>(
+    listeners: Vec<UdpSocket>,
+    config: Config,
+    mut router: CoapRouter<O, S>,
+    credential_store: C,
+    psk_identity_hint: Option<Vec<u8>>,
+    mut disconnect_rx: Option<mpsc::Receiver<String>>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    S: Debug + Clone + Send + Sync + 'static,
+    O: Observer + Send + Sync + 'static,
+    C: CredentialStore,
+{
+    config.validate()?;
+    if listeners.is_empty() {
+        return Err("no listeners to serve".into());
+    }
+    let listeners: Vec<Arc<UdpSocket>> = listeners.into_iter().map(Arc::new).collect();
+    for listener in &listeners {
+        tracing::info!(addr = %listener.local_addr()?, "server.started");
+    }
+    let mut next_listener = 0;
+    if let Some(ref compression) = config.compression {
+        router.recognize_option(compression.coding_option);
+    }
 
-    let connections: Arc<Mutex<HashMap<String, ConnectionInfo>>> =
-        Arc::new(Mutex::new(HashMap::new()));
+    let connections = config.connection_registry.clone().unwrap_or_default();
+    if let Some(ref snapshot) = config.snapshot {
+        restore_snapshot(snapshot, &credential_store, &connections).await;
+    }
     let active_connections = Arc::new(AtomicUsize::new(0));
     let max_connections = config.max_connections;
+    let handshakes = connections.handshakes.clone();
+    let max_handshakes = config.max_concurrent_handshakes;
+    let mut accept_queue = AcceptQueue::new(config.accept_queue_size, config.handshake_timeout);
+    // Takeovers of local connections requested by other nodes
+    let mut takeover_rx = connections.tracker.subscribe_takeovers().await?;
     let mut shutdown_rx = config.shutdown.clone();
 
-    // Dispatch table: SocketAddr → per-connection packet sender
-    let mut dispatch: HashMap<SocketAddr, mpsc::Sender<Vec<u8>>> = HashMap::new();
+    // Dispatch table: SocketAddr → per-connection packet sender. A connection
+    // answers through the listener its first datagram arrived on.
+    let mut dispatch: HashMap<SocketAddr, mpsc::Sender<Datagram>> = HashMap::new();
 
-    // Cleanup channel: connection tasks notify dispatch when they exit
-    let (cleanup_tx, mut cleanup_rx) = mpsc::channel::<SocketAddr>(64);
+    // Cleanup channel: connection tasks notify dispatch when they exit or move
+    let (cleanup_tx, mut cleanup_rx) = mpsc::channel::<DispatchUpdate>(64);
+
+    // Connection tasks end with the server: dropping the set aborts them
+    let mut tasks = JoinSet::new();
 
     let mut recv_buf = vec![0u8; config.buffer_size()];
 
+    let spawn_connection = |remote: SocketAddr,
+                            listener: usize,
+                            datagram: Vec<u8>,
+                            dispatch: &mut HashMap<SocketAddr, mpsc::Sender<Datagram>>,
+                            tasks: &mut JoinSet<()>| {
+        let (tx, rx) = mpsc::channel(256);
+        let _ = tx.try_send(Datagram {
+            from: remote,
+            data: datagram,
+        });
+        dispatch.insert(remote, tx);
+
+        active_connections.fetch_add(1, Ordering::Relaxed);
+        let handshake_slot = HandshakeSlot::acquire(&handshakes);
+
+        let socket = listeners[listener].clone();
+        let store = credential_store.clone();
+        let hint = psk_identity_hint.clone();
+        let router = router.clone();
+        let config = config.clone();
+        let registry = connections.clone();
+        let conn_count = active_connections.clone();
+        let cleanup_tx = cleanup_tx.clone();
+
+        tasks.spawn(async move {
+            connection_task(
+                remote,
+                rx,
+                socket,
+                store,
+                hint,
+                router,
+                config,
+                registry,
+                conn_count,
+                handshake_slot,
+                cleanup_tx,
+            )
+            .await;
+        });
+        connections.tasks.set_running(tasks.len());
+    };
+
     loop {
         // Drain completed connections
-        while let Ok(remote) = cleanup_rx.try_recv() {
-            dispatch.remove(&remote);
+        let mut closed = false;
+        while let Ok(update) = cleanup_rx.try_recv() {
+            match update {
+                DispatchUpdate::Closed(remote) => {
+                    dispatch.remove(&remote);
+                    closed = true;
+                }
+                DispatchUpdate::Migrated { from, to } => {
+                    if let Some(tx) = dispatch.remove(&from) {
+                        dispatch.entry(to).or_insert(tx);
+                    }
+                }
+            }
+        }
+        // A closed connection may unblock peers queued behind `max_connections`
+        if closed && !accept_queue.is_empty() {
+            handshakes.freed.notify_one();
         }
 
         // Drain disconnect commands
         if let Some(ref mut rx) = disconnect_rx {
             while let Ok(identity) = rx.try_recv() {
-                let cons = connections.lock().await;
-                if let Some(info) = cons.get(&identity) {
-                    let _ = info.sender.send(()).await;
-                    tracing::info!(identity = %identity, "client.disconnected");
-                }
+                connections.disconnect(&identity).await;
             }
         }
 
@@ -920,16 +2478,55 @@ where
                 }
             } => {
                 tracing::info!("Shutdown signal received, stopping server");
+                if let Some(ref snapshot) = config.snapshot {
+                    save_snapshot(snapshot, &credential_store, &connections).await;
+                }
+
+                // Closing their packet channels ends the connections
+                dispatch.clear();
+                cleanup_rx.close();
+                let drained = tokio::time::timeout(SHUTDOWN_GRACE, async {
+                    while tasks.join_next().await.is_some() {}
+                })
+                .await;
+                if drained.is_err() {
+                    tracing::warn!(remaining = tasks.len(), "server.shutdown.aborting_connections");
+                    tasks.shutdown().await;
+                }
+                connections.tasks.set_running(0);
                 return Ok(());
             }
 
             // Incoming UDP packet
-            result = socket.recv_from(&mut recv_buf) => {
-                let (n, remote) = result?;
+            result = recv_from_any(&listeners, &mut recv_buf, &mut next_listener) => {
+                let (n, remote, listener) = result?;
 
                 if let Some(tx) = dispatch.get(&remote) {
                     // Fast path: known connection
-                    let _ = tx.try_send(recv_buf[..n].to_vec());
+                    let _ = tx.try_send(Datagram {
+                        from: remote,
+                        data: recv_buf[..n].to_vec(),
+                    });
+                } else if config.address_migration
+                    && is_application_data(&recv_buf[..n])
+                {
+                    // Not a handshake: a known peer whose NAT binding changed.
+                    // The connections of the same host try to authenticate it.
+                    let candidates = dispatch
+                        .iter()
+                        .filter(|(addr, _)| addr.ip() == remote.ip())
+                        .take(MAX_MIGRATION_CANDIDATES);
+                    let mut offered = false;
+                    for (_, tx) in candidates {
+                        offered = true;
+                        let _ = tx.try_send(Datagram {
+                            from: remote,
+                            data: recv_buf[..n].to_vec(),
+                        });
+                    }
+                    if !offered {
+                        tracing::debug!(addr = %remote, "connection.unknown_record");
+                    }
                 } else {
                     // New connection
                     if active_connections.load(Ordering::Relaxed) >= max_connections {
@@ -941,40 +2538,159 @@ where
                         continue;
                     }
 
-                    tracing::debug!(addr = %remote, "connection.incoming");
-
-                    let (tx, rx) = mpsc::channel(256);
-                    let _ = tx.try_send(recv_buf[..n].to_vec());
-                    dispatch.insert(remote, tx);
+                    if handshakes.in_progress() < max_handshakes {
+                        tracing::debug!(addr = %remote, "connection.incoming");
+                        accept_queue.remove(&remote);
+                        spawn_connection(
+                            remote,
+                            listener,
+                            recv_buf[..n].to_vec(),
+                            &mut dispatch,
+                            &mut tasks,
+                        );
+                    } else {
+                        match accept_queue.offer(remote, listener, recv_buf[..n].to_vec()) {
+                            Enqueued::Queued => {
+                                tracing::debug!(addr = %remote, "connection.queued");
+                            }
+                            Enqueued::Refreshed => {}
+                            Enqueued::Full => {
+                                handshakes.record_dropped();
+                                tracing::warn!(
+                                    addr = %remote,
+                                    limit = max_handshakes,
+                                    queued = accept_queue.len(),
+                                    "connection.rejected.handshake_limit"
+                                );
+                            }
+                        }
+                    }
+                    handshakes.set_queued(accept_queue.len());
+                }
+            }
 
-                    active_connections.fetch_add(1, Ordering::Relaxed);
+            // A connection task ended
+            Some(result) = tasks.join_next(), if !tasks.is_empty() => {
+                // Panics in the connection itself are caught by the task
+                if let Err(e) = result
+                    && e.is_panic()
+                {
+                    connections.tasks.record_panic();
+                    tracing::error!(error = %e, "connection.task_panicked");
+                }
+                connections.tasks.set_running(tasks.len());
+            }
 
-                    let socket = socket.clone();
-                    let store = credential_store.clone();
-                    let hint = psk_identity_hint.clone();
-                    let router = router.clone();
-                    let config = config.clone();
-                    let connections = connections.clone();
-                    let conn_count = active_connections.clone();
-                    let cleanup_tx = cleanup_tx.clone();
+            Some(takeover) = takeover_rx.recv() => {
+                connections.evict(&takeover.identity, takeover.connection_id).await;
+            }
 
-                    tokio::spawn(async move {
-                        connection_task(
-                            remote, rx, socket, store,
-                            hint, router, config, connections,
-                            conn_count, cleanup_tx,
-                        ).await;
-                    });
+            // A handshake slot was released: admit queued peers
+            () = handshakes.freed.notified(), if !accept_queue.is_empty() => {
+                while handshakes.in_progress() < max_handshakes
+                    && active_connections.load(Ordering::Relaxed) < max_connections
+                {
+                    let Some((remote, listener, datagram)) = accept_queue.pop() else {
+                        break;
+                    };
+                    if dispatch.contains_key(&remote) {
+                        continue;
+                    }
+                    tracing::debug!(addr = %remote, "connection.dequeued");
+                    spawn_connection(remote, listener, datagram, &mut dispatch, &mut tasks);
                 }
+                handshakes.set_queued(accept_queue.len());
+            }
+        }
+    }
+}
+
+/// Restore the state saved by [`save_snapshot`].
+async fn restore_snapshot<C: CredentialStore>(
+    config: &SnapshotConfig,
+    store: &C,
+    connections: &ConnectionRegistry,
+) {
+    let snapshot = match config.load().await {
+        Ok(Some(snapshot)) => snapshot,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::error!(path = %config.path().display(), error = %e, "snapshot.load_failed");
+            return;
+        }
+    };
+
+    let age = snapshot.age();
+    let mut clients = 0;
+    if config.clients {
+        for (identity, entry) in snapshot.clients {
+            // The store's own clients take precedence
+            if !matches!(store.lookup_psk(&identity), Ok(None)) {
+                continue;
+            }
+            match store
+                .add_client(&identity, entry.key, Some(entry.metadata))
+                .await
+            {
+                Ok(()) => clients += 1,
+                Err(e) => tracing::error!("Failed to restore client {}: {:?}", identity, e),
+            }
+        }
+    }
+
+    let restored = if age < config.max_age {
+        let count = snapshot.connections.len();
+        connections.restore(snapshot.connections, config.max_age - age);
+        count
+    } else {
+        0
+    };
+    tracing::info!(
+        path = %config.path().display(),
+        age_secs = age.as_secs(),
+        clients,
+        connections = restored,
+        "snapshot.restored"
+    );
+}
+
+/// Save the established connections and, unless left out, the clients.
+async fn save_snapshot<C: CredentialStore>(
+    config: &SnapshotConfig,
+    store: &C,
+    connections: &ConnectionRegistry,
+) {
+    let mut snapshot = StateSnapshot::new();
+    snapshot.connections = connections.records().await;
+    if config.clients {
+        match export_entries(store).await {
+            Ok(entries) => snapshot.clients = entries.into_iter().collect(),
+            Err(e) => {
+                // Saving would replace the last snapshot with fewer clients
+                tracing::error!(path = %config.path().display(), error = %e, "snapshot.save_failed");
+                return;
             }
         }
     }
+    match config.save(&snapshot).await {
+        Ok(()) => tracing::info!(
+            path = %config.path().display(),
+            connections = snapshot.connections.len(),
+            clients = snapshot.clients.len(),
+            "snapshot.saved"
+        ),
+        Err(e) => {
+            tracing::error!(path = %config.path().display(), error = %e, "snapshot.save_failed")
+        }
+    }
 }
 
 /// Start a basic CoAP server without client management.
 ///
 /// Requires `config.dimpl_cfg` to be set with a valid dimpl configuration
-/// including a PSK resolver.
+/// including a PSK resolver, and fails with
+/// [`ConfigError::MissingDtlsConfig`] otherwise. Like every `serve` function,
+/// it also checks the configuration with [`Config::validate`].
 ///
 /// # Example
 ///
@@ -1003,10 +2719,7 @@ where
     O: Observer + Send + Sync + 'static,
 {
     if config.dimpl_cfg.is_none() {
-        return Err(
-            "DTLS config not set. Set config.dimpl_cfg or use serve_with_credential_store()."
-                .into(),
-        );
+        return Err(ConfigError::MissingDtlsConfig.into());
     }
 
     // Create a no-op store for the basic serve case (identity captured by user's resolver)
@@ -1056,6 +2769,57 @@ where
     serve_basic(addr, config, router, credential_store, hint, None).await
 }
 
+/// Start a CoAP server on already bound sockets.
+///
+/// Serves every listener with one set of connections and limits, e.g. an
+/// IPv4 and an IPv6 socket bound with [`bind_listeners`], or sockets inherited
+/// from a service manager such as systemd socket activation. Listeners must
+/// be in non-blocking mode; connections are answered through the listener
+/// they arrived on.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use coapum::{RouterBuilder, observer::memory::MemObserver, config::Config};
+/// # use coapum::credential::memory::MemoryCredentialStore;
+/// # use coapum::serve::serve_with_listeners;
+/// # use tokio::net::UdpSocket;
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// # #[derive(Clone, Debug)]
+/// # struct AppState {}
+/// # let state = AppState {};
+/// # let observer = MemObserver::new();
+/// # let router = RouterBuilder::new(state, observer).build();
+/// # #[cfg(unix)]
+/// # {
+/// use std::os::fd::FromRawFd;
+///
+/// // systemd passes the first socket as file descriptor 3
+/// let socket = unsafe { std::net::UdpSocket::from_raw_fd(3) };
+/// socket.set_nonblocking(true)?;
+/// let listener = UdpSocket::from_std(socket)?;
+///
+/// let credentials = MemoryCredentialStore::new();
+/// serve_with_listeners(vec![listener], Config::default(), router, credentials).await?;
+/// # }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn serve_with_listeners<O, S, C>(
+    listeners: Vec<UdpSocket>,
+    config: Config,
+    router: CoapRouter<O, S>,
+    credential_store: C,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: Debug + Clone + Send + Sync + 'static,
+    O: Observer + Send + Sync + 'static,
+    C: CredentialStore,
+{
+    let hint = config.psk_identity_hint.clone();
+    serve_listeners(listeners, config, router, credential_store, hint, None).await
+}
+
 /// Start a CoAP server with dynamic client management capability.
 ///
 /// # Example
@@ -1116,27 +2880,31 @@ where
 
     let credential_store = MemoryCredentialStore::from_clients(initial_clients);
 
-    let (cmd_sender, mut cmd_receiver) = mpsc::channel(config.client_command_buffer);
+    let (cmd_sender, cmd_receiver) = mpsc::channel(config.client_command_buffer);
     let client_manager = ClientManager::new(cmd_sender);
 
     let (disconnect_tx, disconnect_rx) = mpsc::channel::<String>(32);
 
-    let store_for_processor = credential_store.clone();
-    tokio::spawn(async move {
-        while let Some(cmd) = cmd_receiver.recv().await {
-            process_client_command(cmd, &store_for_processor, &disconnect_tx).await;
-        }
-    });
+    let processor =
+        spawn_client_command_processor(cmd_receiver, credential_store.clone(), disconnect_tx);
 
     let hint = config.psk_identity_hint.clone();
-    let server_future = serve_basic(
-        addr,
-        config,
-        router,
-        credential_store,
-        hint,
-        Some(disconnect_rx),
-    );
+    let server_future = async move {
+        let result = serve_basic(
+            addr,
+            config,
+            router,
+            credential_store,
+            hint,
+            Some(disconnect_rx),
+        )
+        .await;
+        // The processor lives exactly as long as the server. Dropping the
+        // handle stops it without holding the non-`Send` result across an
+        // await point.
+        drop(processor);
+        result
+    };
 
     Ok((client_manager, server_future))
 }
@@ -1190,31 +2958,57 @@ where
     O: Observer + Send + Sync + 'static,
     C: CredentialStore,
 {
-    let (cmd_sender, mut cmd_receiver) = mpsc::channel(config.client_command_buffer);
+    let (cmd_sender, cmd_receiver) = mpsc::channel(config.client_command_buffer);
     let client_manager = ClientManager::new(cmd_sender);
 
     let (disconnect_tx, disconnect_rx) = mpsc::channel::<String>(32);
 
-    let store_for_processor = credential_store.clone();
-    tokio::spawn(async move {
-        while let Some(cmd) = cmd_receiver.recv().await {
-            process_client_command(cmd, &store_for_processor, &disconnect_tx).await;
-        }
-    });
+    let processor =
+        spawn_client_command_processor(cmd_receiver, credential_store.clone(), disconnect_tx);
 
     let hint = config.psk_identity_hint.clone();
-    let server_future = serve_basic(
-        addr,
-        config,
-        router,
-        credential_store,
-        hint,
-        Some(disconnect_rx),
-    );
+    let server_future = async move {
+        let result = serve_basic(
+            addr,
+            config,
+            router,
+            credential_store,
+            hint,
+            Some(disconnect_rx),
+        )
+        .await;
+        // The processor lives exactly as long as the server. Dropping the
+        // handle stops it without holding the non-`Send` result across an
+        // await point.
+        drop(processor);
+        result
+    };
 
     Ok((client_manager, server_future))
 }
 
+/// Spawn the task that applies [`ClientManager`] commands to a credential store.
+///
+/// The task exits when every `ClientManager` is dropped or when the returned
+/// handle is shut down or dropped.
+fn spawn_client_command_processor<C: CredentialStore>(
+    mut cmd_receiver: mpsc::Receiver<ClientCommand>,
+    store: C,
+    disconnect_tx: mpsc::Sender<String>,
+) -> BackgroundTask {
+    BackgroundTask::spawn(|mut stop| async move {
+        loop {
+            tokio::select! {
+                cmd = cmd_receiver.recv() => match cmd {
+                    Some(cmd) => process_client_command(cmd, &store, &disconnect_tx).await,
+                    None => break,
+                },
+                _ = &mut stop => break,
+            }
+        }
+    })
+}
+
 /// Process a client command by delegating to a credential store.
 async fn process_client_command<C: CredentialStore>(
     cmd: ClientCommand,
@@ -1265,13 +3059,59 @@ async fn process_client_command<C: CredentialStore>(
                 tracing::error!("Failed to send disconnect for {}: {}", identity, e);
             }
         }
+        ClientCommand::ExportClients { response } => {
+            let _ = response.send(export_entries(store).await);
+        }
+    }
+}
+
+/// Every client of a credential store with its key and metadata.
+///
+/// Fails on the first store error, so an export never silently leaves out
+/// clients.
+async fn export_entries<C: CredentialStore>(
+    store: &C,
+) -> Result<Vec<(String, ClientEntry)>, String> {
+    let identities = store
+        .list_clients()
+        .await
+        .map_err(|e| format!("failed to list clients: {:?}", e))?;
+
+    let mut entries = Vec::with_capacity(identities.len());
+    for identity in identities {
+        let psk = match store.lookup_psk(&identity) {
+            Ok(Some(psk)) => psk,
+            Ok(None) => continue, // removed since listing
+            Err(e) => return Err(format!("failed to look up key for {}: {:?}", identity, e)),
+        };
+
+        // Stores without get_client still export their enabled flag
+        let mut metadata = match store.get_client(&identity).await {
+            Ok(Some(info)) => info.metadata,
+            Ok(None) => ClientMetadata::default(),
+            Err(e) => return Err(format!("failed to get metadata for {}: {:?}", identity, e)),
+        };
+        metadata.enabled = psk.enabled;
+
+        entries.push((
+            identity,
+            ClientEntry {
+                key: psk.key,
+                metadata,
+            },
+        ));
     }
+
+    Ok(entries)
 }
 
 /// Create a client manager connected to a credential store.
 ///
 /// This is useful when you want to manage clients from multiple places
-/// or integrate with existing authentication systems.
+/// or integrate with existing authentication systems. The command processor
+/// is detached and exits only once every clone of the returned manager is
+/// dropped.
+#[deprecated(note = "use `create_client_manager_with_task`, whose processor stops with its handle")]
 pub fn create_client_manager<C: CredentialStore>(
     credential_store: C,
     buffer_size: usize,
@@ -1290,3 +3130,671 @@ pub fn create_client_manager<C: CredentialStore>(
 
     ClientManager::new(cmd_sender)
 }
+
+/// Create a client manager along with the handle of its command processor.
+///
+/// Dropping or shutting down the [`BackgroundTask`] stops the processor;
+/// commands sent afterwards fail with a closed-channel error.
+pub fn create_client_manager_with_task<C: CredentialStore>(
+    credential_store: C,
+    buffer_size: usize,
+) -> (ClientManager, BackgroundTask) {
+    let (cmd_sender, cmd_receiver) = mpsc::channel(buffer_size);
+
+    // Create a no-op disconnect channel (standalone managers aren't wired to a server)
+    let (disconnect_tx, _disconnect_rx) = mpsc::channel::<String>(1);
+
+    let processor = spawn_client_command_processor(cmd_receiver, credential_store, disconnect_tx);
+
+    (ClientManager::new(cmd_sender), processor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RouterBuilder;
+    use crate::extract::Query;
+    use crate::identity::TenantSplit;
+    use crate::observer::memory::MemObserver;
+
+    fn packet(msg_type: MessageType, code: MessageClass) -> Packet {
+        let mut packet = Packet::new();
+        packet.header.set_type(msg_type);
+        packet.header.code = code;
+        packet
+    }
+
+    #[test]
+    fn test_classify_message() {
+        let get = MessageClass::Request(RequestType::Get);
+        let content = MessageClass::Response(ResponseType::Content);
+
+        assert_eq!(
+            classify_message(&packet(MessageType::Confirmable, MessageClass::Empty)),
+            Incoming::Reject
+        );
+        assert_eq!(
+            classify_message(&packet(MessageType::NonConfirmable, MessageClass::Empty)),
+            Incoming::Ignore
+        );
+        assert_eq!(
+            classify_message(&packet(MessageType::Confirmable, content)),
+            Incoming::Reject
+        );
+        assert_eq!(
+            classify_message(&packet(MessageType::NonConfirmable, content)),
+            Incoming::Ignore
+        );
+        assert_eq!(
+            classify_message(&packet(MessageType::Reset, MessageClass::Empty)),
+            Incoming::Reset
+        );
+        assert_eq!(
+            classify_message(&packet(MessageType::Acknowledgement, MessageClass::Empty)),
+            Incoming::Acknowledgement
+        );
+        assert_eq!(
+            classify_message(&packet(MessageType::Confirmable, get)),
+            Incoming::Request
+        );
+        assert_eq!(
+            classify_message(&packet(MessageType::NonConfirmable, get)),
+            Incoming::Request
+        );
+    }
+
+    #[test]
+    fn test_process_datagram() {
+        let source: SocketAddr = "127.0.0.1:5684".parse().unwrap();
+        let mut request: CoapRequest<SocketAddr> = CoapRequest::new();
+        request.set_method(RequestType::Get);
+        request.set_path("/sensors/temp");
+        request.set_observe_flag(ObserveOption::Register);
+        let bytes = request.message.to_bytes().unwrap();
+
+        let decoded = process_datagram(&bytes, source).unwrap();
+        assert_eq!(decoded.get_path(), "sensors/temp");
+        assert_eq!(*decoded.get_observe_flag(), Some(ObserveOption::Register));
+
+        // Requests with an invalid path are dropped, except for discovery
+        request.set_path("/sensors/te mp");
+        let bytes = request.message.to_bytes().unwrap();
+        assert!(process_datagram(&bytes, source).is_none());
+        let mut plain: CoapRequest<SocketAddr> = CoapRequest::new();
+        plain.set_method(RequestType::Post);
+        plain.set_path("/fw/app.bin");
+        assert!(process_datagram(&plain.message.to_bytes().unwrap(), source).is_none());
+        plain.set_method(RequestType::Get);
+        plain.set_path("/.well-known/core");
+        assert!(process_datagram(&plain.message.to_bytes().unwrap(), source).is_some());
+
+        // Only observations need a path
+        plain.set_path("");
+        assert!(process_datagram(&plain.message.to_bytes().unwrap(), source).is_some());
+        request.set_path("");
+        let root = request.message.to_bytes().unwrap();
+        assert!(process_datagram(&root, source).is_none());
+
+        // Messages the connection task answers without routing
+        let ack = packet(MessageType::Acknowledgement, MessageClass::Empty);
+        assert!(process_datagram(&ack.to_bytes().unwrap(), source).is_none());
+        let ping = packet(MessageType::Confirmable, MessageClass::Empty);
+        assert!(process_datagram(&ping.to_bytes().unwrap(), source).is_none());
+
+        // Malformed input
+        assert!(process_datagram(&[], source).is_none());
+        assert!(process_datagram(&bytes[..3], source).is_none());
+    }
+
+    #[test]
+    fn test_reset_for_ping() {
+        let rst = Packet::from_bytes(&reset_for(0xBEEF).to_bytes().unwrap()).unwrap();
+        assert_eq!(rst.header.get_type(), MessageType::Reset);
+        assert_eq!(rst.header.code, MessageClass::Empty);
+        assert_eq!(rst.header.message_id, 0xBEEF);
+        assert!(rst.get_token().is_empty());
+        assert!(rst.payload.is_empty());
+    }
+
+    #[test]
+    fn test_ping_with() {
+        let ping = Packet::from_bytes(&ping_with(0x0042).to_bytes().unwrap()).unwrap();
+        assert_eq!(ping.header.get_type(), MessageType::Confirmable);
+        assert_eq!(ping.header.code, MessageClass::Empty);
+        assert_eq!(ping.header.message_id, 0x0042);
+        assert!(ping.get_token().is_empty());
+        // A peer running this code classifies it as a ping
+        assert_eq!(classify_message(&ping), Incoming::Reject);
+    }
+
+    #[test]
+    fn test_mirror_message_type() {
+        let mut ids = IdAllocator::with_seed(0x0100, 0);
+
+        let mut ack = Packet::new();
+        mirror_message_type(&mut ack, MessageType::Confirmable, 0x1234, &mut ids);
+        assert_eq!(ack.header.get_type(), MessageType::Acknowledgement);
+        assert_eq!(ack.header.message_id, 0x1234);
+
+        let mut first = Packet::new();
+        let mut second = Packet::new();
+        mirror_message_type(&mut first, MessageType::NonConfirmable, 0x1234, &mut ids);
+        mirror_message_type(&mut second, MessageType::NonConfirmable, 0x1234, &mut ids);
+        assert_eq!(first.header.get_type(), MessageType::NonConfirmable);
+        assert_eq!(second.header.get_type(), MessageType::NonConfirmable);
+        // NON responses carry their own message IDs, not the request's
+        assert_ne!(first.header.message_id, 0x1234);
+        assert_ne!(first.header.message_id, second.header.message_id);
+    }
+
+    #[test]
+    fn test_cancel_by_msg_id() {
+        let mut obs = ObserveState::new(Arc::default());
+        for (path, token) in [("temp", 1), ("humidity", 2)] {
+            let observation = Observation {
+                token: vec![token],
+                metadata: ObservationMetadata::new(),
+            };
+            obs.observations.insert(path.into(), observation);
+        }
+        obs.notification_msg_ids.insert(10, "temp".into());
+        obs.notification_msg_ids.insert(11, "humidity".into());
+        obs.notification_msg_ids.insert(12, "temp".into());
+
+        let (path, mut stale) = obs.cancel_by_msg_id(12).unwrap();
+        stale.sort();
+        assert_eq!(path, "temp");
+        assert_eq!(stale, vec![10]);
+        assert!(!obs.observations.contains_key("temp"));
+        assert!(obs.observations.contains_key("humidity"));
+        assert_eq!(obs.notification_msg_ids.len(), 1);
+
+        // Unknown or already-cancelled message IDs are ignored
+        assert!(obs.cancel_by_msg_id(12).is_none());
+        assert!(obs.cancel_by_msg_id(99).is_none());
+    }
+
+    #[test]
+    fn test_is_observing_matches_token() {
+        let mut obs = ObserveState::new(Arc::default());
+        obs.observe(
+            "temp".into(),
+            Observation {
+                token: vec![1],
+                metadata: ObservationMetadata::new(),
+            },
+        );
+        let revision = obs.revision;
+
+        // A sleepy device re-sending its registration renews it
+        assert!(obs.is_observing("temp", &[1]));
+        // A new token or path is a new registration
+        assert!(!obs.is_observing("temp", &[2]));
+        assert!(!obs.is_observing("humidity", &[1]));
+        assert_eq!(obs.revision, revision);
+    }
+
+    async fn device_id(identity: &str, mapper: Option<&dyn IdentityMapper>) -> Option<String> {
+        map_identity(identity, mapper)
+            .await
+            .map(|session| session.identity().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_map_identity() {
+        assert_eq!(device_id("acme.s1", None).await.as_deref(), Some("acme.s1"));
+
+        let strip_tenant = |identity: &str| identity.strip_prefix("acme.").map(str::to_string);
+        let mapper: &dyn IdentityMapper = &strip_tenant;
+        assert_eq!(
+            device_id("acme.s1", Some(mapper)).await.as_deref(),
+            Some("s1")
+        );
+        // Unmapped identities are refused
+        assert_eq!(device_id("other.s1", Some(mapper)).await, None);
+
+        // Mapped IDs are validated like identities
+        let to_path = |identity: &str| Some(format!("devices/{}", identity));
+        assert_eq!(device_id("s1", Some(&to_path)).await, None);
+
+        // Tenants scope the session's identity
+        let split = TenantSplit::new(':');
+        let session = map_identity("acme:s1", Some(&split)).await.unwrap();
+        assert_eq!(session.identity(), "acme/s1");
+        assert_eq!(session.tenant(), Some("acme"));
+        // The session remembers the identity it was mapped from
+        assert_eq!(session.psk_identity(), "acme:s1");
+        assert!(map_identity("acme/x:s1", Some(&split)).await.is_none());
+    }
+
+    async fn reconnect(connections: &ConnectionRegistry, port: u16, max_attempts: usize) -> bool {
+        let (tx, _rx) = channel(1);
+        let (requests, _) = channel(1);
+        manage_connection(
+            "device",
+            SocketAddr::from(([127, 0, 0, 1], port)),
+            tx,
+            requests,
+            Arc::new(ConnectionStats::new()),
+            connections,
+            TakeoverPolicy::EvictOld,
+            Duration::from_secs(5),
+            max_attempts,
+        )
+        .await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_rate_limit() {
+        let connections = ConnectionRegistry::new();
+        assert!(reconnect(&connections, 1000, 10).await);
+
+        tokio::time::advance(Duration::from_secs(4)).await;
+        assert!(!reconnect(&connections, 1001, 10).await);
+
+        // The refused attempt doesn't restart the interval
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(reconnect(&connections, 1001, 10).await);
+        let conn = connections.get("device").await.unwrap();
+        assert_eq!(conn.remote_addr.port(), 1001);
+        assert_eq!(conn.reconnect_count, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_takeover_continues_observe_sequence() {
+        let connections = ConnectionRegistry::new();
+        assert!(reconnect(&connections, 1000, 10).await);
+        connections.inner.lock().await["device"]
+            .stats
+            .set_observe_sequence(7);
+
+        // The device wakes up behind a new port before its old session ended
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(reconnect(&connections, 1001, 10).await);
+        assert_eq!(connections.take_sequence("device"), Some(7));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_attempts_limit() {
+        let connections = ConnectionRegistry::new();
+        for port in 1000..1003 {
+            assert!(reconnect(&connections, port, 1).await);
+            tokio::time::advance(Duration::from_secs(5)).await;
+        }
+        assert!(!reconnect(&connections, 1003, 1).await);
+        assert_eq!(connections.get("device").await.unwrap().reconnect_count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_recv_from_any() {
+        let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let listeners: Vec<_> = bind_listeners(&[addr, addr])
+            .unwrap()
+            .into_iter()
+            .map(Arc::new)
+            .collect();
+        let client = UdpSocket::bind(addr).await.unwrap();
+        let local = client.local_addr().unwrap();
+        for (listener, datagram) in [(1, b"one"), (0, b"two")] {
+            let target = listeners[listener].local_addr().unwrap();
+            client.send_to(datagram, target).await.unwrap();
+        }
+
+        let mut buf = [0u8; 16];
+        let mut next = 0;
+        let mut received = Vec::new();
+        for _ in 0..2 {
+            let (n, remote, listener) = recv_from_any(&listeners, &mut buf, &mut next)
+                .await
+                .unwrap();
+            assert_eq!(remote, local);
+            received.push((listener, buf[..n].to_vec()));
+        }
+        received.sort();
+        assert_eq!(received, vec![(0, b"two".to_vec()), (1, b"one".to_vec())]);
+    }
+
+    #[tokio::test]
+    async fn test_serve_validates_config() {
+        let router =
+            || crate::RouterBuilder::new((), crate::observer::memory::MemObserver::new()).build();
+        let err = serve("127.0.0.1:0".to_string(), Config::default(), router())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ConfigError>(),
+            Some(&ConfigError::MissingDtlsConfig)
+        );
+
+        let config = Config {
+            max_connections: 0,
+            ..Config::default()
+        };
+        let err = serve_with_credential_store(
+            "127.0.0.1:0".to_string(),
+            config,
+            router(),
+            MemoryCredentialStore::new(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ConfigError>(),
+            Some(&ConfigError::ZeroLimit("max_connections"))
+        );
+    }
+
+    #[test]
+    fn test_is_application_data() {
+        // DTLS 1.2 application data and handshake record headers
+        assert!(is_application_data(&[23, 0xfe, 0xfd, 0, 1]));
+        assert!(!is_application_data(&[22, 0xfe, 0xfd, 0, 0]));
+        assert!(!is_application_data(&[]));
+    }
+
+    #[test]
+    fn test_peer_addr_keeps_scope() {
+        let link_local = |scope_id, flowinfo| {
+            SocketAddr::V6(std::net::SocketAddrV6::new(
+                "fe80::1".parse().unwrap(),
+                5684,
+                flowinfo,
+                scope_id,
+            ))
+        };
+
+        // Same link-local address behind different interfaces
+        assert_ne!(peer_addr(link_local(2, 0)), peer_addr(link_local(3, 0)));
+        assert_eq!(peer_addr(link_local(2, 7)), link_local(2, 0));
+        assert_eq!(peer_addr(link_local(2, 0)).to_string(), "[fe80::1%2]:5684");
+
+        let v4: SocketAddr = "192.0.2.1:5684".parse().unwrap();
+        assert_eq!(peer_addr(v4), v4);
+    }
+
+    /// A link collecting what is sent over it
+    struct VecLink(Vec<Packet>);
+
+    impl Link for VecLink {
+        fn remote(&self) -> SocketAddr {
+            "127.0.0.1:5683".parse().unwrap()
+        }
+
+        async fn send(&mut self, message: &[u8]) -> bool {
+            self.0.push(Packet::from_bytes(message).unwrap());
+            true
+        }
+    }
+
+    /// The state of one connection, fed requests with `handle_message`
+    struct TestConnection<O: Observer + Send + Sync + Clone + 'static> {
+        router: CoapRouter<O, ()>,
+        config: Config,
+        session: Session,
+        obs_tx: ObserverSender,
+        obs: ObserveState,
+        block_handler: BlockHandler<SocketAddr>,
+        pending: PendingRequests,
+        reliability: ReliabilityState,
+        link: VecLink,
+    }
+
+    impl<O: Observer + Send + Sync + Clone + 'static> TestConnection<O> {
+        fn new(router: CoapRouter<O, ()>, config: Config) -> Self {
+            let (obs_tx, _) =
+                prioritized_channel(8, config.notification_overflow, router.priorities().clone());
+            Self {
+                obs_tx: Arc::new(obs_tx),
+                obs: ObserveState::new(Arc::default()),
+                block_handler: BlockHandler::new(BlockHandlerConfig {
+                    max_total_message_size: config.max_message_size,
+                    cache_expiry_duration: config.block_cache_expiry,
+                }),
+                pending: PendingRequests::new(),
+                reliability: ReliabilityState::new(RetransmitParams::from_config(&config)),
+                session: Session::new("device"),
+                link: VecLink(Vec::new()),
+                router,
+                config,
+            }
+        }
+
+        /// Handle a GET of `path` with message ID `msg_id` and return the
+        /// response
+        async fn get(
+            &mut self,
+            msg_id: u16,
+            path: &str,
+            observe: Option<ObserveOption>,
+            query: Option<&str>,
+        ) -> Packet {
+            let mut request: CoapRequest<SocketAddr> = CoapRequest::new();
+            request.set_method(RequestType::Get);
+            request.set_path(path);
+            if let Some(observe) = observe {
+                request.set_observe_flag(observe);
+            }
+            if let Some(query) = query {
+                request
+                    .message
+                    .add_option(CoapOption::UriQuery, query.as_bytes().to_vec());
+            }
+            request.message.header.message_id = msg_id;
+            request.message.set_token(vec![0x01]);
+            handle_message(
+                request.message,
+                &mut self.link,
+                &self.session,
+                &mut self.router,
+                &self.obs_tx,
+                &mut self.obs,
+                &mut self.block_handler,
+                &mut self.pending,
+                &self.config,
+                &mut self.reliability,
+            )
+            .await;
+            self.link.0.pop().unwrap()
+        }
+    }
+
+    async fn reading(Query(query): Query<HashMap<String, String>>) -> StatusCode {
+        if query.contains_key("fail") {
+            StatusCode::NotFound
+        } else {
+            StatusCode::Content
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observe_registration_needs_success() {
+        let router = RouterBuilder::new((), MemObserver::new())
+            .observe("/sensors/:id", reading, reading)
+            .build();
+        let mut conn = TestConnection::new(router, Config::default());
+        let register = Some(ObserveOption::Register);
+
+        // A 4.04 doesn't register
+        let response = conn.get(1, "/sensors/temp", register, Some("fail")).await;
+        assert_eq!(
+            response.header.code,
+            MessageClass::Response(ResponseType::NotFound)
+        );
+        assert!(response.get_option(CoapOption::Observe).is_none());
+        assert_eq!(conn.router.observer_count("device").await, 0);
+        assert!(conn.obs.observations.is_empty());
+
+        let response = conn.get(2, "/sensors/temp", register, None).await;
+        assert!(response.get_option(CoapOption::Observe).is_some());
+        assert_eq!(conn.router.observer_count("device").await, 1);
+
+        // A failed registration ends the observation the device had
+        let response = conn.get(3, "/sensors/temp", register, Some("fail")).await;
+        assert!(response.get_option(CoapOption::Observe).is_none());
+        assert_eq!(conn.router.observer_count("device").await, 0);
+        assert!(conn.obs.observations.is_empty());
+    }
+
+    /// A backend that refuses every observer (de)registration
+    #[derive(Clone, Debug)]
+    struct Down;
+
+    #[async_trait::async_trait]
+    impl Observer for Down {
+        type Error = &'static str;
+
+        async fn register(
+            &mut self,
+            _device_id: &str,
+            _path: &str,
+            _sender: ObserverSender,
+        ) -> Result<(), Self::Error> {
+            Err("backend down")
+        }
+        async fn unregister(&mut self, _device_id: &str, _path: &str) -> Result<(), Self::Error> {
+            Err("backend down")
+        }
+        async fn unregister_all(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn unregister_device(&mut self, _device_id: &str) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn write(
+            &mut self,
+            _device_id: &str,
+            _path: &str,
+            _payload: &serde_json::Value,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        async fn read(
+            &mut self,
+            _device_id: &str,
+            _path: &str,
+        ) -> Result<Option<serde_json::Value>, Self::Error> {
+            Ok(None)
+        }
+        async fn clear(&mut self, _device_id: &str) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observe_errors_reach_device() {
+        let router = RouterBuilder::new((), Down)
+            .observe("/sensors/:id", reading, reading)
+            .build();
+        let mut config = Config::default();
+        config.set_diagnostic_payloads(DiagnosticFormat::Text);
+        let mut conn = TestConnection::new(router, config);
+
+        let response = conn
+            .get(1, "/sensors/temp", Some(ObserveOption::Register), None)
+            .await;
+        assert_eq!(
+            response.header.code,
+            MessageClass::Response(ResponseType::ServiceUnavailable)
+        );
+        assert!(response.get_option(CoapOption::Observe).is_none());
+        assert!(response.payload.starts_with(b"observer.register_failed: "));
+
+        let response = conn
+            .get(2, "/sensors/temp", Some(ObserveOption::Deregister), None)
+            .await;
+        assert_eq!(
+            response.header.code,
+            MessageClass::Response(ResponseType::ServiceUnavailable)
+        );
+
+        let response = conn
+            .get(3, "/sensors/te.mp", Some(ObserveOption::Register), None)
+            .await;
+        assert_eq!(
+            response.header.code,
+            MessageClass::Response(ResponseType::BadRequest)
+        );
+        assert!(response.payload.starts_with(b"request.invalid_path: "));
+        assert_eq!(response.get_token(), &[0x01]);
+    }
+
+    #[tokio::test]
+    async fn test_path_policy_applies_to_requests_by_default() {
+        let router = RouterBuilder::new((), MemObserver::new())
+            .get(".d/:device_id", reading)
+            .get("/.well-known/core", reading)
+            .build();
+        let mut conn = TestConnection::new(router.clone(), Config::default());
+        for (id, path, status) in [
+            (1, ".d/sensor-1", ResponseType::BadRequest),
+            (2, "/.well-known/core", ResponseType::Content),
+        ] {
+            let response = conn.get(id, path, None, None).await;
+            assert_eq!(response.header.code, MessageClass::Response(status));
+        }
+
+        let config = Config {
+            validate_request_paths: false,
+            ..Config::default()
+        };
+        let mut conn = TestConnection::new(router, config);
+        let response = conn.get(1, ".d/sensor-1", None, None).await;
+        assert_eq!(
+            response.header.code,
+            MessageClass::Response(ResponseType::Content)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_path_policy_of_requests_can_be_widened() {
+        let router = RouterBuilder::new((), MemObserver::new())
+            .get("/fw/:name", reading)
+            .observe("/sensors/:id", reading, reading)
+            .build();
+        let mut conn = TestConnection::new(router.clone(), Config::default());
+        let response = conn.get(1, "/fw/app.bin", None, None).await;
+        assert_eq!(
+            response.header.code,
+            MessageClass::Response(ResponseType::BadRequest)
+        );
+
+        let config = Config {
+            path_policy: PathPolicy::new().allow_chars(".:"),
+            ..Config::default()
+        };
+        let mut conn = TestConnection::new(router, config);
+        let response = conn.get(1, "/fw/app.bin", None, None).await;
+        assert_eq!(
+            response.header.code,
+            MessageClass::Response(ResponseType::Content)
+        );
+        let response = conn
+            .get(2, "/sensors/urn:dev.1", Some(ObserveOption::Register), None)
+            .await;
+        assert!(response.get_option(CoapOption::Observe).is_some());
+        assert_eq!(conn.router.observer_count("device").await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_recv_from_ipv6() {
+        let addr: SocketAddr = "[::1]:0".parse().unwrap();
+        let Ok(listeners) = bind_listeners(&[addr]) else {
+            // No IPv6 on this host
+            return;
+        };
+        let listeners: Vec<_> = listeners.into_iter().map(Arc::new).collect();
+        let client = UdpSocket::bind(addr).await.unwrap();
+        let target = listeners[0].local_addr().unwrap();
+        client.send_to(b"ping", target).await.unwrap();
+
+        let mut buf = [0u8; 16];
+        let (n, remote, _) = recv_from_any(&listeners, &mut buf, &mut 0).await.unwrap();
+        assert_eq!(&buf[..n], b"ping");
+        assert_eq!(remote, client.local_addr().unwrap());
+
+        // Replies reach the peer at its key
+        listeners[0].send_to(b"pong", remote).await.unwrap();
+        let (n, _) = client.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"pong");
+    }
+}
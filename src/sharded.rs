@@ -0,0 +1,248 @@
+//! A concurrent map for read-heavy handler state.
+//!
+//! Every connection runs its handlers against the same application state, so
+//! state kept in a single `Arc<Mutex<HashMap<..>>>` makes all of them take
+//! turns on that mutex, even when they only read. With many connections the
+//! mutex, not the handlers, limits throughput. A [`ShardedMap`] splits the
+//! map into shards behind their own read-write locks: readers never wait for
+//! each other, and a writer only blocks the keys of its shard.
+//!
+//! `cargo bench --bench state_bench` compares a mutex, a read-write lock and
+//! a sharded map under concurrent requests.
+//!
+//! Callbacks run while a shard is locked. They must not block, and since
+//! the locks are synchronous, no lock guard is ever held across an `.await`.
+
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    fmt,
+    hash::{BuildHasher, Hash, RandomState},
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+/// Shards of a map created with [`ShardedMap::new`]
+const DEFAULT_SHARDS: usize = 16;
+
+/// A map split into independently locked shards, see the
+/// [module documentation](self)
+///
+/// Cheap to clone; clones share the map, so it can be kept in application
+/// state as is.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::extract::{Identity, Json, State};
+/// use coapum::sharded::ShardedMap;
+///
+/// #[derive(Clone, Debug, Default)]
+/// struct AppState {
+///     /// Firmware version reported by each device
+///     firmware: ShardedMap<String, String>,
+/// }
+///
+/// async fn report(Identity(device): Identity, State(state): State<AppState>) {
+///     state.firmware.insert(device, "1.4.2".to_string());
+/// }
+///
+/// async fn version(
+///     Identity(device): Identity,
+///     State(state): State<AppState>,
+/// ) -> Json<Option<String>> {
+///     Json(state.firmware.get(&device))
+/// }
+/// ```
+pub struct ShardedMap<K, V> {
+    shards: Arc<[RwLock<HashMap<K, V>>]>,
+    hasher: RandomState,
+}
+
+impl<K, V> Clone for ShardedMap<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            shards: self.shards.clone(),
+            hasher: self.hasher.clone(),
+        }
+    }
+}
+
+impl<K: Eq + Hash, V> ShardedMap<K, V> {
+    /// An empty map with 16 shards
+    pub fn new() -> Self {
+        Self::with_shards(DEFAULT_SHARDS)
+    }
+
+    /// An empty map with `shards` shards
+    ///
+    /// More shards mean less contention between writers of different keys;
+    /// a few times the number of worker threads is plenty.
+    pub fn with_shards(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1))
+                .map(|_| RwLock::new(HashMap::new()))
+                .collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// A clone of the value of `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.read(key).get(key).cloned()
+    }
+
+    /// Call `f` with the value of `key`, without cloning it
+    pub fn with<Q, R>(&self, key: &Q, f: impl FnOnce(&V) -> R) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.read(key).get(key).map(f)
+    }
+
+    /// Whether the map holds `key`
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.read(key).contains_key(key)
+    }
+
+    /// Set the value of `key`, returning the value it replaced
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.write(&key).insert(key, value)
+    }
+
+    /// Change the value of `key` in place with `f`. Returns `None` if the
+    /// map doesn't hold `key`.
+    pub fn update<Q, R>(&self, key: &Q, f: impl FnOnce(&mut V) -> R) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.write(key).get_mut(key).map(f)
+    }
+
+    /// Change the value of `key` with `f`, inserting `default()` first if
+    /// the map doesn't hold it
+    pub fn upsert<R>(&self, key: K, default: impl FnOnce() -> V, f: impl FnOnce(&mut V) -> R) -> R {
+        let mut shard = self.write(&key);
+        f(shard.entry(key).or_insert_with(default))
+    }
+
+    /// Remove `key`, returning its value
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.write(key).remove(key)
+    }
+
+    /// Number of entries, counted shard by shard, so concurrent writes may
+    /// or may not be included
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| lock_read(shard).len()).sum()
+    }
+
+    /// Whether the map has no entries
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| lock_read(shard).is_empty())
+    }
+
+    /// Remove all entries
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            lock_write(shard).clear();
+        }
+    }
+
+    fn shard<Q>(&self, key: &Q) -> &RwLock<HashMap<K, V>>
+    where
+        Q: Hash + ?Sized,
+    {
+        let index = self.hasher.hash_one(key) as usize % self.shards.len();
+        &self.shards[index]
+    }
+
+    fn read<Q>(&self, key: &Q) -> RwLockReadGuard<'_, HashMap<K, V>>
+    where
+        Q: Hash + ?Sized,
+    {
+        lock_read(self.shard(key))
+    }
+
+    fn write<Q>(&self, key: &Q) -> RwLockWriteGuard<'_, HashMap<K, V>>
+    where
+        Q: Hash + ?Sized,
+    {
+        lock_write(self.shard(key))
+    }
+}
+
+// A panic in a callback leaves the shard consistent, since `HashMap`
+// operations don't panic halfway
+fn lock_read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn lock_write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<K: Eq + Hash, V> Default for ShardedMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> fmt::Debug for ShardedMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardedMap")
+            .field("shards", &self.shards.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sharded_map() {
+        let map: ShardedMap<String, u32> = ShardedMap::with_shards(4);
+        let clone = map.clone();
+        for i in 0..100 {
+            map.insert(format!("dev-{i}"), i);
+        }
+        assert_eq!(clone.len(), 100);
+        assert_eq!(clone.get("dev-42"), Some(42));
+        assert_eq!(clone.with("dev-7", |v| v * 2), Some(14));
+
+        assert_eq!(map.update("dev-42", |v| std::mem::replace(v, 0)), Some(42));
+        assert_eq!(map.update("missing", |v| *v), None);
+        assert_eq!(
+            map.upsert(
+                "dev-0".to_string(),
+                || 0,
+                |v| {
+                    *v += 5;
+                    *v
+                }
+            ),
+            5
+        );
+        assert_eq!(map.upsert("new".to_string(), || 10, |v| *v), 10);
+
+        assert_eq!(map.remove("dev-1"), Some(1));
+        assert!(!clone.contains_key("dev-1"));
+        map.clear();
+        assert!(clone.is_empty());
+    }
+}
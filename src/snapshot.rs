@@ -0,0 +1,322 @@
+//! Runtime state snapshots across server restarts
+//!
+//! With [`Config::set_snapshot`](crate::config::Config::set_snapshot) the
+//! server writes a [`StateSnapshot`] when it receives its shutdown signal and
+//! reads it back when it starts:
+//!
+//! - clients missing from the credential store after the restart are added
+//!   again, so clients provisioned at runtime survive an in-memory store,
+//! - a device that reconnects within [`SnapshotConfig::max_age`] gets its
+//!   observations back: notifications resume with the tokens it registered,
+//!   without the device observing its resources again,
+//! - its reconnect count carries over.
+//!
+//! DTLS sessions are not part of the snapshot, so devices still perform a
+//! handshake when they reconnect.
+//!
+//! The snapshot holds PSK keys in plain text unless clients are left out
+//! with [`SnapshotConfig::without_clients`]; protect the file accordingly.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::router::{ClientEntry, export::hex_bytes};
+
+/// Default time a snapshot's connections can be restored for
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(300);
+
+/// Runtime state written on shutdown and restored on startup
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    /// When the snapshot was taken, in seconds since the Unix epoch
+    pub taken_at: u64,
+    /// Connections that were established
+    #[serde(default)]
+    pub connections: Vec<ConnectionRecord>,
+    /// Clients of the credential store by PSK identity
+    #[serde(default)]
+    pub clients: BTreeMap<String, ClientEntry>,
+}
+
+impl StateSnapshot {
+    /// An empty snapshot taken now
+    pub fn new() -> Self {
+        Self {
+            taken_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            ..Default::default()
+        }
+    }
+
+    /// Time since the snapshot was taken
+    pub fn age(&self) -> Duration {
+        let taken_at = UNIX_EPOCH + Duration::from_secs(self.taken_at);
+        SystemTime::now()
+            .duration_since(taken_at)
+            .unwrap_or_default()
+    }
+}
+
+/// A connection in a [`StateSnapshot`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionRecord {
+    /// PSK identity of the client
+    pub identity: String,
+    /// Remote address of the DTLS session
+    pub remote_addr: SocketAddr,
+    /// Reconnects counted for the identity, see
+    /// [`ConnectionSnapshot::reconnect_count`](crate::ConnectionSnapshot::reconnect_count)
+    pub reconnect_count: u32,
+    /// Observations held by the connection
+    #[serde(default)]
+    pub observations: Vec<ObservationRecord>,
+}
+
+/// An observation in a [`StateSnapshot`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObservationRecord {
+    /// Observed path
+    pub path: String,
+    /// Token of the registering GET, hex-encoded when serialized
+    #[serde(with = "hex_bytes")]
+    pub token: Vec<u8>,
+    /// The observation's [`ObservationMetadata`](crate::extract::ObservationMetadata)
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Errors reading or writing a snapshot
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// Reading or writing the snapshot file failed
+    Io(std::io::Error),
+    /// The snapshot could not be encoded or decoded
+    Serialization(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "snapshot I/O error: {}", e),
+            SnapshotError::Serialization(e) => write!(f, "snapshot serialization error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+/// Encodes snapshots for storage
+///
+/// [`JsonSnapshot`] and [`CborSnapshot`] are provided; implement the trait to
+/// compress, sign or encrypt snapshots.
+pub trait SnapshotSerializer: Send + Sync + 'static {
+    /// Encode a snapshot
+    fn serialize(&self, snapshot: &StateSnapshot) -> Result<Vec<u8>, SnapshotError>;
+
+    /// Decode a snapshot produced by [`serialize`](Self::serialize)
+    fn deserialize(&self, bytes: &[u8]) -> Result<StateSnapshot, SnapshotError>;
+}
+
+/// Snapshots as pretty-printed JSON
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonSnapshot;
+
+impl SnapshotSerializer for JsonSnapshot {
+    fn serialize(&self, snapshot: &StateSnapshot) -> Result<Vec<u8>, SnapshotError> {
+        serde_json::to_vec_pretty(snapshot).map_err(|e| SnapshotError::Serialization(e.to_string()))
+    }
+
+    fn deserialize(&self, bytes: &[u8]) -> Result<StateSnapshot, SnapshotError> {
+        serde_json::from_slice(bytes).map_err(|e| SnapshotError::Serialization(e.to_string()))
+    }
+}
+
+/// Snapshots as CBOR
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborSnapshot;
+
+impl SnapshotSerializer for CborSnapshot {
+    fn serialize(&self, snapshot: &StateSnapshot) -> Result<Vec<u8>, SnapshotError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(snapshot, &mut bytes)
+            .map_err(|e| SnapshotError::Serialization(e.to_string()))?;
+        Ok(bytes)
+    }
+
+    fn deserialize(&self, bytes: &[u8]) -> Result<StateSnapshot, SnapshotError> {
+        ciborium::from_reader(bytes).map_err(|e| SnapshotError::Serialization(e.to_string()))
+    }
+}
+
+/// Where and how the server keeps its snapshot
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::config::Config;
+/// use coapum::snapshot::{CborSnapshot, SnapshotConfig};
+/// use std::time::Duration;
+///
+/// let mut config = Config::default();
+/// config.set_snapshot(
+///     SnapshotConfig::new("/var/lib/coapum/state.cbor")
+///         .serializer(CborSnapshot)
+///         .max_age(Duration::from_secs(120)),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct SnapshotConfig {
+    path: PathBuf,
+    serializer: Arc<dyn SnapshotSerializer>,
+    pub(crate) max_age: Duration,
+    pub(crate) clients: bool,
+}
+
+impl SnapshotConfig {
+    /// Keep the snapshot at `path`, as JSON, including clients
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            serializer: Arc::new(JsonSnapshot),
+            max_age: DEFAULT_MAX_AGE,
+            clients: true,
+        }
+    }
+
+    /// Encode the snapshot with `serializer`
+    pub fn serializer(mut self, serializer: impl SnapshotSerializer) -> Self {
+        self.serializer = Arc::new(serializer);
+        self
+    }
+
+    /// How long after the snapshot was taken its connections are restored.
+    /// Devices reconnecting later start over. Default: 5 minutes.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Leave clients, and with them PSK keys, out of the snapshot, e.g.
+    /// when the credential store persists them itself
+    pub fn without_clients(mut self) -> Self {
+        self.clients = false;
+        self
+    }
+
+    /// Path of the snapshot file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write `snapshot`, replacing the previous one
+    ///
+    /// The snapshot is written to a temporary file first, so a crash while
+    /// saving leaves the previous snapshot intact.
+    pub async fn save(&self, snapshot: &StateSnapshot) -> Result<(), SnapshotError> {
+        let bytes = self.serializer.serialize(snapshot)?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        tokio::fs::write(&tmp, bytes).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+        Ok(())
+    }
+
+    /// Read the snapshot, or `None` if there is none
+    pub async fn load(&self) -> Result<Option<StateSnapshot>, SnapshotError> {
+        match tokio::fs::read(&self.path).await {
+            Ok(bytes) => self.serializer.deserialize(&bytes).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl fmt::Debug for SnapshotConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SnapshotConfig")
+            .field("path", &self.path)
+            .field("max_age", &self.max_age)
+            .field("clients", &self.clients)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::ClientMetadata;
+
+    fn snapshot() -> StateSnapshot {
+        let mut snapshot = StateSnapshot::new();
+        snapshot.connections.push(ConnectionRecord {
+            identity: "device_1".to_string(),
+            remote_addr: "127.0.0.1:5684".parse().unwrap(),
+            reconnect_count: 2,
+            observations: vec![ObservationRecord {
+                path: "/temp".to_string(),
+                token: vec![0xca, 0xfe],
+                metadata: BTreeMap::from([("pmin".to_string(), "10".to_string())]),
+            }],
+        });
+        snapshot.clients.insert(
+            "device_1".to_string(),
+            ClientEntry {
+                key: b"secret".to_vec(),
+                metadata: ClientMetadata {
+                    enabled: true,
+                    ..Default::default()
+                },
+            },
+        );
+        snapshot
+    }
+
+    #[test]
+    fn test_serializers_round_trip() {
+        let snapshot = snapshot();
+        let serializers: [&dyn SnapshotSerializer; 2] = [&JsonSnapshot, &CborSnapshot];
+        for serializer in serializers {
+            let bytes = serializer.serialize(&snapshot).unwrap();
+            let decoded = serializer.deserialize(&bytes).unwrap();
+            assert_eq!(decoded.taken_at, snapshot.taken_at);
+            assert_eq!(decoded.connections, snapshot.connections);
+            assert_eq!(decoded.clients["device_1"].key, b"secret");
+        }
+
+        let json = String::from_utf8(JsonSnapshot.serialize(&snapshot).unwrap()).unwrap();
+        assert!(json.contains("\"token\": \"cafe\""));
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SnapshotConfig::new(dir.path().join("state.cbor")).serializer(CborSnapshot);
+        assert!(config.load().await.unwrap().is_none());
+
+        config.save(&snapshot()).await.unwrap();
+        let loaded = config.load().await.unwrap().unwrap();
+        assert_eq!(loaded.connections.len(), 1);
+        assert!(loaded.age() < Duration::from_secs(60));
+
+        tokio::fs::write(config.path(), b"garbage").await.unwrap();
+        assert!(matches!(
+            config.load().await,
+            Err(SnapshotError::Serialization(_))
+        ));
+    }
+}
@@ -0,0 +1,84 @@
+//! Background tasks owned by the router and server.
+//!
+//! State update and client command processors run as spawned tasks. Each one
+//! is wrapped in a [`BackgroundTask`] so its owner can stop it explicitly, and
+//! so it stops by itself once the owner is dropped instead of outliving the
+//! server or the runtime it was spawned on.
+
+use std::future::Future;
+
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// A spawned task that stops when asked to or when its handle is dropped
+#[derive(Debug)]
+pub struct BackgroundTask {
+    stop: Option<oneshot::Sender<()>>,
+    handle: JoinHandle<()>,
+}
+
+impl BackgroundTask {
+    /// Spawn a task. The future receives a stop signal that resolves on
+    /// [`shutdown`](Self::shutdown) or when the handle is dropped.
+    pub(crate) fn spawn<F, Fut>(task: F) -> Self
+    where
+        F: FnOnce(oneshot::Receiver<()>) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (stop, stop_rx) = oneshot::channel();
+        Self {
+            stop: Some(stop),
+            handle: tokio::spawn(task(stop_rx)),
+        }
+    }
+
+    /// Signal the task to stop and wait for it to finish
+    pub async fn shutdown(mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Err(e) = self.handle.await
+            && e.is_panic()
+        {
+            tracing::error!(error = %e, "task.panicked");
+        }
+    }
+
+    /// Returns true once the task has exited
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_shutdown_stops_task() {
+        let task = BackgroundTask::spawn(|stop| async move {
+            let _ = stop.await;
+        });
+        assert!(!task.is_finished());
+
+        tokio::time::timeout(Duration::from_secs(1), task.shutdown())
+            .await
+            .expect("task did not stop");
+    }
+
+    #[tokio::test]
+    async fn test_drop_stops_task() {
+        let (done_tx, done_rx) = oneshot::channel();
+        let task = BackgroundTask::spawn(|stop| async move {
+            let _ = stop.await;
+            let _ = done_tx.send(());
+        });
+        drop(task);
+
+        tokio::time::timeout(Duration::from_secs(1), done_rx)
+            .await
+            .expect("task did not stop")
+            .unwrap();
+    }
+}
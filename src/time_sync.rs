@@ -0,0 +1,294 @@
+//! Coarse time synchronization for devices.
+//!
+//! Devices without a battery-backed clock often only need the time to within
+//! a second or so, e.g. to timestamp readings.
+//! [`RouterBuilder::time_sync`](crate::RouterBuilder::time_sync) serves the
+//! server's clock at `GET /time`, in seconds since the Unix epoch:
+//!
+//! ```text
+//! GET /time?t=1234.5  ->  2.05 Content {"time": 1700000000.25, "echo": 1234.5}
+//! ```
+//!
+//! The optional `t` parameter is echoed back unchanged. A device sends its
+//! own clock reading, such as its uptime, and when the response arrives at
+//! local time `now`, the round trip took `now - echo` and the server's clock
+//! reads about `time + (now - echo) / 2`.
+//!
+//! The response is CBOR unless the request's Accept option asks for JSON
+//! (50), SenML JSON (110) or SenML CBOR (112); other formats get 4.06 Not
+//! Acceptable. SenML packs carry the time as a record named `time` in
+//! seconds (`s`) and the echo as a record named `echo`. Responses have a
+//! Max-Age of 0, so caches never serve a stale time.
+
+use std::{
+    net::SocketAddr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use coap_lite::{CoapOption, ContentFormat, RequestType, ResponseType};
+use coapum_senml::{SenMLPack, SenMLRecord, content_format};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    extract::{IntoResponse, Query, ResponseError, StatusCode, state::FullRequest},
+    observer::Observer,
+    router::{CoapumRequest, RouterBuilder},
+};
+
+/// Configuration of the time resource
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::RouterBuilder;
+/// use coapum::time_sync::{TimeFormat, TimeSync};
+///
+/// let router = RouterBuilder::new((), ())
+///     .time_sync(TimeSync::new().path("/t").default_format(TimeFormat::SenmlCbor))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TimeSync {
+    path: String,
+    format: TimeFormat,
+}
+
+impl TimeSync {
+    /// Serve the time at `/time`, in CBOR unless the request asks otherwise
+    pub fn new() -> Self {
+        Self {
+            path: "/time".to_string(),
+            format: TimeFormat::Cbor,
+        }
+    }
+
+    /// Serve the time at a different path (default: `/time`)
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = format!("/{}", path.into().trim_matches('/'));
+        self
+    }
+
+    /// Format of responses to requests without an Accept option
+    /// (default: [`TimeFormat::Cbor`])
+    pub fn default_format(mut self, format: TimeFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+impl Default for TimeSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Payload format of the time resource
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeFormat {
+    /// A CBOR map (`application/cbor`, 60)
+    Cbor,
+    /// A JSON object (`application/json`, 50)
+    Json,
+    /// A SenML pack (`application/senml+json`, 110)
+    SenmlJson,
+    /// A SenML pack (`application/senml+cbor`, 112)
+    SenmlCbor,
+}
+
+impl TimeFormat {
+    /// The format of CoAP Content-Format `id`, if the resource serves it
+    pub fn from_content_format(id: u16) -> Option<Self> {
+        match id {
+            60 => Some(Self::Cbor),
+            50 => Some(Self::Json),
+            content_format::SENML_JSON => Some(Self::SenmlJson),
+            content_format::SENML_CBOR => Some(Self::SenmlCbor),
+            _ => None,
+        }
+    }
+
+    /// CoAP Content-Format of the format
+    pub fn content_format(self) -> ContentFormat {
+        match self {
+            Self::Cbor => ContentFormat::ApplicationCBOR,
+            Self::Json => ContentFormat::ApplicationJSON,
+            Self::SenmlJson => ContentFormat::ApplicationSenmlJSON,
+            Self::SenmlCbor => ContentFormat::ApplicationSenmlCBOR,
+        }
+    }
+}
+
+/// The payload of the time resource
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ServerTime {
+    /// Seconds since the Unix epoch when the request was handled
+    pub time: f64,
+    /// The request's `t` parameter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub echo: Option<f64>,
+}
+
+impl ServerTime {
+    /// The current time, echoing `echo`
+    pub fn now(echo: Option<f64>) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        Self { time, echo }
+    }
+
+    /// The time as a SenML pack
+    pub fn to_senml(&self) -> SenMLPack {
+        let mut time = SenMLRecord::with_value("time", self.time);
+        time.u = Some("s".to_string());
+        let mut pack = SenMLPack::new();
+        pack.add_record(time);
+        if let Some(echo) = self.echo {
+            pack.add_record(SenMLRecord::with_value("echo", echo));
+        }
+        pack
+    }
+
+    fn encode(&self, format: TimeFormat) -> Result<Vec<u8>, String> {
+        match format {
+            TimeFormat::Cbor => {
+                let mut buffer = Vec::new();
+                ciborium::ser::into_writer(self, &mut buffer).map_err(|e| e.to_string())?;
+                Ok(buffer)
+            }
+            TimeFormat::Json => serde_json::to_vec(self).map_err(|e| e.to_string()),
+            TimeFormat::SenmlJson => self
+                .to_senml()
+                .to_json()
+                .map(String::into_bytes)
+                .map_err(|e| e.to_string()),
+            TimeFormat::SenmlCbor => self.to_senml().to_cbor().map_err(|e| e.to_string()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TimeQuery {
+    t: Option<f64>,
+}
+
+/// A [`ServerTime`] response in the negotiated format
+struct TimeResponse {
+    time: ServerTime,
+    format: TimeFormat,
+}
+
+impl IntoResponse for TimeResponse {
+    fn into_response(self) -> Result<crate::CoapResponse, ResponseError> {
+        let payload = self
+            .time
+            .encode(self.format)
+            .map_err(ResponseError::SerializationError)?;
+        let mut response = crate::CoapResponse::new(&crate::Packet::new()).ok_or_else(|| {
+            ResponseError::InvalidResponse("Failed to create response".to_string())
+        })?;
+        response.message.payload = payload;
+        response
+            .message
+            .set_content_format(self.format.content_format());
+        // The time is stale as soon as it is sent
+        response.message.add_option(CoapOption::MaxAge, Vec::new());
+        response.set_status(ResponseType::Content);
+        Ok(response)
+    }
+}
+
+/// The Content-Format requested with the Accept option, if any
+fn accept(req: &CoapumRequest<SocketAddr>) -> Option<u32> {
+    let value = req.message.get_option(CoapOption::Accept)?.front()?;
+    Some(
+        value
+            .iter()
+            .take(4)
+            .fold(0, |id, byte| (id << 8) | u32::from(*byte)),
+    )
+}
+
+/// Register the time route
+pub(crate) fn mount<O, S>(builder: &mut RouterBuilder<O, S>, config: TimeSync)
+where
+    S: Clone + std::fmt::Debug + Send + Sync + 'static,
+    O: Observer + Send + Sync + Clone + 'static,
+{
+    let default = config.format;
+    builder.add_route(
+        &config.path,
+        RequestType::Get,
+        move |FullRequest(req): FullRequest| async move {
+            let Query(query) =
+                Query::<TimeQuery>::from_request_parts(&req).map_err(|_| StatusCode::BadRequest)?;
+            let format = match accept(&req) {
+                None => default,
+                Some(id) => u16::try_from(id)
+                    .ok()
+                    .and_then(TimeFormat::from_content_format)
+                    .ok_or(StatusCode::NotAcceptable)?,
+            };
+            Ok::<_, StatusCode>(TimeResponse {
+                time: ServerTime::now(query.t),
+                format,
+            })
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CoapRequest, Packet};
+    use tower::Service;
+
+    fn request(query: Option<&str>, accept: Option<u16>) -> CoapumRequest<SocketAddr> {
+        let mut raw = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+        raw.set_method(RequestType::Get);
+        raw.set_path("/time");
+        if let Some(query) = query {
+            raw.message
+                .add_option(CoapOption::UriQuery, query.as_bytes().to_vec());
+        }
+        if let Some(id) = accept {
+            raw.message
+                .add_option(CoapOption::Accept, id.to_be_bytes().to_vec());
+        }
+        raw.into()
+    }
+
+    #[tokio::test]
+    async fn test_time_resource() {
+        let mut router = RouterBuilder::new((), ())
+            .time_sync(TimeSync::new())
+            .build();
+        let before = ServerTime::now(None).time;
+
+        let response = router.call(request(Some("t=1234.5"), None)).await.unwrap();
+        assert_eq!(*response.get_status(), ResponseType::Content);
+        assert_eq!(
+            response.message.get_content_format(),
+            Some(ContentFormat::ApplicationCBOR)
+        );
+        let time: ServerTime = ciborium::from_reader(&response.message.payload[..]).unwrap();
+        assert!(time.time >= before);
+        assert_eq!(time.echo, Some(1234.5));
+
+        let response = router
+            .call(request(None, Some(content_format::SENML_JSON)))
+            .await
+            .unwrap();
+        let pack =
+            SenMLPack::from_json(std::str::from_utf8(&response.message.payload).unwrap()).unwrap();
+        assert_eq!(pack.records[0].n.as_deref(), Some("time"));
+        assert_eq!(pack.len(), 1);
+
+        // text/plain is not served
+        let response = router.call(request(None, Some(0))).await.unwrap();
+        assert_eq!(*response.get_status(), ResponseType::NotAcceptable);
+        let response = router.call(request(Some("t=soon"), None)).await.unwrap();
+        assert_eq!(*response.get_status(), ResponseType::BadRequest);
+    }
+}
@@ -0,0 +1,142 @@
+//! In-memory connection tracker.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use tokio::sync::mpsc::{Receiver, Sender, channel};
+
+use super::{Claim, ConnectionTracker, Takeover, TrackerError};
+
+/// Connection tracker for a single server instance.
+///
+/// Every claim belongs to this node, so takeovers are handled by the local
+/// [`ConnectionRegistry`](crate::connection::ConnectionRegistry) and the
+/// tracker only keeps the claims for inspection.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryConnectionTracker {
+    claims: Arc<Mutex<HashMap<String, Claim>>>,
+    takeovers: Arc<Mutex<Option<Sender<Takeover>>>>,
+}
+
+impl MemoryConnectionTracker {
+    /// Node ID of the in-memory tracker
+    pub const NODE_ID: &'static str = "local";
+
+    /// Create an empty tracker
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Claim>> {
+        self.claims.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl ConnectionTracker for MemoryConnectionTracker {
+    fn node_id(&self) -> &str {
+        Self::NODE_ID
+    }
+
+    async fn claim(
+        &self,
+        identity: &str,
+        claim: &Claim,
+        replace: bool,
+    ) -> Result<Option<Claim>, TrackerError> {
+        let mut claims = self.lock();
+        if !replace && let Some(existing) = claims.get(identity) {
+            return Ok(Some(existing.clone()));
+        }
+        Ok(claims.insert(identity.to_string(), claim.clone()))
+    }
+
+    async fn release(&self, identity: &str, claim: &Claim) -> Result<(), TrackerError> {
+        let mut claims = self.lock();
+        if claims
+            .get(identity)
+            .is_some_and(|c| c.connection_id == claim.connection_id)
+        {
+            claims.remove(identity);
+        }
+        Ok(())
+    }
+
+    async fn owner(&self, identity: &str) -> Result<Option<Claim>, TrackerError> {
+        Ok(self.lock().get(identity).cloned())
+    }
+
+    async fn request_takeover(&self, identity: &str, owner: &Claim) -> Result<(), TrackerError> {
+        let sender = self
+            .takeovers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        if let Some(sender) = sender {
+            sender
+                .send(Takeover {
+                    identity: identity.to_string(),
+                    connection_id: owner.connection_id,
+                })
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn subscribe_takeovers(&self) -> Result<Receiver<Takeover>, TrackerError> {
+        let (tx, rx) = channel(64);
+        *self.takeovers.lock().unwrap_or_else(|e| e.into_inner()) = Some(tx);
+        Ok(rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claim() -> Claim {
+        Claim::new(
+            MemoryConnectionTracker::NODE_ID,
+            "127.0.0.1:5684".parse().unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_claim_and_release() {
+        let tracker = MemoryConnectionTracker::new();
+        let first = claim();
+        let second = claim();
+
+        assert_eq!(tracker.claim("dev", &first, false).await.unwrap(), None);
+        // Without replace the existing claim wins
+        assert_eq!(
+            tracker.claim("dev", &second, false).await.unwrap(),
+            Some(first.clone())
+        );
+        assert_eq!(
+            tracker.claim("dev", &second, true).await.unwrap(),
+            Some(first.clone())
+        );
+
+        // Releasing a superseded claim leaves the current one alone
+        tracker.release("dev", &first).await.unwrap();
+        assert_eq!(tracker.owner("dev").await.unwrap(), Some(second.clone()));
+        tracker.release("dev", &second).await.unwrap();
+        assert_eq!(tracker.owner("dev").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_takeover_delivery() {
+        let tracker = MemoryConnectionTracker::new();
+        let owner = claim();
+        let mut rx = tracker.subscribe_takeovers().await.unwrap();
+
+        tracker.request_takeover("dev", &owner).await.unwrap();
+        let takeover = rx.recv().await.unwrap();
+        assert_eq!(takeover.identity, "dev");
+        assert_eq!(takeover.connection_id, owner.connection_id);
+    }
+}
@@ -0,0 +1,101 @@
+//! Pluggable tracking of which node holds an identity's connection.
+//!
+//! A single server keeps its connections in a [`ConnectionRegistry`]. When
+//! several instances share a load balancer, a device may reconnect to a
+//! different node than the one holding its previous session, and the
+//! [`TakeoverPolicy`] can only be enforced if the nodes agree on who owns the
+//! identity. The [`ConnectionTracker`] trait provides that shared view:
+//! claims map an identity to a node and connection, and takeover requests ask
+//! the owning node to close the connection.
+//!
+//! [`memory::MemoryConnectionTracker`] is the single-node default. The
+//! `redis-tracker` feature adds [`redis::RedisConnectionTracker`] for
+//! clusters. Use a tracker by building the registry with
+//! [`ConnectionRegistry::with_tracker`].
+//!
+//! [`ConnectionRegistry`]: crate::connection::ConnectionRegistry
+//! [`ConnectionRegistry::with_tracker`]: crate::connection::ConnectionRegistry::with_tracker
+//! [`TakeoverPolicy`]: crate::connection::TakeoverPolicy
+
+pub mod memory;
+#[cfg(feature = "redis-tracker")]
+pub mod redis;
+
+use std::{fmt::Debug, net::SocketAddr, time::SystemTime};
+
+use async_trait::async_trait;
+use rand::RngExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Receiver;
+
+/// Error returned by tracker backends
+pub type TrackerError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Ownership of an identity by one connection on one node
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Claim {
+    /// Node holding the connection
+    pub node_id: String,
+    /// Identifies the connection within the cluster
+    pub connection_id: u64,
+    /// Remote address of the DTLS session
+    pub remote_addr: SocketAddr,
+    /// When the connection claimed the identity
+    pub claimed_at: SystemTime,
+}
+
+impl Claim {
+    /// Create a claim for a new connection on `node_id`
+    pub fn new(node_id: &str, remote_addr: SocketAddr) -> Self {
+        Self {
+            node_id: node_id.to_string(),
+            connection_id: rand::rng().random(),
+            remote_addr,
+            claimed_at: SystemTime::now(),
+        }
+    }
+}
+
+/// Request to close the connection holding an identity
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Takeover {
+    pub identity: String,
+    /// Connection to close; a newer connection of the same identity is left alone
+    pub connection_id: u64,
+}
+
+/// Shared map of identities to the node and connection holding them.
+///
+/// Implementations must be safe to call concurrently from all nodes of a
+/// cluster; [`claim`](Self::claim) in particular decides races between nodes.
+#[async_trait]
+pub trait ConnectionTracker: Debug + Send + Sync + 'static {
+    /// ID of this node, stored in its claims
+    fn node_id(&self) -> &str;
+
+    /// Claim an identity for a connection.
+    ///
+    /// With `replace`, the claim is stored and the previous claim, if any, is
+    /// returned. Without it, an existing claim is left in place and returned,
+    /// and the new claim is only stored if there was none.
+    async fn claim(
+        &self,
+        identity: &str,
+        claim: &Claim,
+        replace: bool,
+    ) -> Result<Option<Claim>, TrackerError>;
+
+    /// Drop the claim on an identity, if `claim` still holds it.
+    async fn release(&self, identity: &str, claim: &Claim) -> Result<(), TrackerError>;
+
+    /// The current claim on an identity
+    async fn owner(&self, identity: &str) -> Result<Option<Claim>, TrackerError>;
+
+    /// Ask the node in `owner` to close its connection for `identity`
+    async fn request_takeover(&self, identity: &str, owner: &Claim) -> Result<(), TrackerError>;
+
+    /// Receive takeover requests addressed to this node.
+    ///
+    /// The server calls this once at startup.
+    async fn subscribe_takeovers(&self) -> Result<Receiver<Takeover>, TrackerError>;
+}
@@ -0,0 +1,210 @@
+//! Redis-backed connection tracker for multi-instance deployments.
+//!
+//! Claims are stored as JSON under `{prefix}:claim:{identity}`; takeover
+//! requests are published on the owning node's `{prefix}:takeover:{node_id}`
+//! channel. Requires Redis 6.2 or later (`SET ... GET`).
+
+use std::{fmt, time::Duration};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use redis::{AsyncCommands, Client, Script, aio::ConnectionManager};
+use tokio::sync::mpsc::{Receiver, channel};
+
+use super::{Claim, ConnectionTracker, Takeover, TrackerError};
+
+/// Delete a key only if it still holds the expected claim
+const RELEASE_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+
+/// Connection tracker sharing claims between nodes through Redis.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use std::{sync::Arc, time::Duration};
+/// # use coapum::{ConnectionRegistry, config::Config};
+/// # use coapum::tracker::redis::RedisConnectionTracker;
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let tracker = RedisConnectionTracker::new("redis://127.0.0.1/", "node-a")
+///     .await?
+///     .with_claim_ttl(Duration::from_secs(24 * 3600));
+///
+/// let mut config = Config::default();
+/// config.set_connection_registry(ConnectionRegistry::with_tracker(Arc::new(tracker)));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RedisConnectionTracker {
+    client: Client,
+    conn: ConnectionManager,
+    node_id: String,
+    prefix: String,
+    claim_ttl: Option<Duration>,
+}
+
+impl RedisConnectionTracker {
+    /// Connect to Redis at `url`, identifying this server as `node_id`.
+    ///
+    /// Node IDs must be unique within the cluster.
+    pub async fn new(url: &str, node_id: impl Into<String>) -> Result<Self, redis::RedisError> {
+        let client = Client::open(url)?;
+        let conn = ConnectionManager::new(client.clone()).await?;
+        Ok(Self {
+            client,
+            conn,
+            node_id: node_id.into(),
+            prefix: "coapum".to_string(),
+            claim_ttl: None,
+        })
+    }
+
+    /// Set the key prefix (default: `coapum`)
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Expire claims after `ttl`.
+    ///
+    /// Claims are released when a connection closes, but a node that crashes
+    /// leaves its claims behind. With a TTL they eventually disappear, which
+    /// matters most for [`TakeoverPolicy::RejectNew`](crate::connection::TakeoverPolicy::RejectNew).
+    /// Choose a TTL longer than the longest expected session.
+    pub fn with_claim_ttl(mut self, ttl: Duration) -> Self {
+        self.claim_ttl = Some(ttl);
+        self
+    }
+
+    fn claim_key(&self, identity: &str) -> String {
+        format!("{}:claim:{}", self.prefix, identity)
+    }
+
+    fn takeover_channel(&self, node_id: &str) -> String {
+        format!("{}:takeover:{}", self.prefix, node_id)
+    }
+
+    fn set_command(&self, key: &str, value: &str) -> redis::Cmd {
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(key).arg(value);
+        if let Some(ttl) = self.claim_ttl {
+            cmd.arg("PX").arg(ttl.as_millis() as u64);
+        }
+        cmd
+    }
+}
+
+impl fmt::Debug for RedisConnectionTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisConnectionTracker")
+            .field("node_id", &self.node_id)
+            .field("prefix", &self.prefix)
+            .field("claim_ttl", &self.claim_ttl)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl ConnectionTracker for RedisConnectionTracker {
+    fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    async fn claim(
+        &self,
+        identity: &str,
+        claim: &Claim,
+        replace: bool,
+    ) -> Result<Option<Claim>, TrackerError> {
+        let key = self.claim_key(identity);
+        let value = serde_json::to_string(claim)?;
+        let mut conn = self.conn.clone();
+
+        let previous: Option<String> = if replace {
+            self.set_command(&key, &value)
+                .arg("GET")
+                .query_async(&mut conn)
+                .await?
+        } else {
+            let stored: Option<String> = self
+                .set_command(&key, &value)
+                .arg("NX")
+                .query_async(&mut conn)
+                .await?;
+            match stored {
+                Some(_) => None,
+                None => conn.get(&key).await?,
+            }
+        };
+
+        Ok(match previous {
+            Some(json) => Some(serde_json::from_str(&json)?),
+            None => None,
+        })
+    }
+
+    async fn release(&self, identity: &str, claim: &Claim) -> Result<(), TrackerError> {
+        let value = serde_json::to_string(claim)?;
+        let mut conn = self.conn.clone();
+        let _: i64 = Script::new(RELEASE_SCRIPT)
+            .key(self.claim_key(identity))
+            .arg(value)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn owner(&self, identity: &str) -> Result<Option<Claim>, TrackerError> {
+        let mut conn = self.conn.clone();
+        let json: Option<String> = conn.get(self.claim_key(identity)).await?;
+        Ok(match json {
+            Some(json) => Some(serde_json::from_str(&json)?),
+            None => None,
+        })
+    }
+
+    async fn request_takeover(&self, identity: &str, owner: &Claim) -> Result<(), TrackerError> {
+        let message = serde_json::to_string(&Takeover {
+            identity: identity.to_string(),
+            connection_id: owner.connection_id,
+        })?;
+        let mut conn = self.conn.clone();
+        let _: i64 = conn
+            .publish(self.takeover_channel(&owner.node_id), message)
+            .await?;
+        Ok(())
+    }
+
+    async fn subscribe_takeovers(&self) -> Result<Receiver<Takeover>, TrackerError> {
+        let mut pubsub = self.client.get_async_pubsub().await?;
+        pubsub
+            .subscribe(self.takeover_channel(&self.node_id))
+            .await?;
+
+        let (tx, rx) = channel(64);
+        tokio::spawn(async move {
+            let mut messages = pubsub.into_on_message();
+            while let Some(msg) = messages.next().await {
+                let takeover = msg
+                    .get_payload::<String>()
+                    .ok()
+                    .and_then(|payload| serde_json::from_str::<Takeover>(&payload).ok());
+                match takeover {
+                    Some(takeover) => {
+                        if tx.send(takeover).await.is_err() {
+                            break;
+                        }
+                    }
+                    None => tracing::warn!("tracker.invalid_takeover"),
+                }
+            }
+            tracing::warn!("tracker.takeover_subscription_closed");
+        });
+        Ok(rx)
+    }
+}
@@ -0,0 +1,495 @@
+//! Serving CoAP over transports other than DTLS.
+//!
+//! Some devices aren't reachable over IP: NB-IoT modules using Non-IP Data
+//! Delivery (NIDD), satellite terminals, or sensors on a serial line. Their
+//! CoAP messages reach the server through a bridge, such as the operator's
+//! NIDD API, which also vouches for the sender. A [`Transport`] hands those
+//! messages to the server along with the identity of the peer that sent
+//! them, and sends messages back to a peer by its identity.
+//!
+//! [`serve_with_transport`] runs a router over a transport with the same
+//! per-connection loop as the DTLS server. A peer's first message opens a
+//! connection in the
+//! [`ConnectionRegistry`](crate::connection::ConnectionRegistry), which
+//! then handles observe, block-wise transfers, deduplication, retransmission
+//! of CON messages, [`DeviceClient`](crate::device_client::DeviceClient) requests,
+//! keepalive pings, observations restored from a snapshot and the idle
+//! timeout like any other. Authentication is up to the transport: peer
+//! identities go through the [`IdentityMapper`](crate::IdentityMapper), but
+//! not through a credential store, so routes restricted to client tags see
+//! the tags of [`Transport::peer_tags`].
+//!
+//! The DTLS server in [`serve`](crate::serve) remains the default transport.
+//! Session lifetimes and address migration concern DTLS over UDP and don't
+//! apply here.
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+
+use tokio::{
+    sync::mpsc::{self, error::TrySendError},
+    task::JoinSet,
+};
+
+use crate::{
+    config::Config,
+    connection::ConnectionRegistry,
+    observer::Observer,
+    router::CoapRouter,
+    serve::{Inbound, Link, Opened, Peer, SHUTDOWN_GRACE, decode_datagram, run_connection},
+};
+
+/// Messages queued for a peer's connection before further ones are dropped
+const PEER_QUEUE: usize = 64;
+
+/// A message transport that authenticates its peers, see the
+/// [module documentation](self)
+///
+/// The server receives and sends concurrently, so the methods take `&self`;
+/// implementations keep mutable state behind a lock or a channel.
+///
+/// # Example
+///
+/// ```rust
+/// use coapum::transport::{Transport, serve_with_transport};
+/// use coapum::{RouterBuilder, config::Config, observer::memory::MemObserver};
+/// use std::io;
+/// use tokio::sync::{Mutex, mpsc};
+///
+/// /// A NIDD bridge passing (external ID, message) pairs
+/// struct Nidd {
+///     uplink: Mutex<mpsc::Receiver<(String, Vec<u8>)>>,
+///     downlink: mpsc::Sender<(String, Vec<u8>)>,
+/// }
+///
+/// impl Transport for Nidd {
+///     async fn recv(&self) -> io::Result<(String, Vec<u8>)> {
+///         let uplink = self.uplink.lock().await.recv().await;
+///         uplink.ok_or_else(|| io::ErrorKind::BrokenPipe.into())
+///     }
+///
+///     async fn send(&self, peer: &str, message: &[u8]) -> io::Result<()> {
+///         self.downlink
+///             .send((peer.to_string(), message.to_vec()))
+///             .await
+///             .map_err(|_| io::ErrorKind::BrokenPipe.into())
+///     }
+/// }
+///
+/// # async fn example(nidd: Nidd) {
+/// let router = RouterBuilder::new((), MemObserver::new()).build();
+/// serve_with_transport(nidd, Config::default(), router)
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+pub trait Transport: Send + Sync + 'static {
+    /// Receive the next CoAP message, along with the identity of the peer
+    /// that sent it. An error stops the server.
+    ///
+    /// The server drops the future when other events come first, so it
+    /// must not lose a message when dropped, like tokio's channel receivers.
+    fn recv(&self) -> impl Future<Output = io::Result<(String, Vec<u8>)>> + Send;
+
+    /// Send a CoAP message to the peer with identity `peer`
+    fn send(&self, peer: &str, message: &[u8]) -> impl Future<Output = io::Result<()>> + Send;
+
+    /// The address handlers see as the source of `peer`'s requests.
+    /// Defaults to the unspecified address, for transports without one.
+    fn peer_addr(&self, _peer: &str) -> SocketAddr {
+        SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+    }
+
+    /// The client tags of `peer`, for routes restricted to tags such as
+    /// [`get_with_tags`](crate::RouterBuilder::get_with_tags). Asked once
+    /// per connection. Defaults to none.
+    fn peer_tags(&self, _peer: &str) -> impl Future<Output = Vec<String>> + Send {
+        std::future::ready(Vec::new())
+    }
+}
+
+/// A peer of a transport, as the link its connection sends over
+struct PeerLink<T> {
+    transport: Arc<T>,
+    peer: String,
+    remote: SocketAddr,
+}
+
+impl<T: Transport> Link for PeerLink<T> {
+    fn remote(&self) -> SocketAddr {
+        self.remote
+    }
+
+    async fn send(&mut self, message: &[u8]) -> bool {
+        match self.transport.send(&self.peer, message).await {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::error!(peer = %self.peer, error = %e, "transport.send_failed");
+                false
+            }
+        }
+    }
+}
+
+/// A peer of a transport, as the far end of its connection
+struct TransportPeer<T> {
+    link: PeerLink<T>,
+    message_rx: mpsc::Receiver<Vec<u8>>,
+    /// Whether the connection has seen the peer's identity yet
+    authenticated: bool,
+}
+
+impl<T: Transport> Peer for TransportPeer<T> {
+    type Record = Vec<u8>;
+
+    async fn recv(&mut self) -> Option<Vec<u8>> {
+        let message = self.message_rx.recv().await;
+        if message.is_none() {
+            tracing::debug!(peer = %self.link.peer, "connection.channel_closed");
+        }
+        message
+    }
+
+    async fn open(&mut self, message: Vec<u8>) -> Opened {
+        // The transport vouches for the sender of every message
+        let mut inbound = Vec::with_capacity(2);
+        if !std::mem::replace(&mut self.authenticated, true) {
+            inbound.push(Inbound::Authenticated(self.link.peer.clone()));
+        }
+        inbound.extend(decode_datagram(&message).map(Inbound::Message));
+        Opened::Record(inbound)
+    }
+
+    fn link(&mut self) -> impl Link + '_ {
+        &mut self.link
+    }
+
+    async fn client_tags(&self, _identity: &str) -> Vec<String> {
+        self.link.transport.peer_tags(&self.link.peer).await
+    }
+}
+
+/// Per-peer task: runs the connection of `peer` until it ends, then
+/// reports `peer` on `cleanup_tx`.
+async fn peer_task<O, S, T>(
+    mut peer: TransportPeer<T>,
+    router: CoapRouter<O, S>,
+    config: Config,
+    connections: ConnectionRegistry,
+    cleanup_tx: mpsc::Sender<String>,
+) where
+    S: Debug + Clone + Send + Sync + 'static,
+    O: Observer + Send + Sync + 'static,
+    T: Transport,
+{
+    run_connection(&mut peer, router, &config, &connections).await;
+    let _ = cleanup_tx.send(peer.link.peer).await;
+}
+
+/// Serve `router` over `transport`
+///
+/// Runs until the transport fails or the
+/// [shutdown signal](crate::config::Config::shutdown) arrives. The DTLS
+/// settings of `config` are ignored.
+pub async fn serve_with_transport<O, S, T>(
+    transport: T,
+    config: Config,
+    router: CoapRouter<O, S>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: Debug + Clone + Send + Sync + 'static,
+    O: Observer + Send + Sync + 'static,
+    T: Transport,
+{
+    // The router's state updates stop with the server, however it exits
+    let state_updates = router.clone();
+    let result = dispatch_transport(transport, config, router).await;
+    state_updates.shutdown_state_updates().await;
+    result.map_err(|e| e as Box<dyn std::error::Error>)
+}
+
+async fn dispatch_transport<O, S, T>(
+    transport: T,
+    config: Config,
+    mut router: CoapRouter<O, S>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    S: Debug + Clone + Send + Sync + 'static,
+    O: Observer + Send + Sync + 'static,
+    T: Transport,
+{
+    config.validate()?;
+    tracing::info!("server.started");
+    if let Some(ref compression) = config.compression {
+        router.recognize_option(compression.coding_option);
+    }
+
+    let transport = Arc::new(transport);
+    let connections = config.connection_registry.clone().unwrap_or_default();
+    let mut shutdown_rx = config.shutdown.clone();
+
+    // Dispatch table: peer identity → its connection's message sender
+    let mut dispatch: HashMap<String, mpsc::Sender<Vec<u8>>> = HashMap::new();
+    let (cleanup_tx, mut cleanup_rx) = mpsc::channel::<String>(64);
+
+    // Connection tasks end with the server: dropping the set aborts them
+    let mut tasks = JoinSet::new();
+
+    loop {
+        tokio::select! {
+            // Shutdown signal
+            _ = async {
+                match &mut shutdown_rx {
+                    Some(rx) => { let _ = rx.changed().await; }
+                    None => std::future::pending::<()>().await,
+                }
+            } => {
+                tracing::info!("Shutdown signal received, stopping server");
+
+                // Closing their message channels ends the connections
+                dispatch.clear();
+                cleanup_rx.close();
+                let drained = tokio::time::timeout(SHUTDOWN_GRACE, async {
+                    while tasks.join_next().await.is_some() {}
+                })
+                .await;
+                if drained.is_err() {
+                    tracing::warn!(remaining = tasks.len(), "server.shutdown.aborting_connections");
+                    tasks.shutdown().await;
+                }
+                connections.tasks.set_running(0);
+                return Ok(());
+            }
+
+            // Message from a peer
+            result = transport.recv() => {
+                let (peer, message) = result?;
+
+                // A connection that ended but isn't cleaned up yet is replaced
+                let message = match dispatch.get(&peer) {
+                    None => message,
+                    Some(tx) => match tx.try_send(message) {
+                        Ok(()) => continue,
+                        Err(TrySendError::Full(_)) => {
+                            tracing::warn!(peer = %peer, "transport.peer_queue_full");
+                            continue;
+                        }
+                        Err(TrySendError::Closed(message)) => message,
+                    },
+                };
+
+                if tasks.len() >= config.max_connections {
+                    tracing::warn!(
+                        peer = %peer,
+                        limit = config.max_connections,
+                        "connection.rejected.limit"
+                    );
+                    continue;
+                }
+
+                tracing::debug!(peer = %peer, "connection.incoming");
+                let (tx, rx) = mpsc::channel(PEER_QUEUE);
+                let _ = tx.try_send(message);
+                dispatch.insert(peer.clone(), tx);
+
+                let peer = TransportPeer {
+                    link: PeerLink {
+                        remote: transport.peer_addr(&peer),
+                        transport: transport.clone(),
+                        peer,
+                    },
+                    message_rx: rx,
+                    authenticated: false,
+                };
+                tasks.spawn(peer_task(
+                    peer,
+                    router.clone(),
+                    config.clone(),
+                    connections.clone(),
+                    cleanup_tx.clone(),
+                ));
+                connections.tasks.set_running(tasks.len());
+            }
+
+            // A connection ended. The peer may have a new one already.
+            Some(peer) = cleanup_rx.recv() => {
+                if dispatch.get(&peer).is_some_and(mpsc::Sender::is_closed) {
+                    dispatch.remove(&peer);
+                }
+            }
+
+            // A connection task ended
+            Some(result) = tasks.join_next(), if !tasks.is_empty() => {
+                // Panics in the connection itself are caught by the task
+                if let Err(e) = result
+                    && e.is_panic()
+                {
+                    connections.tasks.record_panic();
+                    tracing::error!(error = %e, "connection.task_panicked");
+                }
+                connections.tasks.set_running(tasks.len());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use coap_lite::{CoapRequest, MessageClass, MessageType, Packet, RequestType, ResponseType};
+    use tokio::sync::Mutex;
+
+    use super::*;
+    use crate::{
+        RouterBuilder,
+        connection::ConnectionRegistry,
+        extract::{Identity, Raw},
+        observer::memory::MemObserver,
+    };
+
+    /// A transport fed and drained through channels
+    struct Channels {
+        uplink: Mutex<mpsc::Receiver<(String, Vec<u8>)>>,
+        downlink: mpsc::Sender<(String, Vec<u8>)>,
+    }
+
+    impl Transport for Channels {
+        async fn recv(&self) -> io::Result<(String, Vec<u8>)> {
+            let uplink = self.uplink.lock().await.recv().await;
+            uplink.ok_or_else(|| io::ErrorKind::BrokenPipe.into())
+        }
+
+        async fn send(&self, peer: &str, message: &[u8]) -> io::Result<()> {
+            self.downlink
+                .send((peer.to_string(), message.to_vec()))
+                .await
+                .map_err(|_| io::ErrorKind::BrokenPipe.into())
+        }
+
+        async fn peer_tags(&self, peer: &str) -> Vec<String> {
+            match peer {
+                "nidd-1" => vec!["fleet".to_string()],
+                _ => Vec::new(),
+            }
+        }
+    }
+
+    /// A message along with its peer
+    type Message = (String, Vec<u8>);
+
+    fn channels() -> (Channels, mpsc::Sender<Message>, mpsc::Receiver<Message>) {
+        let (uplink_tx, uplink_rx) = mpsc::channel(8);
+        let (downlink_tx, downlink_rx) = mpsc::channel(8);
+        let transport = Channels {
+            uplink: Mutex::new(uplink_rx),
+            downlink: downlink_tx,
+        };
+        (transport, uplink_tx, downlink_rx)
+    }
+
+    async fn whoami(Identity(id): Identity) -> Raw {
+        Raw {
+            payload: id.into_bytes(),
+            content_format: None,
+        }
+    }
+
+    fn get(path: &str, msg_id: u16) -> Vec<u8> {
+        let mut request: CoapRequest<SocketAddr> = CoapRequest::new();
+        request.set_method(RequestType::Get);
+        request.set_path(path);
+        request.message.header.set_type(MessageType::Confirmable);
+        request.message.header.message_id = msg_id;
+        request.message.to_bytes().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_serve_with_transport() {
+        let (transport, uplink_tx, mut downlink_rx) = channels();
+        let registry = ConnectionRegistry::new();
+        let mut config = Config::default();
+        config.set_connection_registry(registry.clone());
+        let mut router = RouterBuilder::new((), MemObserver::new())
+            .get("/whoami", whoami)
+            .build();
+        let updates = router.enable_state_updates(4);
+        let server = tokio::spawn(async move {
+            serve_with_transport(transport, config, router)
+                .await
+                .map_err(|e| e.to_string())
+        });
+
+        for (peer, msg_id) in [("nidd-1", 1), ("nidd-2", 2), ("nidd-1", 3)] {
+            uplink_tx
+                .send((peer.to_string(), get("/whoami", msg_id)))
+                .await
+                .unwrap();
+            let (to, reply) = tokio::time::timeout(Duration::from_secs(1), downlink_rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            let reply = Packet::from_bytes(&reply).unwrap();
+            assert_eq!(to, peer);
+            assert_eq!(reply.header.get_type(), MessageType::Acknowledgement);
+            assert_eq!(reply.header.message_id, msg_id);
+            assert_eq!(
+                reply.header.code,
+                MessageClass::Response(ResponseType::Content)
+            );
+            assert_eq!(reply.payload, peer.as_bytes());
+        }
+        assert_eq!(registry.len().await, 2);
+
+        // A retransmitted request is answered from the deduplication cache
+        uplink_tx
+            .send(("nidd-2".to_string(), get("/whoami", 2)))
+            .await
+            .unwrap();
+        let (_, reply) = downlink_rx.recv().await.unwrap();
+        assert_eq!(Packet::from_bytes(&reply).unwrap().header.message_id, 2);
+
+        // The transport closing stops the server and its state updates
+        drop(uplink_tx);
+        assert!(server.await.unwrap().is_err());
+        assert!(updates.update(|_| {}).await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_transport_peer_tags_and_keepalive() {
+        let (transport, uplink_tx, mut downlink_rx) = channels();
+        let mut config = Config::default();
+        config.set_keepalive_interval(Duration::from_secs(20));
+        let router = RouterBuilder::new((), MemObserver::new())
+            .get_with_tags("/fleet", whoami, ["fleet"])
+            .build();
+        tokio::spawn(async move {
+            let _ = serve_with_transport(transport, config, router).await;
+        });
+
+        // Tagged routes see the tags of the transport
+        for (peer, msg_id, status) in [
+            ("nidd-1", 1, ResponseType::Content),
+            ("nidd-2", 2, ResponseType::Forbidden),
+        ] {
+            uplink_tx
+                .send((peer.to_string(), get("/fleet", msg_id)))
+                .await
+                .unwrap();
+            let (_, reply) = downlink_rx.recv().await.unwrap();
+            let reply = Packet::from_bytes(&reply).unwrap();
+            assert_eq!(reply.header.code, MessageClass::Response(status));
+        }
+
+        // Silent peers are pinged
+        let (_, ping) = downlink_rx.recv().await.unwrap();
+        let ping = Packet::from_bytes(&ping).unwrap();
+        assert_eq!(ping.header.get_type(), MessageType::Confirmable);
+        assert_eq!(ping.header.code, MessageClass::Empty);
+    }
+}
@@ -401,7 +401,9 @@ async fn test_observe_with_sled_backend() {
     };
 
     // Start server with Sled observer (using temporary database)
-    let observer = coapum::observer::sled::SledObserver::new("test_observe_integration.db");
+    let db_dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let db_path = db_dir.path().join("observe.db");
+    let observer = coapum::observer::sled::SledObserver::new(db_path.to_str().unwrap());
     let server_addr = start_test_server(app_state.clone(), observer)
         .await
         .expect("Failed to start server");
@@ -466,9 +468,6 @@ async fn test_observe_with_sled_backend() {
         response.header.code,
         coapum::MessageClass::Response(ResponseType::Content)
     );
-
-    // Cleanup test database
-    let _ = std::fs::remove_file("test_observe_integration.db");
 }
 
 #[tokio::test]
@@ -497,7 +497,7 @@ async fn test_debug_path_format() {
     let mut observer = MemObserver::new();
 
     // Create a channel to receive notifications
-    let (tx, mut rx) = tokio::sync::mpsc::channel::<coapum::observer::ObserverValue>(10);
+    let (tx, mut rx) = coapum::observer::queue::notification_channel(10, Default::default());
     let sender = Arc::new(tx);
 
     // Register for a specific path
@@ -5,10 +5,13 @@ mod redb_integration_tests {
 
     use serde_json::json;
     use tempfile::NamedTempFile;
-    use tokio::sync::mpsc;
     use tokio::time::sleep;
 
-    use coapum::observer::{Observer, ObserverValue, redb::RedbObserver};
+    use coapum::observer::{
+        Observer,
+        queue::{OverflowPolicy, notification_channel},
+        redb::RedbObserver,
+    };
 
     // Named constants for test timing
     const REGISTRATION_DELAY: Duration = Duration::from_millis(100);
@@ -81,9 +84,9 @@ mod redb_integration_tests {
         observer.clear("device_3").await.unwrap();
 
         // Set up channels for each device
-        let (tx1, mut rx1) = mpsc::channel::<ObserverValue>(10);
-        let (tx2, mut rx2) = mpsc::channel::<ObserverValue>(10);
-        let (tx3, mut rx3) = mpsc::channel::<ObserverValue>(10);
+        let (tx1, mut rx1) = notification_channel(10, OverflowPolicy::default());
+        let (tx2, mut rx2) = notification_channel(10, OverflowPolicy::default());
+        let (tx3, mut rx3) = notification_channel(10, OverflowPolicy::default());
 
         // Register observers for different paths on different devices
         observer
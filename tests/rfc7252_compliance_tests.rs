@@ -401,7 +401,7 @@ mod empty_message {
 mod observer_token_storage {
     use std::collections::HashMap;
 
-    /// Mirrors the ObserveState.observer_tokens field from serve.rs.
+    /// Mirrors the token kept per observation in ObserveState from serve.rs.
     /// We test the HashMap-based storage logic directly since ObserveState is private.
 
     #[test]
@@ -0,0 +1,88 @@
+//! Tests for routes declared with `#[coap_route]`
+//!
+//! The compile-time checks are covered by the unit tests in
+//! `coapum-macros`; these tests make sure the generated registration
+//! mounts working routes.
+
+use coapum::{
+    CoapRequest, Packet, RequestType, ResponseType, coap_route,
+    extract::{Path, State, StatusCode},
+    observer::memory::MemObserver,
+    router::RouterBuilder,
+    routes,
+};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tower::Service;
+
+#[derive(Debug, Clone, Default)]
+struct SensorState {
+    deleted: Arc<Mutex<Vec<String>>>,
+}
+
+impl AsRef<SensorState> for SensorState {
+    fn as_ref(&self) -> &SensorState {
+        self
+    }
+}
+
+#[coap_route(get, "/health")]
+async fn health() -> StatusCode {
+    StatusCode::Content
+}
+
+mod sensors {
+    use super::*;
+
+    #[coap_route(delete, "/sensors/:id")]
+    pub async fn delete_sensor(
+        Path(id): Path<String>,
+        State(state): State<SensorState>,
+    ) -> StatusCode {
+        state.deleted.lock().unwrap().push(id);
+        StatusCode::Deleted
+    }
+}
+
+fn request(method: RequestType, path: &str) -> coapum::router::CoapumRequest<SocketAddr> {
+    let mut request = CoapRequest::from_packet(Packet::new(), "127.0.0.1:5683".parse().unwrap());
+    request.set_method(method);
+    request.set_path(path);
+    request.into()
+}
+
+#[test]
+fn test_route_constants() {
+    assert_eq!(health::METHOD, RequestType::Get);
+    assert_eq!(health::PATH, "/health");
+    assert_eq!(sensors::delete_sensor::METHOD, RequestType::Delete);
+    assert_eq!(sensors::delete_sensor::PATH, "/sensors/:id");
+}
+
+#[tokio::test]
+async fn test_mounted_routes() {
+    let state = SensorState::default();
+    let mut router = RouterBuilder::new(state.clone(), MemObserver::new())
+        .mount(routes![health, sensors::delete_sensor])
+        .build();
+
+    let response = router
+        .call(request(RequestType::Get, "/health"))
+        .await
+        .unwrap();
+    assert_eq!(*response.get_status(), ResponseType::Content);
+
+    let response = router
+        .call(request(RequestType::Delete, "/sensors/temp1"))
+        .await
+        .unwrap();
+    assert_eq!(*response.get_status(), ResponseType::Deleted);
+    assert_eq!(*state.deleted.lock().unwrap(), vec!["temp1".to_string()]);
+
+    // Only the declared method is routed
+    let response = router
+        .call(request(RequestType::Get, "/sensors/temp1"))
+        .await
+        .unwrap();
+    assert_eq!(*response.get_status(), ResponseType::MethodNotAllowed);
+}
@@ -0,0 +1,226 @@
+//! Timeout and rate-limit tests on tokio's paused clock
+//!
+//! DTLS handshakes run in real time over localhost. Once a connection is
+//! established the test pauses tokio's clock and advances it past the
+//! configured limits, so idle timeouts and the reconnect interval are
+//! exercised without waiting for them. Keepalive pings are checked the same
+//! way.
+//!
+//! **Must run with `--test-threads=1`** to avoid port conflicts from the
+//! bind-drop-rebind pattern used to discover free ports.
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+
+use coapum::{
+    ConnectionRegistry, MemoryCredentialStore, MessageClass, MessageType, Packet,
+    client::DtlsClient, config::Config, credential::resolver::MapResolver, extract::StatusCode,
+    observer::memory::MemObserver, router::RouterBuilder, serve,
+};
+
+const PSK: &[u8] = b"test_psk_key_1234567890abcdef";
+const IDENTITY: &str = "sim_client";
+const IDLE_TIMEOUT_SECS: u64 = 60;
+const MIN_RECONNECT_INTERVAL: Duration = Duration::from_secs(30);
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(20);
+
+async fn ping() -> StatusCode {
+    StatusCode::Content
+}
+
+/// Start a server sharing `registry` and return its address
+async fn start_server(registry: ConnectionRegistry) -> SocketAddr {
+    start_server_with(registry, |_| {}).await
+}
+
+/// Start a server sharing `registry`, with `configure` applied to its config
+async fn start_server_with(
+    registry: ConnectionRegistry,
+    configure: impl FnOnce(&mut Config),
+) -> SocketAddr {
+    let listener = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+
+    let mut clients = HashMap::new();
+    clients.insert(IDENTITY.to_string(), PSK.to_vec());
+    let credential_store = MemoryCredentialStore::from_clients(&clients);
+
+    let router = RouterBuilder::new((), MemObserver::new())
+        .get("/ping", ping)
+        .build();
+
+    let mut config = Config {
+        psk_identity_hint: Some(b"sim_server".to_vec()),
+        timeout: IDLE_TIMEOUT_SECS,
+        ..Default::default()
+    };
+    config.set_min_reconnect_interval(MIN_RECONNECT_INTERVAL);
+    config.set_connection_registry(registry);
+    configure(&mut config);
+
+    tokio::spawn(async move {
+        if let Err(e) =
+            serve::serve_with_credential_store(addr.to_string(), config, router, credential_store)
+                .await
+        {
+            eprintln!("Server error: {}", e);
+        }
+    });
+
+    // Give the server time to start
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    addr
+}
+
+async fn connect(server: SocketAddr) -> Result<DtlsClient, Box<dyn std::error::Error>> {
+    let mut keys = HashMap::new();
+    keys.insert(IDENTITY.to_string(), PSK.to_vec());
+    let resolver = Arc::new(MapResolver::new(keys));
+
+    let config = dimpl::Config::builder()
+        .with_psk_client(
+            IDENTITY.as_bytes().to_vec(),
+            resolver as Arc<dyn dimpl::PskResolver>,
+        )
+        .build()
+        .expect("valid DTLS config");
+
+    DtlsClient::connect(&server.to_string(), Arc::new(config)).await
+}
+
+/// Wait in real time for the server to catch up with the client
+async fn eventually<F, Fut>(mut check: F) -> bool
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    for _ in 0..50 {
+        if check().await {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    false
+}
+
+/// Wait until the registry tracks `client` for `IDENTITY`
+async fn registered(registry: &ConnectionRegistry, client: &DtlsClient) -> bool {
+    let port = client.local_addr().unwrap().port();
+    eventually(|| async move {
+        registry
+            .get(IDENTITY)
+            .await
+            .is_some_and(|conn| conn.remote_addr.port() == port)
+    })
+    .await
+}
+
+/// Wait until no DTLS handshake is in progress on the server
+async fn handshakes_done(registry: &ConnectionRegistry) -> bool {
+    eventually(|| async move { registry.handshake_stats().in_progress == 0 }).await
+}
+
+/// Let spawned connection tasks react to the advanced clock
+async fn settle() {
+    for _ in 0..100 {
+        tokio::task::yield_now().await;
+    }
+}
+
+#[tokio::test]
+async fn test_idle_connection_evicted_after_timeout() {
+    let registry = ConnectionRegistry::new();
+    let server = start_server(registry.clone()).await;
+
+    let client = connect(server).await.expect("handshake");
+    assert!(registered(&registry, &client).await);
+
+    tokio::time::pause();
+
+    tokio::time::advance(Duration::from_secs(IDLE_TIMEOUT_SECS - 1)).await;
+    settle().await;
+    assert!(registry.get(IDENTITY).await.is_some());
+
+    tokio::time::advance(Duration::from_secs(2)).await;
+    settle().await;
+    assert!(registry.get(IDENTITY).await.is_none());
+}
+
+#[tokio::test]
+async fn test_reconnect_rate_limited_until_interval_elapses() {
+    let registry = ConnectionRegistry::new();
+    let server = start_server(registry.clone()).await;
+
+    let first = connect(server).await.expect("handshake");
+    assert!(registered(&registry, &first).await);
+
+    // An immediate reconnect is refused. The client may or may not see its
+    // handshake complete, so wait for the server to finish with it instead
+    let _ = tokio::time::timeout(Duration::from_secs(2), connect(server)).await;
+    assert!(handshakes_done(&registry).await);
+    assert!(registered(&registry, &first).await);
+    assert_eq!(registry.get(IDENTITY).await.unwrap().reconnect_count, 0);
+
+    tokio::time::pause();
+    tokio::time::advance(MIN_RECONNECT_INTERVAL).await;
+    tokio::time::resume();
+
+    let second = connect(server).await.expect("handshake");
+    assert!(registered(&registry, &second).await);
+    assert_eq!(registry.get(IDENTITY).await.unwrap().reconnect_count, 1);
+}
+
+#[tokio::test]
+async fn test_keepalive_ping_keeps_silent_connection_alive() {
+    let registry = ConnectionRegistry::new();
+    let server = start_server_with(registry.clone(), |config| {
+        config.set_keepalive_interval(KEEPALIVE_INTERVAL);
+    })
+    .await;
+
+    let mut client = connect(server).await.expect("handshake");
+    assert!(registered(&registry, &client).await);
+
+    tokio::time::pause();
+    tokio::time::advance(KEEPALIVE_INTERVAL + Duration::from_secs(1)).await;
+    settle().await;
+
+    let ping = Packet::from_bytes(&client.recv(Duration::from_secs(1)).await.unwrap()).unwrap();
+    assert_eq!(ping.header.get_type(), MessageType::Confirmable);
+    assert_eq!(ping.header.code, MessageClass::Empty);
+
+    let mut rst = Packet::new();
+    rst.header.set_type(MessageType::Reset);
+    rst.header.message_id = ping.header.message_id;
+    client.send(&rst.to_bytes().unwrap()).await.unwrap();
+    settle().await;
+
+    // Past the idle timeout counted from the handshake, the answered ping
+    // keeps the connection
+    tokio::time::advance(Duration::from_secs(IDLE_TIMEOUT_SECS - 20)).await;
+    settle().await;
+    assert!(registry.get(IDENTITY).await.is_some());
+}
+
+#[tokio::test]
+async fn test_unanswered_keepalive_ping_ends_connection() {
+    let registry = ConnectionRegistry::new();
+    let server = start_server_with(registry.clone(), |config| {
+        config.set_timeout(600).unwrap();
+        config.set_keepalive_interval(KEEPALIVE_INTERVAL);
+    })
+    .await;
+
+    let client = connect(server).await.expect("handshake");
+    assert!(registered(&registry, &client).await);
+
+    tokio::time::pause();
+    // The ping and its retransmissions go unanswered. With the default
+    // retransmission settings the server gives up within 93 seconds,
+    // long before the idle timeout
+    for _ in 0..30 {
+        tokio::time::advance(Duration::from_secs(5)).await;
+        settle().await;
+    }
+    assert!(registry.get(IDENTITY).await.is_none());
+}
@@ -290,3 +290,70 @@ async fn test_concurrent_state_updates() {
     // Give final updates time to process
     tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
 }
+
+#[tokio::test]
+async fn test_shutdown_state_updates() {
+    let state = TestAppState::new();
+    let observer = MemObserver::new();
+    let mut router = CoapRouter::new(state, observer);
+
+    let state_handle = router.enable_state_updates(10);
+    state_handle
+        .update(|state: &mut TestAppState| {
+            state.counter += 1;
+        })
+        .await
+        .unwrap();
+
+    tokio::time::timeout(
+        tokio::time::Duration::from_secs(1),
+        router.shutdown_state_updates(),
+    )
+    .await
+    .expect("state update task did not stop");
+
+    let result = state_handle
+        .update(|state: &mut TestAppState| {
+            state.counter += 1;
+        })
+        .await;
+    assert_eq!(result, Err(StateUpdateError::ChannelClosed));
+}
+
+#[tokio::test]
+async fn test_query_and_update_and_get() {
+    let state = TestAppState::new();
+    let observer = MemObserver::new();
+    let mut router = CoapRouter::new(state, observer);
+
+    let state_handle = router.enable_state_updates(10);
+
+    let counter = state_handle
+        .update_and_get(|state: &mut TestAppState| {
+            state.counter += 5;
+            state.counter
+        })
+        .await
+        .unwrap();
+    assert_eq!(counter, 5);
+
+    // Queued behind the update without waiting for it
+    state_handle
+        .try_update(|state: &mut TestAppState| {
+            state.data.insert("key".to_string(), "value".to_string());
+        })
+        .unwrap();
+    let value = state_handle
+        .query(|state: &TestAppState| state.data.get("key").cloned())
+        .await
+        .unwrap();
+    assert_eq!(value.as_deref(), Some("value"));
+
+    router.shutdown_state_updates().await;
+    assert_eq!(
+        state_handle
+            .query(|state: &TestAppState| state.counter)
+            .await,
+        Err(StateUpdateError::ChannelClosed)
+    );
+}